@@ -11,6 +11,9 @@ use form::MessageForm;
 pub mod typing;
 use typing::TypingState;
 
+/// Channel all chat messages are sent to.
+pub const CHAT_ROOM: &str = "chat";
+
 #[derive(Clone, rwf::macros::TemplateValue)]
 struct UserMessage {
     user: User,
@@ -102,9 +105,9 @@ impl PageController for ChatController {
                 .fetch(&mut conn)
                 .await?;
 
-        // Broadcast the message to everyone else.
+        // Broadcast the message to everyone else in the room.
         {
-            let broadcast = Comms::broadcast(&user);
+            let broadcast = Comms::channel(CHAT_ROOM).broadcast(&user);
             let message = Self::chat_message(request, &user, &message, false)?.render();
 
             broadcast.send(message)?;
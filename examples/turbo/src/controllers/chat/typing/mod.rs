@@ -1,6 +1,7 @@
 use rwf::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use super::CHAT_ROOM;
 use crate::models::User;
 
 #[derive(Default)]
@@ -14,7 +15,7 @@ impl Controller for TypingController {
         let user = request.user::<User>(&mut conn).await?;
 
         if let Some(user) = user {
-            let broadcast = Comms::broadcast(&user);
+            let broadcast = Comms::channel(CHAT_ROOM).broadcast(&user);
             broadcast.send(state.render(request, &user)?)?;
 
             Ok(serde_json::json!({
@@ -24,7 +24,16 @@ impl Controller for IndexController {
 struct TurboStreamController;
 
 #[rwf::async_trait]
-impl WebsocketController for TurboStreamController {}
+impl WebsocketController for TurboStreamController {
+    /// Join the chat room. Clients leave it automatically when they disconnect.
+    async fn client_connected(&self, session_id: &SessionId) -> Result<(), Error> {
+        if session_id.authenticated() {
+            Comms::channel(CHAT_ROOM).subscribe(session_id);
+        }
+
+        Ok(())
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
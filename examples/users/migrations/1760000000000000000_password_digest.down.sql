@@ -0,0 +1 @@
+ALTER TABLE users RENAME COLUMN password_digest TO password;
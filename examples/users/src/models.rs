@@ -1,7 +1,6 @@
 // use rwf::model::Error;
-use rwf::crypto::{hash, hash_validate};
+use rwf::auth::password;
 use rwf::prelude::*;
-use tokio::task::spawn_blocking;
 
 pub enum UserLogin {
     NoSuchUser,
@@ -9,21 +8,18 @@ pub enum UserLogin {
     Ok(User),
 }
 
-#[derive(Clone, macros::Model)]
+#[derive(Clone, macros::Model, macros::SecurePassword)]
 pub struct User {
     id: Option<i64>,
     email: String,
-    password: String,
+    password_digest: String,
     created_at: OffsetDateTime,
 }
 
 impl User {
     /// Create new user with email and password.
     pub async fn signup(email: &str, password: &str) -> Result<UserLogin, Error> {
-        let hash_password = password.to_owned();
-        let encrypted_password = spawn_blocking(move || hash(hash_password.as_bytes()))
-            .await
-            .unwrap()?;
+        let password_digest = password::hash(password).await?;
 
         match Self::login(email, password).await? {
             UserLogin::Ok(user) => return Ok(UserLogin::Ok(user)),
@@ -33,7 +29,7 @@ impl User {
 
         let user = User::create(&[
             ("email", email.to_value()),
-            ("password", encrypted_password.to_value()),
+            ("password_digest", password_digest.to_value()),
         ])
         .fetch(Pool::pool())
         .await?;
@@ -50,7 +46,7 @@ impl User {
             .fetch_optional(Pool::pool())
             .await?
         {
-            if hash_validate(password.as_bytes(), &user.password)? {
+            if user.verify_password(password).await? {
                 return Ok(UserLogin::Ok(user));
             } else {
                 return Ok(UserLogin::WrongPassword);
@@ -0,0 +1,53 @@
+//! Audit log of admin logins and actions.
+use rwf::prelude::*;
+
+use super::Identity;
+
+/// Entry in the audit log.
+#[derive(Clone, macros::Model, Debug)]
+#[table_name("rwf_admin_audit_log")]
+pub struct AuditLog {
+    pub id: Option<i64>,
+    /// Application user ID, if logged in with the session.
+    pub user_id: Option<i64>,
+    /// Username, email, or user ID of the admin.
+    pub actor: String,
+    /// `login`, `login_failed`, `logout`, `denied`, or `action`.
+    pub action: String,
+    /// Request method.
+    pub method: String,
+    /// Request path.
+    pub path: String,
+    /// Client IP.
+    pub ip: String,
+    /// When it happened.
+    pub created_at: OffsetDateTime,
+}
+
+impl AuditLog {
+    /// Record what the admin did with this request.
+    pub async fn record(
+        identity: &Identity,
+        action: &str,
+        request: &Request,
+    ) -> Result<AuditLog, Error> {
+        let entry = AuditLog {
+            id: None,
+            user_id: identity.user_id,
+            actor: identity.name.clone(),
+            action: action.to_string(),
+            method: request.method().to_string(),
+            path: request.path().path().to_string(),
+            ip: request.peer().ip().to_string(),
+            created_at: OffsetDateTime::now_utc(),
+        };
+
+        let mut conn = Pool::connection().await?;
+        Ok(entry.save().fetch(&mut conn).await?)
+    }
+
+    /// Most recent entries.
+    pub fn recent(limit: i64) -> Scope<Self> {
+        Self::all().order(("id", "DESC")).limit(limit)
+    }
+}
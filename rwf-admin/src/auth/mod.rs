@@ -0,0 +1,348 @@
+//! Authentication for the admin panel.
+//!
+//! Admins can be authenticated with HTTP Basic auth, with the application session and a
+//! policy deciding which users are admins, or with single sign-on through an OpenID Connect
+//! provider, e.g. Google Workspace or Okta. Logins and all changes made through the admin
+//! panel are recorded in the audit log.
+use std::sync::Arc;
+
+use rwf::http::{Authorization, Handler};
+use rwf::prelude::*;
+
+pub mod audit;
+pub mod sso;
+
+pub use audit::AuditLog;
+pub use sso::Sso;
+
+/// Decides which users can access the admin panel.
+///
+/// Implemented for closures, e.g. `|user_id| user_id == 1`. To check in the database,
+/// implement it on a struct:
+///
+/// ```ignore
+/// struct IsAdmin;
+///
+/// #[async_trait]
+/// impl AdminPolicy for IsAdmin {
+///     async fn is_admin(&self, _request: &Request, user_id: i64) -> Result<bool, Error> {
+///         let mut conn = Pool::connection().await?;
+///         let user = User::find(user_id).fetch(&mut conn).await?;
+///         Ok(user.admin)
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait AdminPolicy: Send + Sync {
+    /// The logged in user is an admin.
+    async fn is_admin(&self, request: &Request, user_id: i64) -> Result<bool, Error>;
+}
+
+#[async_trait]
+impl<F> AdminPolicy for F
+where
+    F: Fn(i64) -> bool + Send + Sync,
+{
+    async fn is_admin(&self, _request: &Request, user_id: i64) -> Result<bool, Error> {
+        Ok(self(user_id))
+    }
+}
+
+/// Admin who made a request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identity {
+    /// Application user ID, if logged in with the session.
+    pub user_id: Option<i64>,
+    /// Username, email, or user ID.
+    pub name: String,
+}
+
+/// Authentication for the admin panel. Use it with [`crate::engine_with_auth`].
+///
+/// # Example
+///
+/// ```ignore
+/// let auth = AdminAuth::session(IsAdmin)
+///     .login_url("/login")
+///     .sso(Sso::new("https://accounts.google.com", client_id, client_secret).allow_domain("example.com"));
+///
+/// Server::new(rwf_admin::routes_with_auth(auth)?).launch().await?;
+/// ```
+#[derive(Clone, Default)]
+pub struct AdminAuth {
+    basic: Option<(String, String)>,
+    policy: Option<Arc<dyn AdminPolicy>>,
+    sso: Option<Arc<Sso>>,
+    login_url: Option<String>,
+}
+
+impl AdminAuth {
+    /// Authenticate admins with HTTP Basic auth.
+    pub fn basic(user: impl ToString, password: impl ToString) -> Self {
+        Self {
+            basic: Some((user.to_string(), password.to_string())),
+            ..Default::default()
+        }
+    }
+
+    /// Authenticate admins with the application session. The policy decides
+    /// which logged in users are admins.
+    pub fn session(policy: impl AdminPolicy + 'static) -> Self {
+        Self {
+            policy: Some(Arc::new(policy)),
+            ..Default::default()
+        }
+    }
+
+    /// Authenticate admins with single sign-on. Can be combined with session
+    /// authentication: admins can use either one.
+    pub fn sso(mut self, sso: Sso) -> Self {
+        self.sso = Some(Arc::new(sso));
+        self
+    }
+
+    /// Where to send visitors who aren't logged in, e.g. the application login page.
+    /// Default: the single sign-on page if configured, `401 - Unauthorized` otherwise.
+    pub fn login_url(mut self, url: impl ToString) -> Self {
+        self.login_url = Some(url.to_string());
+        self
+    }
+
+    /// Controllers handling single sign-on, added to the admin routes.
+    pub(crate) fn handlers(&self) -> Vec<Handler> {
+        match self.sso {
+            Some(ref sso) => sso::handlers(sso.clone()),
+            None => vec![],
+        }
+    }
+
+    /// Identify the admin making the request.
+    pub async fn identify(&self, request: &Request) -> Result<Option<Identity>, Error> {
+        if let Some((ref user, ref password)) = self.basic {
+            if let Some(Authorization::Basic {
+                user: ref u,
+                password: ref p,
+            }) = request.authorization()
+            {
+                if u == user && p == password {
+                    return Ok(Some(Identity {
+                        user_id: None,
+                        name: user.clone(),
+                    }));
+                }
+            }
+        }
+
+        if let Some(ref policy) = self.policy {
+            if let Some(user_id) = request.session_id().user_id() {
+                if policy.is_admin(request, user_id).await? {
+                    return Ok(Some(Identity {
+                        user_id: Some(user_id),
+                        name: user_id.to_string(),
+                    }));
+                }
+            }
+        }
+
+        if let Some(ref sso) = self.sso {
+            if let Some(email) = sso.logged_in(request)? {
+                return Ok(Some(Identity {
+                    user_id: None,
+                    name: email,
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl Authentication for AdminAuth {
+    async fn authorize(&self, request: &Request) -> Result<bool, Error> {
+        // Single sign-on pages are used to log in.
+        if self.sso.is_some() && request.path().path().starts_with(sso::PATH) {
+            return Ok(true);
+        }
+
+        let identity = match self.identify(request).await? {
+            Some(identity) => identity,
+            None => return Ok(false),
+        };
+
+        // Record changes, i.e. all requests that aren't reads.
+        if !matches!(request.method(), Method::Get | Method::Head) {
+            AuditLog::record(&identity, "action", request).await?;
+        }
+
+        Ok(true)
+    }
+
+    async fn denied(&self, request: &Request) -> Result<Response, Error> {
+        // Logged in, but not an admin.
+        if self.policy.is_some() && request.session().authenticated() {
+            let identity = Identity {
+                user_id: request.session_id().user_id(),
+                name: request.session_id().to_string(),
+            };
+            AuditLog::record(&identity, "denied", request).await?;
+            return Ok(Response::forbidden());
+        }
+
+        if let Some(ref url) = self.login_url {
+            Ok(Response::new().redirect(url))
+        } else if self.sso.is_some() {
+            Ok(Response::new().redirect(format!("{}/login", sso::PATH)))
+        } else if self.basic.is_some() {
+            Ok(Response::unauthorized(Some("Basic")))
+        } else {
+            Ok(Response::unauthorized(None))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    use serde_json::json;
+
+    async fn request(path: &str, headers: &str) -> Request {
+        Request::read(
+            "127.0.0.1:8000".parse().unwrap(),
+            format!("GET {} HTTP/1.1\r\n{}\r\n", path, headers).as_bytes(),
+        )
+        .await
+        .unwrap()
+    }
+
+    fn basic(user: &str, password: &str) -> String {
+        format!(
+            "Authorization: Basic {}\r\n",
+            STANDARD.encode(format!("{}:{}", user, password))
+        )
+    }
+
+    /// Cookie set once the admin logged in with single sign-on.
+    fn sso_login(email: &str, expires_in: Duration) -> String {
+        let login = json!({
+            "email": email,
+            "expires": (OffsetDateTime::now_utc() + expires_in).unix_timestamp(),
+        });
+        let cookie = CookieBuilder::new()
+            .name("rwf_admin")
+            .value(login.to_string())
+            .build();
+        let mut response = Response::new().private_cookie(cookie).unwrap();
+        let cookie = response.cookies().get("rwf_admin").unwrap();
+        format!("Cookie: rwf_admin={}\r\n", cookie.value())
+    }
+
+    fn sso() -> Sso {
+        Sso::new("https://accounts.example.com", "client", "secret").allow_domain("example.com")
+    }
+
+    #[tokio::test]
+    async fn test_identify() {
+        let auth = AdminAuth::basic("admin", "password").sso(sso());
+
+        let identity = auth
+            .identify(&request("/admin/", &basic("admin", "password")).await)
+            .await
+            .unwrap();
+        assert_eq!(
+            identity,
+            Some(Identity {
+                user_id: None,
+                name: "admin".into()
+            })
+        );
+
+        let identity = auth
+            .identify(
+                &request(
+                    "/admin/",
+                    &sso_login("alice@example.com", Duration::hours(1)),
+                )
+                .await,
+            )
+            .await
+            .unwrap();
+        assert_eq!(identity.unwrap().name, "alice@example.com");
+
+        for headers in [
+            String::new(),
+            basic("admin", "wrong"),
+            basic("other", "password"),
+            sso_login("alice@example.com", Duration::hours(-1)),
+            sso_login("bob@other.com", Duration::hours(1)),
+            "Cookie: rwf_admin=forged\r\n".to_string(),
+        ] {
+            let identity = auth
+                .identify(&request("/admin/", &headers).await)
+                .await
+                .unwrap();
+            assert!(identity.is_none(), "{}", headers);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_authorize_and_denied() {
+        let auth = AdminAuth::basic("admin", "password").sso(sso());
+
+        assert!(auth
+            .authorize(&request("/admin/", &basic("admin", "password")).await)
+            .await
+            .unwrap());
+        assert!(auth
+            .authorize(
+                &request(
+                    "/admin/",
+                    &sso_login("alice@example.com", Duration::hours(1))
+                )
+                .await
+            )
+            .await
+            .unwrap());
+
+        // Single sign-on pages are used to log in.
+        assert!(auth
+            .authorize(&request("/admin/sso/callback", "").await)
+            .await
+            .unwrap());
+
+        let anonymous = request("/admin/", "").await;
+        assert!(!auth.authorize(&anonymous).await.unwrap());
+        let response = auth.denied(&anonymous).await.unwrap();
+        assert_eq!(response.status().code(), 302);
+        assert_eq!(
+            response.headers().get("location"),
+            Some(&"/admin/sso/login".to_string())
+        );
+
+        // Without single sign-on, the SSO pages aren't public.
+        let auth = AdminAuth::basic("admin", "password");
+        assert!(!auth
+            .authorize(&request("/admin/sso/callback", "").await)
+            .await
+            .unwrap());
+        let response = auth.denied(&anonymous).await.unwrap();
+        assert_eq!(response.status().code(), 401);
+        assert_eq!(
+            response.headers().get("www-authenticate"),
+            Some(&"Basic".to_string())
+        );
+
+        let auth = AdminAuth::session(|user_id| user_id == 1).login_url("/login");
+        assert!(!auth.authorize(&anonymous).await.unwrap());
+        let response = auth.denied(&anonymous).await.unwrap();
+        assert_eq!(response.status().code(), 302);
+        assert_eq!(
+            response.headers().get("location"),
+            Some(&"/login".to_string())
+        );
+
+        let response = AdminAuth::default().denied(&anonymous).await.unwrap();
+        assert_eq!(response.status().code(), 401);
+    }
+}
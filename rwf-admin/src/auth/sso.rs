@@ -0,0 +1,464 @@
+//! Single sign-on with an OpenID Connect provider, e.g. Google Workspace, Okta, or Keycloak.
+//!
+//! Admins are sent to the provider to log in, and come back to `/admin/sso/callback`
+//! with a code, which is exchanged for an ID token containing their email. Only verified
+//! emails from allowed domains, or allowed individually, can access the admin panel.
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use rwf::crypto::random_string;
+use rwf::http::{client::Client, urlencode, Handler};
+use rwf::prelude::*;
+use tokio::sync::OnceCell;
+
+use super::{AuditLog, Identity};
+use crate::config::AdminConfig;
+
+/// Where single sign-on controllers are mounted.
+pub(crate) const PATH: &str = "/admin/sso";
+
+/// Private cookie holding the logged in admin.
+const SESSION_COOKIE: &str = "rwf_admin";
+
+/// Private cookie holding the state of a login in progress.
+const STATE_COOKIE: &str = "rwf_admin_sso_state";
+
+/// OpenID Connect provider configuration.
+pub struct Sso {
+    issuer: String,
+    client_id: String,
+    client_secret: String,
+    redirect_uri: Option<String>,
+    domains: Vec<String>,
+    emails: Vec<String>,
+    session_duration: Duration,
+    discovery: OnceCell<Discovery>,
+}
+
+#[derive(Deserialize)]
+struct Discovery {
+    authorization_endpoint: String,
+    token_endpoint: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LoginState {
+    state: String,
+    nonce: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Login {
+    email: String,
+    expires: i64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    iss: String,
+    aud: Audience,
+    exp: i64,
+    nonce: Option<String>,
+    email: Option<String>,
+    email_verified: Option<bool>,
+}
+
+impl Sso {
+    /// Configure single sign-on with the provider. The issuer is the provider URL,
+    /// e.g. `https://accounts.google.com`. The client ID and secret are created when registering
+    /// the admin panel with the provider.
+    pub fn new(
+        issuer: impl ToString,
+        client_id: impl ToString,
+        client_secret: impl ToString,
+    ) -> Self {
+        Self {
+            issuer: issuer.to_string().trim_end_matches('/').to_string(),
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            redirect_uri: None,
+            domains: vec![],
+            emails: vec![],
+            session_duration: Duration::hours(8),
+            discovery: OnceCell::new(),
+        }
+    }
+
+    /// URL the provider sends admins back to, registered with the provider.
+    /// Default: `<base_url>/admin/sso/callback`, with `base_url` set in the `[admin]`
+    /// section of the configuration.
+    pub fn redirect_uri(mut self, url: impl ToString) -> Self {
+        self.redirect_uri = Some(url.to_string());
+        self
+    }
+
+    /// Allow all emails from this domain, e.g. `example.com`.
+    pub fn allow_domain(mut self, domain: impl ToString) -> Self {
+        self.domains.push(domain.to_string().to_lowercase());
+        self
+    }
+
+    /// Allow this email.
+    pub fn allow_email(mut self, email: impl ToString) -> Self {
+        self.emails.push(email.to_string().to_lowercase());
+        self
+    }
+
+    /// How long admins stay logged in. Default: 8 hours.
+    pub fn session_duration(mut self, duration: Duration) -> Self {
+        self.session_duration = duration;
+        self
+    }
+
+    /// The email can access the admin panel. If no domains or emails
+    /// are allowed, nobody can.
+    pub fn allowed(&self, email: &str) -> bool {
+        let email = email.to_lowercase();
+        let domain = email.rsplit_once('@').map(|(_, domain)| domain);
+
+        self.emails.contains(&email)
+            || domain
+                .map(|domain| self.domains.iter().any(|allowed| allowed == domain))
+                .unwrap_or(false)
+    }
+
+    /// Email of the admin logged in with single sign-on, if any.
+    pub fn logged_in(&self, request: &Request) -> Result<Option<String>, Error> {
+        let cookie = match request.cookies().get_private(SESSION_COOKIE)? {
+            Some(cookie) => cookie,
+            None => return Ok(None),
+        };
+
+        let login = match serde_json::from_str::<Login>(cookie.value()) {
+            Ok(login) => login,
+            Err(_) => return Ok(None),
+        };
+
+        if login.expires > OffsetDateTime::now_utc().unix_timestamp() && self.allowed(&login.email)
+        {
+            Ok(Some(login.email))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn discovery(&self) -> Result<&Discovery, Error> {
+        self.discovery
+            .get_or_try_init(|| async {
+                let url = format!("{}/.well-known/openid-configuration", self.issuer);
+                let response = Client::get(url).send().await.map_err(Error::new)?;
+                response.json::<Discovery>().map_err(Error::new)
+            })
+            .await
+    }
+
+    /// The callback URL is configured, since the `Host` header is chosen by the client.
+    fn callback_url(&self) -> Result<String, Error> {
+        if let Some(ref url) = self.redirect_uri {
+            return Ok(url.clone());
+        }
+
+        match Config::section::<AdminConfig>()?.base_url {
+            Some(ref base_url) => Ok(format!(
+                "{}{}/callback",
+                base_url.trim_end_matches('/'),
+                PATH
+            )),
+            None => Err(rwf::config::Error::Missing("admin.base_url".into()).into()),
+        }
+    }
+
+    /// Check the ID token and get the admin's email from it.
+    ///
+    /// The token comes straight from the provider's token endpoint over TLS,
+    /// so its signature doesn't need to be verified.
+    fn verify(&self, id_token: &str, nonce: &str) -> Option<String> {
+        let payload = id_token.split('.').nth(1)?;
+        let payload = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+        let claims = serde_json::from_slice::<Claims>(&payload).ok()?;
+
+        let audience = match claims.aud {
+            Audience::One(ref aud) => aud == &self.client_id,
+            Audience::Many(ref aud) => aud.contains(&self.client_id),
+        };
+
+        if claims.iss.trim_end_matches('/') != self.issuer
+            || !audience
+            || claims.exp < OffsetDateTime::now_utc().unix_timestamp()
+            || claims.nonce.as_deref() != Some(nonce)
+            || claims.email_verified != Some(true)
+        {
+            return None;
+        }
+
+        claims.email.filter(|email| self.allowed(email))
+    }
+}
+
+/// Single sign-on controllers, mounted in the admin engine.
+pub(crate) fn handlers(sso: Arc<Sso>) -> Vec<Handler> {
+    vec![
+        SsoLogin { sso: sso.clone() }.route("/sso/login"),
+        SsoCallback { sso: sso.clone() }.route("/sso/callback"),
+        SsoLogout { sso }.route("/sso/logout"),
+    ]
+}
+
+/// Redirects to the provider login page.
+struct SsoLogin {
+    sso: Arc<Sso>,
+}
+
+#[async_trait]
+impl Controller for SsoLogin {
+    async fn handle(&self, _request: &Request) -> Result<Response, Error> {
+        let discovery = self.sso.discovery().await?;
+        let state = LoginState {
+            state: random_string(32),
+            nonce: random_string(32),
+        };
+
+        let url = format!(
+            "{}?response_type=code&client_id={}&redirect_uri={}&scope={}&state={}&nonce={}",
+            discovery.authorization_endpoint,
+            urlencode(&self.sso.client_id),
+            urlencode(&self.sso.callback_url()?),
+            urlencode("openid email profile"),
+            state.state,
+            state.nonce,
+        );
+
+        let cookie = CookieBuilder::new()
+            .name(STATE_COOKIE)
+            .value(serde_json::to_string(&state)?)
+            .path("/admin")
+            .max_age(Duration::minutes(10))
+            .http_only()
+            .lax()
+            .build();
+
+        Ok(Response::new().redirect(url).private_cookie(cookie)?)
+    }
+}
+
+/// Receives the admin back from the provider and logs them in.
+struct SsoCallback {
+    sso: Arc<Sso>,
+}
+
+impl SsoCallback {
+    /// Exchange the code for the admin's email.
+    async fn login(&self, request: &Request) -> Result<Option<String>, Error> {
+        let state = match request.cookies().get_private(STATE_COOKIE)? {
+            Some(cookie) => match serde_json::from_str::<LoginState>(cookie.value()) {
+                Ok(state) => state,
+                Err(_) => return Ok(None),
+            },
+            None => return Ok(None),
+        };
+
+        let code = request.query().get::<String>("code");
+        let returned = request.query().get::<String>("state");
+
+        let code = match (code, returned) {
+            (Some(code), Some(returned)) if returned == state.state => code,
+            _ => return Ok(None),
+        };
+
+        let discovery = self.sso.discovery().await?;
+        let redirect_uri = self.sso.callback_url()?;
+        let response = Client::post(&discovery.token_endpoint)
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", &code),
+                ("redirect_uri", &redirect_uri),
+                ("client_id", &self.sso.client_id),
+                ("client_secret", &self.sso.client_secret),
+            ])
+            .send()
+            .await
+            .map_err(Error::new)?;
+
+        if !response.ok() {
+            return Ok(None);
+        }
+
+        let token = response.json::<TokenResponse>().map_err(Error::new)?;
+
+        Ok(self.sso.verify(&token.id_token, &state.nonce))
+    }
+}
+
+#[async_trait]
+impl Controller for SsoCallback {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let email = match self.login(request).await? {
+            Some(email) => email,
+            None => {
+                let identity = Identity {
+                    user_id: None,
+                    name: "unknown".into(),
+                };
+                AuditLog::record(&identity, "login_failed", request).await?;
+                return Ok(Response::forbidden());
+            }
+        };
+
+        let identity = Identity {
+            user_id: None,
+            name: email.clone(),
+        };
+        AuditLog::record(&identity, "login", request).await?;
+
+        let login = Login {
+            email,
+            expires: (OffsetDateTime::now_utc() + self.sso.session_duration).unix_timestamp(),
+        };
+
+        let cookie = CookieBuilder::new()
+            .name(SESSION_COOKIE)
+            .value(serde_json::to_string(&login)?)
+            .path("/admin")
+            .max_age(self.sso.session_duration)
+            .http_only()
+            .lax()
+            .build();
+
+        Ok(Response::new().redirect("/admin/").private_cookie(cookie)?)
+    }
+}
+
+/// Logs the admin out.
+struct SsoLogout {
+    sso: Arc<Sso>,
+}
+
+#[async_trait]
+impl Controller for SsoLogout {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        if let Some(email) = self.sso.logged_in(request)? {
+            let identity = Identity {
+                user_id: None,
+                name: email,
+            };
+            AuditLog::record(&identity, "logout", request).await?;
+        }
+
+        let cookie = CookieBuilder::new()
+            .name(SESSION_COOKIE)
+            .value("")
+            .path("/admin")
+            .max_age(Duration::ZERO)
+            .build();
+
+        Ok(Response::new().redirect("/").cookie(cookie))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn sso() -> Sso {
+        Sso::new("https://accounts.example.com/", "client", "secret")
+            .allow_domain("Example.com")
+            .allow_email("contractor@gmail.com")
+    }
+
+    fn id_token(claims: &serde_json::Value) -> String {
+        format!(
+            "e30.{}.signature",
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        )
+    }
+
+    #[test]
+    fn test_allowed() {
+        let sso = sso();
+        assert!(sso.allowed("alice@example.com"));
+        assert!(sso.allowed("Alice@EXAMPLE.com"));
+        assert!(sso.allowed("contractor@gmail.com"));
+        assert!(!sso.allowed("other@gmail.com"));
+        assert!(!sso.allowed("alice@example.com.evil.com"));
+        assert!(!sso.allowed("alice@sub.example.com"));
+        assert!(!sso.allowed("example.com"));
+
+        let nobody = Sso::new("https://accounts.example.com", "client", "secret");
+        assert!(!nobody.allowed("alice@example.com"));
+    }
+
+    #[test]
+    fn test_verify() {
+        let sso = sso();
+        let exp = OffsetDateTime::now_utc().unix_timestamp() + 60;
+        let claims = json!({
+            "iss": "https://accounts.example.com",
+            "aud": "client",
+            "exp": exp,
+            "nonce": "nonce",
+            "email": "alice@example.com",
+            "email_verified": true,
+        });
+
+        assert_eq!(
+            sso.verify(&id_token(&claims), "nonce"),
+            Some("alice@example.com".into())
+        );
+        assert!(sso.verify(&id_token(&claims), "other").is_none());
+
+        let mut many = claims.clone();
+        many["aud"] = json!(["other", "client"]);
+        assert!(sso.verify(&id_token(&many), "nonce").is_some());
+
+        for (claim, value) in [
+            ("iss", json!("https://other.example.com")),
+            ("aud", json!("other")),
+            ("aud", json!(["other"])),
+            ("exp", json!(exp - 120)),
+            ("nonce", json!("other")),
+            ("email", json!("bob@other.com")),
+            ("email_verified", json!(false)),
+            ("email_verified", json!(null)),
+        ] {
+            let mut rejected = claims.clone();
+            rejected[claim] = value;
+            assert!(
+                sso.verify(&id_token(&rejected), "nonce").is_none(),
+                "{}",
+                claim
+            );
+        }
+
+        // The provider didn't say the email is verified.
+        let mut unverified = claims.clone();
+        unverified.as_object_mut().unwrap().remove("email_verified");
+        assert!(sso.verify(&id_token(&unverified), "nonce").is_none());
+
+        assert!(sso.verify("garbage", "nonce").is_none());
+    }
+
+    #[test]
+    fn test_callback_url() {
+        let sso = sso().redirect_uri("https://example.com/admin/sso/callback");
+        assert_eq!(
+            sso.callback_url().unwrap(),
+            "https://example.com/admin/sso/callback"
+        );
+
+        // Not built from the Host header when the base URL isn't configured.
+        assert!(self::sso().callback_url().is_err());
+    }
+}
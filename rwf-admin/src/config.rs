@@ -0,0 +1,63 @@
+//! Admin panel settings, configured in the `[admin]` section of `rwf.toml`.
+//!
+//! ```toml
+//! [admin]
+//! sql_console = true
+//! statement_timeout = 5000
+//! base_url = "https://example.com"
+//! ```
+use rwf::config::ConfigSection;
+use serde::Deserialize;
+
+/// Admin panel settings.
+#[derive(Deserialize, Debug, Clone)]
+pub struct AdminConfig {
+    /// Enable the SQL console. Disabled by default, and only available
+    /// when the admin panel requires authentication.
+    #[serde(default)]
+    pub sql_console: bool,
+    /// How long queries run in the SQL console can take before they are cancelled.
+    /// Configured in milliseconds.
+    #[serde(default = "AdminConfig::default_statement_timeout")]
+    pub statement_timeout: usize,
+    /// Maximum number of rows shown by the SQL console.
+    #[serde(default = "AdminConfig::default_max_rows")]
+    pub max_rows: usize,
+    /// Public URL of the application, e.g. `https://example.com`. Used to build
+    /// the single sign-on callback URL.
+    #[serde(default)]
+    pub base_url: Option<String>,
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self {
+            sql_console: false,
+            statement_timeout: Self::default_statement_timeout(),
+            max_rows: Self::default_max_rows(),
+            base_url: None,
+        }
+    }
+}
+
+impl AdminConfig {
+    fn default_statement_timeout() -> usize {
+        5 * 1000
+    }
+
+    fn default_max_rows() -> usize {
+        500
+    }
+}
+
+impl ConfigSection for AdminConfig {
+    const NAME: &'static str = "admin";
+
+    fn validate(&self) -> Result<(), String> {
+        if self.sql_console && self.statement_timeout == 0 {
+            return Err("statement_timeout must be set when sql_console is enabled".into());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,20 @@
+use rwf::prelude::*;
+
+use crate::auth::AuditLog;
+
+#[derive(Default)]
+pub struct Audit;
+
+#[async_trait]
+impl Controller for Audit {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let mut conn = Pool::connection().await?;
+        let entries = AuditLog::recent(100).fetch_all(&mut conn).await?;
+
+        render!(request,
+            "templates/rwf_admin/audit.html",
+            "title" => "Audit log | Rust Web Framework",
+            "entries" => entries
+        )
+    }
+}
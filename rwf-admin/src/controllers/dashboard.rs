@@ -0,0 +1,82 @@
+use rwf::dashboard::{Dashboard as Widgets, WidgetLayout};
+use rwf::prelude::*;
+
+#[derive(Default, macros::PageController)]
+pub struct Dashboard;
+
+#[derive(Clone, macros::TemplateValue)]
+struct Panel {
+    name: String,
+    dom_id: String,
+    position: i32,
+    width: i32,
+    hidden: bool,
+    html: String,
+}
+
+impl Panel {
+    async fn load() -> Result<Vec<Self>, Error> {
+        let mut conn = Pool::connection().await?;
+        let mut panels = vec![];
+
+        for panel in Widgets::panels(&mut conn).await.map_err(Error::new)? {
+            let html = if panel.layout.hidden {
+                String::new()
+            } else {
+                match panel.widget.render(&mut conn).await {
+                    Ok(html) => html,
+                    Err(err) => format!(
+                        r#"<p class="text-danger mb-0">{}</p>"#,
+                        rwf::safe_html(&err.to_string())
+                    ),
+                }
+            };
+
+            panels.push(Panel {
+                name: panel.widget.name().to_string(),
+                dom_id: panel.widget.dom_id(),
+                position: panel.layout.position,
+                width: panel.layout.width,
+                hidden: panel.layout.hidden,
+                html,
+            });
+        }
+
+        Ok(panels)
+    }
+}
+
+#[async_trait]
+impl PageController for Dashboard {
+    async fn get(&self, request: &Request) -> Result<Response, Error> {
+        let panels = Panel::load().await?;
+
+        render!(request,
+            "templates/rwf_admin/dashboard.html",
+            "title" => "Dashboard | Rust Web Framework",
+            "panels" => panels
+        )
+    }
+
+    async fn post(&self, request: &Request) -> Result<Response, Error> {
+        let form = request.form_data()?;
+        let mut conn = Pool::connection().await?;
+
+        for widget in Widgets::widgets() {
+            let name = widget.name();
+            let position = form
+                .get::<i32>(&format!("position_{}", name))
+                .unwrap_or_default();
+            let width = form
+                .get::<i32>(&format!("width_{}", name))
+                .unwrap_or(widget.default_width());
+            let hidden = form.get::<String>(&format!("hidden_{}", name)).is_some();
+
+            WidgetLayout::arrange(name, position, width, hidden)
+                .execute(&mut conn)
+                .await?;
+        }
+
+        Ok(Response::new().redirect("/admin/dashboard"))
+    }
+}
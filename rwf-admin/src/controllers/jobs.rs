@@ -1,29 +1,67 @@
-use rwf::job::JobModel;
+use rwf::job::{Clock, JobModel};
+use rwf::model::Scope;
 use rwf::prelude::*;
+use rwf::view::Context;
 
+use crate::models::{JobLastQueued, JobRuntime, JobRuntimeSeries};
+
+/// Jobs in the queue. Without authentication, jobs can't be retried or discarded.
 #[derive(Default)]
-pub struct Jobs;
+pub struct Jobs {
+    authenticated: bool,
+}
+
+impl Jobs {
+    /// The admin panel requires authentication.
+    pub fn new(authenticated: bool) -> Self {
+        Self { authenticated }
+    }
+}
 
 #[derive(macros::Context)]
 struct JobsContext {
     queued: i64,
     running: i64,
-    errors: i64,
+    retrying: i64,
+    failed: i64,
+    succeeded: i64,
     latency: i64,
+    status: String,
     jobs: Vec<JobModel>,
+    manage: bool,
     title: String,
 }
 
 impl JobsContext {
-    pub async fn load() -> Result<Self, Error> {
+    pub async fn load(status: &str, manage: bool) -> Result<Self, Error> {
         let mut conn = Pool::connection().await?;
         let queued = JobModel::queued().count(&mut conn).await?;
-        let errors = JobModel::errors().count(&mut conn).await?;
         let running = JobModel::running().count(&mut conn).await?;
+        let retrying = JobModel::retrying().count(&mut conn).await?;
+        let failed = JobModel::dead().count(&mut conn).await?;
+        let succeeded = JobModel::succeeded().count(&mut conn).await?;
 
-        let jobs = JobModel::all()
-            .order("completed_at DESC NULLS FIRST")
-            .order("started_at DESC NULLS LAST")
+        let (status, jobs): (&str, Scope<JobModel>) = match status {
+            "queued" => ("queued", JobModel::queued().order(("start_after", "ASC"))),
+            "running" => ("running", JobModel::running().order(("started_at", "ASC"))),
+            "retrying" => (
+                "retrying",
+                JobModel::retrying().order(("start_after", "ASC")),
+            ),
+            "failed" => ("failed", JobModel::dead().order(("id", "DESC"))),
+            "succeeded" => (
+                "succeeded",
+                JobModel::succeeded().order(("completed_at", "DESC")),
+            ),
+            _ => (
+                "all",
+                JobModel::all()
+                    .order("completed_at DESC NULLS FIRST")
+                    .order("started_at DESC NULLS LAST"),
+            ),
+        };
+
+        let jobs = jobs
             .order(("id", "DESC"))
             .limit(25)
             .fetch_all(&mut conn)
@@ -43,10 +81,14 @@ impl JobsContext {
 
         Ok(Self {
             queued,
-            errors,
             running,
+            retrying,
+            failed,
+            succeeded,
             jobs,
             latency,
+            manage,
+            status: status.to_string(),
             title: format!("Jobs | Rust Web Framework"),
         })
     }
@@ -54,10 +96,229 @@ impl JobsContext {
 
 #[async_trait]
 impl Controller for Jobs {
-    async fn handle(&self, _request: &Request) -> Result<Response, Error> {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let status = request.query().get::<String>("status").unwrap_or_default();
         let template = Template::load("templates/rwf_admin/jobs.html")?;
-        let context = JobsContext::load().await?;
+        // The request is needed for the CSRF token of the retry and discard forms.
+        let context = Context::try_from(JobsContext::load(&status, self.authenticated).await?)?
+            .with_request(request)?;
+
+        Ok(Response::new().html(template.render(&context)?))
+    }
+}
+
+/// Page the admin came from, or the list of all jobs.
+fn back(request: &Request) -> String {
+    match request
+        .form_data()
+        .ok()
+        .and_then(|form| form.get::<String>("status"))
+    {
+        Some(status) => format!("/admin/jobs?status={}", rwf::http::urlencode(&status)),
+        None => "/admin/jobs".to_string(),
+    }
+}
+
+/// Run a failed or queued job again now, or all failed jobs.
+#[derive(Default)]
+pub struct RetryJob {
+    authenticated: bool,
+}
+
+impl RetryJob {
+    /// The admin panel requires authentication.
+    pub fn new(authenticated: bool) -> Self {
+        Self { authenticated }
+    }
+}
+
+#[async_trait]
+impl Controller for RetryJob {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        if !request.post() {
+            return Ok(Response::method_not_allowed());
+        }
+
+        // Anyone can use the admin panel without authentication.
+        if !self.authenticated {
+            return Ok(Response::forbidden());
+        }
+
+        let form = request.form_data()?;
+        let mut conn = Pool::connection().await?;
+
+        match form.get::<i64>("id") {
+            Some(id) => JobModel::retry(id).execute(&mut conn).await?,
+            None => JobModel::retry_dead().execute(&mut conn).await?,
+        };
+
+        Ok(Response::new().redirect(back(request)))
+    }
+}
+
+/// Remove a failed or queued job from the queue.
+#[derive(Default)]
+pub struct DiscardJob {
+    authenticated: bool,
+}
+
+impl DiscardJob {
+    /// The admin panel requires authentication.
+    pub fn new(authenticated: bool) -> Self {
+        Self { authenticated }
+    }
+}
+
+#[async_trait]
+impl Controller for DiscardJob {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        if !request.post() {
+            return Ok(Response::method_not_allowed());
+        }
+
+        // Anyone can use the admin panel without authentication.
+        if !self.authenticated {
+            return Ok(Response::forbidden());
+        }
+
+        let id = request.form_data()?.get_required::<i64>("id")?;
+        let mut conn = Pool::connection().await?;
+        JobModel::discard(id).fetch_all(&mut conn).await?;
+
+        Ok(Response::new().redirect(back(request)))
+    }
+}
+
+/// Runtime of the jobs which finished recently.
+#[derive(Default)]
+pub struct JobsRuntime;
+
+#[async_trait]
+impl Controller for JobsRuntime {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let hours = request
+            .query()
+            .get::<i64>("hours")
+            .unwrap_or(24)
+            .clamp(1, 24 * 30);
+        let mut conn = Pool::connection().await?;
+        let stats = JobRuntime::stats(hours).fetch_all(&mut conn).await?;
+        let series = JobRuntimeSeries::series(hours).fetch_all(&mut conn).await?;
+
+        render!(request, "templates/rwf_admin/jobs_runtime.html",
+            "title" => "Job runtime | Rust Web Framework",
+            "stats" => serde_json::to_value(&stats)?,
+            "series" => serde_json::to_value(&series)?,
+            "status" => "runtime",
+            "interval" => match hours {
+                1 => "Last hour".into(),
+                24 => "Last 24 hours".into(),
+                168 => "Last 7 days".into(),
+                h => format!("Last {} hours", h),
+            }
+        )
+    }
+}
+
+/// Scheduled job in the schedule overview.
+#[derive(Clone, macros::TemplateValue)]
+struct ScheduleEntry {
+    name: String,
+    schedule: String,
+    args: String,
+    next_run: String,
+    last_queued: String,
+}
+
+fn timestamp(time: OffsetDateTime) -> String {
+    let format =
+        time::macros::format_description!("[year]-[month]-[day] [hour]:[minute]:[second] UTC");
+    time.to_offset(time::UtcOffset::UTC)
+        .format(format)
+        .unwrap_or_default()
+}
+
+/// Jobs run on a schedule by the clock.
+#[derive(Default)]
+pub struct JobsSchedule;
+
+#[async_trait]
+impl Controller for JobsSchedule {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let clock = Clock::current();
+        let jobs = clock.as_ref().map(|clock| clock.jobs()).unwrap_or_default();
+        let names = jobs
+            .iter()
+            .map(|job| job.job().job_name().to_string())
+            .collect::<Vec<_>>();
+
+        let mut conn = Pool::connection().await?;
+        let last_queued = JobLastQueued::for_jobs(&names).fetch_all(&mut conn).await?;
+
+        let now = OffsetDateTime::now_utc();
+        let schedule = jobs
+            .iter()
+            .map(|job| {
+                let name = job.job().job_name().to_string();
+                let last_queued = last_queued
+                    .iter()
+                    .find(|last| last.name == name)
+                    .map(|last| timestamp(last.created_at))
+                    .unwrap_or_default();
+
+                ScheduleEntry {
+                    schedule: job.timing().to_string(),
+                    args: job.args().to_string(),
+                    next_run: job
+                        .timing()
+                        .next_run(&now)
+                        .map(timestamp)
+                        .unwrap_or_default(),
+                    last_queued,
+                    name,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        render!(request, "templates/rwf_admin/jobs_schedule.html",
+            "title" => "Job schedule | Rust Web Framework",
+            "schedule" => schedule,
+            "clock" => clock.is_some(),
+            "status" => "schedule"
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rwf::http::Server;
+    use rwf::testing::TestClient;
+
+    #[tokio::test]
+    async fn test_manage_requires_auth() {
+        let client = TestClient::new(Server::new(crate::routes().unwrap()));
+
+        for path in ["/admin/jobs/retry", "/admin/jobs/discard"] {
+            client
+                .post(path)
+                .form(&[("id", "1")])
+                .send()
+                .await
+                .unwrap()
+                .assert_status(403);
+        }
 
-        Ok(Response::new().html(template.render(context)?))
+        // With authentication, the request is handled: the job ID is missing.
+        let client = TestClient::new(Server::new(vec![
+            DiscardJob::new(true).route("/admin/jobs/discard")
+        ]));
+        let response = client
+            .post("/admin/jobs/discard")
+            .form(&[("status", "failed")])
+            .send()
+            .await
+            .unwrap();
+        assert_ne!(response.status(), 403);
     }
 }
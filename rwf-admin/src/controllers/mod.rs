@@ -1,6 +1,10 @@
 // This file is automatically generated by rwf-cli.
 // Manual modifications to this file will not be preserved.
+pub mod audit;
+pub mod console;
+pub mod dashboard;
 pub mod index;
 pub mod jobs;
 pub mod models;
 pub mod requests;
+pub mod resources;
@@ -53,6 +53,8 @@ impl PageController for ModelController {
             } else {
                 ""
             };
+            let publishable =
+                !order_by.is_empty() && columns.iter().any(|c| c.column_name == "published_at");
 
             let columns = columns
                 .into_iter()
@@ -95,6 +97,7 @@ impl PageController for ModelController {
                     "create_columns" => create_columns,
                     "selected_columns" => selected_columns,
                     "page" => page,
+                    "publishable" => publishable,
                 )
             }
         }
@@ -162,3 +165,37 @@ impl PageController for NewModelController {
         Ok(Response::new().redirect(format!("/admin/models/model?name={}", table_name)))
     }
 }
+
+#[derive(Default)]
+pub struct PublishController;
+
+#[async_trait]
+impl Controller for PublishController {
+    async fn handle(&self, req: &Request) -> Result<Response, Error> {
+        if !req.post() {
+            return Ok(Response::method_not_allowed());
+        }
+
+        let form = req.form_data()?;
+        let table_name = form.get_required::<String>("rwf_table_name")?;
+        let id = form.get_required::<i64>("id")?;
+        let published_at = match form.get_required::<String>("action")?.as_str() {
+            "publish" => "NOW()",
+            "unpublish" => "NULL",
+            _ => return Ok(Response::bad_request()),
+        };
+
+        let query = format!(
+            "UPDATE \"{}\" SET published_at = {} WHERE id = {}",
+            table_name.escape(),
+            published_at,
+            id
+        );
+
+        Pool::pool()
+            .with_connection(|mut conn| async move { conn.query_cached(&query, &[]).await })
+            .await?;
+
+        Ok(Response::new().redirect(format!("/admin/models/model?name={}", table_name.escape())))
+    }
+}
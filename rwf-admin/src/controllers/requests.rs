@@ -1,6 +1,16 @@
-use crate::models::{RequestByCode, RequestsDuration};
+use crate::models::{RequestByCode, RequestError, RequestsDuration, RouteLatency};
 use rwf::prelude::*;
 
+/// Name of the time interval, shown in the interval dropdown.
+fn interval(minutes: i64) -> String {
+    match minutes {
+        60 => "Last hour".into(),
+        180 => "Last 3 hours".into(),
+        1440 => "Last 24 hours".into(),
+        m => format!("Last {} minutes", m),
+    }
+}
+
 #[derive(Default)]
 pub struct Requests;
 
@@ -20,19 +30,57 @@ impl Controller for Requests {
                 .await?
         };
 
-        let requests = serde_json::to_string(&requests)?;
-        let duration = serde_json::to_string(&duration)?;
+        let requests = serde_json::to_value(&requests)?;
+        let duration = serde_json::to_value(&duration)?;
 
         render!(request, "templates/rwf_admin/requests.html",
             "title" => "Requests | Rust Web Framework",
             "requests" => requests,
             "duration" => duration,
-            "interval" => match minutes {
-                60 => "Last hour".into(),
-                180 => "Last 3 hours".into(),
-                1440 => "Last 24 hours".into(),
-                m => format!("Last {} minutes", m)
-            }
+            "status" => "volume",
+            "interval" => interval(minutes)
+        )
+    }
+}
+
+/// Latency of each route, slowest first.
+#[derive(Default)]
+pub struct RequestsRoutes;
+
+#[async_trait]
+impl Controller for RequestsRoutes {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let minutes = request
+            .query()
+            .get::<i64>("minutes")
+            .unwrap_or(60)
+            .clamp(1, 60 * 24 * 30);
+        let mut conn = Pool::connection().await?;
+        let routes = RouteLatency::stats(minutes).fetch_all(&mut conn).await?;
+
+        render!(request, "templates/rwf_admin/requests_routes.html",
+            "title" => "Routes | Rust Web Framework",
+            "routes" => routes,
+            "status" => "routes",
+            "interval" => interval(minutes)
+        )
+    }
+}
+
+/// Requests which recently failed with a server error.
+#[derive(Default)]
+pub struct RequestsErrors;
+
+#[async_trait]
+impl Controller for RequestsErrors {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let mut conn = Pool::connection().await?;
+        let errors = RequestError::recent(50).fetch_all(&mut conn).await?;
+
+        render!(request, "templates/rwf_admin/requests_errors.html",
+            "title" => "Errors | Rust Web Framework",
+            "errors" => errors,
+            "status" => "errors"
         )
     }
 }
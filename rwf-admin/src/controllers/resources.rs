@@ -0,0 +1,274 @@
+use rwf::http::urlencode;
+use rwf::prelude::*;
+
+use crate::resource::{self, Action, AdminResource, ListParams};
+
+/// The admin can take the action on the resource. Without authentication,
+/// records can only be listed, since anyone can use the admin panel.
+async fn allowed(
+    resource: &dyn AdminResource,
+    request: &Request,
+    action: Action,
+    authenticated: bool,
+) -> Result<bool, Error> {
+    if action != Action::List && !authenticated {
+        return Ok(false);
+    }
+
+    resource.allowed(request, action).await
+}
+
+/// Resource named in the query string, if the admin can take the action on it.
+async fn find(
+    request: &Request,
+    action: Action,
+    authenticated: bool,
+) -> Result<Result<std::sync::Arc<dyn AdminResource>, Response>, Error> {
+    let name = request.query().get_required::<String>("name")?;
+
+    let resource = match resource::get(&name) {
+        Some(resource) => resource,
+        None => return Ok(Err(Response::not_found())),
+    };
+
+    if !allowed(resource.as_ref(), request, action, authenticated).await? {
+        return Ok(Err(Response::forbidden()));
+    }
+
+    Ok(Ok(resource))
+}
+
+fn list_url(name: &str) -> String {
+    format!("/admin/resources/list?name={}", urlencode(name))
+}
+
+#[derive(Default)]
+pub struct ResourcesController;
+
+#[async_trait]
+impl Controller for ResourcesController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let mut resources = vec![];
+
+        for resource in resource::resources() {
+            if resource.allowed(request, Action::List).await? {
+                resources.push(resource.name().to_string());
+            }
+        }
+
+        render!(request,
+            "templates/rwf_admin/resources.html",
+            "title" => "Resources | Rust Web Framework",
+            "resources" => resources
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct ResourceController {
+    authenticated: bool,
+}
+
+impl ResourceController {
+    /// The admin panel requires authentication.
+    pub fn new(authenticated: bool) -> Self {
+        Self { authenticated }
+    }
+}
+
+#[async_trait]
+impl Controller for ResourceController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let resource = match find(request, Action::List, self.authenticated).await? {
+            Ok(resource) => resource,
+            Err(response) => return Ok(response),
+        };
+
+        let listing = resource
+            .list(request, &ListParams::from_request(request))
+            .await?;
+        let name = resource.name();
+
+        render!(request,
+            "templates/rwf_admin/resource.html",
+            "title" => format!("{} | Rust Web Framework", name),
+            "name" => name,
+            "columns" => listing.columns,
+            "rows" => listing.rows,
+            "total" => listing.total,
+            "page" => listing.page,
+            "pages" => listing.pages,
+            "search" => listing.search.unwrap_or_default(),
+            "searchable" => listing.searchable,
+            "sort" => listing.sort,
+            "descending" => listing.descending,
+            "can_create" => allowed(resource.as_ref(), request, Action::Create, self.authenticated).await?,
+            "can_update" => allowed(resource.as_ref(), request, Action::Update, self.authenticated).await?,
+            "can_delete" => allowed(resource.as_ref(), request, Action::Delete, self.authenticated).await?,
+        )
+    }
+}
+
+#[derive(Default, macros::PageController)]
+pub struct NewResourceController {
+    authenticated: bool,
+}
+
+impl NewResourceController {
+    /// The admin panel requires authentication.
+    pub fn new(authenticated: bool) -> Self {
+        Self { authenticated }
+    }
+}
+
+#[async_trait]
+impl PageController for NewResourceController {
+    async fn get(&self, request: &Request) -> Result<Response, Error> {
+        let resource = match find(request, Action::Create, self.authenticated).await? {
+            Ok(resource) => resource,
+            Err(response) => return Ok(response),
+        };
+
+        match resource.fields(request, None).await? {
+            Some(fields) => form(request, resource.name(), None, fields),
+            None => Ok(Response::not_found()),
+        }
+    }
+
+    async fn post(&self, request: &Request) -> Result<Response, Error> {
+        let resource = match find(request, Action::Create, self.authenticated).await? {
+            Ok(resource) => resource,
+            Err(response) => return Ok(response),
+        };
+
+        match resource.create(request, &request.form_data()?).await? {
+            Some(fields) => form(request, resource.name(), None, fields),
+            None => Ok(Response::new().redirect(list_url(resource.name()))),
+        }
+    }
+}
+
+#[derive(Default, macros::PageController)]
+pub struct EditResourceController {
+    authenticated: bool,
+}
+
+impl EditResourceController {
+    /// The admin panel requires authentication.
+    pub fn new(authenticated: bool) -> Self {
+        Self { authenticated }
+    }
+}
+
+#[async_trait]
+impl PageController for EditResourceController {
+    async fn get(&self, request: &Request) -> Result<Response, Error> {
+        let resource = match find(request, Action::Update, self.authenticated).await? {
+            Ok(resource) => resource,
+            Err(response) => return Ok(response),
+        };
+        let id = request.query().get_required::<String>("id")?;
+
+        match resource.fields(request, Some(&id)).await? {
+            Some(fields) => form(request, resource.name(), Some(&id), fields),
+            None => Ok(Response::not_found()),
+        }
+    }
+
+    async fn post(&self, request: &Request) -> Result<Response, Error> {
+        let resource = match find(request, Action::Update, self.authenticated).await? {
+            Ok(resource) => resource,
+            Err(response) => return Ok(response),
+        };
+        let id = request.query().get_required::<String>("id")?;
+
+        // Records outside of the scope can't be edited.
+        if resource.fields(request, Some(&id)).await?.is_none() {
+            return Ok(Response::not_found());
+        }
+
+        match resource.update(request, &id, &request.form_data()?).await? {
+            Some(fields) => form(request, resource.name(), Some(&id), fields),
+            None => Ok(Response::new().redirect(list_url(resource.name()))),
+        }
+    }
+}
+
+fn form(
+    request: &Request,
+    name: &str,
+    id: Option<&str>,
+    fields: Vec<resource::Field>,
+) -> Result<Response, Error> {
+    let title = match id {
+        Some(_) => "Edit record",
+        None => "New record",
+    };
+    let action = match id {
+        Some(id) => format!(
+            "/admin/resources/edit?name={}&id={}",
+            urlencode(name),
+            urlencode(id)
+        ),
+        None => format!("/admin/resources/new?name={}", urlencode(name)),
+    };
+
+    render!(request,
+        "templates/rwf_admin/resource_form.html",
+        "title" => format!("{} | {} | Rust Web Framework", title, name),
+        "heading" => title,
+        "name" => name,
+        "id" => id.unwrap_or_default(),
+        "action" => action,
+        "fields" => fields,
+    )
+}
+
+#[derive(Default, macros::PageController)]
+pub struct DeleteResourceController {
+    authenticated: bool,
+}
+
+impl DeleteResourceController {
+    /// The admin panel requires authentication.
+    pub fn new(authenticated: bool) -> Self {
+        Self { authenticated }
+    }
+}
+
+#[async_trait]
+impl PageController for DeleteResourceController {
+    /// Ask the admin to confirm.
+    async fn get(&self, request: &Request) -> Result<Response, Error> {
+        let resource = match find(request, Action::Delete, self.authenticated).await? {
+            Ok(resource) => resource,
+            Err(response) => return Ok(response),
+        };
+        let id = request.query().get_required::<String>("id")?;
+
+        match resource.fields(request, Some(&id)).await? {
+            Some(fields) => render!(request,
+                "templates/rwf_admin/resource_delete.html",
+                "title" => format!("Delete record | {} | Rust Web Framework", resource.name()),
+                "name" => resource.name(),
+                "id" => id,
+                "fields" => fields,
+            ),
+            None => Ok(Response::not_found()),
+        }
+    }
+
+    async fn post(&self, request: &Request) -> Result<Response, Error> {
+        let resource = match find(request, Action::Delete, self.authenticated).await? {
+            Ok(resource) => resource,
+            Err(response) => return Ok(response),
+        };
+        let id = request.query().get_required::<String>("id")?;
+
+        if resource.delete(request, &id).await? {
+            Ok(Response::new().redirect(list_url(resource.name())))
+        } else {
+            Ok(Response::not_found())
+        }
+    }
+}
@@ -7,37 +7,126 @@ use controllers::*;
 
 mod models;
 
+pub mod auth;
+pub use auth::{AdminAuth, AdminPolicy, Sso};
+
+pub mod config;
+pub use config::AdminConfig;
+
+pub mod resource;
+pub use resource::{resource, Action, Resource, ResourcePolicy};
+
+/// Admin routes without authentication. Records of registered resources
+/// can be listed, but not changed, and jobs can't be retried or discarded.
 pub fn routes() -> Result<Vec<Handler>, Error> {
     Ok(vec![engine!("/admin" => engine()), static_files()?])
 }
 
+/// Admin routes protected with authentication.
+pub fn routes_with_auth(auth: AdminAuth) -> Result<Vec<Handler>, Error> {
+    Ok(vec![
+        engine!("/admin" => engine_with_auth(auth)),
+        static_files()?,
+    ])
+}
+
 pub fn engine() -> Engine {
-    Engine::new(vec![
+    Engine::new(handlers(None)).remount(&Path::parse("/admin").unwrap())
+}
+
+/// Admin engine protected with authentication. Changes made
+/// by admins are recorded in the audit log.
+pub fn engine_with_auth(auth: AdminAuth) -> Engine {
+    let mut handlers = handlers(Some(&auth));
+    handlers.extend(auth.handlers());
+
+    Engine::new(handlers)
+        .remount(&Path::parse("/admin").unwrap())
+        .auth(auth.handler())
+}
+
+fn handlers(auth: Option<&AdminAuth>) -> Vec<Handler> {
+    let authenticated = auth.is_some();
+
+    vec![
         route!("/" => index::Index),
-        route!("/jobs" => jobs::Jobs),
+        route!("/dashboard" => dashboard::Dashboard),
+        jobs::Jobs::new(authenticated).route("/jobs"),
+        jobs::RetryJob::new(authenticated).route("/jobs/retry"),
+        jobs::DiscardJob::new(authenticated).route("/jobs/discard"),
+        route!("/jobs/runtime" => jobs::JobsRuntime),
+        route!("/jobs/schedule" => jobs::JobsSchedule),
         route!("/requests" => requests::Requests),
+        route!("/requests/routes" => requests::RequestsRoutes),
+        route!("/requests/errors" => requests::RequestsErrors),
+        route!("/audit" => audit::Audit),
+        console::Console::new(auth.cloned()).route("/console"),
         route!("/models" => controllers::models::ModelsController),
         route!("/models/model" => controllers::models::ModelController),
         route!("/models/new" => controllers::models::NewModelController),
-    ])
-    .remount(&Path::parse("/admin").unwrap())
+        route!("/models/publish" => controllers::models::PublishController),
+        route!("/resources" => resources::ResourcesController),
+        resources::ResourceController::new(authenticated).route("/resources/list"),
+        resources::NewResourceController::new(authenticated).route("/resources/new"),
+        resources::EditResourceController::new(authenticated).route("/resources/edit"),
+        resources::DeleteResourceController::new(authenticated).route("/resources/delete"),
+    ]
 }
 
 pub fn install() -> Result<(), Error> {
     use rwf::view::Templates;
 
+    // Check the admin settings when the server starts.
+    Config::register::<AdminConfig>();
+
     Templates::cache().preload_str(
         "templates/rwf_admin/requests.html",
         include_str!("../templates/rwf_admin/requests.html"),
     )?;
+    Templates::cache().preload_str(
+        "templates/rwf_admin/requests_nav.html",
+        include_str!("../templates/rwf_admin/requests_nav.html"),
+    )?;
+    Templates::cache().preload_str(
+        "templates/rwf_admin/requests_routes.html",
+        include_str!("../templates/rwf_admin/requests_routes.html"),
+    )?;
+    Templates::cache().preload_str(
+        "templates/rwf_admin/requests_errors.html",
+        include_str!("../templates/rwf_admin/requests_errors.html"),
+    )?;
     Templates::cache().preload_str(
         "templates/rwf_admin/model_pages.html",
         include_str!("../templates/rwf_admin/model_pages.html"),
     )?;
+    Templates::cache().preload_str(
+        "templates/rwf_admin/dashboard.html",
+        include_str!("../templates/rwf_admin/dashboard.html"),
+    )?;
+    Templates::cache().preload_str(
+        "templates/rwf_admin/audit.html",
+        include_str!("../templates/rwf_admin/audit.html"),
+    )?;
     Templates::cache().preload_str(
         "templates/rwf_admin/jobs.html",
         include_str!("../templates/rwf_admin/jobs.html"),
     )?;
+    Templates::cache().preload_str(
+        "templates/rwf_admin/jobs_nav.html",
+        include_str!("../templates/rwf_admin/jobs_nav.html"),
+    )?;
+    Templates::cache().preload_str(
+        "templates/rwf_admin/jobs_runtime.html",
+        include_str!("../templates/rwf_admin/jobs_runtime.html"),
+    )?;
+    Templates::cache().preload_str(
+        "templates/rwf_admin/jobs_schedule.html",
+        include_str!("../templates/rwf_admin/jobs_schedule.html"),
+    )?;
+    Templates::cache().preload_str(
+        "templates/rwf_admin/console.html",
+        include_str!("../templates/rwf_admin/console.html"),
+    )?;
     Templates::cache().preload_str(
         "templates/rwf_admin/head.html",
         include_str!("../templates/rwf_admin/head.html"),
@@ -62,6 +151,22 @@ pub fn install() -> Result<(), Error> {
         "templates/rwf_admin/models.html",
         include_str!("../templates/rwf_admin/models.html"),
     )?;
+    Templates::cache().preload_str(
+        "templates/rwf_admin/resources.html",
+        include_str!("../templates/rwf_admin/resources.html"),
+    )?;
+    Templates::cache().preload_str(
+        "templates/rwf_admin/resource.html",
+        include_str!("../templates/rwf_admin/resource.html"),
+    )?;
+    Templates::cache().preload_str(
+        "templates/rwf_admin/resource_form.html",
+        include_str!("../templates/rwf_admin/resource_form.html"),
+    )?;
+    Templates::cache().preload_str(
+        "templates/rwf_admin/resource_delete.html",
+        include_str!("../templates/rwf_admin/resource_delete.html"),
+    )?;
     Templates::cache().preload_str(
         "templates/rwf_admin/footer.html",
         include_str!("../templates/rwf_admin/footer.html"),
@@ -89,6 +194,10 @@ pub fn static_files() -> Result<Handler, Error> {
             "/static/rwf_admin/js/requests_controller.js",
             include_bytes!("../static/rwf_admin/js/requests_controller.js"),
         )
+        .preload(
+            "/static/rwf_admin/js/jobs_controller.js",
+            include_bytes!("../static/rwf_admin/js/jobs_controller.js"),
+        )
         .preload(
             "/static/rwf_admin/js/bootstrap.min.js.map",
             include_bytes!("../static/rwf_admin/js/bootstrap.min.js.map"),
@@ -1,9 +1,9 @@
-use rwf::controller::BasicAuth;
 use rwf::{
     controller::{StaticFiles, TurboStream},
     http::{self, Server},
     prelude::*,
 };
+use rwf_admin::AdminAuth;
 use std::path::PathBuf;
 
 #[derive(Default)]
@@ -24,12 +24,10 @@ async fn main() -> Result<(), http::Error> {
     // Enable HMR.
     rwf::hmr::hmr(PathBuf::from("templates"));
 
-    // Basic auth is just an example, it's not secure. I would recommend using SessionAuth
-    // and checking that the user is an admin using an internal check.
-    let admin = rwf_admin::engine().auth(AuthHandler::new(BasicAuth {
-        user: "admin".to_string(),
-        password: "admin".to_string(),
-    }));
+    // Basic auth is just an example, it's not secure. I would recommend using
+    // AdminAuth::session and checking that the user is an admin using an internal check,
+    // or single sign-on with your identity provider.
+    let admin = rwf_admin::engine_with_auth(AdminAuth::basic("admin", "admin"));
 
     Server::new(vec![
         route!("/" => Redirect),
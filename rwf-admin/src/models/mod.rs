@@ -203,3 +203,180 @@ impl RequestsDuration {
         )
     }
 }
+
+/// Runtime of the jobs which finished recently, in milliseconds.
+#[derive(Clone, macros::Model, Serialize)]
+pub struct JobRuntime {
+    pub name: String,
+    pub count: i64,
+    pub avg: f64,
+    pub p95: f64,
+    pub max: f64,
+}
+
+impl JobRuntime {
+    pub fn stats(hours: i64) -> Scope<Self> {
+        Self::find_by_sql(
+            "WITH runtimes AS (
+                SELECT
+                    name,
+                    EXTRACT(EPOCH FROM completed_at - started_at)::float8 * 1000.0 AS runtime
+                FROM rwf_jobs
+                WHERE
+                    completed_at > now() - ($1::bigint || ' hours')::interval
+                    AND started_at IS NOT NULL
+            )
+            SELECT
+                name,
+                COUNT(*)::bigint AS count,
+                round(AVG(runtime)::numeric, 1)::float8 AS avg,
+                round((percentile_cont(0.95) WITHIN GROUP (ORDER BY runtime))::numeric, 1)::float8 AS p95,
+                round(MAX(runtime)::numeric, 1)::float8 AS max
+            FROM runtimes
+            GROUP BY name
+            ORDER BY name",
+            &[hours.to_value()],
+        )
+    }
+}
+
+/// Average runtime of each job, in milliseconds, by minute or hour.
+#[derive(Clone, macros::Model, Serialize)]
+pub struct JobRuntimeSeries {
+    pub name: String,
+    pub runtime: f64,
+    #[serde(with = "time::serde::rfc2822")]
+    pub created_at: OffsetDateTime,
+}
+
+impl JobRuntimeSeries {
+    pub fn series(hours: i64) -> Scope<Self> {
+        // Charts of more than a few hours are more readable by hour.
+        let unit = if hours > 3 { "hour" } else { "minute" };
+
+        Self::find_by_sql(
+            "SELECT
+                name,
+                AVG(EXTRACT(EPOCH FROM completed_at - started_at))::float8 * 1000.0 AS runtime,
+                date_trunc($2::text, completed_at) AS created_at
+            FROM rwf_jobs
+            WHERE
+                completed_at > now() - ($1::bigint || ' hours')::interval
+                AND started_at IS NOT NULL
+            GROUP BY 1, 3
+            ORDER BY 3, 1",
+            &[hours.to_value(), unit.to_value()],
+        )
+    }
+}
+
+/// When each job was last added to the queue.
+#[derive(Clone, macros::Model)]
+pub struct JobLastQueued {
+    pub name: String,
+    pub created_at: OffsetDateTime,
+}
+
+impl JobLastQueued {
+    pub fn for_jobs(names: &[String]) -> Scope<Self> {
+        Self::find_by_sql(
+            "SELECT name, MAX(created_at) AS created_at
+            FROM rwf_jobs
+            WHERE name = ANY($1)
+            GROUP BY name",
+            &[names.to_value()],
+        )
+    }
+}
+
+/// Request volume and latency of each route, in milliseconds.
+#[derive(Clone, macros::Model)]
+pub struct RouteLatency {
+    pub method: String,
+    pub route: String,
+    pub count: i64,
+    pub errors: i64,
+    pub avg: f64,
+    pub p95: f64,
+    pub max: f64,
+}
+
+impl RouteLatency {
+    /// Routes served in the last `minutes`, slowest first.
+    pub fn stats(minutes: i64) -> Scope<Self> {
+        Self::find_by_sql(
+            "SELECT
+                method,
+                COALESCE(route, path) AS route,
+                COUNT(*)::bigint AS count,
+                (COUNT(*) FILTER (WHERE code >= 500))::bigint AS errors,
+                round(AVG(duration)::numeric, 1)::float8 AS avg,
+                round((percentile_cont(0.95) WITHIN GROUP (ORDER BY duration))::numeric, 1)::float8 AS p95,
+                round(MAX(duration)::numeric, 1)::float8 AS max
+            FROM rwf_requests
+            WHERE created_at > now() - ($1::bigint || ' minutes')::interval
+            GROUP BY 1, 2
+            ORDER BY p95 DESC, count DESC
+            LIMIT 100",
+            &[minutes.to_value()],
+        )
+    }
+}
+
+/// Request which failed with a server error.
+#[derive(Clone, macros::Model)]
+pub struct RequestError {
+    pub id: Option<i64>,
+    pub method: String,
+    pub path: String,
+    pub route: String,
+    pub code: i32,
+    pub duration: f32,
+    pub error: String,
+    pub backtrace: String,
+    pub created_at: OffsetDateTime,
+}
+
+impl RequestError {
+    /// Most recent server errors.
+    pub fn recent(limit: i64) -> Scope<Self> {
+        Self::find_by_sql(
+            "SELECT
+                id,
+                method,
+                path,
+                COALESCE(route, '') AS route,
+                code,
+                duration,
+                COALESCE(error, '') AS error,
+                COALESCE(backtrace, '') AS backtrace,
+                created_at
+            FROM rwf_requests
+            WHERE code >= 500
+            ORDER BY created_at DESC
+            LIMIT $1::bigint",
+            &[limit.to_value()],
+        )
+    }
+}
+
+/// Query run in the SQL console.
+#[derive(Clone, macros::Model, Debug)]
+#[table_name("rwf_admin_console_queries")]
+pub struct ConsoleQuery {
+    pub id: Option<i64>,
+    /// Admin who ran the query.
+    pub actor: String,
+    pub query: String,
+    pub created_at: OffsetDateTime,
+}
+
+impl ConsoleQuery {
+    /// Queries most recently run by the admin.
+    pub fn history(actor: &str, limit: i64) -> Scope<Self> {
+        Self::all()
+            .filter("actor", actor)
+            .order(("id", "DESC"))
+            .limit(limit)
+    }
+}
@@ -0,0 +1,820 @@
+//! Administration of the application's models.
+//!
+//! Registered models get pages listing their records, with search, sorting and pagination,
+//! forms to create and edit records, generated from the types of the table columns, and
+//! a confirmation page to delete them:
+//!
+//! ```ignore
+//! rwf_admin::resource::<User>()
+//!     .list_columns(&["email", "name", "created_at"])
+//!     .search(&["email", "name"])
+//!     .register();
+//! ```
+//!
+//! Records can be scoped, e.g. to the admin's organization, and actions restricted with a policy:
+//!
+//! ```ignore
+//! rwf_admin::resource::<Order>()
+//!     .scope(|request, orders| orders.filter("organization_id", organization_id(request)))
+//!     .policy(|_request: &Request, action| action != Action::Delete)
+//!     .register();
+//! ```
+//!
+//! Columns with types the forms don't support, e.g. arrays, are only listed.
+use std::marker::PhantomData;
+use std::sync::{Arc, RwLock};
+
+use once_cell::sync::Lazy;
+use rwf::http::FormData;
+use rwf::model::{Escape, Query, Value};
+use rwf::prelude::*;
+use rwf::view::ToTemplateValue;
+use time::{macros::format_description, PrimitiveDateTime};
+
+use crate::models::TableColumn;
+
+static RESOURCES: Lazy<RwLock<Vec<Arc<dyn AdminResource>>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+/// Timestamps are edited as `2024-10-17 10:23:34`, in UTC.
+const TIMESTAMP_FORMAT: &[time::format_description::FormatItem<'static>] =
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+
+/// What the admin is doing with the records of a model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Listing and searching records.
+    List,
+    /// Creating a record.
+    Create,
+    /// Editing a record.
+    Update,
+    /// Deleting a record.
+    Delete,
+}
+
+/// Decides which actions admins can take on the records of a model.
+///
+/// Implemented for closures, e.g. `|_request: &Request, action| action == Action::List`.
+#[async_trait]
+pub trait ResourcePolicy: Send + Sync {
+    /// The admin making the request can take the action.
+    async fn allowed(&self, request: &Request, action: Action) -> Result<bool, Error>;
+}
+
+#[async_trait]
+impl<F> ResourcePolicy for F
+where
+    F: Fn(&Request, Action) -> bool + Send + Sync,
+{
+    async fn allowed(&self, request: &Request, action: Action) -> Result<bool, Error> {
+        Ok(self(request, action))
+    }
+}
+
+type ScopeFn<T> = Arc<dyn Fn(&Request, Query<T>) -> Query<T> + Send + Sync>;
+
+/// Model administered in the admin panel. Create it with [`resource`].
+pub struct Resource<T: Model> {
+    name: String,
+    list_columns: Vec<String>,
+    search_columns: Vec<String>,
+    per_page: i64,
+    scope: Option<ScopeFn<T>>,
+    policy: Option<Arc<dyn ResourcePolicy>>,
+    _model: PhantomData<fn() -> T>,
+}
+
+/// Administer the model in the admin panel. Call [`Resource::register`] to add it.
+pub fn resource<T: Model + Send + Sync + 'static>() -> Resource<T> {
+    Resource {
+        name: T::table_name().to_string(),
+        list_columns: vec![],
+        search_columns: vec![],
+        per_page: 25,
+        scope: None,
+        policy: None,
+        _model: PhantomData,
+    }
+}
+
+impl<T: Model + Send + Sync + 'static> Resource<T> {
+    /// Name of the resource in URLs. Default: the table name.
+    pub fn name(mut self, name: impl ToString) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Columns shown in the list of records. Default: the primary key and all columns of the model.
+    pub fn list_columns(mut self, columns: &[&str]) -> Self {
+        self.list_columns = columns.iter().map(|column| column.to_string()).collect();
+        self
+    }
+
+    /// Columns searched for the text entered in the search box. Search is disabled if not set.
+    pub fn search(mut self, columns: &[&str]) -> Self {
+        self.search_columns = columns.iter().map(|column| column.to_string()).collect();
+        self
+    }
+
+    /// Number of records on each page of the list. Default: 25.
+    pub fn per_page(mut self, per_page: i64) -> Self {
+        self.per_page = per_page.max(1);
+        self
+    }
+
+    /// Only show, edit and delete the records found by the query, e.g. the records
+    /// of the admin's organization.
+    pub fn scope(
+        mut self,
+        scope: impl Fn(&Request, Query<T>) -> Query<T> + Send + Sync + 'static,
+    ) -> Self {
+        self.scope = Some(Arc::new(scope));
+        self
+    }
+
+    /// Decide which actions admins can take. All actions are allowed by default.
+    pub fn policy(mut self, policy: impl ResourcePolicy + 'static) -> Self {
+        self.policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Add the resource to the admin panel, replacing the resource with the same name.
+    pub fn register(self) {
+        let mut resources = RESOURCES.write().unwrap();
+        resources.retain(|resource| resource.name() != self.name);
+        resources.push(Arc::new(self));
+    }
+
+    fn query(&self, request: &Request) -> Query<T> {
+        match self.scope {
+            Some(ref scope) => scope(request, T::all()),
+            None => T::all(),
+        }
+    }
+
+    fn sortable(&self, column: &str) -> bool {
+        column == T::primary_key() || T::column_names().contains(&column)
+    }
+
+    /// Find the record in the resource's scope.
+    async fn find(&self, request: &Request, id: &str) -> Result<Option<T>, Error> {
+        let mut conn = Pool::connection().await?;
+        Ok(self
+            .query(request)
+            .filter(T::primary_key(), id_value(id))
+            .take_one()
+            .fetch_optional(&mut conn)
+            .await?)
+    }
+
+    /// Columns of the model which can be edited with forms.
+    async fn editable_columns(&self) -> Result<Vec<TableColumn>, Error> {
+        Ok(TableColumn::for_table(T::table_name())
+            .await?
+            .into_iter()
+            .filter(|column| {
+                T::column_names().contains(&column.column_name.as_str())
+                    && !column.skip()
+                    && input_type(&column.data_type).is_some()
+            })
+            .collect())
+    }
+}
+
+/// Resource with the model type erased, so resources of all models can be stored together.
+#[async_trait]
+pub(crate) trait AdminResource: Send + Sync {
+    /// Name of the resource in URLs.
+    fn name(&self) -> &str;
+
+    /// The admin can take the action.
+    async fn allowed(&self, request: &Request, action: Action) -> Result<bool, Error>;
+
+    /// Page of records.
+    async fn list(&self, request: &Request, params: &ListParams) -> Result<Listing, Error>;
+
+    /// Form fields, with the values of the record if it's edited.
+    async fn fields(
+        &self,
+        request: &Request,
+        id: Option<&str>,
+    ) -> Result<Option<Vec<Field>>, Error>;
+
+    /// Create a record with the values submitted in the form. Returns the form with errors
+    /// if some values are invalid.
+    async fn create(&self, request: &Request, form: &FormData)
+        -> Result<Option<Vec<Field>>, Error>;
+
+    /// Update the record with the values submitted in the form. Returns the form with errors
+    /// if some values are invalid.
+    async fn update(
+        &self,
+        request: &Request,
+        id: &str,
+        form: &FormData,
+    ) -> Result<Option<Vec<Field>>, Error>;
+
+    /// Delete the record. Returns false if it doesn't exist.
+    async fn delete(&self, request: &Request, id: &str) -> Result<bool, Error>;
+}
+
+#[async_trait]
+impl<T: Model + Send + Sync + 'static> AdminResource for Resource<T> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn allowed(&self, request: &Request, action: Action) -> Result<bool, Error> {
+        match self.policy {
+            Some(ref policy) => policy.allowed(request, action).await,
+            None => Ok(true),
+        }
+    }
+
+    async fn list(&self, request: &Request, params: &ListParams) -> Result<Listing, Error> {
+        let columns = if self.list_columns.is_empty() {
+            std::iter::once(T::primary_key())
+                .chain(T::column_names().iter().copied())
+                .map(|column| column.to_string())
+                .collect()
+        } else {
+            self.list_columns.clone()
+        };
+
+        let mut query = self.query(request);
+
+        let search = params
+            .search
+            .as_deref()
+            .map(|search| search.trim())
+            .filter(|search| !search.is_empty() && !self.search_columns.is_empty());
+
+        if let Some(search) = search {
+            query = query.filter_contains(&self.search_columns, search);
+        }
+
+        let mut conn = Pool::connection().await?;
+        let total = query.clone().count(&mut conn).await?;
+
+        let sort = params
+            .sort
+            .as_deref()
+            .filter(|column| self.sortable(column))
+            .unwrap_or(T::primary_key());
+        let direction = if params.descending { "DESC" } else { "ASC" };
+        let page = params.page.max(1);
+
+        let records = query
+            .order((sort, direction))
+            .limit(self.per_page)
+            .offset((page - 1) * self.per_page)
+            .fetch_all(&mut conn)
+            .await?;
+
+        let mut rows = vec![];
+        for record in records {
+            let values = record.values();
+            let mut row = vec![];
+
+            for column in &columns {
+                let value = if column == T::primary_key() {
+                    record.id()
+                } else {
+                    T::column_names()
+                        .iter()
+                        .position(|name| name == column)
+                        .and_then(|position| values.get(position).cloned())
+                        .unwrap_or(Value::Null)
+                };
+                row.push(value.to_template_value()?);
+            }
+
+            rows.push(Row {
+                id: input_value(&record.id()),
+                cells: row,
+            });
+        }
+
+        Ok(Listing {
+            columns,
+            rows,
+            total,
+            page,
+            pages: (total + self.per_page - 1) / self.per_page,
+            search: search.map(|search| search.to_string()),
+            searchable: !self.search_columns.is_empty(),
+            sort: sort.to_string(),
+            descending: params.descending,
+        })
+    }
+
+    async fn fields(
+        &self,
+        request: &Request,
+        id: Option<&str>,
+    ) -> Result<Option<Vec<Field>>, Error> {
+        let record = match id {
+            Some(id) => match self.find(request, id).await? {
+                Some(record) => Some(record),
+                None => return Ok(None),
+            },
+            None => None,
+        };
+
+        let fields = self
+            .editable_columns()
+            .await?
+            .into_iter()
+            .map(|column| {
+                let value = match record {
+                    Some(ref record) => T::column_names()
+                        .iter()
+                        .position(|name| *name == column.column_name)
+                        .and_then(|position| record.values().get(position).map(input_value))
+                        .unwrap_or_default(),
+                    None => column.column_default.trim().to_string(),
+                };
+
+                Field::new(&column, value)
+            })
+            .collect();
+
+        Ok(Some(fields))
+    }
+
+    async fn create(
+        &self,
+        _request: &Request,
+        form: &FormData,
+    ) -> Result<Option<Vec<Field>>, Error> {
+        let (fields, attributes) = self.submitted(form).await?;
+
+        if fields.iter().any(|field| field.error.is_some()) {
+            return Ok(Some(fields));
+        }
+
+        let mut conn = Pool::connection().await?;
+        T::create(&attributes).fetch(&mut conn).await?;
+
+        Ok(None)
+    }
+
+    async fn update(
+        &self,
+        request: &Request,
+        id: &str,
+        form: &FormData,
+    ) -> Result<Option<Vec<Field>>, Error> {
+        let (fields, attributes) = self.submitted(form).await?;
+
+        if fields.iter().any(|field| field.error.is_some()) {
+            return Ok(Some(fields));
+        }
+
+        if !attributes.is_empty() {
+            let mut conn = Pool::connection().await?;
+            self.query(request)
+                .filter(T::primary_key(), id_value(id))
+                .update_all(&attributes)
+                .execute(&mut conn)
+                .await?;
+        }
+
+        Ok(None)
+    }
+
+    async fn delete(&self, request: &Request, id: &str) -> Result<bool, Error> {
+        if self.find(request, id).await?.is_none() {
+            return Ok(false);
+        }
+
+        let mut conn = Pool::connection().await?;
+        let deleted = T::find_by_sql(
+            format!(
+                r#"DELETE FROM "{}" WHERE "{}" = $1 RETURNING *"#,
+                T::table_name().escape(),
+                T::primary_key().escape()
+            ),
+            &[id_value(id)],
+        )
+        .fetch_all(&mut conn)
+        .await?;
+
+        Ok(!deleted.is_empty())
+    }
+}
+
+impl<T: Model + Send + Sync + 'static> Resource<T> {
+    /// Form fields with the submitted values and their errors, and the values converted to the column types.
+    async fn submitted(
+        &self,
+        form: &FormData,
+    ) -> Result<(Vec<Field>, Vec<(String, Value)>), Error> {
+        let mut fields = vec![];
+        let mut attributes = vec![];
+
+        for column in self.editable_columns().await? {
+            let submitted = form.get::<String>(&column.column_name);
+            let mut field = Field::new(&column, submitted.clone().unwrap_or_default());
+
+            match parse_value(&column, submitted.as_deref()) {
+                Ok(value) => attributes.push((column.column_name.clone(), value)),
+                Err(error) => field.error = Some(error),
+            }
+
+            fields.push(field);
+        }
+
+        Ok((fields, attributes))
+    }
+}
+
+/// Search, sorting and page of the list of records, from the query string.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ListParams {
+    pub search: Option<String>,
+    pub sort: Option<String>,
+    pub descending: bool,
+    pub page: i64,
+}
+
+impl ListParams {
+    pub fn from_request(request: &Request) -> Self {
+        let query = request.query();
+
+        Self {
+            search: query.get::<String>("q"),
+            sort: query.get::<String>("sort"),
+            descending: query.get::<String>("order").as_deref() == Some("desc"),
+            page: query.get::<i64>("page").unwrap_or(1),
+        }
+    }
+}
+
+/// Page of records.
+pub(crate) struct Listing {
+    pub columns: Vec<String>,
+    pub rows: Vec<Row>,
+    pub total: i64,
+    pub page: i64,
+    pub pages: i64,
+    pub search: Option<String>,
+    pub searchable: bool,
+    pub sort: String,
+    pub descending: bool,
+}
+
+/// Record in the list, with the values of the listed columns.
+#[derive(Clone)]
+pub(crate) struct Row {
+    pub id: String,
+    pub cells: Vec<rwf::view::Value>,
+}
+
+impl ToTemplateValue for Row {
+    fn to_template_value(&self) -> Result<rwf::view::Value, rwf::view::Error> {
+        Ok(rwf::view::Value::Hash(
+            [
+                ("id".to_string(), self.id.to_template_value()?),
+                (
+                    "cells".to_string(),
+                    rwf::view::Value::List(self.cells.clone()),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        ))
+    }
+}
+
+/// Form field generated from a table column.
+#[derive(Debug, Clone)]
+pub(crate) struct Field {
+    pub name: String,
+    pub data_type: String,
+    /// `text`, `number`, `checkbox`, `textarea`, etc.
+    pub input: &'static str,
+    pub value: String,
+    pub required: bool,
+    pub error: Option<String>,
+}
+
+impl Field {
+    fn new(column: &TableColumn, value: String) -> Self {
+        Self {
+            name: column.column_name.clone(),
+            data_type: column.data_type.clone(),
+            input: input_type(&column.data_type).unwrap_or("text"),
+            value,
+            required: column.is_required && column.data_type != "boolean",
+            error: None,
+        }
+    }
+}
+
+impl ToTemplateValue for Field {
+    fn to_template_value(&self) -> Result<rwf::view::Value, rwf::view::Error> {
+        Ok(rwf::view::Value::Hash(
+            [
+                ("name".to_string(), self.name.to_template_value()?),
+                ("data_type".to_string(), self.data_type.to_template_value()?),
+                ("input".to_string(), self.input.to_template_value()?),
+                ("value".to_string(), self.value.to_template_value()?),
+                ("required".to_string(), self.required.to_template_value()?),
+                ("error".to_string(), self.error.to_template_value()?),
+            ]
+            .into_iter()
+            .collect(),
+        ))
+    }
+}
+
+/// Form input for the column type, if forms support it.
+fn input_type(data_type: &str) -> Option<&'static str> {
+    Some(match data_type {
+        "boolean" => "checkbox",
+        "smallint" | "integer" | "bigint" | "real" | "double precision" => "number",
+        "text" | "json" | "jsonb" => "textarea",
+        "character varying" | "character" | "uuid" | "inet" => "text",
+        "timestamp with time zone" | "timestamp without time zone" => "datetime",
+        _ => return None,
+    })
+}
+
+/// Convert the value submitted in the form to the column type.
+fn parse_value(column: &TableColumn, value: Option<&str>) -> Result<Value, String> {
+    let value = value.unwrap_or_default();
+
+    // Unchecked checkboxes aren't submitted.
+    if column.data_type == "boolean" {
+        return Ok(Value::Boolean(matches!(value, "on" | "true" | "1")));
+    }
+
+    if value.is_empty() && column.is_nullable {
+        return Ok(Value::Null);
+    }
+
+    let invalid = || format!("not a valid {}", column.data_type);
+
+    Ok(match column.data_type.as_str() {
+        "smallint" => Value::SmallInt(value.trim().parse().map_err(|_| invalid())?),
+        "integer" => Value::Int(value.trim().parse().map_err(|_| invalid())?),
+        "bigint" => Value::BigInt(value.trim().parse().map_err(|_| invalid())?),
+        "real" => Value::Real(value.trim().parse().map_err(|_| invalid())?),
+        "double precision" => Value::Float(value.trim().parse().map_err(|_| invalid())?),
+        "uuid" => Value::Uuid(value.trim().parse().map_err(|_| invalid())?),
+        "inet" => Value::IpAddr(value.trim().parse().map_err(|_| invalid())?),
+        "json" | "jsonb" => Value::Json(serde_json::from_str(value).map_err(|_| invalid())?),
+        "timestamp with time zone" => Value::TimestampT(
+            PrimitiveDateTime::parse(value.trim(), TIMESTAMP_FORMAT)
+                .map_err(|_| invalid())?
+                .assume_utc(),
+        ),
+        "timestamp without time zone" => Value::Timestamp(
+            PrimitiveDateTime::parse(value.trim(), TIMESTAMP_FORMAT).map_err(|_| invalid())?,
+        ),
+        _ => Value::String(value.to_string()),
+    })
+}
+
+/// Value of the column shown in the form.
+fn input_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Optional(value) => value.as_ref().as_ref().map(input_value).unwrap_or_default(),
+        Value::String(string) => string.clone(),
+        Value::Integer(int) | Value::BigInt(int) => int.to_string(),
+        Value::Int(int) => int.to_string(),
+        Value::SmallInt(int) => int.to_string(),
+        Value::Float(float) => float.to_string(),
+        Value::Real(float) => float.to_string(),
+        Value::Boolean(boolean) => boolean.to_string(),
+        Value::TimestampT(timestamp) => timestamp
+            .to_offset(time::UtcOffset::UTC)
+            .format(TIMESTAMP_FORMAT)
+            .unwrap_or_default(),
+        Value::Timestamp(timestamp) => timestamp.format(TIMESTAMP_FORMAT).unwrap_or_default(),
+        Value::Uuid(uuid) => uuid.to_string(),
+        Value::IpAddr(ip) => ip.to_string(),
+        Value::Json(json) => serde_json::to_string_pretty(json).unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+/// Primary key from the URL. Most tables use integers or UUIDs.
+fn id_value(id: &str) -> Value {
+    if let Ok(id) = id.parse::<i64>() {
+        Value::BigInt(id)
+    } else if let Ok(id) = id.parse::<Uuid>() {
+        Value::Uuid(id)
+    } else {
+        Value::String(id.to_string())
+    }
+}
+
+/// Registered resources, in the order they were registered.
+pub(crate) fn resources() -> Vec<Arc<dyn AdminResource>> {
+    RESOURCES.read().unwrap().clone()
+}
+
+/// Registered resource with this name.
+pub(crate) fn get(name: &str) -> Option<Arc<dyn AdminResource>> {
+    RESOURCES
+        .read()
+        .unwrap()
+        .iter()
+        .find(|resource| resource.name() == name)
+        .cloned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::controllers::resources::{DeleteResourceController, ResourceController};
+    use rwf::http::Server;
+    use rwf::testing::TestClient;
+
+    /// Resource with one record, which doesn't need the database.
+    struct Users;
+
+    #[async_trait]
+    impl AdminResource for Users {
+        fn name(&self) -> &str {
+            "users"
+        }
+
+        async fn allowed(&self, _request: &Request, _action: Action) -> Result<bool, Error> {
+            Ok(true)
+        }
+
+        async fn list(&self, _request: &Request, params: &ListParams) -> Result<Listing, Error> {
+            Ok(Listing {
+                columns: vec!["email".into()],
+                rows: vec![Row {
+                    id: "1".into(),
+                    cells: vec![rwf::view::Value::String("alice@example.com".into())],
+                }],
+                total: 1,
+                page: 1,
+                pages: 2,
+                search: params.search.clone(),
+                searchable: true,
+                sort: "email".into(),
+                descending: false,
+            })
+        }
+
+        async fn fields(
+            &self,
+            _request: &Request,
+            _id: Option<&str>,
+        ) -> Result<Option<Vec<Field>>, Error> {
+            Ok(None)
+        }
+
+        async fn create(
+            &self,
+            _request: &Request,
+            _form: &FormData,
+        ) -> Result<Option<Vec<Field>>, Error> {
+            Ok(None)
+        }
+
+        async fn update(
+            &self,
+            _request: &Request,
+            _id: &str,
+            _form: &FormData,
+        ) -> Result<Option<Vec<Field>>, Error> {
+            Ok(None)
+        }
+
+        async fn delete(&self, _request: &Request, _id: &str) -> Result<bool, Error> {
+            Ok(false)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_escapes_search() {
+        RESOURCES.write().unwrap().push(Arc::new(Users));
+
+        let client = TestClient::new(Server::new(vec![
+            ResourceController::new(true).route("/admin/resources/list")
+        ]));
+
+        let response = client
+            .get(r#"/admin/resources/list?name=users&q="><script>alert(1)</script>"#)
+            .send()
+            .await
+            .unwrap()
+            .assert_status(200);
+        let html = response.text();
+
+        assert!(!html.contains("<script>alert"));
+        assert!(html.contains("&quot;&gt;&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(html.contains("q=&quot;&gt;&lt;script&gt;alert%281%29&lt;%2Fscript&gt;"));
+    }
+
+    /// Resource which allows everything, to check the admin panel itself refuses changes.
+    struct Records;
+
+    #[async_trait]
+    impl AdminResource for Records {
+        fn name(&self) -> &str {
+            "records"
+        }
+
+        async fn allowed(&self, _request: &Request, _action: Action) -> Result<bool, Error> {
+            Ok(true)
+        }
+
+        async fn list(&self, _request: &Request, _params: &ListParams) -> Result<Listing, Error> {
+            Ok(Listing {
+                columns: vec!["name".into()],
+                rows: vec![Row {
+                    id: "1".into(),
+                    cells: vec![rwf::view::Value::String("first".into())],
+                }],
+                total: 1,
+                page: 1,
+                pages: 1,
+                search: None,
+                searchable: false,
+                sort: "name".into(),
+                descending: false,
+            })
+        }
+
+        async fn fields(
+            &self,
+            _request: &Request,
+            _id: Option<&str>,
+        ) -> Result<Option<Vec<Field>>, Error> {
+            Ok(Some(vec![]))
+        }
+
+        async fn create(
+            &self,
+            _request: &Request,
+            _form: &FormData,
+        ) -> Result<Option<Vec<Field>>, Error> {
+            Ok(None)
+        }
+
+        async fn update(
+            &self,
+            _request: &Request,
+            _id: &str,
+            _form: &FormData,
+        ) -> Result<Option<Vec<Field>>, Error> {
+            Ok(None)
+        }
+
+        async fn delete(&self, _request: &Request, _id: &str) -> Result<bool, Error> {
+            Ok(true)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_only_without_auth() {
+        RESOURCES.write().unwrap().push(Arc::new(Records));
+        let client = TestClient::new(Server::new(crate::routes().unwrap()));
+
+        let html = client
+            .get("/admin/resources/list?name=records")
+            .send()
+            .await
+            .unwrap()
+            .assert_status(200)
+            .text();
+        assert!(html.contains("first"));
+        assert!(!html.contains("/admin/resources/delete"));
+        assert!(!html.contains("/admin/resources/edit"));
+
+        for path in [
+            "/admin/resources/new?name=records",
+            "/admin/resources/edit?name=records&id=1",
+            "/admin/resources/delete?name=records&id=1",
+        ] {
+            client.get(path).send().await.unwrap().assert_status(403);
+            client
+                .post(path)
+                .form(&[("name", "changed")])
+                .send()
+                .await
+                .unwrap()
+                .assert_status(403);
+        }
+
+        // With authentication, the resource decides.
+        let client = TestClient::new(Server::new(vec![
+            DeleteResourceController::new(true).route("/admin/resources/delete")
+        ]));
+        client
+            .post("/admin/resources/delete?name=records&id=1")
+            .send()
+            .await
+            .unwrap()
+            .assert_status(302);
+    }
+}
@@ -0,0 +1,45 @@
+import { Controller } from "hotwired/stimulus";
+import "https://cdn.jsdelivr.net/npm/chart.js";
+
+export default class extends Controller {
+  static targets = ["series", "chart"];
+
+  connect() {
+    const series = JSON.parse(this.seriesTarget.innerHTML);
+    const times = Array.from(new Set(series.map((item) => item.created_at)));
+    const names = Array.from(new Set(series.map((item) => item.name)));
+
+    // One line per job, with gaps where the job didn't run.
+    const datasets = names.map((name) => {
+      const runtimes = new Map(
+        series
+          .filter((item) => item.name === name)
+          .map((item) => [item.created_at, item.runtime]),
+      );
+
+      return {
+        label: name,
+        data: times.map((time) => runtimes.get(time) ?? null),
+        spanGaps: true,
+      };
+    });
+
+    new Chart(this.chartTarget, {
+      type: "line",
+      data: {
+        labels: times.map((time) => new Date(time).toLocaleString()),
+        datasets,
+      },
+      options: {
+        scales: {
+          y: {
+            title: {
+              display: true,
+              text: "Average runtime (ms)",
+            },
+          },
+        },
+      },
+    });
+  }
+}
@@ -5,6 +5,7 @@ use std::path::PathBuf;
 
 use flate2::write::GzEncoder;
 use flate2::Compression;
+use rwf::assets::Manifest;
 use rwf::config::get_config;
 use rwf::config::Config;
 use tokio::process::Command;
@@ -99,6 +100,13 @@ pub async fn package(
         return Ok(());
     }
 
+    // Fingerprint assets now, not every time the app starts.
+    let assets = &get_config().assets;
+    if assets.directory.is_dir() {
+        Manifest::build(&assets.directory)?.save(assets.manifest())?;
+        packaging(format!("{} (asset manifest)", assets.manifest().display()));
+    }
+
     let archive = File::create("build.tar.gz")?;
     let enc = GzEncoder::new(archive, Compression::default());
     let mut tar = tar::Builder::new(enc);
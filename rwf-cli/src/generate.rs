@@ -0,0 +1,525 @@
+use rwf::controller::Error;
+use rwf::macros::context;
+use rwf::view::Template;
+use std::path::Path;
+use tokio::fs::{create_dir_all, File};
+use tokio::io::AsyncWriteExt;
+
+use crate::add::modules;
+use crate::logging::{created, error};
+use crate::migrate;
+
+/// Column type, e.g. `string` in `title:string`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    String,
+    Text,
+    Integer,
+    BigInt,
+    Float,
+    Boolean,
+    Uuid,
+    DateTime,
+}
+
+impl Kind {
+    fn parse(kind: &str) -> Option<Self> {
+        Some(match kind {
+            "string" => Kind::String,
+            "text" => Kind::Text,
+            "integer" | "int" => Kind::Integer,
+            "bigint" => Kind::BigInt,
+            "float" | "double" => Kind::Float,
+            "boolean" | "bool" => Kind::Boolean,
+            "uuid" => Kind::Uuid,
+            "datetime" | "timestamp" => Kind::DateTime,
+            _ => return None,
+        })
+    }
+
+    fn rust(&self) -> &'static str {
+        match self {
+            Kind::String | Kind::Text => "String",
+            Kind::Integer => "i32",
+            Kind::BigInt => "i64",
+            Kind::Float => "f64",
+            Kind::Boolean => "bool",
+            Kind::Uuid => "Uuid",
+            Kind::DateTime => "OffsetDateTime",
+        }
+    }
+
+    fn sql(&self) -> &'static str {
+        match self {
+            Kind::String => "VARCHAR",
+            Kind::Text => "TEXT",
+            Kind::Integer => "INTEGER",
+            Kind::BigInt => "BIGINT",
+            Kind::Float => "DOUBLE PRECISION",
+            Kind::Boolean => "BOOLEAN",
+            Kind::Uuid => "UUID",
+            Kind::DateTime => "TIMESTAMPTZ",
+        }
+    }
+
+    /// The ORM can bind `NULL` values of this type.
+    fn nullable(&self) -> bool {
+        !matches!(self, Kind::Float | Kind::Boolean)
+    }
+}
+
+/// Rust keywords, including reserved ones, which can't name struct fields.
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl",
+    "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "static", "struct", "super", "trait", "true", "try", "type", "typeof",
+    "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// Model field, e.g. `title:string` or `published_at:datetime?`.
+#[derive(Debug, Clone)]
+struct Field {
+    name: String,
+    kind: Kind,
+    optional: bool,
+}
+
+impl Field {
+    fn parse(field: &str) -> Result<Self, String> {
+        let (name, kind) = field.split_once(':').unwrap_or((field, "string"));
+        let (kind, optional) = match kind.strip_suffix('?') {
+            Some(kind) => (kind, true),
+            None => (kind, false),
+        };
+        let name = rwf::snake_case(name);
+
+        let kind = match Kind::parse(&kind.to_lowercase()) {
+            Some(kind) => kind,
+            None => return Err(format!("\"{}\" has unknown type \"{}\"", name, kind)),
+        };
+
+        if optional && !kind.nullable() {
+            return Err(format!(
+                "\"{}\" of type \"{}\" can't be optional",
+                name,
+                kind.sql()
+            ));
+        }
+
+        if name.is_empty() || name == "id" {
+            return Err(format!("\"{}\" is not a valid field name", field));
+        }
+
+        if KEYWORDS.contains(&name.as_str()) {
+            return Err(format!(
+                "\"{}\" is a Rust keyword and can't be a field name",
+                name
+            ));
+        }
+
+        Ok(Self {
+            name,
+            kind,
+            optional,
+        })
+    }
+
+    fn rust(&self) -> String {
+        if self.optional {
+            format!("Option<{}>", self.kind.rust())
+        } else {
+            self.kind.rust().to_string()
+        }
+    }
+
+    fn sql(&self) -> String {
+        let mut column = format!("{} {}", self.name, self.kind.sql());
+        if !self.optional {
+            column.push_str(" NOT NULL");
+
+            // Timestamps aren't sent in forms.
+            if !self.in_form() {
+                column.push_str(" DEFAULT NOW()");
+            }
+        }
+        column
+    }
+
+    /// The field is edited in forms. Timestamps are set by the database.
+    fn in_form(&self) -> bool {
+        self.kind != Kind::DateTime
+    }
+
+    /// Read the field from the submitted form.
+    fn form(&self) -> String {
+        let value = match (self.kind, self.optional) {
+            (Kind::Boolean, _) => format!("form.get::<String>(\"{}\").is_some()", self.name),
+            (_, true) => format!("form.get::<{}>(\"{}\")", self.kind.rust(), self.name),
+            _ => format!(
+                "form.get_required::<{}>(\"{}\")?",
+                self.kind.rust(),
+                self.name
+            ),
+        };
+
+        format!("(\"{}\", {}.to_value())", self.name, value)
+    }
+
+    /// HTML input editing the field.
+    fn input(&self) -> String {
+        let required = if self.optional { "" } else { " required" };
+        match self.kind {
+            Kind::Text => format!(
+                "<textarea name=\"{}\" id=\"{}\"{}></textarea>",
+                self.name, self.name, required
+            ),
+            Kind::Boolean => format!(
+                "<input type=\"checkbox\" name=\"{}\" id=\"{}\">",
+                self.name, self.name
+            ),
+            Kind::Integer | Kind::BigInt => format!(
+                "<input type=\"number\" name=\"{}\" id=\"{}\"{}>",
+                self.name, self.name, required
+            ),
+            Kind::Float => format!(
+                "<input type=\"number\" step=\"any\" name=\"{}\" id=\"{}\"{}>",
+                self.name, self.name, required
+            ),
+            _ => format!(
+                "<input type=\"text\" name=\"{}\" id=\"{}\"{}>",
+                self.name, self.name, required
+            ),
+        }
+    }
+}
+
+fn parse_fields(fields: &[String]) -> Option<Vec<Field>> {
+    let mut parsed = vec![];
+    for field in fields {
+        match Field::parse(field) {
+            Ok(field) => parsed.push(field),
+            Err(err) => {
+                error(err);
+                return None;
+            }
+        }
+    }
+    Some(parsed)
+}
+
+/// Names of the model, its table and its controller.
+struct Names {
+    /// Model, e.g. `BlogPost`.
+    model: String,
+    /// Model module, e.g. `blog_post`.
+    snake: String,
+    /// Table, controller module and URL, e.g. `blog_posts`.
+    plural: String,
+    /// Controller, e.g. `BlogPosts`.
+    controller: String,
+}
+
+impl Names {
+    fn new(name: &str) -> Self {
+        let snake = rwf::snake_case(name);
+        // Same as the `Model` derive names the table.
+        let plural = pluralizer::pluralize(&snake, 2, false);
+
+        Self {
+            model: rwf::pascal_case(&snake),
+            controller: rwf::pascal_case(&plural),
+            snake,
+            plural,
+        }
+    }
+}
+
+async fn write(path: &Path, content: &str, overwrite: bool) -> Result<bool, Error> {
+    if path.exists() && !overwrite {
+        error(format!(
+            "{} already exists, pass --overwrite to recreate it",
+            path.display()
+        ));
+        return Ok(false);
+    }
+
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).await?;
+    }
+
+    let mut file = File::create(path).await?;
+    file.write_all(content.as_bytes()).await?;
+    created(path.display().to_string());
+
+    Ok(true)
+}
+
+/// Generate a model and the migration creating its table.
+pub async fn model(name: &str, fields: &[String], overwrite: bool) {
+    if let Some(fields) = parse_fields(fields) {
+        if let Err(err) = model_internal(&Names::new(name), &fields, overwrite).await {
+            error(format!("failed to create model: {}", err));
+        }
+    }
+}
+
+/// Generate a model, its migration, a controller and its templates.
+pub async fn scaffold(name: &str, fields: &[String], overwrite: bool) {
+    if let Some(fields) = parse_fields(fields) {
+        let names = Names::new(name);
+        let result = match model_internal(&names, &fields, overwrite).await {
+            Ok(true) => scaffold_internal(&names, &fields, overwrite).await,
+            Ok(false) => Ok(()),
+            Err(err) => Err(err),
+        };
+
+        if let Err(err) = result {
+            error(format!("failed to create scaffold: {}", err));
+        }
+    }
+}
+
+async fn model_internal(names: &Names, fields: &[Field], overwrite: bool) -> Result<bool, Error> {
+    let mod_path = Path::new("src/models");
+    let path = mod_path.join(format!("{}.rs", names.snake));
+
+    if !write(&path, &model_source(names, fields)?, overwrite).await? {
+        return Ok(false);
+    }
+    modules(mod_path).await?;
+
+    let (up, down) = migration(names, fields);
+    migrate::create(&format!("create_{}", names.plural), &up, &down).await?;
+
+    Ok(true)
+}
+
+/// Source of the model module.
+fn model_source(names: &Names, fields: &[Field]) -> Result<String, Error> {
+    let tpl = Template::from_str(include_str!("templates/model.rs.tpl"))?;
+    Ok(tpl.render(&context!(
+        "name" => names.model.clone(),
+        "fields" => fields
+            .iter()
+            .map(|field| format!("pub {}: {},", field.name, field.rust()))
+            .collect::<Vec<_>>()
+    ))?)
+}
+
+/// Up and down migrations creating the model's table.
+fn migration(names: &Names, fields: &[Field]) -> (String, String) {
+    let mut columns = vec!["id BIGSERIAL PRIMARY KEY".to_string()];
+    columns.extend(fields.iter().map(|field| field.sql()));
+    let up = format!(
+        "CREATE TABLE {} (\n    {}\n);\n",
+        names.plural,
+        columns.join(",\n    ")
+    );
+    let down = format!("DROP TABLE {};\n", names.plural);
+
+    (up, down)
+}
+
+async fn scaffold_internal(names: &Names, fields: &[Field], overwrite: bool) -> Result<(), Error> {
+    let mod_path = Path::new("src/controllers");
+    let path = mod_path.join(format!("{}.rs", names.plural));
+
+    let tpl = Template::from_str(include_str!("templates/scaffold-controller.rs.tpl"))?;
+    let rendered = tpl.render(&context!(
+        "name" => names.controller.clone(),
+        "model" => names.model.clone(),
+        "module" => names.snake.clone(),
+        "plural" => names.plural.clone(),
+        "fields" => fields
+            .iter()
+            .filter(|field| field.in_form())
+            .map(|field| field.form())
+            .collect::<Vec<_>>()
+    ))?;
+
+    if !write(&path, &rendered, overwrite).await? {
+        return Ok(());
+    }
+    modules(mod_path).await?;
+
+    // Templates use the template language, so they are filled in without it.
+    let templates = Path::new("templates").join(&names.plural);
+    let columns = fields
+        .iter()
+        .map(|field| format!("<th>{}</th>", field.name))
+        .collect::<Vec<_>>()
+        .join("\n                ");
+    let cells = fields
+        .iter()
+        .map(|field| format!("<td><%= {}.{} %></td>", names.snake, field.name))
+        .collect::<Vec<_>>()
+        .join("\n                ");
+    let inputs = fields
+        .iter()
+        .filter(|field| field.in_form())
+        .map(|field| {
+            format!(
+                "<p>\n        <label for=\"{}\">{}</label>\n        {}\n    </p>",
+                field.name,
+                field.name,
+                field.input()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n    ");
+    let values = fields
+        .iter()
+        .map(|field| {
+            format!(
+                "<dt>{}</dt>\n    <dd><%= {}.{} %></dd>",
+                field.name, names.snake, field.name
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n    ");
+
+    let fill = |template: &str| {
+        template
+            .replace("__model__", &names.model)
+            .replace("__record__", &names.snake)
+            .replace("__plural__", &names.plural)
+            .replace("__columns__", &columns)
+            .replace("__cells__", &cells)
+            .replace("__inputs__", &inputs)
+            .replace("__values__", &values)
+    };
+
+    write(
+        &templates.join("index.html"),
+        &fill(include_str!("templates/scaffold/index.html")),
+        overwrite,
+    )
+    .await?;
+    write(
+        &templates.join("show.html"),
+        &fill(include_str!("templates/scaffold/show.html")),
+        overwrite,
+    )
+    .await?;
+
+    eprintln!(
+        "\nAdd the route to the server:\n\n    rest!(\"/{}\" => controllers::{}::{}),\n",
+        names.plural, names.plural, names.controller
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_field_parse() {
+        let field = Field::parse("title").unwrap();
+        assert_eq!(field.name, "title");
+        assert_eq!(field.kind, Kind::String);
+        assert!(!field.optional);
+
+        let field = Field::parse("PublishedAt:timestamp?").unwrap();
+        assert_eq!(field.name, "published_at");
+        assert_eq!(field.kind, Kind::DateTime);
+        assert!(field.optional);
+        assert_eq!(field.rust(), "Option<OffsetDateTime>");
+        assert_eq!(field.sql(), "published_at TIMESTAMPTZ");
+
+        let field = Field::parse("views:Int").unwrap();
+        assert_eq!(field.rust(), "i32");
+        assert_eq!(field.sql(), "views INTEGER NOT NULL");
+
+        let field = Field::parse("created_at:datetime").unwrap();
+        assert_eq!(field.sql(), "created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()");
+        assert!(!field.in_form());
+
+        assert!(Field::parse("title:varchar").is_err());
+        assert!(Field::parse("draft:bool?").is_err());
+        assert!(Field::parse("id:bigint").is_err());
+        assert!(Field::parse(":string").is_err());
+    }
+
+    #[test]
+    fn test_keywords() {
+        for name in ["type", "match", "Self", "async:string", "gen:text"] {
+            let err = Field::parse(name).unwrap_err();
+            assert!(err.contains("Rust keyword"), "{}", err);
+        }
+
+        assert!(Field::parse("kind").is_ok());
+        assert!(Field::parse("types").is_ok());
+    }
+
+    #[test]
+    fn test_form_and_input() {
+        let field = Field::parse("draft:bool").unwrap();
+        assert_eq!(
+            field.form(),
+            "(\"draft\", form.get::<String>(\"draft\").is_some().to_value())"
+        );
+        assert_eq!(
+            field.input(),
+            "<input type=\"checkbox\" name=\"draft\" id=\"draft\">"
+        );
+
+        let field = Field::parse("body:text?").unwrap();
+        assert_eq!(
+            field.form(),
+            "(\"body\", form.get::<String>(\"body\").to_value())"
+        );
+        assert_eq!(
+            field.input(),
+            "<textarea name=\"body\" id=\"body\"></textarea>"
+        );
+
+        let field = Field::parse("price:float").unwrap();
+        assert_eq!(
+            field.form(),
+            "(\"price\", form.get_required::<f64>(\"price\")?.to_value())"
+        );
+        assert_eq!(
+            field.input(),
+            "<input type=\"number\" step=\"any\" name=\"price\" id=\"price\" required>"
+        );
+    }
+
+    #[test]
+    fn test_names() {
+        for name in ["BlogPost", "blog_post", "blogPost"] {
+            let names = Names::new(name);
+            assert_eq!(names.model, "BlogPost");
+            assert_eq!(names.snake, "blog_post");
+            assert_eq!(names.plural, "blog_posts");
+            assert_eq!(names.controller, "BlogPosts");
+        }
+
+        let names = Names::new("category");
+        assert_eq!(names.model, "Category");
+        assert_eq!(names.plural, "categories");
+        assert_eq!(names.controller, "Categories");
+    }
+
+    #[test]
+    fn test_render() {
+        let names = Names::new("post");
+        let fields =
+            parse_fields(&["title:string".to_string(), "views:bigint?".to_string()]).unwrap();
+
+        let source = model_source(&names, &fields).unwrap();
+        assert!(source.contains("pub struct Post {"));
+        assert!(source.contains(
+            "    pub id: Option<i64>,\n    pub title: String,\n    pub views: Option<i64>,\n}"
+        ));
+
+        let (up, down) = migration(&names, &fields);
+        assert_eq!(
+            up,
+            "CREATE TABLE posts (\n    id BIGSERIAL PRIMARY KEY,\n    title VARCHAR NOT NULL,\n    views BIGINT\n);\n"
+        );
+        assert_eq!(down, "DROP TABLE posts;\n");
+    }
+}
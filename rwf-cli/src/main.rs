@@ -6,10 +6,14 @@ use std::path::{Path, PathBuf};
 
 mod add;
 mod deploy;
+mod generate;
 mod logging;
 mod migrate;
+mod new;
 mod remove;
+mod serve;
 mod setup;
+mod tasks;
 mod util;
 
 #[derive(Parser, Debug)]
@@ -21,17 +25,35 @@ struct Cli {
 
 #[derive(Subcommand, Debug)]
 enum Subcommands {
+    /// Create a new Rwf project
+    New {
+        #[arg(help = "Project name, e.g. myapp")]
+        name: String,
+    },
+
     Migrate(MigrateSubcommand),
 
     /// Setup the project for Rwf
     Setup,
 
+    /// Run the app, rebuilding and restarting it when the code changes
+    Serve {
+        #[arg(
+            long,
+            help = "Name of the binary to run, if the package has more than one"
+        )]
+        bin: Option<String>,
+    },
+
     /// Add a controller/view/model/all of the above
     Add(AddSubcommand),
 
     /// Remove a controller/view/model/all of the above
     Remove(RemoveSubcommand),
 
+    /// Generate a controller, a model, or a scaffold
+    Generate(GenerateSubcommand),
+
     /// Package the application into a tarball.
     Package {
         #[arg(
@@ -44,6 +66,17 @@ enum Subcommands {
         #[arg(long, short, help = "Target CPU architecture")]
         target: Option<String>,
     },
+
+    /// Run a maintenance task, e.g. vacuum
+    #[command(name = "tasks:run")]
+    TasksRun {
+        #[arg(help = "Task name")]
+        name: String,
+    },
+
+    /// List maintenance tasks
+    #[command(name = "tasks:list")]
+    TasksList,
 }
 
 #[derive(Args, Debug)]
@@ -83,6 +116,13 @@ enum Migrate {
         #[arg(long, short, help = "Migration name", default_value = "unnamed")]
         name: String,
     },
+
+    /// Add the migration creating the tables of an optional feature,
+    /// or list the features if no name is given
+    Feature {
+        #[arg(help = "Feature name, e.g. sessions")]
+        name: Option<String>,
+    },
 }
 
 #[derive(Args, Debug)]
@@ -100,6 +140,45 @@ struct RemoveSubcommand {
     command: Remove,
 }
 
+#[derive(Args, Debug)]
+struct GenerateSubcommand {
+    #[command(subcommand)]
+    command: Generate,
+
+    #[arg(long, short, help = "Overwrite if file exists")]
+    overwrite: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Generate {
+    /// Create new controller.
+    Controller {
+        #[arg(help = "Controller name, e.g. Posts")]
+        name: String,
+
+        #[arg(long, short, help = "Create a page controller")]
+        page: bool,
+    },
+
+    /// Create new model and the migration creating its table.
+    Model {
+        #[arg(help = "Model name, e.g. Post")]
+        name: String,
+
+        #[arg(help = "Fields, e.g. title:string body:text published_at:datetime?")]
+        fields: Vec<String>,
+    },
+
+    /// Create new model, its migration, a controller and its templates.
+    Scaffold {
+        #[arg(help = "Model name, e.g. Post")]
+        name: String,
+
+        #[arg(help = "Fields, e.g. title:string body:text published_at:datetime?")]
+        fields: Vec<String>,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 enum Add {
     /// Create new controller.
@@ -126,14 +205,21 @@ async fn main() {
     // std::env::set_var("RWF_LOG_QUERIES", "1");
     Logger::init();
 
+    let args = Cli::parse();
+
+    if let Subcommands::New { name } = args.subcommands {
+        new::project(&name).await;
+        return;
+    }
+
     if !check_root() {
         eprintln!("{}", "rwf-cli must run from the root of a cargo project",);
         std::process::exit(1);
     }
 
-    let args = Cli::parse();
-
     match args.subcommands {
+        Subcommands::New { .. } => unreachable!(),
+
         Subcommands::Migrate(migrate) => match migrate.command {
             Migrate::Run { version } => migrate::migrate(version).await,
             Migrate::Revert { version } => migrate::revert(version).await,
@@ -153,16 +239,31 @@ async fn main() {
                 }
             }
             Migrate::Add { name } => migrate::add(&name).await,
+            Migrate::Feature { name } => migrate::feature(name.as_deref()).await,
         },
 
         Subcommands::Setup => setup::setup().await,
 
+        Subcommands::Serve { bin } => serve::serve(bin).await,
+
         Subcommands::Add(add) => match add.command {
             Add::Controller { name, page } => {
                 add::controller(&name, page, add.overwrite).await;
             }
         },
 
+        Subcommands::Generate(generate) => match generate.command {
+            Generate::Controller { name, page } => {
+                add::controller(&name, page, generate.overwrite).await;
+            }
+            Generate::Model { name, fields } => {
+                generate::model(&name, &fields, generate.overwrite).await;
+            }
+            Generate::Scaffold { name, fields } => {
+                generate::scaffold(&name, &fields, generate.overwrite).await;
+            }
+        },
+
         Subcommands::Remove(remove) => match remove.command {
             Remove::Controller { name } => {
                 remove::controller(&name).await.unwrap();
@@ -170,6 +271,10 @@ async fn main() {
         },
 
         Subcommands::Package { config, target } => deploy::package(config, target).await.unwrap(),
+
+        Subcommands::TasksRun { name } => tasks::run(&name).await,
+
+        Subcommands::TasksList => tasks::list(),
     }
 }
 
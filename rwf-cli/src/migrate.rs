@@ -1,11 +1,13 @@
-use rwf::model::migrations::{Direction, Migrations};
+use rwf::colors::MaybeColorize;
+use rwf::model::migrations::{features, Direction, Migrations};
 use std::path::Path;
 use time::OffsetDateTime;
 
 use regex::Regex;
 use tokio::fs::{create_dir, File};
+use tokio::io::AsyncWriteExt;
 
-use crate::logging::created;
+use crate::logging::{created, error};
 
 pub async fn migrate(version: Option<i64>) {
     let migrations = Migrations::sync().await.expect("failed to sync migrations");
@@ -31,23 +33,53 @@ pub async fn revert(version: Option<i64>) {
 }
 
 pub async fn add(name: &str) {
+    create(name, "", "")
+        .await
+        .expect("failed to create migration file");
+}
+
+/// Add the migration creating the tables of an optional feature.
+pub async fn feature(name: Option<&str>) {
+    match name.map(|name| (name, features::feature(name))) {
+        Some((_, Some(feature))) => {
+            create(&format!("rwf_{}", feature.name), feature.up, feature.down)
+                .await
+                .expect("failed to create migration file")
+        }
+
+        Some((name, None)) => {
+            error(format!("\"{}\" is not a feature with tables", name));
+            list_features();
+        }
+
+        None => list_features(),
+    }
+}
+
+fn list_features() {
+    for feature in features::FEATURES {
+        println!("{:<20} {}", feature.name.green(), feature.description);
+    }
+}
+
+/// Create a migration running the `up` and `down` SQL.
+pub async fn create(name: &str, up: &str, down: &str) -> Result<(), std::io::Error> {
     let regex = Regex::new("[^a-zA-Z0-9_]").unwrap();
     let name = regex.replace_all(name, "_");
     let version = OffsetDateTime::now_utc().unix_timestamp_nanos();
     let path = Path::new("migrations");
 
     if !path.exists() {
-        create_dir(&path)
-            .await
-            .expect("cannot create migrations directory");
+        create_dir(&path).await?;
         created(format!("created \"migrations\" directory"));
     }
 
-    for suffix in ["up", "down"] {
+    for (suffix, sql) in [("up", up), ("down", down)] {
         let name = path.join(format!("{}_{}.{}.sql", version, name, suffix));
-        File::create(&name)
-            .await
-            .expect("failed to create migration file");
+        let mut file = File::create(&name).await?;
+        file.write_all(sql.as_bytes()).await?;
         created(format!("\"{}\"", name.display()));
     }
+
+    Ok(())
 }
@@ -0,0 +1,96 @@
+use base64::{engine::general_purpose, Engine as _};
+use rwf::macros::context;
+use rwf::view::Template;
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::add::modules;
+use crate::logging::{created, error};
+use crate::setup::setup;
+
+/// Create a new Rwf project in the `name` directory.
+pub async fn project(name: &str) {
+    let path = Path::new(name);
+
+    if path.exists() {
+        error(format!("\"{}\" already exists", path.display()));
+        return;
+    }
+
+    let status = Command::new("cargo")
+        .arg("new")
+        .arg(name)
+        .status()
+        .await
+        .expect("failed to run cargo");
+
+    if !status.success() {
+        error("failed to create the cargo project");
+        return;
+    }
+
+    std::env::set_current_dir(path).expect("failed to enter the project directory");
+
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(name)
+        .to_string();
+
+    if let Err(err) = skeleton(&name).await {
+        error(format!("failed to create project: {}", err));
+        return;
+    }
+
+    setup().await;
+
+    eprintln!(
+        "\nYour app is ready. Start it with:\n\n    cd {}\n    cargo run\n",
+        path.display()
+    );
+}
+
+async fn skeleton(name: &str) -> Result<(), rwf::controller::Error> {
+    let secret_key = general_purpose::STANDARD.encode(rand::random::<[u8; 32]>());
+    let database = rwf::snake_case(name);
+
+    let files = [
+        (
+            "src/main.rs",
+            Template::from_str(include_str!("templates/new/main.rs.tpl"))?.render(&context!())?,
+        ),
+        (
+            "src/controllers/index.rs",
+            Template::from_str(include_str!("templates/new/index.rs.tpl"))?
+                .render(&context!("name" => name))?,
+        ),
+        (
+            "templates/index.html",
+            include_str!("templates/new/index.html").to_string(),
+        ),
+        (
+            "rwf.toml",
+            Template::from_str(include_str!("templates/new/rwf.toml.tpl"))?.render(&context!(
+                "secret_key" => secret_key,
+                "database" => database
+            ))?,
+        ),
+    ];
+
+    for (path, content) in files {
+        let path = Path::new(path);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let mut file = File::create(path).await?;
+        file.write_all(content.as_bytes()).await?;
+        created(format!("\"{}\"", path.display()));
+    }
+
+    modules(Path::new("src/controllers")).await?;
+
+    Ok(())
+}
@@ -0,0 +1,218 @@
+//! Development server: rebuilds the app and restarts it when its code changes.
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use rwf::config::get_config;
+use rwf::http::server::LISTEN_FD;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::select;
+use tokio::signal::ctrl_c;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::time::timeout;
+
+use crate::logging::{error, using, warning};
+
+/// Files changing in the same save are rebuilt together.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// How long the old server has to close its connections.
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Build and run the app, and restart it every time the code changes.
+///
+/// The listening socket is opened here and passed to the app, so it stays open
+/// while the app restarts. The new version starts accepting connections before
+/// the old one stops, and the old one asks browsers to reload the page.
+pub async fn serve(bin: Option<String>) {
+    let config = get_config();
+    let addr = format!("{}:{}", config.general.host, config.general.port);
+
+    let listener = match TcpListener::bind(&addr) {
+        Ok(listener) => listener,
+        Err(err) => {
+            error(format!("failed to listen on {}: {}", addr, err));
+            return;
+        }
+    };
+
+    if let Err(err) = inherit(&listener) {
+        error(format!("failed to share the socket with the app: {}", err));
+        return;
+    }
+
+    let mut changes = match watch() {
+        Ok(changes) => changes,
+        Err(err) => {
+            error(format!("failed to watch for changes: {}", err));
+            return;
+        }
+    };
+
+    using(format!("http://{} (Ctrl-C to stop)", addr));
+
+    let mut server: Option<Child> = None;
+
+    loop {
+        match build(bin.as_deref()).await {
+            Some(binary) => match start(&binary, &listener) {
+                Ok(child) => {
+                    if let Some(old) = server.replace(child) {
+                        stop(old).await;
+                    }
+                }
+                Err(err) => error(format!("failed to start {}: {}", binary.display(), err)),
+            },
+            None => warning("build failed, the previous version is still running"),
+        }
+
+        select! {
+            _ = ctrl_c() => {
+                if let Some(server) = server.take() {
+                    stop(server).await;
+                }
+                return;
+            }
+
+            changed = changes.recv() => {
+                if changed.is_none() {
+                    return;
+                }
+
+                // Wait for the editor to finish saving.
+                while let Ok(Some(_)) = timeout(DEBOUNCE, changes.recv()).await {}
+            }
+        }
+    }
+}
+
+/// Notify when the code or the configuration changes.
+fn watch() -> Result<UnboundedReceiver<PathBuf>, notify::Error> {
+    let (tx, rx) = unbounded_channel();
+
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+        if let Ok(event) = res {
+            if matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+            ) {
+                for path in event.paths {
+                    let _ = tx.send(path);
+                }
+            }
+        }
+    })?;
+
+    for path in ["src", "Cargo.toml", "build.rs", "rwf.toml"] {
+        let path = Path::new(path);
+        if path.exists() {
+            watcher.watch(path, RecursiveMode::Recursive)?;
+        }
+    }
+
+    // Watch for as long as the CLI runs.
+    std::mem::forget(watcher);
+
+    Ok(rx)
+}
+
+/// Build the app and return the path to its executable.
+async fn build(bin: Option<&str>) -> Option<PathBuf> {
+    let mut build = Command::new("cargo");
+    build
+        .arg("build")
+        .arg("--message-format=json-render-diagnostics")
+        .stdout(Stdio::piped());
+
+    if let Some(bin) = bin {
+        build.arg("--bin").arg(bin);
+    }
+
+    let mut child = build.spawn().ok()?;
+    let mut lines = BufReader::new(child.stdout.take()?).lines();
+    let mut executable = None;
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        let message: serde_json::Value = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(_) => continue,
+        };
+
+        if message["reason"] == "compiler-artifact" {
+            if let Some(path) = message["executable"].as_str() {
+                executable = Some(PathBuf::from(path));
+            }
+        }
+    }
+
+    if child.wait().await.ok()?.success() {
+        executable
+    } else {
+        None
+    }
+}
+
+/// Run the app, accepting connections on the listener.
+fn start(binary: &Path, listener: &TcpListener) -> std::io::Result<Child> {
+    let mut command = Command::new(binary);
+    command.env("RWF_WEBSOCKET__RELOAD_ON_SHUTDOWN", "true");
+
+    #[cfg(unix)]
+    {
+        use std::os::fd::AsRawFd;
+        command.env(LISTEN_FD, listener.as_raw_fd().to_string());
+    }
+
+    #[cfg(not(unix))]
+    let _ = listener;
+
+    command.spawn()
+}
+
+/// Shut the app down like Ctrl-C does, letting it close connections and ask browsers to reload.
+async fn stop(mut child: Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // SAFETY: the process is our child and hasn't been waited on, so the pid wasn't reused.
+        unsafe {
+            libc::kill(pid as libc::pid_t, libc::SIGINT);
+        }
+
+        if timeout(STOP_TIMEOUT, child.wait()).await.is_ok() {
+            return;
+        }
+    }
+
+    let _ = child.kill().await;
+}
+
+/// Let the app inherit the listening socket.
+#[cfg(unix)]
+fn inherit(listener: &TcpListener) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let fd = listener.as_raw_fd();
+
+    // SAFETY: the socket is open for as long as the listener exists.
+    let result = unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFD);
+        libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC)
+    };
+
+    if result < 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Passing the socket to the app is only supported on Unix.
+#[cfg(not(unix))]
+fn inherit(_listener: &TcpListener) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "rwf-cli serve requires a Unix system",
+    ))
+}
@@ -0,0 +1,17 @@
+use rwf::colors::MaybeColorize;
+use rwf::tasks;
+
+use crate::logging::error;
+
+pub async fn run(name: &str) {
+    if let Err(err) = tasks::run(name).await {
+        error(err);
+        std::process::exit(1);
+    }
+}
+
+pub fn list() {
+    for task in tasks::tasks() {
+        println!("{:<20} {}", task.name().green(), task.description());
+    }
+}
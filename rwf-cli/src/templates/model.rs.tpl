@@ -0,0 +1,7 @@
+use rwf::prelude::*;
+
+#[derive(Clone, macros::Model)]
+pub struct <%= name %> {
+    pub id: Option<i64>,<% for field in fields %>
+    <%- field %><% end %>
+}
@@ -0,0 +1,11 @@
+use rwf::prelude::*;
+
+#[derive(Default, macros::PageController)]
+pub struct Index;
+
+#[async_trait]
+impl PageController for Index {
+    async fn get(&self, request: &Request) -> Result<Response, Error> {
+        render!(request, "templates/index.html", "name" => "<%= name %>")
+    }
+}
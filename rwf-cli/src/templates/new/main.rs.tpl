@@ -0,0 +1,16 @@
+use rwf::http::{self, Server};
+use rwf::prelude::*;
+
+mod controllers;
+mod models;
+
+#[tokio::main]
+async fn main() -> Result<(), http::Error> {
+    Logger::init();
+
+    Server::new(vec![
+        route!("/" => controllers::index::Index),
+    ])
+    .launch()
+    .await
+}
@@ -0,0 +1,5 @@
+[general]
+secret_key = "<%= secret_key %>"
+
+[database]
+name = "<%= database %>"
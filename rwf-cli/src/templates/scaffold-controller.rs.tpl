@@ -0,0 +1,48 @@
+use crate::models::<%= module %>::<%= model %>;
+use rwf::prelude::*;
+
+#[derive(Default, macros::RestController)]
+pub struct <%= name %>;
+
+#[async_trait]
+impl RestController for <%= name %> {
+    type Resource = i64;
+
+    async fn list(&self, request: &Request) -> Result<Response, Error> {
+        let <%= plural %> = <%= model %>::order("id")
+            .fetch_all(Pool::pool())
+            .await?;
+
+        render!(request, "templates/<%= plural %>/index.html", "<%= plural %>" => <%= plural %>)
+    }
+
+    async fn get(&self, request: &Request, id: &i64) -> Result<Response, Error> {
+        let <%= module %> = <%= model %>::find(*id).fetch_optional(Pool::pool()).await?;
+
+        match <%= module %> {
+            Some(<%= module %>) => {
+                render!(request, "templates/<%= plural %>/show.html", "<%= module %>" => <%= module %>)
+            }
+            None => Ok(Response::not_found()),
+        }
+    }
+
+    async fn create(&self, request: &Request) -> Result<Response, Error> {
+        let form = request.form_data()?;
+        let <%= module %> = <%= model %>::create(&[<% for field in fields %>
+            <%- field %>,<% end %>
+        ])
+        .fetch(Pool::pool())
+        .await?;
+
+        Ok(Response::new().redirect(format!("/<%= plural %>/{}", <%= module %>.id.unwrap_or_default())))
+    }
+
+    async fn delete(&self, _request: &Request, id: &i64) -> Result<Response, Error> {
+        <%= model %>::find_by_sql("DELETE FROM <%= plural %> WHERE id = $1 RETURNING *", &[id.to_value()])
+            .fetch_optional(Pool::pool())
+            .await?;
+
+        Ok(Response::new().redirect("/<%= plural %>"))
+    }
+}
@@ -3,15 +3,22 @@ extern crate proc_macro;
 use proc_macro::TokenStream;
 
 use syn::{
-    parse_macro_input, punctuated::Punctuated, Attribute, Data, DeriveInput, Expr, ItemFn, Meta,
-    ReturnType, Token, Type, Visibility,
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    Attribute, Data, DeriveInput, Expr, Ident, ItemFn, LitStr, Meta, ReturnType, Token, Type,
+    Visibility,
 };
 
 use quote::quote;
 
 mod model;
+mod policy;
 mod prelude;
 mod render;
+mod respond;
+mod rpc;
+mod schema;
 
 /// The `#[derive(Model)]` macro.
 ///
@@ -36,6 +43,7 @@ mod render;
 /// - `foreign_key` overrides the value returned by `Model::foreign_key` implementation
 /// - `belongs_to` annotates the struct with a "belongs to" relationship to anoter model
 /// - `has_many` annotates the struct with a "has many" relationship to another model
+/// - `readable` and `writable` set who can see and change a field, e.g. `#[readable(roles("admin"))]`, see `Model::field_rules`
 ///
 /// # Example
 ///
@@ -127,7 +135,10 @@ mod render;
 /// }
 /// ```
 ///
-#[proc_macro_derive(Model, attributes(belongs_to, has_many, table_name, foreign_key))]
+#[proc_macro_derive(
+    Model,
+    attributes(belongs_to, has_many, table_name, foreign_key, readable, writable)
+)]
 pub fn derive_model(input: TokenStream) -> TokenStream {
     model::impl_derive_model(input)
 }
@@ -185,6 +196,10 @@ pub fn derive_model_controller(input: TokenStream) -> TokenStream {
         impl rwf::controller::Controller for #ident {
             #overrides
 
+            fn openapi(&self) -> Vec<rwf::http::openapi::Operation> {
+                rwf::controller::ModelController::operations(self)
+            }
+
             async fn handle(&self, request: &rwf::http::Request) -> Result<rwf::http::Response, rwf::controller::Error> {
                 rwf::controller::ModelController::handle(self, request).await
             }
@@ -316,6 +331,62 @@ pub fn derive_rest_controller(input: TokenStream) -> TokenStream {
     }.into()
 }
 
+/// Create a Server-Sent Events controller.
+///
+/// This implements mappings between the `Controller`
+/// trait and the struct implementing
+/// the `SseController` trait.
+#[proc_macro_derive(SseController, attributes(auth, middleware, skip_csrf))]
+pub fn derive_sse_controller(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let overrides = handle_overrides(&input.attrs);
+
+    let ident = match &input.data {
+        Data::Struct(_data) => input.ident.clone(),
+
+        _ => panic!("macro can only be used on structs"),
+    };
+
+    quote! {
+       #[rwf::async_trait]
+        impl rwf::controller::Controller for #ident {
+            #overrides
+
+            async fn handle(&self, request: &rwf::http::Request) -> Result<rwf::http::Response, rwf::controller::Error> {
+                rwf::controller::SseController::handle(self, request).await
+            }
+        }
+    }.into()
+}
+
+/// Create a direct-to-storage upload controller.
+///
+/// This implements mappings between the `Controller`
+/// trait and the struct implementing
+/// the `UploadController` trait.
+#[proc_macro_derive(UploadController, attributes(auth, middleware, skip_csrf))]
+pub fn derive_upload_controller(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let overrides = handle_overrides(&input.attrs);
+
+    let ident = match &input.data {
+        Data::Struct(_data) => input.ident.clone(),
+
+        _ => panic!("macro can only be used on structs"),
+    };
+
+    quote! {
+       #[rwf::async_trait]
+        impl rwf::controller::Controller for #ident {
+            #overrides
+
+            async fn handle(&self, request: &rwf::http::Request) -> Result<rwf::http::Response, rwf::controller::Error> {
+                rwf::storage::UploadController::handle(self, request).await
+            }
+        }
+    }.into()
+}
+
 /// Automatically implement the `FromRow` trait.
 /// Converts database rows to Rust struct fields.
 #[proc_macro_derive(FromRow)]
@@ -350,6 +421,401 @@ pub fn derive_from_row(input: TokenStream) -> TokenStream {
     }
 }
 
+/// Automatically implement the `Publishable` trait.
+/// The struct must have a `published_at: Option<OffsetDateTime>` field.
+#[proc_macro_derive(Publishable)]
+pub fn derive_publishable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match input.data {
+        Data::Struct(ref data) => {
+            let ident = input.ident;
+
+            if !data
+                .fields
+                .iter()
+                .any(|field| field.ident.as_ref().map(|i| i == "published_at") == Some(true))
+            {
+                panic!("struct must have a \"published_at\" field");
+            }
+
+            quote! {
+                #[automatically_derived]
+                impl rwf::model::Publishable for #ident {
+                    fn published_at(&self) -> Option<rwf::prelude::OffsetDateTime> {
+                        self.published_at
+                    }
+
+                    fn set_published_at(&mut self, published_at: Option<rwf::prelude::OffsetDateTime>) {
+                        self.published_at = published_at;
+                    }
+                }
+            }
+            .into()
+        }
+
+        _ => panic!("macro can only be used on structs"),
+    }
+}
+
+/// Automatically implement the `SecurePassword` trait.
+/// The struct must have a `password_digest: String` field.
+#[proc_macro_derive(SecurePassword)]
+pub fn derive_secure_password(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match input.data {
+        Data::Struct(ref data) => {
+            let ident = input.ident;
+
+            if !data
+                .fields
+                .iter()
+                .any(|field| field.ident.as_ref().map(|i| i == "password_digest") == Some(true))
+            {
+                panic!("struct must have a \"password_digest\" field");
+            }
+
+            quote! {
+                #[automatically_derived]
+                impl rwf::auth::SecurePassword for #ident {
+                    fn password_digest(&self) -> &str {
+                        &self.password_digest
+                    }
+
+                    fn set_password_digest(&mut self, digest: String) {
+                        self.password_digest = digest;
+                    }
+                }
+            }
+            .into()
+        }
+
+        _ => panic!("macro can only be used on structs"),
+    }
+}
+
+/// Automatically implement the `Tree` trait.
+/// The struct must have a `parent_id: Option<i64>` and a `path: String` field.
+#[proc_macro_derive(Tree)]
+pub fn derive_tree(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match input.data {
+        Data::Struct(ref data) => {
+            let ident = input.ident;
+
+            for name in ["parent_id", "path"] {
+                if !data
+                    .fields
+                    .iter()
+                    .any(|field| field.ident.as_ref().map(|i| i == name) == Some(true))
+                {
+                    panic!("struct must have a \"{}\" field", name);
+                }
+            }
+
+            quote! {
+                #[automatically_derived]
+                impl rwf::model::Tree for #ident {
+                    fn parent_id(&self) -> Option<i64> {
+                        self.parent_id
+                    }
+
+                    fn set_parent_id(&mut self, parent_id: Option<i64>) {
+                        self.parent_id = parent_id;
+                    }
+
+                    fn path(&self) -> &str {
+                        &self.path
+                    }
+
+                    fn set_path(&mut self, path: String) {
+                        self.path = path;
+                    }
+                }
+            }
+            .into()
+        }
+
+        _ => panic!("macro can only be used on structs"),
+    }
+}
+
+/// Implement `rwf::auth::policy::Policy` from simple role rules.
+///
+/// Each action takes a rule: `"any"` allows everyone, `"user"` allows logged in users, `"none"` allows no one,
+/// and anything else is a list of roles separated by `|`, checked with `rwf::auth::policy::Roles`.
+/// Actions without a rule are denied.
+///
+/// ```ignore
+/// #[derive(Default, macros::Policy)]
+/// #[policy(record = Post, user = User, view = "any", edit = "admin | editor")]
+/// struct PostPolicy;
+/// ```
+#[proc_macro_derive(Policy, attributes(policy))]
+pub fn derive_policy(input: TokenStream) -> TokenStream {
+    policy::derive_policy_impl(input)
+}
+
+/// Implement `rwf::http::openapi::Schema`, describing the type with JSON Schema in the OpenAPI specification.
+///
+/// Structs are objects; fields which aren't an `Option` are required. Enums with only unit variants
+/// are strings. Doc comments are used as descriptions. `serde` attributes are respected, e.g. `rename`,
+/// `rename_all`, `skip`, `default`, `flatten`, `tag` and `untagged`.
+///
+/// ```ignore
+/// /// A registered user.
+/// #[derive(Serialize, Deserialize, macros::Schema)]
+/// #[serde(rename_all = "camelCase")]
+/// struct User {
+///     full_name: String,
+///     #[serde(skip)]
+///     password: String,
+///     /// Where the user lives.
+///     country: Option<String>,
+/// }
+/// ```
+#[proc_macro_derive(Schema, attributes(serde))]
+pub fn derive_schema(input: TokenStream) -> TokenStream {
+    schema::derive_schema_impl(input)
+}
+
+/// Implement the `StateMachine` trait for an enum of states.
+///
+/// Allowed transitions are listed on each variant with the `transitions` attribute.
+/// States are stored in the database as the variant name in snake case, e.g. `"pending"`.
+///
+/// ```ignore
+/// #[derive(Clone, Copy, Debug, PartialEq, macros::StateMachine)]
+/// enum OrderState {
+///     #[transitions(Paid, Cancelled)]
+///     Pending,
+///     #[transitions(Shipped, Cancelled)]
+///     Paid,
+///     Shipped,
+///     Cancelled,
+/// }
+/// ```
+#[proc_macro_derive(StateMachine, attributes(transitions))]
+pub fn derive_state_machine(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let ident = input.ident;
+
+    let data = match input.data {
+        Data::Enum(data) => data,
+        _ => panic!("macro can only be used on enums"),
+    };
+
+    let variants = data
+        .variants
+        .iter()
+        .map(|variant| {
+            if !variant.fields.is_empty() {
+                panic!("state variants can't have fields");
+            }
+            &variant.ident
+        })
+        .collect::<Vec<_>>();
+
+    let names = variants
+        .iter()
+        .map(|variant| {
+            let name = snake_case(&variant.to_string());
+            quote! {
+                Self::#variant => #name,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let transitions = data
+        .variants
+        .iter()
+        .map(|variant| {
+            let ident = &variant.ident;
+            let to = variant
+                .attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("transitions"))
+                .flat_map(|attr| {
+                    attr.parse_args_with(Punctuated::<syn::Ident, Token![,]>::parse_terminated)
+                        .expect("transitions must be a list of states")
+                })
+                .collect::<Vec<_>>();
+
+            quote! {
+                Self::#ident => &[#(Self::#to),*],
+            }
+        })
+        .collect::<Vec<_>>();
+
+    quote! {
+        #[automatically_derived]
+        impl rwf::model::StateMachine for #ident {
+            fn name(&self) -> &'static str {
+                match self {
+                    #(#names)*
+                }
+            }
+
+            fn from_name(name: &str) -> Option<Self> {
+                <Self as rwf::model::StateMachine>::states()
+                    .iter()
+                    .find(|state| rwf::model::StateMachine::name(*state) == name)
+                    .copied()
+            }
+
+            fn states() -> &'static [Self] {
+                &[#(Self::#variants),*]
+            }
+
+            fn transitions(&self) -> &'static [Self] {
+                match self {
+                    #(#transitions)*
+                }
+            }
+        }
+
+        #[automatically_derived]
+        impl rwf::model::ToValue for #ident {
+            fn to_value(&self) -> rwf::model::Value {
+                rwf::model::ToValue::to_value(&rwf::model::StateMachine::name(self))
+            }
+        }
+
+        #[automatically_derived]
+        impl<'a> rwf::tokio_postgres::types::FromSql<'a> for #ident {
+            fn from_sql(
+                ty: &rwf::tokio_postgres::types::Type,
+                raw: &'a [u8],
+            ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+                let name = <&str as rwf::tokio_postgres::types::FromSql>::from_sql(ty, raw)?;
+                <Self as rwf::model::StateMachine>::from_name(name)
+                    .ok_or_else(|| format!("unknown state \"{}\"", name).into())
+            }
+
+            fn accepts(ty: &rwf::tokio_postgres::types::Type) -> bool {
+                <&str as rwf::tokio_postgres::types::FromSql>::accepts(ty)
+            }
+        }
+    }
+    .into()
+}
+
+/// Implement the `Anonymize` trait for a model.
+///
+/// Fields containing personal data are marked with the `pii` attribute and the kind of data,
+/// which determines the fake value replacing it, e.g. `email`, `name`, `phone` or `null`.
+///
+/// ```ignore
+/// #[derive(Clone, macros::Model, macros::Anonymize)]
+/// struct User {
+///     id: Option<i64>,
+///     #[pii(email)]
+///     email: String,
+///     #[pii(first_name)]
+///     first_name: String,
+/// }
+/// ```
+#[proc_macro_derive(Anonymize, attributes(pii))]
+pub fn derive_anonymize(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match input.data {
+        Data::Struct(ref data) => {
+            let ident = input.ident;
+
+            let columns = data
+                .fields
+                .iter()
+                .flat_map(|field| {
+                    let column = field.ident.as_ref().map(|ident| ident.to_string());
+                    field
+                        .attrs
+                        .iter()
+                        .filter(|attr| attr.path().is_ident("pii"))
+                        .map(move |attr| {
+                            let kind = attr
+                                .parse_args::<syn::Ident>()
+                                .expect("expected the kind of personal data, e.g. #[pii(email)]");
+                            let kind = syn::Ident::new(&camel_case(&kind.to_string()), kind.span());
+                            let column = column.clone().expect("pii field must be named");
+
+                            quote! {
+                                (#column, rwf::model::anonymize::Pii::#kind),
+                            }
+                        })
+                })
+                .collect::<Vec<_>>();
+
+            quote! {
+                #[automatically_derived]
+                impl rwf::model::Anonymize for #ident {
+                    fn pii() -> &'static [(&'static str, rwf::model::anonymize::Pii)] {
+                        &[#(#columns)*]
+                    }
+                }
+            }
+            .into()
+        }
+
+        _ => panic!("macro can only be used on structs"),
+    }
+}
+
+/// Implement the `Searchable` trait for a model.
+///
+/// The fields sent to the search engine are listed in the `searchable` attribute.
+///
+/// ```ignore
+/// #[derive(Clone, macros::Model, macros::Searchable)]
+/// #[searchable(title, body)]
+/// struct Post {
+///     id: Option<i64>,
+///     title: String,
+///     body: String,
+/// }
+/// ```
+#[proc_macro_derive(Searchable, attributes(searchable))]
+pub fn derive_searchable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let ident = input.ident;
+
+    let fields = input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("searchable"))
+        .flat_map(|attr| {
+            attr.parse_args_with(Punctuated::<syn::Ident, Token![,]>::parse_terminated)
+                .expect("searchable must be a list of fields, e.g. #[searchable(title, body)]")
+        })
+        .collect::<Vec<_>>();
+
+    if fields.is_empty() {
+        panic!("searchable fields are required, e.g. #[searchable(title, body)]");
+    }
+
+    let names = fields
+        .iter()
+        .map(|field| field.to_string())
+        .collect::<Vec<_>>();
+
+    quote! {
+        #[automatically_derived]
+        impl rwf::search::Searchable for #ident {
+            fn searchable_fields() -> &'static [&'static str] {
+                &[#(#names),*]
+            }
+
+            fn search_document(&self) -> rwf::search::Document {
+                rwf::search::Document::new(self)
+                    #(.field(#names, &self.#fields))*
+            }
+        }
+    }
+    .into()
+}
+
 /// Automatically implement the `ToTemplateValue` trait
 /// for the Rust struct. This allows to use the struct
 /// directly in template contexts.
@@ -390,7 +856,22 @@ pub fn derive_template_value(input: TokenStream) -> TokenStream {
 /// Automatically implement the `FromFormData` trait.
 /// Allows to extract values from a HTTP form and
 /// convert it to a Rust struct.
-#[proc_macro_derive(Form)]
+///
+/// `Option` fields are optional, `bool` fields are checkboxes, and `Vec` fields
+/// contain all values of the field, e.g. from a multi-select. Errors of all fields are returned
+/// together. The error message of a field can be changed with the `form` attribute:
+///
+/// ```ignore
+/// #[derive(macros::Form)]
+/// struct SignupForm {
+///     email: String,
+///     #[form(message = "must be a number")]
+///     age: i32,
+///     newsletter: bool,
+///     interests: Vec<String>,
+/// }
+/// ```
+#[proc_macro_derive(Form, attributes(form))]
 pub fn derive_form(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
@@ -398,31 +879,56 @@ pub fn derive_form(input: TokenStream) -> TokenStream {
         Data::Struct(ref data) => {
             let ident = input.ident;
 
-            let from_row_fields = data.fields.iter().map(|field| {
+            let parse_fields = data.fields.iter().map(|field| {
                 let ident = &field.ident;
 
-                let optional = match &field.ty {
-                    Type::Path(path) => {
-                        let optional = &path
-                            .path
-                            .segments
-                            .iter()
-                            .next()
-                            .map(|segment| segment.ident == "Option");
-                        optional.unwrap_or(false)
+                let mut message = quote! { None };
+                for attr in field
+                    .attrs
+                    .iter()
+                    .filter(|attr| attr.path().is_ident("form"))
+                {
+                    attr.parse_nested_meta(|meta| {
+                        if meta.path.is_ident("message") {
+                            let value: LitStr = meta.value()?.parse()?;
+                            message = quote! { Some(#value) };
+                            Ok(())
+                        } else {
+                            Err(meta.error("unsupported form attribute"))
+                        }
+                    })
+                    .expect("form attribute should be #[form(message = \"...\")]");
+                }
+
+                match form_field(&field.ty) {
+                    ("optional", Some(ty)) => quote! {
+                        let #ident = fields.optional::<#ty>(stringify!(#ident), #message);
+                    },
+                    ("list", Some(ty)) => quote! {
+                        let #ident = fields.list::<#ty>(stringify!(#ident), #message);
+                    },
+                    ("checkbox", _) => quote! {
+                        let #ident = fields.checkbox(stringify!(#ident), #message);
+                    },
+                    _ => {
+                        let ty = &field.ty;
+                        quote! {
+                            let #ident = fields.required::<#ty>(stringify!(#ident), #message);
+                        }
                     }
+                }
+            });
 
-                    _ => false,
-                };
+            let build_fields = data.fields.iter().map(|field| {
+                let ident = &field.ident;
 
-                if optional {
-                    quote! {
-                        #ident: form_data.get(stringify!(#ident)),
-                    }
-                } else {
-                    quote! {
-                        #ident: form_data.get_required(stringify!(#ident))?,
-                    }
+                match form_field(&field.ty) {
+                    ("required", _) => quote! {
+                        #ident: #ident.expect("field errors were checked"),
+                    },
+                    _ => quote! {
+                        #ident,
+                    },
                 }
             });
 
@@ -430,8 +936,14 @@ pub fn derive_form(input: TokenStream) -> TokenStream {
                 #[automatically_derived]
                 impl rwf::http::FromFormData for #ident {
                     fn from_form_data(form_data: &rwf::http::FormData) -> Result<Self, rwf::http::Error> {
+                        let mut fields = rwf::http::form::Fields::new(form_data);
+
+                        #(#parse_fields)*
+
+                        fields.finish()?;
+
                         Ok(Self {
-                            #(#from_row_fields)*
+                            #(#build_fields)*
                         })
                     }
                 }
@@ -443,6 +955,33 @@ pub fn derive_form(input: TokenStream) -> TokenStream {
     }
 }
 
+/// How a form field is parsed, and the type of its values for `Option` and `Vec`.
+fn form_field(ty: &Type) -> (&'static str, Option<&Type>) {
+    if let Type::Path(path) = ty {
+        if let Some(segment) = path.path.segments.last() {
+            let inner = match segment.arguments {
+                syn::PathArguments::AngleBracketed(ref args) => {
+                    args.args.iter().find_map(|arg| match arg {
+                        syn::GenericArgument::Type(ty) => Some(ty),
+                        _ => None,
+                    })
+                }
+                _ => None,
+            };
+
+            if segment.ident == "Option" && inner.is_some() {
+                return ("optional", inner);
+            } else if segment.ident == "Vec" && inner.is_some() {
+                return ("list", inner);
+            } else if segment.ident == "bool" {
+                return ("checkbox", None);
+            }
+        }
+    }
+
+    ("required", None)
+}
+
 /// Allows to automatically convert a Rust struct into a
 /// template context. Templates can then define
 /// strictly-typed contexts for additional type safety.
@@ -506,9 +1045,70 @@ pub fn error(input: TokenStream) -> TokenStream {
     .into()
 }
 
+/// Route from the HTTP path to the controller, optionally named, e.g. `"/users/:id" => User, as: "user"`.
+struct RouteInput {
+    path: Expr,
+    controller: Expr,
+    name: Option<LitStr>,
+}
+
+impl Parse for RouteInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let path: Expr = input.parse()?;
+        let _arrow: Token![=>] = input.parse()?;
+        let controller: Expr = input.parse()?;
+        let _comma: Option<Token![,]> = input.parse()?;
+
+        let name = if input.peek(Token![as]) {
+            let _as: Token![as] = input.parse()?;
+            let _colon: Token![:] = input.parse()?;
+            let name: LitStr = input.parse()?;
+            let _comma: Option<Token![,]> = input.parse()?;
+            Some(name)
+        } else {
+            None
+        };
+
+        Ok(RouteInput {
+            path,
+            controller,
+            name,
+        })
+    }
+}
+
+impl RouteInput {
+    /// Create the handler with the controller method, e.g. `route`, and name it.
+    fn handler(&self, method: &str) -> proc_macro2::TokenStream {
+        let path = &self.path;
+        let controller = &self.controller;
+        let method = Ident::new(method, proc_macro2::Span::call_site());
+
+        match self.name {
+            Some(ref name) => quote! {
+                #controller::default().#method(#path).name(#name)
+            },
+            None => quote! {
+                #controller::default().#method(#path)
+            },
+        }
+    }
+
+    /// Create a route accepting only the HTTP method, e.g. `Get`.
+    fn method_handler(&self, method: &str) -> proc_macro2::TokenStream {
+        let handler = self.handler("route");
+        let method = Ident::new(method, proc_macro2::Span::call_site());
+
+        quote! {
+            #handler.methods(&[rwf::http::Method::#method])
+        }
+    }
+}
+
 /// Create a route from the HTTP path to the controller.
 ///
-/// The controller needs to implement the [`Default`] trait.
+/// The controller needs to implement the [`Default`] trait. Routes can be named,
+/// so their URLs can be generated with `url_for!`.
 ///
 /// ### Example
 ///
@@ -517,54 +1117,75 @@ pub fn error(input: TokenStream) -> TokenStream {
 /// use rwf::http::Server;
 ///
 /// Server::new(vec![
-///     route!("/turbo-stream" => TurboStream)
+///     route!("/turbo-stream" => TurboStream),
+///     route!("/users/:id" => UserController, as: "user"),
 /// ]);
 /// ```
 #[proc_macro]
 pub fn route(input: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(input with Punctuated<Expr, Token![=>]>::parse_terminated);
-    let mut iter = input.into_iter();
-
-    let route = iter.next().unwrap();
-    let controller = iter.next().unwrap();
-
-    quote! {
-        #controller::default().route(#route)
-    }
-    .into()
+    let input = parse_macro_input!(input as RouteInput);
+    input.handler("route").into()
 }
 
 /// Create CRUD routes for the controller.
 ///
 /// CRUD routes include multiple routes following the
-/// REST specification.
+/// REST specification. Like `route!`, they can be named.
 #[proc_macro]
 pub fn crud(input: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(input with Punctuated<Expr, Token![=>]>::parse_terminated);
-    let mut iter = input.into_iter();
+    let input = parse_macro_input!(input as RouteInput);
+    input.handler("crud").into()
+}
 
-    let route = iter.next().unwrap();
-    let controller = iter.next().unwrap();
+/// Create REST routes for the controller. Like `route!`, they can be named.
+#[proc_macro]
+pub fn rest(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as RouteInput);
+    input.handler("rest").into()
+}
 
-    quote! {
-        #controller::default().crud(#route)
-    }
-    .into()
+/// Create a route accepting only `GET` (and `HEAD`) requests. Like `route!`, it can be named.
+///
+/// ### Example
+///
+/// ```rust,ignore
+/// Server::new(vec![
+///     get!("/users" => UsersList),
+///     post!("/users" => CreateUser),
+/// ]);
+/// ```
+#[proc_macro]
+pub fn get(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as RouteInput);
+    input.method_handler("Get").into()
 }
 
-/// Create REST routes for the controller.
+/// Create a route accepting only `POST` requests. Like `route!`, it can be named.
 #[proc_macro]
-pub fn rest(input: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(input with Punctuated<Expr, Token![=>]>::parse_terminated);
-    let mut iter = input.into_iter();
+pub fn post(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as RouteInput);
+    input.method_handler("Post").into()
+}
 
-    let route = iter.next().unwrap();
-    let controller = iter.next().unwrap();
+/// Create a route accepting only `PUT` requests. Like `route!`, it can be named.
+#[proc_macro]
+pub fn put(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as RouteInput);
+    input.method_handler("Put").into()
+}
 
-    quote! {
-        #controller::default().rest(#route)
-    }
-    .into()
+/// Create a route accepting only `PATCH` requests. Like `route!`, it can be named.
+#[proc_macro]
+pub fn patch(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as RouteInput);
+    input.method_handler("Patch").into()
+}
+
+/// Create a route accepting only `DELETE` requests. Like `route!`, it can be named.
+#[proc_macro]
+pub fn delete(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as RouteInput);
+    input.method_handler("Delete").into()
 }
 
 /// Create a route and mount an engine on it.
@@ -642,6 +1263,85 @@ pub fn turbo_stream(input: TokenStream) -> TokenStream {
     render::turbo_stream_impl(input)
 }
 
+/// Render a page containing a Turbo Frame. If the frame is requested by Turbo,
+/// only the frame is sent.
+///
+/// ### Example
+///
+/// ```rust,ignore
+/// use rwf_macros::turbo_frame;
+///
+/// Ok(turbo_frame!(request, "templates/post.html", "comments", "post" => post))
+/// ```
+#[proc_macro]
+pub fn turbo_frame(input: TokenStream) -> TokenStream {
+    render::turbo_frame_impl(input)
+}
+
+/// Respond with the format the client prefers, using the `Accept` header, and return the response
+/// from the controller. Formats are `html`, `json`, `turbo_stream`, `text`, `xml` and `csv`. If the client accepts
+/// any format, the first one is used; clients which accept none of them get `406 - Not Acceptable`.
+///
+/// Each branch can return a `Response`, a `Result<Response, _>`, a `TurboStream`, or use `render!`.
+///
+/// ### Example
+///
+/// ```ignore
+/// respond_to!(request, {
+///     html => render!(request, "templates/post.html", "post" => post),
+///     turbo_stream => turbo_stream!(request, "templates/post.html", "post", "post" => post),
+///     json => Response::new().json(&post),
+/// })
+/// ```
+#[proc_macro]
+pub fn respond_to(input: TokenStream) -> TokenStream {
+    respond::respond_to_impl(input)
+}
+
+/// Return `403 - Forbidden` from a controller if the user isn't allowed to perform the action on the record.
+///
+/// ```ignore
+/// authorize!(user.as_ref(), post, edit);
+/// ```
+#[proc_macro]
+pub fn authorize(input: TokenStream) -> TokenStream {
+    policy::authorize_impl(input)
+}
+
+/// Define a typed WebSocket protocol. Each method of the trait is a request the client can make,
+/// and `push` is the type of messages the server sends on its own.
+///
+/// Generates `<Trait>Request` and `<Trait>Response` enums, and `<Trait>Service`, which dispatches requests
+/// to the trait's implementation and can be served with `rwf::controller::RpcController`.
+///
+/// ### Example
+///
+/// ```ignore
+/// #[rpc(push = ChatPush)]
+/// trait Chat {
+///     async fn send(&self, client: &Client, body: String) -> Result<usize, Error>;
+/// }
+///
+/// route!("/chat" => { RpcController::new(ChatService(Room)) })
+/// ```
+#[proc_macro_attribute]
+pub fn rpc(args: TokenStream, input: TokenStream) -> TokenStream {
+    rpc::impl_rpc(args, input)
+}
+
+fn camel_case(string: &str) -> String {
+    string
+        .split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
 fn snake_case(string: &str) -> String {
     let mut result = "".to_string();
 
@@ -57,6 +57,11 @@ pub fn impl_derive_model(input: TokenStream) -> TokenStream {
                 }
             });
 
+            let field_rules = match handle_field_rules(data) {
+                Ok(field_rules) => field_rules,
+                Err(err) => return err.to_compile_error().into(),
+            };
+
             let singular = snake_case(&ident.to_string());
             let foreign_key = format!("{}_id", singular);
 
@@ -111,6 +116,8 @@ pub fn impl_derive_model(input: TokenStream) -> TokenStream {
                     }
 
                     #id
+
+                    #field_rules
                 }
 
                 #relationships
@@ -122,6 +129,78 @@ pub fn impl_derive_model(input: TokenStream) -> TokenStream {
     }
 }
 
+/// Field access rules set with `#[readable(...)]` and `#[writable(...)]`.
+fn handle_field_rules(data: &DataStruct) -> Result<proc_macro2::TokenStream> {
+    let mut rules = vec![];
+
+    for field in &data.fields {
+        let (mut read, mut write) = (None, None);
+
+        for attr in &field.attrs {
+            if attr.path().is_ident("readable") {
+                read = Some(access(attr)?);
+            } else if attr.path().is_ident("writable") {
+                write = Some(access(attr)?);
+            }
+        }
+
+        if read.is_none() && write.is_none() {
+            continue;
+        }
+
+        let name = field
+            .ident
+            .as_ref()
+            .expect("field must be named")
+            .to_string();
+        let any = quote! { rwf::auth::fields::Access::Any };
+        let read = read.unwrap_or(any.clone());
+        let write = write.unwrap_or(any);
+
+        rules.push(quote! {
+            rwf::auth::fields::FieldRule {
+                field: #name,
+                read: #read,
+                write: #write,
+            },
+        });
+    }
+
+    if rules.is_empty() {
+        return Ok(quote! {});
+    }
+
+    Ok(quote! {
+        fn field_rules() -> &'static [rwf::auth::fields::FieldRule] {
+            &[
+                #(#rules)*
+            ]
+        }
+    })
+}
+
+/// Access rule, e.g. `any`, `user`, `none`, or `roles("admin", "editor")`.
+fn access(attr: &Attribute) -> Result<proc_macro2::TokenStream> {
+    let expected = "expected any, user, none, or roles(\"...\")";
+
+    match attr.parse_args::<Meta>()? {
+        Meta::Path(path) if path.is_ident("any") => Ok(quote! { rwf::auth::fields::Access::Any }),
+        Meta::Path(path) if path.is_ident("user") => Ok(quote! { rwf::auth::fields::Access::User }),
+        Meta::Path(path) if path.is_ident("none") => Ok(quote! { rwf::auth::fields::Access::None }),
+        Meta::List(list) if list.path.is_ident("roles") => {
+            let roles = list.parse_args_with(Punctuated::<LitStr, Token![,]>::parse_terminated)?;
+
+            if roles.is_empty() {
+                return Err(Error::new_spanned(list, "at least one role is required"));
+            }
+
+            let roles = roles.iter();
+            Ok(quote! { rwf::auth::fields::Access::Roles(&[#(#roles),*]) })
+        }
+        meta => Err(Error::new_spanned(meta, expected)),
+    }
+}
+
 fn handle_override(
     name: &str,
     default_value: proc_macro2::TokenStream,
@@ -0,0 +1,179 @@
+use crate::prelude::*;
+use quote::format_ident;
+use syn::punctuated::Punctuated;
+
+/// Actions with rules in `#[policy(...)]`, and the policy methods checking them.
+const ACTIONS: &[(&str, &str)] = &[
+    ("view", "can_view"),
+    ("create", "can_create"),
+    ("edit", "can_edit"),
+    ("delete", "can_delete"),
+];
+
+pub fn derive_policy_impl(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let ident = &input.ident;
+
+    let attr = match input
+        .attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("policy"))
+    {
+        Some(attr) => attr,
+        None => {
+            return Error::new_spanned(ident, "missing #[policy(record = ..., user = ...)]")
+                .to_compile_error()
+                .into()
+        }
+    };
+
+    let args = match attr.parse_args_with(Punctuated::<MetaNameValue, Token![,]>::parse_terminated)
+    {
+        Ok(args) => args,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let mut record = None;
+    let mut user = None;
+    let mut methods = vec![];
+
+    for arg in args {
+        let name = arg.path.get_ident().map(|ident| ident.to_string());
+
+        match name.as_deref() {
+            Some("record") => record = Some(arg.value),
+            Some("user") => user = Some(arg.value),
+            Some(action) => match ACTIONS.iter().find(|(name, _)| *name == action) {
+                Some((_, method)) => {
+                    let rule = match arg.value {
+                        Expr::Lit(ExprLit {
+                            lit: Lit::Str(ref rule),
+                            ..
+                        }) => rule.value(),
+                        ref value => {
+                            return Error::new_spanned(value, "expected a rule, e.g. \"admin\"")
+                                .to_compile_error()
+                                .into()
+                        }
+                    };
+
+                    let method = format_ident!("{}", method);
+                    let check = rule_check(&rule);
+
+                    methods.push(quote! {
+                        fn #method(&self, user: Option<&Self::User>, _record: &#record) -> bool {
+                            #check
+                        }
+                    });
+                }
+                None => {
+                    return Error::new_spanned(
+                        arg.path,
+                        "unknown argument, expected record, user, view, create, edit or delete",
+                    )
+                    .to_compile_error()
+                    .into()
+                }
+            },
+            None => {
+                return Error::new_spanned(arg.path, "expected an argument name")
+                    .to_compile_error()
+                    .into()
+            }
+        }
+    }
+
+    let (record, user) = match (record, user) {
+        (Some(record), Some(user)) => (record, user),
+        _ => {
+            return Error::new_spanned(attr, "both record and user are required")
+                .to_compile_error()
+                .into()
+        }
+    };
+
+    quote! {
+        #[automatically_derived]
+        impl rwf::auth::policy::Policy<#record> for #ident {
+            type User = #user;
+
+            #(#methods)*
+        }
+
+        #[automatically_derived]
+        impl rwf::auth::policy::Authorize for #record {
+            type Policy = #ident;
+        }
+    }
+    .into()
+}
+
+/// Check a rule, e.g. `"any"`, `"user"`, or `"admin | editor"`.
+fn rule_check(rule: &str) -> proc_macro2::TokenStream {
+    match rule.trim() {
+        "any" => quote! { true },
+        "user" => quote! { user.is_some() },
+        "none" => quote! { false },
+        roles => {
+            let roles = roles
+                .split('|')
+                .map(|role| role.trim())
+                .filter(|role| !role.is_empty());
+
+            quote! {
+                match user {
+                    Some(user) => false #(|| rwf::auth::policy::Roles::has_role(user, #roles))*,
+                    None => false,
+                }
+            }
+        }
+    }
+}
+
+/// `authorize!(user, record, action)`
+struct Authorize {
+    user: Expr,
+    record: Expr,
+    action: Expr,
+}
+
+impl Parse for Authorize {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let user = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let record = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let action = input.parse()?;
+        let _ = input.parse::<Token![,]>();
+
+        Ok(Self {
+            user,
+            record,
+            action,
+        })
+    }
+}
+
+pub fn authorize_impl(input: TokenStream) -> TokenStream {
+    let Authorize {
+        user,
+        record,
+        action,
+    } = parse_macro_input!(input as Authorize);
+
+    // Actions can be identifiers, e.g. `edit`, or strings.
+    let action = match action {
+        Expr::Path(ref path) if path.path.get_ident().is_some() => {
+            let action = path.path.get_ident().unwrap().to_string();
+            quote! { #action }
+        }
+        action => quote! { #action },
+    };
+
+    quote! {
+        if !rwf::auth::policy::can(#user, &#record, #action) {
+            return Err(rwf::http::Error::Forbidden.into());
+        }
+    }
+    .into()
+}
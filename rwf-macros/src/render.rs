@@ -300,3 +300,19 @@ pub fn turbo_stream_impl(input: TokenStream) -> TokenStream {
     }
     .into()
 }
+
+/// `turbo_frame!` implementation.
+pub fn turbo_frame_impl(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as TurboStreamInput);
+    let render_input = input.render_input();
+    let render_call = render_template(&render_input);
+    let id = input.id;
+
+    quote! {
+        {
+            #render_call
+            rwf::http::Response::new().turbo_frame(#id, html)
+        }
+    }
+    .into()
+}
@@ -0,0 +1,92 @@
+use crate::prelude::*;
+use quote::format_ident;
+use syn::punctuated::Punctuated;
+
+/// `format => response` branch of `respond_to!`.
+struct Branch {
+    format: Ident,
+    _arrow: Token![=>],
+    response: Expr,
+}
+
+impl Parse for Branch {
+    fn parse(input: ParseStream) -> Result<Self> {
+        Ok(Branch {
+            format: input.parse()?,
+            _arrow: input.parse()?,
+            response: input.parse()?,
+        })
+    }
+}
+
+struct RespondTo {
+    request: Expr,
+    branches: Punctuated<Branch, Token![,]>,
+}
+
+impl Parse for RespondTo {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let request: Expr = input.parse()?;
+        let _: Token![,] = input.parse()?;
+        let content;
+        braced!(content in input);
+        let branches = content.parse_terminated(Branch::parse, Token![,])?;
+        let _: Option<Token![,]> = input.parse()?;
+
+        Ok(RespondTo { request, branches })
+    }
+}
+
+pub fn respond_to_impl(input: TokenStream) -> TokenStream {
+    let RespondTo { request, branches } = parse_macro_input!(input as RespondTo);
+
+    // `turbo_stream` is `Format::TurboStream`.
+    let formats = branches
+        .iter()
+        .map(|branch| {
+            let format = crate::camel_case(&branch.format.to_string());
+            format_ident!("{}", format, span = branch.format.span())
+        })
+        .collect::<Vec<_>>();
+    let responses = branches.iter().map(|branch| {
+        let response = &branch.response;
+        if renders(response) {
+            // `render!` returns the response itself.
+            quote! { #response }
+        } else {
+            quote! { rwf::http::ToResponse::to_response(#response) }
+        }
+    });
+
+    quote! {
+        {
+            match #request.format(&[#(rwf::http::Format::#formats),*]) {
+                #(
+                    Some(rwf::http::Format::#formats) => {
+                        let response: Result<rwf::http::Response, rwf::controller::Error> = async {
+                            #responses
+                        }
+                        .await;
+                        return response.map(|response| response.header("vary", "accept"));
+                    }
+                )*
+                _ => return Ok(rwf::http::Response::not_acceptable().header("vary", "accept")),
+            }
+        }
+    }
+    .into()
+}
+
+/// The branch is `render!` or `render_include!`.
+fn renders(response: &Expr) -> bool {
+    match response {
+        Expr::Macro(expr) => expr
+            .mac
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident == "render" || segment.ident == "render_include")
+            .unwrap_or(false),
+        _ => false,
+    }
+}
@@ -0,0 +1,207 @@
+use super::*;
+use quote::format_ident;
+use syn::*;
+
+struct Method {
+    ident: Ident,
+    variant: Ident,
+    params: Vec<(Ident, Type)>,
+    response: Type,
+}
+
+pub fn impl_rpc(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args with Punctuated<MetaNameValue, Token![,]>::parse_terminated);
+    let mut input = parse_macro_input!(input as ItemTrait);
+
+    let mut push = None;
+    for arg in args {
+        if arg.path.is_ident("push") {
+            push = Some(arg.value);
+        } else {
+            return Error::new_spanned(arg.path, "unknown argument, expected `push`")
+                .to_compile_error()
+                .into();
+        }
+    }
+
+    let mut methods = vec![];
+    for item in &input.items {
+        if let TraitItem::Fn(item) = item {
+            match method(&item.sig) {
+                Ok(method) => methods.push(method),
+                Err(err) => return err.to_compile_error().into(),
+            }
+        }
+    }
+
+    let vis = input.vis.clone();
+    let ident = input.ident.clone();
+    let request = format_ident!("{}Request", ident);
+    let response = format_ident!("{}Response", ident);
+    let service = format_ident!("{}Service", ident);
+
+    input.supertraits.push(parse_quote!(Send));
+    input.supertraits.push(parse_quote!(Sync));
+
+    if let Some(push) = push {
+        input.items.push(parse_quote! {
+            /// Send a push to all connections of a session, or a user if a user ID or model is passed in.
+            fn push(
+                to: impl rwf::comms::IntoSessionId,
+                push: &#push,
+            ) -> Result<usize, rwf::comms::Error>
+            where
+                Self: Sized,
+            {
+                rwf::controller::rpc::push_to(to, push)
+            }
+        });
+        input.items.push(parse_quote! {
+            /// Send a push to everyone connected.
+            fn broadcast(push: &#push) -> Result<(), rwf::comms::Error>
+            where
+                Self: Sized,
+            {
+                rwf::controller::rpc::broadcast(push)
+            }
+        });
+    }
+
+    let request_variants = methods.iter().map(|method| {
+        let variant = &method.variant;
+        if method.params.is_empty() {
+            quote! { #variant }
+        } else {
+            let params = method
+                .params
+                .iter()
+                .map(|(ident, ty)| quote! { #ident: #ty });
+            quote! { #variant { #(#params),* } }
+        }
+    });
+
+    let response_variants = methods.iter().map(|method| {
+        let variant = &method.variant;
+        let ty = &method.response;
+        quote! { #variant(#ty) }
+    });
+
+    let arms = methods.iter().map(|method| {
+        let variant = &method.variant;
+        let ident = &method.ident;
+        let params = method
+            .params
+            .iter()
+            .map(|(ident, _)| ident)
+            .collect::<Vec<_>>();
+        let pattern = if params.is_empty() {
+            quote! { #request::#variant }
+        } else {
+            quote! { #request::#variant { #(#params),* } }
+        };
+
+        quote! {
+            #pattern => Ok(#response::#variant(self.0.#ident(client, #(#params),*).await?)),
+        }
+    });
+
+    quote! {
+        #[rwf::async_trait]
+        #input
+
+        /// Requests sent by the client.
+        #[derive(rwf::serde::Deserialize)]
+        #[serde(crate = "rwf::serde", tag = "method", content = "params", rename_all = "snake_case")]
+        #vis enum #request {
+            #(#request_variants),*
+        }
+
+        /// Responses to requests sent by the client.
+        #[derive(rwf::serde::Serialize)]
+        #[serde(crate = "rwf::serde", untagged)]
+        #vis enum #response {
+            #(#response_variants),*
+        }
+
+        /// Dispatches requests to the protocol handler.
+        #vis struct #service<T>(pub T);
+
+        #[rwf::async_trait]
+        impl<T: #ident> rwf::controller::rpc::Dispatch for #service<T> {
+            type Request = #request;
+            type Response = #response;
+
+            async fn dispatch(
+                &self,
+                client: &rwf::controller::rpc::Client,
+                request: #request,
+            ) -> Result<#response, rwf::controller::Error> {
+                match request {
+                    #(#arms)*
+                }
+            }
+        }
+    }
+    .into()
+}
+
+/// Parse a request method, e.g. `async fn send(&self, client: &Client, body: String) -> Result<usize, Error>`.
+fn method(sig: &Signature) -> Result<Method> {
+    if sig.asyncness.is_none() {
+        return Err(Error::new_spanned(sig, "protocol methods must be async"));
+    }
+
+    let mut inputs = sig.inputs.iter();
+
+    match inputs.next() {
+        Some(FnArg::Receiver(_)) => (),
+        _ => return Err(Error::new_spanned(sig, "protocol methods must take &self")),
+    }
+
+    if inputs.next().is_none() {
+        return Err(Error::new_spanned(
+            sig,
+            "protocol methods must take the client as the second argument",
+        ));
+    }
+
+    let mut params = vec![];
+    for input in inputs {
+        match input {
+            FnArg::Typed(PatType { pat, ty, .. }) => match pat.as_ref() {
+                Pat::Ident(pat) => params.push((pat.ident.clone(), ty.as_ref().clone())),
+                pat => return Err(Error::new_spanned(pat, "expected a parameter name")),
+            },
+            input => return Err(Error::new_spanned(input, "expected a parameter")),
+        }
+    }
+
+    Ok(Method {
+        ident: sig.ident.clone(),
+        variant: format_ident!("{}", camel_case(&sig.ident.to_string())),
+        params,
+        response: response(&sig.output)?,
+    })
+}
+
+/// Get `T` from `Result<T, E>`.
+fn response(output: &ReturnType) -> Result<Type> {
+    if let ReturnType::Type(_, ty) = output {
+        if let Type::Path(path) = ty.as_ref() {
+            if let Some(segment) = path.path.segments.last() {
+                if segment.ident == "Result" {
+                    if let PathArguments::AngleBracketed(args) = &segment.arguments {
+                        if let Some(GenericArgument::Type(ty)) = args.args.first() {
+                            return Ok(ty.clone());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Err(Error::new_spanned(
+        output,
+        "protocol methods must return Result<T, Error>",
+    ))
+}
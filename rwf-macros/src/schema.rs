@@ -0,0 +1,399 @@
+use crate::prelude::*;
+use syn::meta::ParseNestedMeta;
+
+/// The `serde` attributes which change how a type is serialized.
+#[derive(Default)]
+struct Serde {
+    rename: Option<String>,
+    rename_all: Option<String>,
+    tag: Option<String>,
+    content: Option<String>,
+    untagged: bool,
+    skip: bool,
+    default: bool,
+    flatten: bool,
+    skip_serializing_if: bool,
+}
+
+impl Serde {
+    fn parse(attrs: &[Attribute]) -> Result<Self> {
+        let mut serde = Serde::default();
+
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+            attr.parse_nested_meta(|meta| {
+                let name = meta
+                    .path
+                    .get_ident()
+                    .map(|ident| ident.to_string())
+                    .unwrap_or_default();
+
+                match name.as_str() {
+                    "rename" if meta.input.peek(Token![=]) => serde.rename = Some(string(&meta)?),
+                    "rename_all" if meta.input.peek(Token![=]) => {
+                        serde.rename_all = Some(string(&meta)?)
+                    }
+                    "tag" => serde.tag = Some(string(&meta)?),
+                    "content" => serde.content = Some(string(&meta)?),
+                    "untagged" => serde.untagged = true,
+                    "skip" | "skip_deserializing" => serde.skip = true,
+                    "flatten" => serde.flatten = true,
+                    "default" => {
+                        serde.default = true;
+                        ignore(&meta)?;
+                    }
+                    "skip_serializing_if" => {
+                        serde.skip_serializing_if = true;
+                        ignore(&meta)?;
+                    }
+                    _ => ignore(&meta)?,
+                }
+
+                Ok(())
+            })?;
+        }
+
+        Ok(serde)
+    }
+
+    /// Name of the field or variant, after renaming.
+    fn name(&self, ident: &Ident, rename_all: Option<&str>) -> String {
+        match self.rename {
+            Some(ref rename) => rename.clone(),
+            None => {
+                let ident = ident.to_string();
+                let ident = ident.strip_prefix("r#").unwrap_or(&ident);
+                match rename_all {
+                    Some(rule) => rename(ident, rule),
+                    None => ident.to_string(),
+                }
+            }
+        }
+    }
+}
+
+/// Value of a `name = "value"` attribute.
+fn string(meta: &ParseNestedMeta) -> Result<String> {
+    let value: LitStr = meta.value()?.parse()?;
+    Ok(value.value())
+}
+
+/// Skip the value of an attribute, e.g. `= "path"` or `(serialize = "name")`.
+fn ignore(meta: &ParseNestedMeta) -> Result<()> {
+    if meta.input.peek(Token![=]) {
+        let _: Expr = meta.value()?.parse()?;
+    } else if meta.input.peek(token::Paren) {
+        meta.parse_nested_meta(|meta| ignore(&meta))?;
+    }
+
+    Ok(())
+}
+
+/// Rename a field or variant like `#[serde(rename_all = "...")]`.
+fn rename(ident: &str, rule: &str) -> String {
+    let mut words: Vec<String> = vec![];
+    for part in ident.split('_').filter(|part| !part.is_empty()) {
+        let mut word = String::new();
+        for c in part.chars() {
+            if c.is_ascii_uppercase() && !word.is_empty() {
+                words.push(word);
+                word = String::new();
+            }
+            word.push(c.to_ascii_lowercase());
+        }
+        words.push(word);
+    }
+
+    let capitalize = |word: &String| {
+        let mut chars = word.chars();
+        match chars.next() {
+            Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+            None => String::new(),
+        }
+    };
+
+    match rule {
+        "lowercase" => words.concat(),
+        "UPPERCASE" => words.concat().to_uppercase(),
+        "PascalCase" => words.iter().map(capitalize).collect(),
+        "camelCase" => words
+            .iter()
+            .enumerate()
+            .map(|(i, word)| {
+                if i == 0 {
+                    word.clone()
+                } else {
+                    capitalize(word)
+                }
+            })
+            .collect(),
+        "snake_case" => words.join("_"),
+        "SCREAMING_SNAKE_CASE" => words.join("_").to_uppercase(),
+        "kebab-case" => words.join("-"),
+        "SCREAMING-KEBAB-CASE" => words.join("-").to_uppercase(),
+        _ => ident.to_string(),
+    }
+}
+
+/// Doc comment, used as the description.
+fn description(attrs: &[Attribute]) -> Option<String> {
+    let lines = attrs
+        .iter()
+        .filter_map(|attr| match attr.meta {
+            Meta::NameValue(ref meta) if meta.path.is_ident("doc") => match meta.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(ref line),
+                    ..
+                }) => Some(line.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join("\n").trim().to_string())
+    }
+}
+
+/// Add the description to the schema in `schema`.
+fn describe(schema: proc_macro2::TokenStream, attrs: &[Attribute]) -> proc_macro2::TokenStream {
+    match description(attrs) {
+        Some(description) => quote! {{
+            let mut schema = #schema;
+            if let Some(object) = schema.as_object_mut() {
+                object.insert("description".into(), rwf::serde_json::Value::from(#description));
+            }
+            schema
+        }},
+        None => schema,
+    }
+}
+
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident == "Option")
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Schema of a struct, or of a struct variant.
+fn object(
+    fields: &FieldsNamed,
+    rename_all: Option<&str>,
+    default: bool,
+) -> Result<proc_macro2::TokenStream> {
+    let mut statements = vec![];
+
+    for field in &fields.named {
+        let serde = Serde::parse(&field.attrs)?;
+        let ty = &field.ty;
+
+        if serde.skip {
+            continue;
+        }
+
+        if serde.flatten {
+            statements.push(quote! {
+                let flattened = <#ty as rwf::http::openapi::Schema>::schema();
+                if let Some(rwf::serde_json::Value::Object(flattened)) = flattened.get("properties") {
+                    properties.extend(flattened.clone());
+                }
+                if let Some(rwf::serde_json::Value::Array(flattened)) = flattened.get("required") {
+                    required.extend(flattened.clone());
+                }
+            });
+            continue;
+        }
+
+        let name = serde.name(field.ident.as_ref().unwrap(), rename_all);
+        let schema = describe(
+            quote! { <#ty as rwf::http::openapi::Schema>::schema() },
+            &field.attrs,
+        );
+        statements.push(quote! {
+            properties.insert(#name.to_string(), #schema);
+        });
+
+        if !(default || serde.default || serde.skip_serializing_if || is_option(ty)) {
+            statements.push(quote! {
+                required.push(rwf::serde_json::Value::from(#name));
+            });
+        }
+    }
+
+    Ok(quote! {{
+        let mut properties = rwf::serde_json::Map::new();
+        let mut required: Vec<rwf::serde_json::Value> = vec![];
+        #(#statements)*
+
+        let mut schema = rwf::serde_json::json!({ "type": "object", "properties": properties });
+        if !required.is_empty() {
+            schema["required"] = rwf::serde_json::Value::Array(required);
+        }
+        schema
+    }})
+}
+
+/// Schema of the fields of a struct or variant.
+fn fields(
+    fields: &Fields,
+    rename_all: Option<&str>,
+    default: bool,
+) -> Result<proc_macro2::TokenStream> {
+    Ok(match fields {
+        Fields::Named(named) => object(named, rename_all, default)?,
+        Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => {
+            let ty = &unnamed.unnamed[0].ty;
+            quote! { <#ty as rwf::http::openapi::Schema>::schema() }
+        }
+        Fields::Unnamed(_) => quote! { rwf::serde_json::json!({ "type": "array" }) },
+        Fields::Unit => quote! { rwf::serde_json::json!({}) },
+    })
+}
+
+/// Schema of an enum, following its `serde` representation.
+fn variants(data: &DataEnum, container: &Serde) -> Result<proc_macro2::TokenStream> {
+    let rename_all = container.rename_all.as_deref();
+    let mut variants = vec![];
+    for variant in &data.variants {
+        let serde = Serde::parse(&variant.attrs)?;
+        if !serde.skip {
+            variants.push((serde.name(&variant.ident, rename_all), serde, variant));
+        }
+    }
+
+    let unit = variants
+        .iter()
+        .all(|(_, _, variant)| matches!(variant.fields, Fields::Unit));
+
+    if unit && !container.untagged && container.content.is_none() {
+        let names = variants.iter().map(|(name, _, _)| name);
+        return Ok(match container.tag {
+            Some(ref tag) => quote! {
+                rwf::serde_json::json!({
+                    "type": "object",
+                    "properties": { #tag: { "type": "string", "enum": [#(#names),*] } },
+                    "required": [#tag],
+                })
+            },
+            None => quote! {
+                rwf::serde_json::json!({ "type": "string", "enum": [#(#names),*] })
+            },
+        });
+    }
+
+    let mut schemas = vec![];
+    for (name, serde, variant) in variants {
+        let content = describe(
+            fields(&variant.fields, serde.rename_all.as_deref(), false)?,
+            &variant.attrs,
+        );
+        let unit = matches!(variant.fields, Fields::Unit);
+
+        schemas.push(match (container.tag.as_ref(), container.content.as_ref()) {
+            _ if container.untagged => content,
+
+            (Some(tag), Some(content_name)) => {
+                let content = if unit {
+                    quote! {}
+                } else {
+                    quote! { properties.insert(#content_name.to_string(), #content); }
+                };
+                quote! {{
+                    let mut properties = rwf::serde_json::Map::new();
+                    properties.insert(#tag.to_string(), rwf::serde_json::json!({ "type": "string", "enum": [#name] }));
+                    #content
+                    rwf::serde_json::json!({ "type": "object", "properties": properties, "required": [#tag] })
+                }}
+            }
+
+            (Some(tag), None) => quote! {{
+                let mut schema = #content;
+                if schema.get("type").is_none() {
+                    schema["type"] = rwf::serde_json::Value::from("object");
+                }
+                schema["properties"][#tag] = rwf::serde_json::json!({ "type": "string", "enum": [#name] });
+                match schema.get_mut("required").and_then(|required| required.as_array_mut()) {
+                    Some(required) => required.push(rwf::serde_json::Value::from(#tag)),
+                    None => schema["required"] = rwf::serde_json::json!([#tag]),
+                }
+                schema
+            }},
+
+            _ if unit => quote! {
+                rwf::serde_json::json!({ "type": "string", "enum": [#name] })
+            },
+
+            _ => quote! {
+                rwf::serde_json::json!({
+                    "type": "object",
+                    "properties": { #name: (#content) },
+                    "required": [#name],
+                })
+            },
+        });
+    }
+
+    Ok(quote! {
+        rwf::serde_json::json!({ "oneOf": [#((#schemas)),*] })
+    })
+}
+
+pub fn derive_schema_impl(input: TokenStream) -> TokenStream {
+    let mut input = parse_macro_input!(input as DeriveInput);
+    let ident = input.ident.clone();
+
+    let container = match Serde::parse(&input.attrs) {
+        Ok(container) => container,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let schema = match input.data {
+        Data::Struct(ref data) => fields(
+            &data.fields,
+            container.rename_all.as_deref(),
+            container.default,
+        ),
+        Data::Enum(ref data) => variants(data, &container),
+        Data::Union(_) => Err(Error::new_spanned(
+            &ident,
+            "Schema can't be derived for unions",
+        )),
+    };
+
+    let schema = match schema {
+        Ok(schema) => describe(schema, &input.attrs),
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    // Generic types need a schema too.
+    let params = input
+        .generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect::<Vec<_>>();
+    let where_clause = input.generics.make_where_clause();
+    for param in params {
+        where_clause
+            .predicates
+            .push(parse_quote! { #param: rwf::http::openapi::Schema });
+    }
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    quote! {
+        #[automatically_derived]
+        impl #impl_generics rwf::http::openapi::Schema for #ident #ty_generics #where_clause {
+            fn schema() -> rwf::serde_json::Value {
+                #schema
+            }
+        }
+    }
+    .into()
+}
@@ -0,0 +1,108 @@
+//! Framework hot path: router, controller, ORM and template, served in-process.
+//!
+//! The ORM queries are built as usual, but their results come from records kept in memory,
+//! so no database is needed. Run with:
+//!
+//! ```bash
+//! cargo bench -p rwf
+//! ```
+use criterion::{criterion_group, criterion_main, Criterion};
+use rwf::http::{Harness, Path};
+use rwf::model::Recorded;
+use rwf::prelude::*;
+use std::sync::Arc;
+
+#[derive(Clone, Debug, macros::Model)]
+struct User {
+    id: Option<i64>,
+    email: String,
+    admin: bool,
+}
+
+struct Users {
+    users: Recorded<User>,
+    template: Arc<Template>,
+}
+
+impl Default for Users {
+    fn default() -> Self {
+        let users = (1..=25)
+            .map(|id| User {
+                id: Some(id),
+                email: format!("user{}@example.com", id),
+                admin: id % 5 == 0,
+            })
+            .collect();
+
+        Self {
+            users: Recorded::new().record(Self::query(), users),
+            template: Arc::new(
+                Template::from_str(
+                    "<ul><% for user in users %><li><%= user.email %><% if user.admin %> (admin)<% end %></li><% end %></ul>",
+                )
+                .unwrap(),
+            ),
+        }
+    }
+}
+
+impl Users {
+    fn query() -> Scope<User> {
+        User::all()
+            .filter_gt("id", 0)
+            .order(("id", "ASC"))
+            .limit(25)
+    }
+
+    fn render(&self) -> Result<String, Error> {
+        let users = self.users.fetch_all(Self::query())?;
+        Ok(self.template.render(&context!("users" => users))?)
+    }
+}
+
+#[async_trait]
+impl Controller for Users {
+    async fn handle(&self, _request: &Request) -> Result<Response, Error> {
+        Ok(Response::new().html(self.render()?))
+    }
+}
+
+fn harness() -> Harness {
+    Harness::new(vec![
+        route!("/" => Users),
+        route!("/users" => Users),
+        route!("/users/:id" => Users),
+        route!("/users/:id/tasks" => Users),
+    ])
+    .unwrap()
+}
+
+fn router(c: &mut Criterion) {
+    let harness = harness();
+    let path = Path::parse("/users/5/tasks").unwrap();
+
+    c.bench_function("router", |b| b.iter(|| harness.router().find(&path)));
+}
+
+fn render(c: &mut Criterion) {
+    let controller = Users::default();
+    assert!(controller
+        .render()
+        .unwrap()
+        .contains("<li>user25@example.com (admin)</li>"));
+
+    c.bench_function("render", |b| b.iter(|| controller.render().unwrap()));
+}
+
+fn request(c: &mut Criterion) {
+    let harness = harness();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    c.bench_function("request", |b| {
+        b.to_async(&runtime)
+            .iter(|| async { harness.get("/users/5/tasks").await.unwrap() })
+    });
+}
+
+criterion_group!(benches, router, render, request);
+criterion_main!(benches);
@@ -0,0 +1,383 @@
+//! Export query results to [Apache Arrow](https://arrow.apache.org) record batches and
+//! [Parquet](https://parquet.apache.org) files.
+//!
+//! Rows are streamed from the database and converted to Arrow in batches, so large tables
+//! can be exported without loading them into memory. Column types are taken from the query
+//! and mapped to their Arrow equivalents:
+//!
+//! | PostgreSQL | Arrow |
+//! |------------|-------|
+//! | `BIGINT` | `Int64` |
+//! | `INTEGER` | `Int32` |
+//! | `SMALLINT` | `Int16` |
+//! | `DOUBLE PRECISION` | `Float64` |
+//! | `REAL` | `Float32` |
+//! | `BOOLEAN` | `Boolean` |
+//! | `TEXT`, `VARCHAR` | `Utf8` |
+//! | `JSON`, `JSONB`, `UUID`, `INET` | `Utf8` |
+//! | `TIMESTAMPTZ` | `Timestamp(Microsecond, "UTC")` |
+//! | `TIMESTAMP` | `Timestamp(Microsecond)` |
+//!
+//! Requires the `parquet` feature.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use rwf::analytics::Export;
+//!
+//! let query = User::all().filter_gt("created_at", since);
+//! let rows = Export::new(query).to_file("users.parquet", &mut conn).await?;
+//! ```
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use arrow_array::{
+    ArrayRef, BinaryArray, BooleanArray, Float32Array, Float64Array, Int16Array, Int32Array,
+    Int64Array, RecordBatch, StringArray, TimestampMicrosecondArray,
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::arrow::AsyncArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use thiserror::Error;
+use tokio::io::AsyncWrite;
+use tokio_postgres::{types::Type, Column, RowStream};
+use tokio_stream::StreamExt;
+
+use crate::model::{ConnectionGuard, FromRow, Query, ToSql, Value};
+
+/// Error returned by the exporter.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// Only `SELECT` and raw queries can be exported.
+    #[error("only select queries can be exported")]
+    UnsupportedQuery,
+
+    /// The column type doesn't have an Arrow equivalent.
+    #[error("column \"{0}\" has unsupported type \"{1}\"")]
+    UnsupportedType(String, String),
+
+    /// Error building the record batch.
+    #[error("arrow error: {0}")]
+    Arrow(#[from] ArrowError),
+
+    /// Error writing the Parquet file.
+    #[error("parquet error: {0}")]
+    Parquet(#[from] ParquetError),
+
+    /// The ORM returned an error.
+    #[error("export database error: {0}")]
+    DatabaseError(#[from] crate::model::Error),
+}
+
+impl From<tokio_postgres::Error> for Error {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DatabaseError(err.into())
+    }
+}
+
+/// Export the results of a query.
+#[derive(Debug, Clone)]
+pub struct Export<T: FromRow> {
+    query: Query<T>,
+    batch_size: usize,
+}
+
+impl<T: FromRow> Export<T> {
+    /// Export the results of the query. Only `SELECT` and raw queries are supported.
+    pub fn new(query: Query<T>) -> Self {
+        Self {
+            query,
+            batch_size: 8192,
+        }
+    }
+
+    /// Number of rows in each record batch. Default: 8192.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Execute the query and read the results as Arrow record batches.
+    pub async fn batches(&self, conn: &mut ConnectionGuard) -> Result<RecordBatches, Error> {
+        let placeholders = match self.query {
+            Query::Select(ref select) => select.placeholders(),
+            Query::Raw {
+                ref placeholders, ..
+            } => placeholders,
+            _ => return Err(Error::UnsupportedQuery),
+        };
+
+        let client = conn.client();
+        let statement = client.prepare(&self.query.to_sql()).await?;
+        let schema = schema(statement.columns())?;
+        let rows = client.query_raw(&statement, placeholders.values()).await?;
+
+        Ok(RecordBatches {
+            schema,
+            rows: Box::pin(rows),
+            batch_size: self.batch_size,
+            done: false,
+        })
+    }
+
+    /// Write the results to a Parquet file, compressed with Zstandard.
+    /// Returns the number of rows written.
+    pub async fn write<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: W,
+        conn: &mut ConnectionGuard,
+    ) -> Result<usize, Error> {
+        let mut batches = self.batches(conn).await?;
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .build();
+        let mut writer = AsyncArrowWriter::try_new(writer, batches.schema(), Some(properties))?;
+        let mut rows = 0;
+
+        while let Some(batch) = batches.next().await? {
+            rows += batch.num_rows();
+            writer.write(&batch).await?;
+        }
+
+        writer.close().await?;
+
+        Ok(rows)
+    }
+
+    /// Write the results to a Parquet file at the path.
+    /// Returns the number of rows written.
+    pub async fn to_file(
+        &self,
+        path: impl AsRef<Path>,
+        conn: &mut ConnectionGuard,
+    ) -> Result<usize, Error> {
+        let file = tokio::fs::File::create(path)
+            .await
+            .map_err(ParquetError::from)?;
+        self.write(file, conn).await
+    }
+}
+
+/// Query results, read as Arrow record batches.
+pub struct RecordBatches {
+    schema: SchemaRef,
+    rows: Pin<Box<RowStream>>,
+    batch_size: usize,
+    done: bool,
+}
+
+impl RecordBatches {
+    /// Schema of the record batches.
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    /// Read the next batch of rows. Returns `None` when all rows have been read.
+    pub async fn next(&mut self) -> Result<Option<RecordBatch>, Error> {
+        let mut columns = vec![Vec::with_capacity(self.batch_size); self.schema.fields().len()];
+        let mut rows = 0;
+
+        // The stream can't be polled again once it's finished.
+        while rows < self.batch_size && !self.done {
+            let row = match self.rows.next().await {
+                Some(row) => row?,
+                None => {
+                    self.done = true;
+                    break;
+                }
+            };
+
+            for (i, column) in columns.iter_mut().enumerate() {
+                column.push(row.try_get::<_, Value>(i)?);
+            }
+
+            rows += 1;
+        }
+
+        if rows == 0 {
+            return Ok(None);
+        }
+
+        let arrays = self
+            .schema
+            .fields()
+            .iter()
+            .zip(columns)
+            .map(|(field, values)| array(field.data_type(), values))
+            .collect::<Vec<_>>();
+
+        Ok(Some(RecordBatch::try_new(self.schema.clone(), arrays)?))
+    }
+}
+
+/// Build the Arrow schema from the query columns.
+fn schema(columns: &[Column]) -> Result<SchemaRef, Error> {
+    let fields = columns
+        .iter()
+        .map(|column| {
+            let data_type = data_type(column.type_()).ok_or_else(|| {
+                Error::UnsupportedType(column.name().to_string(), column.type_().to_string())
+            })?;
+            Ok(Field::new(column.name(), data_type, true))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(Arc::new(Schema::new(fields)))
+}
+
+/// Arrow type of values read from a column of this type.
+fn data_type(ty: &Type) -> Option<DataType> {
+    Some(match *ty {
+        Type::BOOL => DataType::Boolean,
+        Type::INT8 => DataType::Int64,
+        Type::INT4 => DataType::Int32,
+        Type::INT2 => DataType::Int16,
+        Type::FLOAT4 => DataType::Float32,
+        Type::FLOAT8 => DataType::Float64,
+        Type::TEXT | Type::VARCHAR | Type::JSON | Type::JSONB | Type::UUID | Type::INET => {
+            DataType::Utf8
+        }
+        Type::TIMESTAMPTZ => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+        Type::TIMESTAMP => DataType::Timestamp(TimeUnit::Microsecond, None),
+        Type::BYTEA => DataType::Binary,
+        _ => return None,
+    })
+}
+
+/// Convert the column values to an Arrow array of the type.
+fn array(data_type: &DataType, values: Vec<Value>) -> ArrayRef {
+    let values = values.into_iter();
+
+    match data_type {
+        DataType::Boolean => Arc::new(BooleanArray::from_iter(values.map(|value| match value {
+            Value::Boolean(value) => Some(value),
+            _ => None,
+        }))),
+        DataType::Int64 => Arc::new(Int64Array::from_iter(values.map(|value| match value {
+            Value::Integer(value) | Value::BigInt(value) => Some(value),
+            _ => None,
+        }))),
+        DataType::Int32 => Arc::new(Int32Array::from_iter(values.map(|value| match value {
+            Value::Int(value) => Some(value),
+            _ => None,
+        }))),
+        DataType::Int16 => Arc::new(Int16Array::from_iter(values.map(|value| match value {
+            Value::SmallInt(value) => Some(value),
+            _ => None,
+        }))),
+        DataType::Float32 => Arc::new(Float32Array::from_iter(values.map(|value| match value {
+            Value::Real(value) => Some(value),
+            _ => None,
+        }))),
+        DataType::Float64 => Arc::new(Float64Array::from_iter(values.map(|value| match value {
+            Value::Float(value) => Some(value),
+            _ => None,
+        }))),
+        DataType::Binary => Arc::new(BinaryArray::from_iter(values.map(|value| match value {
+            Value::Bytes(value) => Some(value),
+            _ => None,
+        }))),
+        DataType::Timestamp(_, timezone) => {
+            let array = TimestampMicrosecondArray::from_iter(values.map(|value| match value {
+                Value::TimestampT(value) => Some(micros(value.unix_timestamp_nanos())),
+                Value::Timestamp(value) => Some(micros(value.assume_utc().unix_timestamp_nanos())),
+                _ => None,
+            }));
+            Arc::new(array.with_timezone_opt(timezone.clone()))
+        }
+        _ => Arc::new(StringArray::from_iter(values.map(|value| match value {
+            Value::String(value) => Some(value),
+            Value::Json(value) => Some(value.to_string()),
+            Value::Uuid(value) => Some(value.to_string()),
+            Value::IpAddr(value) => Some(value.to_string()),
+            _ => None,
+        }))),
+    }
+}
+
+fn micros(nanos: i128) -> i64 {
+    (nanos / 1_000) as i64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{Model, Pool, Row, ToValue};
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Int32Type, Int64Type, TimestampMicrosecondType};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[tokio::test]
+    async fn test_export() -> Result<(), Error> {
+        let pool = Pool::from_env();
+        let mut conn = pool.transaction().await?;
+
+        conn.client()
+            .batch_execute(
+                "CREATE TABLE rwf_test_exports (
+                    id BIGSERIAL PRIMARY KEY,
+                    name VARCHAR,
+                    score INTEGER,
+                    tags JSONB,
+                    created_at TIMESTAMPTZ NOT NULL DEFAULT '2024-01-01 00:00:00.5+00'
+                );
+                INSERT INTO rwf_test_exports (name, score, tags)
+                SELECT 'user ' || n, n, '{\"a\": 1}' FROM generate_series(1, 5) n;
+                INSERT INTO rwf_test_exports (name, score, tags) VALUES (NULL, NULL, NULL);",
+            )
+            .await?;
+
+        let query = Row::find_by_sql(
+            "SELECT * FROM rwf_test_exports WHERE id > $1 ORDER BY id",
+            &[1_i64.to_value()],
+        );
+        let export = Export::new(query).batch_size(2);
+
+        let mut batches = export.batches(&mut conn).await?;
+        let schema = batches.schema();
+        assert_eq!(schema.field(0).data_type(), &DataType::Int64);
+        assert_eq!(schema.field(1).data_type(), &DataType::Utf8);
+        assert_eq!(schema.field(2).data_type(), &DataType::Int32);
+        assert_eq!(schema.field(3).data_type(), &DataType::Utf8);
+
+        let mut sizes = vec![];
+        while let Some(batch) = batches.next().await? {
+            sizes.push(batch.num_rows());
+        }
+        assert_eq!(sizes, vec![2, 2, 1]);
+
+        let mut file = vec![];
+        assert_eq!(export.write(&mut file, &mut conn).await?, 5);
+
+        let batch = ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(file))?
+            .build()?
+            .next()
+            .unwrap()?;
+        assert_eq!(batch.num_rows(), 5);
+        assert_eq!(batch.column(0).as_primitive::<Int64Type>().value(0), 2);
+        assert_eq!(batch.column(1).as_string::<i32>().value(0), "user 2");
+        assert_eq!(batch.column(2).as_primitive::<Int32Type>().value(3), 5);
+        assert_eq!(batch.column(3).as_string::<i32>().value(0), r#"{"a":1}"#);
+        assert_eq!(
+            batch
+                .column(4)
+                .as_primitive::<TimestampMicrosecondType>()
+                .value(0),
+            1_704_067_200_500_000
+        );
+        assert!(batch.column(1).is_null(4));
+        assert!(batch.column(2).is_null(4));
+
+        let unsupported = Export::new(Row::find_by_sql("SELECT 1::numeric AS n", &[]));
+        assert!(matches!(
+            unsupported.batches(&mut conn).await,
+            Err(Error::UnsupportedType(..))
+        ));
+
+        conn.rollback().await?;
+        Ok(())
+    }
+}
@@ -1,9 +1,14 @@
 //! Analytics around aplication usage.
 //!
-//! Work in progress, but currently handles HTTP request tracking. On the roadmap:
+//! Work in progress, but currently handles HTTP request tracking and [usage metering](usage). On the roadmap:
 //!
 //! * Experiments (A/B testing)
 
+#[cfg(feature = "parquet")]
+pub mod export;
 pub mod requests;
+pub mod usage;
 
+#[cfg(feature = "parquet")]
+pub use export::Export;
 pub use requests::Request;
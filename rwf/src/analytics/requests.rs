@@ -1,7 +1,8 @@
 //! Record HTTP requests made to the application.
 //!
-//! This will record the request path, response code, client IP, duration
-//! and other metadata.
+//! This will record the request path, route, response code, client IP, duration
+//! and other metadata. Requests which failed with a server error record the error
+//! and, if backtraces are enabled, where it happened.
 //!
 use std::net::IpAddr;
 
@@ -14,12 +15,15 @@ use time::OffsetDateTime;
 pub struct Request {
     id: Option<i64>,
     path: String,
+    route: Option<String>,
     method: String,
     query: serde_json::Value,
     code: i32,
     client_ip: Option<IpAddr>,
     created_at: OffsetDateTime,
     duration: f32,
+    error: Option<String>,
+    backtrace: Option<String>,
 }
 
 impl FromRow for Request {
@@ -27,12 +31,15 @@ impl FromRow for Request {
         Ok(Self {
             id: row.try_get("id")?,
             path: row.try_get("path")?,
+            route: row.try_get("route")?,
             method: row.try_get("method")?,
             query: row.try_get("query")?,
             code: row.try_get("code")?,
             client_ip: row.try_get("client")?,
             created_at: row.try_get("created_at")?,
             duration: row.try_get("duration")?,
+            error: row.try_get("error")?,
+            backtrace: row.try_get("backtrace")?,
         })
     }
 }
@@ -53,24 +60,30 @@ impl Model for Request {
     fn column_names() -> &'static [&'static str] {
         &[
             "path",
+            "route",
             "method",
             "query",
             "code",
             "client_ip",
             "created_at",
             "duration",
+            "error",
+            "backtrace",
         ]
     }
 
     fn values(&self) -> Vec<Value> {
         vec![
             self.path.to_value(),
+            self.route.to_value(),
             self.method.to_value(),
             self.query.to_value(),
             self.code.to_value(),
             self.client_ip.to_value(),
             self.created_at.to_value(),
             self.duration.to_value(),
+            self.error.to_value(),
+            self.backtrace.to_value(),
         ]
     }
 }
@@ -0,0 +1,306 @@
+//! Usage metering, e.g. to bill customers for what they use.
+//!
+//! Usage is counted per account, which can be a user, a team, or a tenant, and per metric,
+//! e.g. `requests`, `bandwidth`, `jobs`, or anything the app wants to count:
+//!
+//! ```
+//! use rwf::analytics::usage;
+//!
+//! usage::record("team_42", "emails_sent", 1);
+//! ```
+//!
+//! Counters are kept in memory and written to the `rwf_usage_events` table every few seconds.
+//! The [`Rollup`] job aggregates events into hourly totals in the `rwf_usage_rollups` table, which keeps
+//! queries fast no matter how much is recorded. Requests and bandwidth are counted by the
+//! [`Metering`](crate::controller::middleware::Metering) middleware, and jobs by the worker for jobs
+//! which return an account from [`Job::account`](crate::job::Job::account).
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::Mutex;
+use time::OffsetDateTime;
+use tokio::time::interval;
+use tracing::error;
+
+use crate::job::{Error as JobError, Job};
+use crate::model::{get_connection, ConnectionGuard, Error};
+
+static METER: Lazy<Meter> = Lazy::new(Meter::default);
+static FLUSHER: OnceCell<()> = OnceCell::new();
+
+/// How often counters are written to the database.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Count usage of the metric by the account.
+pub fn record(account: impl ToString, metric: impl ToString, quantity: i64) {
+    METER.record(account, metric, quantity);
+
+    // Write counters periodically, if we're running inside Tokio.
+    if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+        FLUSHER.get_or_init(|| {
+            runtime.spawn(async {
+                let mut interval = interval(FLUSH_INTERVAL);
+
+                loop {
+                    interval.tick().await;
+
+                    if let Err(err) = flush().await {
+                        error!("usage metering flush failed: {}", err);
+                    }
+                }
+            });
+        });
+    }
+}
+
+/// Write recorded usage to the database now. Returns the number of counters written.
+pub async fn flush() -> Result<usize, Error> {
+    let mut conn = get_connection().await?;
+    METER.flush(&mut conn).await
+}
+
+/// Usage counters waiting to be written to the database.
+#[derive(Default)]
+pub struct Meter {
+    counters: Mutex<HashMap<(String, String), i64>>,
+}
+
+impl Meter {
+    /// Count usage of the metric by the account.
+    pub fn record(&self, account: impl ToString, metric: impl ToString, quantity: i64) {
+        *self
+            .counters
+            .lock()
+            .entry((account.to_string(), metric.to_string()))
+            .or_default() += quantity;
+    }
+
+    /// Write the counters to the database. If that fails, they are kept for the next attempt.
+    pub async fn flush(&self, conn: &mut ConnectionGuard) -> Result<usize, Error> {
+        let counters = std::mem::take(&mut *self.counters.lock());
+
+        if counters.is_empty() {
+            return Ok(0);
+        }
+
+        let mut accounts = vec![];
+        let mut metrics = vec![];
+        let mut quantities = vec![];
+
+        for ((account, metric), quantity) in &counters {
+            accounts.push(account.as_str());
+            metrics.push(metric.as_str());
+            quantities.push(*quantity);
+        }
+
+        let result = conn
+            .client()
+            .execute(
+                "INSERT INTO rwf_usage_events (account, metric, quantity)
+                SELECT * FROM UNNEST($1::VARCHAR[], $2::VARCHAR[], $3::BIGINT[])",
+                &[&accounts, &metrics, &quantities],
+            )
+            .await;
+
+        match result {
+            Ok(_) => Ok(counters.len()),
+            Err(err) => {
+                for ((account, metric), quantity) in counters {
+                    self.record(account, metric, quantity);
+                }
+
+                Err(err.into())
+            }
+        }
+    }
+}
+
+/// Aggregate recorded events into hourly rollups. Returns the number of events aggregated.
+pub async fn rollup(conn: &mut ConnectionGuard) -> Result<u64, Error> {
+    let rows = conn
+        .client()
+        .query(
+            "WITH events AS (
+                DELETE FROM rwf_usage_events
+                RETURNING account, metric, quantity, created_at
+            ), rollups AS (
+                INSERT INTO rwf_usage_rollups (account, metric, period_start, quantity)
+                SELECT account, metric, date_trunc('hour', created_at), SUM(quantity)::BIGINT
+                FROM events
+                GROUP BY 1, 2, 3
+                ON CONFLICT (account, metric, period_start)
+                DO UPDATE SET quantity = rwf_usage_rollups.quantity + EXCLUDED.quantity
+            )
+            SELECT COUNT(*) FROM events",
+            &[],
+        )
+        .await?;
+
+    let count: i64 = rows.first().map(|row| row.get(0)).unwrap_or(0);
+
+    Ok(count as u64)
+}
+
+/// Total usage of the metric by the account, between `from` (inclusive) and `to` (exclusive).
+/// Rolled up usage is counted by the hour it was recorded in.
+pub async fn total(
+    conn: &mut ConnectionGuard,
+    account: &str,
+    metric: &str,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+) -> Result<i64, Error> {
+    let rows = conn
+        .client()
+        .query(
+            "SELECT COALESCE(SUM(quantity), 0)::BIGINT FROM (
+                SELECT quantity FROM rwf_usage_rollups
+                WHERE account = $1 AND metric = $2 AND period_start >= $3 AND period_start < $4
+                UNION ALL
+                SELECT quantity FROM rwf_usage_events
+                WHERE account = $1 AND metric = $2 AND created_at >= $3 AND created_at < $4
+            ) usage",
+            &[&account, &metric, &from, &to],
+        )
+        .await?;
+
+    Ok(rows.first().map(|row| row.get(0)).unwrap_or(0))
+}
+
+/// Length of the periods usage is grouped by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Period {
+    /// Hourly usage.
+    Hour,
+    /// Daily usage.
+    Day,
+    /// Monthly usage, e.g. for billing.
+    Month,
+}
+
+impl Period {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Hour => "hour",
+            Self::Day => "day",
+            Self::Month => "month",
+        }
+    }
+}
+
+/// Usage of a metric in a period.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Usage {
+    /// What was used, e.g. `requests`.
+    pub metric: String,
+    /// When the period started.
+    pub period_start: OffsetDateTime,
+    /// How much was used.
+    pub quantity: i64,
+}
+
+/// Usage of all metrics by the account, between `from` (inclusive) and `to` (exclusive),
+/// grouped by period, e.g. for an invoice or a usage chart.
+pub async fn breakdown(
+    conn: &mut ConnectionGuard,
+    account: &str,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+    period: Period,
+) -> Result<Vec<Usage>, Error> {
+    let rows = conn
+        .client()
+        .query(
+            "SELECT metric, date_trunc($4, recorded_at) AS period_start, SUM(quantity)::BIGINT FROM (
+                SELECT metric, quantity, period_start AS recorded_at FROM rwf_usage_rollups
+                WHERE account = $1 AND period_start >= $2 AND period_start < $3
+                UNION ALL
+                SELECT metric, quantity, created_at AS recorded_at FROM rwf_usage_events
+                WHERE account = $1 AND created_at >= $2 AND created_at < $3
+            ) usage
+            GROUP BY 1, 2
+            ORDER BY 2, 1",
+            &[&account, &from, &to, &period.as_str()],
+        )
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Usage {
+            metric: row.get(0),
+            period_start: row.get(1),
+            quantity: row.get(2),
+        })
+        .collect())
+}
+
+/// Job aggregating recorded usage into hourly rollups. Run it on a schedule, e.g. every 5 minutes:
+///
+/// ```
+/// # use rwf::job::{Job, Worker};
+/// # use rwf::analytics::usage::Rollup;
+/// let worker = Worker::new(vec![]).clock(vec![
+///     Rollup.schedule(serde_json::json!({}), "*/5 * * * *").unwrap(),
+/// ]);
+/// ```
+#[derive(Default, Debug, Clone)]
+pub struct Rollup;
+
+#[async_trait]
+impl Job for Rollup {
+    async fn execute(&self, _args: serde_json::Value) -> Result<(), JobError> {
+        let mut conn = get_connection().await?;
+        rollup(&mut conn).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{test::bootstrap, Pool};
+    use time::Duration;
+
+    #[tokio::test]
+    async fn test_usage() -> Result<(), Error> {
+        let pool = Pool::from_env();
+        let mut conn = pool.transaction().await?;
+
+        bootstrap(conn.client()).await?;
+
+        let account = "rwf_test_usage_account";
+        let meter = Meter::default();
+        meter.record(account, "requests", 1);
+        meter.record(account, "requests", 2);
+        meter.record(account, "bandwidth", 1024);
+        assert_eq!(meter.flush(&mut conn).await?, 2);
+        assert_eq!(meter.flush(&mut conn).await?, 0);
+
+        let from = OffsetDateTime::now_utc() - Duration::hours(2);
+        let to = OffsetDateTime::now_utc() + Duration::hours(2);
+        assert_eq!(total(&mut conn, account, "requests", from, to).await?, 3);
+
+        assert!(rollup(&mut conn).await? >= 2);
+
+        meter.record(account, "requests", 4);
+        meter.flush(&mut conn).await?;
+
+        // Rolled up and recent usage are both counted.
+        assert_eq!(total(&mut conn, account, "requests", from, to).await?, 7);
+
+        let usage = breakdown(&mut conn, account, from, to, Period::Day).await?;
+        let quantity = |metric: &str| {
+            usage
+                .iter()
+                .filter(|usage| usage.metric == metric)
+                .map(|usage| usage.quantity)
+                .sum::<i64>()
+        };
+        assert_eq!(quantity("requests"), 7);
+        assert_eq!(quantity("bandwidth"), 1024);
+
+        Ok(())
+    }
+}
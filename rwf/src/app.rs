@@ -0,0 +1,547 @@
+//! Process supervisor, running the HTTP server, background jobs and other services in one binary.
+//!
+//! Each service runs in its own task and is restarted, with exponential back-off, if it fails.
+//! When the process receives `SIGINT`/Ctrl-C, all services are told to stop and are given some time
+//! to finish what they're doing, e.g. the job being executed, before they are aborted.
+//!
+//! ### Example
+//!
+//! ```rust,no_run
+//! use rwf::prelude::*;
+//! use rwf::app::App;
+//! use rwf::http::Server;
+//! use rwf::job::Worker;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), rwf::error::Error> {
+//! App::new()
+//!     .server(Server::new(vec![]))
+//!     .worker(Worker::new(vec![]))
+//!     .run()
+//!     .await
+//! # }
+//! ```
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use tokio::select;
+use tokio::signal::ctrl_c;
+use tokio::sync::{watch, OnceCell};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::{sleep, timeout};
+use tracing::{error, info, warn};
+
+use crate::cluster;
+use crate::colors::MaybeColorize;
+use crate::error::Error;
+use crate::http::Server;
+use crate::job::{JobModel, Worker};
+use crate::model::get_connection;
+use crate::telemetry;
+
+type ServiceFn =
+    Arc<dyn Fn(Shutdown) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> + Send + Sync>;
+
+/// When a service is restarted after it stops.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Restart {
+    /// Never restart the service. If it fails, the whole application shuts down.
+    Never,
+    /// Restart the service if it returns an error or panics.
+    #[default]
+    OnFailure,
+    /// Restart the service whenever it stops.
+    Always,
+}
+
+/// Tells services the application is shutting down.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    receiver: Option<watch::Receiver<bool>>,
+}
+
+impl Shutdown {
+    /// Never shut down, for services running outside of the supervisor.
+    pub fn never() -> Self {
+        Self { receiver: None }
+    }
+
+    /// The application is shutting down.
+    pub fn is_shutdown(&self) -> bool {
+        self.receiver
+            .as_ref()
+            .map(|receiver| *receiver.borrow())
+            .unwrap_or(false)
+    }
+
+    /// Wait for the application to shut down.
+    pub async fn wait(&mut self) {
+        match self.receiver {
+            Some(ref mut receiver) => {
+                let _ = receiver.wait_for(|shutdown| *shutdown).await;
+            }
+            None => std::future::pending().await,
+        }
+    }
+}
+
+/// State of a service.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    /// The service is running.
+    Running,
+    /// The service stopped and will be restarted.
+    Restarting,
+    /// The service stopped and won't be restarted.
+    Stopped,
+    /// The service failed and won't be restarted.
+    Failed,
+}
+
+/// Health of a service.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceHealth {
+    /// Service name.
+    pub name: String,
+    /// What the service is doing.
+    pub state: State,
+    /// How many times the service was restarted.
+    pub restarts: usize,
+    /// Error returned by the service the last time it failed.
+    pub last_error: Option<String>,
+}
+
+/// Health of all services, updated by the supervisor.
+#[derive(Debug, Clone, Default)]
+pub struct Health {
+    services: Arc<Mutex<Vec<ServiceHealth>>>,
+}
+
+impl Health {
+    /// None of the services are failing or waiting to be restarted.
+    pub fn healthy(&self) -> bool {
+        self.services
+            .lock()
+            .iter()
+            .all(|service| matches!(service.state, State::Running | State::Stopped))
+    }
+
+    /// Health of each service.
+    pub fn services(&self) -> Vec<ServiceHealth> {
+        self.services.lock().clone()
+    }
+
+    fn update(&self, index: usize, f: impl FnOnce(&mut ServiceHealth)) {
+        if let Some(service) = self.services.lock().get_mut(index) {
+            f(service);
+        }
+    }
+}
+
+/// A task supervised by the [`App`].
+pub struct Service {
+    name: String,
+    restart: Restart,
+    backoff: Duration,
+    max_backoff: Duration,
+    run: ServiceFn,
+}
+
+impl Service {
+    /// Create a service. The function is called every time the service is started. It should return
+    /// when the application is shutting down, which it can wait for with [`Shutdown::wait`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rwf::app::Service;
+    /// # use rwf::broker::Publisher;
+    /// let service = Service::new("outbox", |mut shutdown| async move {
+    ///     let publisher = Publisher::new();
+    ///
+    ///     rwf::tokio::select! {
+    ///         _ = publisher.run() => (),
+    ///         _ = shutdown.wait() => (),
+    ///     }
+    ///
+    ///     Ok(())
+    /// });
+    /// ```
+    pub fn new<F, Fut>(name: impl ToString, run: F) -> Self
+    where
+        F: Fn(Shutdown) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            restart: Restart::default(),
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            run: Arc::new(move |shutdown| Box::pin(run(shutdown))),
+        }
+    }
+
+    /// When to restart the service. Default: on failure.
+    pub fn restart(mut self, restart: Restart) -> Self {
+        self.restart = restart;
+        self
+    }
+
+    /// How long to wait before restarting the service. The delay doubles every time
+    /// the service is restarted, up to `max`. Default: 1 second, up to 30 seconds.
+    pub fn backoff(mut self, min: Duration, max: Duration) -> Self {
+        self.backoff = min;
+        self.max_backoff = max.max(min);
+        self
+    }
+}
+
+/// Aborts the service when the supervisor is aborted.
+struct Task(JoinHandle<Result<(), Error>>);
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Application running multiple services in one process.
+pub struct App {
+    services: Vec<Service>,
+    shutdown_timeout: Duration,
+    health: Health,
+}
+
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl App {
+    /// Create an application without any services.
+    pub fn new() -> Self {
+        Self {
+            services: vec![],
+            shutdown_timeout: Duration::from_secs(30),
+            health: Health::default(),
+        }
+    }
+
+    /// Run the HTTP server. It stops accepting connections when the application shuts down.
+    pub fn server(self, server: Server) -> Self {
+        self.service(Service::new("server", move |mut shutdown| {
+            let server = server.clone();
+            async move {
+                server
+                    .launch_with_shutdown(async move { shutdown.wait().await })
+                    .await?;
+                Ok(())
+            }
+        }))
+    }
+
+    /// Run the background jobs worker, and its scheduled jobs, if any, as the `scheduler` service.
+    /// The worker finishes the job it's executing before shutting down.
+    pub fn worker(self, worker: Worker) -> Self {
+        let scheduler = worker.scheduler().cloned();
+
+        // Jobs left running by the previous process are retried once.
+        let rescheduled = Arc::new(OnceCell::new());
+
+        let app = self.service(
+            Service::new("worker", move |shutdown| {
+                let worker = worker.clone();
+                let rescheduled = rescheduled.clone();
+                async move {
+                    rescheduled
+                        .get_or_try_init(|| async {
+                            let mut conn = get_connection().await?;
+                            JobModel::reschedule().execute(&mut conn).await?;
+                            Ok::<(), crate::job::Error>(())
+                        })
+                        .await?;
+
+                    worker.run_until(shutdown).await;
+                    Ok(())
+                }
+            })
+            .restart(Restart::Always),
+        );
+
+        match scheduler {
+            Some(clock) => app.service(Service::new("scheduler", move |mut shutdown| {
+                let clock = clock.clone();
+                async move {
+                    select! {
+                        result = clock.run() => result?,
+                        _ = shutdown.wait() => (),
+                    }
+                    Ok(())
+                }
+            })),
+            None => app,
+        }
+    }
+
+    /// Run a service.
+    pub fn service(mut self, service: Service) -> Self {
+        self.services.push(service);
+        self
+    }
+
+    /// How long services have to stop after the application is told to shut down,
+    /// before they are aborted. Default: 30 seconds.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Health of the services, e.g. to report it from a health check endpoint.
+    pub fn health(&self) -> Health {
+        self.health.clone()
+    }
+
+    /// Run all services until the process receives `SIGINT`/Ctrl-C, or a service
+    /// that can't be restarted fails.
+    pub async fn run(self) -> Result<(), Error> {
+        self.run_until(async {
+            let _ = ctrl_c().await;
+        })
+        .await
+    }
+
+    /// Run all services until the `shutdown` future completes, or a service
+    /// that can't be restarted fails.
+    pub async fn run_until(self, shutdown: impl Future<Output = ()>) -> Result<(), Error> {
+        cluster::verify()?;
+
+        let (sender, receiver) = watch::channel(false);
+        let receiver = Shutdown {
+            receiver: Some(receiver),
+        };
+
+        *self.health.services.lock() = self
+            .services
+            .iter()
+            .map(|service| ServiceHealth {
+                name: service.name.clone(),
+                state: State::Running,
+                restarts: 0,
+                last_error: None,
+            })
+            .collect();
+
+        let mut tasks = JoinSet::new();
+
+        for (index, service) in self.services.into_iter().enumerate() {
+            info!("Starting {} service", service.name.green());
+            tasks.spawn(supervise(
+                service,
+                index,
+                self.health.clone(),
+                receiver.clone(),
+            ));
+        }
+
+        tokio::pin!(shutdown);
+        let mut result = Ok(());
+
+        loop {
+            select! {
+                _ = &mut shutdown => {
+                    info!("Shutting down...");
+                    break;
+                }
+
+                joined = tasks.join_next() => match joined {
+                    // All services stopped.
+                    None => break,
+                    Some(Ok(Err(err))) => {
+                        error!("service failed, shutting down: {}", err);
+                        result = Err(err);
+                        break;
+                    }
+                    Some(_) => (),
+                }
+            }
+        }
+
+        let _ = sender.send(true);
+
+        // Give services time to finish what they're doing.
+        let stopped = timeout(self.shutdown_timeout, async {
+            while tasks.join_next().await.is_some() {}
+        })
+        .await;
+
+        if stopped.is_err() {
+            warn!(
+                "services didn't stop within {:?}, aborting",
+                self.shutdown_timeout
+            );
+            tasks.shutdown().await;
+        }
+
+        telemetry::shutdown();
+
+        result
+    }
+}
+
+/// Run the service, restarting it according to its policy. Returns an error
+/// if the service failed and won't be restarted.
+async fn supervise(
+    service: Service,
+    index: usize,
+    health: Health,
+    mut shutdown: Shutdown,
+) -> Result<(), Error> {
+    let mut delay = service.backoff;
+
+    loop {
+        let started = Instant::now();
+        health.update(index, |health| health.state = State::Running);
+
+        let mut task = Task(tokio::spawn((service.run)(shutdown.clone())));
+        let result = match (&mut task.0).await {
+            Ok(result) => result,
+            Err(_) => Err(Error::Error("service panicked".into())),
+        };
+
+        if shutdown.is_shutdown() {
+            health.update(index, |health| health.state = State::Stopped);
+            return Ok(());
+        }
+
+        let restart = match service.restart {
+            Restart::Always => true,
+            Restart::OnFailure => result.is_err(),
+            Restart::Never => false,
+        };
+
+        if !restart {
+            return match result {
+                Ok(()) => {
+                    info!("{} service stopped", service.name.green());
+                    health.update(index, |health| health.state = State::Stopped);
+                    Ok(())
+                }
+                Err(err) => {
+                    error!("{} service failed: {}", service.name.green(), err);
+                    health.update(index, |health| {
+                        health.state = State::Failed;
+                        health.last_error = Some(err.to_string());
+                    });
+                    Err(err)
+                }
+            };
+        }
+
+        // The service ran for a while, so it's not failing on start.
+        if started.elapsed() > service.max_backoff {
+            delay = service.backoff;
+        }
+
+        match result {
+            Ok(()) => warn!(
+                "{} service stopped, restarting in {:?}",
+                service.name.green(),
+                delay
+            ),
+            Err(ref err) => error!(
+                "{} service failed, restarting in {:?}: {}",
+                service.name.green(),
+                delay,
+                err
+            ),
+        }
+
+        health.update(index, |health| {
+            health.state = State::Restarting;
+            health.restarts += 1;
+            if let Err(err) = result {
+                health.last_error = Some(err.to_string());
+            }
+        });
+
+        select! {
+            _ = sleep(delay) => (),
+            _ = shutdown.wait() => {
+                health.update(index, |health| health.state = State::Stopped);
+                return Ok(());
+            }
+        }
+
+        delay = (delay * 2).min(service.max_backoff);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_restart() {
+        let starts = Arc::new(AtomicUsize::new(0));
+        let counter = starts.clone();
+
+        let app = App::new().service(
+            Service::new("flaky", move |mut shutdown| {
+                let starts = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                async move {
+                    if starts < 3 {
+                        return Err(Error::Error("failed".into()));
+                    }
+                    shutdown.wait().await;
+                    Ok(())
+                }
+            })
+            .backoff(Duration::from_millis(1), Duration::from_millis(5)),
+        );
+        let health = app.health();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+
+        let handle = tokio::spawn(app.run_until(async move {
+            let _ = stopped.await;
+        }));
+
+        while starts.load(Ordering::SeqCst) < 3 {
+            sleep(Duration::from_millis(1)).await;
+        }
+
+        let services = health.services();
+        assert_eq!(services[0].restarts, 2);
+        assert_eq!(services[0].last_error.as_deref(), Some("failed"));
+        assert!(health.healthy());
+
+        stop.send(()).unwrap();
+        handle.await.unwrap().unwrap();
+        assert_eq!(health.services()[0].state, State::Stopped);
+    }
+
+    #[tokio::test]
+    async fn test_never_restart() {
+        let app = App::new()
+            .service(
+                Service::new("fatal", |_| async { Err(Error::Error("fatal".into())) })
+                    .restart(Restart::Never),
+            )
+            .service(Service::new("other", |mut shutdown| async move {
+                shutdown.wait().await;
+                Ok(())
+            }));
+        let health = app.health();
+
+        // The failure shuts down the other service as well.
+        let result = app.run_until(std::future::pending()).await;
+        assert_eq!(result.unwrap_err().to_string(), "fatal");
+        assert!(!health.healthy());
+        assert_eq!(health.services()[0].state, State::Failed);
+        assert_eq!(health.services()[1].state, State::Stopped);
+    }
+}
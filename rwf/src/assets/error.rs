@@ -0,0 +1,13 @@
+//! Errors returned when fingerprinting assets.
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    /// Error reading the assets or the manifest.
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The manifest isn't valid JSON.
+    #[error("manifest: {0}")]
+    Json(#[from] serde_json::Error),
+}
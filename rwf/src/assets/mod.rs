@@ -0,0 +1,272 @@
+//! Static assets, like CSS, JavaScript and images, served with the hash of their content in their name.
+//!
+//! When fingerprinting is enabled in the `[assets]` section of `rwf.toml` (it is by default in release builds),
+//! assets are served under names that change when their content changes, e.g. `/static/css/app-3f2a9c1b7d6e5f40.css`
+//! for `static/css/app.css`. Browsers can cache them forever, and still download the new version after a deploy.
+//!
+//! Templates link to assets with `asset_path`, and Rust code with [`asset_path`]:
+//!
+//! ```erb
+//! <link rel="stylesheet" href="<%= asset_path("css/app.css") %>">
+//! ```
+//!
+//! The assets are served by [`StaticFiles::assets`](crate::controller::StaticFiles::assets). The manifest
+//! of fingerprinted names is generated by `rwf-cli package`, or when the app starts if there isn't one.
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use sha2::{Digest, Sha256};
+use tracing::error;
+
+use crate::config::get_config;
+
+pub mod error;
+
+pub use error::Error;
+
+static MANIFEST: Lazy<RwLock<Option<Arc<Manifest>>>> = Lazy::new(|| RwLock::new(None));
+
+/// Fingerprinted names of the assets.
+#[derive(Debug, Clone, Default)]
+pub struct Manifest {
+    /// Fingerprinted path by asset path, e.g. `css/app-3f2a9c1b7d6e5f40.css` for `css/app.css`.
+    files: BTreeMap<String, String>,
+    /// Asset path by fingerprinted path.
+    originals: HashMap<String, String>,
+}
+
+impl Manifest {
+    /// No assets.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fingerprint the files in the directory and its subdirectories. Hidden files,
+    /// like the manifest, are skipped. If the directory doesn't exist, there are no assets.
+    pub fn build(directory: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut manifest = Self::new();
+        let directory = directory.as_ref();
+
+        if directory.is_dir() {
+            manifest.add_dir(directory, "")?;
+        }
+
+        Ok(manifest)
+    }
+
+    fn add_dir(&mut self, directory: &Path, prefix: &str) -> Result<(), Error> {
+        for entry in std::fs::read_dir(directory)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+
+            if name.starts_with('.') {
+                continue;
+            }
+
+            let path = format!("{}{}", prefix, name);
+
+            if entry.file_type()?.is_dir() {
+                self.add_dir(&entry.path(), &format!("{}/", path))?;
+            } else {
+                self.add(&path, &std::fs::read(entry.path())?);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Load the manifest saved with [`Manifest::save`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let files: BTreeMap<String, String> =
+            serde_json::from_str(&std::fs::read_to_string(path)?)?;
+
+        let mut manifest = Self::new();
+        for (path, fingerprinted) in files {
+            manifest.insert(path, fingerprinted);
+        }
+
+        Ok(manifest)
+    }
+
+    /// Save the manifest as JSON, so the assets don't have to be fingerprinted when the app starts.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        std::fs::write(path, serde_json::to_string_pretty(&self.files)?)?;
+        Ok(())
+    }
+
+    /// Add the asset, e.g. `css/app.css`, fingerprinting its content.
+    pub fn add(&mut self, path: &str, content: &[u8]) -> &mut Self {
+        let path = path.trim_start_matches('/');
+        self.insert(path.to_string(), fingerprint(path, content));
+        self
+    }
+
+    fn insert(&mut self, path: String, fingerprinted: String) {
+        if let Some(previous) = self.files.insert(path.clone(), fingerprinted.clone()) {
+            self.originals.remove(&previous);
+        }
+        self.originals.insert(fingerprinted, path);
+    }
+
+    /// Fingerprinted path of the asset, e.g. `css/app-3f2a9c1b7d6e5f40.css` for `css/app.css`.
+    pub fn fingerprinted(&self, path: &str) -> Option<&str> {
+        self.files
+            .get(path.trim_start_matches('/'))
+            .map(|path| path.as_str())
+    }
+
+    /// Path of the asset with the fingerprinted path, e.g. `css/app.css` for `css/app-3f2a9c1b7d6e5f40.css`.
+    pub fn original(&self, fingerprinted: &str) -> Option<&str> {
+        self.originals
+            .get(fingerprinted.trim_start_matches('/'))
+            .map(|path| path.as_str())
+    }
+
+    /// Number of assets.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// There are no assets.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+/// Add the hash of the content to the file name, before its extension, e.g. `app-3f2a9c1b7d6e5f40.css`.
+fn fingerprint(path: &str, content: &[u8]) -> String {
+    let hash = Sha256::digest(content)
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+
+    let (directory, name) = match path.rsplit_once('/') {
+        Some((directory, name)) => (format!("{}/", directory), name),
+        None => (String::new(), path),
+    };
+
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => {
+            format!("{}{}-{}.{}", directory, stem, hash, extension)
+        }
+        _ => format!("{}{}-{}", directory, name, hash),
+    }
+}
+
+/// Manifest used by the app. Unless it was set with [`set_manifest`], it's loaded from the manifest file
+/// configured in `rwf.toml` when it's first needed, or built from the assets directory if the file doesn't exist.
+/// If fingerprinting is disabled, the manifest is empty.
+pub fn manifest() -> Arc<Manifest> {
+    if let Some(ref manifest) = *MANIFEST.read() {
+        return manifest.clone();
+    }
+
+    let config = &get_config().assets;
+    let manifest = if !config.fingerprint {
+        Ok(Manifest::new())
+    } else if config.manifest().is_file() {
+        Manifest::load(config.manifest())
+    } else {
+        Manifest::build(&config.directory)
+    };
+
+    let manifest = manifest.unwrap_or_else(|err| {
+        error!(
+            "failed to fingerprint assets in \"{}\": {}",
+            config.directory.display(),
+            err
+        );
+        Manifest::new()
+    });
+
+    MANIFEST
+        .write()
+        .get_or_insert_with(|| Arc::new(manifest))
+        .clone()
+}
+
+/// Use this manifest instead of the one configured in `rwf.toml`.
+pub fn set_manifest(manifest: Manifest) {
+    *MANIFEST.write() = Some(Arc::new(manifest));
+}
+
+/// URL of the asset, e.g. `/static/css/app-3f2a9c1b7d6e5f40.css` for `css/app.css`. Assets missing
+/// from the manifest keep their name.
+pub fn asset_path(path: &str) -> String {
+    let path = path.trim_start_matches('/');
+    let manifest = manifest();
+
+    format!(
+        "{}/{}",
+        get_config().assets.prefix.trim_end_matches('/'),
+        manifest.fingerprinted(path).unwrap_or(path)
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::view::Template;
+
+    #[test]
+    fn test_manifest() {
+        let dir = tempdir::TempDir::new("rwf_assets").unwrap();
+        std::fs::create_dir(dir.path().join("css")).unwrap();
+        std::fs::write(dir.path().join("css/app.css"), "body {}").unwrap();
+        std::fs::write(dir.path().join("robots.txt"), "").unwrap();
+        std::fs::write(dir.path().join(".manifest.json"), "{}").unwrap();
+
+        let manifest = Manifest::build(dir.path()).unwrap();
+        assert_eq!(manifest.len(), 2);
+
+        let app = manifest.fingerprinted("/css/app.css").unwrap().to_string();
+        assert!(app.starts_with("css/app-"));
+        assert!(app.ends_with(".css"));
+        assert_eq!(app.len(), "css/app-.css".len() + 16);
+        assert_eq!(manifest.original(&app), Some("css/app.css"));
+        assert!(manifest.original("css/app.css").is_none());
+
+        // Same content, same name.
+        let mut other = Manifest::new();
+        other.add("css/app.css", b"body {}");
+        assert_eq!(other.fingerprinted("css/app.css"), Some(app.as_str()));
+
+        // The content changed.
+        other.add("css/app.css", b"body { color: red; }");
+        assert_ne!(other.fingerprinted("css/app.css"), Some(app.as_str()));
+        assert!(other.original(&app).is_none());
+
+        let path = dir.path().join(".manifest.json");
+        manifest.save(&path).unwrap();
+        let loaded = Manifest::load(&path).unwrap();
+        assert_eq!(loaded.fingerprinted("css/app.css"), Some(app.as_str()));
+        assert_eq!(loaded.original(&app), Some("css/app.css"));
+    }
+
+    #[test]
+    fn test_asset_path() {
+        let mut manifest = Manifest::new();
+        manifest.add("css/app.css", b"body {}");
+        let app = format!("/static/{}", manifest.fingerprinted("css/app.css").unwrap());
+        set_manifest(manifest);
+
+        assert_eq!(asset_path("/css/app.css"), app);
+        assert_eq!(asset_path("logo.png"), "/static/logo.png");
+
+        let template = Template::from_str(r#"<%= asset_path("css/app.css") %>"#).unwrap();
+        assert_eq!(template.render_default().unwrap(), app);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        assert!(fingerprint("app.min.js", b"").starts_with("app.min-"));
+        assert!(fingerprint("LICENSE", b"")
+            .strip_prefix("LICENSE-")
+            .is_some_and(|hash| hash.len() == 16));
+        assert!(fingerprint(".env", b"").starts_with(".env-"));
+    }
+}
@@ -0,0 +1,237 @@
+//! Field access rules.
+//!
+//! Fields of a model can be hidden from users, or protected from changes, based on their roles.
+//! Rules are added to fields with the `readable` and `writable` attributes of the `Model` derive:
+//!
+//! ```
+//! use rwf::prelude::*;
+//! use rwf::auth::{fields, Roles};
+//!
+//! #[derive(Clone, macros::Model, Serialize, Deserialize)]
+//! struct Employee {
+//!     id: Option<i64>,
+//!     name: String,
+//!     #[readable(roles("admin", "hr"))]
+//!     #[writable(roles("admin"))]
+//!     salary: i64,
+//!     #[writable(none)]
+//!     created_at: OffsetDateTime,
+//! }
+//!
+//! struct User {
+//!     role: String,
+//! }
+//!
+//! impl Roles for User {
+//!     fn has_role(&self, role: &str) -> bool {
+//!         self.role == role
+//!     }
+//! }
+//!
+//! let hr = User { role: "hr".into() };
+//! assert!(fields::readable::<Employee>("salary", Some(&hr)));
+//! assert!(!fields::writable::<Employee>("salary", Some(&hr)));
+//! assert!(!fields::readable::<Employee>("salary", None));
+//! assert!(fields::writable::<Employee>("name", None));
+//! ```
+//!
+//! A rule is one of `any` (everyone), `user` (logged in users), `none` (nobody), or `roles(...)`
+//! (users with any of these roles, see [`Roles`]). Fields without rules can be read and written by everyone.
+//!
+//! [`ModelController`](crate::controller::ModelController) checks the rules automatically. Other controllers can
+//! use [`to_json`] to serialize records, and [`permit_json`] or [`permit_form`] to remove fields
+//! the user can't change from requests.
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::Roles;
+use crate::http::FormData;
+use crate::model::Model;
+
+/// Who can read or write a field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    /// Everyone.
+    Any,
+    /// Logged in users.
+    User,
+    /// Users with any of these roles.
+    Roles(&'static [&'static str]),
+    /// Nobody.
+    None,
+}
+
+impl Access {
+    /// The user is allowed.
+    pub fn allows(&self, user: Option<&dyn Roles>) -> bool {
+        match self {
+            Access::Any => true,
+            Access::User => user.is_some(),
+            Access::Roles(roles) => user
+                .map(|user| roles.iter().any(|role| user.has_role(role)))
+                .unwrap_or(false),
+            Access::None => false,
+        }
+    }
+}
+
+/// Access rules for a model field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FieldRule {
+    /// Name of the field.
+    pub field: &'static str,
+    /// Who can see the field.
+    pub read: Access,
+    /// Who can change the field.
+    pub write: Access,
+}
+
+fn rule<T: Model>(field: &str) -> Option<&'static FieldRule> {
+    T::field_rules().iter().find(|rule| rule.field == field)
+}
+
+/// The user can see the field.
+pub fn readable<T: Model>(field: &str, user: Option<&dyn Roles>) -> bool {
+    rule::<T>(field)
+        .map(|rule| rule.read.allows(user))
+        .unwrap_or(true)
+}
+
+/// The user can change the field.
+pub fn writable<T: Model>(field: &str, user: Option<&dyn Roles>) -> bool {
+    rule::<T>(field)
+        .map(|rule| rule.write.allows(user))
+        .unwrap_or(true)
+}
+
+/// Fields the user can't change.
+pub fn unwritable<T: Model>(user: Option<&dyn Roles>) -> Vec<&'static str> {
+    T::field_rules()
+        .iter()
+        .filter(|rule| !rule.write.allows(user))
+        .map(|rule| rule.field)
+        .collect()
+}
+
+/// Serialize the record to JSON, without the fields the user can't see.
+pub fn to_json<T: Model + Serialize>(
+    record: &T,
+    user: Option<&dyn Roles>,
+) -> Result<Value, serde_json::Error> {
+    let mut value = serde_json::to_value(record)?;
+
+    if let Value::Object(ref mut object) = value {
+        object.retain(|field, _| readable::<T>(field, user));
+    }
+
+    Ok(value)
+}
+
+/// Remove the fields the user can't change from a JSON request body.
+pub fn permit_json<T: Model>(body: &mut Map<String, Value>, user: Option<&dyn Roles>) {
+    for field in unwritable::<T>(user) {
+        body.remove(field);
+    }
+}
+
+/// Remove the fields the user can't change from a submitted form.
+pub fn permit_form<T: Model>(form: &mut FormData, user: Option<&dyn Roles>) {
+    for field in unwritable::<T>(user) {
+        form.remove(field);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{Error, FromRow, Value as ModelValue};
+    use serde_json::json;
+
+    struct User(&'static str);
+
+    impl Roles for User {
+        fn has_role(&self, role: &str) -> bool {
+            self.0 == role
+        }
+    }
+
+    #[derive(Clone, Serialize)]
+    struct Employee {
+        id: Option<i64>,
+        name: String,
+        salary: i64,
+        created_by: i64,
+    }
+
+    impl FromRow for Employee {
+        fn from_row(_row: tokio_postgres::Row) -> Result<Self, Error> {
+            unimplemented!()
+        }
+    }
+
+    impl Model for Employee {
+        fn table_name() -> &'static str {
+            "employees"
+        }
+
+        fn foreign_key() -> &'static str {
+            "employee_id"
+        }
+
+        fn column_names() -> &'static [&'static str] {
+            &["name", "salary", "created_by"]
+        }
+
+        fn values(&self) -> Vec<ModelValue> {
+            vec![]
+        }
+
+        fn id(&self) -> ModelValue {
+            ModelValue::Null
+        }
+
+        fn field_rules() -> &'static [FieldRule] {
+            &[
+                FieldRule {
+                    field: "salary",
+                    read: Access::Roles(&["admin", "hr"]),
+                    write: Access::Roles(&["admin"]),
+                },
+                FieldRule {
+                    field: "created_by",
+                    read: Access::User,
+                    write: Access::None,
+                },
+            ]
+        }
+    }
+
+    #[test]
+    fn test_field_rules() {
+        let employee = Employee {
+            id: Some(1),
+            name: "Alice".into(),
+            salary: 100,
+            created_by: 2,
+        };
+        let (admin, hr) = (User("admin"), User("hr"));
+
+        assert_eq!(
+            to_json(&employee, None).unwrap(),
+            json!({"id": 1, "name": "Alice"})
+        );
+        assert_eq!(
+            to_json(&employee, Some(&hr)).unwrap(),
+            json!({"id": 1, "name": "Alice", "salary": 100, "created_by": 2})
+        );
+
+        assert!(writable::<Employee>("name", None));
+        assert!(!writable::<Employee>("salary", Some(&hr)));
+        assert!(writable::<Employee>("salary", Some(&admin)));
+        assert_eq!(unwritable::<Employee>(Some(&admin)), vec!["created_by"]);
+
+        let mut body = json!({"name": "Bob", "salary": 1000, "created_by": 1});
+        permit_json::<Employee>(body.as_object_mut().unwrap(), Some(&hr));
+        assert_eq!(body, json!({"name": "Bob"}));
+    }
+}
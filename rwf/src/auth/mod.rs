@@ -0,0 +1,9 @@
+//! Helpers for authenticating users.
+//!
+//! Authentication handlers used by controllers are in [`crate::controller::auth`].
+pub mod fields;
+pub mod password;
+pub mod policy;
+
+pub use password::SecurePassword;
+pub use policy::{Authorize, Permissions, Policy, Roles};
@@ -0,0 +1,160 @@
+//! Password hashing.
+//!
+//! Passwords are hashed with Argon2id, using a random salt. Hashing is deliberately slow, so it runs on
+//! Tokio's blocking thread pool instead of the async runtime.
+//!
+//! Models storing a password implement [`SecurePassword`], or derive it with `macros::SecurePassword`,
+//! which requires a `password_digest: String` field:
+//!
+//! ```ignore
+//! #[derive(Clone, macros::Model, macros::SecurePassword)]
+//! struct User {
+//!     id: Option<i64>,
+//!     email: String,
+//!     password_digest: String,
+//! }
+//!
+//! let mut user = User::find_by("email", email).fetch(&mut conn).await?;
+//!
+//! if user.verify_password(password).await? {
+//!     // Logged in.
+//! }
+//!
+//! user.set_password("hunter3").await?;
+//! let user = user.save().fetch(&mut conn).await?;
+//! ```
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use async_trait::async_trait;
+use thiserror::Error;
+use tokio::task::spawn_blocking;
+
+use crate::model::Model;
+
+/// Password hashing error.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The digest isn't a valid Argon2 hash, or hashing failed.
+    #[error("password hash error: {0}")]
+    Hash(argon2::password_hash::Error),
+
+    /// The hashing task panicked or was cancelled.
+    #[error("password hash task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+}
+
+impl From<argon2::password_hash::Error> for Error {
+    fn from(error: argon2::password_hash::Error) -> Self {
+        Self::Hash(error)
+    }
+}
+
+fn argon2() -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, Params::default())
+}
+
+/// Hash the password. The digest includes the algorithm, its parameters and the salt,
+/// so it can be stored in a single column.
+pub async fn hash(password: &str) -> Result<String, Error> {
+    let password = password.to_string();
+
+    spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Ok(argon2()
+            .hash_password(password.as_bytes(), &salt)?
+            .to_string())
+    })
+    .await?
+}
+
+/// Check that the password matches the digest created by [`hash`].
+pub async fn verify(password: &str, digest: &str) -> Result<bool, Error> {
+    let password = password.to_string();
+    let digest = digest.to_string();
+
+    spawn_blocking(move || {
+        let digest = PasswordHash::new(&digest)?;
+        Ok(argon2()
+            .verify_password(password.as_bytes(), &digest)
+            .is_ok())
+    })
+    .await?
+}
+
+/// The digest was created with other parameters than the current ones, e.g. by an older
+/// version of Rwf, and the password should be hashed again the next time the user logs in.
+pub fn needs_rehash(digest: &str) -> bool {
+    let digest = match PasswordHash::new(digest) {
+        Ok(digest) => digest,
+        Err(_) => return true,
+    };
+
+    let params = match Params::try_from(&digest) {
+        Ok(params) => params,
+        Err(_) => return true,
+    };
+
+    let current = Params::default();
+
+    digest.algorithm != Algorithm::Argon2id.ident()
+        || digest.version != Some(Version::V0x13.into())
+        || params.m_cost() != current.m_cost()
+        || params.t_cost() != current.t_cost()
+        || params.p_cost() != current.p_cost()
+}
+
+/// Model with a hashed password.
+#[async_trait]
+pub trait SecurePassword: Model + Send + Sync {
+    /// The password's digest.
+    fn password_digest(&self) -> &str;
+
+    /// Set the password's digest.
+    fn set_password_digest(&mut self, digest: String);
+
+    /// Name of the column storing the digest.
+    fn password_digest_column() -> &'static str {
+        "password_digest"
+    }
+
+    /// Hash the password and set the digest. The record still needs to be saved.
+    async fn set_password(&mut self, password: &str) -> Result<(), Error> {
+        let digest = hash(password).await?;
+        self.set_password_digest(digest);
+        Ok(())
+    }
+
+    /// Check the password. Returns `false` if the digest isn't valid, e.g. it was never set.
+    async fn verify_password(&self, password: &str) -> Result<bool, Error> {
+        match verify(password, self.password_digest()).await {
+            Ok(valid) => Ok(valid),
+            Err(Error::Hash(_)) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hash_verify() {
+        let digest = hash("hunter2").await.unwrap();
+        assert!(digest.starts_with("$argon2id$v=19$"));
+        assert!(verify("hunter2", &digest).await.unwrap());
+        assert!(!verify("hunter3", &digest).await.unwrap());
+        assert!(verify("hunter2", "not_encrypted").await.is_err());
+
+        // Salted.
+        assert_ne!(digest, hash("hunter2").await.unwrap());
+
+        assert!(!needs_rehash(&digest));
+        assert!(needs_rehash("not_encrypted"));
+        assert!(needs_rehash(
+            "$argon2i$v=19$m=16,t=2,p=1$c2FsdHNhbHQ$L7W6D+oUXbHgZqMUWlROmw"
+        ));
+    }
+}
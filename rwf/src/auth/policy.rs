@@ -0,0 +1,299 @@
+//! Authorization policies.
+//!
+//! A policy decides what a user can do with a record, e.g. who can edit a post. Policies are
+//! implemented for each record type, and checked in controllers with the `authorize!` macro,
+//! which returns `403 - Forbidden` if the user isn't allowed.
+//!
+//! ```
+//! use rwf::prelude::*;
+//! use rwf::auth::policy::{Authorize, Policy};
+//!
+//! struct User {
+//!     id: i64,
+//!     admin: bool,
+//! }
+//!
+//! struct Post {
+//!     user_id: i64,
+//!     published: bool,
+//! }
+//!
+//! #[derive(Default)]
+//! struct PostPolicy;
+//!
+//! impl Policy<Post> for PostPolicy {
+//!     type User = User;
+//!
+//!     fn can_view(&self, user: Option<&User>, post: &Post) -> bool {
+//!         post.published || self.can_edit(user, post)
+//!     }
+//!
+//!     fn can_edit(&self, user: Option<&User>, post: &Post) -> bool {
+//!         user.map(|user| user.admin || user.id == post.user_id).unwrap_or(false)
+//!     }
+//! }
+//!
+//! impl Authorize for Post {
+//!     type Policy = PostPolicy;
+//! }
+//!
+//! # async fn handle(user: Option<User>, post: Post) -> Result<Response, Error> {
+//! authorize!(user.as_ref(), post, edit);
+//! # Ok(Response::new())
+//! # }
+//! ```
+//!
+//! Policies for simple role rules can be derived, see [`Roles`].
+use std::collections::HashMap;
+
+use crate::view::template::{Error as TemplateError, ToTemplateValue, Value};
+
+/// What users can do with records of type `T`. Actions are denied unless allowed.
+pub trait Policy<T>: Send + Sync {
+    /// The user model.
+    type User;
+
+    /// The user can see the record.
+    fn can_view(&self, _user: Option<&Self::User>, _record: &T) -> bool {
+        false
+    }
+
+    /// The user can create the record.
+    fn can_create(&self, _user: Option<&Self::User>, _record: &T) -> bool {
+        false
+    }
+
+    /// The user can change the record.
+    fn can_edit(&self, _user: Option<&Self::User>, _record: &T) -> bool {
+        false
+    }
+
+    /// The user can delete the record.
+    fn can_delete(&self, _user: Option<&Self::User>, _record: &T) -> bool {
+        false
+    }
+
+    /// The user can perform an action other than view, create, edit, or delete, e.g. `publish`.
+    fn can_custom(&self, _user: Option<&Self::User>, _record: &T, _action: &str) -> bool {
+        false
+    }
+
+    /// The user can perform the action.
+    fn can(&self, user: Option<&Self::User>, record: &T, action: &str) -> bool {
+        match action {
+            "view" => self.can_view(user, record),
+            "create" => self.can_create(user, record),
+            "edit" => self.can_edit(user, record),
+            "delete" => self.can_delete(user, record),
+            action => self.can_custom(user, record, action),
+        }
+    }
+}
+
+/// Record with an authorization policy.
+pub trait Authorize: Sized {
+    /// Policy deciding what users can do with the record.
+    type Policy: Policy<Self> + Default;
+}
+
+/// User with roles, e.g. `admin`. Used by derived policies.
+///
+/// ```
+/// use rwf::auth::policy::{Authorize, Roles};
+/// use rwf::macros::Policy;
+///
+/// struct User {
+///     role: String,
+/// }
+///
+/// impl Roles for User {
+///     fn has_role(&self, role: &str) -> bool {
+///         self.role == role
+///     }
+/// }
+///
+/// struct Post;
+///
+/// // "any" allows everyone, "user" allows logged in users,
+/// // anything else is a list of roles.
+/// #[derive(Default, Policy)]
+/// #[policy(record = Post, user = User, view = "any", create = "user", edit = "admin | editor", delete = "admin")]
+/// struct PostPolicy;
+///
+/// let editor = User { role: "editor".into() };
+/// assert!(rwf::auth::policy::can(None, &Post, "view"));
+/// assert!(rwf::auth::policy::can(&editor, &Post, "edit"));
+/// assert!(!rwf::auth::policy::can(&editor, &Post, "delete"));
+/// ```
+pub trait Roles {
+    /// The user has the role.
+    fn has_role(&self, role: &str) -> bool;
+}
+
+/// The user can perform the action on the record.
+pub fn can<'a, T: Authorize>(
+    user: impl Into<Option<&'a <T::Policy as Policy<T>>::User>>,
+    record: &T,
+    action: &str,
+) -> bool
+where
+    <T::Policy as Policy<T>>::User: 'a,
+{
+    T::Policy::default().can(user.into(), record, action)
+}
+
+/// What the user can do with the record, for hiding links and buttons in templates.
+///
+/// ```
+/// # use rwf::prelude::*;
+/// # use rwf::auth::policy::{Authorize, Permissions, Policy};
+/// # use rwf::view::Context;
+/// # struct User;
+/// # struct Post;
+/// # #[derive(Default)]
+/// # struct PostPolicy;
+/// # impl Policy<Post> for PostPolicy {
+/// #     type User = User;
+/// #     fn can_edit(&self, user: Option<&User>, _post: &Post) -> bool { user.is_some() }
+/// # }
+/// # impl Authorize for Post { type Policy = PostPolicy; }
+/// let template = Template::from_str(r#"<% if can(post, "edit") %><a href="/edit">Edit</a><% end %>"#).unwrap();
+/// let mut context = Context::new();
+/// context.set("post", Permissions::new(Some(&User), &Post)).unwrap();
+/// let html = template.render(&context).unwrap();
+/// assert_eq!(html, r#"<a href="/edit">Edit</a>"#);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Permissions {
+    actions: HashMap<String, bool>,
+}
+
+impl Permissions {
+    /// Check the view, create, edit, and delete actions.
+    pub fn new<'a, T: Authorize>(
+        user: impl Into<Option<&'a <T::Policy as Policy<T>>::User>>,
+        record: &T,
+    ) -> Self
+    where
+        <T::Policy as Policy<T>>::User: 'a,
+    {
+        Self::with_actions(user, record, &["view", "create", "edit", "delete"])
+    }
+
+    /// Check these actions.
+    pub fn with_actions<'a, T: Authorize>(
+        user: impl Into<Option<&'a <T::Policy as Policy<T>>::User>>,
+        record: &T,
+        actions: &[&str],
+    ) -> Self
+    where
+        <T::Policy as Policy<T>>::User: 'a,
+    {
+        let policy = T::Policy::default();
+        let user = user.into();
+
+        Self {
+            actions: actions
+                .iter()
+                .map(|action| (action.to_string(), policy.can(user, record, action)))
+                .collect(),
+        }
+    }
+
+    /// The action is allowed.
+    pub fn can(&self, action: &str) -> bool {
+        self.actions.get(action).copied().unwrap_or(false)
+    }
+}
+
+impl ToTemplateValue for Permissions {
+    fn to_template_value(&self) -> Result<Value, TemplateError> {
+        Ok(Value::Hash(
+            self.actions
+                .iter()
+                .map(|(action, allowed)| (action.clone(), Value::Boolean(*allowed)))
+                .collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct User {
+        id: i64,
+        role: &'static str,
+    }
+
+    impl Roles for User {
+        fn has_role(&self, role: &str) -> bool {
+            self.role == role
+        }
+    }
+
+    struct Post {
+        user_id: i64,
+    }
+
+    #[derive(Default)]
+    struct PostPolicy;
+
+    impl Policy<Post> for PostPolicy {
+        type User = User;
+
+        fn can_view(&self, _user: Option<&User>, _post: &Post) -> bool {
+            true
+        }
+
+        fn can_edit(&self, user: Option<&User>, post: &Post) -> bool {
+            user.map(|user| user.has_role("admin") || user.id == post.user_id)
+                .unwrap_or(false)
+        }
+
+        fn can_custom(&self, user: Option<&User>, _post: &Post, action: &str) -> bool {
+            match action {
+                "publish" => user.map(|user| user.has_role("admin")).unwrap_or(false),
+                _ => false,
+            }
+        }
+    }
+
+    impl Authorize for Post {
+        type Policy = PostPolicy;
+    }
+
+    #[test]
+    fn test_policy() {
+        let post = Post { user_id: 1 };
+        let author = User {
+            id: 1,
+            role: "user",
+        };
+        let admin = User {
+            id: 2,
+            role: "admin",
+        };
+        let other = User {
+            id: 3,
+            role: "user",
+        };
+
+        assert!(can(None, &post, "view"));
+        assert!(!can(None, &post, "edit"));
+        assert!(can(&author, &post, "edit"));
+        assert!(can(&admin, &post, "edit"));
+        assert!(!can(&other, &post, "edit"));
+        assert!(!can(&author, &post, "delete"));
+        assert!(can(&admin, &post, "publish"));
+        assert!(!can(&author, &post, "publish"));
+
+        let permissions = Permissions::new(&other, &post);
+        assert!(permissions.can("view"));
+        assert!(!permissions.can("edit"));
+        assert!(!permissions.can("publish"));
+
+        let permissions = Permissions::with_actions(&admin, &post, &["publish"]);
+        assert!(permissions.can("publish"));
+    }
+}
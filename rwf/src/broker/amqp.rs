@@ -0,0 +1,77 @@
+//! AMQP (e.g. RabbitMQ) producer.
+//!
+//! Requires the `amqp` feature. Messages are published to the configured exchange,
+//! using the message topic as the routing key.
+use async_trait::async_trait;
+use lapin::options::{BasicPublishOptions, ConfirmSelectOptions};
+use lapin::publisher_confirm::Confirmation;
+use lapin::types::{AMQPValue, FieldTable};
+use lapin::{BasicProperties, Channel, Connection, ConnectionProperties};
+
+use super::{Error, Message, Producer};
+use crate::config::BrokerConfig;
+
+/// Publishes messages to an AMQP exchange.
+pub struct AmqpProducer {
+    // Closing the connection closes the channel.
+    _connection: Connection,
+    channel: Channel,
+    exchange: String,
+}
+
+impl AmqpProducer {
+    /// Connect to the broker specified in the broker configuration.
+    pub async fn connect(config: &BrokerConfig) -> Result<Self, Error> {
+        let connection =
+            Connection::connect(&config.url(), ConnectionProperties::default()).await?;
+        let channel = connection.create_channel().await?;
+        channel
+            .confirm_select(ConfirmSelectOptions::default())
+            .await?;
+
+        Ok(Self {
+            _connection: connection,
+            channel,
+            exchange: config.exchange.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl Producer for AmqpProducer {
+    async fn send(&self, message: &Message) -> Result<(), Error> {
+        let mut headers = FieldTable::default();
+
+        for (name, value) in &message.headers {
+            headers.insert(
+                name.clone().into(),
+                AMQPValue::LongString(value.clone().into()),
+            );
+        }
+
+        if let Some(ref key) = message.key {
+            headers.insert("key".into(), AMQPValue::LongString(key.clone().into()));
+        }
+
+        let confirmation = self
+            .channel
+            .basic_publish(
+                &self.exchange,
+                &message.topic,
+                BasicPublishOptions::default(),
+                &message.payload,
+                BasicProperties::default().with_headers(headers),
+            )
+            .await?
+            .await?;
+
+        match confirmation {
+            Confirmation::Nack(_) => Err(Error::Delivery("message not acknowledged".into())),
+            _ => Ok(()),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "amqp"
+    }
+}
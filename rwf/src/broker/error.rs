@@ -0,0 +1,34 @@
+//! Errors returned by message broker producers.
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    /// No broker backend is configured.
+    #[error("message broker is not configured")]
+    NotConfigured,
+
+    /// The configured backend wasn't compiled in. Enable
+    /// the corresponding crate feature, e.g. `kafka` or `amqp`.
+    #[error("message broker backend \"{0}\" is not enabled, enable the \"{0}\" feature")]
+    BackendDisabled(&'static str),
+
+    /// The broker didn't acknowledge the message.
+    #[error("message broker error: {0}")]
+    Delivery(String),
+
+    /// Couldn't serialize the message payload.
+    #[error("message serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    /// The ORM returned an error.
+    #[error("outbox database error: {0}")]
+    DatabaseError(#[from] crate::model::Error),
+
+    #[cfg(feature = "kafka")]
+    #[error("kafka error: {0}")]
+    Kafka(#[from] rdkafka::error::KafkaError),
+
+    #[cfg(feature = "amqp")]
+    #[error("amqp error: {0}")]
+    Amqp(#[from] lapin::Error),
+}
@@ -0,0 +1,71 @@
+//! Kafka producer.
+//!
+//! Requires the `kafka` feature, which builds `librdkafka`.
+use async_trait::async_trait;
+use rdkafka::config::ClientConfig;
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use std::time::Duration;
+
+use super::{Error, Message, Producer};
+use crate::config::BrokerConfig;
+
+/// Sends messages to a Kafka cluster.
+pub struct KafkaProducer {
+    producer: FutureProducer,
+    timeout: Duration,
+}
+
+impl KafkaProducer {
+    /// Create a producer using the broker configuration.
+    /// The broker URL is a comma-separated list of bootstrap servers.
+    pub fn new(config: &BrokerConfig) -> Result<Self, Error> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", config.url())
+            .set("client.id", &config.client_id)
+            .set(
+                "message.timeout.ms",
+                config.timeout().whole_milliseconds().to_string(),
+            )
+            .create()?;
+
+        Ok(Self {
+            producer,
+            timeout: Duration::from_millis(config.timeout as u64),
+        })
+    }
+}
+
+#[async_trait]
+impl Producer for KafkaProducer {
+    async fn send(&self, message: &Message) -> Result<(), Error> {
+        let headers = message
+            .headers
+            .iter()
+            .fold(OwnedHeaders::new(), |headers, (key, value)| {
+                headers.insert(Header {
+                    key,
+                    value: Some(value),
+                })
+            });
+
+        let mut record = FutureRecord::to(&message.topic)
+            .payload(&message.payload)
+            .headers(headers);
+
+        if let Some(ref key) = message.key {
+            record = record.key(key);
+        }
+
+        self.producer
+            .send(record, self.timeout)
+            .await
+            .map_err(|(err, _)| Error::Delivery(err.to_string()))?;
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "kafka"
+    }
+}
@@ -0,0 +1,37 @@
+//! Producer that writes messages to the log.
+//!
+//! Useful in development and tests, when a real broker isn't available.
+use async_trait::async_trait;
+use tracing::info;
+
+use super::{Error, Message, Producer};
+use crate::colors::MaybeColorize;
+
+/// Logs messages instead of sending them to a broker.
+#[derive(Default, Debug, Clone)]
+pub struct LogProducer;
+
+impl LogProducer {
+    /// Create new log producer.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl Producer for LogProducer {
+    async fn send(&self, message: &Message) -> Result<(), Error> {
+        info!(
+            "{} {} ({} bytes)",
+            "broker".purple(),
+            message.topic.green(),
+            message.payload.len()
+        );
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "log"
+    }
+}
@@ -0,0 +1,171 @@
+//! Message broker integration.
+//!
+//! Publishes domain events to an external message broker, e.g. Kafka or RabbitMQ,
+//! using the [`Producer`] trait. The broker is configured in the `[broker]` section of `rwf.toml`
+//! and is shared by the whole application:
+//!
+//! ```toml
+//! [broker]
+//! backend = "kafka"
+//! url = "localhost:9092"
+//! ```
+//!
+//! Kafka and AMQP backends are behind the `kafka` and `amqp` crate features respectively.
+//! Events can be sent directly with [`producer`], or written to the transactional [`outbox`]
+//! and delivered by the [`outbox::Publisher`] once the transaction commits.
+use async_trait::async_trait;
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+use tracing::info;
+
+use crate::colors::MaybeColorize;
+use crate::config::{get_config, BrokerBackend};
+
+#[cfg(feature = "amqp")]
+pub mod amqp;
+pub mod error;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod log;
+pub mod outbox;
+
+pub use error::Error;
+pub use log::LogProducer;
+pub use outbox::{OutboxEvent, Publisher};
+
+static PRODUCER: OnceCell<Arc<Box<dyn Producer>>> = OnceCell::const_new();
+
+/// A message sent to the broker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    /// Kafka topic or AMQP routing key.
+    pub topic: String,
+    /// Partitioning key.
+    pub key: Option<String>,
+    /// Message body.
+    pub payload: Vec<u8>,
+    /// Message headers.
+    pub headers: Vec<(String, String)>,
+}
+
+impl Message {
+    /// Create a message with the given payload.
+    pub fn new(topic: impl ToString, payload: impl Into<Vec<u8>>) -> Self {
+        Self {
+            topic: topic.to_string(),
+            key: None,
+            payload: payload.into(),
+            headers: vec![],
+        }
+    }
+
+    /// Create a message by serializing the payload to JSON.
+    pub fn json(topic: impl ToString, payload: &impl Serialize) -> Result<Self, Error> {
+        Ok(Self::new(topic, serde_json::to_vec(payload)?)
+            .header("content-type", "application/json"))
+    }
+
+    /// Set the message key.
+    pub fn key(mut self, key: impl ToString) -> Self {
+        self.key = Some(key.to_string());
+        self
+    }
+
+    /// Add a header to the message.
+    pub fn header(mut self, name: impl ToString, value: impl ToString) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// Sends messages to a message broker.
+///
+/// Implement this trait to add support for a broker that isn't
+/// supported out of the box.
+#[async_trait]
+pub trait Producer: Send + Sync {
+    /// Send a message to the broker. Returns once the broker
+    /// acknowledged the message.
+    async fn send(&self, message: &Message) -> Result<(), Error>;
+
+    /// Name of the producer, used in logging.
+    fn name(&self) -> &'static str;
+
+    /// Convert the producer into a handle that can be shared between tasks.
+    fn handler(self) -> Arc<Box<dyn Producer>>
+    where
+        Self: Sized + 'static,
+    {
+        Arc::new(Box::new(self))
+    }
+}
+
+/// Get the producer configured in `[broker]`.
+///
+/// The connection to the broker is created on first use and shared
+/// by the entire application.
+pub async fn producer() -> Result<Arc<Box<dyn Producer>>, Error> {
+    PRODUCER
+        .get_or_try_init(|| async {
+            let producer = connect().await?;
+            info!(
+                "Message broker producer \"{}\" started",
+                producer.name().green()
+            );
+            Ok(producer)
+        })
+        .await
+        .cloned()
+}
+
+/// Send a message using the configured producer.
+pub async fn send(message: &Message) -> Result<(), Error> {
+    producer().await?.send(message).await
+}
+
+async fn connect() -> Result<Arc<Box<dyn Producer>>, Error> {
+    let config = &get_config().broker;
+
+    match config.backend {
+        BrokerBackend::None => Err(Error::NotConfigured),
+        BrokerBackend::Log => Ok(LogProducer::new().handler()),
+
+        #[cfg(feature = "kafka")]
+        BrokerBackend::Kafka => Ok(kafka::KafkaProducer::new(config)?.handler()),
+        #[cfg(not(feature = "kafka"))]
+        BrokerBackend::Kafka => Err(Error::BackendDisabled("kafka")),
+
+        #[cfg(feature = "amqp")]
+        BrokerBackend::Amqp => Ok(amqp::AmqpProducer::connect(config).await?.handler()),
+        #[cfg(not(feature = "amqp"))]
+        BrokerBackend::Amqp => Err(Error::BackendDisabled("amqp")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_message() {
+        let message = Message::json("users", &serde_json::json!({"id": 1}))
+            .unwrap()
+            .key("1");
+
+        assert_eq!(message.topic, "users");
+        assert_eq!(message.key, Some("1".into()));
+        assert_eq!(message.payload, br#"{"id":1}"#);
+        assert_eq!(
+            message.headers,
+            vec![("content-type".to_string(), "application/json".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_log_producer() {
+        let producer = LogProducer::new().handler();
+        producer.send(&Message::new("test", "hello")).await.unwrap();
+        assert_eq!(producer.name(), "log");
+    }
+}
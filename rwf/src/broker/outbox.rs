@@ -0,0 +1,288 @@
+//! Transactional outbox.
+//!
+//! Events are written to the `rwf_outbox` table in the same transaction as the data
+//! they describe, so they are never lost or sent for changes that were rolled back.
+//! The [`Publisher`] delivers them to the message broker in the background.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut transaction = start_transaction().await?;
+//!
+//! let user = User::create(&[("email", "test@test.com")]).fetch(&mut transaction).await?;
+//! OutboxEvent::new("users.created", serde_json::json!({"id": user.id}))
+//!     .key(user.id)
+//!     .save()
+//!     .execute(&mut transaction)
+//!     .await?;
+//!
+//! transaction.commit().await?;
+//! ```
+use super::{producer, Error, Message, Producer};
+
+use crate::colors::MaybeColorize;
+use crate::config::get_config;
+use crate::model::{start_transaction, FromRow, Model, Scope, ToValue, Value};
+
+use std::sync::Arc;
+use time::OffsetDateTime;
+use tokio::time::{sleep, Duration};
+use tracing::{error, info};
+
+/// Event waiting to be published to the message broker.
+#[derive(Clone, Debug)]
+pub struct OutboxEvent {
+    pub id: Option<i64>,
+    pub topic: String,
+    pub key: Option<String>,
+    pub payload: serde_json::Value,
+    pub created_at: OffsetDateTime,
+    pub published_at: Option<OffsetDateTime>,
+    pub attempts: i32,
+    pub error: Option<String>,
+}
+
+impl OutboxEvent {
+    /// Create new event. Call `save()` to write it to the outbox.
+    pub fn new(topic: impl ToString, payload: serde_json::Value) -> Self {
+        Self {
+            id: None,
+            topic: topic.to_string(),
+            key: None,
+            payload,
+            created_at: OffsetDateTime::now_utc(),
+            published_at: None,
+            attempts: 0,
+            error: None,
+        }
+    }
+
+    /// Set the event key.
+    pub fn key(mut self, key: impl ToString) -> Self {
+        self.key = Some(key.to_string());
+        self
+    }
+
+    /// Fetch events that haven't been published yet, oldest first.
+    ///
+    /// Locks the events from being fetched by other publishers.
+    pub fn pending(limit: i64) -> Scope<Self> {
+        Self::filter("published_at", Value::Null)
+            .order((Self::column("id"), "ASC"))
+            .take_many(limit)
+            .lock()
+            .skip_locked()
+    }
+
+    /// Convert the event to a broker message.
+    pub fn message(&self) -> Result<Message, Error> {
+        let mut message = Message::json(&self.topic, &self.payload)?;
+
+        if let Some(ref key) = self.key {
+            message = message.key(key);
+        }
+
+        if let Some(id) = self.id {
+            message = message.header("rwf-outbox-id", id);
+        }
+
+        Ok(message)
+    }
+}
+
+impl FromRow for OutboxEvent {
+    fn from_row(row: tokio_postgres::Row) -> Result<Self, crate::model::Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            topic: row.try_get("topic")?,
+            key: row.try_get("key")?,
+            payload: row.try_get("payload")?,
+            created_at: row.try_get("created_at")?,
+            published_at: row.try_get("published_at")?,
+            attempts: row.try_get("attempts")?,
+            error: row.try_get("error")?,
+        })
+    }
+}
+
+impl Model for OutboxEvent {
+    fn id(&self) -> Value {
+        self.id.to_value()
+    }
+
+    fn table_name() -> &'static str {
+        "rwf_outbox"
+    }
+
+    fn primary_key() -> &'static str {
+        "id"
+    }
+
+    fn foreign_key() -> &'static str {
+        "rwf_outbox_id"
+    }
+
+    fn column_names() -> &'static [&'static str] {
+        &[
+            "topic",
+            "key",
+            "payload",
+            "created_at",
+            "published_at",
+            "attempts",
+            "error",
+        ]
+    }
+
+    fn values(&self) -> Vec<Value> {
+        vec![
+            self.topic.to_value(),
+            self.key.to_value(),
+            self.payload.to_value(),
+            self.created_at.to_value(),
+            self.published_at.to_value(),
+            self.attempts.to_value(),
+            self.error.to_value(),
+        ]
+    }
+}
+
+/// Delivers outbox events to the message broker.
+#[derive(Clone)]
+pub struct Publisher {
+    producer: Option<Arc<Box<dyn Producer>>>,
+    batch_size: i64,
+}
+
+impl Default for Publisher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Publisher {
+    /// Create a publisher using the producer configured in `[broker]`.
+    pub fn new() -> Self {
+        Self {
+            producer: None,
+            batch_size: get_config().broker.outbox_batch_size,
+        }
+    }
+
+    /// Use this producer instead of the configured one.
+    pub fn producer(mut self, producer: Arc<Box<dyn Producer>>) -> Self {
+        self.producer = Some(producer);
+        self
+    }
+
+    /// How many events to publish in one transaction.
+    pub fn batch_size(mut self, batch_size: i64) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Start the publisher in an async task.
+    ///
+    /// This returns immediately. Callers can drop the variable, the publisher
+    /// is running in a separate Tokio task.
+    pub async fn start(self) -> Result<Self, Error> {
+        let publisher = match self.producer {
+            Some(_) => self,
+            None => self.clone().producer(producer().await?),
+        };
+
+        let task = publisher.clone();
+        tokio::spawn(async move {
+            task.run().await;
+        });
+
+        Ok(publisher)
+    }
+
+    /// Run the publisher. Blocks forever. Use [`Self::start`] instead to start the publisher.
+    pub async fn run(&self) {
+        info!("Outbox publisher started");
+
+        loop {
+            match self.publish().await {
+                // Drained the outbox, wait for more events.
+                Ok(published) if published < self.batch_size as usize => {
+                    sleep(Duration::from_millis(1000)).await;
+                }
+                Ok(_) => (),
+                Err(err) => {
+                    error!("outbox publisher error, restarting: {:?}", err);
+                    sleep(Duration::from_millis(1000)).await;
+                }
+            }
+        }
+    }
+
+    /// Publish one batch of pending events. Returns the number
+    /// of events published.
+    pub async fn publish(&self) -> Result<usize, Error> {
+        let producer = match self.producer {
+            Some(ref producer) => producer.clone(),
+            None => producer().await?,
+        };
+
+        let mut transaction = start_transaction().await?;
+        let events = OutboxEvent::pending(self.batch_size)
+            .fetch_all(&mut transaction)
+            .await?;
+        let mut published = 0;
+
+        for mut event in events {
+            let result = match event.message() {
+                Ok(message) => producer.send(&message).await,
+                Err(err) => Err(err),
+            };
+
+            event.attempts += 1;
+
+            match result {
+                Ok(()) => {
+                    info!("event {} published", event.topic.green());
+                    event.published_at = Some(OffsetDateTime::now_utc());
+                    event.error = None;
+                    published += 1;
+                }
+
+                Err(err) => {
+                    error!("event {} error: {}", event.topic.green(), err);
+                    event.error = Some(err.to_string());
+                    event.save().execute(&mut transaction).await?;
+
+                    // Preserve ordering: don't publish newer events
+                    // until this one goes through.
+                    break;
+                }
+            }
+
+            event.save().execute(&mut transaction).await?;
+        }
+
+        transaction.commit().await?;
+
+        Ok(published)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_event_message() {
+        let mut event = OutboxEvent::new("orders.paid", serde_json::json!({"order_id": 5})).key(5);
+        event.id = Some(1);
+
+        let message = event.message().unwrap();
+        assert_eq!(message.topic, "orders.paid");
+        assert_eq!(message.key, Some("5".into()));
+        assert_eq!(message.payload, br#"{"order_id":5}"#);
+        assert!(message
+            .headers
+            .contains(&("rwf-outbox-id".to_string(), "1".to_string())));
+    }
+}
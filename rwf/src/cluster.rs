@@ -0,0 +1,125 @@
+//! Running multiple instances of the app behind a load balancer.
+//!
+//! Instances don't need sticky sessions as long as they don't keep state in memory: sessions are stored
+//! in encrypted cookies and background jobs are queued in Postgres, but WebSocket messages and rate limiter counters
+//! are kept in memory unless a shared backend is configured.
+//!
+//! With `cluster = true` in the `[general]` section of `rwf.toml`, the server checks that all stateful
+//! subsystems use a shared backend when it starts, and refuses to start otherwise.
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use thiserror::Error;
+use tracing::info;
+
+use crate::colors::MaybeColorize;
+use crate::comms::backplane;
+use crate::config::get_config;
+use crate::controller::session_store;
+
+static SUBSYSTEMS: Lazy<Mutex<Vec<Subsystem>>> = Lazy::new(|| Mutex::new(vec![]));
+
+/// Error returned when the app can't run in cluster mode.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// These subsystems keep their state in memory.
+    #[error(
+        "cluster mode requires shared backends, but these subsystems keep state in memory: {0}"
+    )]
+    LocalState(String),
+}
+
+/// Subsystem keeping state, e.g. WebSocket connections or rate limiter counters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subsystem {
+    /// Name of the subsystem.
+    pub name: String,
+    /// Where the state is kept, e.g. `memory` or `postgres`.
+    pub backend: String,
+    /// The state is shared between instances.
+    pub shared: bool,
+}
+
+/// Register a subsystem keeping state, so it's checked in cluster mode.
+pub fn register(name: impl ToString, backend: impl ToString, shared: bool) {
+    let subsystem = Subsystem {
+        name: name.to_string(),
+        backend: backend.to_string(),
+        shared,
+    };
+
+    let mut subsystems = SUBSYSTEMS.lock();
+    if !subsystems.contains(&subsystem) {
+        subsystems.push(subsystem);
+    }
+}
+
+/// All subsystems keeping state.
+pub fn subsystems() -> Vec<Subsystem> {
+    let comms = backplane::backplane();
+    let sessions = session_store::store();
+    let mut subsystems = vec![
+        Subsystem {
+            name: "comms".into(),
+            backend: comms.name().into(),
+            shared: comms.shared(),
+        },
+        Subsystem {
+            name: "sessions".into(),
+            backend: sessions.name().into(),
+            shared: sessions.shared(),
+        },
+    ];
+
+    subsystems.extend(SUBSYSTEMS.lock().iter().cloned());
+    subsystems
+}
+
+/// Check that all subsystems share their state with other instances, if cluster mode is enabled.
+pub fn verify() -> Result<(), Error> {
+    if !get_config().general.cluster {
+        return Ok(());
+    }
+
+    let subsystems = subsystems();
+    let local = subsystems
+        .iter()
+        .filter(|subsystem| !subsystem.shared)
+        .map(|subsystem| format!("{} ({})", subsystem.name, subsystem.backend))
+        .collect::<Vec<_>>();
+
+    if !local.is_empty() {
+        return Err(Error::LocalState(local.join(", ")));
+    }
+
+    for subsystem in subsystems {
+        info!(
+            "Cluster mode: {} using {}",
+            subsystem.name.green(),
+            subsystem.backend.purple()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_register() {
+        register("test limiter", "postgres", true);
+        register("test limiter", "postgres", true);
+
+        let subsystems = subsystems();
+        assert_eq!(subsystems[0].name, "comms");
+        assert!(!subsystems[0].shared);
+
+        let limiter = subsystems
+            .iter()
+            .filter(|subsystem| subsystem.name == "test limiter")
+            .collect::<Vec<_>>();
+        assert_eq!(limiter.len(), 1);
+        assert!(limiter[0].shared);
+    }
+}
@@ -0,0 +1,336 @@
+//! Delivering messages to clients connected to other instances of the app.
+//!
+//! Each instance keeps track of the WebSocket connections it serves. When a message is sent
+//! with [`super::Comms`], it's delivered to local connections and published to the backplane,
+//! which passes it to all other instances, so they can deliver it to their own connections.
+//! This way, clients can connect to any instance and load balancers don't need sticky sessions.
+//!
+//! By default, the backplane is local: messages only reach clients connected to this instance.
+//! In cluster mode, messages are passed through Postgres with `LISTEN`/`NOTIFY`. The backplane is configured
+//! in the `[general]` section of `rwf.toml`:
+//!
+//! ```toml
+//! [general]
+//! backplane = "postgres"
+//! ```
+//!
+//! or set in code with [`set_backplane`], e.g. to use Redis.
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::future::poll_fn;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::time::sleep;
+use tokio_postgres::{tls::NoTls, AsyncMessage};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use super::Error;
+use crate::config::{get_config, BackplaneBackend};
+use crate::controller::auth::SessionId;
+use crate::http::websocket::Message;
+use crate::model::Pool;
+
+static BACKPLANE: Lazy<RwLock<Arc<dyn Backplane>>> = Lazy::new(|| {
+    let backplane: Arc<dyn Backplane> = match get_config().general.backplane() {
+        BackplaneBackend::Memory => Arc::new(LocalBackplane),
+        BackplaneBackend::Postgres => Arc::new(PostgresBackplane::new()),
+    };
+    RwLock::new(backplane)
+});
+
+static INSTANCE: Lazy<String> = Lazy::new(|| Uuid::new_v4().to_string());
+
+/// Who the message is for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Target {
+    /// All connections with this session.
+    Session(SessionId),
+    /// All connections, except the ones with this session, if any.
+    Everyone { except: Option<SessionId> },
+    /// All connections subscribed to the channel, except the ones with this session, if any.
+    Channel {
+        channel: String,
+        except: Option<SessionId>,
+    },
+}
+
+/// Message passed between instances.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    /// Instance which sent the message.
+    pub origin: String,
+    /// Who the message is for.
+    pub target: Target,
+    /// The message.
+    pub message: Message,
+}
+
+impl Envelope {
+    /// Create an envelope sent from this instance.
+    pub fn new(target: Target, message: Message) -> Self {
+        Self {
+            origin: instance_id().to_string(),
+            target,
+            message,
+        }
+    }
+
+    /// The message was sent by this instance, so it was delivered already.
+    pub fn local(&self) -> bool {
+        self.origin == instance_id()
+    }
+}
+
+/// Passes messages between instances of the app, e.g. over Postgres `LISTEN`/`NOTIFY` or Redis pub/sub.
+///
+/// Implementations publish envelopes sent by this instance, and call [`super::deliver`]
+/// with envelopes received from other instances.
+pub trait Backplane: Send + Sync {
+    /// Send the envelope to all other instances. This shouldn't block:
+    /// queue the envelope and publish it in the background instead.
+    fn publish(&self, envelope: Envelope) -> Result<(), Error>;
+
+    /// Messages reach other instances. Required in cluster mode.
+    fn shared(&self) -> bool {
+        true
+    }
+
+    /// Name of the backend, used in logs.
+    fn name(&self) -> &'static str;
+
+    /// Start receiving messages from other instances. Called when the server starts.
+    fn start(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Messages only reach clients connected to this instance.
+pub struct LocalBackplane;
+
+impl Backplane for LocalBackplane {
+    fn publish(&self, _envelope: Envelope) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn shared(&self) -> bool {
+        false
+    }
+
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+}
+
+/// Postgres channel the messages are sent to.
+const CHANNEL: &str = "rwf_comms";
+
+/// Largest notification payload accepted by Postgres, in bytes.
+const MAX_PAYLOAD: usize = 8000;
+
+/// Passes messages to other instances with Postgres `LISTEN`/`NOTIFY`, using the app's database.
+///
+/// Messages are limited to about 8 KB, including the envelope. Larger messages, e.g. long Turbo Streams,
+/// are only delivered to clients connected to this instance.
+pub struct PostgresBackplane {
+    sender: UnboundedSender<String>,
+    receiver: Mutex<Option<UnboundedReceiver<String>>>,
+}
+
+impl PostgresBackplane {
+    /// Create the backplane. It connects to the database when the server starts,
+    /// or when the first message is published.
+    pub fn new() -> Self {
+        let (sender, receiver) = unbounded_channel();
+
+        Self {
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+        }
+    }
+
+    /// Start publishing and listening for messages in the background, if it's not done already.
+    fn spawn(&self) -> Result<(), Error> {
+        let handle = Handle::try_current()
+            .map_err(|_| Error::Backplane("postgres backplane requires a tokio runtime".into()))?;
+
+        if let Some(receiver) = self.receiver.lock().take() {
+            handle.spawn(notify(receiver));
+            handle.spawn(listen());
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for PostgresBackplane {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backplane for PostgresBackplane {
+    fn publish(&self, envelope: Envelope) -> Result<(), Error> {
+        let payload = serde_json::to_string(&envelope)?;
+
+        if payload.len() > MAX_PAYLOAD {
+            return Err(Error::Backplane(format!(
+                "message is {} bytes, notifications are limited to {} bytes",
+                payload.len(),
+                MAX_PAYLOAD
+            )));
+        }
+
+        self.spawn()?;
+        self.sender
+            .send(payload)
+            .map_err(|_| Error::Backplane("postgres backplane stopped".into()))
+    }
+
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn start(&self) -> Result<(), Error> {
+        self.spawn()
+    }
+}
+
+/// Send queued messages to other instances.
+async fn notify(mut receiver: UnboundedReceiver<String>) {
+    while let Some(payload) = receiver.recv().await {
+        let result = async {
+            let conn = Pool::connection().await?;
+            conn.client()
+                .execute("SELECT pg_notify($1, $2)", &[&CHANNEL, &payload])
+                .await?;
+            Ok::<_, crate::model::Error>(())
+        }
+        .await;
+
+        if let Err(err) = result {
+            error!("postgres backplane error: {}", err);
+        }
+    }
+}
+
+/// Deliver messages sent by other instances, reconnecting if the connection is lost.
+async fn listen() {
+    loop {
+        if let Err(err) = listen_connection().await {
+            error!("postgres backplane error: {}", err);
+        }
+
+        sleep(Duration::from_secs(1)).await;
+    }
+}
+
+async fn listen_connection() -> Result<(), tokio_postgres::Error> {
+    let database_url = get_config().database.database_url();
+    let (client, mut connection) = tokio_postgres::connect(&database_url, NoTls).await?;
+    let (sender, mut notifications) = unbounded_channel();
+
+    // Notifications are received while the connection is polled.
+    let connection = tokio::spawn(async move {
+        while let Some(message) = poll_fn(|cx| connection.poll_message(cx)).await {
+            if let AsyncMessage::Notification(notification) = message? {
+                let _ = sender.send(notification.payload().to_string());
+            }
+        }
+
+        Ok::<_, tokio_postgres::Error>(())
+    });
+
+    client.batch_execute(&format!("LISTEN {}", CHANNEL)).await?;
+    info!("postgres backplane listening on \"{}\"", CHANNEL);
+
+    while let Some(payload) = notifications.recv().await {
+        match serde_json::from_str::<Envelope>(&payload) {
+            Ok(envelope) => {
+                super::deliver(envelope);
+            }
+            Err(err) => warn!("postgres backplane received an invalid message: {}", err),
+        }
+    }
+
+    match connection.await {
+        Ok(result) => result,
+        Err(_) => Ok(()),
+    }
+}
+
+/// Unique ID of this instance.
+pub fn instance_id() -> &'static str {
+    &INSTANCE
+}
+
+/// The backplane used by this instance.
+pub fn backplane() -> Arc<dyn Backplane> {
+    BACKPLANE.read().clone()
+}
+
+/// Use this backplane to pass messages to other instances.
+pub fn set_backplane(backplane: impl Backplane + 'static) {
+    *BACKPLANE.write() = Arc::new(backplane);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::comms::Comms;
+    use tokio::time::timeout;
+
+    #[test]
+    fn test_payload_limit() {
+        let backplane = PostgresBackplane::new();
+        let envelope = Envelope::new(
+            Target::Everyone { except: None },
+            Message::Text("a".repeat(MAX_PAYLOAD)),
+        );
+        assert!(matches!(
+            backplane.publish(envelope),
+            Err(Error::Backplane(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_postgres_backplane() {
+        let backplane = PostgresBackplane::new();
+        backplane.start().unwrap();
+
+        let session = SessionId::Authenticated(9_001);
+        let mut receiver = Comms::receiver(&session);
+
+        // Sent by another instance.
+        let mut envelope = Envelope::new(
+            Target::Session(session.clone()),
+            Message::Text("hello".into()),
+        );
+        envelope.origin = "other".into();
+        let payload = serde_json::to_string(&envelope).unwrap();
+
+        let conn = Pool::connection().await.unwrap();
+
+        // The listener may not be connected yet.
+        let message = timeout(Duration::from_secs(5), async {
+            loop {
+                conn.client()
+                    .execute("SELECT pg_notify($1, $2)", &[&CHANNEL, &payload])
+                    .await
+                    .unwrap();
+
+                if let Ok(Ok(message)) = timeout(Duration::from_millis(100), receiver.recv()).await
+                {
+                    break message;
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        assert!(matches!(message, Message::Text(text) if text == "hello"));
+    }
+}
@@ -0,0 +1,152 @@
+//! Channels, e.g. chat rooms, which sessions subscribe to in order to receive messages sent to them.
+//!
+//! Subscriptions are kept while the session has an open WebSocket connection. When its last connection
+//! to this instance closes, the session is unsubscribed from all channels automatically.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let room = Comms::channel("room:42");
+//!
+//! // When the client connects.
+//! room.subscribe(&session_id);
+//!
+//! // Send a message to everyone in the room, except the sender.
+//! room.broadcast(&session_id).send("hello")?;
+//!
+//! // Who's in the room.
+//! let online = room.presence();
+//! ```
+use super::{get_comms, Broadcast, Error, IntoSessionId};
+use crate::controller::auth::SessionId;
+use crate::http::ToMessage;
+
+/// Handle for a channel, created with [`super::Comms::channel`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    name: String,
+}
+
+impl Channel {
+    /// Create a handle for the channel with this name, e.g. `"room:42"`.
+    pub fn new(name: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+        }
+    }
+
+    /// Channel name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Subscribe the session to the channel. Returns `false` if it was subscribed already.
+    pub fn subscribe(&self, session: impl IntoSessionId) -> bool {
+        get_comms().channel_subscribe(&session.into_session_id(), &self.name)
+    }
+
+    /// Unsubscribe the session from the channel. Returns `false` if it wasn't subscribed.
+    pub fn unsubscribe(&self, session: impl IntoSessionId) -> bool {
+        get_comms().channel_unsubscribe(&session.into_session_id(), &self.name)
+    }
+
+    /// Check that the session is subscribed to the channel.
+    pub fn subscribed(&self, session: impl IntoSessionId) -> bool {
+        get_comms().channel_subscribed(&session.into_session_id(), &self.name)
+    }
+
+    /// Sessions subscribed to the channel which are currently connected, e.g. users online in a chat room.
+    ///
+    /// Only sessions connected to this instance of the app are included.
+    pub fn presence(&self) -> Vec<SessionId> {
+        get_comms().channel_presence(&self.name)
+    }
+
+    /// Get a broadcast handle for sending messages to everyone subscribed to the channel
+    /// except the session sending the message.
+    pub fn broadcast(&self, session: impl IntoSessionId) -> Broadcast {
+        get_comms().channel_broadcast(&self.name, Some(&session.into_session_id()))
+    }
+
+    /// Send a message to everyone subscribed to the channel, including sessions connected to other instances of the app.
+    pub fn send(&self, message: impl ToMessage) -> Result<(), Error> {
+        get_comms()
+            .channel_broadcast(&self.name, None)
+            .send(message)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{deliver, Comms, Envelope, Target, WebsocketReceiver};
+    use super::*;
+    use crate::http::websocket::Message;
+
+    // Other tests may broadcast to everyone, so only look for messages sent by this one.
+    fn received(receiver: &mut WebsocketReceiver) -> Vec<String> {
+        let mut received = vec![];
+        while let Ok(message) = receiver.try_recv() {
+            if let Message::Text(text) = message {
+                received.push(text);
+            }
+        }
+        received
+    }
+
+    #[tokio::test]
+    async fn test_channel() {
+        let alice = SessionId::Authenticated(7_001);
+        let bob = SessionId::Authenticated(7_002);
+        let carol = SessionId::Authenticated(7_003);
+
+        let mut alice_receiver = Comms::receiver(&alice);
+        let mut bob_receiver = Comms::receiver(&bob);
+        let mut carol_receiver = Comms::receiver(&carol);
+
+        let room = Comms::channel("room:test");
+        assert!(room.subscribe(&alice));
+        assert!(!room.subscribe(&alice));
+        assert!(room.subscribe(&bob));
+        assert!(room.subscribed(&bob));
+        assert!(!room.subscribed(&carol));
+
+        let mut presence = room.presence();
+        presence.sort_by_key(|session| session.to_string());
+        assert_eq!(presence, vec![alice.clone(), bob.clone()]);
+
+        room.broadcast(&alice).send("hello").unwrap();
+        assert!(received(&mut bob_receiver).contains(&"hello".to_string()));
+        assert!(!received(&mut alice_receiver).contains(&"hello".to_string()));
+        assert!(!received(&mut carol_receiver).contains(&"hello".to_string()));
+
+        room.send("everyone").unwrap();
+        assert!(received(&mut alice_receiver).contains(&"everyone".to_string()));
+        assert!(received(&mut bob_receiver).contains(&"everyone".to_string()));
+        assert!(!received(&mut carol_receiver).contains(&"everyone".to_string()));
+
+        Comms::channel("room:other").send("elsewhere").unwrap();
+        assert!(!received(&mut alice_receiver).contains(&"elsewhere".to_string()));
+
+        // Sent by another instance.
+        let mut envelope = Envelope::new(
+            Target::Channel {
+                channel: "room:test".into(),
+                except: Some(bob.clone()),
+            },
+            Message::Text("remote".into()),
+        );
+        envelope.origin = "other".into();
+        assert_eq!(deliver(envelope), 1);
+        assert!(received(&mut alice_receiver).contains(&"remote".to_string()));
+        assert!(!received(&mut bob_receiver).contains(&"remote".to_string()));
+
+        // Disconnecting unsubscribes from all channels.
+        drop(bob_receiver);
+        assert!(!room.subscribed(&bob));
+        assert_eq!(room.presence(), vec![alice.clone()]);
+
+        assert!(room.unsubscribe(&alice));
+        assert!(!room.unsubscribe(&alice));
+        assert!(room.presence().is_empty());
+    }
+}
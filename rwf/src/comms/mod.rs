@@ -0,0 +1,650 @@
+//! Communication channels between clients and servers.
+//!
+//! Currenty used for sending messages to clients via WebSocket connections.
+//! Messages reach clients connected to other instances of the app through the [`backplane`].
+//! Sessions can subscribe to a [`channel`] to receive messages sent to a topic, e.g. a chat room.
+//! When the server shuts down, e.g. during a deploy, connections are closed and clients reconnect
+//! to the new version of the app.
+//!
+//! On the roadmap:
+//!
+//! * ORM-triggered events, e.g. callbacks
+pub mod backplane;
+pub mod channel;
+
+pub use backplane::{Backplane, Envelope, LocalBackplane, Target};
+pub use channel::Channel;
+
+use crate::colors::MaybeColorize;
+use crate::config::get_config;
+use crate::controller::auth::SessionId;
+use crate::events::{self, BroadcastSent, Event};
+use crate::http::websocket::Message;
+use crate::http::ToMessage;
+use crate::model::{Model, Value};
+use crate::view::TurboStream;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+use tokio::sync::broadcast::{channel, error::SendError, Receiver, Sender};
+use tokio::sync::watch;
+use tokio::time::sleep;
+use tracing::{debug, error, info};
+
+/// Error returned by comms.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// Error sending message through Tokio channel.
+    #[error("{0}")]
+    SendError(#[from] SendError<Message>),
+
+    /// Error publishing the message to other instances.
+    #[error("backplane error: {0}")]
+    Backplane(String),
+
+    /// Error encoding the message.
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+static MESSAGES: Lazy<Messages> = Lazy::new(|| Messages::new());
+static DEFAULT_TOPIC: &str = "default";
+
+// Incremented every time all connections are asked to close.
+static DISCONNECT: Lazy<watch::Sender<usize>> = Lazy::new(|| watch::channel(0).0);
+
+fn get_comms() -> &'static Messages {
+    &MESSAGES
+}
+
+struct Websocket {
+    sender: Sender<Message>,
+    receiver: Receiver<Message>,
+    channels: HashSet<String>,
+}
+
+impl Clone for Websocket {
+    fn clone(&self) -> Self {
+        Websocket {
+            sender: self.sender.clone(),
+            receiver: self.receiver.resubscribe(),
+            channels: self.channels.clone(),
+        }
+    }
+}
+
+impl Websocket {
+    fn new() -> Self {
+        let (sender, receiver) = channel(1024);
+        Self {
+            sender,
+            receiver,
+            channels: HashSet::new(),
+        }
+    }
+
+    /// The session has at least one open connection, not counting the receiver kept by the entry.
+    fn connected(&self) -> bool {
+        self.sender.receiver_count() > 1
+    }
+
+    fn receiver(&self) -> Receiver<Message> {
+        self.receiver.resubscribe()
+    }
+
+    fn sender(&self) -> Sender<Message> {
+        self.sender.clone()
+    }
+}
+
+/// Global messages channel.
+pub struct Messages {
+    websocket: Arc<Mutex<HashMap<SessionId, Websocket>>>,
+}
+
+impl Messages {
+    /// Create new messages channel.
+    pub fn new() -> Self {
+        Self {
+            websocket: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn websocket_disconnect(&self, session_id: &SessionId) {
+        debug!("websocket session \"{:?}\" closed", session_id);
+        self.websocket.lock().remove(session_id);
+    }
+
+    /// Check that a session has an active WebSocket connection.
+    pub fn websocket_connected(&self, session_id: &SessionId) -> bool {
+        self.websocket.lock().get(session_id).is_some()
+    }
+
+    /// Get a websocket message receiver. All messages sent from clients will be sent to the receiver.
+    pub fn websocket_receiver(&self, session_id: &SessionId, _topic: &str) -> WebsocketReceiver {
+        let mut guard = self.websocket.lock();
+        let entry = guard
+            .entry(session_id.clone())
+            .or_insert_with(Websocket::new);
+
+        WebsocketReceiver {
+            receiver: Some(entry.receiver()),
+            sender: entry.sender(),
+            session_id: session_id.clone(),
+        }
+    }
+
+    /// Get a websocket message sender. This allows to send messages to all websocket connections
+    /// that this session has.
+    pub fn websocket_sender(&self, session_id: &SessionId, _topic: &str) -> WebsocketSender {
+        let mut guard = self.websocket.lock();
+        let entry = guard
+            .entry(session_id.clone())
+            .or_insert_with(Websocket::new);
+        WebsocketSender {
+            sender: entry.sender(),
+            session_id: session_id.clone(),
+        }
+    }
+
+    /// Get a websocket message sender that will send messages to all _other_ sessions.
+    pub fn websocket_broadcast(&self, session_id: &SessionId, _topic: &str) -> Broadcast {
+        let guard = self.websocket.lock();
+        let entries = guard
+            .iter()
+            .filter(|(id, _)| *session_id != **id)
+            .map(|(_, websocket)| websocket.clone())
+            .collect::<Vec<_>>();
+
+        Broadcast {
+            everyone: entries,
+            target: Target::Everyone {
+                except: Some(session_id.clone()),
+            },
+        }
+    }
+
+    /// Get a websocket message sender that will send messages to _everyone_ connected.
+    pub fn websocket_notify(&self, _topic: &str) -> Broadcast {
+        let guard = self.websocket.lock();
+        let entries = guard
+            .iter()
+            .map(|(_, websocket)| websocket.clone())
+            .collect::<Vec<_>>();
+
+        Broadcast {
+            everyone: entries,
+            target: Target::Everyone { except: None },
+        }
+    }
+
+    /// Subscribe the session to the channel. Returns `false` if it was subscribed already.
+    pub fn channel_subscribe(&self, session_id: &SessionId, channel: &str) -> bool {
+        let mut guard = self.websocket.lock();
+        let entry = guard
+            .entry(session_id.clone())
+            .or_insert_with(Websocket::new);
+        entry.channels.insert(channel.to_string())
+    }
+
+    /// Unsubscribe the session from the channel. Returns `false` if it wasn't subscribed.
+    pub fn channel_unsubscribe(&self, session_id: &SessionId, channel: &str) -> bool {
+        self.websocket
+            .lock()
+            .get_mut(session_id)
+            .map(|websocket| websocket.channels.remove(channel))
+            .unwrap_or(false)
+    }
+
+    /// Check that the session is subscribed to the channel.
+    pub fn channel_subscribed(&self, session_id: &SessionId, channel: &str) -> bool {
+        self.websocket
+            .lock()
+            .get(session_id)
+            .map(|websocket| websocket.channels.contains(channel))
+            .unwrap_or(false)
+    }
+
+    /// Sessions subscribed to the channel with an open connection to this instance.
+    pub fn channel_presence(&self, channel: &str) -> Vec<SessionId> {
+        self.websocket
+            .lock()
+            .iter()
+            .filter(|(_, websocket)| websocket.channels.contains(channel) && websocket.connected())
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+
+    /// Get a websocket message sender that will send messages to sessions subscribed to the channel,
+    /// except the given session, if any.
+    pub fn channel_broadcast(&self, channel: &str, except: Option<&SessionId>) -> Broadcast {
+        let guard = self.websocket.lock();
+        let entries = guard
+            .iter()
+            .filter(|(id, websocket)| websocket.channels.contains(channel) && except != Some(*id))
+            .map(|(_, websocket)| websocket.clone())
+            .collect::<Vec<_>>();
+
+        Broadcast {
+            everyone: entries,
+            target: Target::Channel {
+                channel: channel.to_string(),
+                except: except.cloned(),
+            },
+        }
+    }
+
+    /// Number of open connections served by this instance.
+    pub fn connections(&self) -> usize {
+        self.websocket
+            .lock()
+            .values()
+            // Don't count the receiver kept by the entry.
+            .map(|websocket| websocket.sender.receiver_count() - 1)
+            .sum()
+    }
+
+    /// Deliver a message sent by another instance to connections served by this one.
+    /// Returns the number of connections the message was sent to.
+    fn deliver(&self, target: &Target, message: Message) -> usize {
+        let guard = self.websocket.lock();
+
+        guard
+            .iter()
+            .filter(|(id, websocket)| match target {
+                Target::Session(session_id) => session_id == *id,
+                Target::Everyone { except } => except.as_ref() != Some(*id),
+                Target::Channel { channel, except } => {
+                    websocket.channels.contains(channel) && except.as_ref() != Some(*id)
+                }
+            })
+            // Only count live connections, not the receiver kept by the entry.
+            .map(|(_, websocket)| {
+                websocket
+                    .sender
+                    .send(message.clone())
+                    .map(|receivers| receivers - 1)
+                    .unwrap_or(0)
+            })
+            .sum()
+    }
+}
+
+/// Publish the message to other instances of the app. Local connections already got it.
+fn publish(target: Target, message: Message) {
+    events::emit(Event::BroadcastSent(BroadcastSent {
+        target: &target,
+        message: &message,
+    }));
+
+    let backplane = backplane::backplane();
+
+    if backplane.shared() {
+        if let Err(err) = backplane.publish(Envelope::new(target, message)) {
+            error!("{} backplane error: {}", backplane.name(), err);
+        }
+    }
+}
+
+/// Deliver a message received from another instance to clients connected to this one.
+/// Called by [`Backplane`] implementations. Messages sent by this instance are ignored,
+/// since they were delivered already.
+pub fn deliver(envelope: Envelope) -> usize {
+    if envelope.local() {
+        return 0;
+    }
+
+    get_comms().deliver(&envelope.target, envelope.message)
+}
+
+/// Notified when all connections served by this instance should be closed.
+pub(crate) fn disconnect_receiver() -> watch::Receiver<usize> {
+    DISCONNECT.subscribe()
+}
+
+/// Close all connections when the server shuts down, asking clients to reload the page first
+/// if configured, and wait a little for the connections to send their close frames.
+pub(crate) async fn shutdown() {
+    let config = &get_config().websocket;
+
+    if Comms::connections() == 0 {
+        return;
+    }
+
+    if config.reload_on_shutdown {
+        get_comms().deliver(&Target::Everyone { except: None }, reload_message());
+    }
+
+    let closing = Comms::disconnect_all();
+    info!("Closing {} {} connection(s)", closing, "websocket".purple());
+
+    let started = Instant::now();
+    let timeout = config.ping_timeout().unsigned_abs();
+
+    while Comms::connections() > 0 && started.elapsed() < timeout {
+        sleep(Duration::from_millis(10)).await;
+    }
+}
+
+fn reload_message() -> Message {
+    TurboStream::new("").action("reload-page").to_message()
+}
+
+/// WebSocket message sender.
+#[derive(Debug)]
+pub struct WebsocketSender {
+    sender: Sender<Message>,
+    session_id: SessionId,
+}
+
+impl WebsocketSender {
+    /// Send a message via WebSocket connection, including connections to other instances of the app.
+    pub fn send(&self, message: impl ToMessage) -> Result<usize, Error> {
+        let message = message.to_message();
+        let sent = self.sender.send(message.clone())?;
+        publish(Target::Session(self.session_id.clone()), message);
+
+        Ok(sent)
+    }
+}
+
+impl std::ops::Deref for WebsocketSender {
+    type Target = Sender<Message>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.sender
+    }
+}
+
+/// Receiver for WebSocket messages.
+///
+/// Once this receiver is created, all subsequent messages will be sent to this
+/// receiver as well as all others.
+#[derive(Debug)]
+pub struct WebsocketReceiver {
+    receiver: Option<Receiver<Message>>,
+    sender: Sender<Message>,
+    session_id: SessionId,
+}
+
+impl WebsocketReceiver {
+    /// Get the session ID for this WebSocket receiver.
+    pub fn session_id(&self) -> &SessionId {
+        &self.session_id
+    }
+}
+
+impl std::ops::Deref for WebsocketReceiver {
+    type Target = Receiver<Message>;
+
+    fn deref(&self) -> &Self::Target {
+        self.receiver.as_ref().unwrap()
+    }
+}
+
+impl std::ops::DerefMut for WebsocketReceiver {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.receiver.as_mut().unwrap()
+    }
+}
+
+impl Drop for WebsocketReceiver {
+    fn drop(&mut self) {
+        drop(self.receiver.take());
+        if self.sender.receiver_count() == 1 {
+            get_comms().websocket_disconnect(&self.session_id);
+        }
+    }
+}
+
+/// Send messages to every single connected
+/// WebSocket session, or to every session subscribed to a channel.
+pub struct Broadcast {
+    everyone: Vec<Websocket>,
+    target: Target,
+}
+
+impl Broadcast {
+    /// Send a message to all connected sessions, including sessions connected to other instances of the app.
+    pub fn send(&self, message: impl ToMessage) -> Result<(), Error> {
+        for socket in &self.everyone {
+            socket.sender.send(message.clone().to_message())?;
+        }
+
+        publish(self.target.clone(), message.to_message());
+
+        Ok(())
+    }
+}
+
+/// Convert an object into a session.
+///
+/// If a model is passed in, the `id` field is used.
+pub trait IntoSessionId {
+    /// Convert a struct to a session identifier.
+    fn into_session_id(self) -> SessionId;
+}
+
+impl IntoSessionId for &SessionId {
+    fn into_session_id(self) -> SessionId {
+        self.clone()
+    }
+}
+
+impl IntoSessionId for SessionId {
+    fn into_session_id(self) -> SessionId {
+        self
+    }
+}
+
+impl IntoSessionId for &i64 {
+    fn into_session_id(self) -> SessionId {
+        SessionId::Authenticated(*self)
+    }
+}
+
+impl IntoSessionId for i64 {
+    fn into_session_id(self) -> SessionId {
+        SessionId::Authenticated(self)
+    }
+}
+
+impl<T: Model> IntoSessionId for &T {
+    fn into_session_id(self) -> SessionId {
+        match self.id() {
+            Value::Optional(user_id) => match *user_id {
+                Some(Value::Integer(user_id)) => SessionId::Authenticated(user_id),
+                _ => panic!("session id cannot be extrated"),
+            },
+            _ => panic!("session id cannot be extracted"),
+        }
+    }
+}
+
+/// App-wide communications using WebSockets.
+pub struct Comms;
+
+impl Comms {
+    /// Get a handle for a WebSocket connection for a session.
+    ///
+    /// Allows sending WebSocket messages to all connections with this session.
+    pub fn websocket(session: impl IntoSessionId) -> WebsocketSender {
+        let session_id = session.into_session_id();
+        get_comms().websocket_sender(&session_id, DEFAULT_TOPIC)
+    }
+
+    /// Get a handle for a WebSocket connection _receiver_ for a session.
+    ///
+    /// Allows listening for WebSocket messages sent by clients (browsers)
+    /// connected with this session.
+    pub fn receiver(session_id: impl IntoSessionId) -> WebsocketReceiver {
+        let session_id = session_id.into_session_id();
+        get_comms().websocket_receiver(&session_id, DEFAULT_TOPIC)
+    }
+
+    /// Get a broadcast handle for a WebSocket message to everyone else except
+    /// the session sending this message.
+    pub fn broadcast(session_id: impl IntoSessionId) -> Broadcast {
+        let session_id = session_id.into_session_id();
+        get_comms().websocket_broadcast(&session_id, DEFAULT_TOPIC)
+    }
+
+    /// Used for dev server notifications (sent to every connected session).
+    pub fn notify() -> Broadcast {
+        get_comms().websocket_notify(DEFAULT_TOPIC)
+    }
+
+    /// Get a handle for a channel, e.g. a chat room. Sessions subscribed to the channel receive
+    /// all messages sent to it, and are unsubscribed automatically when they disconnect.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rwf::prelude::*;
+    /// # use rwf::controller::auth::SessionId;
+    /// let session_id = SessionId::Authenticated(42);
+    /// let room = Comms::channel("room:42");
+    ///
+    /// room.subscribe(&session_id);
+    /// room.broadcast(&session_id).send("hello")?;
+    /// # Ok::<(), rwf::comms::Error>(())
+    /// ```
+    pub fn channel(name: impl ToString) -> Channel {
+        Channel::new(name)
+    }
+
+    /// Ask everyone connected to reload the page, e.g. after a deploy changed the app's assets.
+    /// Requires the Turbo JavaScript included with `rwf_head()`.
+    pub fn reload() -> Result<(), Error> {
+        Self::notify().send(reload_message())
+    }
+
+    /// Show everyone connected a maintenance message, e.g. before a deploy. The message is HTML,
+    /// shown in a banner at the top of the page. Requires the Turbo JavaScript included with `rwf_head()`.
+    pub fn maintenance(message: impl ToString) -> Result<(), Error> {
+        Self::notify().send(TurboStream::new(message).action("maintenance"))
+    }
+
+    /// Close all WebSocket connections served by this instance, telling clients the server is
+    /// restarting, so they reconnect. Messages sent before are delivered first. Returns the number
+    /// of connections being closed.
+    ///
+    /// This is done automatically when the server shuts down.
+    pub fn disconnect_all() -> usize {
+        let connections = Self::connections();
+        DISCONNECT.send_modify(|generation| *generation += 1);
+        connections
+    }
+
+    /// Number of WebSocket connections served by this instance.
+    pub fn connections() -> usize {
+        get_comms().connections()
+    }
+
+    /// Pass messages to other instances of the app through this backplane, so
+    /// clients can connect to any instance.
+    pub fn backplane(backplane: impl Backplane + 'static) {
+        backplane::set_backplane(backplane);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{FromRow, ToValue, Value};
+
+    #[test]
+    fn test_websocket_sender() {
+        let session = SessionId::Authenticated(5);
+        let websocket = Comms::websocket(&session);
+        websocket.send(Message::Text("test".into())).unwrap();
+
+        #[derive(Clone)]
+        struct User {
+            id: Option<i64>,
+        }
+
+        impl Model for User {
+            fn table_name() -> &'static str {
+                "users"
+            }
+
+            fn foreign_key() -> &'static str {
+                "user_id"
+            }
+
+            fn column_names() -> &'static [&'static str] {
+                &[]
+            }
+
+            fn values(&self) -> Vec<Value> {
+                vec![]
+            }
+
+            fn id(&self) -> Value {
+                self.id.to_value()
+            }
+        }
+
+        impl FromRow for User {
+            fn from_row(_row: tokio_postgres::Row) -> Result<Self, crate::model::Error>
+            where
+                Self: Sized,
+            {
+                unimplemented!()
+            }
+        }
+
+        let user = User { id: Some(5) };
+        let websocket = Comms::websocket(&user);
+        websocket.send(Message::Text("test2".into())).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_deliver() {
+        let session = SessionId::Authenticated(42);
+        let mut receiver = Comms::receiver(&session);
+
+        // Sent by another instance.
+        let mut envelope = Envelope::new(
+            Target::Session(session.clone()),
+            Message::Text("hello".into()),
+        );
+        envelope.origin = "other".into();
+        assert_eq!(deliver(envelope.clone()), 1);
+        assert!(matches!(receiver.recv().await.unwrap(), Message::Text(text) if text == "hello"));
+
+        envelope.target = Target::Everyone {
+            except: Some(session.clone()),
+        };
+        deliver(envelope);
+        assert!(receiver.try_recv().is_err());
+
+        // Sent by this instance, so delivered already.
+        let envelope = Envelope::new(Target::Session(session), Message::Text("again".into()));
+        assert_eq!(deliver(envelope), 0);
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_all() {
+        let session = SessionId::Authenticated(43);
+        let mut disconnect = disconnect_receiver();
+        let mut receiver = Comms::receiver(&session);
+        assert!(Comms::connections() >= 1);
+
+        get_comms().deliver(&Target::Session(session.clone()), reload_message());
+        assert!(Comms::disconnect_all() >= 1);
+        assert!(disconnect.has_changed().unwrap());
+        disconnect.changed().await.unwrap();
+
+        // Messages sent before the disconnect are still delivered.
+        assert!(
+            matches!(receiver.try_recv().unwrap(), Message::Text(text) if text.contains("reload-page"))
+        );
+    }
+}
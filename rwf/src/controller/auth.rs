@@ -13,7 +13,7 @@ use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use time::{Duration, OffsetDateTime};
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Debug;
 use std::sync::Arc;
 
@@ -51,6 +51,13 @@ pub trait Authentication: Sync + Send {
     /// going forward.
     async fn authorize(&self, request: &Request) -> Result<bool, Error>;
 
+    /// Authorize the request and return it, with the authenticated user attached if the
+    /// mechanism identifies one, e.g. from a bearer token. Default calls [`Authentication::authorize`].
+    async fn authenticate(&self, request: Request) -> Result<(Request, bool), Error> {
+        let allowed = self.authorize(&request).await?;
+        Ok((request, allowed))
+    }
+
     /// If the request is denied, return a specific response.
     /// Default is `401 - Unauthorized`.
     async fn denied(&self, request: &Request) -> Result<Response, Error> {
@@ -210,6 +217,9 @@ pub struct Session {
     /// Type of session, e.g. guest or user.
     #[serde(rename = "s")]
     pub session_id: SessionId,
+    /// Flash messages, shown on the next page the user sees, see [`Response::flash`].
+    #[serde(rename = "f", default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub flash: BTreeMap<String, String>,
 }
 
 impl Default for Session {
@@ -253,6 +263,7 @@ impl Session {
             expiration: (OffsetDateTime::now_utc() + get_config().general.session_duration())
                 .unix_timestamp(),
             session_id: SessionId::default(),
+            flash: BTreeMap::new(),
         })
     }
 
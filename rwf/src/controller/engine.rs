@@ -3,7 +3,7 @@
 //!
 //! Currently a work in progress. The closest analogy in other frameworks
 //! are [Rails engines](https://guides.rubyonrails.org/engines.html).
-use crate::http::{Handler, Path, Request, Response, Router};
+use crate::http::{Handler, Path, Request, Response, RouteMatch, Router};
 
 use super::{AuthHandler, Controller, Error};
 
@@ -55,12 +55,13 @@ impl Controller for Engine {
         }
 
         let path = request.path().pop_base(&self.mount);
-        let handler = self.router.find(&path);
 
-        if let Some(handler) = handler {
-            handler.handle(request).await
-        } else {
-            Ok(Response::not_found())
+        match self.router.route(&path, request) {
+            RouteMatch::Found(handler) => handler.handle(request).await,
+            RouteMatch::MethodNotAllowed(methods) => {
+                Ok(Response::method_not_allowed().allow(&methods))
+            }
+            RouteMatch::NotFound => Ok(Response::not_found()),
         }
     }
 }
@@ -34,6 +34,15 @@ pub enum Error {
     #[error("crypto error: {0}")]
     CryptoError(#[from] crate::crypto::Error),
 
+    #[error("storage error: {0}")]
+    StorageError(#[from] crate::storage::Error),
+
+    #[error("payments error: {0}")]
+    PaymentsError(#[from] crate::payments::Error),
+
+    #[error("{0}")]
+    PasswordError(#[from] crate::auth::password::Error),
+
     #[error("{0}")]
     Error(#[from] Box<dyn std::error::Error + Sync + Send>),
 
@@ -62,3 +71,9 @@ impl From<crate::http::Error> for Error {
         Error::HttpError(Box::new(error))
     }
 }
+
+impl From<crate::http::ValidationErrors> for Error {
+    fn from(errors: crate::http::ValidationErrors) -> Self {
+        crate::http::Error::Validation(errors).into()
+    }
+}
@@ -0,0 +1,319 @@
+//! Health check endpoints, e.g. for Kubernetes liveness and readiness probes.
+//!
+//! `/health/live` returns `200 - OK` as long as the server is running. `/health/ready` checks
+//! that the database is reachable, all migrations have been applied, and runs all checks added
+//! with [`HealthController::check`]. If any of them fail, it returns `503 - Service Unavailable`,
+//! so the load balancer stops sending requests to this instance.
+//!
+//! # Example
+//!
+//! ```rust
+//! use rwf::prelude::*;
+//! use rwf::controller::health::{HealthCheck, HealthController};
+//!
+//! struct Cache;
+//!
+//! #[async_trait]
+//! impl HealthCheck for Cache {
+//!     fn name(&self) -> &str {
+//!         "cache"
+//!     }
+//!
+//!     async fn check(&self) -> Result<(), Error> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let handler = HealthController::new().check(Cache).handler();
+//! ```
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::json;
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+
+use super::{Controller, Error};
+use crate::app::{self, State};
+use crate::http::{Handler, Request, Response};
+use crate::model::{migrations::Migrations, Pool};
+
+/// Check run by the readiness endpoint.
+#[async_trait]
+pub trait HealthCheck: Send + Sync {
+    /// Name of the check, used in the response.
+    fn name(&self) -> &str;
+
+    /// Return an error if the application isn't ready to serve requests.
+    async fn check(&self) -> Result<(), Error>;
+}
+
+/// The database is reachable.
+pub struct Database;
+
+#[async_trait]
+impl HealthCheck for Database {
+    fn name(&self) -> &str {
+        "database"
+    }
+
+    async fn check(&self) -> Result<(), Error> {
+        let conn = Pool::connection().await?;
+        conn.client()
+            .simple_query("SELECT 1")
+            .await
+            .map_err(Error::new)?;
+        Ok(())
+    }
+}
+
+/// All migrations in the `"migrations"` folder have been applied.
+pub struct MigrationsApplied;
+
+#[async_trait]
+impl HealthCheck for MigrationsApplied {
+    fn name(&self) -> &str {
+        "migrations"
+    }
+
+    async fn check(&self) -> Result<(), Error> {
+        let pending = Migrations::pending().await?;
+
+        if pending.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Error(
+                format!("pending migrations: {}", pending.join(", ")).into(),
+            ))
+        }
+    }
+}
+
+/// Services supervised by the [`crate::app::App`] are running.
+#[async_trait]
+impl HealthCheck for app::Health {
+    fn name(&self) -> &str {
+        "services"
+    }
+
+    async fn check(&self) -> Result<(), Error> {
+        let failing = self
+            .services()
+            .into_iter()
+            .filter(|service| matches!(service.state, State::Restarting | State::Failed))
+            .map(|service| service.name)
+            .collect::<Vec<_>>();
+
+        if failing.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Error(
+                format!("failing services: {}", failing.join(", ")).into(),
+            ))
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CheckResult {
+    name: String,
+    status: &'static str,
+    duration_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Controller serving the liveness and readiness endpoints.
+pub struct HealthController {
+    prefix: String,
+    checks: Vec<Arc<dyn HealthCheck>>,
+    timeout: Duration,
+}
+
+impl Default for HealthController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HealthController {
+    /// Create the controller, checking the database and migrations. It's mounted at `/health`.
+    pub fn new() -> Self {
+        Self {
+            prefix: "/health".into(),
+            checks: vec![Arc::new(Database), Arc::new(MigrationsApplied)],
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Create the controller without any checks, e.g. for apps which don't use the database.
+    pub fn empty() -> Self {
+        Self {
+            checks: vec![],
+            ..Self::new()
+        }
+    }
+
+    /// Run this check before reporting the application is ready.
+    pub fn check(mut self, check: impl HealthCheck + 'static) -> Self {
+        self.checks.push(Arc::new(check));
+        self
+    }
+
+    /// How long each check can take before it's considered failed. Default: 5 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the prefix used in URLs. Default: `/health`.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Create the route handler, serving `<prefix>/live` and `<prefix>/ready`.
+    pub fn handler(self) -> Handler {
+        let prefix = self.prefix.clone();
+        Handler::wildcard(&prefix, self)
+    }
+
+    /// Run all checks concurrently.
+    async fn ready(&self) -> Vec<CheckResult> {
+        let mut tasks = JoinSet::new();
+
+        for (index, check) in self.checks.iter().enumerate() {
+            let check = check.clone();
+            let limit = self.timeout;
+
+            tasks.spawn(async move {
+                let start = Instant::now();
+                let error = match timeout(limit, check.check()).await {
+                    Ok(Ok(())) => None,
+                    Ok(Err(err)) => Some(err.to_string()),
+                    Err(_) => Some(format!("timed out after {:?}", limit)),
+                };
+
+                let result = CheckResult {
+                    name: check.name().to_string(),
+                    status: if error.is_none() { "ok" } else { "error" },
+                    duration_ms: start.elapsed().as_secs_f64() * 1000.0,
+                    error,
+                };
+
+                (index, result)
+            });
+        }
+
+        let mut results = vec![];
+        while let Some(result) = tasks.join_next().await {
+            match result {
+                Ok(result) => results.push(result),
+                Err(_) => results.push((
+                    usize::MAX,
+                    CheckResult {
+                        name: "unknown".into(),
+                        status: "error",
+                        duration_ms: 0.0,
+                        error: Some("check panicked".into()),
+                    },
+                )),
+            }
+        }
+
+        // Keep the order checks were added in.
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+}
+
+#[async_trait]
+impl Controller for HealthController {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let path = request.path().path();
+        let endpoint = path
+            .strip_prefix(self.prefix.as_str())
+            .unwrap_or(path)
+            .trim_matches('/');
+
+        let response = match endpoint {
+            "live" => Response::new().json(json!({ "status": "ok" }))?,
+            "ready" => {
+                let checks = self.ready().await;
+                let ok = checks.iter().all(|check| check.error.is_none());
+
+                Response::new()
+                    .json(json!({
+                        "status": if ok { "ok" } else { "error" },
+                        "checks": checks,
+                    }))?
+                    .code(if ok { 200 } else { 503 })
+            }
+            _ => return Ok(Response::not_found()),
+        };
+
+        Ok(response.header("cache-control", "no-store"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::test::dummy_ip;
+
+    struct Failing;
+
+    #[async_trait]
+    impl HealthCheck for Failing {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        async fn check(&self) -> Result<(), Error> {
+            Err(Error::Error("cache is down".into()))
+        }
+    }
+
+    async fn get(controller: &HealthController, path: &str) -> (u16, serde_json::Value) {
+        let request = Request::read(
+            dummy_ip(),
+            format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes(),
+        )
+        .await
+        .unwrap();
+        let response = controller.handle(&request).await.unwrap();
+        let code = response.status().code();
+
+        let mut bytes = vec![];
+        response.send(&mut bytes).await.unwrap();
+        let body = String::from_utf8(bytes).unwrap();
+        let (_, body) = body.split_once("\r\n\r\n").unwrap();
+
+        (code, serde_json::from_str(body).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn test_health() {
+        let controller = HealthController::empty();
+
+        let (code, body) = get(&controller, "/health/live").await;
+        assert_eq!(code, 200);
+        assert_eq!(body["status"], "ok");
+
+        let (code, body) = get(&controller, "/health/ready").await;
+        assert_eq!(code, 200);
+        assert_eq!(body["checks"], json!([]));
+
+        let (code, _) = get(&controller, "/health/other").await;
+        assert_eq!(code, 404);
+
+        let controller = controller.check(Failing);
+        let (code, body) = get(&controller, "/health/ready").await;
+        assert_eq!(code, 503);
+        assert_eq!(body["status"], "error");
+        assert_eq!(body["checks"][0]["name"], "failing");
+        assert_eq!(body["checks"][0]["error"], "cache is down");
+    }
+}
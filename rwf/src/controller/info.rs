@@ -0,0 +1,197 @@
+//! Runtime information endpoint, useful for verifying what exactly is deployed.
+//!
+//! `/_info` returns the Rwf version, the Git commit the app was built from, the environment,
+//! enabled Cargo features, the latest applied migration, and the configuration, with secrets
+//! filtered out. The same information, except the configuration, is logged when the server starts.
+//!
+//! The commit is read from the `RWF_GIT_SHA` environment variable, when the app is built
+//! or when it's started, e.g.:
+//!
+//! ```bash
+//! RWF_GIT_SHA=$(git rev-parse HEAD) cargo build --release
+//! ```
+//!
+//! The endpoint exposes details about the deployment, so it requires authentication.
+//!
+//! # Example
+//!
+//! ```rust
+//! use rwf::controller::{BasicAuth, InfoController};
+//!
+//! let handler = InfoController::new(BasicAuth {
+//!     user: "admin".into(),
+//!     password: "hunter2".into(),
+//! })
+//! .handler();
+//! ```
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Serialize;
+use tokio::time::timeout;
+use tracing::{debug, info};
+
+use super::{AuthHandler, Authentication, Controller, Error};
+use crate::colors::MaybeColorize;
+use crate::config::get_config;
+use crate::http::{Handler, Request, Response};
+use crate::model::migrations::Migrations;
+
+/// Cargo features Rwf was compiled with.
+const FEATURES: &[(&str, bool)] = &[
+    ("wsgi", cfg!(feature = "wsgi")),
+    ("rack", cfg!(feature = "rack")),
+    ("kafka", cfg!(feature = "kafka")),
+    ("amqp", cfg!(feature = "amqp")),
+    ("geoip", cfg!(feature = "geoip")),
+    ("parquet", cfg!(feature = "parquet")),
+    ("telemetry", cfg!(feature = "telemetry")),
+];
+
+/// What's deployed.
+#[derive(Debug, Clone, Serialize)]
+pub struct Info {
+    /// Rwf version.
+    pub version: &'static str,
+    /// Git commit the app was built from, if known.
+    pub git_sha: Option<String>,
+    /// Environment the configuration was loaded for.
+    pub environment: &'static str,
+    /// Enabled Cargo features.
+    pub features: Vec<&'static str>,
+    /// Latest migration applied to the database, e.g. `"12_add_users"`.
+    pub migration: Option<String>,
+    /// Configuration, with secrets filtered out.
+    pub config: serde_json::Value,
+}
+
+impl Info {
+    /// Collect information about the running app. The database is queried for the latest
+    /// migration; if it can't be reached quickly, the migration is left out.
+    pub async fn collect() -> Self {
+        let config = get_config();
+        let migration = match timeout(Duration::from_secs(1), Migrations::version()).await {
+            Ok(Ok(migration)) => migration,
+            _ => None,
+        };
+
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: git_sha(),
+            environment: config.environment.name(),
+            features: FEATURES
+                .iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| *name)
+                .collect(),
+            migration,
+            config: config.sanitized(),
+        }
+    }
+
+    /// Log the information when the server starts. The configuration is logged at debug level.
+    pub(crate) fn log(&self) {
+        info!(
+            "Rwf {} (commit: {}), environment: {}",
+            self.version.green(),
+            self.git_sha.as_deref().unwrap_or("unknown").purple(),
+            self.environment.green()
+        );
+
+        info!(
+            "Features: {}, migration: {}",
+            if self.features.is_empty() {
+                "none".to_string()
+            } else {
+                self.features.join(", ")
+            },
+            self.migration.as_deref().unwrap_or("none")
+        );
+
+        debug!("Configuration: {}", self.config);
+    }
+}
+
+/// Commit set at build time takes precedence over the one set at runtime.
+fn git_sha() -> Option<String> {
+    option_env!("RWF_GIT_SHA")
+        .map(|sha| sha.to_string())
+        .or_else(|| std::env::var("RWF_GIT_SHA").ok())
+        .filter(|sha| !sha.is_empty())
+}
+
+/// Controller serving runtime information as JSON.
+pub struct InfoController {
+    path: String,
+    auth: AuthHandler,
+}
+
+impl InfoController {
+    /// Create the controller, protected by the authentication method. It's mounted at `/_info`.
+    pub fn new(auth: impl Authentication + 'static) -> Self {
+        Self {
+            path: "/_info".into(),
+            auth: AuthHandler::new(auth),
+        }
+    }
+
+    /// Set the path the controller is mounted at. Default: `/_info`.
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = path.to_string();
+        self
+    }
+
+    /// Create the route handler.
+    pub fn handler(self) -> Handler {
+        let path = self.path.clone();
+        Handler::route(&path, self)
+    }
+}
+
+#[async_trait]
+impl Controller for InfoController {
+    fn auth(&self) -> &AuthHandler {
+        &self.auth
+    }
+
+    async fn handle(&self, _request: &Request) -> Result<Response, Error> {
+        let info = Info::collect().await;
+
+        Ok(Response::new()
+            .json(info)?
+            .header("cache-control", "no-store"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::controller::DenyAll;
+    use crate::http::request::test::dummy_ip;
+
+    #[tokio::test]
+    async fn test_info() {
+        let controller = InfoController::new(DenyAll);
+        let request = Request::read(
+            dummy_ip(),
+            "GET /_info HTTP/1.1\r\nHost: localhost\r\n\r\n".as_bytes(),
+        )
+        .await
+        .unwrap();
+
+        // Denied before reaching the controller.
+        let response = controller.handle_internal(request.clone()).await.unwrap();
+        assert_eq!(response.status().code(), 401);
+
+        let response = controller.handle(&request).await.unwrap();
+        let mut bytes = vec![];
+        response.send(&mut bytes).await.unwrap();
+        let body = String::from_utf8(bytes).unwrap();
+        let (_, body) = body.split_once("\r\n\r\n").unwrap();
+        let info: serde_json::Value = serde_json::from_str(body).unwrap();
+
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+        assert!(info["features"].is_array());
+        assert_eq!(info["config"]["general"]["secret_key"], "[FILTERED]");
+    }
+}
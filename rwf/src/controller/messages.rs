@@ -0,0 +1,454 @@
+//! WebSocket controllers receiving typed JSON messages.
+//!
+//! Messages sent by clients are JSON objects with a `type` field, deserialized into an enum and passed to
+//! a [`MessageHandler`]. Each connection gets its own state, kept for as long as it's open.
+//!
+//! ```
+//! use rwf::prelude::*;
+//! use rwf::controller::messages::{Connection, MessageController, MessageHandler};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! #[serde(tag = "type", rename_all = "snake_case")]
+//! enum ChatMessage {
+//!     Join { room: String },
+//!     Say { body: String },
+//! }
+//!
+//! struct Chat;
+//!
+//! #[async_trait]
+//! impl MessageHandler for Chat {
+//!     type Message = ChatMessage;
+//!     type State = Option<String>; // The room the client joined.
+//!
+//!     async fn handle(&self, conn: &mut Connection<Self::State>, message: ChatMessage) -> Result<(), Error> {
+//!         match message {
+//!             ChatMessage::Join { room } => {
+//!                 Comms::channel(&room).subscribe(conn.session_id());
+//!                 *conn.state_mut() = Some(room);
+//!             }
+//!             ChatMessage::Say { body } => match conn.state() {
+//!                 Some(room) => Comms::channel(room).send(body)?,
+//!                 None => conn.reply(&serde_json::json!({"type": "error", "message": "join a room first"}))?,
+//!             },
+//!         }
+//!
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let route = MessageController::new(Chat).route("/chat");
+//! ```
+//!
+//! Clients can check that the connection is alive by sending `{"type": "ping"}`, which is answered with `{"type": "pong"}`.
+//! Messages which can't be handled are answered with an error, e.g.
+//! `{"type": "error", "code": "invalid_message", "message": "unknown variant `shout`"}`, and the connection stays open.
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value as Json};
+use tracing::{debug, warn};
+
+use super::{serve_websocket, AuthHandler, Controller, Error, SessionId, WebsocketConnection};
+use crate::http::{websocket::Message, Request, Response, Stream};
+
+/// Handles messages sent by clients.
+#[async_trait]
+#[allow(unused_variables)]
+pub trait MessageHandler: Send + Sync {
+    /// Messages clients can send, usually an enum with `#[serde(tag = "type")]`.
+    type Message: DeserializeOwned + Send;
+
+    /// State kept for each connection.
+    type State: Default + Send;
+
+    /// Handle a message sent by the client.
+    async fn handle(
+        &self,
+        conn: &mut Connection<Self::State>,
+        message: Self::Message,
+    ) -> Result<(), Error>;
+
+    /// Do something when a client connects.
+    async fn connected(&self, conn: &mut Connection<Self::State>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Do something when a client disconnects.
+    async fn disconnected(&self, conn: &mut Connection<Self::State>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Limits protecting the server from clients sending or receiving too much.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    /// Largest message accepted, in bytes.
+    pub max_message_size: usize,
+    /// Most messages accepted from a client each second. Messages over the limit are answered
+    /// with an error and not handled.
+    pub max_messages_per_second: usize,
+    /// Close the connection if the client is too slow to receive this many
+    /// messages sent with [`Comms`](crate::comms::Comms). They are dropped otherwise.
+    pub max_lag: Option<u64>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_message_size: 64 * 1024,
+            max_messages_per_second: 100,
+            max_lag: None,
+        }
+    }
+}
+
+/// A client's connection.
+#[derive(Debug)]
+pub struct Connection<S> {
+    session_id: SessionId,
+    state: S,
+    replies: Vec<Message>,
+    window: Instant,
+    received: usize,
+}
+
+impl<S: Default> Connection<S> {
+    /// New connection for the session.
+    pub fn new(session_id: SessionId) -> Self {
+        Self {
+            session_id,
+            state: S::default(),
+            replies: vec![],
+            window: Instant::now(),
+            received: 0,
+        }
+    }
+}
+
+impl<S> Connection<S> {
+    /// The client's session.
+    pub fn session_id(&self) -> &SessionId {
+        &self.session_id
+    }
+
+    /// The logged in user, if any.
+    pub fn user_id(&self) -> Option<i64> {
+        self.session_id.user_id()
+    }
+
+    /// The connection state.
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// Change the connection state.
+    pub fn state_mut(&mut self) -> &mut S {
+        &mut self.state
+    }
+
+    /// Send a JSON message to this connection only. Other connections of the same session don't receive it.
+    pub fn reply(&mut self, message: &impl Serialize) -> Result<(), Error> {
+        self.replies
+            .push(Message::Text(serde_json::to_string(message)?));
+        Ok(())
+    }
+
+    /// Messages to send to the client, in the order they were added.
+    pub fn take_replies(&mut self) -> Vec<Message> {
+        std::mem::take(&mut self.replies)
+    }
+
+    /// Count a message received from the client. Returns `false` if it's over the limit.
+    fn count(&mut self, limit: usize) -> bool {
+        if self.window.elapsed() >= Duration::from_secs(1) {
+            self.window = Instant::now();
+            self.received = 0;
+        }
+
+        self.received += 1;
+        self.received <= limit
+    }
+
+    fn error(&mut self, code: &str, message: impl ToString) {
+        self.replies.push(Message::Text(
+            json!({ "type": "error", "code": code, "message": message.to_string() }).to_string(),
+        ));
+    }
+}
+
+/// WebSocket controller passing messages to a [`MessageHandler`].
+pub struct MessageController<H: MessageHandler> {
+    handler: H,
+    limits: Limits,
+    auth: Option<AuthHandler>,
+}
+
+impl<H: MessageHandler> MessageController<H> {
+    /// Pass messages to the handler.
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            limits: Limits::default(),
+            auth: None,
+        }
+    }
+
+    /// Require authentication before the connection is accepted.
+    pub fn auth(mut self, auth: AuthHandler) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Change the default limits.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Handle a message sent by the client. Replies, including errors, are added to the connection.
+    pub async fn receive(&self, conn: &mut Connection<H::State>, message: &str) {
+        if !conn.count(self.limits.max_messages_per_second) {
+            conn.error("rate_limited", "too many messages, slow down");
+            return;
+        }
+
+        if message.len() > self.limits.max_message_size {
+            conn.error(
+                "too_large",
+                format!(
+                    "message is larger than {} bytes",
+                    self.limits.max_message_size
+                ),
+            );
+            return;
+        }
+
+        let message = match serde_json::from_str::<Json>(message) {
+            Ok(Json::Object(message)) => message,
+            _ => {
+                conn.error("invalid_message", "message is not a JSON object");
+                return;
+            }
+        };
+
+        if message.get("type").and_then(|kind| kind.as_str()) == Some("ping") {
+            conn.replies
+                .push(Message::Text(json!({"type": "pong"}).to_string()));
+            return;
+        }
+
+        let message = match serde_json::from_value::<H::Message>(Json::Object(message)) {
+            Ok(message) => message,
+            Err(err) => {
+                debug!("invalid websocket message: {}", err);
+                conn.error("invalid_message", err);
+                return;
+            }
+        };
+
+        if let Err(err) = self.handler.handle(conn, message).await {
+            warn!("websocket message error: {}", err);
+
+            // Only show internal errors in development.
+            if cfg!(debug_assertions) {
+                conn.error("internal_error", err);
+            } else {
+                conn.error("internal_error", "internal error");
+            }
+        }
+    }
+}
+
+struct HandlerConnection<'a, H: MessageHandler> {
+    controller: &'a MessageController<H>,
+    conn: Connection<H::State>,
+}
+
+#[async_trait]
+impl<H: MessageHandler> WebsocketConnection for HandlerConnection<'_, H> {
+    fn session_id(&self) -> &SessionId {
+        self.conn.session_id()
+    }
+
+    async fn connected(&mut self) -> Result<(), Error> {
+        self.controller.handler.connected(&mut self.conn).await
+    }
+
+    async fn message(&mut self, message: Message) -> Result<Vec<Message>, Error> {
+        match message {
+            Message::Text(text) => self.controller.receive(&mut self.conn, &text).await,
+            _ => self
+                .conn
+                .error("invalid_message", "binary messages are not supported"),
+        }
+
+        Ok(self.conn.take_replies())
+    }
+
+    fn lagged(&mut self, skipped: u64) -> bool {
+        match self.controller.limits.max_lag {
+            Some(max_lag) if skipped > max_lag => {
+                warn!(
+                    "closing websocket for session \"{}\", {} messages dropped",
+                    self.conn.session_id(),
+                    skipped
+                );
+                false
+            }
+            _ => true,
+        }
+    }
+
+    async fn disconnected(&mut self) -> Result<(), Error> {
+        self.controller.handler.disconnected(&mut self.conn).await
+    }
+}
+
+#[async_trait]
+impl<H: MessageHandler> Controller for MessageController<H> {
+    fn auth(&self) -> &AuthHandler {
+        match self.auth {
+            Some(ref auth) => auth,
+            None => &crate::config::get_config().general.default_auth,
+        }
+    }
+
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        super::WebsocketController::handle(self, request).await
+    }
+
+    async fn handle_stream(&self, request: &Request, stream: Stream<'_>) -> Result<bool, Error> {
+        let mut connection = HandlerConnection {
+            controller: self,
+            conn: Connection::new(request.session().session_id.clone()),
+        };
+
+        serve_websocket(&mut connection, request, stream, self.controller_name()).await
+    }
+
+    fn controller_name(&self) -> &'static str {
+        std::any::type_name::<H>()
+    }
+}
+
+// Upgrades the connection; messages are handled by `Controller::handle_stream`.
+impl<H: MessageHandler> super::WebsocketController for MessageController<H> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    enum CounterMessage {
+        Add { value: i64 },
+        Fail,
+    }
+
+    struct Counter;
+
+    #[async_trait]
+    impl MessageHandler for Counter {
+        type Message = CounterMessage;
+        type State = i64;
+
+        async fn handle(
+            &self,
+            conn: &mut Connection<i64>,
+            message: CounterMessage,
+        ) -> Result<(), Error> {
+            match message {
+                CounterMessage::Add { value } => {
+                    *conn.state_mut() += value;
+                    let total = *conn.state();
+                    conn.reply(&json!({"type": "total", "value": total}))
+                }
+                CounterMessage::Fail => Err(Error::Error("failed".into())),
+            }
+        }
+    }
+
+    fn replies(conn: &mut Connection<i64>) -> Vec<Json> {
+        conn.take_replies()
+            .into_iter()
+            .map(|reply| match reply {
+                Message::Text(text) => serde_json::from_str(&text).unwrap(),
+                reply => panic!("unexpected reply: {:?}", reply),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_messages() {
+        let controller = MessageController::new(Counter);
+        let mut conn = Connection::new(SessionId::Authenticated(3));
+
+        controller
+            .receive(&mut conn, r#"{"type": "add", "value": 2}"#)
+            .await;
+        controller
+            .receive(&mut conn, r#"{"type": "add", "value": 3}"#)
+            .await;
+        assert_eq!(*conn.state(), 5);
+        assert_eq!(
+            replies(&mut conn),
+            vec![
+                json!({"type": "total", "value": 2}),
+                json!({"type": "total", "value": 5})
+            ]
+        );
+
+        // Each connection has its own state.
+        let mut other = Connection::new(SessionId::Authenticated(3));
+        controller
+            .receive(&mut other, r#"{"type": "add", "value": 1}"#)
+            .await;
+        assert_eq!(*other.state(), 1);
+
+        controller.receive(&mut conn, r#"{"type": "ping"}"#).await;
+        assert_eq!(replies(&mut conn), vec![json!({"type": "pong"})]);
+
+        controller.receive(&mut conn, r#"{"type": "shout"}"#).await;
+        controller.receive(&mut conn, "hello").await;
+        controller.receive(&mut conn, r#"{"type": "fail"}"#).await;
+        let codes = replies(&mut conn)
+            .into_iter()
+            .map(|reply| reply["code"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            codes,
+            vec!["invalid_message", "invalid_message", "internal_error"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_limits() {
+        let controller = MessageController::new(Counter).limits(Limits {
+            max_message_size: 32,
+            max_messages_per_second: 2,
+            max_lag: Some(10),
+        });
+        let mut conn = Connection::new(SessionId::Authenticated(4));
+
+        let large = format!(r#"{{"type": "add", "value": {}}}"#, "1".repeat(32));
+        controller.receive(&mut conn, &large).await;
+        controller.receive(&mut conn, r#"{"type": "ping"}"#).await;
+        controller.receive(&mut conn, r#"{"type": "ping"}"#).await;
+        let codes = replies(&mut conn)
+            .into_iter()
+            .map(|reply| reply["code"].as_str().unwrap_or("").to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(codes, vec!["too_large", "", "rate_limited"]);
+
+        let mut connection = HandlerConnection {
+            controller: &controller,
+            conn,
+        };
+        assert!(connection.lagged(10));
+        assert!(!connection.lagged(11));
+    }
+}
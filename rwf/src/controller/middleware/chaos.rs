@@ -0,0 +1,233 @@
+//! Fault injection for resilience testing.
+//!
+//! Slows down, fails, or drops a percentage of requests, so the application's clients and its own
+//! retry and timeout paths can be exercised before something breaks in production. The same faults
+//! can be injected into database connection checkouts with [`Chaos::database`].
+//!
+//! Faults are only injected in debug builds, unless the `RWF_CHAOS` environment variable is set to `1`.
+//!
+//! ### Usage
+//!
+//! ```
+//! use std::time::Duration;
+//! use rwf::controller::middleware::{Chaos, Middleware};
+//!
+//! let chaos = Chaos::new()
+//!     .latency(10.0, Duration::from_millis(500))
+//!     .error(5.0, 503)
+//!     .drop_connection(1.0);
+//!
+//! // Checkouts from the connection pool fail or are slowed down too.
+//! chaos.database();
+//!
+//! let middleware = chaos.middleware();
+//! ```
+use std::sync::Arc;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::time::sleep;
+use tracing::warn;
+
+use super::prelude::*;
+
+static DATABASE: Lazy<RwLock<Option<Chaos>>> = Lazy::new(|| RwLock::new(None));
+
+/// Fault injection middleware.
+#[derive(Debug, Clone)]
+pub struct Chaos {
+    latency: Option<(f64, Duration)>,
+    error: Option<(f64, u16)>,
+    drop: Option<f64>,
+    rng: Arc<Mutex<StdRng>>,
+}
+
+impl Default for Chaos {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Faults picked for a request or checkout.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Faults {
+    latency: Option<Duration>,
+    error: Option<u16>,
+    drop: bool,
+}
+
+impl Chaos {
+    /// Create middleware that doesn't inject any faults. Add faults with
+    /// [`Chaos::latency`], [`Chaos::error`] and [`Chaos::drop_connection`].
+    pub fn new() -> Self {
+        Self {
+            latency: None,
+            error: None,
+            drop: None,
+            rng: Arc::new(Mutex::new(StdRng::from_entropy())),
+        }
+    }
+
+    /// Delay `percent` of requests by a random duration, up to `max`.
+    pub fn latency(mut self, percent: f64, max: Duration) -> Self {
+        self.latency = Some((percent, max));
+        self
+    }
+
+    /// Fail `percent` of requests with the status code, e.g. `503`.
+    pub fn error(mut self, percent: f64, code: u16) -> Self {
+        self.error = Some((percent, code));
+        self
+    }
+
+    /// Close the connection without a response for `percent` of requests.
+    pub fn drop_connection(mut self, percent: f64) -> Self {
+        self.drop = Some(percent);
+        self
+    }
+
+    /// Seed the random number generator, so the same faults are injected on every run.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng = Arc::new(Mutex::new(StdRng::seed_from_u64(seed)));
+        self
+    }
+
+    /// Inject the same faults into database connection checkouts. Latency counts towards
+    /// the pool's checkout timeout, and errors and dropped connections fail the checkout
+    /// with a pool timeout.
+    pub fn database(&self) {
+        if Self::enabled() {
+            warn!("chaos: injecting faults into database checkouts");
+        }
+
+        *DATABASE.write() = Some(self.clone());
+    }
+
+    /// Stop injecting faults into database connection checkouts.
+    pub fn disable_database() {
+        *DATABASE.write() = None;
+    }
+
+    fn enabled() -> bool {
+        cfg!(debug_assertions) || std::env::var("RWF_CHAOS").as_deref() == Ok("1")
+    }
+
+    fn roll(&self) -> Faults {
+        if !Self::enabled() {
+            return Faults::default();
+        }
+
+        let mut rng = self.rng.lock();
+        let mut hit = |percent: f64| rng.gen_range(0.0..100.0) < percent;
+
+        let latency = self
+            .latency
+            .filter(|(percent, _)| hit(*percent))
+            .map(|(_, max)| max);
+        let error = self
+            .error
+            .filter(|(percent, _)| hit(*percent))
+            .map(|(_, code)| code);
+        let drop = self.drop.map(&mut hit).unwrap_or(false);
+
+        Faults {
+            latency: latency.map(|max| max.mul_f64(rng.gen_range(0.0..=1.0))),
+            error,
+            drop,
+        }
+    }
+}
+
+/// Inject faults into a database connection checkout, if enabled with [`Chaos::database`].
+pub(crate) async fn checkout() -> Result<(), crate::model::Error> {
+    let faults = match DATABASE.read().as_ref() {
+        Some(chaos) => chaos.roll(),
+        None => return Ok(()),
+    };
+
+    if let Some(latency) = faults.latency {
+        sleep(latency).await;
+    }
+
+    if faults.error.is_some() || faults.drop {
+        Err(crate::model::Error::PoolTimeout)
+    } else {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Middleware for Chaos {
+    async fn handle_request(&self, request: Request) -> Result<Outcome, Error> {
+        let faults = self.roll();
+
+        if let Some(latency) = faults.latency {
+            sleep(latency).await;
+        }
+
+        if faults.drop {
+            return Ok(Outcome::Stop(request, Response::new().drop_connection()));
+        }
+
+        if let Some(code) = faults.error {
+            let response =
+                Response::error_pretty(&format!("{} - Injected Fault", code), "").code(code);
+            return Ok(Outcome::Stop(request, response));
+        }
+
+        Ok(Outcome::Forward(request))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::test::dummy_ip;
+
+    #[test]
+    fn test_roll() {
+        let chaos = Chaos::new().seed(42);
+        assert_eq!(chaos.roll(), Faults::default());
+
+        let chaos = Chaos::new()
+            .latency(100.0, Duration::from_millis(10))
+            .error(100.0, 503)
+            .drop_connection(0.0)
+            .seed(42);
+        let faults = chaos.roll();
+        assert!(faults.latency.unwrap() <= Duration::from_millis(10));
+        assert_eq!(faults.error, Some(503));
+        assert!(!faults.drop);
+
+        let chaos = Chaos::new().error(50.0, 500).seed(42);
+        let errors = (0..1000).filter(|_| chaos.roll().error.is_some()).count();
+        assert!(errors > 400 && errors < 600);
+    }
+
+    #[tokio::test]
+    async fn test_chaos_middleware() {
+        let request = Request::read(dummy_ip(), "GET / HTTP/1.1\r\n\r\n".as_bytes())
+            .await
+            .unwrap();
+
+        let chaos = Chaos::new().error(100.0, 503);
+        match chaos.handle_request(request.clone()).await.unwrap() {
+            Outcome::Stop(_, response) => assert_eq!(response.status().code(), 503),
+            _ => panic!("expected the request to fail"),
+        }
+
+        let chaos = Chaos::new().drop_connection(100.0);
+        match chaos.handle_request(request.clone()).await.unwrap() {
+            Outcome::Stop(_, response) => assert!(response.dropped()),
+            _ => panic!("expected the connection to be dropped"),
+        }
+
+        let chaos = Chaos::new().error(0.0, 503);
+        assert!(matches!(
+            chaos.handle_request(request).await.unwrap(),
+            Outcome::Forward(_)
+        ));
+    }
+}
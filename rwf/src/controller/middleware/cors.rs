@@ -0,0 +1,339 @@
+//! CORS (cross-origin resource sharing).
+//!
+//! Browsers block JavaScript from reading responses to requests sent to a different origin,
+//! unless the server allows it with CORS headers. This middleware sets those headers
+//! and answers preflight (`OPTIONS`) requests, so a frontend served from another domain,
+//! e.g. a React or Vue app, can use your API.
+//!
+//! ### Usage
+//!
+//! ```
+//! use rwf::controller::middleware::{Cors, Middleware};
+//!
+//! let cors = Cors::new()
+//!     .allow_origin("https://app.example.com")
+//!     .allow_credentials(true)
+//!     .max_age(3600)
+//!     .middleware();
+//! ```
+use super::prelude::*;
+use crate::http::Method;
+
+/// CORS middleware.
+#[derive(Debug, Clone)]
+pub struct Cors {
+    origins: Option<Vec<String>>,
+    methods: Vec<String>,
+    headers: Option<Vec<String>>,
+    expose_headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<u64>,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cors {
+    /// Create CORS middleware that doesn't allow any origins. Add origins
+    /// with [`Cors::allow_origin`].
+    pub fn new() -> Self {
+        Self {
+            origins: Some(vec![]),
+            methods: ["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            headers: None,
+            expose_headers: vec![],
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// Allow requests from any origin, with any method and headers, without credentials.
+    pub fn permissive() -> Self {
+        Self::new().allow_any_origin()
+    }
+
+    /// Allow requests from the origin, e.g. `https://app.example.com`.
+    pub fn allow_origin(mut self, origin: impl ToString) -> Self {
+        let origin = origin.to_string().trim_end_matches('/').to_string();
+        self.origins.get_or_insert_with(Vec::new).push(origin);
+        self
+    }
+
+    /// Allow requests from any origin.
+    pub fn allow_any_origin(mut self) -> Self {
+        self.origins = None;
+        self
+    }
+
+    /// Methods allowed in cross-origin requests. By default, all methods are allowed.
+    pub fn allow_methods(mut self, methods: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.methods = methods
+            .into_iter()
+            .map(|method| method.to_string().to_uppercase())
+            .collect();
+        self
+    }
+
+    /// Headers the client can send. By default, all headers requested by the client are allowed.
+    pub fn allow_headers(mut self, headers: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.headers = Some(
+            headers
+                .into_iter()
+                .map(|header| header.to_string().to_lowercase())
+                .collect(),
+        );
+        self
+    }
+
+    /// Response headers JavaScript is allowed to read, in addition to the default safe headers.
+    pub fn expose_headers(mut self, headers: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.expose_headers = headers.into_iter().map(|h| h.to_string()).collect();
+        self
+    }
+
+    /// Allow requests to include cookies.
+    pub fn allow_credentials(mut self, credentials: bool) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// How long, in seconds, the browser can cache the preflight response.
+    pub fn max_age(mut self, seconds: u64) -> Self {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    fn origin_allowed(&self, origin: &str) -> bool {
+        match self.origins {
+            Some(ref origins) => origins.iter().any(|allowed| allowed == origin),
+            None => true,
+        }
+    }
+
+    fn allow_origin_headers(&self, origin: &str, response: Response) -> Response {
+        // Credentials can't be used with the "*" wildcard.
+        let response = if self.origins.is_none() && !self.credentials {
+            response.header("access-control-allow-origin", "*")
+        } else {
+            response
+                .header("access-control-allow-origin", origin)
+                .header("vary", "Origin")
+        };
+
+        if self.credentials {
+            response.header("access-control-allow-credentials", "true")
+        } else {
+            response
+        }
+    }
+
+    fn preflight(&self, request: &Request, origin: &str, method: &str) -> Response {
+        if !self
+            .methods
+            .iter()
+            .any(|allowed| allowed == &method.to_uppercase())
+        {
+            return Response::forbidden();
+        }
+
+        let requested_headers = request
+            .header("access-control-request-headers")
+            .map(|headers| headers.to_lowercase())
+            .unwrap_or_default();
+
+        let allowed_headers = match self.headers {
+            Some(ref headers) => {
+                let allowed = requested_headers
+                    .split(',')
+                    .map(|header| header.trim())
+                    .filter(|header| !header.is_empty())
+                    .all(|header| headers.iter().any(|allowed| allowed == header));
+
+                if !allowed {
+                    return Response::forbidden();
+                }
+
+                headers.join(", ")
+            }
+            None => requested_headers,
+        };
+
+        let mut response = self
+            .allow_origin_headers(origin, Response::new().code(204))
+            .header("access-control-allow-methods", self.methods.join(", "));
+
+        if !allowed_headers.is_empty() {
+            response = response.header("access-control-allow-headers", allowed_headers);
+        }
+
+        if let Some(max_age) = self.max_age {
+            response = response.header("access-control-max-age", max_age);
+        }
+
+        response
+    }
+}
+
+#[async_trait]
+impl Middleware for Cors {
+    async fn handle_request(&self, request: Request) -> Result<Outcome, Error> {
+        let origin = match request.header("origin") {
+            Some(origin) => origin.clone(),
+            None => return Ok(Outcome::Forward(request)),
+        };
+
+        let preflight = request.method() == &Method::Other("OPTIONS".into());
+        let method = request.header("access-control-request-method").cloned();
+
+        match (preflight, method) {
+            (true, Some(method)) => {
+                let response = if self.origin_allowed(&origin) {
+                    self.preflight(&request, &origin, &method)
+                } else {
+                    Response::forbidden()
+                };
+
+                Ok(Outcome::Stop(request, response))
+            }
+
+            _ => Ok(Outcome::Forward(request)),
+        }
+    }
+
+    async fn handle_response(
+        &self,
+        request: &Request,
+        response: Response,
+    ) -> Result<Response, Error> {
+        let origin = match request.header("origin") {
+            Some(origin) if self.origin_allowed(origin) => origin,
+            _ => return Ok(response),
+        };
+
+        let response = self.allow_origin_headers(origin, response);
+
+        if self.expose_headers.is_empty() {
+            Ok(response)
+        } else {
+            Ok(response.header(
+                "access-control-expose-headers",
+                self.expose_headers.join(", "),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::test::dummy_ip;
+
+    async fn request(method: &str, headers: &[(&str, &str)]) -> Request {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}\r\n", name, value))
+            .collect::<String>();
+        let request = format!("{} /api/users HTTP/1.1\r\n{}\r\n", method, headers);
+        Request::read(dummy_ip(), request.as_bytes()).await.unwrap()
+    }
+
+    async fn preflight(cors: &Cors, headers: &[(&str, &str)]) -> Response {
+        match cors
+            .handle_request(request("OPTIONS", headers).await)
+            .await
+            .unwrap()
+        {
+            Outcome::Stop(_, response) => response,
+            Outcome::Forward(_) => panic!("preflight should be answered by middleware"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_preflight() {
+        let cors = Cors::new()
+            .allow_origin("https://app.example.com")
+            .allow_methods(["GET", "POST"])
+            .allow_headers(["Content-Type"])
+            .allow_credentials(true)
+            .max_age(600);
+
+        let response = preflight(
+            &cors,
+            &[
+                ("Origin", "https://app.example.com"),
+                ("Access-Control-Request-Method", "POST"),
+                ("Access-Control-Request-Headers", "content-type"),
+            ],
+        )
+        .await;
+        let headers = response.headers();
+        assert_eq!(response.status().code(), 204);
+        assert_eq!(
+            headers.get("access-control-allow-origin").unwrap(),
+            "https://app.example.com"
+        );
+        assert_eq!(
+            headers.get("access-control-allow-credentials").unwrap(),
+            "true"
+        );
+        assert_eq!(
+            headers.get("access-control-allow-methods").unwrap(),
+            "GET, POST"
+        );
+        assert_eq!(headers.get("access-control-max-age").unwrap(), "600");
+
+        for headers in [
+            [
+                ("Origin", "https://evil.com"),
+                ("Access-Control-Request-Method", "POST"),
+            ],
+            [
+                ("Origin", "https://app.example.com"),
+                ("Access-Control-Request-Method", "DELETE"),
+            ],
+        ] {
+            assert_eq!(preflight(&cors, &headers).await.status().code(), 403);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_simple_request() {
+        let cors = Cors::permissive().expose_headers(["X-Request-Id"]);
+
+        let req = request("GET", &[("Origin", "https://app.example.com")]).await;
+        let req = match cors.handle_request(req).await.unwrap() {
+            Outcome::Forward(req) => req,
+            Outcome::Stop(_, _) => panic!("request should be forwarded"),
+        };
+
+        let response = cors.handle_response(&req, Response::new()).await.unwrap();
+        assert_eq!(
+            response
+                .headers()
+                .get("access-control-allow-origin")
+                .unwrap(),
+            "*"
+        );
+        assert_eq!(
+            response
+                .headers()
+                .get("access-control-expose-headers")
+                .unwrap(),
+            "X-Request-Id"
+        );
+
+        // Same-origin requests don't have the Origin header.
+        let req = request("GET", &[]).await;
+        let response = cors.handle_response(&req, Response::new()).await.unwrap();
+        assert!(response
+            .headers()
+            .get("access-control-allow-origin")
+            .is_none());
+    }
+}
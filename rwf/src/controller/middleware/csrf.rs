@@ -15,8 +15,11 @@
 //! </form>
 //! ```
 //!
+//! All requests using unsafe methods, i.e. `POST`, `PUT`, `PATCH` and `DELETE`, are checked.
+//!
 //! If used via AJAX, include the CSRF token in the `X-CSRF-Token` header.
-//! You can obtain the token by calling the `csrf_token_raw` template function:
+//! You can obtain the token by calling the `csrf_token_raw` template function,
+//! or [`Request::csrf_token`](crate::http::Request::csrf_token) in a controller:
 //!
 //! ```html
 //! <script>
@@ -56,7 +59,7 @@ impl Middleware for Csrf {
             return Ok(Outcome::Forward(request));
         }
 
-        if ![Method::Put, Method::Post, Method::Patch].contains(request.method()) {
+        if ![Method::Put, Method::Post, Method::Patch, Method::Delete].contains(request.method()) {
             return Ok(Outcome::Forward(request));
         }
 
@@ -84,3 +87,57 @@ impl Middleware for Csrf {
         Ok(Outcome::Stop(request, Response::csrf_error()))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::test::dummy_ip;
+
+    async fn request(method: &str, token: Option<&str>, body: &str) -> Request {
+        let header = match token {
+            Some(token) => format!("{}: {}\r\n", CSRF_HEADER, token),
+            None => "".into(),
+        };
+        let request = format!(
+            "{} /orders HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\n{}Content-Length: {}\r\n\r\n{}",
+            method,
+            header,
+            body.len(),
+            body
+        );
+        Request::read(dummy_ip(), request.as_bytes()).await.unwrap()
+    }
+
+    fn forwarded(outcome: Outcome) -> bool {
+        matches!(outcome, Outcome::Forward(_))
+    }
+
+    #[tokio::test]
+    async fn test_csrf() {
+        let csrf = Csrf::new();
+
+        let get = request("GET", None, "").await;
+        assert!(forwarded(csrf.handle_request(get).await.unwrap()));
+
+        for method in ["POST", "PUT", "PATCH", "DELETE"] {
+            let req = request(method, None, "").await;
+            assert!(!forwarded(csrf.handle_request(req).await.unwrap()));
+        }
+
+        let session = request("GET", None, "").await.session().clone();
+        let token = crate::crypto::csrf_token(&session.session_id.to_string()).unwrap();
+
+        // Token is bound to the session.
+        let req = request("DELETE", Some(&token), "").await;
+        assert!(!forwarded(csrf.handle_request(req).await.unwrap()));
+
+        let req = request("DELETE", Some(&token), "")
+            .await
+            .set_session(session.clone());
+        assert!(forwarded(csrf.handle_request(req).await.unwrap()));
+
+        let body = format!("{}={}", CSRF_INPUT, crate::http::urlencode(&token));
+        let req = request("POST", None, &body).await.set_session(session);
+        assert!(forwarded(csrf.handle_request(req).await.unwrap()));
+    }
+}
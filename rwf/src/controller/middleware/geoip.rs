@@ -0,0 +1,121 @@
+//! GeoIP lookup.
+//!
+//! Looks up the client's IP address in a MaxMind database, e.g. GeoLite2 City or GeoIP2 Country,
+//! and sets the client's location on the request. The location is available in controllers
+//! with [`Request::geo`].
+//!
+//! ### Configuration
+//!
+//! Requires the `geoip` feature. The middleware runs on every controller
+//! if the path to the database is set in the configuration:
+//!
+//! ```toml
+//! [geoip]
+//! database = "/usr/share/GeoIP/GeoLite2-City.mmdb"
+//! ```
+use super::prelude::*;
+use crate::config::{get_config, GeoIpConfig};
+use crate::http::Geo;
+
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+/// GeoIP middleware.
+#[derive(Clone)]
+pub struct GeoIp {
+    reader: Arc<Reader<Vec<u8>>>,
+}
+
+impl GeoIp {
+    /// Load the database from disk.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MaxMindDBError> {
+        Ok(Self {
+            reader: Arc::new(Reader::open_readfile(path)?),
+        })
+    }
+
+    /// Load the database configured in `[geoip]`.
+    pub fn from_config() -> Result<Self, MaxMindDBError> {
+        Self::new(&get_config().geoip)
+    }
+
+    /// Load the database specified in the configuration.
+    pub fn new(config: &GeoIpConfig) -> Result<Self, MaxMindDBError> {
+        match config.database() {
+            Some(path) => Self::open(path),
+            None => Err(MaxMindDBError::IoError(
+                "GeoIP database is not configured".into(),
+            )),
+        }
+    }
+
+    /// Find the location of the IP address. Returns `None` if the
+    /// address isn't in the database, e.g. it's a private address.
+    pub fn lookup(&self, ip: IpAddr) -> Option<Geo> {
+        let city: geoip2::City = self.reader.lookup(ip).ok()?;
+
+        let region = city
+            .subdivisions
+            .as_ref()
+            .and_then(|subdivisions| subdivisions.first());
+
+        Some(Geo {
+            country: city
+                .country
+                .as_ref()
+                .and_then(|c| c.iso_code)
+                .map(String::from),
+            country_name: city.country.as_ref().and_then(|c| english(&c.names)),
+            region: region.and_then(|r| r.iso_code).map(String::from),
+            region_name: region.and_then(|r| english(&r.names)),
+            city: city.city.as_ref().and_then(|c| english(&c.names)),
+            continent: city
+                .continent
+                .as_ref()
+                .and_then(|c| c.code)
+                .map(String::from),
+            time_zone: city
+                .location
+                .as_ref()
+                .and_then(|l| l.time_zone)
+                .map(String::from),
+            latitude: city.location.as_ref().and_then(|l| l.latitude),
+            longitude: city.location.as_ref().and_then(|l| l.longitude),
+            in_european_union: city
+                .country
+                .as_ref()
+                .and_then(|c| c.is_in_european_union)
+                .unwrap_or(false),
+        })
+    }
+}
+
+fn english(names: &Option<BTreeMap<&str, &str>>) -> Option<String> {
+    names
+        .as_ref()
+        .and_then(|names| names.get("en"))
+        .map(|name| name.to_string())
+}
+
+#[async_trait]
+impl Middleware for GeoIp {
+    async fn handle_request(&self, request: Request) -> Result<Outcome, Error> {
+        match self.lookup(request.client_ip()) {
+            Some(geo) => Ok(Outcome::Forward(request.with_geo(geo))),
+            None => Ok(Outcome::Forward(request)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_missing_database() {
+        assert!(GeoIp::open("/does/not/exist.mmdb").is_err());
+    }
+}
@@ -0,0 +1,83 @@
+//! Count requests and bandwidth used by each account, for [usage metering](crate::analytics::usage).
+//!
+//! Requests are counted under the `requests` metric, and bytes received and sent under `bandwidth`.
+//! By default, usage is counted per logged in user. Requests from anonymous clients are not counted.
+//!
+//! ### Usage
+//!
+//! ```
+//! use rwf::controller::middleware::{Metering, Middleware};
+//!
+//! // Count usage per API key.
+//! let metering = Metering::new()
+//!     .by_key(|request| request.header("x-api-key").cloned())
+//!     .middleware();
+//! ```
+use std::sync::Arc;
+
+use super::prelude::*;
+use crate::analytics::usage;
+
+type Extractor = Arc<dyn Fn(&Request) -> Option<String> + Send + Sync>;
+
+/// Usage metering middleware.
+#[derive(Clone)]
+pub struct Metering {
+    account: Option<Extractor>,
+}
+
+impl Default for Metering {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metering {
+    /// Count usage per logged in user.
+    pub fn new() -> Self {
+        Self { account: None }
+    }
+
+    /// Count usage per account extracted from the request, e.g. a tenant or an API key.
+    /// Requests without an account are not counted.
+    pub fn by_key(
+        mut self,
+        extractor: impl Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.account = Some(Arc::new(extractor));
+        self
+    }
+
+    fn account(&self, request: &Request) -> Option<String> {
+        match self.account {
+            Some(ref extractor) => extractor(request),
+            None => request.user_id().ok().map(|id| id.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware for Metering {
+    async fn handle_request(&self, request: Request) -> Result<Outcome, Error> {
+        Ok(Outcome::Forward(request))
+    }
+
+    async fn handle_response(
+        &self,
+        request: &Request,
+        response: Response,
+    ) -> Result<Response, Error> {
+        if let Some(account) = self.account(request) {
+            let sent = response
+                .headers()
+                .get("content-length")
+                .and_then(|length| length.parse::<i64>().ok())
+                .unwrap_or(0);
+
+            usage::record(&account, "requests", 1);
+            usage::record(&account, "bandwidth", request.body().len() as i64 + sent);
+        }
+
+        Ok(response)
+    }
+}
@@ -26,7 +26,18 @@ pub mod prelude;
 pub mod secure_id;
 pub use secure_id::SecureId;
 
+pub mod cors;
+pub use cors::Cors;
+
+pub mod chaos;
+pub use chaos::Chaos;
+
+pub mod metering;
+pub use metering::Metering;
+
 pub mod csrf;
+#[cfg(feature = "geoip")]
+pub mod geoip;
 pub mod request_tracker;
 
 /// The result of middleware processing a request.
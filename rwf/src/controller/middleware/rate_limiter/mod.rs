@@ -0,0 +1,245 @@
+//! Limit how many requests our clients can perform per unit of time.
+//!
+//! Clients that exceed those limits will have their requests rejected with HTTP `429 - Too Many`,
+//! and the `Retry-After` header set to the number of seconds they should wait before trying again.
+//! Requests are counted with a sliding window by default, or with a token bucket, which allows
+//! short bursts.
+//!
+//! Clients are bucketed per IP. The rate limiter supports proxies, so if the request was forwarded by a
+//! trusted proxy, the client IP in the `Forwarded` or `X-Forwarded-For` header is used instead.
+//! Clients can also be bucketed by session, or by any other key extracted from the request, e.g. an API key.
+//!
+//! Counters are kept in memory, unless another [`Store`] is configured, e.g. [`PostgresStore`]
+//! to share limits between servers.
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tracing::warn;
+
+use super::{
+    super::{Error, Request, Response},
+    Middleware, MiddlewareHandler, Outcome,
+};
+use crate::cluster;
+
+pub mod store;
+pub use store::{Algorithm, Decision, MemoryStore, Policy, PostgresStore, Store};
+
+type Extractor = Arc<dyn Fn(&Request) -> Option<String> + Send + Sync>;
+
+enum Key {
+    Ip,
+    Session,
+    Custom(Extractor),
+}
+
+/// Rate limiter.
+pub struct RateLimiter {
+    policy: Policy,
+    key: Key,
+    prefix: String,
+    store: Box<dyn Store>,
+}
+
+impl RateLimiter {
+    /// Create rate limiter allowing this many requests per period.
+    pub fn new(limit: u64, period: Duration) -> Self {
+        Self {
+            policy: Policy {
+                algorithm: Algorithm::default(),
+                limit,
+                period,
+            },
+            key: Key::Ip,
+            prefix: "rwf".into(),
+            store: Box::new(MemoryStore::new()),
+        }
+    }
+
+    /// Create rate limiter with this limit of requests per second.
+    pub fn per_second(limit: u64) -> Self {
+        Self::new(limit, Duration::from_secs(1))
+    }
+
+    /// Create rate limiter with this limit of requests per minute.
+    pub fn per_minute(limit: u64) -> Self {
+        Self::new(limit, Duration::from_secs(60))
+    }
+
+    /// Create rate limiter with this limit of requests per hour.
+    pub fn per_hour(limit: u64) -> Self {
+        Self::new(limit, Duration::from_secs(3600))
+    }
+
+    /// Create rate limiter with this limit of requests per day.
+    pub fn per_day(limit: u64) -> Self {
+        Self::new(limit, Duration::from_secs(3600 * 24))
+    }
+
+    /// Count requests with a token bucket. Clients can spend all their requests at once,
+    /// and get them back gradually over the period.
+    pub fn token_bucket(mut self) -> Self {
+        self.policy.algorithm = Algorithm::TokenBucket;
+        self
+    }
+
+    /// Count requests with a sliding window. This is the default.
+    pub fn sliding_window(mut self) -> Self {
+        self.policy.algorithm = Algorithm::SlidingWindow;
+        self
+    }
+
+    /// Limit requests per client IP. This is the default.
+    pub fn by_ip(mut self) -> Self {
+        self.key = Key::Ip;
+        self
+    }
+
+    /// Limit requests per session, i.e. per user if they are logged in, or per browser otherwise.
+    pub fn by_session(mut self) -> Self {
+        self.key = Key::Session;
+        self
+    }
+
+    /// Limit requests per key extracted from the request, e.g. an API key.
+    /// Requests without a key are not limited.
+    pub fn by_key(
+        mut self,
+        extractor: impl Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.key = Key::Custom(Arc::new(extractor));
+        self
+    }
+
+    /// Prefix added to keys, so rate limiters sharing a store count requests separately.
+    pub fn prefix(mut self, prefix: impl ToString) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// Where counters are kept. Default: in memory.
+    pub fn store(mut self, store: impl Store + 'static) -> Self {
+        self.store = Box::new(store);
+        self
+    }
+
+    fn key(&self, request: &Request) -> Option<String> {
+        let key = match self.key {
+            Key::Ip => format!("ip:{}", request.client_ip()),
+            Key::Session => format!("session:{}", request.session_id()),
+            Key::Custom(ref extractor) => format!("key:{}", extractor(request)?),
+        };
+
+        Some(format!("{}:{}", self.prefix, key))
+    }
+}
+
+#[async_trait]
+impl Middleware for RateLimiter {
+    fn middleware(self) -> MiddlewareHandler {
+        cluster::register(
+            format!("rate limiter \"{}\"", self.prefix),
+            self.store.name(),
+            self.store.shared(),
+        );
+
+        MiddlewareHandler::new(self)
+    }
+
+    async fn handle_request(&self, request: Request) -> Result<Outcome, Error> {
+        let key = match self.key(&request) {
+            Some(key) => key,
+            None => return Ok(Outcome::Forward(request)),
+        };
+
+        // Don't take the site down if the store is unavailable.
+        let decision = match self.store.hit(&key, &self.policy).await {
+            Ok(decision) => decision,
+            Err(err) => {
+                warn!("rate limiter store error, request allowed: {}", err);
+                return Ok(Outcome::Forward(request));
+            }
+        };
+
+        if decision.allowed {
+            Ok(Outcome::Forward(request))
+        } else {
+            let retry_after = decision.retry_after.as_secs_f64().ceil().max(1.) as u64;
+            let response = Response::too_many()
+                .header("retry-after", retry_after)
+                .header("x-ratelimit-limit", self.policy.limit)
+                .header("x-ratelimit-remaining", decision.remaining);
+
+            Ok(Outcome::Stop(request, response))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::test::dummy_ip;
+
+    async fn request(headers: &[(&str, &str)]) -> Request {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}\r\n", name, value))
+            .collect::<String>();
+        let request = format!("GET /api/users HTTP/1.1\r\n{}\r\n", headers);
+        Request::read(dummy_ip(), request.as_bytes()).await.unwrap()
+    }
+
+    async fn allowed(limiter: &RateLimiter, headers: &[(&str, &str)]) -> bool {
+        match limiter
+            .handle_request(request(headers).await)
+            .await
+            .unwrap()
+        {
+            Outcome::Forward(_) => true,
+            Outcome::Stop(_, response) => {
+                assert_eq!(response.status().code(), 429);
+                assert!(response.headers().get("retry-after").is_some());
+                false
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter() {
+        let limiter = RateLimiter::per_hour(2);
+        assert!(allowed(&limiter, &[]).await);
+        assert!(allowed(&limiter, &[]).await);
+        assert!(!allowed(&limiter, &[]).await);
+
+        // Clients behind a proxy are limited separately.
+        let proxied = [("x-forwarded-for", "10.0.0.1, 10.0.0.2")];
+        assert!(allowed(&limiter, &proxied).await);
+        assert!(allowed(&limiter, &proxied).await);
+        assert!(!allowed(&limiter, &proxied).await);
+
+        match limiter.handle_request(request(&[]).await).await.unwrap() {
+            Outcome::Stop(_, response) => {
+                let retry_after = response.headers().get("retry-after").unwrap();
+                assert!(retry_after.parse::<u64>().unwrap() > 1800);
+                assert_eq!(response.headers().get("x-ratelimit-limit").unwrap(), "2");
+            }
+            Outcome::Forward(_) => panic!("request should be limited"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_custom_key() {
+        let limiter = RateLimiter::per_minute(1)
+            .token_bucket()
+            .by_key(|request| request.header("x-api-key").cloned());
+
+        assert!(allowed(&limiter, &[("x-api-key", "a")]).await);
+        assert!(!allowed(&limiter, &[("x-api-key", "a")]).await);
+        assert!(allowed(&limiter, &[("x-api-key", "b")]).await);
+
+        // No key, no limit.
+        assert!(allowed(&limiter, &[]).await);
+        assert!(allowed(&limiter, &[]).await);
+    }
+}
@@ -0,0 +1,380 @@
+//! Where the rate limiter keeps its counters.
+//!
+//! By default, counters are kept in memory, so each server enforces its own limits.
+//! To share limits between servers, use [`PostgresStore`], or implement [`Store`]
+//! for another backend, e.g. Redis.
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+
+use super::super::Error;
+use crate::model::{Error as ModelError, Pool};
+
+/// Rate limiting algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Algorithm {
+    /// Clients get `limit` tokens, refilled continuously over the period. Each request
+    /// spends one token. Allows short bursts, up to the limit.
+    TokenBucket,
+    /// Requests are counted in fixed windows, and the count of the previous window is
+    /// weighted by how much of it overlaps the sliding window. Smooths out bursts
+    /// at window boundaries.
+    #[default]
+    SlidingWindow,
+}
+
+/// How many requests are allowed per period, and how they are counted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Policy {
+    /// Rate limiting algorithm.
+    pub algorithm: Algorithm,
+    /// Number of requests allowed per period.
+    pub limit: u64,
+    /// The period.
+    pub period: Duration,
+}
+
+/// State of the rate limiter for one client.
+///
+/// For the token bucket, `value` is the number of tokens left and `started_at` is
+/// when they were last refilled. For the sliding window, `value` and `previous` are the
+/// number of requests in the current and previous windows, and `started_at` is when
+/// the current window started.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bucket {
+    /// Tokens left, or requests in the current window.
+    pub value: f64,
+    /// Requests in the previous window.
+    pub previous: f64,
+    /// When the bucket was last updated, in seconds since the Unix epoch.
+    pub started_at: f64,
+}
+
+/// Whether a request is allowed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decision {
+    /// The request is allowed.
+    pub allowed: bool,
+    /// How many more requests the client can make right now.
+    pub remaining: u64,
+    /// How long the client should wait before trying again.
+    pub retry_after: Duration,
+}
+
+impl Policy {
+    /// Bucket of a client seen for the first time.
+    pub fn bucket(&self, now: f64) -> Bucket {
+        match self.algorithm {
+            Algorithm::TokenBucket => Bucket {
+                value: self.limit as f64,
+                previous: 0.,
+                started_at: now,
+            },
+            Algorithm::SlidingWindow => Bucket {
+                value: 0.,
+                previous: 0.,
+                started_at: now,
+            },
+        }
+    }
+
+    /// Count a request made at `now` and decide whether it's allowed.
+    pub fn hit(&self, bucket: &mut Bucket, now: f64) -> Decision {
+        let limit = self.limit as f64;
+        let period = self.period.as_secs_f64().max(f64::EPSILON);
+
+        match self.algorithm {
+            Algorithm::TokenBucket => {
+                let rate = limit / period;
+                let elapsed = (now - bucket.started_at).max(0.);
+                bucket.value = (bucket.value + elapsed * rate).min(limit);
+                bucket.started_at = now;
+
+                if bucket.value >= 1. {
+                    bucket.value -= 1.;
+                    Decision::allow(bucket.value)
+                } else {
+                    Decision::deny((1. - bucket.value) / rate)
+                }
+            }
+
+            Algorithm::SlidingWindow => {
+                if now >= bucket.started_at + period {
+                    let windows = ((now - bucket.started_at) / period).floor();
+                    bucket.previous = if windows == 1. { bucket.value } else { 0. };
+                    bucket.value = 0.;
+                    bucket.started_at += windows * period;
+                }
+
+                let weight = 1. - (now - bucket.started_at) / period;
+                let count = bucket.previous * weight + bucket.value;
+
+                if count + 1. <= limit {
+                    bucket.value += 1.;
+                    Decision::allow(limit - count - 1.)
+                } else if bucket.value + 1. <= limit && bucket.previous > 0. {
+                    // Wait until enough of the previous window slides out.
+                    let weight = (limit - 1. - bucket.value) / bucket.previous;
+                    Decision::deny(bucket.started_at + period * (1. - weight) - now)
+                } else {
+                    Decision::deny(bucket.started_at + period - now)
+                }
+            }
+        }
+    }
+}
+
+impl Decision {
+    fn allow(remaining: f64) -> Self {
+        Self {
+            allowed: true,
+            remaining: remaining.max(0.).floor() as u64,
+            retry_after: Duration::ZERO,
+        }
+    }
+
+    fn deny(retry_after: f64) -> Self {
+        Self {
+            allowed: false,
+            remaining: 0,
+            retry_after: Duration::from_secs_f64(retry_after.max(0.)),
+        }
+    }
+}
+
+/// Current time, in seconds since the Unix epoch.
+pub(super) fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// Storage for rate limiter counters.
+#[async_trait]
+pub trait Store: Send + Sync {
+    /// Count a request made by the client identified by `key` and
+    /// decide whether it's allowed.
+    async fn hit(&self, key: &str, policy: &Policy) -> Result<Decision, Error>;
+
+    /// Name of the backend, e.g. `redis`.
+    fn name(&self) -> &'static str {
+        "custom"
+    }
+
+    /// Counters are shared between all servers. Required in [cluster mode](crate::cluster).
+    fn shared(&self) -> bool {
+        false
+    }
+}
+
+/// Counters kept in memory. Each server enforces its own limits.
+#[derive(Default)]
+pub struct MemoryStore {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    buckets: HashMap<String, Bucket>,
+    pruned_at: f64,
+}
+
+impl MemoryStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn hit(&self, key: &str, policy: &Policy) -> Result<Decision, Error> {
+        // Get current time before locking mutex.
+        let now = now();
+        let period = policy.period.as_secs_f64();
+        let mut state = self.state.lock();
+
+        // Forget clients that haven't been seen for two periods, since their
+        // buckets are as good as new, so memory doesn't grow forever.
+        if now - state.pruned_at >= period * 2. {
+            state
+                .buckets
+                .retain(|_, bucket| now - bucket.started_at < period * 2.);
+            state.pruned_at = now;
+        }
+
+        let bucket = state
+            .buckets
+            .entry(key.to_string())
+            .or_insert_with(|| policy.bucket(now));
+
+        Ok(policy.hit(bucket, now))
+    }
+}
+
+/// Counters kept in the `rwf_rate_limits` table, shared between all servers
+/// using the same database. Each request runs a short transaction.
+#[derive(Default)]
+pub struct PostgresStore;
+
+impl PostgresStore {
+    /// Create a store using the default connection pool.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl Store for PostgresStore {
+    async fn hit(&self, key: &str, policy: &Policy) -> Result<Decision, Error> {
+        Ok(self.update(key, policy).await?)
+    }
+
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn shared(&self) -> bool {
+        true
+    }
+}
+
+impl PostgresStore {
+    async fn update(&self, key: &str, policy: &Policy) -> Result<Decision, ModelError> {
+        let now = now();
+        let new = policy.bucket(now);
+        let conn = Pool::begin().await?;
+
+        conn.client()
+            .execute(
+                "INSERT INTO rwf_rate_limits (key, value, previous, started_at)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (key) DO NOTHING",
+                &[&key, &new.value, &new.previous, &new.started_at],
+            )
+            .await?;
+
+        let row = conn
+            .client()
+            .query_one(
+                "SELECT value, previous, started_at FROM rwf_rate_limits WHERE key = $1 FOR UPDATE",
+                &[&key],
+            )
+            .await?;
+
+        let mut bucket = Bucket {
+            value: row.get(0),
+            previous: row.get(1),
+            started_at: row.get(2),
+        };
+        let decision = policy.hit(&mut bucket, now);
+
+        conn.client()
+            .execute(
+                "UPDATE rwf_rate_limits SET value = $2, previous = $3, started_at = $4 WHERE key = $1",
+                &[&key, &bucket.value, &bucket.previous, &bucket.started_at],
+            )
+            .await?;
+        conn.commit().await?;
+
+        Ok(decision)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::test::bootstrap;
+
+    #[test]
+    fn test_token_bucket() {
+        let policy = Policy {
+            algorithm: Algorithm::TokenBucket,
+            limit: 3,
+            period: Duration::from_secs(3),
+        };
+        let mut bucket = policy.bucket(100.);
+
+        // Bursts up to the limit are allowed.
+        for remaining in [2, 1, 0] {
+            let decision = policy.hit(&mut bucket, 100.);
+            assert!(decision.allowed);
+            assert_eq!(decision.remaining, remaining);
+        }
+
+        let decision = policy.hit(&mut bucket, 100.5);
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after, Duration::from_millis(500));
+
+        // One token per second is refilled.
+        assert!(policy.hit(&mut bucket, 101.).allowed);
+        assert!(!policy.hit(&mut bucket, 101.).allowed);
+
+        // Up to the limit.
+        let decision = policy.hit(&mut bucket, 200.);
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 2);
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let policy = Policy {
+            algorithm: Algorithm::SlidingWindow,
+            limit: 4,
+            period: Duration::from_secs(10),
+        };
+        let mut bucket = policy.bucket(0.);
+
+        for _ in 0..4 {
+            assert!(policy.hit(&mut bucket, 5.).allowed);
+        }
+        let decision = policy.hit(&mut bucket, 9.);
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after, Duration::from_secs(1));
+
+        // Half of the previous window still counts.
+        let decision = policy.hit(&mut bucket, 15.);
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 1);
+        assert!(policy.hit(&mut bucket, 15.).allowed);
+        let decision = policy.hit(&mut bucket, 15.);
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after, Duration::from_millis(2500));
+
+        // Windows without requests reset the count.
+        let decision = policy.hit(&mut bucket, 100.);
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 3);
+    }
+
+    #[tokio::test]
+    async fn test_postgres_store() -> Result<(), ModelError> {
+        let conn = Pool::connection().await?;
+        bootstrap(conn.client()).await?;
+
+        let policy = Policy {
+            algorithm: Algorithm::TokenBucket,
+            limit: 2,
+            period: Duration::from_secs(3600),
+        };
+        let key = format!("test:{}", now());
+        let store = PostgresStore::new();
+
+        assert!(store.update(&key, &policy).await?.allowed);
+        assert!(store.update(&key, &policy).await?.allowed);
+        assert!(!store.update(&key, &policy).await?.allowed);
+
+        conn.client()
+            .execute("DELETE FROM rwf_rate_limits WHERE key = $1", &[&key])
+            .await?;
+
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
 //! Record HTTP requests served by the application.
 //!
-//! Requests record metadata like client IP, request duration, path, query, and HTTP method.
+//! Requests record metadata like client IP, request duration, path, route, query, and HTTP method.
+//! Sensitive query parameters, e.g. passwords and tokens, are filtered out using the `filter_parameters` setting.
+//! Busy applications can record a fraction of requests with the `request_sample_ratio` setting.
+//! Requests which fail with a server error are always recorded, along with the error and its backtrace.
 //! Each client is given a cookie which uniquely identifies that browser. This allows to record unique sessions.
 //!
 //! You can view requests in real time in the [admin panel](https://github.com/levkk/rwf/tree/main/rwf-admin), or by querying the `rwf_requests` table, e.g.:
@@ -10,11 +13,13 @@
 //! WHERE created_at > NOW() - INTERVAL '5 minutes';
 //! ```
 use base64::{engine::general_purpose, Engine as _};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use time::{Duration, OffsetDateTime};
 use uuid::Uuid;
 
 use crate::analytics::Request as AnalyticsRequest;
+use crate::config::get_config;
 use crate::controller::middleware::prelude::*;
 use crate::http::CookieBuilder;
 use crate::model::{Model, Pool, ToValue};
@@ -77,6 +82,13 @@ impl RequestTracker {
     pub fn new() -> Self {
         Self {}
     }
+
+    /// Should the request be recorded. Server errors are always recorded.
+    fn sampled(code: i32) -> bool {
+        let ratio = get_config().general.request_sample_ratio;
+
+        code >= 500 || ratio >= 1.0 || rand::thread_rng().gen_bool(ratio.max(0.0))
+    }
 }
 
 #[crate::async_trait]
@@ -92,11 +104,11 @@ impl Middleware for RequestTracker {
     ) -> Result<Response, Error> {
         let method = request.method().to_string();
         let path = request.path().path().to_string();
-        let query = request.path().query().to_json();
+        let query = request.path().query().scrub().to_json();
         let code = response.status().code() as i32;
         let duration =
             ((OffsetDateTime::now_utc() - request.received_at()).as_seconds_f64() * 1000.0) as f32;
-        let client = request.peer().ip();
+        let client = request.client_ip();
 
         let (create, cookie) = match request
             .cookies()
@@ -117,16 +129,27 @@ impl Middleware for RequestTracker {
             response = response.cookie(cookie);
         }
 
+        if !Self::sampled(code) {
+            return Ok(response);
+        }
+
+        let route = request.route().map(|route| route.to_string());
+        let error = response.error().map(|error| error.message.clone());
+        let backtrace = response.error().and_then(|error| error.backtrace.clone());
+
         if let Ok(mut conn) = Pool::connection().await {
             if let Some(client_id) = cookie.uuid() {
                 let _ = AnalyticsRequest::create(&[
                     ("method", method.to_value()),
                     ("path", path.to_value()),
+                    ("route", route.to_value()),
                     ("query", query.to_value()),
                     ("client_ip", client.to_value()),
                     ("client_id", client_id.to_value()),
                     ("code", code.to_value()),
                     ("duration", duration.to_value()),
+                    ("error", error.to_value()),
+                    ("backtrace", backtrace.to_value()),
                 ])
                 .execute(&mut conn)
                 .await;
@@ -29,9 +29,18 @@ use async_trait::async_trait;
 pub mod auth;
 pub mod engine;
 pub mod error;
+pub mod health;
+pub mod info;
+pub mod messages;
 pub mod middleware;
+pub mod oauth;
+pub mod resource;
+pub mod rpc;
 pub mod ser;
+pub mod session_store;
+pub mod sse;
 pub mod static_files;
+pub mod token_auth;
 pub mod turbo_stream;
 
 #[cfg(feature = "wsgi")]
@@ -47,17 +56,33 @@ pub use rack::RackController;
 pub use auth::{AllowAll, AuthHandler, Authentication, BasicAuth, DenyAll, Session, SessionId};
 pub use engine::Engine;
 pub use error::Error;
+pub use health::{HealthCheck, HealthController};
+pub use info::{Info, InfoController};
+pub use messages::{MessageController, MessageHandler};
 pub use middleware::{Middleware, MiddlewareHandler, MiddlewareSet, Outcome, RateLimiter};
+pub use oauth::{OAuthController, OAuthHandler};
+pub use resource::Resource;
+pub use rpc::RpcController;
+pub use session_store::SessionStore;
+pub use sse::SseController;
 pub use static_files::{CacheControl, StaticFiles};
+pub use token_auth::TokenAuth;
 pub use turbo_stream::TurboStream;
 
 use super::http::{
+    openapi::{Operation, Schema},
+    pagination, problem,
     websocket::{self, DataFrame},
-    Handler, Method, Request, Response, Stream, ToParameter,
+    Handler, Method, Pagination, Problem, Request, Response, Stream, ToParameter,
 };
-use super::model::{get_connection, Insert, Model, Query, ToValue, Update, Value};
+use super::model::{
+    get_connection,
+    sluggable::{integer_id, Found},
+    Insert, Model, Query, Value,
+};
+use crate::auth::{fields, Roles};
 use crate::colors::MaybeColorize;
-use crate::comms::Comms;
+use crate::comms::{self, Comms};
 use crate::config::get_config;
 
 use tokio::select;
@@ -213,11 +238,14 @@ pub trait Controller: Sync + Send {
     async fn handle_internal(&self, request: Request) -> Result<Response, Error> {
         let auth = self.auth();
 
-        if !auth.auth().authorize(&request).await? {
+        let (request, allowed) = auth.auth().authenticate(request).await?;
+        if !allowed {
             return auth.auth().denied(&request).await;
         }
 
-        let request = request.set_skip_csrf(self.skip_csrf());
+        // Requests authenticated with a token don't rely on cookies, so they can't be forged.
+        let skip_csrf = self.skip_csrf() || request.token_claims().is_some();
+        let request = request.set_skip_csrf(skip_csrf);
 
         // Run the middleware chain (forward).
         let outcome = self.middleware().handle_request(request).await?;
@@ -233,10 +261,20 @@ pub trait Controller: Sync + Send {
                     error!("{:?}", err);
 
                     let response = match err {
+                        err if problem::api_request(&request) => {
+                            Problem::from_error(&err).response(&request)
+                        }
+
                         Error::HttpError(err) => match err.code() {
                             400 => Response::bad_request(),
                             401 => Response::unauthorized(None),
+                            403 => Response::forbidden(),
                             413 => Response::content_too_large(),
+                            422 => Response::error_pretty(
+                                "422 - Unprocessable Entity",
+                                &err.to_string(),
+                            )
+                            .code(422),
                             _ => Response::internal_error(err),
                         },
 
@@ -273,6 +311,23 @@ pub trait Controller: Sync + Send {
     /// ```
     async fn handle(&self, request: &Request) -> Result<Response, Error>;
 
+    /// Requests handled by this controller, documented in the app's [OpenAPI specification](crate::http::openapi).
+    /// By default, the controller isn't documented.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// fn openapi(&self) -> Vec<Operation> {
+    ///     vec![Operation::get()
+    ///         .summary("List users")
+    ///         .extract::<Query<Search>>()
+    ///         .response::<Vec<User>>(200, "Users")]
+    /// }
+    /// ```
+    fn openapi(&self) -> Vec<Operation> {
+        vec![]
+    }
+
     /// The name of this controller. Used for logging. All names are globally unique, so
     /// you won't need to override this method.
     fn controller_name(&self) -> &'static str {
@@ -529,13 +584,31 @@ pub trait ModelController: Controller {
     /// Handle the request to this controller.
     async fn handle(&self, request: &Request) -> Result<Response, Error> {
         let method = request.method();
-        let parameter = request.parameter::<i64>("id");
+        let parameter = match request.parameter::<i64>("id") {
+            Err(_) => match request.parameter::<String>("id")? {
+                Some(slug) => match self.find_by_slug(&slug).await? {
+                    Some(Found::Moved(_, current)) if method == &Method::Get => {
+                        let path = request.path().path();
+                        let base = path.rsplit_once('/').map(|(base, _)| base).unwrap_or("");
+                        let location = format!("{}/{}", base, current);
+                        return Ok(Response::new().redirect(location).code(301));
+                    }
+                    Some(found) => match integer_id(&found.record().id()) {
+                        Some(id) => Ok(Some(id)),
+                        None => return Ok(Response::not_found()),
+                    },
+                    None => return Ok(Response::not_found()),
+                },
+                None => Ok(None),
+            },
+            parameter => parameter,
+        };
 
         match parameter {
             Ok(Some(id)) => match method {
                 Method::Get => ModelController::get(self, request, &id).await,
                 Method::Put => ModelController::update(self, request, &id).await,
-                Method::Delete => return Ok(Response::not_found()),
+                Method::Delete => ModelController::delete(self, request, &id).await,
                 Method::Patch => ModelController::patch(self, request, &id).await,
                 _ => Ok(Response::method_not_allowed()),
             },
@@ -550,6 +623,123 @@ pub trait ModelController: Controller {
         }
     }
 
+    /// Find the record identified by a slug instead of its primary key, e.g. `/posts/hello-world`.
+    /// By default, only primary keys are accepted. Controllers of [`Sluggable`](crate::model::Sluggable) models can use
+    /// [`Sluggable::find_by_slug_or_id`](crate::model::Sluggable::find_by_slug_or_id); records found by an old slug are redirected to the current one.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// async fn find_by_slug(&self, slug: &str) -> Result<Option<Found<Post>>, Error> {
+    ///     let mut conn = get_connection().await?;
+    ///     Ok(Post::find_by_slug_or_id(slug, &mut conn).await?)
+    /// }
+    /// ```
+    async fn find_by_slug(&self, _slug: &str) -> Result<Option<Found<Self::Model>>, Error> {
+        Err(crate::http::Error::InvalidParameter("id".into()).into())
+    }
+
+    /// The user making the request. Their roles decide which fields of the model they can see
+    /// and change, see [`crate::auth::fields`]. By default, requests are anonymous, so fields
+    /// restricted to users or roles are hidden and can't be changed.
+    ///
+    /// Only called if the model has field rules.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// async fn user(&self, request: &Request) -> Result<Option<Box<dyn Roles + Send + Sync>>, Error> {
+    ///     let mut conn = get_connection().await?;
+    ///     let user = request.user::<User>(&mut conn).await?;
+    ///     Ok(user.map(|user| Box::new(user) as _))
+    /// }
+    /// ```
+    async fn user(
+        &self,
+        _request: &Request,
+    ) -> Result<Option<Box<dyn Roles + Send + Sync>>, Error> {
+        Ok(None)
+    }
+
+    /// Columns clients can set when creating and updating records, also known as strong parameters.
+    /// Other fields sent by the client are ignored. By default, all columns can be set, except the ones
+    /// the user can't change because of the model's field rules.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// fn permitted_columns(&self) -> Option<&[&str]> {
+    ///     Some(&["name", "email"])
+    /// }
+    /// ```
+    fn permitted_columns(&self) -> Option<&[&str]> {
+        None
+    }
+
+    /// Columns records can be sorted by with the `sort` parameter, e.g. `?sort=-created_at,name`.
+    /// Columns prefixed with `-` are sorted in descending order. The primary key is always sortable.
+    /// By default, all columns are sortable.
+    fn sortable_columns(&self) -> &[&str] {
+        Self::Model::column_names()
+    }
+
+    /// JSON Schema of a record, used to document the controller in the [OpenAPI specification](crate::http::openapi).
+    /// By default, lists the model's columns without their types. Return `Self::Model::schema()` if
+    /// the model implements [`Schema`].
+    fn record_schema(&self) -> serde_json::Value {
+        model_schema::<Self::Model>()
+    }
+
+    /// The 6 REST requests handled by this controller, documented in the [OpenAPI specification](crate::http::openapi).
+    fn operations(&self) -> Vec<Operation> {
+        let table = Self::Model::table_name();
+        let record = self.record_schema();
+        let records = serde_json::json!({ "type": "array", "items": record });
+
+        vec![
+            Operation::get()
+                .summary(format!("List {}", table))
+                .tag(table)
+                .parameter::<Option<i64>>("page", "query")
+                .parameter::<Option<i64>>("page_size", "query")
+                .parameter::<Option<String>>("sort", "query")
+                .response_schema(200, "Records", records)
+                .status(400, "Invalid sort column"),
+            Operation::get()
+                .path(":id")
+                .summary(format!("Fetch a record from {}", table))
+                .tag(table)
+                .response_schema(200, "The record", record.clone())
+                .status(404, "Not found"),
+            Operation::post()
+                .summary(format!("Create a record in {}", table))
+                .tag(table)
+                .body_schema("application/json", record.clone())
+                .response_schema(201, "The new record", record.clone()),
+            Operation::put()
+                .path(":id")
+                .summary(format!("Replace a record in {}", table))
+                .tag(table)
+                .body_schema("application/json", record.clone())
+                .response_schema(200, "The updated record", record.clone())
+                .status(404, "Not found"),
+            Operation::patch()
+                .path(":id")
+                .summary(format!("Update a record in {}", table))
+                .description("The body is a JSON Merge Patch.")
+                .tag(table)
+                .body_schema("application/merge-patch+json", record.clone())
+                .response_schema(200, "The updated record", record)
+                .status(404, "Not found"),
+            Operation::delete()
+                .path(":id")
+                .summary(format!("Delete a record from {}", table))
+                .tag(table)
+                .status(204, "Deleted")
+                .status(404, "Not found"),
+        ]
+    }
+
     /// Returns the controller route handler. Used when mapping this
     /// controller to a path in the server.
     ///
@@ -566,40 +756,73 @@ pub trait ModelController: Controller {
     }
 
     /// List all records for the model. Supports pagination with `page` parameter. Supports number of records per page with `page_size` parameter.
+    /// Pages can also be requested with the `Range` header, see [`Pagination`]. Records are sorted with the `sort` parameter,
+    /// see [`ModelController::sortable_columns`].
     ///
     /// # Example
     ///
     /// ```text,ignore
-    /// GET /users?page=3&page_size=40
+    /// GET /users?page=3&page_size=40&sort=-created_at
     /// ```
     async fn list(&self, request: &Request) -> Result<Response, Error> {
+        let user = field_user(self, request).await?;
+        let pagination = Pagination::new(request, pagination::DEFAULT_PAGE_SIZE);
+
+        let mut query = Self::Model::all();
+        if let Some(sort) = request.query().get::<String>("sort") {
+            for column in sort.split(',').map(|column| column.trim()) {
+                let (column, direction) = match column.strip_prefix('-') {
+                    Some(column) => (column, "DESC"),
+                    None => (column, "ASC"),
+                };
+
+                if column != Self::Model::primary_key()
+                    && !self.sortable_columns().contains(&column)
+                {
+                    return Err(crate::http::Error::InvalidParameter("sort".into()).into());
+                }
+
+                query = query.order((column, direction));
+            }
+        }
+
         let mut conn = get_connection().await?;
-        let page_size = request.query().get::<i64>("page_size").unwrap_or(25);
-        let page = request.query().get::<i64>("page").unwrap_or(1);
-        let offset = (std::cmp::max(1, page) - 1) * page_size;
 
-        let models = Self::Model::all()
-            .limit(page_size)
-            .offset(offset)
+        // Range requests include the size of the collection.
+        let total = if pagination.range() {
+            Some(Self::Model::all().count(&mut conn).await?)
+        } else {
+            None
+        };
+
+        let models = query
+            .limit(pagination.limit())
+            .offset(pagination.offset())
             .fetch_all(&mut conn)
             .await?;
+        let count = models.len();
+        let models = models
+            .iter()
+            .map(|model| fields::to_json(model, roles(&user)))
+            .collect::<Result<Vec<_>, _>>()?;
         let response = match Response::new().json(models) {
             Ok(response) => response,
             Err(err) => Response::internal_error(err),
         };
 
-        Ok(response)
+        Ok(pagination.response(response, count, total))
     }
 
     /// Fetch a model record identified by its primary key.
-    async fn get(&self, _request: &Request, id: &i64) -> Result<Response, Error> {
+    async fn get(&self, request: &Request, id: &i64) -> Result<Response, Error> {
+        let user = field_user(self, request).await?;
         let mut conn = get_connection().await?;
 
         match Self::Model::find_by(Self::Model::primary_key(), *id)
             .fetch(&mut conn)
             .await
         {
-            Ok(model) => match Response::new().json(model) {
+            Ok(model) => match Response::new().json(fields::to_json(&model, roles(&user))?) {
                 Ok(response) => Ok(response),
                 Err(err) => Ok(Response::internal_error(err)),
             },
@@ -610,7 +833,22 @@ pub trait ModelController: Controller {
 
     /// Create new model record.
     async fn create(&self, request: &Request) -> Result<Response, Error> {
-        let model = match request.json::<Self::Model>() {
+        let user = field_user(self, request).await?;
+
+        // Fields the user can't change are ignored.
+        let model = match request.json_raw() {
+            Ok(serde_json::Value::Object(mut body)) => {
+                fields::permit_json::<Self::Model>(&mut body, roles(&user));
+                if let Some(permitted) = self.permitted_columns() {
+                    body.retain(|field, _| permitted.contains(&field.as_str()));
+                }
+                serde_json::from_value::<Self::Model>(serde_json::Value::Object(body))
+            }
+            Ok(_) => return Ok(Response::bad_request()),
+            Err(err) => Err(err),
+        };
+
+        let model = match model {
             Ok(model) => model,
             Err(err) => {
                 println!("ser err: {:?}", err);
@@ -627,13 +865,48 @@ pub trait ModelController: Controller {
         .fetch(&mut conn)
         .await?;
 
-        Ok(Response::new().code(201).json(model)?)
+        Ok(Response::new()
+            .code(201)
+            .json(fields::to_json(&model, roles(&user))?)?)
     }
 
     /// Update existing model record.
     async fn update(&self, request: &Request, id: &i64) -> Result<Response, Error> {
+        let user = field_user(self, request).await?;
+
         // The REST spec requires the entire model to be sent over for a PUT.
-        let model = request.json::<Self::Model>()?;
+        let mut body = match request.json_raw()? {
+            serde_json::Value::Object(body) => body,
+            _ => return Ok(Response::bad_request()),
+        };
+
+        let mut conn = get_connection().await?;
+
+        // Fields the user can't change keep their current values.
+        let mut unwritable = fields::unwritable::<Self::Model>(roles(&user));
+        if let Some(permitted) = self.permitted_columns() {
+            unwritable.extend(
+                Self::Model::column_names()
+                    .iter()
+                    .filter(|column| !permitted.contains(column)),
+            );
+        }
+
+        if !unwritable.is_empty() {
+            let current = match Self::Model::find(*id).fetch_optional(&mut conn).await? {
+                Some(current) => serde_json::to_value(current)?,
+                None => return Ok(Response::not_found()),
+            };
+
+            for field in unwritable {
+                match current.get(field) {
+                    Some(value) => body.insert(field.to_string(), value.clone()),
+                    None => body.remove(field),
+                };
+            }
+        }
+
+        let model = serde_json::from_value::<Self::Model>(serde_json::Value::Object(body))?;
 
         // The id field is immutable, but let's do a sanity check here just to
         // be sure the client sent the right model.
@@ -641,42 +914,83 @@ pub trait ModelController: Controller {
             return Ok(Response::bad_request());
         }
 
-        let mut conn = get_connection().await?;
         let model = model.save().fetch(&mut conn).await?;
-        Ok(Response::new().json(model)?)
+        Ok(Response::new().json(fields::to_json(&model, roles(&user))?)?)
     }
 
-    /// Partially update an existing model record.
+    /// Partially update an existing model record. The request body is
+    /// a [JSON Merge Patch](https://www.rfc-editor.org/rfc/rfc7386).
     async fn patch(&self, request: &Request, id: &i64) -> Result<Response, Error> {
+        let user = field_user(self, request).await?;
         let mut conn = get_connection().await?;
-        let exists = Self::Model::find(*id).count(&mut conn).await?;
-
-        if exists == 0 {
-            return Ok(Response::not_found());
-        }
 
-        let req = match request.json_raw()?.as_object() {
-            Some(req) => req.clone(),
-            None => return Ok(Response::bad_request()),
+        let current = match Self::Model::find(*id).fetch_optional(&mut conn).await? {
+            Some(current) => current,
+            None => return Ok(Response::not_found()),
         };
 
-        let (mut columns, mut values) = (vec![], vec![]);
+        // Only update columns which changed and the user can change, ignore the rest.
+        let patch = request.merge_patch(&current)?.retain(|column| {
+            fields::writable::<Self::Model>(column, roles(&user))
+                && self
+                    .permitted_columns()
+                    .map(|permitted| permitted.contains(&column))
+                    .unwrap_or(true)
+        });
 
-        // Only accept columns we know about, ignore the rest.
-        for column in Self::Model::column_names() {
-            if let Some(value) = req.get(*column) {
-                let value = value.to_value();
-                columns.push(*column);
-                values.push(value);
-            }
+        let model = patch.update(*id).fetch(&mut conn).await?;
+
+        Ok(Response::new().json(fields::to_json(&model, roles(&user))?)?)
+    }
+
+    /// Delete a model record identified by its primary key. Responds with `204 - No Content`,
+    /// or `404 - Not Found` if the record doesn't exist.
+    async fn delete(&self, _request: &Request, id: &i64) -> Result<Response, Error> {
+        let mut conn = get_connection().await?;
+
+        let deleted = Self::Model::find_by_sql(
+            format!(
+                r#"DELETE FROM "{}" WHERE "{}" = $1 RETURNING *"#,
+                Self::Model::table_name(),
+                Self::Model::primary_key(),
+            ),
+            &[Value::Integer(*id)],
+        )
+        .fetch_optional(&mut conn)
+        .await?;
+
+        match deleted {
+            Some(_) => Ok(Response::new().code(204)),
+            None => Ok(Response::not_found()),
         }
+    }
+}
 
-        let model = Query::Update(Update::<Self::Model>::from_columns(*id, &columns, &values))
-            .fetch(&mut conn)
-            .await?;
+/// The user checked against the model's field rules. Not fetched if the model doesn't have any.
+async fn field_user<C: ModelController + ?Sized>(
+    controller: &C,
+    request: &Request,
+) -> Result<Option<Box<dyn Roles + Send + Sync>>, Error> {
+    if <C::Model as Model>::field_rules().is_empty() {
+        Ok(None)
+    } else {
+        controller.user(request).await
+    }
+}
+
+fn roles(user: &Option<Box<dyn Roles + Send + Sync>>) -> Option<&dyn Roles> {
+    user.as_deref().map(|user| user as &dyn Roles)
+}
 
-        Ok(Response::new().json(model)?)
+/// JSON Schema listing the model's columns, without their types.
+pub(crate) fn model_schema<T: Model>() -> serde_json::Value {
+    let mut properties = serde_json::Map::new();
+    properties.insert(T::primary_key().to_string(), i64::schema());
+    for column in T::column_names() {
+        properties.insert(column.to_string(), serde_json::json!({}));
     }
+
+    serde_json::json!({ "type": "object", "properties": properties })
 }
 
 /// A controller that handles WebSocket connections.
@@ -713,6 +1027,17 @@ pub trait WebsocketController: Controller {
         Ok(())
     }
 
+    /// Handle an incoming client message and reply to it. The reply is sent only to the connection
+    /// that sent the message. By default, the message is passed to [`WebsocketController::client_message`].
+    async fn client_request(
+        &self,
+        session_id: &SessionId,
+        message: websocket::Message,
+    ) -> Result<Option<websocket::Message>, Error> {
+        self.client_message(session_id, message).await?;
+        Ok(None)
+    }
+
     /// Do something when a client creates a new WebSocket connection.
     async fn client_connected(&self, session_id: &SessionId) -> Result<(), Error> {
         Ok(())
@@ -721,93 +1046,209 @@ pub trait WebsocketController: Controller {
     /// Handle the WebSocket TCP stream. Provides the WebSocket
     /// protocol implementation. You may not want to override this unless you
     /// want to change how WebSockets work in Rwf.
-    async fn handle_stream(
-        &self,
-        request: &Request,
-        mut stream: Stream<'_>,
-    ) -> Result<bool, Error> {
-        use tokio::sync::broadcast::error::RecvError;
-
-        let session_id = request.session().session_id.clone();
-
-        info!(
-            "{} {} {} connected",
-            "websocket".purple(),
-            request.path().path().purple(),
-            self.controller_name().green(),
-        );
-
-        let config = get_config();
-        let mut stream = stream.stream();
-        let mut receiver = Comms::receiver(&session_id);
-        let mut check = interval(config.websocket.ping_interval().unsigned_abs());
-        let mut lost_pings = 0_i64;
-
-        self.client_connected(&session_id).await?;
-
-        loop {
-            select! {
-                _ = check.tick() => {
-                    debug!("{} check session \"{}\"", "websocket".purple(), session_id);
-
-                    let closed = match timeout(
+    async fn handle_stream(&self, request: &Request, stream: Stream<'_>) -> Result<bool, Error> {
+        let mut connection = SessionConnection {
+            controller: self,
+            session_id: request.session().session_id.clone(),
+        };
+
+        serve_websocket(&mut connection, request, stream, self.controller_name()).await
+    }
+}
+
+/// A WebSocket connection served by [`serve_websocket`].
+#[async_trait]
+pub(crate) trait WebsocketConnection: Send {
+    /// The client's session.
+    fn session_id(&self) -> &SessionId;
+
+    /// The client connected.
+    async fn connected(&mut self) -> Result<(), Error>;
+
+    /// Handle a message sent by the client. Returns replies sent only to this connection.
+    async fn message(
+        &mut self,
+        message: websocket::Message,
+    ) -> Result<Vec<websocket::Message>, Error>;
+
+    /// Messages sent to the session with [`Comms`] were dropped because the client is too slow
+    /// to receive them. Returns `false` to close the connection.
+    fn lagged(&mut self, _skipped: u64) -> bool {
+        true
+    }
+
+    /// The connection was closed.
+    async fn disconnected(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Connection handled by a [`WebsocketController`].
+struct SessionConnection<'a, C: ?Sized> {
+    controller: &'a C,
+    session_id: SessionId,
+}
+
+#[async_trait]
+impl<C: WebsocketController + ?Sized> WebsocketConnection for SessionConnection<'_, C> {
+    fn session_id(&self) -> &SessionId {
+        &self.session_id
+    }
+
+    async fn connected(&mut self) -> Result<(), Error> {
+        self.controller.client_connected(&self.session_id).await
+    }
+
+    async fn message(
+        &mut self,
+        message: websocket::Message,
+    ) -> Result<Vec<websocket::Message>, Error> {
+        Ok(self
+            .controller
+            .client_request(&self.session_id, message)
+            .await?
+            .into_iter()
+            .collect())
+    }
+}
+
+/// Serve a WebSocket connection: check that the client is alive with pings, send it messages
+/// addressed to its session with [`Comms`], and pass messages it sends to the connection handler.
+/// The connection is closed when the client or [`Comms::disconnect_all`] asks for it.
+pub(crate) async fn serve_websocket(
+    connection: &mut (impl WebsocketConnection + ?Sized),
+    request: &Request,
+    mut stream: Stream<'_>,
+    controller_name: &str,
+) -> Result<bool, Error> {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let session_id = connection.session_id().clone();
+
+    info!(
+        "{} {} {} connected",
+        "websocket".purple(),
+        request.path().path().purple(),
+        controller_name.green(),
+    );
+
+    let config = get_config();
+    let mut stream = stream.stream();
+    let mut receiver = Comms::receiver(&session_id);
+    let mut disconnect = comms::disconnect_receiver();
+    let mut check = interval(config.websocket.ping_interval().unsigned_abs());
+    let mut lost_pings = 0_i64;
+
+    connection.connected().await?;
+
+    let result = loop {
+        select! {
+            _ = check.tick() => {
+                debug!("{} check session \"{}\"", "websocket".purple(), session_id);
+
+                let closed = !matches!(
+                    timeout(
                         config.websocket.ping_timeout().unsigned_abs(),
                         DataFrame::new_ping().flush(&mut stream)
-                    ).await {
-                        Ok(Ok(_)) => false,
-                        _ => true,
-                    };
+                    ).await,
+                    Ok(Ok(_))
+                );
 
-                    lost_pings += 1;
+                lost_pings += 1;
 
-                    if closed || lost_pings as usize > config.websocket.ping_disconnect_count {
+                if closed || lost_pings as usize > config.websocket.ping_disconnect_count {
+                    break Ok(false);
+                }
+            }
+
+            _ = disconnect.changed() => {
+                // Deliver messages sent before the disconnect, e.g. asking the client to reload.
+                while let Ok(message) = receiver.try_recv() {
+                    if message.send(&mut stream).await.is_err() {
                         break;
                     }
                 }
 
-                message = receiver.recv() => {
-                    match message {
-                        Ok(message) => {
-                            debug!("{} sending {:?} to session \"{}\"",
-                                "websocket".purple(),
-                                message, receiver.session_id());
-                            message.send(&mut stream).await?;
+                let _ = DataFrame::new_close(websocket::CLOSE_SERVICE_RESTART, "server restarting")
+                    .flush(&mut stream)
+                    .await;
+
+                break Ok(false);
+            }
+
+            message = receiver.recv() => {
+                match message {
+                    Ok(message) => {
+                        debug!("{} sending {:?} to session \"{}\"",
+                            "websocket".purple(),
+                            message, receiver.session_id());
+                        if let Err(err) = message.send(&mut stream).await {
+                            break Err(err.into());
                         }
+                    }
 
-                        Err(RecvError::Closed) => break,
+                    Err(RecvError::Closed) => break Ok(false),
 
-                        // Lagging behind. This is best effort
-                        // message delivery, so we are ok dropping
-                        // messages if the client can't receive them
-                        // fast enough.
-                        Err(RecvError::Lagged(_)) => continue,
+                    // Lagging behind. This is best effort
+                    // message delivery, so we are ok dropping
+                    // messages if the client can't receive them
+                    // fast enough.
+                    Err(RecvError::Lagged(skipped)) => {
+                        if !connection.lagged(skipped) {
+                            break Ok(false);
+                        }
                     }
                 }
+            }
 
-                frame = DataFrame::read(&mut stream) => {
-                    let frame = frame?;
+            frame = DataFrame::read(&mut stream) => {
+                let frame = match frame {
+                    Ok(frame) => frame,
+                    Err(err) => break Err(err.into()),
+                };
 
-                    if frame.is_pong() {
-                        debug!("{} session \"{}\" is alive", "websocket".purple(), session_id);
-                        lost_pings -= 1;
+                if frame.is_pong() {
+                    debug!("{} session \"{}\" is alive", "websocket".purple(), session_id);
+                    lost_pings -= 1;
 
-                        // Protect against weird clients.
-                        if lost_pings < 0 {
-                            lost_pings = 0;
-                        }
+                    // Protect against weird clients.
+                    if lost_pings < 0 {
+                        lost_pings = 0;
+                    }
 
-                        continue;
-                    } else if frame.is_ping() {
-                        DataFrame::new_pong(frame).flush(&mut stream).await?;
-                        continue;
+                    continue;
+                } else if frame.is_close() {
+                    debug!("{} session \"{}\" closed the connection", "websocket".purple(), session_id);
+                    let _ = DataFrame::new_close(websocket::CLOSE_NORMAL, "").flush(&mut stream).await;
+                    break Ok(false);
+                } else if frame.is_ping() {
+                    if let Err(err) = DataFrame::new_pong(frame).flush(&mut stream).await {
+                        break Err(err.into());
                     }
+                    continue;
+                }
 
-                    self.client_message(&session_id, frame.message()).await?;
+                let replies = match connection.message(frame.message()).await {
+                    Ok(replies) => replies,
+                    Err(err) => break Err(err),
+                };
+
+                let mut sent = Ok(());
+                for reply in replies {
+                    sent = reply.send(&mut stream).await;
+                    if sent.is_err() {
+                        break;
+                    }
                 }
 
+                if let Err(err) = sent {
+                    break Err(err.into());
+                }
             }
         }
+    };
 
-        Ok(false)
-    }
+    connection.disconnected().await?;
+
+    result
 }
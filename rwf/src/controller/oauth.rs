@@ -0,0 +1,698 @@
+//! Sign in with OAuth2 and OpenID Connect providers, e.g. Google or GitHub.
+//!
+//! The [`OAuthController`] sends users to the provider to sign in, and handles the callback:
+//! it exchanges the authorization code for an access token, fetches the user's [`Claims`]
+//! and passes them to an [`OAuthHandler`], which finds or creates the user in the application's
+//! database. The user is then logged in with a session, like with [`crate::http::Request::login`].
+//!
+//! Client IDs and secrets are read from the `[oauth]` section of `rwf.toml`:
+//!
+//! ```toml
+//! [oauth.google]
+//! client_id = "1234.apps.googleusercontent.com"
+//! client_secret = "GOCSPX-..."
+//! ```
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use rwf::controller::oauth::{Claims, OAuthController, OAuthHandler, Provider, Tokens};
+//!
+//! struct Users;
+//!
+//! #[async_trait]
+//! impl OAuthHandler for Users {
+//!     async fn user(&self, claims: &Claims, _tokens: &Tokens) -> Result<i64, Error> {
+//!         let mut conn = Pool::connection().await?;
+//!         let user = User::find_or_create_by(&[("email", claims.email.clone())])
+//!             .fetch(&mut conn)
+//!             .await?;
+//!         Ok(user.id.unwrap())
+//!     }
+//! }
+//!
+//! // Serves /auth/google/login and /auth/google/callback.
+//! OAuthController::new(Provider::google(), Users).handler()
+//! ```
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use time::Duration;
+use tracing::warn;
+
+use super::{Controller, Error as ControllerError};
+use crate::config::get_config;
+use crate::http::{
+    client::{self, Client},
+    url::urlencode,
+    CookieBuilder, Handler, Request, Response,
+};
+
+/// Error returned by the OAuth flow.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The client ID or secret is missing.
+    #[error("oauth provider \"{0}\" is not configured: {1} is not set")]
+    NotConfigured(String, &'static str),
+
+    /// The state returned by the provider doesn't match the one sent with the user.
+    #[error("oauth state is missing or invalid")]
+    InvalidState,
+
+    /// The provider returned an error.
+    #[error("oauth provider error ({0}): {1}")]
+    Provider(u16, String),
+
+    /// Couldn't reach the provider.
+    #[error("{0}")]
+    Http(#[from] client::Error),
+
+    /// Error encoding/decoding JSON.
+    #[error("json: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// How the provider returns the user's information.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    /// OpenID Connect `userinfo` endpoint.
+    Oidc,
+    /// GitHub's REST API.
+    GitHub,
+}
+
+/// OAuth2 or OpenID Connect provider.
+#[derive(Debug, Clone)]
+pub struct Provider {
+    name: String,
+    kind: Kind,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    authorize_url: String,
+    token_url: String,
+    userinfo_url: String,
+    scopes: Vec<String>,
+}
+
+impl Provider {
+    /// Google, using OpenID Connect. Credentials are read from `[oauth.google]`.
+    pub fn google() -> Self {
+        Self::oidc(
+            "google",
+            "https://accounts.google.com/o/oauth2/v2/auth",
+            "https://oauth2.googleapis.com/token",
+            "https://openidconnect.googleapis.com/v1/userinfo",
+        )
+    }
+
+    /// GitHub. Credentials are read from `[oauth.github]`.
+    pub fn github() -> Self {
+        Self {
+            kind: Kind::GitHub,
+            scopes: vec!["read:user".into(), "user:email".into()],
+            ..Self::oidc(
+                "github",
+                "https://github.com/login/oauth/authorize",
+                "https://github.com/login/oauth/access_token",
+                "https://api.github.com/user",
+            )
+        }
+    }
+
+    /// Any OpenID Connect provider. Credentials are read from `[oauth.<name>]`.
+    pub fn oidc(
+        name: &str,
+        authorize_url: impl ToString,
+        token_url: impl ToString,
+        userinfo_url: impl ToString,
+    ) -> Self {
+        let config = get_config().oauth.get(name).cloned().unwrap_or_default();
+
+        Self {
+            name: name.to_string(),
+            kind: Kind::Oidc,
+            client_id: config.client_id(name),
+            client_secret: config.client_secret(name),
+            authorize_url: authorize_url.to_string(),
+            token_url: token_url.to_string(),
+            userinfo_url: userinfo_url.to_string(),
+            scopes: vec!["openid".into(), "email".into(), "profile".into()],
+        }
+    }
+
+    /// Any OpenID Connect provider, with endpoints read from its discovery document
+    /// at `<issuer>/.well-known/openid-configuration`.
+    pub async fn discover(name: &str, issuer: &str) -> Result<Self, Error> {
+        #[derive(Deserialize)]
+        struct Discovery {
+            authorization_endpoint: String,
+            token_endpoint: String,
+            userinfo_endpoint: String,
+        }
+
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            issuer.trim_end_matches('/')
+        );
+        let response = Client::get(url).send().await?;
+        if !response.ok() {
+            return Err(Error::Provider(response.code(), response.text()));
+        }
+
+        let discovery: Discovery = response.json()?;
+
+        Ok(Self::oidc(
+            name,
+            discovery.authorization_endpoint,
+            discovery.token_endpoint,
+            discovery.userinfo_endpoint,
+        ))
+    }
+
+    /// Set the client ID and secret, instead of reading them from the configuration.
+    pub fn client(mut self, client_id: impl ToString, client_secret: impl ToString) -> Self {
+        self.client_id = Some(client_id.to_string());
+        self.client_secret = Some(client_secret.to_string());
+        self
+    }
+
+    /// Request these scopes instead of the default ones.
+    pub fn scopes(mut self, scopes: &[&str]) -> Self {
+        self.scopes = scopes.iter().map(|scope| scope.to_string()).collect();
+        self
+    }
+
+    /// Name of the provider, e.g. `google`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn client_id(&self) -> Result<&str, Error> {
+        self.client_id
+            .as_deref()
+            .ok_or(Error::NotConfigured(self.name.clone(), "client_id"))
+    }
+
+    fn client_secret(&self) -> Result<&str, Error> {
+        self.client_secret
+            .as_deref()
+            .ok_or(Error::NotConfigured(self.name.clone(), "client_secret"))
+    }
+
+    /// Exchange the authorization code for tokens.
+    async fn tokens(
+        &self,
+        code: &str,
+        redirect_uri: &str,
+        verifier: &str,
+    ) -> Result<Tokens, Error> {
+        let response = Client::post(&self.token_url)
+            .header("accept", "application/json")
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", redirect_uri),
+                ("client_id", self.client_id()?),
+                ("client_secret", self.client_secret()?),
+                ("code_verifier", verifier),
+            ])
+            .send()
+            .await?;
+
+        // GitHub returns errors with 200 - OK.
+        let json: Json = response.json()?;
+        if !response.ok() || json.get("error").is_some() {
+            return Err(Error::Provider(response.code(), json.to_string()));
+        }
+
+        Ok(serde_json::from_value(json)?)
+    }
+
+    /// Get the user's information.
+    async fn claims(&self, tokens: &Tokens) -> Result<Claims, Error> {
+        let raw: Json = get(&self.userinfo_url, &tokens.access_token).await?;
+
+        Ok(match self.kind {
+            Kind::Oidc => Claims {
+                provider: self.name.clone(),
+                subject: string(&raw["sub"]).unwrap_or_default(),
+                email: string(&raw["email"]),
+                email_verified: raw["email_verified"].as_bool().unwrap_or(false),
+                name: string(&raw["name"]),
+                picture: string(&raw["picture"]),
+                raw,
+            },
+
+            Kind::GitHub => {
+                // The email is only returned if the user made it public.
+                let emails: Vec<Json> = get(
+                    &format!("{}/emails", self.userinfo_url),
+                    &tokens.access_token,
+                )
+                .await
+                .unwrap_or_default();
+                let primary = emails
+                    .iter()
+                    .find(|email| email["primary"].as_bool() == Some(true));
+
+                Claims {
+                    provider: self.name.clone(),
+                    subject: string(&raw["id"]).unwrap_or_default(),
+                    email: primary
+                        .and_then(|email| string(&email["email"]))
+                        .or_else(|| string(&raw["email"])),
+                    email_verified: primary
+                        .and_then(|email| email["verified"].as_bool())
+                        .unwrap_or(false),
+                    name: string(&raw["name"]).or_else(|| string(&raw["login"])),
+                    picture: string(&raw["avatar_url"]),
+                    raw,
+                }
+            }
+        })
+    }
+}
+
+/// Call the provider's API with the access token.
+async fn get<T: serde::de::DeserializeOwned>(url: &str, access_token: &str) -> Result<T, Error> {
+    let response = Client::get(url)
+        .bearer(access_token)
+        .header("accept", "application/json")
+        // Required by GitHub.
+        .header("user-agent", "rwf")
+        .send()
+        .await?;
+
+    if !response.ok() {
+        return Err(Error::Provider(response.code(), response.text()));
+    }
+
+    Ok(response.json()?)
+}
+
+fn string(value: &Json) -> Option<String> {
+    match value {
+        Json::String(value) => Some(value.clone()),
+        Json::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Tokens returned by the provider.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Tokens {
+    /// Token used to call the provider's API on behalf of the user.
+    pub access_token: String,
+    /// Type of the access token, usually `Bearer`.
+    #[serde(default)]
+    pub token_type: Option<String>,
+    /// Seconds until the access token expires.
+    #[serde(default)]
+    pub expires_in: Option<i64>,
+    /// Token used to get a new access token, if offline access was requested.
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    /// OpenID Connect ID token.
+    #[serde(default)]
+    pub id_token: Option<String>,
+    /// Scopes granted by the user.
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+/// Information about the user returned by the provider.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Claims {
+    /// Name of the provider, e.g. `google`.
+    pub provider: String,
+    /// The user's unique ID at the provider. Use it with the provider name
+    /// to identify the user, since emails can change.
+    pub subject: String,
+    /// The user's email.
+    pub email: Option<String>,
+    /// The provider verified the user owns the email.
+    pub email_verified: bool,
+    /// The user's name.
+    pub name: Option<String>,
+    /// URL of the user's picture.
+    pub picture: Option<String>,
+    /// Everything returned by the provider.
+    pub raw: Json,
+}
+
+/// Maps the user signed in with the provider to a user of the application.
+#[async_trait]
+pub trait OAuthHandler: Send + Sync {
+    /// Find or create the user with these claims, and return their ID.
+    /// Return an error to refuse the sign in.
+    async fn user(&self, claims: &Claims, tokens: &Tokens) -> Result<i64, ControllerError>;
+}
+
+/// State kept in a cookie while the user signs in with the provider.
+#[derive(Serialize, Deserialize)]
+struct State {
+    #[serde(rename = "s")]
+    state: String,
+    #[serde(rename = "v")]
+    verifier: String,
+}
+
+/// Controller signing users in with an OAuth2 or OpenID Connect provider.
+///
+/// It serves two endpoints: `<prefix>/login` sends the user to the provider, and `<prefix>/callback`
+/// is where the provider sends them back. The callback URL must be registered with the provider.
+pub struct OAuthController {
+    provider: Provider,
+    handler: Box<dyn OAuthHandler>,
+    prefix: String,
+    redirect: String,
+    callback_url: Option<String>,
+}
+
+impl OAuthController {
+    /// Create the controller, mounted at `/auth/<provider>`.
+    pub fn new(provider: Provider, handler: impl OAuthHandler + 'static) -> Self {
+        Self {
+            prefix: format!("/auth/{}", provider.name),
+            provider,
+            handler: Box::new(handler),
+            redirect: "/".into(),
+            callback_url: None,
+        }
+    }
+
+    /// Set the prefix used in URLs. Default: `/auth/<provider>`.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Where to send the user after they signed in. Default: `/`.
+    pub fn redirect(mut self, redirect: impl ToString) -> Self {
+        self.redirect = redirect.to_string();
+        self
+    }
+
+    /// Full URL of the callback endpoint, e.g. `https://example.com/auth/google/callback`.
+    /// By default, it's built from the `Host` and `X-Forwarded-Proto` headers.
+    pub fn callback_url(mut self, callback_url: impl ToString) -> Self {
+        self.callback_url = Some(callback_url.to_string());
+        self
+    }
+
+    /// Create the route handler.
+    pub fn handler(self) -> Handler {
+        let prefix = self.prefix.clone();
+        Handler::wildcard(&prefix, self)
+    }
+
+    fn cookie_name(&self) -> String {
+        format!("rwf_oauth_{}", self.provider.name)
+    }
+
+    fn redirect_uri(&self, request: &Request) -> String {
+        if let Some(ref callback_url) = self.callback_url {
+            return callback_url.clone();
+        }
+
+        let host = request
+            .header("host")
+            .cloned()
+            .unwrap_or("localhost".into());
+        let scheme = match request.header("x-forwarded-proto") {
+            Some(scheme) => scheme.clone(),
+            None if host.starts_with("localhost") || host.starts_with("127.0.0.1") => "http".into(),
+            None => "https".into(),
+        };
+
+        format!("{}://{}{}/callback", scheme, host, self.prefix)
+    }
+
+    /// Send the user to the provider.
+    async fn login(&self, request: &Request) -> Result<Response, ControllerError> {
+        let state = State {
+            state: random(32),
+            verifier: random(64),
+        };
+        let challenge =
+            general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(state.verifier.as_bytes()));
+
+        let params = [
+            ("response_type", "code"),
+            (
+                "client_id",
+                self.provider.client_id().map_err(ControllerError::new)?,
+            ),
+            ("redirect_uri", &self.redirect_uri(request)),
+            ("scope", &self.provider.scopes.join(" ")),
+            ("state", &state.state),
+            ("code_challenge", &challenge),
+            ("code_challenge_method", "S256"),
+        ]
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, urlencode(value)))
+        .collect::<Vec<_>>()
+        .join("&");
+
+        let separator = if self.provider.authorize_url.contains('?') {
+            "&"
+        } else {
+            "?"
+        };
+        let url = format!("{}{}{}", self.provider.authorize_url, separator, params);
+
+        // The cookie is sent back when the provider redirects the user to the callback,
+        // so it can't be `SameSite=Strict`.
+        let cookie = CookieBuilder::new()
+            .name(self.cookie_name())
+            .value(serde_json::to_string(&state).map_err(ControllerError::new)?)
+            .path(&self.prefix)
+            .max_age(Duration::minutes(10))
+            .http_only()
+            .lax()
+            .build();
+
+        Ok(Response::new().private_cookie(cookie)?.redirect(url))
+    }
+
+    /// The provider sent the user back.
+    async fn callback(&self, request: &Request) -> Result<Response, ControllerError> {
+        let query = request.query();
+
+        if let Some(error) = query.get::<String>("error") {
+            warn!(
+                "oauth sign in with {} failed: {}",
+                self.provider.name, error
+            );
+            return Ok(Response::unauthorized(None));
+        }
+
+        let state = request
+            .cookies()
+            .get_private(&self.cookie_name())?
+            .and_then(|cookie| serde_json::from_str::<State>(cookie.value()).ok());
+        let (state, code) = match (state, query.get::<String>("code")) {
+            (Some(state), Some(code))
+                if query.get::<String>("state") == Some(state.state.clone()) =>
+            {
+                (state, code)
+            }
+            _ => {
+                warn!(
+                    "oauth sign in with {}: {}",
+                    self.provider.name,
+                    Error::InvalidState
+                );
+                return Ok(Response::bad_request());
+            }
+        };
+
+        let tokens = self
+            .provider
+            .tokens(&code, &self.redirect_uri(request), &state.verifier)
+            .await
+            .map_err(ControllerError::new)?;
+        let claims = self
+            .provider
+            .claims(&tokens)
+            .await
+            .map_err(ControllerError::new)?;
+        let user_id = self.handler.user(&claims, &tokens).await?;
+
+        // Forget the state, so it can't be used again.
+        let cookie = CookieBuilder::new()
+            .name(self.cookie_name())
+            .path(&self.prefix)
+            .max_age(Duration::ZERO)
+            .build();
+
+        Ok(request
+            .login(user_id)
+            .cookie(cookie)
+            .redirect(&self.redirect))
+    }
+}
+
+#[async_trait]
+impl Controller for OAuthController {
+    async fn handle(&self, request: &Request) -> Result<Response, ControllerError> {
+        let path = request.path().path();
+        let endpoint = path
+            .strip_prefix(self.prefix.as_str())
+            .unwrap_or(path)
+            .trim_matches('/');
+
+        match endpoint {
+            "login" => self.login(request).await,
+            "callback" => self.callback(request).await,
+            _ => Ok(Response::not_found()),
+        }
+    }
+}
+
+fn random(length: usize) -> String {
+    use rand::{distributions::Alphanumeric, thread_rng, Rng};
+
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::{request::test::dummy_ip, Query, Router, Server};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    /// Token and userinfo endpoints of a fake provider.
+    struct FakeProvider;
+
+    #[async_trait]
+    impl Controller for FakeProvider {
+        fn skip_csrf(&self) -> bool {
+            true
+        }
+
+        async fn handle(&self, request: &Request) -> Result<Response, ControllerError> {
+            match request.path().path() {
+                "/token" => {
+                    let form = Query::parse(&String::from_utf8_lossy(request.body()));
+                    assert_eq!(form.get::<String>("code"), Some("abc".into()));
+                    assert_eq!(form.get::<String>("client_secret"), Some("secret".into()));
+                    assert_eq!(
+                        form.get::<String>("code_verifier").map(|v| v.len()),
+                        Some(64)
+                    );
+
+                    Ok(Response::new().json(json!({
+                        "access_token": "token",
+                        "token_type": "Bearer",
+                        "expires_in": 3600,
+                    }))?)
+                }
+                _ => {
+                    assert_eq!(
+                        request.header("authorization"),
+                        Some(&"Bearer token".to_string())
+                    );
+
+                    Ok(Response::new().json(json!({
+                        "sub": "1234",
+                        "email": "jane@example.com",
+                        "email_verified": true,
+                        "name": "Jane",
+                    }))?)
+                }
+            }
+        }
+    }
+
+    struct Users;
+
+    #[async_trait]
+    impl OAuthHandler for Users {
+        async fn user(&self, claims: &Claims, tokens: &Tokens) -> Result<i64, ControllerError> {
+            assert_eq!(claims.provider, "fake");
+            assert_eq!(claims.subject, "1234");
+            assert_eq!(claims.email.as_deref(), Some("jane@example.com"));
+            assert!(claims.email_verified);
+            assert_eq!(tokens.expires_in, Some(3600));
+            Ok(42)
+        }
+    }
+
+    async fn get(controller: &OAuthController, path: &str, cookie: &str) -> Response {
+        let request = Request::read(
+            dummy_ip(),
+            format!(
+                "GET {} HTTP/1.1\r\nHost: localhost:8000\r\nCookie: {}\r\n\r\n",
+                path, cookie
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+        controller.handle(&request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_oauth() {
+        let router = Arc::new(Router::new(vec![Handler::wildcard("/", FakeProvider)]).unwrap());
+        let addr = Server::serve_test(router).await;
+
+        let provider = Provider::oidc(
+            "fake",
+            format!("http://{}/authorize", addr),
+            format!("http://{}/token", addr),
+            format!("http://{}/userinfo", addr),
+        )
+        .client("client", "secret");
+        let controller = OAuthController::new(provider, Users).redirect("/dashboard");
+
+        let mut response = get(&controller, "/auth/fake/login", "").await;
+        assert_eq!(response.status().code(), 302);
+        let location = response.headers().get("location").unwrap().clone();
+        assert!(location.starts_with(&format!("http://{}/authorize?", addr)));
+        assert!(location.contains("client_id=client"));
+        assert!(location.contains("code_challenge_method=S256"));
+        assert!(location.contains(&urlencode("http://localhost:8000/auth/fake/callback")));
+
+        let state = Query::parse(location.split_once('?').unwrap().1)
+            .get::<String>("state")
+            .unwrap();
+        let cookie = response.cookies().get("rwf_oauth_fake").unwrap().clone();
+        let cookie = format!("{}={}", cookie.name(), cookie.value());
+
+        // The state must match.
+        let response = get(
+            &controller,
+            "/auth/fake/callback?code=abc&state=xyz",
+            &cookie,
+        )
+        .await;
+        assert_eq!(response.status().code(), 400);
+        let path = format!("/auth/fake/callback?code=abc&state={}", state);
+        let response = get(&controller, &path, "").await;
+        assert_eq!(response.status().code(), 400);
+
+        let response = get(&controller, &path, &cookie).await;
+        assert_eq!(response.status().code(), 302);
+        assert_eq!(
+            response.headers().get("location"),
+            Some(&"/dashboard".to_string())
+        );
+        assert_eq!(
+            response.session().as_ref().unwrap().session_id.user_id(),
+            Some(42)
+        );
+
+        let response = get(&controller, "/auth/fake/callback?error=access_denied", "").await;
+        assert_eq!(response.status().code(), 401);
+    }
+}
@@ -0,0 +1,252 @@
+//! JSON API for a model, without writing a controller.
+//!
+//! [`Resource`] serves the model's records with [`ModelController`]:
+//!
+//! | Request | Action |
+//! |---------|--------|
+//! | `GET /api/users` | List users. Supports pagination and sorting, e.g. `?page=2&sort=-created_at`. |
+//! | `GET /api/users/:id` | Fetch a user. |
+//! | `POST /api/users` | Create a user. |
+//! | `PUT /api/users/:id` | Replace a user. |
+//! | `PATCH /api/users/:id` | Update some fields of a user, with a JSON Merge Patch. |
+//! | `DELETE /api/users/:id` | Delete a user. |
+//!
+//! # Example
+//!
+//! ```ignore
+//! Server::new(vec![
+//!     route!("/api/users" => Resource::<User>),
+//!     Resource::<Post>::new()
+//!         .permit(&["title", "body"])
+//!         .sortable(&["created_at"])
+//!         .auth(AuthHandler::new(TokenAuth::new()))
+//!         .route("/api/posts"),
+//! ])
+//! ```
+use std::marker::PhantomData;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::{model_schema, AuthHandler, Controller, Error, MiddlewareSet, ModelController};
+use crate::config::get_config;
+use crate::http::{
+    openapi::{Operation, Schema},
+    Handler, Request, Response,
+};
+use crate::model::Model;
+
+/// Serves a model as a JSON API.
+pub struct Resource<T> {
+    auth: Option<AuthHandler>,
+    middleware: Option<MiddlewareSet>,
+    permitted: Option<Vec<&'static str>>,
+    sortable: Option<Vec<&'static str>>,
+    schema: Option<serde_json::Value>,
+    skip_csrf: bool,
+    _model: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for Resource<T> {
+    fn default() -> Self {
+        Self {
+            auth: None,
+            middleware: None,
+            permitted: None,
+            sortable: None,
+            schema: None,
+            skip_csrf: false,
+            _model: PhantomData,
+        }
+    }
+}
+
+impl<T> Resource<T> {
+    /// Serve all columns of the model.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only allow clients to set these columns. Other fields they send are ignored.
+    pub fn permit(mut self, columns: &[&'static str]) -> Self {
+        self.permitted = Some(columns.to_vec());
+        self
+    }
+
+    /// Only allow sorting records by these columns, and the primary key.
+    pub fn sortable(mut self, columns: &[&'static str]) -> Self {
+        self.sortable = Some(columns.to_vec());
+        self
+    }
+
+    /// Describe records with the model's [`Schema`] in the OpenAPI specification, instead of only
+    /// listing its columns.
+    pub fn schema(mut self) -> Self
+    where
+        T: Schema,
+    {
+        self.schema = Some(T::schema());
+        self
+    }
+
+    /// Require authentication.
+    pub fn auth(mut self, auth: AuthHandler) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Run this middleware on requests.
+    pub fn middleware(mut self, middleware: MiddlewareSet) -> Self {
+        self.middleware = Some(middleware);
+        self
+    }
+
+    /// Don't check CSRF tokens, e.g. because clients authenticate with a bearer token.
+    pub fn skip_csrf(mut self) -> Self {
+        self.skip_csrf = true;
+        self
+    }
+}
+
+#[async_trait]
+impl<T> Controller for Resource<T>
+where
+    T: Model + Serialize + Send + Sync + for<'a> Deserialize<'a> + 'static,
+{
+    fn auth(&self) -> &AuthHandler {
+        match self.auth {
+            Some(ref auth) => auth,
+            None => &get_config().general.default_auth,
+        }
+    }
+
+    fn middleware(&self) -> &MiddlewareSet {
+        match self.middleware {
+            Some(ref middleware) => middleware,
+            None => &get_config().general.default_middleware,
+        }
+    }
+
+    fn skip_csrf(&self) -> bool {
+        self.skip_csrf
+    }
+
+    /// Serve the collection and its records, e.g. `/api/users` and `/api/users/:id`.
+    fn route(self, path: &str) -> Handler
+    where
+        Self: Sized + 'static,
+    {
+        Handler::rest(path, self)
+    }
+
+    fn openapi(&self) -> Vec<Operation> {
+        ModelController::operations(self)
+    }
+
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        ModelController::handle(self, request).await
+    }
+}
+
+#[async_trait]
+impl<T> ModelController for Resource<T>
+where
+    T: Model + Serialize + Send + Sync + for<'a> Deserialize<'a> + 'static,
+{
+    type Model = T;
+
+    fn permitted_columns(&self) -> Option<&[&str]> {
+        self.permitted.as_deref()
+    }
+
+    fn sortable_columns(&self) -> &[&str] {
+        match self.sortable {
+            Some(ref sortable) => sortable,
+            None => T::column_names(),
+        }
+    }
+
+    fn record_schema(&self) -> serde_json::Value {
+        match self.schema {
+            Some(ref schema) => schema.clone(),
+            None => model_schema::<T>(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::test::dummy_ip;
+    use crate::model::{FromRow, ToValue, Value};
+
+    #[derive(Clone, Serialize, Deserialize)]
+    struct User {
+        id: Option<i64>,
+        email: String,
+        password: String,
+    }
+
+    impl FromRow for User {
+        fn from_row(_row: tokio_postgres::Row) -> Result<Self, crate::model::Error> {
+            unimplemented!()
+        }
+    }
+
+    impl Model for User {
+        fn id(&self) -> Value {
+            self.id.to_value()
+        }
+
+        fn table_name() -> &'static str {
+            "users"
+        }
+
+        fn foreign_key() -> &'static str {
+            "user_id"
+        }
+
+        fn column_names() -> &'static [&'static str] {
+            &["email", "password"]
+        }
+
+        fn values(&self) -> Vec<Value> {
+            vec![self.email.to_value(), self.password.to_value()]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resource() {
+        let resource = Resource::<User>::new();
+        assert_eq!(resource.permitted_columns(), None);
+        assert_eq!(resource.sortable_columns(), &["email", "password"]);
+
+        let resource = Resource::<User>::new()
+            .permit(&["email"])
+            .sortable(&["email"]);
+        assert_eq!(resource.permitted_columns(), Some(&["email"][..]));
+
+        let operations = Controller::openapi(&resource);
+        assert_eq!(operations.len(), 6);
+        assert_eq!(
+            resource.record_schema()["properties"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>(),
+            vec!["email", "id", "password"]
+        );
+
+        // Columns which aren't sortable are rejected before querying the database.
+        let request = Request::read(
+            dummy_ip(),
+            "GET /api/users?sort=-password HTTP/1.1\r\n\r\n".as_bytes(),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            ModelController::list(&resource, &request).await,
+            Err(Error::HttpError(err)) if err.code() == 400
+        ));
+    }
+}
@@ -0,0 +1,340 @@
+//! Typed request/response protocols over WebSockets.
+//!
+//! A protocol is a trait annotated with `#[rwf::macros::rpc]`. Each method is a request the client can make;
+//! its arguments are the parameters and its return value is the response. Messages the server sends without being asked,
+//! e.g. chat messages, are pushes, defined by an enum.
+//!
+//! ```
+//! use rwf::prelude::*;
+//! use rwf::controller::rpc::{Client, RpcController};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize, Clone)]
+//! #[serde(tag = "type", rename_all = "snake_case")]
+//! enum ChatPush {
+//!     Message { from: String, body: String },
+//! }
+//!
+//! #[rwf::macros::rpc(push = ChatPush)]
+//! trait Chat {
+//!     async fn send(&self, client: &Client, body: String) -> Result<usize, Error>;
+//! }
+//!
+//! struct Room;
+//!
+//! #[async_trait]
+//! impl Chat for Room {
+//!     async fn send(&self, client: &Client, body: String) -> Result<usize, Error> {
+//!         let message = ChatPush::Message { from: client.session_id().to_string(), body };
+//!         Self::broadcast(&message)?;
+//!         Ok(1)
+//!     }
+//! }
+//!
+//! let route = RpcController::new(ChatService(Room)).route("/chat");
+//! ```
+//!
+//! The macro generates the `ChatRequest` and `ChatResponse` enums, and `ChatService`, which dispatches requests to the
+//! trait methods. With `push`, the trait gets `push` and `broadcast` methods to send pushes to one session or everyone.
+//! Messages are JSON text frames:
+//!
+//! ```text
+//! -> {"id": 1, "method": "send", "params": {"body": "hello"}}
+//! <- {"id": 1, "result": 1}
+//! <- {"push": {"type": "message", "from": "...", "body": "hello"}}
+//! ```
+//!
+//! Responses are sent only to the connection that made the request. Pushes are addressed by session, like
+//! other [`Comms`] messages, and reach all connections of that session, including ones served by other instances of the app.
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value as Json};
+use tracing::debug;
+
+use super::{AuthHandler, Controller, Error, SessionId, WebsocketController};
+use crate::comms::{self, Comms, IntoSessionId};
+use crate::http::{websocket::Message, Request, Response, Stream};
+
+/// Client making the request.
+#[derive(Debug, Clone)]
+pub struct Client {
+    session_id: SessionId,
+}
+
+impl Client {
+    /// Client with this session.
+    pub fn new(session_id: SessionId) -> Self {
+        Self { session_id }
+    }
+
+    /// The client's session.
+    pub fn session_id(&self) -> &SessionId {
+        &self.session_id
+    }
+
+    /// The logged in user, if any.
+    pub fn user_id(&self) -> Option<i64> {
+        self.session_id.user_id()
+    }
+
+    /// Send a push to this client.
+    pub fn push(&self, push: &impl Serialize) -> Result<usize, comms::Error> {
+        push_to(&self.session_id, push)
+    }
+}
+
+/// Send a push to all connections of a session, or a user if a user ID or model is passed in.
+pub fn push_to(to: impl IntoSessionId, push: &impl Serialize) -> Result<usize, comms::Error> {
+    Comms::websocket(to).send(encode_push(push)?)
+}
+
+/// Send a push to everyone connected.
+pub fn broadcast(push: &impl Serialize) -> Result<(), comms::Error> {
+    Comms::notify().send(encode_push(push)?)
+}
+
+fn encode_push(push: &impl Serialize) -> Result<String, serde_json::Error> {
+    serde_json::to_string(&json!({ "push": push }))
+}
+
+/// Dispatches requests to the protocol's handler. Implemented by the `#[rpc]` macro.
+#[async_trait]
+pub trait Dispatch: Send + Sync {
+    /// Requests defined by the protocol.
+    type Request: DeserializeOwned + Send;
+
+    /// Responses defined by the protocol.
+    type Response: Serialize + Send;
+
+    /// Handle the request.
+    async fn dispatch(
+        &self,
+        client: &Client,
+        request: Self::Request,
+    ) -> Result<Self::Response, Error>;
+}
+
+/// WebSocket controller serving a protocol.
+pub struct RpcController<D: Dispatch> {
+    service: D,
+    auth: Option<AuthHandler>,
+}
+
+impl<D: Dispatch> RpcController<D> {
+    /// Serve the protocol handled by the service.
+    pub fn new(service: D) -> Self {
+        Self {
+            service,
+            auth: None,
+        }
+    }
+
+    /// Require authentication before the connection is accepted.
+    pub fn auth(mut self, auth: AuthHandler) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Handle a message, returning the response. Requests without an `id` are notifications
+    /// and get no response.
+    pub async fn call(&self, client: &Client, message: &str) -> Option<String> {
+        let mut call = match serde_json::from_str::<Json>(message) {
+            Ok(Json::Object(call)) => call,
+            _ => return Some(reply(Json::Null, Err(RpcError::parse()))),
+        };
+
+        let id = call.remove("id");
+        let result = match serde_json::from_value::<D::Request>(Json::Object(call)) {
+            Ok(request) => match self.service.dispatch(client, request).await {
+                Ok(response) => {
+                    serde_json::to_value(response).map_err(|err| RpcError::internal(err.into()))
+                }
+                Err(err) => Err(RpcError::internal(err)),
+            },
+            Err(err) => Err(RpcError::invalid(err)),
+        };
+
+        if let Err(ref err) = result {
+            debug!("rpc error: {}", err.message);
+        }
+
+        id.map(|id| reply(id, result))
+    }
+}
+
+struct RpcError {
+    code: &'static str,
+    message: String,
+}
+
+impl RpcError {
+    fn parse() -> Self {
+        Self {
+            code: "parse_error",
+            message: "message is not a JSON object".into(),
+        }
+    }
+
+    fn invalid(err: serde_json::Error) -> Self {
+        Self {
+            code: "invalid_request",
+            message: err.to_string(),
+        }
+    }
+
+    fn internal(err: Error) -> Self {
+        // Only show internal errors in development.
+        let message = if cfg!(debug_assertions) {
+            err.to_string()
+        } else {
+            "internal error".into()
+        };
+
+        Self {
+            code: "internal_error",
+            message,
+        }
+    }
+}
+
+fn reply(id: Json, result: Result<Json, RpcError>) -> String {
+    let reply = match result {
+        Ok(result) => json!({ "id": id, "result": result }),
+        Err(err) => json!({ "id": id, "error": { "code": err.code, "message": err.message } }),
+    };
+
+    reply.to_string()
+}
+
+#[async_trait]
+impl<D: Dispatch> Controller for RpcController<D> {
+    fn auth(&self) -> &AuthHandler {
+        match self.auth {
+            Some(ref auth) => auth,
+            None => &crate::config::get_config().general.default_auth,
+        }
+    }
+
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        WebsocketController::handle(self, request).await
+    }
+
+    async fn handle_stream(&self, request: &Request, stream: Stream<'_>) -> Result<bool, Error> {
+        WebsocketController::handle_stream(self, request, stream).await
+    }
+
+    fn controller_name(&self) -> &'static str {
+        std::any::type_name::<D>()
+    }
+}
+
+#[async_trait]
+impl<D: Dispatch> WebsocketController for RpcController<D> {
+    async fn client_request(
+        &self,
+        session_id: &SessionId,
+        message: Message,
+    ) -> Result<Option<Message>, Error> {
+        let message = match message {
+            Message::Text(text) => text,
+            _ => return Ok(None),
+        };
+
+        let client = Client::new(session_id.clone());
+        Ok(self.call(&client, &message).await.map(Message::Text))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::controller::Session;
+    use serde::Deserialize;
+
+    // What the `#[rpc]` macro generates.
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    #[serde(tag = "method", content = "params", rename_all = "snake_case")]
+    enum CounterRequest {
+        Add { a: i64, b: i64 },
+        Reset,
+    }
+
+    #[derive(Serialize)]
+    #[serde(untagged)]
+    #[allow(dead_code)]
+    enum CounterResponse {
+        Add(i64),
+        Reset(()),
+    }
+
+    struct Counter;
+
+    #[async_trait]
+    impl Dispatch for Counter {
+        type Request = CounterRequest;
+        type Response = CounterResponse;
+
+        async fn dispatch(
+            &self,
+            client: &Client,
+            request: CounterRequest,
+        ) -> Result<CounterResponse, Error> {
+            match request {
+                CounterRequest::Add { a, b } => {
+                    client.push(&json!({"type": "changed", "value": a + b}))?;
+                    Ok(CounterResponse::Add(a + b))
+                }
+                CounterRequest::Reset => Err(Error::Error("can't reset".into())),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rpc() {
+        let controller = RpcController::new(Counter);
+        let session = Session::new_authenticated(json!({}), 7).unwrap();
+        let client = Client::new(session.session_id.clone());
+        let mut receiver = Comms::receiver(client.session_id());
+
+        let response = controller
+            .call(
+                &client,
+                r#"{"id": 1, "method": "add", "params": {"a": 2, "b": 3}}"#,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<Json>(&response).unwrap(),
+            json!({"id": 1, "result": 5})
+        );
+
+        let push = match receiver.recv().await.unwrap() {
+            Message::Text(text) => serde_json::from_str::<Json>(&text).unwrap(),
+            message => panic!("unexpected message: {:?}", message),
+        };
+        assert_eq!(push, json!({"push": {"type": "changed", "value": 5}}));
+
+        let response = controller
+            .call(&client, r#"{"id": "a", "method": "reset"}"#)
+            .await
+            .unwrap();
+        let response = serde_json::from_str::<Json>(&response).unwrap();
+        assert_eq!(response["id"], "a");
+        assert_eq!(response["error"]["code"], "internal_error");
+
+        let response = controller
+            .call(&client, r#"{"id": 2, "method": "divide"}"#)
+            .await
+            .unwrap();
+        let response = serde_json::from_str::<Json>(&response).unwrap();
+        assert_eq!(response["error"]["code"], "invalid_request");
+
+        // Notifications don't get a response.
+        assert!(controller
+            .call(&client, r#"{"method": "add", "params": {"a": 1, "b": 1}}"#)
+            .await
+            .is_none());
+        assert!(controller.call(&client, "[]").await.is_some());
+    }
+}
@@ -0,0 +1,376 @@
+//! Where sessions are stored.
+//!
+//! By default, sessions are stored in an encrypted cookie, so the server doesn't keep any state.
+//! Cookies are limited to about 4 KB though, and a session can't be revoked before it expires.
+//! With a server-side store, the cookie only holds a random key, and sessions are kept in memory or
+//! in the `rwf_sessions` table, so they can be large and users can be logged out of all their devices.
+//!
+//! The store is configured in the `[general]` section of `rwf.toml`:
+//!
+//! ```toml
+//! [general]
+//! session_store = "postgres"
+//! ```
+//!
+//! or set in code with [`set_store`], e.g. to use another backend.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use thiserror::Error;
+use time::OffsetDateTime;
+
+use super::Session;
+use crate::config::{get_config, SessionBackend};
+use crate::model::{Error as ModelError, Pool};
+
+static STORE: Lazy<RwLock<Arc<dyn SessionStore>>> = Lazy::new(|| {
+    let store: Arc<dyn SessionStore> = match get_config().general.session_store {
+        SessionBackend::Cookie => Arc::new(CookieStore),
+        SessionBackend::Memory => Arc::new(MemoryStore::new()),
+        SessionBackend::Postgres => Arc::new(PostgresStore::new()),
+    };
+    RwLock::new(store)
+});
+
+/// Session store error.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The store can't do this, e.g. revoke sessions stored in cookies.
+    #[error("session store \"{0}\" doesn't support this operation")]
+    Unsupported(&'static str),
+
+    /// The store is unavailable.
+    #[error("session store error: {0}")]
+    Store(String),
+}
+
+impl From<ModelError> for Error {
+    fn from(err: ModelError) -> Self {
+        Self::Store(err.to_string())
+    }
+}
+
+impl From<tokio_postgres::Error> for Error {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::Store(err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Store(err.to_string())
+    }
+}
+
+/// Storage for sessions, identified by a random key saved in the session cookie.
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    /// Get the session, if it exists and hasn't expired.
+    async fn load(&self, key: &str) -> Result<Option<Session>, Error>;
+
+    /// Save the session, replacing the one with the same key, if any.
+    async fn save(&self, key: &str, session: &Session) -> Result<(), Error>;
+
+    /// Delete the session, e.g. when the user logs out.
+    async fn delete(&self, key: &str) -> Result<(), Error>;
+
+    /// Delete all sessions of the user, logging them out everywhere.
+    /// Returns the number of sessions deleted.
+    async fn delete_user(&self, user_id: i64) -> Result<u64, Error>;
+
+    /// Sessions are stored in the cookie, so the other methods aren't used.
+    fn cookie(&self) -> bool {
+        false
+    }
+
+    /// Name of the backend, e.g. `redis`.
+    fn name(&self) -> &'static str {
+        "custom"
+    }
+
+    /// Sessions are shared between all servers. Required in [cluster mode](crate::cluster).
+    fn shared(&self) -> bool {
+        false
+    }
+}
+
+/// Sessions stored in an encrypted cookie. This is the default.
+pub struct CookieStore;
+
+#[async_trait]
+impl SessionStore for CookieStore {
+    async fn load(&self, _key: &str) -> Result<Option<Session>, Error> {
+        Ok(None)
+    }
+
+    async fn save(&self, _key: &str, _session: &Session) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn delete(&self, _key: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn delete_user(&self, _user_id: i64) -> Result<u64, Error> {
+        Err(Error::Unsupported(self.name()))
+    }
+
+    fn cookie(&self) -> bool {
+        true
+    }
+
+    fn name(&self) -> &'static str {
+        "cookie"
+    }
+
+    fn shared(&self) -> bool {
+        true
+    }
+}
+
+/// Sessions kept in memory. They are lost when the server restarts.
+#[derive(Default)]
+pub struct MemoryStore {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    sessions: HashMap<String, Session>,
+    pruned_at: i64,
+}
+
+impl MemoryStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemoryStore {
+    async fn load(&self, key: &str) -> Result<Option<Session>, Error> {
+        Ok(self
+            .state
+            .lock()
+            .sessions
+            .get(key)
+            .filter(|session| !session.expired())
+            .cloned())
+    }
+
+    async fn save(&self, key: &str, session: &Session) -> Result<(), Error> {
+        let now = now();
+        let mut state = self.state.lock();
+
+        // Forget expired sessions once a minute, so memory doesn't grow forever.
+        if now - state.pruned_at >= 60 {
+            state.sessions.retain(|_, session| !session.expired());
+            state.pruned_at = now;
+        }
+
+        state.sessions.insert(key.to_string(), session.clone());
+
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        self.state.lock().sessions.remove(key);
+        Ok(())
+    }
+
+    async fn delete_user(&self, user_id: i64) -> Result<u64, Error> {
+        let mut state = self.state.lock();
+        let before = state.sessions.len();
+        state
+            .sessions
+            .retain(|_, session| session.session_id.user_id() != Some(user_id));
+
+        Ok((before - state.sessions.len()) as u64)
+    }
+
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+}
+
+/// Sessions kept in the `rwf_sessions` table, shared between all servers
+/// using the same database. Expired sessions are deleted once a minute.
+#[derive(Default)]
+pub struct PostgresStore {
+    pruned_at: Mutex<i64>,
+}
+
+impl PostgresStore {
+    /// Create a store using the default connection pool.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SessionStore for PostgresStore {
+    async fn load(&self, key: &str) -> Result<Option<Session>, Error> {
+        let conn = Pool::connection().await?;
+        let row = conn
+            .client()
+            .query_opt(
+                "SELECT session FROM rwf_sessions WHERE key = $1 AND expires_at > $2",
+                &[&key, &now()],
+            )
+            .await?;
+
+        match row {
+            Some(row) => Ok(Some(serde_json::from_value(row.try_get(0)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn save(&self, key: &str, session: &Session) -> Result<(), Error> {
+        let now = now();
+        let conn = Pool::connection().await?;
+
+        let prune = {
+            let mut pruned_at = self.pruned_at.lock();
+            if now - *pruned_at >= 60 {
+                *pruned_at = now;
+                true
+            } else {
+                false
+            }
+        };
+
+        if prune {
+            conn.client()
+                .execute("DELETE FROM rwf_sessions WHERE expires_at <= $1", &[&now])
+                .await?;
+        }
+
+        conn.client()
+            .execute(
+                "INSERT INTO rwf_sessions (key, user_id, session, expires_at) VALUES ($1, $2, $3, $4)
+                ON CONFLICT (key) DO UPDATE SET
+                    user_id = EXCLUDED.user_id,
+                    session = EXCLUDED.session,
+                    expires_at = EXCLUDED.expires_at",
+                &[
+                    &key,
+                    &session.session_id.user_id(),
+                    &serde_json::to_value(session)?,
+                    &session.expiration,
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        let conn = Pool::connection().await?;
+        conn.client()
+            .execute("DELETE FROM rwf_sessions WHERE key = $1", &[&key])
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_user(&self, user_id: i64) -> Result<u64, Error> {
+        let conn = Pool::connection().await?;
+        Ok(conn
+            .client()
+            .execute("DELETE FROM rwf_sessions WHERE user_id = $1", &[&user_id])
+            .await?)
+    }
+
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn shared(&self) -> bool {
+        true
+    }
+}
+
+/// The session store used by the server.
+pub fn store() -> Arc<dyn SessionStore> {
+    STORE.read().clone()
+}
+
+/// Use this session store instead of the one configured in `rwf.toml`.
+/// Set it before starting the server.
+pub fn set_store(store: impl SessionStore + 'static) {
+    *STORE.write() = Arc::new(store);
+}
+
+/// Generate a random session key.
+pub(crate) fn key() -> String {
+    use rand::{distributions::Alphanumeric, thread_rng, Rng};
+
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+fn now() -> i64 {
+    OffsetDateTime::now_utc().unix_timestamp()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::test::bootstrap;
+
+    #[tokio::test]
+    async fn test_memory_store() -> Result<(), Error> {
+        let store = MemoryStore::new();
+        let guest = Session::anonymous();
+        let user = Session::new_authenticated(serde_json::json!({"cart": [1, 2]}), 5)
+            .map_err(|err| Error::Store(err.to_string()))?;
+
+        store.save("a", &guest).await?;
+        store.save("b", &user).await?;
+        store.save("c", &user).await?;
+
+        assert_eq!(store.load("a").await?, Some(guest.clone()));
+        assert_eq!(store.load("b").await?, Some(user.clone()));
+
+        store.delete("a").await?;
+        assert_eq!(store.load("a").await?, None);
+
+        // Log out everywhere.
+        assert_eq!(store.delete_user(5).await?, 2);
+        assert_eq!(store.load("c").await?, None);
+
+        let expired = Session {
+            expiration: now() - 1,
+            ..guest
+        };
+        store.save("d", &expired).await?;
+        assert_eq!(store.load("d").await?, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_postgres_store() -> Result<(), Error> {
+        let conn = Pool::connection().await?;
+        bootstrap(conn.client()).await?;
+
+        let store = PostgresStore::new();
+        let key = key();
+        let user = Session::new_authenticated(serde_json::json!({}), -1)
+            .map_err(|err| Error::Store(err.to_string()))?;
+
+        store.save(&key, &user).await?;
+        assert_eq!(store.load(&key).await?, Some(user.clone()));
+        assert!(store.delete_user(-1).await? >= 1);
+        assert_eq!(store.load(&key).await?, None);
+
+        Ok(())
+    }
+}
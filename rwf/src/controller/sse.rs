@@ -0,0 +1,250 @@
+//! Server-Sent Events (SSE).
+//!
+//! SSE keeps the HTTP connection open and pushes events to the client, which receives them with
+//! the browser's [`EventSource`](https://developer.mozilla.org/en-US/docs/Web/API/EventSource) API.
+//! Unlike WebSockets, it's one-way (server to client) and works over plain HTTP, so it passes
+//! through proxies which don't support WebSockets.
+//!
+//! # Example
+//!
+//! ```rust
+//! use rwf::prelude::*;
+//! use rwf::controller::sse::{Event, Sender, SseController};
+//!
+//! #[derive(Default, macros::SseController)]
+//! struct Clock;
+//!
+//! #[async_trait]
+//! impl SseController for Clock {
+//!     async fn connected(&self, request: &Request, sender: Sender<Event>) -> Result<(), Error> {
+//!         tokio::spawn(async move {
+//!             loop {
+//!                 let now = OffsetDateTime::now_utc().to_string();
+//!                 // The client disconnected.
+//!                 if sender.send(Event::new(now).event("tick")).await.is_err() {
+//!                     break;
+//!                 }
+//!                 tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+//!             }
+//!         });
+//!
+//!         Ok(())
+//!     }
+//! }
+//! ```
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde::Serialize;
+use tokio::sync::mpsc::{channel, Receiver};
+use tokio::time::{interval_at, Instant, Interval};
+use tokio_stream::Stream;
+
+use super::{Controller, Error};
+use crate::http::{Request, Response};
+
+pub use tokio::sync::mpsc::Sender;
+
+/// Number of events that can be queued before [`Sender::send`] waits for the client to receive them.
+const BUFFER: usize = 128;
+
+/// An event sent to the client.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Event {
+    id: Option<String>,
+    event: Option<String>,
+    data: String,
+    retry: Option<Duration>,
+}
+
+impl Event {
+    /// Create an event with the given data. Multi-line data is supported.
+    pub fn new(data: impl ToString) -> Self {
+        Self {
+            data: data.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Create an event with the data serialized as JSON.
+    pub fn json(data: impl Serialize) -> Result<Self, serde_json::Error> {
+        Ok(Self::new(serde_json::to_string(&data)?))
+    }
+
+    /// Set the event name. The client can listen to named events
+    /// with `addEventListener(name, ...)`. Unnamed events are delivered as `message`.
+    pub fn event(mut self, event: impl ToString) -> Self {
+        self.event = Some(event.to_string());
+        self
+    }
+
+    /// Set the event ID. If the connection is lost, the browser reconnects
+    /// and sends the last ID it received in the `Last-Event-ID` header,
+    /// so the server can resume the stream. See [`Request::last_event_id`].
+    pub fn id(mut self, id: impl ToString) -> Self {
+        self.id = Some(id.to_string());
+        self
+    }
+
+    /// Tell the client how long to wait before reconnecting if the connection is lost.
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Serialize the event to the wire format.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rwf::controller::sse::Event;
+    /// let event = Event::new("hello\nworld").event("greeting").id(1);
+    /// assert_eq!(event.to_bytes(), "event: greeting\nid: 1\ndata: hello\ndata: world\n\n");
+    /// ```
+    pub fn to_bytes(&self) -> Bytes {
+        let mut frame = String::new();
+
+        // Newlines would start a new field, so they are removed
+        // from single-line fields.
+        if let Some(ref event) = self.event {
+            frame.push_str(&format!("event: {}\n", single_line(event)));
+        }
+
+        if let Some(ref id) = self.id {
+            frame.push_str(&format!("id: {}\n", single_line(id)));
+        }
+
+        if let Some(retry) = self.retry {
+            frame.push_str(&format!("retry: {}\n", retry.as_millis()));
+        }
+
+        for line in self.data.lines() {
+            frame.push_str(&format!("data: {}\n", line));
+        }
+
+        if self.data.is_empty() {
+            frame.push_str("data: \n");
+        }
+
+        frame.push('\n');
+
+        Bytes::from(frame)
+    }
+}
+
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n'], "")
+}
+
+/// Stream of events sent to the client.
+///
+/// If no events are sent for a while, a comment is sent instead, so proxies don't close the connection
+/// and disconnected clients are detected.
+pub struct EventStream {
+    receiver: Receiver<Event>,
+    keep_alive: Interval,
+}
+
+impl EventStream {
+    /// Create an event stream with the given keep-alive interval. Returns the sender
+    /// used to push events to the stream. The stream ends when all senders are dropped.
+    pub fn new(keep_alive: Duration) -> (Sender<Event>, Self) {
+        let (sender, receiver) = channel(BUFFER);
+        let keep_alive = interval_at(Instant::now() + keep_alive, keep_alive);
+
+        (
+            sender,
+            Self {
+                receiver,
+                keep_alive,
+            },
+        )
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Bytes;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.receiver.poll_recv(cx) {
+            Poll::Ready(Some(event)) => {
+                self.keep_alive.reset();
+                Poll::Ready(Some(event.to_bytes()))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => match self.keep_alive.poll_tick(cx) {
+                Poll::Ready(_) => Poll::Ready(Some(Bytes::from_static(b":\n\n"))),
+                Poll::Pending => Poll::Pending,
+            },
+        }
+    }
+}
+
+/// Controller that streams Server-Sent Events to the client.
+///
+/// The connection stays open until the [`Sender`] passed to [`SseController::connected`]
+/// (and all its clones) is dropped, or the client disconnects.
+#[async_trait]
+pub trait SseController: Controller {
+    /// A client connected. Use the sender to push events to it, usually from a spawned task.
+    /// Sending fails once the client disconnects.
+    async fn connected(&self, request: &Request, sender: Sender<Event>) -> Result<(), Error>;
+
+    /// How often to send a keep-alive comment when there are no events. Default: 15 seconds.
+    fn keep_alive(&self) -> Duration {
+        Duration::from_secs(15)
+    }
+
+    /// Open the event stream.
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let (sender, stream) = EventStream::new(self.keep_alive());
+
+        self.connected(request, sender).await?;
+
+        Ok(Response::new()
+            .stream(stream)
+            .header("content-type", "text/event-stream")
+            .header("cache-control", "no-cache")
+            // Disable response buffering in nginx.
+            .header("x-accel-buffering", "no"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio_stream::StreamExt;
+
+    #[test]
+    fn test_event() {
+        assert_eq!(Event::new("hello").to_bytes(), "data: hello\n\n");
+        assert_eq!(
+            Event::new("").event("ping\nevil").to_bytes(),
+            "event: pingevil\ndata: \n\n"
+        );
+        assert_eq!(
+            Event::json(serde_json::json!({"a": 1}))
+                .unwrap()
+                .retry(Duration::from_secs(3))
+                .to_bytes(),
+            "retry: 3000\ndata: {\"a\":1}\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_event_stream() {
+        let (sender, mut stream) = EventStream::new(Duration::from_millis(50));
+
+        sender.send(Event::new("1").id(1)).await.unwrap();
+        assert_eq!(stream.next().await.unwrap(), "id: 1\ndata: 1\n\n");
+
+        // Keep-alive comment.
+        assert_eq!(stream.next().await.unwrap(), ":\n\n");
+
+        drop(sender);
+        assert!(stream.next().await.is_none());
+    }
+}
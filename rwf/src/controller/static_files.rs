@@ -7,18 +7,35 @@
 //!
 //! To change this behavior, create the controller with [`StaticFiles::serve`] and
 //! then call [`StaticFiles::prefix`] to set the URL prefix to whatever you want.
+//!
+//! Files are served with the `ETag` and `Last-Modified` headers, so browsers can revalidate
+//! them with conditional requests and receive `304 Not Modified` if the file didn't change.
+//! Single byte ranges are supported, which allows media players to seek in audio and video files.
+//!
+//! Assets created with [`StaticFiles::assets`] are also served under their fingerprinted names,
+//! e.g. `/static/app-3f2a9c1b7d6e5f40.css`, and cached by browsers forever. See [`crate::assets`].
 use super::{Controller, Error};
+use crate::assets::{self, Manifest};
+use crate::config::get_config;
 use crate::http::{Body, Handler, Request, Response};
 use std::{
     collections::HashMap,
+    fs::Metadata,
+    io::SeekFrom,
     path::{Path, PathBuf},
+    sync::Arc,
+    time::UNIX_EPOCH,
 };
 
 use async_trait::async_trait;
-use time::Duration;
-use tokio::fs::File;
+use time::{format_description::FormatItem, Duration, OffsetDateTime, PrimitiveDateTime};
+use tokio::{fs::File, io::AsyncSeekExt};
 use tracing::debug;
 
+// IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+static HTTP_DATE: &str =
+    "[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT";
+
 /// Cache control header.
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum CacheControl {
@@ -26,6 +43,8 @@ pub enum CacheControl {
     MaxAge(Duration),
     Private,
     NoCache,
+    /// Cached for a year and never revalidated, for files with the hash of their content in their name.
+    Immutable,
 }
 
 impl std::fmt::Display for CacheControl {
@@ -36,6 +55,7 @@ impl std::fmt::Display for CacheControl {
             MaxAge(duration) => format!("max-age={}", duration.whole_seconds()),
             Private => "private".into(),
             NoCache => "no-cache".into(),
+            Immutable => "public, max-age=31536000, immutable".into(),
         };
 
         write!(f, "{}", s)
@@ -48,6 +68,9 @@ pub struct StaticFiles {
     root: PathBuf,
     preloads: HashMap<PathBuf, Body>,
     cache_control: CacheControl,
+    extensions: HashMap<String, CacheControl>,
+    index: Option<String>,
+    manifest: Option<Arc<Manifest>>,
 }
 
 impl StaticFiles {
@@ -74,11 +97,34 @@ impl StaticFiles {
             root,
             preloads: HashMap::new(),
             cache_control: CacheControl::NoStore,
+            extensions: HashMap::new(),
+            index: None,
+            manifest: None,
         };
 
         Ok(statics)
     }
 
+    /// Serve the assets configured in the `[assets]` section of `rwf.toml`. Assets are also
+    /// served under their fingerprinted names, which browsers cache forever, and revalidated otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use rwf::controller::StaticFiles;
+    /// # use rwf::http::Server;
+    /// let server = Server::new(vec![StaticFiles::assets().unwrap()]);
+    /// ```
+    pub fn assets() -> std::io::Result<Handler> {
+        let config = &get_config().assets;
+
+        Ok(Self::new(&config.directory.display().to_string())?
+            .prefix(&config.prefix)
+            .cache_control(CacheControl::NoCache)
+            .fingerprinted(assets::manifest())
+            .handler())
+    }
+
     /// Serve static files with the specified `Cache-Control: max-age` attribute.
     pub fn cached(path: &str, duration: Duration) -> std::io::Result<Handler> {
         Ok(Self::new(path)?
@@ -111,6 +157,50 @@ impl StaticFiles {
         self
     }
 
+    /// Set the `Cache-Control` header for files with this extension, overriding
+    /// the default set with [`StaticFiles::cache_control`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rwf::controller::{CacheControl, StaticFiles};
+    /// # use time::Duration;
+    /// StaticFiles::new("static")
+    ///     .unwrap()
+    ///     .cache_control(CacheControl::NoCache)
+    ///     .cache_control_for("woff2", CacheControl::MaxAge(Duration::days(365)))
+    ///     .cache_control_for("css", CacheControl::MaxAge(Duration::hours(1)));
+    /// ```
+    pub fn cache_control_for(mut self, extension: &str, cache_control: CacheControl) -> Self {
+        self.extensions.insert(
+            extension.trim_start_matches('.').to_lowercase(),
+            cache_control,
+        );
+        self
+    }
+
+    /// Serve files in the manifest under their fingerprinted names, with `Cache-Control: immutable`.
+    pub fn fingerprinted(mut self, manifest: Arc<Manifest>) -> Self {
+        self.manifest = Some(manifest);
+        self
+    }
+
+    /// Serve this file, e.g. `index.html`, when a directory is requested.
+    /// By default, requests for directories return `404 - Not Found`.
+    pub fn index(mut self, file: &str) -> Self {
+        self.index = Some(file.to_string());
+        self
+    }
+
+    /// Get the `Cache-Control` header for a file.
+    fn cache_control_of(&self, path: &Path) -> CacheControl {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| self.extensions.get(&extension.to_lowercase()))
+            .copied()
+            .unwrap_or(self.cache_control)
+    }
+
     /// Set the prefix used in URLs.
     ///
     /// For example, if the prefix `static` is set,
@@ -150,6 +240,18 @@ impl Controller for StaticFiles {
             })
             .collect::<PathBuf>();
 
+        // Fingerprinted names are mapped back to the file.
+        let original = self.manifest.as_ref().and_then(|manifest| {
+            let path = path
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            manifest.original(&path).map(PathBuf::from)
+        });
+        let immutable = original.is_some();
+        let path = original.unwrap_or(path);
+
         // Replace the prefix with the root.
         let path = PathBuf::from(self.root.join(path));
 
@@ -168,23 +270,395 @@ impl Controller for StaticFiles {
             return Ok(Response::not_found());
         }
 
-        match File::open(&path).await {
-            Ok(file) => {
-                let metadata = match file.metadata().await {
-                    Ok(metadata) => metadata,
-                    Err(err) => return Ok(Response::internal_error(err)),
-                };
+        let is_dir = tokio::fs::metadata(&path)
+            .await
+            .map(|metadata| metadata.is_dir())
+            .unwrap_or(false);
 
-                if !metadata.is_file() {
-                    return Ok(Response::not_found());
+        let path = if is_dir {
+            match self.index {
+                Some(ref index) => path.join(index),
+                None => return Ok(Response::not_found()),
+            }
+        } else {
+            path
+        };
+
+        let mut file = match File::open(&path).await {
+            Ok(file) => file,
+            Err(_) => return Ok(Response::not_found()),
+        };
+
+        let metadata = match file.metadata().await {
+            Ok(metadata) => metadata,
+            Err(err) => return Ok(Response::internal_error(err)),
+        };
+
+        if !metadata.is_file() {
+            return Ok(Response::not_found());
+        }
+
+        let validators = Validators::new(&metadata);
+        let cache_control = if immutable {
+            CacheControl::Immutable
+        } else {
+            self.cache_control_of(&path)
+        };
+        let mut response = Response::new()
+            .header("cache-control", cache_control)
+            .header("accept-ranges", "bytes")
+            .header("etag", &validators.etag);
+
+        if let Some(ref last_modified) = validators.last_modified {
+            response = response.header("last-modified", last_modified);
+        }
+
+        if validators.not_modified(request) {
+            return Ok(response.code(304));
+        }
+
+        let range = match request.header("range") {
+            Some(range) if validators.fresh(request.header("if-range")) => {
+                parse_range(range, metadata.len())
+            }
+            _ => None,
+        };
+
+        match range {
+            None => Ok(response.body((path, file, metadata))),
+            Some(Err(())) => Ok(response
+                .header("content-range", format!("bytes */{}", metadata.len()))
+                .code(416)),
+            Some(Ok((start, end))) => {
+                if let Err(err) = file.seek(SeekFrom::Start(start)).await {
+                    return Ok(Response::internal_error(err));
                 }
 
-                let response =
-                    Response::new().header("cache-control", self.cache_control.to_string());
+                Ok(response
+                    .body(Body::FileRange {
+                        path,
+                        file,
+                        length: end - start + 1,
+                    })
+                    .header(
+                        "content-range",
+                        format!("bytes {}-{}/{}", start, end, metadata.len()),
+                    )
+                    .code(206))
+            }
+        }
+    }
+}
+
+/// Cache validators of a file.
+struct Validators {
+    etag: String,
+    last_modified: Option<String>,
+    modified: Option<OffsetDateTime>,
+}
+
+impl Validators {
+    fn new(metadata: &Metadata) -> Self {
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok());
+
+        // The file changes if either its size or modification time change.
+        let etag = format!(
+            r#""{:x}-{:x}""#,
+            metadata.len(),
+            modified.map(|modified| modified.as_nanos()).unwrap_or(0)
+        );
 
-                Ok(response.body((path, file, metadata)))
+        let modified = modified.and_then(|modified| {
+            OffsetDateTime::from_unix_timestamp(modified.as_secs() as i64).ok()
+        });
+
+        Self {
+            etag,
+            last_modified: modified.and_then(|modified| modified.format(&http_date()).ok()),
+            modified,
+        }
+    }
+
+    /// The client has the current version of the file.
+    fn not_modified(&self, request: &Request) -> bool {
+        // If-None-Match takes precedence over If-Modified-Since.
+        if let Some(if_none_match) = request.header("if-none-match") {
+            return if_none_match.split(',').any(|etag| {
+                let etag = etag.trim();
+                etag == "*" || etag.trim_start_matches("W/") == self.etag
+            });
+        }
+
+        match (request.header("if-modified-since"), self.modified) {
+            (Some(since), Some(modified)) => {
+                parse_http_date(since).is_some_and(|since| modified <= since)
             }
-            Err(_) => return Ok(Response::not_found()),
+            _ => false,
+        }
+    }
+
+    /// The `If-Range` condition matches the file, or is not set.
+    fn fresh(&self, if_range: Option<&String>) -> bool {
+        match if_range {
+            None => true,
+            Some(if_range) if if_range.starts_with('"') => *if_range == self.etag,
+            Some(if_range) => self
+                .last_modified
+                .as_ref()
+                .is_some_and(|last_modified| last_modified == if_range),
         }
     }
 }
+
+fn http_date() -> Vec<FormatItem<'static>> {
+    time::format_description::parse(HTTP_DATE).expect("valid HTTP date format")
+}
+
+fn parse_http_date(date: &str) -> Option<OffsetDateTime> {
+    PrimitiveDateTime::parse(date.trim(), &http_date())
+        .map(|date| date.assume_utc())
+        .or_else(|_| {
+            OffsetDateTime::parse(date.trim(), &time::format_description::well_known::Rfc2822)
+        })
+        .ok()
+}
+
+/// Parse the `Range` header, returning the first and last byte of the range, inclusive.
+///
+/// Only single byte ranges are supported; for anything else `None` is returned and the whole
+/// file is sent. Ranges outside of the file return an error.
+fn parse_range(header: &str, size: u64) -> Option<Result<(u64, u64), ()>> {
+    let range = header.trim().strip_prefix("bytes=")?;
+
+    if range.contains(',') {
+        return None;
+    }
+
+    let (start, end) = range.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    let range = if start.is_empty() {
+        // Suffix range, e.g. `bytes=-500` are the last 500 bytes.
+        let suffix = end.parse::<u64>().ok()?;
+        if suffix == 0 || size == 0 {
+            return Some(Err(()));
+        }
+        (size.saturating_sub(suffix), size - 1)
+    } else {
+        let start = start.parse::<u64>().ok()?;
+        let end = if end.is_empty() {
+            u64::MAX
+        } else {
+            end.parse::<u64>().ok()?
+        };
+
+        if start > end {
+            return None;
+        }
+
+        if start >= size {
+            return Some(Err(()));
+        }
+
+        (start, end.min(size - 1))
+    };
+
+    Some(Ok(range))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::test::dummy_ip;
+
+    async fn request(path: &str, headers: &[(&str, &str)]) -> Request {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}\r\n", name, value))
+            .collect::<String>();
+        let req = format!("GET {} HTTP/1.1\r\n{}\r\n", path, headers);
+        Request::read(dummy_ip(), req.as_bytes()).await.unwrap()
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some(Ok((0, 99))));
+        assert_eq!(parse_range("bytes=500-", 1000), Some(Ok((500, 999))));
+        assert_eq!(parse_range("bytes=-100", 1000), Some(Ok((900, 999))));
+        assert_eq!(parse_range("bytes=-2000", 1000), Some(Ok((0, 999))));
+        assert_eq!(parse_range("bytes=900-2000", 1000), Some(Ok((900, 999))));
+        assert_eq!(parse_range("bytes=1000-", 1000), Some(Err(())));
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), None);
+        assert_eq!(parse_range("bytes=5-1", 1000), None);
+        assert_eq!(parse_range("items=0-1", 1000), None);
+    }
+
+    #[test]
+    fn test_http_date() {
+        let date = parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT").unwrap();
+        assert_eq!(date.unix_timestamp(), 784111777);
+        assert_eq!(
+            date.format(&http_date()).unwrap(),
+            "Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+        assert!(parse_http_date("yesterday").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_static_files() {
+        let root = std::env::temp_dir().join("rwf_test_static_files");
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::write(root.join("video.mp4"), b"0123456789").unwrap();
+        std::fs::write(root.join("style.CSS"), b"body {}").unwrap();
+        std::fs::write(root.join("docs/index.html"), b"<h1>Docs</h1>").unwrap();
+        let root = root.canonicalize().unwrap();
+
+        let statics = StaticFiles::new(root.to_str().unwrap())
+            .unwrap()
+            .prefix("/static")
+            .cache_control_for("css", CacheControl::MaxAge(Duration::hours(1)));
+
+        let response = statics
+            .handle(&request("/static/video.mp4", &[]).await)
+            .await
+            .unwrap();
+        assert_eq!(response.status().code(), 200);
+        assert_eq!(response.headers().get("accept-ranges").unwrap(), "bytes");
+        assert_eq!(response.headers().get("cache-control").unwrap(), "no-store");
+        assert_eq!(response.headers().get("content-length").unwrap(), "10");
+        let etag = response.headers().get("etag").unwrap().clone();
+        let last_modified = response.headers().get("last-modified").unwrap().clone();
+
+        let response = statics
+            .handle(&request("/static/style.CSS", &[]).await)
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers().get("cache-control").unwrap(),
+            "max-age=3600"
+        );
+
+        // Conditional requests.
+        let response = statics
+            .handle(&request("/static/video.mp4", &[("If-None-Match", &etag)]).await)
+            .await
+            .unwrap();
+        assert_eq!(response.status().code(), 304);
+
+        let response = statics
+            .handle(&request("/static/video.mp4", &[("If-None-Match", "\"other\"")]).await)
+            .await
+            .unwrap();
+        assert_eq!(response.status().code(), 200);
+
+        let response = statics
+            .handle(
+                &request(
+                    "/static/video.mp4",
+                    &[("If-Modified-Since", &last_modified)],
+                )
+                .await,
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status().code(), 304);
+
+        // Ranges.
+        let response = statics
+            .handle(&request("/static/video.mp4", &[("Range", "bytes=2-5")]).await)
+            .await
+            .unwrap();
+        assert_eq!(response.status().code(), 206);
+        assert_eq!(
+            response.headers().get("content-range").unwrap(),
+            "bytes 2-5/10"
+        );
+        assert_eq!(response.headers().get("content-length").unwrap(), "4");
+        assert_eq!(response.headers().get("content-type").unwrap(), "video/mp4");
+        let mut sent = vec![];
+        response.send(&mut sent).await.unwrap();
+        assert!(sent.ends_with(b"\r\n\r\n2345"));
+
+        let response = statics
+            .handle(&request("/static/video.mp4", &[("Range", "bytes=20-")]).await)
+            .await
+            .unwrap();
+        assert_eq!(response.status().code(), 416);
+        assert_eq!(
+            response.headers().get("content-range").unwrap(),
+            "bytes */10"
+        );
+
+        // Stale If-Range sends the whole file.
+        let response = statics
+            .handle(
+                &request(
+                    "/static/video.mp4",
+                    &[("Range", "bytes=2-5"), ("If-Range", "\"stale\"")],
+                )
+                .await,
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status().code(), 200);
+
+        // Directory index is opt-in.
+        let response = statics
+            .handle(&request("/static/docs", &[]).await)
+            .await
+            .unwrap();
+        assert_eq!(response.status().code(), 404);
+
+        let statics = statics.index("index.html");
+        let response = statics
+            .handle(&request("/static/docs", &[]).await)
+            .await
+            .unwrap();
+        assert_eq!(response.status().code(), 200);
+        assert_eq!(response.headers().get("content-type").unwrap(), "text/html");
+    }
+
+    #[tokio::test]
+    async fn test_fingerprinted() {
+        let root = std::env::temp_dir().join("rwf_test_fingerprinted");
+        std::fs::create_dir_all(root.join("css")).unwrap();
+        std::fs::write(root.join("css/app.css"), b"body {}").unwrap();
+        let root = root.canonicalize().unwrap();
+
+        let manifest = Manifest::build(&root).unwrap();
+        let fingerprinted = manifest.fingerprinted("css/app.css").unwrap().to_string();
+
+        let statics = StaticFiles::new(root.to_str().unwrap())
+            .unwrap()
+            .prefix("/static")
+            .cache_control(CacheControl::NoCache)
+            .fingerprinted(Arc::new(manifest));
+
+        let response = statics
+            .handle(&request(&format!("/static/{}", fingerprinted), &[]).await)
+            .await
+            .unwrap();
+        assert_eq!(response.status().code(), 200);
+        assert_eq!(
+            response.headers().get("cache-control").unwrap(),
+            "public, max-age=31536000, immutable"
+        );
+        assert_eq!(response.headers().get("content-type").unwrap(), "text/css");
+
+        let response = statics
+            .handle(&request("/static/css/app.css", &[]).await)
+            .await
+            .unwrap();
+        assert_eq!(response.status().code(), 200);
+        assert_eq!(response.headers().get("cache-control").unwrap(), "no-cache");
+
+        let response = statics
+            .handle(&request("/static/css/app-0000000000000000.css", &[]).await)
+            .await
+            .unwrap();
+        assert_eq!(response.status().code(), 404);
+    }
+}
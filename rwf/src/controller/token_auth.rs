@@ -0,0 +1,640 @@
+//! Bearer token authentication for JSON APIs.
+//!
+//! [`TokenAuth`] validates the token sent in the `Authorization: Bearer` header. Tokens are either
+//! JSON Web Tokens, signed with a shared secret (HS256) or with a key published by an identity provider
+//! in a JWKS document (RS256), or opaque tokens, e.g. API keys, checked by the application with a [`TokenValidator`].
+//!
+//! Once the token is validated, the request is authenticated to the token's user, so
+//! [`Request::user_id`] and [`Request::user`] work like with sessions. No session cookie is sent
+//! back to the client, and CSRF protection isn't needed.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! struct Api {
+//!     auth: AuthHandler,
+//! }
+//!
+//! impl Default for Api {
+//!     fn default() -> Self {
+//!         Self {
+//!             auth: TokenAuth::hs256(secret).issuer("https://example.com").handler(),
+//!         }
+//!     }
+//! }
+//! ```
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hmac::{Hmac, Mac};
+use parking_lot::RwLock;
+use ring::signature::{RsaPublicKeyComponents, RSA_PKCS1_2048_8192_SHA256};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use sha2::Sha256;
+use thiserror::Error;
+use time::OffsetDateTime;
+use tracing::{debug, warn};
+
+use super::{Authentication, Error as ControllerError};
+use crate::http::{client::Client, Authorization, Request, Response};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How long keys fetched from a JWKS endpoint are used before fetching them again.
+const JWKS_TTL: Duration = Duration::from_secs(3600);
+
+/// Error returned when a token is rejected.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The token isn't a JWT.
+    #[error("token is malformed")]
+    Malformed,
+
+    /// The token is signed with an algorithm this handler doesn't accept.
+    #[error("token algorithm \"{0}\" is not accepted")]
+    Algorithm(String),
+
+    /// The token is signed with a key this handler doesn't know.
+    #[error("token key is unknown")]
+    UnknownKey,
+
+    /// The signature is invalid.
+    #[error("token signature is invalid")]
+    Signature,
+
+    /// The token has expired.
+    #[error("token has expired")]
+    Expired,
+
+    /// The token doesn't have an `exp` claim.
+    #[error("token has no expiration")]
+    MissingExpiration,
+
+    /// The token can't be used yet.
+    #[error("token is not valid yet")]
+    NotYetValid,
+
+    /// The token was issued by someone else.
+    #[error("token issuer is invalid")]
+    Issuer,
+
+    /// The token was issued for another application.
+    #[error("token audience is invalid")]
+    Audience,
+
+    /// Couldn't fetch the JWKS document.
+    #[error("jwks: {0}")]
+    Jwks(String),
+
+    /// Error encoding/decoding JSON.
+    #[error("json: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Finds the user a token belongs to.
+#[async_trait]
+pub trait TokenValidator: Send + Sync {
+    /// Return the ID of the token's user, or `None` to reject it. `claims` are the JWT's claims,
+    /// after its signature has been verified, or `null` for opaque tokens.
+    async fn user(&self, token: &str, claims: &Json) -> Result<Option<i64>, ControllerError>;
+}
+
+/// Bearer token authentication.
+pub struct TokenAuth {
+    keys: Keys,
+    validator: Option<Arc<dyn TokenValidator>>,
+    issuer: Option<String>,
+    audience: Option<String>,
+    leeway: i64,
+}
+
+enum Keys {
+    Hs256(Vec<u8>),
+    Jwks(Jwks),
+    Opaque,
+}
+
+impl TokenAuth {
+    /// Accept JWTs signed with HS256 and this secret. The user's ID is read from the `sub` claim.
+    pub fn hs256(secret: impl AsRef<[u8]>) -> Self {
+        Self::new(Keys::Hs256(secret.as_ref().to_vec()))
+    }
+
+    /// Accept JWTs signed with RS256 and one of the keys published at this URL, e.g.
+    /// `https://www.googleapis.com/oauth2/v3/certs`. The keys are cached for an hour.
+    ///
+    /// Providers sign tokens issued to all their clients with the same keys, so tokens
+    /// must be issued for this audience (`aud` claim), e.g. the app's client ID.
+    pub fn jwks(url: impl ToString, audience: impl ToString) -> Self {
+        Self::new(Keys::Jwks(Jwks::new(url.to_string()))).audience(audience)
+    }
+
+    /// Accept opaque tokens, e.g. API keys, checked by the validator.
+    pub fn opaque(validator: impl TokenValidator + 'static) -> Self {
+        Self::new(Keys::Opaque).validator(validator)
+    }
+
+    fn new(keys: Keys) -> Self {
+        Self {
+            keys,
+            validator: None,
+            issuer: None,
+            audience: None,
+            leeway: 60,
+        }
+    }
+
+    /// Find the user with this validator, e.g. when the `sub` claim isn't the user's ID,
+    /// or to check the token hasn't been revoked.
+    pub fn validator(mut self, validator: impl TokenValidator + 'static) -> Self {
+        self.validator = Some(Arc::new(validator));
+        self
+    }
+
+    /// Only accept JWTs issued by this issuer (`iss` claim).
+    pub fn issuer(mut self, issuer: impl ToString) -> Self {
+        self.issuer = Some(issuer.to_string());
+        self
+    }
+
+    /// Only accept JWTs issued for this audience (`aud` claim), e.g. the app's client ID.
+    pub fn audience(mut self, audience: impl ToString) -> Self {
+        self.audience = Some(audience.to_string());
+        self
+    }
+
+    /// How much clock difference is tolerated when checking `exp` and `nbf`. Default: 60 seconds.
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway.as_secs() as i64;
+        self
+    }
+
+    /// Verify the JWT and return its claims.
+    pub async fn verify(&self, token: &str) -> Result<Json, Error> {
+        let parts = token.split('.').collect::<Vec<_>>();
+        if parts.len() != 3 {
+            return Err(Error::Malformed);
+        }
+
+        let header: Header = serde_json::from_slice(&decode(parts[0])?)?;
+        let claims: Json = serde_json::from_slice(&decode(parts[1])?)?;
+        let signature = decode(parts[2])?;
+        let message = &token[..parts[0].len() + parts[1].len() + 1];
+
+        // The algorithm is checked against the keys, so a token can't
+        // pick a weaker one, e.g. `none`.
+        match (&self.keys, header.alg.as_str()) {
+            (Keys::Hs256(secret), "HS256") => {
+                let mut mac = HmacSha256::new_from_slice(secret).expect("hmac key");
+                mac.update(message.as_bytes());
+                mac.verify_slice(&signature).map_err(|_| Error::Signature)?;
+            }
+
+            (Keys::Jwks(jwks), "RS256") => {
+                let key = jwks.key(header.kid.as_deref()).await?;
+                RsaPublicKeyComponents {
+                    n: &key.n,
+                    e: &key.e,
+                }
+                .verify(&RSA_PKCS1_2048_8192_SHA256, message.as_bytes(), &signature)
+                .map_err(|_| Error::Signature)?;
+            }
+
+            (_, alg) => return Err(Error::Algorithm(alg.to_string())),
+        }
+
+        self.validate(&claims)?;
+
+        Ok(claims)
+    }
+
+    /// Check the registered claims. Tokens must expire.
+    fn validate(&self, claims: &Json) -> Result<(), Error> {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        match claims["exp"].as_i64() {
+            Some(exp) if exp + self.leeway <= now => return Err(Error::Expired),
+            Some(_) => (),
+            None => return Err(Error::MissingExpiration),
+        }
+
+        if let Some(nbf) = claims["nbf"].as_i64() {
+            if nbf - self.leeway > now {
+                return Err(Error::NotYetValid);
+            }
+        }
+
+        if let Some(ref issuer) = self.issuer {
+            if claims["iss"].as_str() != Some(issuer.as_str()) {
+                return Err(Error::Issuer);
+            }
+        }
+
+        if let Some(ref audience) = self.audience {
+            let valid = match &claims["aud"] {
+                Json::String(aud) => aud == audience,
+                Json::Array(aud) => aud.iter().any(|aud| aud.as_str() == Some(audience)),
+                _ => false,
+            };
+
+            if !valid {
+                return Err(Error::Audience);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Find the user the token belongs to.
+    async fn user(&self, token: &str) -> Result<Option<(i64, Json)>, ControllerError> {
+        let claims = match self.keys {
+            Keys::Opaque => Json::Null,
+            _ => match self.verify(token).await {
+                Ok(claims) => claims,
+                Err(Error::Jwks(err)) => return Err(ControllerError::new(Error::Jwks(err))),
+                Err(err) => {
+                    debug!("bearer token rejected: {}", err);
+                    return Ok(None);
+                }
+            },
+        };
+
+        let user_id = match self.validator {
+            Some(ref validator) => validator.user(token, &claims).await?,
+            None => match &claims["sub"] {
+                Json::Number(sub) => sub.as_i64(),
+                Json::String(sub) => sub.parse().ok(),
+                _ => None,
+            },
+        };
+
+        Ok(user_id.map(|user_id| match claims {
+            Json::Null => (user_id, serde_json::json!({ "sub": user_id })),
+            claims => (user_id, claims),
+        }))
+    }
+}
+
+#[async_trait]
+impl Authentication for TokenAuth {
+    async fn authorize(&self, request: &Request) -> Result<bool, ControllerError> {
+        Ok(self.authenticate(request.clone()).await?.1)
+    }
+
+    async fn authenticate(&self, request: Request) -> Result<(Request, bool), ControllerError> {
+        let token = match request.authorization() {
+            Some(Authorization::Bearer { token }) => token,
+            _ => return Ok((request, false)),
+        };
+
+        match self.user(&token).await? {
+            Some((user_id, claims)) => Ok((request.with_token(user_id, claims), true)),
+            None => Ok((request, false)),
+        }
+    }
+
+    async fn denied(&self, _request: &Request) -> Result<Response, ControllerError> {
+        Ok(Response::unauthorized(Some("Bearer")))
+    }
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    #[serde(default)]
+    kid: Option<String>,
+}
+
+/// Sign the claims with HS256, creating a JWT accepted by [`TokenAuth::hs256`].
+pub fn encode(claims: &impl Serialize, secret: impl AsRef<[u8]>) -> Result<String, Error> {
+    let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#);
+    let claims = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims)?);
+    let message = format!("{}.{}", header, claims);
+
+    let mut mac = HmacSha256::new_from_slice(secret.as_ref()).expect("hmac key");
+    mac.update(message.as_bytes());
+    let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+
+    Ok(format!("{}.{}", message, signature))
+}
+
+fn decode(part: &str) -> Result<Vec<u8>, Error> {
+    URL_SAFE_NO_PAD.decode(part).map_err(|_| Error::Malformed)
+}
+
+/// RSA public key from a JWKS document.
+#[derive(Clone)]
+struct RsaKey {
+    n: Vec<u8>,
+    e: Vec<u8>,
+}
+
+/// Keys published by an identity provider.
+struct Jwks {
+    url: String,
+    keys: RwLock<(HashMap<String, RsaKey>, Option<Instant>)>,
+}
+
+impl Jwks {
+    fn new(url: String) -> Self {
+        Self {
+            url,
+            keys: RwLock::new((HashMap::new(), None)),
+        }
+    }
+
+    /// Get the key with this ID, fetching the keys again if they're stale
+    /// or the key is unknown, e.g. because the provider rotated them.
+    async fn key(&self, kid: Option<&str>) -> Result<RsaKey, Error> {
+        let kid = kid.unwrap_or_default();
+
+        let refresh = {
+            let keys = self.keys.read();
+            if let (Some(key), Some(fetched_at)) = (keys.0.get(kid), keys.1) {
+                if fetched_at.elapsed() < JWKS_TTL {
+                    return Ok(key.clone());
+                }
+            }
+
+            // Don't let tokens with unknown keys make us fetch the keys on every request.
+            keys.1
+                .map(|fetched_at| fetched_at.elapsed() >= Duration::from_secs(60))
+                .unwrap_or(true)
+        };
+
+        if refresh {
+            match self.fetch().await {
+                Ok(fetched) => *self.keys.write() = (fetched, Some(Instant::now())),
+                Err(err) => {
+                    warn!("couldn't fetch keys from \"{}\": {}", self.url, err);
+
+                    // Keep using the keys we have.
+                    if self.keys.read().0.is_empty() {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+
+        self.keys
+            .read()
+            .0
+            .get(kid)
+            .cloned()
+            .ok_or(Error::UnknownKey)
+    }
+
+    async fn fetch(&self) -> Result<HashMap<String, RsaKey>, Error> {
+        #[derive(Deserialize)]
+        struct Document {
+            keys: Vec<Key>,
+        }
+
+        #[derive(Deserialize)]
+        struct Key {
+            kty: String,
+            #[serde(default)]
+            kid: Option<String>,
+            #[serde(default)]
+            n: Option<String>,
+            #[serde(default)]
+            e: Option<String>,
+        }
+
+        let response = Client::get(&self.url)
+            .send()
+            .await
+            .map_err(|err| Error::Jwks(err.to_string()))?;
+        if !response.ok() {
+            return Err(Error::Jwks(format!("status code {}", response.code())));
+        }
+
+        let document: Document = response
+            .json()
+            .map_err(|err| Error::Jwks(err.to_string()))?;
+        let mut keys = HashMap::new();
+
+        for key in document.keys {
+            if let ("RSA", Some(n), Some(e)) = (key.kty.as_str(), key.n, key.e) {
+                keys.insert(
+                    key.kid.unwrap_or_default(),
+                    RsaKey {
+                        n: decode(&n)?,
+                        e: decode(&e)?,
+                    },
+                );
+            }
+        }
+
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::controller::Controller;
+    use crate::http::{request::test::dummy_ip, Router, Server};
+    use ring::{rand::SystemRandom, signature::RsaKeyPair};
+    use serde_json::json;
+
+    // Test-only 2048-bit key, PKCS#8.
+    const RSA_KEY: &str = "
+MIIEvAIBADANBgkqhkiG9w0BAQEFAASCBKYwggSiAgEAAoIBAQCvp6SPPdT2/Hyh
+FhU1BRdCh8xE61wFMeBgvQDt7GCYA5z9vmAy8+KukU3pvB+somsoN6jOTsnxwybR
+ju/g0LujS+UAVRrMTYHsQnaGNVTbbS9vI3CpLCj6ZxYq3Dd9fW6BHwdQrqwmTebI
+DHKa0Vy9RzQrbh09r16fLzHz6ooFHcomf5NsEdEyYw+SWZUYXAdmBZwcdq2dL+Yt
+3GCLnXamXbz/VcmcSDoXv7PEscic9sGPG13faRGDHvxMPhkjdgbLG+arZqlkOSv+
+QQdMN7rVaODQpPtD3L9t874omAsz7T9PdBruXTWTqe+Z3Tkxhoh5uR4oe5WQ6Jh9
+Y+BF73IDAgMBAAECggEALKxCnkl37Z62JZPDrfnO+hUmXsrK2uld6sx2vYvV3yNg
+HGp79clv97ytp4m4NoQSI6bQMDP6iRl8OGSVXkRHzFaDdauvRpFZvxgCI8zReYLR
+wv6s6uF3xINOMHVUSX6KiZ02E49w6cb7MBp2VSh04RCIVJlLJ4J/2pU6q5GVK4qF
+gw9NawWLh5qTDG54jOnqSLoosdFgk3jVKdcOCDHuJgaSDO/rDi3KpJP32Ww6Bg30
+X5kUd5ZdNWcXhapc/UUn/7H5XyGjoa6KtePOZIZfbaT+rek75RiOrLNXD0BtXjqS
+04/FfBmkD6fHcmSzEFatI4D1N0mZxlQdSpoCxGFKYQKBgQDd7gKRAZbnNGcwzcAL
+XQPcOkVY0Ec7u3mRrQN87uCTTZ5L/CwnGojeOsMgslcyg/WLwSpmym6bBPUc6lDh
+NiAoba9S9ReivcJo4MJikKIArD1pLEwVm6TF/TnKPHZTF20xPqsErMnF/Uqk20Iy
+z2PtRCAbqvJxG8AVWkOqQ/vOoQKBgQDKnv+pv/J4MXtViHvBucVHD6MHHOC3Deby
+mdVV3aGU7kgtBFSWRY6YlIz/nACLLEXxn7/Ev0bFSg2BV3gAiiakfGd0I745CAH6
+2tvBJmGXS4uA744YxnYyue+ZP2zPAnG1ZQeFgUqxLKg3TgcftcsREsbeeB3hWLjq
+/D85KG7yIwKBgHYJhnHrn4BfXdcWR+ODWG9Eyud+97QsdW9mzykewHF4Yg5WFIHD
+2jIgnPICCODEUNBBMDU1y/hFfb0gX145dIcsN4ju9OBI/2La0GiuNowXEB6lQd5o
+Rw5LLfaDjAeLuTbs9vbok/TrUE04lRADQEnM5yrwFDzWnqZ8uOqReBehAoGATPVv
+Lc5WDgjdS3N3bfFdWMw7o6v4Pg7ttR9wxBhWEvceOtr2Nc1dh96EX2GlYzcBqfB/
+Kf9speqmjoXmcm4Gl1sZACfDTG4aMmmGJiqpzolHB0X1trtKkaHFu3M3pLjM75Tb
+8n1VHU5U5gyg8A5gfq+fEUiWMM0+DyKX8OHGofkCgYBXSKTND7f9dA7l57L93EzT
+C+wMctcnm9+URS4D0Vvppt6bMediOlTA7cf50cdFkbp4FB1nsXiaGCpbiw+lDn3b
+g8nr06bB25PSY969bal8znRp8/UwcUcY+iI4dwO4rBBt5lR2fUm6jcdSAJM9yrEH
+fhc7d/kD/o4jZAiOvGFMBg==";
+
+    async fn bearer(token: &str) -> Request {
+        Request::read(
+            dummy_ip(),
+            format!(
+                "GET /api HTTP/1.1\r\nAuthorization: Bearer {}\r\n\r\n",
+                token
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap()
+    }
+
+    fn now() -> i64 {
+        OffsetDateTime::now_utc().unix_timestamp()
+    }
+
+    #[tokio::test]
+    async fn test_hs256() {
+        let auth = TokenAuth::hs256("secret").issuer("rwf");
+
+        let token = encode(
+            &json!({"sub": 5, "iss": "rwf", "exp": now() + 60}),
+            "secret",
+        )
+        .unwrap();
+        let (request, allowed) = auth.authenticate(bearer(&token).await).await.unwrap();
+        assert!(allowed);
+        assert_eq!(request.user_id().unwrap(), 5);
+        assert_eq!(request.token_claims().unwrap()["iss"], "rwf");
+
+        // No session cookie is sent back.
+        let response = Response::new().from_request(&request).unwrap();
+        assert!(response.session().is_none());
+
+        for (claims, secret) in [
+            (
+                json!({"sub": 5, "iss": "rwf", "exp": now() - 120}),
+                "secret",
+            ),
+            (
+                json!({"sub": 5, "iss": "rwf", "nbf": now() + 120}),
+                "secret",
+            ),
+            (json!({"sub": 5, "iss": "rwf"}), "secret"),
+            (
+                json!({"sub": 5, "iss": "other", "exp": now() + 60}),
+                "secret",
+            ),
+            (json!({"iss": "rwf"}), "secret"),
+            (json!({"sub": 5, "iss": "rwf"}), "wrong"),
+        ] {
+            let token = encode(&claims, secret).unwrap();
+            let (request, allowed) = auth.authenticate(bearer(&token).await).await.unwrap();
+            assert!(!allowed);
+            assert!(request.user_id().is_err());
+        }
+
+        // alg: none
+        let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"none"}"#);
+        let claims = URL_SAFE_NO_PAD.encode(r#"{"sub":5,"iss":"rwf"}"#);
+        assert!(matches!(
+            auth.verify(&format!("{}.{}.", header, claims)).await,
+            Err(Error::Algorithm(_))
+        ));
+    }
+
+    struct ApiKeys;
+
+    #[async_trait]
+    impl TokenValidator for ApiKeys {
+        async fn user(&self, token: &str, claims: &Json) -> Result<Option<i64>, ControllerError> {
+            assert!(claims.is_null());
+            Ok(if token == "key_1234" { Some(7) } else { None })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_opaque() {
+        let auth = TokenAuth::opaque(ApiKeys);
+
+        let (request, allowed) = auth.authenticate(bearer("key_1234").await).await.unwrap();
+        assert!(allowed);
+        assert_eq!(request.user_id().unwrap(), 7);
+
+        let (_, allowed) = auth.authenticate(bearer("key_5678").await).await.unwrap();
+        assert!(!allowed);
+    }
+
+    struct JwksEndpoint(Json);
+
+    #[async_trait]
+    impl Controller for JwksEndpoint {
+        async fn handle(&self, _request: &Request) -> Result<Response, ControllerError> {
+            Ok(Response::new().json(&self.0)?)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rs256() {
+        let der = base64::engine::general_purpose::STANDARD
+            .decode(RSA_KEY.replace('\n', ""))
+            .unwrap();
+        let key = RsaKeyPair::from_pkcs8(&der).unwrap();
+        let public = RsaPublicKeyComponents::<Vec<u8>>::from(key.public());
+        let jwks = json!({"keys": [{
+            "kty": "RSA",
+            "kid": "1",
+            "n": URL_SAFE_NO_PAD.encode(&public.n),
+            "e": URL_SAFE_NO_PAD.encode(&public.e),
+        }]});
+
+        let router = Router::new(vec![JwksEndpoint(jwks).route("/jwks")]).unwrap();
+        let addr = Server::serve_test(Arc::new(router)).await;
+        let auth = TokenAuth::jwks(format!("http://{}/jwks", addr), "app");
+
+        let sign_claims = |kid: &str, claims: Json| {
+            let header = URL_SAFE_NO_PAD.encode(json!({"alg": "RS256", "kid": kid}).to_string());
+            let claims = URL_SAFE_NO_PAD.encode(claims.to_string());
+            let message = format!("{}.{}", header, claims);
+            let mut signature = vec![0; key.public().modulus_len()];
+            key.sign(
+                &ring::signature::RSA_PKCS1_SHA256,
+                &SystemRandom::new(),
+                message.as_bytes(),
+                &mut signature,
+            )
+            .unwrap();
+            format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature))
+        };
+        let sign =
+            |kid: &str| sign_claims(kid, json!({"sub": "9", "aud": ["app"], "exp": now() + 60}));
+
+        let (request, allowed) = auth.authenticate(bearer(&sign("1")).await).await.unwrap();
+        assert!(allowed);
+        assert_eq!(request.user_id().unwrap(), 9);
+
+        assert!(matches!(
+            auth.verify(&sign("2")).await,
+            Err(Error::UnknownKey)
+        ));
+
+        assert!(matches!(
+            auth.verify(&sign_claims("1", json!({"sub": "9", "aud": ["app"]})))
+                .await,
+            Err(Error::MissingExpiration)
+        ));
+
+        for aud in [json!("other"), json!(["other"]), Json::Null] {
+            let token = sign_claims("1", json!({"sub": "9", "aud": aud, "exp": now() + 60}));
+            assert!(matches!(auth.verify(&token).await, Err(Error::Audience)));
+            let (_, allowed) = auth.authenticate(bearer(&token).await).await.unwrap();
+            assert!(!allowed);
+        }
+
+        // HS256 tokens aren't accepted, even when signed with the public key.
+        let token = encode(
+            &json!({"sub": 9, "aud": "app", "exp": now() + 60}),
+            "secret",
+        )
+        .unwrap();
+        assert!(matches!(
+            auth.verify(&token).await,
+            Err(Error::Algorithm(_))
+        ));
+    }
+}
@@ -1,21 +1,32 @@
 //! Cryptographic primitives, wrapped in a simple interface.
 //!
-//! The cipher used is AES-128.
+//! The cipher used is AES-128. Every [`Purpose`], e.g. sessions or CSRF tokens, uses its own key,
+//! derived from the application secret key with HKDF-SHA256, so a value encrypted for one purpose
+//! can't be used for another.
+//!
+//! ### Key rotation
+//!
+//! When the secret key is replaced, the old key can be added to `previous_secret_keys` in `rwf.toml`.
+//! Data is always encrypted with the current key, but data encrypted with the previous keys, e.g. cookies
+//! of logged in users, can still be decrypted until the old key is removed.
+use aes::Aes128;
 use aes_gcm_siv::{
     aead::{Aead, KeyInit},
-    Aes128GcmSiv, Nonce,
+    Aes128GcmSiv, AesGcmSiv, Key, Nonce,
 };
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2,
 };
 use base64::{engine::general_purpose, Engine as _};
+use hmac::{Hmac, Mac};
 use rand::{distributions::Alphanumeric, Rng};
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 use thiserror::Error;
 use time::OffsetDateTime;
 
-use crate::config::get_config;
+use crate::config::{get_config, Environment};
 
 /// Errors returned by the crypto implementation.
 #[derive(Error, Debug)]
@@ -41,6 +52,10 @@ pub enum Error {
 
     #[error("argon2 error: {0}")]
     Argon(argon2::Error),
+
+    /// The secret key wasn't configured, so a random or empty key is used.
+    #[error("secret key is not configured, set secret_key in rwf.toml or RWF_SECRET_KEY")]
+    DefaultKey,
 }
 
 impl From<aes_gcm_siv::Error> for Error {
@@ -61,6 +76,108 @@ impl From<argon2::Error> for Error {
     }
 }
 
+/// What a key is used for. Each purpose has its own key, derived from the application secret key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Purpose<'a> {
+    /// Arbitrary data, e.g. private cookies. Used by [`encrypt`] and [`decrypt`].
+    Data,
+    /// User sessions.
+    Session,
+    /// CSRF protection tokens.
+    Csrf,
+    /// Secure identifiers, see [`encrypt_number`].
+    SecureId,
+    /// Signed data, e.g. signed cookies. Used by [`sign`] and [`verify`].
+    Signature,
+    /// Defined by the application.
+    Custom(&'a str),
+}
+
+impl Purpose<'_> {
+    fn info(&self) -> String {
+        match self {
+            Purpose::Data => "rwf data".into(),
+            Purpose::Session => "rwf session".into(),
+            Purpose::Csrf => "rwf csrf".into(),
+            Purpose::SecureId => "rwf secure id".into(),
+            Purpose::Signature => "rwf signature".into(),
+            Purpose::Custom(name) => format!("rwf custom {}", name),
+        }
+    }
+
+    /// Key used before keys were derived, so data encrypted by older versions of Rwf can be decrypted.
+    fn legacy_key(&self, secret: &[u8]) -> Option<Key<AesGcmSiv<Aes128>>> {
+        match self {
+            Purpose::Custom(_) | Purpose::Signature => None,
+            Purpose::SecureId => Some(Key::<AesGcmSiv<Aes128>>::clone_from_slice(
+                &secret[128 / 8..],
+            )),
+            _ => Some(Key::<AesGcmSiv<Aes128>>::clone_from_slice(
+                &secret[0..128 / 8],
+            )),
+        }
+    }
+}
+
+/// Encryption keys, derived from the current secret key and the secret keys it replaced.
+#[derive(Clone, Default)]
+pub struct Keyring {
+    secrets: Vec<Vec<u8>>,
+}
+
+impl Keyring {
+    /// Create a keyring from the current secret key and previous secret keys, newest first.
+    /// Secret keys are 256 bits long.
+    pub fn new(secret: Vec<u8>, previous: Vec<Vec<u8>>) -> Self {
+        let mut secrets = vec![secret];
+        secrets.extend(previous);
+
+        Self { secrets }
+    }
+
+    /// Key used to encrypt data for this purpose.
+    pub fn key(&self, purpose: Purpose) -> Key<AesGcmSiv<Aes128>> {
+        derive(&self.secrets[0], purpose)
+    }
+
+    /// Keys accepted when decrypting data for this purpose, starting with the current key.
+    pub fn keys(&self, purpose: Purpose) -> Vec<Key<AesGcmSiv<Aes128>>> {
+        let derived = self.secrets.iter().map(|secret| derive(secret, purpose));
+        let legacy = self
+            .secrets
+            .iter()
+            .filter_map(|secret| purpose.legacy_key(secret));
+
+        derived.chain(legacy).collect()
+    }
+}
+
+/// Derive a key with HKDF-SHA256.
+fn derive(secret: &[u8], purpose: Purpose) -> Key<AesGcmSiv<Aes128>> {
+    let mut extract = <Hmac<Sha256> as Mac>::new_from_slice(b"rwf").expect("hmac key");
+    extract.update(secret);
+    let prk = extract.finalize().into_bytes();
+
+    let mut expand = <Hmac<Sha256> as Mac>::new_from_slice(&prk).expect("hmac key");
+    expand.update(purpose.info().as_bytes());
+    expand.update(&[1]);
+    let okm = expand.finalize().into_bytes();
+
+    Key::<AesGcmSiv<Aes128>>::clone_from_slice(&okm[0..128 / 8])
+}
+
+/// Check that the secret key was configured. Data encrypted with a random key can't be decrypted after
+/// a restart or by other instances of the app, so the server refuses to start in production.
+pub fn verify_secret_key() -> Result<(), Error> {
+    let config = get_config();
+
+    if config.general.insecure_secret_key() && config.environment == Environment::Production {
+        Err(Error::DefaultKey)
+    } else {
+        Ok(())
+    }
+}
+
 fn nonce() -> Vec<u8> {
     rand::thread_rng().gen::<[u8; 96 / 8]>().to_vec()
 }
@@ -100,17 +217,7 @@ impl Encrypted {
 /// let ciphertext = encrypt(b"hello world").expect("encryption failed");
 /// ```
 pub fn encrypt(data: &[u8]) -> Result<String, Error> {
-    let config = get_config();
-    let nonce = nonce();
-
-    let key = config.general.aes_key;
-    let cipher = Aes128GcmSiv::new(&key);
-    let aes_nonce = Nonce::from_slice(&nonce); // 96-bits; unique per message
-    let ciphertext = cipher
-        .encrypt(aes_nonce, data)
-        .expect("aes-128 encryption failed");
-
-    Encrypted { ciphertext, nonce }.to_bytes()
+    encrypt_with(Purpose::Data, data)
 }
 
 /// Decrypt data encrypted with the application secret key.
@@ -126,15 +233,103 @@ pub fn encrypt(data: &[u8]) -> Result<String, Error> {
 /// assert_eq!(plain, "super secret".as_bytes());
 /// ```
 pub fn decrypt(data: &str) -> Result<Vec<u8>, Error> {
-    let config = get_config();
-    let encrypted = Encrypted::from_base64(data)?;
+    decrypt_with(Purpose::Data, data)
+}
 
-    let key = config.general.aes_key;
-    let cipher = Aes128GcmSiv::new(&key);
+/// Encrypt data using the key for this purpose. It can only be decrypted
+/// by [`decrypt_with`] using the same purpose.
+///
+/// # Example
+///
+/// ```
+/// use rwf::crypto::{encrypt_with, decrypt, decrypt_with, Purpose};
+///
+/// let cipher = encrypt_with(Purpose::Custom("invite"), b"alice@example.com").unwrap();
+/// assert!(decrypt(&cipher).is_err());
+///
+/// let plain = decrypt_with(Purpose::Custom("invite"), &cipher).unwrap();
+/// assert_eq!(plain, b"alice@example.com");
+/// ```
+pub fn encrypt_with(purpose: Purpose, data: &[u8]) -> Result<String, Error> {
+    let key = get_config().general.keyring.key(purpose);
+    encrypt_key(&key, data)
+}
+
+/// Decrypt data encrypted with [`encrypt_with`] using the same purpose. Data encrypted with
+/// one of the previous secret keys is decrypted as well.
+pub fn decrypt_with(purpose: Purpose, data: &str) -> Result<Vec<u8>, Error> {
+    let keys = get_config().general.keyring.keys(purpose);
+    decrypt_keys(&keys, data)
+}
+
+/// Sign data with the application secret key and return the signature. The data isn't encrypted,
+/// but it can't be modified without [`verify`] noticing.
+///
+/// # Example
+///
+/// ```
+/// use rwf::crypto::{sign, verify};
+///
+/// let signature = sign(b"theme=dark");
+/// assert!(verify(b"theme=dark", &signature));
+/// assert!(!verify(b"theme=light", &signature));
+/// ```
+pub fn sign(data: &[u8]) -> String {
+    let key = get_config().general.keyring.key(Purpose::Signature);
+    general_purpose::URL_SAFE_NO_PAD.encode(mac(&key, data).finalize().into_bytes())
+}
+
+/// Check the signature returned by [`sign`]. Signatures made with one of the previous
+/// secret keys are valid as well.
+pub fn verify(data: &[u8], signature: &str) -> bool {
+    let signature = match general_purpose::URL_SAFE_NO_PAD.decode(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+
+    get_config()
+        .general
+        .keyring
+        .keys(Purpose::Signature)
+        .iter()
+        .any(|key| mac(key, data).verify_slice(&signature).is_ok())
+}
+
+fn mac(key: &Key<AesGcmSiv<Aes128>>, data: &[u8]) -> Hmac<Sha256> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("hmac key");
+    mac.update(data);
+    mac
+}
+
+fn encrypt_key(key: &Key<AesGcmSiv<Aes128>>, data: &[u8]) -> Result<String, Error> {
+    let nonce = nonce();
+
+    let cipher = Aes128GcmSiv::new(key);
+    let aes_nonce = Nonce::from_slice(&nonce); // 96-bits; unique per message
+    let ciphertext = cipher
+        .encrypt(aes_nonce, data)
+        .expect("aes-128 encryption failed");
+
+    Encrypted { ciphertext, nonce }.to_bytes()
+}
+
+fn decrypt_keys(keys: &[Key<AesGcmSiv<Aes128>>], data: &str) -> Result<Vec<u8>, Error> {
+    let encrypted = Encrypted::from_base64(data)?;
     let aes_nonce = Nonce::from_slice(&encrypted.nonce);
-    let plaintext = cipher.decrypt(aes_nonce, encrypted.ciphertext.as_ref())?;
+    let mut result = Err(Error::Generic("no decryption keys"));
+
+    for key in keys {
+        let cipher = Aes128GcmSiv::new(key);
+        result = cipher
+            .decrypt(aes_nonce, encrypted.ciphertext.as_ref())
+            .map_err(Error::from);
 
-    Ok(plaintext)
+        if result.is_ok() {
+            break;
+        }
+    }
+
+    result
 }
 
 /// Encrypt an integer using the application secret key and return
@@ -152,7 +347,7 @@ pub fn encrypt_number(n: i64) -> Result<String, Error> {
     let config = get_config();
     let nonce = nonce();
 
-    let key = config.general.secure_id_key;
+    let key = config.general.keyring.key(Purpose::SecureId);
     let cipher = Aes128GcmSiv::new(&key);
     let aes_nonce = Nonce::from_slice(&nonce);
     let data = n.to_be_bytes();
@@ -197,9 +392,6 @@ pub fn encrypt_number(n: i64) -> Result<String, Error> {
 pub fn decrypt_number(s: &str) -> Result<i64, Error> {
     let config = get_config();
 
-    let key = config.general.secure_id_key;
-    let cipher = Aes128GcmSiv::new(&key);
-
     // Remove the pretty format.
     let s = s.replace("-", "");
 
@@ -222,7 +414,17 @@ pub fn decrypt_number(s: &str) -> Result<i64, Error> {
 
     let aes_nonce = Nonce::from_slice(nonce);
 
-    let plaintext = cipher.decrypt(aes_nonce, ciphertext.as_ref())?;
+    let plaintext = config
+        .general
+        .keyring
+        .keys(Purpose::SecureId)
+        .iter()
+        .find_map(|key| {
+            Aes128GcmSiv::new(key)
+                .decrypt(aes_nonce, ciphertext.as_ref())
+                .ok()
+        })
+        .ok_or(Error::Generic("incorrect secure id"))?;
 
     // Should be a i64-size structure.
     if plaintext.len() != 8 {
@@ -269,7 +471,7 @@ pub fn csrf_token(session_id: &str) -> Result<String, Error> {
         OffsetDateTime::now_utc().unix_timestamp(),
         session_id
     );
-    encrypt(token.as_bytes())
+    encrypt_with(Purpose::Csrf, token.as_bytes())
 }
 
 /// Validate a CSRF token. Checks that the token was generated by the same secret key and
@@ -283,10 +485,10 @@ pub fn csrf_token(session_id: &str) -> Result<String, Error> {
 /// assert!(csrf_token_validate(&token, "1234"));
 /// ```
 pub fn csrf_token_validate(token: &str, session_id: &str) -> bool {
-    match decrypt(token) {
+    match decrypt_with(Purpose::Csrf, token) {
         Ok(value) => {
             let value = String::from_utf8_lossy(&value).to_string();
-            let mut parts = value.split("_");
+            let mut parts = value.splitn(2, "_");
             let expiration = parts.next();
             let marker = parts.next();
 
@@ -369,4 +571,41 @@ mod test {
         let result = decrypt_number(&bad_input);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_key_rotation() {
+        let old = vec![1; 256 / 8];
+        let new = vec![2; 256 / 8];
+
+        let keyring = Keyring::new(old.clone(), vec![]);
+        let session = encrypt_key(&keyring.key(Purpose::Session), b"session").unwrap();
+        let legacy = encrypt_key(&Purpose::Session.legacy_key(&old).unwrap(), b"legacy").unwrap();
+
+        // Keys are different for each purpose.
+        assert!(decrypt_keys(&keyring.keys(Purpose::Csrf)[0..1], &session).is_err());
+
+        let rotated = Keyring::new(new.clone(), vec![old]);
+        assert_eq!(
+            decrypt_keys(&rotated.keys(Purpose::Session), &session).unwrap(),
+            b"session"
+        );
+        assert_eq!(
+            decrypt_keys(&rotated.keys(Purpose::Session), &legacy).unwrap(),
+            b"legacy"
+        );
+        assert!(rotated.key(Purpose::Session) != keyring.key(Purpose::Session));
+
+        // The old key was removed.
+        let removed = Keyring::new(new, vec![]);
+        assert!(decrypt_keys(&removed.keys(Purpose::Session), &session).is_err());
+    }
+
+    #[test]
+    fn test_sign() {
+        let signature = sign(b"user_id=1");
+        assert!(verify(b"user_id=1", &signature));
+        assert!(!verify(b"user_id=2", &signature));
+        assert!(!verify(b"user_id=1", "not a signature"));
+        assert!(!verify(b"user_id=1", &sign(b"user_id=2")));
+    }
 }
@@ -0,0 +1,29 @@
+//! Errors returned by the dashboard.
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The widget template couldn't be rendered.
+    #[error("{0}")]
+    Template(Box<crate::view::Error>),
+
+    /// The ORM returned an error.
+    #[error("dashboard database error: {0}")]
+    DatabaseError(#[from] crate::model::Error),
+
+    /// The refresh job couldn't be scheduled.
+    #[error("{0}")]
+    Job(#[from] crate::job::Error),
+}
+
+impl From<tokio_postgres::Error> for Error {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DatabaseError(err.into())
+    }
+}
+
+impl From<crate::view::Error> for Error {
+    fn from(err: crate::view::Error) -> Self {
+        Self::Template(Box::new(err))
+    }
+}
@@ -0,0 +1,85 @@
+//! Position and size of widgets on the dashboard, arranged in the admin panel.
+use crate::model::{Error, FromRow, Model, Query, ToValue, Value};
+
+/// Saved position and size of a widget.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WidgetLayout {
+    id: Option<i64>,
+    /// Name of the widget.
+    pub name: String,
+    /// Position on the dashboard, starting at 0.
+    pub position: i32,
+    /// Width in columns, out of 12.
+    pub width: i32,
+    /// The widget isn't shown on the dashboard.
+    pub hidden: bool,
+}
+
+impl FromRow for WidgetLayout {
+    fn from_row(row: tokio_postgres::Row) -> Result<Self, Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            position: row.try_get("position")?,
+            width: row.try_get("width")?,
+            hidden: row.try_get("hidden")?,
+        })
+    }
+}
+
+impl Model for WidgetLayout {
+    fn table_name() -> &'static str {
+        "rwf_dashboard_widgets"
+    }
+
+    fn foreign_key() -> &'static str {
+        "dashboard_widget_id"
+    }
+
+    fn id(&self) -> Value {
+        self.id.to_value()
+    }
+
+    fn column_names() -> &'static [&'static str] {
+        &["name", "position", "width", "hidden"]
+    }
+
+    fn values(&self) -> Vec<Value> {
+        vec![
+            self.name.to_value(),
+            self.position.to_value(),
+            self.width.to_value(),
+            self.hidden.to_value(),
+        ]
+    }
+}
+
+impl WidgetLayout {
+    /// Default layout of a widget that hasn't been arranged yet.
+    pub fn new(name: &str, position: i32, width: i32) -> Self {
+        Self {
+            id: None,
+            name: name.to_string(),
+            position,
+            width,
+            hidden: false,
+        }
+    }
+
+    /// Save the position and size of the widget.
+    pub fn arrange(name: &str, position: i32, width: i32, hidden: bool) -> Query<Self> {
+        Self::find_by_sql(
+            "INSERT INTO rwf_dashboard_widgets (name, position, width, hidden)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (name) DO UPDATE
+            SET position = EXCLUDED.position, width = EXCLUDED.width, hidden = EXCLUDED.hidden
+            RETURNING *",
+            &[
+                name.to_value(),
+                position.to_value(),
+                width.clamp(1, 12).to_value(),
+                hidden.to_value(),
+            ],
+        )
+    }
+}
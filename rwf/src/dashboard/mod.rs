@@ -0,0 +1,280 @@
+//! Dashboards showing live data from the database.
+//!
+//! A dashboard is made of widgets, each one a SQL query, a template used to render
+//! its results, and how often it's refreshed:
+//!
+//! ```ignore
+//! use rwf::dashboard::{Dashboard, RefreshWidget, Widget};
+//!
+//! Dashboard::install(vec![
+//!     Widget::new("signups", "SELECT COUNT(*) FROM users WHERE created_at > NOW() - INTERVAL '1 day'")
+//!         .title("Signups today")
+//!         .refresh(Duration::from_secs(10)),
+//! ]);
+//! ```
+//!
+//! Widgets are refreshed on schedule by the [`RefreshWidget`] job, which renders them on the
+//! server and sends the result to connected browsers as a Turbo Stream:
+//!
+//! ```ignore
+//! Worker::new(vec![RefreshWidget.job()])
+//!     .clock(Dashboard::schedule()?)
+//!     .start()
+//!     .await?;
+//! ```
+//!
+//! Widgets are shown and arranged in the admin panel.
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde_json::{json, Value as Json};
+use tracing::debug;
+
+use crate::comms::Comms;
+use crate::http::Message;
+use crate::job::{clock::ScheduledJob, Error as JobError, Job};
+use crate::model::{ConnectionGuard, Model, Pool};
+use crate::view::TurboStream;
+
+pub mod error;
+pub mod layout;
+pub mod widget;
+
+pub use error::Error;
+pub use layout::WidgetLayout;
+pub use widget::Widget;
+
+static WIDGETS: Lazy<RwLock<Vec<Arc<Widget>>>> = Lazy::new(|| RwLock::new(vec![]));
+
+/// Widget and its position on the dashboard.
+#[derive(Debug, Clone)]
+pub struct Panel {
+    /// The widget.
+    pub widget: Arc<Widget>,
+    /// Position and size of the widget.
+    pub layout: WidgetLayout,
+}
+
+/// The application dashboard.
+pub struct Dashboard;
+
+impl Dashboard {
+    /// Set the widgets shown on the dashboard.
+    pub fn install(widgets: Vec<Widget>) {
+        *WIDGETS.write() = widgets.into_iter().map(Arc::new).collect();
+    }
+
+    /// All installed widgets.
+    pub fn widgets() -> Vec<Arc<Widget>> {
+        WIDGETS.read().clone()
+    }
+
+    /// Get a widget by name.
+    pub fn widget(name: &str) -> Option<Arc<Widget>> {
+        WIDGETS
+            .read()
+            .iter()
+            .find(|widget| widget.name() == name)
+            .cloned()
+    }
+
+    /// Jobs refreshing each widget on its schedule. Add them to the worker clock.
+    pub fn schedule() -> Result<Vec<ScheduledJob>, Error> {
+        Ok(Self::widgets()
+            .iter()
+            .map(|widget| {
+                RefreshWidget.schedule(
+                    json!({ "widget": widget.name() }),
+                    &cron(widget.refresh_interval()),
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Widgets in the order they were arranged in. Widgets which weren't arranged yet
+    /// are shown in the order they were installed.
+    pub async fn panels(conn: &mut ConnectionGuard) -> Result<Vec<Panel>, Error> {
+        let mut layouts = WidgetLayout::all()
+            .fetch_all(conn)
+            .await?
+            .into_iter()
+            .map(|layout| (layout.name.clone(), layout))
+            .collect::<HashMap<_, _>>();
+
+        let mut panels = Self::widgets()
+            .into_iter()
+            .enumerate()
+            .map(|(position, widget)| {
+                let layout = layouts.remove(widget.name()).unwrap_or_else(|| {
+                    WidgetLayout::new(widget.name(), position as i32, widget.default_width())
+                });
+                Panel { widget, layout }
+            })
+            .collect::<Vec<_>>();
+
+        panels.sort_by_key(|panel| panel.layout.position);
+
+        Ok(panels)
+    }
+
+    /// Send the rendered widget to all connected browsers.
+    pub fn update(widget: &Widget, html: impl ToString) {
+        let stream = TurboStream::new(html)
+            .action("update")
+            .target(widget.dom_id());
+
+        // Sessions can disconnect while we're sending the update.
+        if let Err(err) = Comms::notify().send(Message::turbo_stream(stream)) {
+            debug!("dashboard update not delivered: {}", err);
+        }
+    }
+}
+
+/// Render a widget and send it to connected browsers.
+#[derive(Default)]
+pub struct RefreshWidget;
+
+#[async_trait]
+impl Job for RefreshWidget {
+    async fn execute(&self, args: Json) -> Result<(), JobError> {
+        let name = args["widget"]
+            .as_str()
+            .ok_or(JobError::Unknown("widget is required".into()))?;
+
+        // Widget was removed since the job was scheduled.
+        let widget = match Dashboard::widget(name) {
+            Some(widget) => widget,
+            None => return Ok(()),
+        };
+
+        let mut conn = Pool::connection().await?;
+        let html = widget
+            .render(&mut conn)
+            .await
+            .map_err(|err| JobError::Unknown(err.to_string()))?;
+
+        Dashboard::update(&widget, html);
+
+        Ok(())
+    }
+}
+
+/// Cron schedule running as close as possible to every `interval`.
+fn cron(interval: Duration) -> String {
+    let seconds = interval.as_secs().max(1);
+
+    if seconds < 60 {
+        format!("*/{} * * * * *", seconds)
+    } else if seconds < 3600 {
+        format!("0 */{} * * * *", seconds / 60)
+    } else {
+        format!("0 0 */{} * * *", (seconds / 3600).min(23))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::websocket::Message;
+    use crate::model::test::bootstrap;
+    use crate::prelude::SessionId;
+    use crate::view::Templates;
+
+    #[test]
+    fn test_cron() {
+        assert_eq!(cron(Duration::from_secs(5)), "*/5 * * * * *");
+        assert_eq!(cron(Duration::from_millis(10)), "*/1 * * * * *");
+        assert_eq!(cron(Duration::from_secs(300)), "0 */5 * * * *");
+        assert_eq!(cron(Duration::from_secs(7200)), "0 0 */2 * * *");
+    }
+
+    #[tokio::test]
+    async fn test_dashboard() -> Result<(), Error> {
+        let pool = Pool::from_env();
+        let mut conn = pool.transaction().await?;
+
+        bootstrap(conn.client()).await?;
+
+        Templates::cache().preload_str(
+            "templates/rwf_test_widget.html",
+            "<% for row in rows %><%= row.name %>=<%= row.count %>;<% end %>",
+        )?;
+
+        Dashboard::install(vec![
+            Widget::new("total", "SELECT 5::bigint AS total").title("Total <b>"),
+            Widget::new(
+                "split",
+                "SELECT * FROM (VALUES ('a', 1), ('b', 2)) AS t (name, count)",
+            )
+            .width(6),
+            Widget::new(
+                "custom",
+                "SELECT * FROM (VALUES ('a', 1), ('b', 2)) AS t (name, count)",
+            )
+            .template("templates/rwf_test_widget.html"),
+        ]);
+
+        let total = Dashboard::widget("total").unwrap();
+        let html = total.render(&mut conn).await?;
+        assert!(html.contains("Total &lt;b&gt;"));
+        assert!(html.contains(r#"<p class="rwf-widget-value fs-2 mb-0">5</p>"#));
+
+        let html = Dashboard::widget("split")
+            .unwrap()
+            .render(&mut conn)
+            .await?;
+        assert!(html.contains("<th>name</th>"));
+        assert!(html.contains("<td>b</td>"));
+
+        let html = Dashboard::widget("custom")
+            .unwrap()
+            .render(&mut conn)
+            .await?;
+        assert_eq!(html, "a=1;b=2;");
+
+        let panels = Dashboard::panels(&mut conn).await?;
+        let names = panels
+            .iter()
+            .map(|panel| panel.widget.name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["total", "split", "custom"]);
+        assert_eq!(panels[1].layout.width, 6);
+
+        WidgetLayout::arrange("total", 5, 12, false)
+            .execute(&mut conn)
+            .await?;
+        WidgetLayout::arrange("custom", 0, 3, true)
+            .execute(&mut conn)
+            .await?;
+
+        let panels = Dashboard::panels(&mut conn).await?;
+        let names = panels
+            .iter()
+            .map(|panel| panel.widget.name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["custom", "split", "total"]);
+        assert!(panels[0].layout.hidden);
+        assert_eq!(panels[2].layout.width, 12);
+
+        // Updates are sent to connected browsers.
+        let mut receiver = Comms::receiver(SessionId::Authenticated(93_461));
+        Dashboard::update(&total, "<p>6</p>");
+        match receiver.recv().await.unwrap() {
+            Message::Text(text) => {
+                assert!(text.contains(r#"action="update" target="rwf-widget-total""#));
+                assert!(text.contains("<p>6</p>"));
+            }
+            message => panic!("unexpected message: {:?}", message),
+        }
+
+        assert_eq!(Dashboard::schedule()?.len(), 3);
+        assert!(Dashboard::widget("missing").is_none());
+
+        conn.rollback().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+//! Dashboard widget: a query, the template used to render its results, and how often
+//! it's refreshed.
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use time::OffsetDateTime;
+
+use super::Error;
+use crate::model::{ConnectionGuard, Model, Row, Value};
+use crate::view::{Context, Template};
+
+static TEMPLATE: Lazy<Template> =
+    Lazy::new(|| Template::from_str(include_str!("widget.html")).unwrap());
+
+/// Dashboard widget.
+#[derive(Debug, Clone)]
+pub struct Widget {
+    name: String,
+    title: String,
+    query: String,
+    template: Option<String>,
+    refresh: Duration,
+    width: i32,
+}
+
+impl Widget {
+    /// Create a widget showing the results of the SQL query. The name must be unique,
+    /// since it identifies the widget on the page.
+    pub fn new(name: impl ToString, query: impl ToString) -> Self {
+        let name = name.to_string();
+
+        Self {
+            title: name.clone(),
+            name,
+            query: query.to_string(),
+            template: None,
+            refresh: Duration::from_secs(60),
+            width: 4,
+        }
+    }
+
+    /// Title shown above the widget.
+    pub fn title(mut self, title: impl ToString) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    /// Path to the template used to render the query results. By default, a single value
+    /// is shown as a number and multiple values as a table.
+    pub fn template(mut self, path: impl ToString) -> Self {
+        self.template = Some(path.to_string());
+        self
+    }
+
+    /// How often the widget is refreshed. Default: every minute.
+    pub fn refresh(mut self, refresh: Duration) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Default width of the widget, in columns out of 12. Default: 4.
+    pub fn width(mut self, width: i32) -> Self {
+        self.width = width.clamp(1, 12);
+        self
+    }
+
+    /// Unique name of the widget.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// How often the widget is refreshed.
+    pub fn refresh_interval(&self) -> Duration {
+        self.refresh
+    }
+
+    /// Default width of the widget.
+    pub fn default_width(&self) -> i32 {
+        self.width
+    }
+
+    /// ID of the DOM element containing the widget.
+    pub fn dom_id(&self) -> String {
+        format!("rwf-widget-{}", self.name)
+    }
+
+    /// Run the query and render the widget.
+    ///
+    /// The template receives the query results as `rows` (a list of hashes), `columns`
+    /// and `values` (a list of lists, in column order), and the `title` of the widget.
+    /// If the query returns a single value, it's available as `value`.
+    pub async fn render(&self, conn: &mut ConnectionGuard) -> Result<String, Error> {
+        let rows = Row::find_by_sql(&self.query, &[]).fetch_all(conn).await?;
+
+        let columns = rows
+            .first()
+            .map(|row| {
+                row.columns()
+                    .iter()
+                    .map(|column| column.name().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        let mut values = vec![];
+        for row in &rows {
+            values.push(
+                (0..columns.len())
+                    .map(|i| row.try_get::<_, Value>(i))
+                    .collect::<Result<Vec<_>, _>>()?,
+            );
+        }
+
+        let single = values.len() == 1 && columns.len() == 1;
+        let value = if single {
+            values[0][0].clone()
+        } else {
+            Value::Null
+        };
+
+        let rows = rows
+            .into_iter()
+            .map(|row| row.values())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut context = Context::new();
+        context.set("title", self.title.as_str())?;
+        context.set("rows", rows)?;
+        context.set("columns", columns)?;
+        context.set("values", values)?;
+        context.set("value", value)?;
+        context.set("single", single)?;
+        context.set("updated_at", OffsetDateTime::now_utc())?;
+
+        Ok(match self.template {
+            Some(ref path) => Template::load(path.as_str())?.render(&context)?,
+            None => TEMPLATE.render(&context)?,
+        })
+    }
+}
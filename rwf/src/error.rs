@@ -36,6 +36,10 @@ pub enum Error {
     #[error("{0}")]
     Comms(#[from] crate::comms::Error),
 
+    /// Error returned when the app can't run in cluster mode.
+    #[error("{0}")]
+    Cluster(#[from] crate::cluster::Error),
+
     /// Utf-8 decoding error.
     #[error("{0}")]
     Utf8(#[from] std::string::FromUtf8Error),
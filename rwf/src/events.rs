@@ -0,0 +1,206 @@
+//! Framework events.
+//!
+//! Rwf emits events when it serves requests, runs queries, fails jobs and sends WebSocket messages.
+//! Applications can subscribe to them to record custom metrics, write an audit log, or debug
+//! what the framework is doing, without changing Rwf itself.
+//!
+//! Listeners are called synchronously by the task that emitted the event, so they should be quick.
+//! Slow work, e.g. writing to the database, should be sent to a background task or a [job](crate::job).
+//!
+//! # Example
+//!
+//! ```
+//! use rwf::events::{self, Event};
+//!
+//! events::subscribe(|event: &Event| {
+//!     if let Event::RequestFinished(finished) = event {
+//!         if finished.response.status().code() >= 500 {
+//!             eprintln!("{} {} failed", finished.request.method(), finished.request.path().path());
+//!         }
+//!     }
+//! });
+//! ```
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde_json::Value;
+
+use crate::comms::Target;
+use crate::http::websocket::Message;
+use crate::http::{Request, Response};
+use crate::model::Error as ModelError;
+
+static LISTENERS: Lazy<RwLock<Vec<Arc<dyn Listener>>>> = Lazy::new(|| RwLock::new(vec![]));
+// Checked before building events, so the framework does no extra work when nobody is listening.
+static LISTENING: AtomicBool = AtomicBool::new(false);
+
+/// Event emitted by the framework.
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// The server received a request and is about to route it to a controller.
+    RequestStarted(RequestStarted<'a>),
+    /// The server handled a request and is about to send the response.
+    RequestFinished(RequestFinished<'a>),
+    /// The ORM executed a query.
+    QueryExecuted(QueryExecuted<'a>),
+    /// A background job returned an error or panicked.
+    JobFailed(JobFailed<'a>),
+    /// A WebSocket message was sent to a session, to a channel, or to everyone.
+    BroadcastSent(BroadcastSent<'a>),
+}
+
+impl Event<'_> {
+    /// Name of the event, e.g. `request_started`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::RequestStarted(_) => "request_started",
+            Event::RequestFinished(_) => "request_finished",
+            Event::QueryExecuted(_) => "query_executed",
+            Event::JobFailed(_) => "job_failed",
+            Event::BroadcastSent(_) => "broadcast_sent",
+        }
+    }
+}
+
+/// Payload of [`Event::RequestStarted`].
+#[derive(Debug)]
+pub struct RequestStarted<'a> {
+    /// The request.
+    pub request: &'a Request,
+}
+
+/// Payload of [`Event::RequestFinished`].
+#[derive(Debug)]
+pub struct RequestFinished<'a> {
+    /// The request.
+    pub request: &'a Request,
+    /// The response returned by the controller and middleware.
+    pub response: &'a Response,
+    /// Name of the controller which handled the request.
+    pub controller: &'a str,
+    /// How long it took to handle the request, including routing.
+    pub duration: Duration,
+}
+
+/// Payload of [`Event::QueryExecuted`].
+#[derive(Debug)]
+pub struct QueryExecuted<'a> {
+    /// Name of the model.
+    pub model: &'a str,
+    /// Query action, e.g. `load` or `save`.
+    pub action: &'a str,
+    /// The query.
+    pub query: &'a str,
+    /// How long the query took.
+    pub duration: Duration,
+    /// Number of rows returned by the query.
+    pub rows: usize,
+    /// The error returned by the database, if the query failed.
+    pub error: Option<&'a ModelError>,
+}
+
+/// Payload of [`Event::JobFailed`].
+#[derive(Debug)]
+pub struct JobFailed<'a> {
+    /// Name of the job.
+    pub name: &'a str,
+    /// Job ID in the queue.
+    pub id: Option<i64>,
+    /// Job arguments.
+    pub args: &'a Value,
+    /// The error, or `"job panicked"`.
+    pub error: &'a str,
+    /// How many times the job was attempted, including this attempt.
+    pub attempts: i32,
+    /// The job won't be retried.
+    pub dead: bool,
+}
+
+/// Payload of [`Event::BroadcastSent`].
+#[derive(Debug)]
+pub struct BroadcastSent<'a> {
+    /// Who the message was sent to.
+    pub target: &'a Target,
+    /// The message.
+    pub message: &'a Message,
+}
+
+/// Receives framework events.
+pub trait Listener: Send + Sync {
+    /// Handle the event.
+    fn event(&self, event: &Event<'_>);
+}
+
+impl<F> Listener for F
+where
+    F: Fn(&Event<'_>) + Send + Sync,
+{
+    fn event(&self, event: &Event<'_>) {
+        self(event)
+    }
+}
+
+/// Call the listener for every event emitted by the framework.
+pub fn subscribe(listener: impl Listener + 'static) {
+    LISTENERS.write().push(Arc::new(listener));
+    LISTENING.store(true, Ordering::Relaxed);
+}
+
+/// Someone is listening to events.
+pub(crate) fn listening() -> bool {
+    LISTENING.load(Ordering::Relaxed)
+}
+
+/// Send the event to all listeners.
+pub(crate) fn emit(event: Event<'_>) {
+    if !listening() {
+        return;
+    }
+
+    // Listeners can subscribe other listeners, so don't hold the lock while calling them.
+    let listeners = LISTENERS.read().clone();
+
+    for listener in listeners {
+        listener.event(&event);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_subscribe() {
+        static JOBS: AtomicUsize = AtomicUsize::new(0);
+
+        subscribe(|event: &Event| {
+            if let Event::JobFailed(failed) = event {
+                if failed.name == "test_subscribe" {
+                    assert_eq!(failed.error, "job panicked");
+                    JOBS.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        });
+
+        assert!(listening());
+
+        let args = serde_json::json!({});
+        let failed = JobFailed {
+            name: "test_subscribe",
+            id: Some(1),
+            args: &args,
+            error: "job panicked",
+            attempts: 1,
+            dead: false,
+        };
+        let event = Event::JobFailed(failed);
+        assert_eq!(event.name(), "job_failed");
+        emit(event);
+
+        assert_eq!(JOBS.load(Ordering::Relaxed), 1);
+    }
+}
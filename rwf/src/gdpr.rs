@@ -0,0 +1,769 @@
+//! Export and erase personal data of a user, as required by the GDPR.
+//!
+//! The application describes where personal data is stored: the user model, models
+//! which belong to it, and hooks for anything else, e.g. files in object storage or
+//! records in a third-party service. The framework then handles data subject requests:
+//!
+//! - **export** collects all the data into a ZIP archive of JSON files, one per table,
+//! - **erasure** deletes or anonymizes the data, keeping records which must be retained, e.g. invoices.
+//!
+//! Both run in the background using the [job queue](crate::job), and every request
+//! is recorded in the `rwf_data_requests` table as an audit trail.
+//!
+//! # Example
+//!
+//! ```ignore
+//! fn personal_data() -> PersonalData<User> {
+//!     PersonalData::new()
+//!         .anonymize_user()
+//!         .delete::<Comment>()
+//!         .anonymize::<Order>()
+//!         .keep::<Invoice>()
+//!         .hook(AvatarStorage)
+//! }
+//!
+//! // Register the job with the worker.
+//! Worker::new(vec![personal_data().job()]).start().await?;
+//!
+//! // In a controller.
+//! personal_data().export_later(user.id()).await?;
+//! ```
+use std::collections::HashMap;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::{json, Map, Value as Json};
+use time::OffsetDateTime;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::colors::MaybeColorize;
+use crate::job::{Error as JobError, Job};
+use crate::model::{
+    Anonymize, Association, ConnectionGuard, Error, FromRow, Model, Pool, ToValue, Value,
+};
+
+/// What happens to the records of a model when a user's data is erased.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Erasure {
+    /// Delete the records.
+    Delete,
+    /// Replace personal data in the records with fake values, using the
+    /// query generated by [`Anonymize::anonymize_sql`].
+    Anonymize(String),
+    /// Keep the records unchanged, e.g. because the law requires to retain them.
+    /// They are still included in exports.
+    Keep,
+}
+
+/// Table storing personal data of a user.
+#[derive(Debug, Clone)]
+struct Source {
+    table: &'static str,
+    primary_key: &'static str,
+    foreign_key: &'static str,
+    erasure: Erasure,
+}
+
+impl Source {
+    /// Filter selecting the records of the user, whose id is passed as the placeholder.
+    fn filter(&self, placeholder: usize) -> String {
+        format!(r#"WHERE "{}" = ${}"#, self.foreign_key, placeholder)
+    }
+
+    async fn export(&self, user_id: i64, conn: &mut ConnectionGuard) -> Result<Json, Error> {
+        let sql = format!(
+            r#"SELECT row_to_json("data")::TEXT FROM "{}" AS "data" {} ORDER BY "{}""#,
+            self.table,
+            self.filter(1),
+            self.primary_key
+        );
+
+        conn.client()
+            .query(&sql, &[&user_id])
+            .await?
+            .into_iter()
+            .map(|row| {
+                let json: String = row.try_get(0)?;
+                serde_json::from_str(&json)
+                    .map_err(|err| Error::ValueError("personal data", err.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Json::Array)
+    }
+
+    async fn erase(&self, user_id: i64, conn: &mut ConnectionGuard) -> Result<u64, Error> {
+        match self.erasure {
+            Erasure::Delete => {
+                let sql = format!(r#"DELETE FROM "{}" {}"#, self.table, self.filter(1));
+                Ok(conn.client().execute(&sql, &[&user_id]).await?)
+            }
+
+            Erasure::Anonymize(ref update) => {
+                // Fake values are generated from a random seed, so they can't be reversed.
+                let sql = format!("{} {}", update, self.filter(2));
+                let seed = Uuid::new_v4().to_string();
+                Ok(conn.client().execute(&sql, &[&seed, &user_id]).await?)
+            }
+
+            Erasure::Keep => Ok(0),
+        }
+    }
+}
+
+/// Application-specific personal data, e.g. files in object storage or
+/// records in a third-party service.
+#[async_trait]
+pub trait DataHook: Send + Sync {
+    /// Name of the data, used as the file name in the export archive.
+    fn name(&self) -> &'static str;
+
+    /// Export the data of the user.
+    async fn export(&self, _user_id: i64, _conn: &mut ConnectionGuard) -> Result<Json, Error> {
+        Ok(Json::Null)
+    }
+
+    /// Erase the data of the user. Called after all models are erased,
+    /// in the same transaction.
+    async fn erase(&self, _user_id: i64, _conn: &mut ConnectionGuard) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// The export archive is ready, e.g. to send the user a download link.
+    async fn exported(&self, _user_id: i64, _archive: &Path) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Data subject request recorded in the `rwf_data_requests` table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataRequest {
+    id: Option<i64>,
+    /// `export` or `erasure`.
+    pub action: String,
+    /// Table of the user model.
+    pub subject: String,
+    /// Primary key of the user.
+    pub subject_id: i64,
+    /// `pending`, `completed` or `failed`.
+    pub status: String,
+    /// Number of exported or erased records per table, the archive path, or the error.
+    pub summary: Json,
+    /// When the request was made.
+    pub created_at: OffsetDateTime,
+    /// When the request was completed.
+    pub completed_at: Option<OffsetDateTime>,
+}
+
+impl FromRow for DataRequest {
+    fn from_row(row: tokio_postgres::Row) -> Result<Self, Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            action: row.try_get("action")?,
+            subject: row.try_get("subject")?,
+            subject_id: row.try_get("subject_id")?,
+            status: row.try_get("status")?,
+            summary: row.try_get("summary")?,
+            created_at: row.try_get("created_at")?,
+            completed_at: row.try_get("completed_at")?,
+        })
+    }
+}
+
+impl Model for DataRequest {
+    fn table_name() -> &'static str {
+        "rwf_data_requests"
+    }
+
+    fn foreign_key() -> &'static str {
+        "data_request_id"
+    }
+
+    fn id(&self) -> Value {
+        self.id.to_value()
+    }
+
+    fn column_names() -> &'static [&'static str] {
+        &[
+            "action",
+            "subject",
+            "subject_id",
+            "status",
+            "summary",
+            "created_at",
+            "completed_at",
+        ]
+    }
+
+    fn values(&self) -> Vec<Value> {
+        vec![
+            self.action.to_value(),
+            self.subject.to_value(),
+            self.subject_id.to_value(),
+            self.status.to_value(),
+            self.summary.to_value(),
+            self.created_at.to_value(),
+            self.completed_at.to_value(),
+        ]
+    }
+}
+
+impl DataRequest {
+    fn new(action: &str, subject: &str, subject_id: i64, status: &str, summary: Json) -> Self {
+        let now = OffsetDateTime::now_utc();
+        Self {
+            id: None,
+            action: action.to_string(),
+            subject: subject.to_string(),
+            subject_id,
+            status: status.to_string(),
+            summary,
+            created_at: now,
+            completed_at: if status == "pending" { None } else { Some(now) },
+        }
+    }
+
+    async fn complete(
+        request_id: Option<i64>,
+        request: DataRequest,
+        conn: &mut ConnectionGuard,
+    ) -> Result<(), Error> {
+        match request_id {
+            Some(id) => {
+                DataRequest::find(id)
+                    .update_all(&[
+                        ("status", request.status.to_value()),
+                        ("summary", request.summary.to_value()),
+                        ("completed_at", request.completed_at.to_value()),
+                    ])
+                    .execute(conn)
+                    .await?;
+            }
+            None => {
+                request.save().execute(conn).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Where personal data of users is stored, and what to do with it on erasure.
+pub struct PersonalData<U: Model> {
+    user_erasure: Erasure,
+    sources: Vec<Source>,
+    hooks: Vec<Arc<dyn DataHook>>,
+    directory: Option<PathBuf>,
+    _user: PhantomData<fn() -> U>,
+}
+
+impl<U: Model> Default for PersonalData<U> {
+    fn default() -> Self {
+        Self {
+            user_erasure: Erasure::Delete,
+            sources: vec![],
+            hooks: vec![],
+            directory: None,
+            _user: PhantomData,
+        }
+    }
+}
+
+impl<U: Model> PersonalData<U> {
+    /// Personal data stored in the user model. On erasure, the user record is deleted.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Anonymize the user record on erasure instead of deleting it, e.g. to keep
+    /// records which can't be deleted pointing to it.
+    pub fn anonymize_user(mut self) -> Self
+    where
+        U: Anonymize,
+    {
+        self.user_erasure = Erasure::Anonymize(U::anonymize_sql());
+        self
+    }
+
+    /// Records of the model belonging to the user are deleted on erasure.
+    pub fn delete<T: Association<U>>(self) -> Self {
+        self.source::<T>(Erasure::Delete)
+    }
+
+    /// Personal data in records of the model belonging to the user is anonymized on erasure.
+    pub fn anonymize<T: Association<U> + Anonymize>(self) -> Self {
+        self.source::<T>(Erasure::Anonymize(T::anonymize_sql()))
+    }
+
+    /// Records of the model belonging to the user are exported, but kept on erasure.
+    pub fn keep<T: Association<U>>(self) -> Self {
+        self.source::<T>(Erasure::Keep)
+    }
+
+    fn source<T: Association<U>>(mut self, erasure: Erasure) -> Self {
+        self.sources.push(Source {
+            table: T::table_name(),
+            primary_key: T::primary_key(),
+            foreign_key: U::foreign_key(),
+            erasure,
+        });
+        self
+    }
+
+    /// Add application-specific data.
+    pub fn hook(mut self, hook: impl DataHook + 'static) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Directory where export archives are written. Defaults to the system temporary directory.
+    pub fn directory(mut self, directory: impl AsRef<Path>) -> Self {
+        self.directory = Some(directory.as_ref().to_owned());
+        self
+    }
+
+    fn user(&self) -> Source {
+        Source {
+            table: U::table_name(),
+            primary_key: U::primary_key(),
+            foreign_key: U::primary_key(),
+            erasure: self.user_erasure.clone(),
+        }
+    }
+
+    /// Collect all personal data of the user. Keys are table names
+    /// and hook names, values are the exported records.
+    pub async fn export(
+        &self,
+        user_id: i64,
+        conn: &mut ConnectionGuard,
+    ) -> Result<Map<String, Json>, Error> {
+        let mut export = Map::new();
+
+        for source in std::iter::once(self.user()).chain(self.sources.iter().cloned()) {
+            let records = source.export(user_id, conn).await?;
+            export.insert(source.table.to_string(), records);
+        }
+
+        for hook in &self.hooks {
+            let data = hook.export(user_id, conn).await?;
+            if !data.is_null() {
+                export.insert(hook.name().to_string(), data);
+            }
+        }
+
+        Ok(export)
+    }
+
+    /// Export all personal data of the user into a ZIP archive, with one JSON file per table.
+    /// Returns the path to the archive.
+    pub async fn export_archive(
+        &self,
+        user_id: i64,
+        conn: &mut ConnectionGuard,
+    ) -> Result<PathBuf, Error> {
+        self.export_request(None, user_id, conn).await
+    }
+
+    async fn export_request(
+        &self,
+        request_id: Option<i64>,
+        user_id: i64,
+        conn: &mut ConnectionGuard,
+    ) -> Result<PathBuf, Error> {
+        let export = self.export(user_id, conn).await?;
+
+        let summary = export
+            .iter()
+            .map(|(name, data)| {
+                let count = data.as_array().map(|records| records.len()).unwrap_or(1);
+                (name.clone(), Json::from(count))
+            })
+            .collect::<Map<_, _>>();
+
+        let path = self
+            .directory
+            .clone()
+            .unwrap_or_else(std::env::temp_dir)
+            .join(format!(
+                "{}-{}-{}.zip",
+                U::table_name(),
+                user_id,
+                OffsetDateTime::now_utc().unix_timestamp()
+            ));
+
+        let archive = path.clone();
+        tokio::task::spawn_blocking(move || write_archive(&archive, export))
+            .await
+            .map_err(|err| Error::Unknown(err.to_string()))??;
+
+        for hook in &self.hooks {
+            hook.exported(user_id, &path).await?;
+        }
+
+        let summary = json!({
+            "archive": path.display().to_string(),
+            "records": summary,
+        });
+
+        DataRequest::complete(
+            request_id,
+            DataRequest::new("export", U::table_name(), user_id, "completed", summary),
+            conn,
+        )
+        .await?;
+
+        info!(
+            "{} exported personal data of user {} to \"{}\"",
+            U::table_name().green(),
+            user_id,
+            path.display()
+        );
+
+        Ok(path)
+    }
+
+    /// Erase all personal data of the user. Returns the number of records
+    /// deleted or anonymized in each table.
+    ///
+    /// Use a transaction to make sure all data is erased, or none.
+    pub async fn erase(
+        &self,
+        user_id: i64,
+        conn: &mut ConnectionGuard,
+    ) -> Result<HashMap<String, u64>, Error> {
+        self.erase_request(None, user_id, conn).await
+    }
+
+    async fn erase_request(
+        &self,
+        request_id: Option<i64>,
+        user_id: i64,
+        conn: &mut ConnectionGuard,
+    ) -> Result<HashMap<String, u64>, Error> {
+        let mut erased = HashMap::new();
+
+        // Records belonging to the user first, so foreign keys don't prevent deleting the user.
+        for source in self
+            .sources
+            .iter()
+            .rev()
+            .chain(std::iter::once(&self.user()))
+        {
+            let rows = source.erase(user_id, conn).await?;
+            erased.insert(source.table.to_string(), rows);
+        }
+
+        for hook in &self.hooks {
+            hook.erase(user_id, conn).await?;
+        }
+
+        let summary = json!({ "records": erased });
+
+        DataRequest::complete(
+            request_id,
+            DataRequest::new("erasure", U::table_name(), user_id, "completed", summary),
+            conn,
+        )
+        .await?;
+
+        info!(
+            "{} erased personal data of user {}",
+            U::table_name().green(),
+            user_id
+        );
+
+        Ok(erased)
+    }
+
+    /// Export the personal data of the user in the background.
+    pub async fn export_later(&self, user_id: i64) -> Result<(), JobError>
+    where
+        U: 'static,
+    {
+        self.later("export", user_id).await
+    }
+
+    /// Erase the personal data of the user in the background.
+    pub async fn erase_later(&self, user_id: i64) -> Result<(), JobError>
+    where
+        U: 'static,
+    {
+        self.later("erasure", user_id).await
+    }
+
+    async fn later(&self, action: &str, user_id: i64) -> Result<(), JobError>
+    where
+        U: 'static,
+    {
+        let mut conn = Pool::connection().await?;
+        let request = DataRequest::new(action, U::table_name(), user_id, "pending", json!({}))
+            .save()
+            .fetch(&mut conn)
+            .await?;
+
+        self.execute_async(json!({
+            "action": action,
+            "user_id": user_id,
+            "request_id": request.id,
+        }))
+        .await
+    }
+}
+
+#[async_trait]
+impl<U: Model + 'static> Job for PersonalData<U> {
+    async fn execute(&self, args: Json) -> Result<(), JobError> {
+        let user_id = args["user_id"]
+            .as_i64()
+            .ok_or(JobError::Unknown("user_id is required".into()))?;
+        let request_id = args["request_id"].as_i64();
+
+        let mut conn = Pool::begin().await?;
+
+        let result = match args["action"].as_str() {
+            Some("export") => self
+                .export_request(request_id, user_id, &mut conn)
+                .await
+                .map(|_| ()),
+            Some("erasure") => self
+                .erase_request(request_id, user_id, &mut conn)
+                .await
+                .map(|_| ()),
+            _ => return Err(JobError::Unknown("unknown personal data action".into())),
+        };
+
+        match result {
+            Ok(()) => Ok(conn.commit().await?),
+            Err(err) => {
+                conn.rollback().await?;
+
+                let mut conn = Pool::connection().await?;
+                let summary = json!({ "error": err.to_string() });
+                let action = args["action"].as_str().unwrap_or_default();
+                DataRequest::complete(
+                    request_id,
+                    DataRequest::new(action, U::table_name(), user_id, "failed", summary),
+                    &mut conn,
+                )
+                .await?;
+
+                Err(err.into())
+            }
+        }
+    }
+}
+
+fn write_archive(path: &Path, export: Map<String, Json>) -> Result<(), Error> {
+    let file = std::fs::File::create(path)?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default();
+
+    for (name, data) in export {
+        zip.start_file(format!("{}.json", name), options)
+            .map_err(std::io::Error::from)?;
+        let json = serde_json::to_vec_pretty(&data)
+            .map_err(|err| Error::ValueError("personal data", err.to_string()))?;
+        zip.write_all(&json)?;
+    }
+
+    zip.finish().map_err(std::io::Error::from)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::anonymize::Pii;
+    use crate::model::test::bootstrap;
+    use std::io::Read;
+
+    #[derive(Clone, Debug)]
+    struct Member {
+        id: Option<i64>,
+        email: String,
+    }
+
+    impl FromRow for Member {
+        fn from_row(row: tokio_postgres::Row) -> Result<Self, Error> {
+            Ok(Self {
+                id: row.try_get("id")?,
+                email: row.try_get("email")?,
+            })
+        }
+    }
+
+    impl Model for Member {
+        fn id(&self) -> Value {
+            self.id.to_value()
+        }
+
+        fn table_name() -> &'static str {
+            "rwf_test_gdpr_members"
+        }
+
+        fn foreign_key() -> &'static str {
+            "member_id"
+        }
+
+        fn column_names() -> &'static [&'static str] {
+            &["email"]
+        }
+
+        fn values(&self) -> Vec<Value> {
+            vec![self.email.to_value()]
+        }
+    }
+
+    impl Anonymize for Member {
+        fn pii() -> &'static [(&'static str, Pii)] {
+            &[("email", Pii::Email)]
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct Note {
+        id: Option<i64>,
+        member_id: i64,
+        body: String,
+    }
+
+    impl FromRow for Note {
+        fn from_row(row: tokio_postgres::Row) -> Result<Self, Error> {
+            Ok(Self {
+                id: row.try_get("id")?,
+                member_id: row.try_get("member_id")?,
+                body: row.try_get("body")?,
+            })
+        }
+    }
+
+    impl Model for Note {
+        fn id(&self) -> Value {
+            self.id.to_value()
+        }
+
+        fn table_name() -> &'static str {
+            "rwf_test_gdpr_notes"
+        }
+
+        fn foreign_key() -> &'static str {
+            "note_id"
+        }
+
+        fn column_names() -> &'static [&'static str] {
+            &["member_id", "body"]
+        }
+
+        fn values(&self) -> Vec<Value> {
+            vec![self.member_id.to_value(), self.body.to_value()]
+        }
+    }
+
+    impl Association<Member> for Note {}
+
+    struct Avatar;
+
+    #[async_trait]
+    impl DataHook for Avatar {
+        fn name(&self) -> &'static str {
+            "avatar"
+        }
+
+        async fn export(&self, user_id: i64, _conn: &mut ConnectionGuard) -> Result<Json, Error> {
+            Ok(json!({ "url": format!("https://example.com/{}.png", user_id) }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_export_erase() -> Result<(), Error> {
+        let mut conn = Pool::begin().await?;
+
+        bootstrap(conn.client()).await?;
+
+        conn.client()
+            .batch_execute(
+                "CREATE TABLE rwf_test_gdpr_members (
+                    id BIGSERIAL PRIMARY KEY,
+                    email VARCHAR NOT NULL
+                );
+                CREATE TABLE rwf_test_gdpr_notes (
+                    id BIGSERIAL PRIMARY KEY,
+                    member_id BIGINT NOT NULL REFERENCES rwf_test_gdpr_members(id),
+                    body VARCHAR NOT NULL
+                );",
+            )
+            .await?;
+
+        let member = Member::create(&[("email", "jane@example.org")])
+            .fetch(&mut conn)
+            .await?;
+        let member_id = member.id.unwrap();
+
+        for body in ["first", "second"] {
+            Note::create(&[
+                ("member_id", member_id.to_value()),
+                ("body", body.to_value()),
+            ])
+            .execute(&mut conn)
+            .await?;
+        }
+
+        let directory = std::env::temp_dir().join("rwf_test_gdpr");
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let personal_data = PersonalData::<Member>::new()
+            .delete::<Note>()
+            .hook(Avatar)
+            .directory(&directory);
+
+        let export = personal_data.export(member_id, &mut conn).await?;
+        assert_eq!(
+            export["rwf_test_gdpr_members"][0]["email"],
+            "jane@example.org"
+        );
+        assert_eq!(export["rwf_test_gdpr_notes"].as_array().unwrap().len(), 2);
+        assert_eq!(export["rwf_test_gdpr_notes"][1]["body"], "second");
+        assert!(export["avatar"]["url"].is_string());
+
+        let archive = personal_data.export_archive(member_id, &mut conn).await?;
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(&archive).unwrap()).unwrap();
+        assert_eq!(zip.len(), 3);
+        let mut notes = String::new();
+        zip.by_name("rwf_test_gdpr_notes.json")
+            .unwrap()
+            .read_to_string(&mut notes)
+            .unwrap();
+        assert!(notes.contains("first"));
+        std::fs::remove_file(archive).unwrap();
+
+        // Keep the user, but remove their personal data.
+        let erased = personal_data
+            .anonymize_user()
+            .erase(member_id, &mut conn)
+            .await?;
+        assert_eq!(erased["rwf_test_gdpr_notes"], 2);
+        assert_eq!(erased["rwf_test_gdpr_members"], 1);
+
+        assert_eq!(Note::all().count(&mut conn).await?, 0);
+        let member = Member::find(member_id).fetch(&mut conn).await?;
+        assert_ne!(member.email, "jane@example.org");
+
+        let requests = DataRequest::filter("subject_id", member_id)
+            .filter("subject", "rwf_test_gdpr_members")
+            .order("id")
+            .fetch_all(&mut conn)
+            .await?;
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].action, "export");
+        assert_eq!(requests[1].action, "erasure");
+        assert_eq!(requests[1].status, "completed");
+        assert_eq!(requests[1].summary["records"]["rwf_test_gdpr_notes"], 2);
+
+        conn.rollback().await
+    }
+}
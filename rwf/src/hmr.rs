@@ -12,7 +12,10 @@ use notify::{
 use tokio::time::sleep;
 
 use crate::http::websocket::Message;
-use crate::{comms::Comms, view::TurboStream};
+use crate::{
+    comms::Comms,
+    view::{Templates, TurboStream},
+};
 
 use parking_lot::Mutex;
 use std::sync::Arc;
@@ -34,6 +37,13 @@ pub fn hmr(path: PathBuf) {
                 match event.kind {
                     EventKind::Access(AccessKind::Close(AccessMode::Write))
                     | EventKind::Modify(ModifyKind::Data(_)) => {
+                        // Compile changed templates again.
+                        let mut templates = Templates::cache();
+                        for path in &event.paths {
+                            templates.invalidate(path);
+                        }
+                        drop(templates);
+
                         let since_last_reload = last_reload.lock().elapsed();
                         *last_reload.lock() = Instant::now();
 
@@ -1,13 +1,42 @@
 //! Handle sending a response body to the client.
 //!
-//! The body can be text, HTML, raw bytes, JSON and a static file. The `Content-Type` and `Content-Length` headers
+//! The body can be text, HTML, raw bytes, JSON, a static file or a stream. The `Content-Type` and `Content-Length` headers
 //! are set automatically.
+use bytes::Bytes;
+use parking_lot::Mutex;
 use std::fmt::Debug;
 use std::fs::Metadata;
 use std::marker::Unpin;
 use std::path::PathBuf;
+use std::pin::Pin;
 use tokio::fs::File;
-use tokio::io::{copy, AsyncWrite, AsyncWriteExt};
+use tokio::io::{copy, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_stream::{Stream, StreamExt};
+
+/// Stream of body chunks.
+// The mutex makes the body `Sync` without requiring it from the stream;
+// it's never locked since the stream is only polled through a mutable reference.
+pub struct BodyStream(Mutex<Pin<Box<dyn Stream<Item = Bytes> + Send>>>);
+
+impl BodyStream {
+    /// Create a body stream from any stream of bytes.
+    pub fn new<B: Into<Bytes>>(stream: impl Stream<Item = B> + Send + 'static) -> Self {
+        Self(Mutex::new(Box::pin(stream.map(|chunk| chunk.into()))))
+    }
+}
+
+impl BodyStream {
+    /// Get the stream of chunks.
+    pub(crate) fn into_inner(self) -> Pin<Box<dyn Stream<Item = Bytes> + Send>> {
+        self.0.into_inner()
+    }
+}
+
+impl Debug for BodyStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("BodyStream")
+    }
+}
 
 /// Response body.
 #[derive(Debug)]
@@ -18,6 +47,13 @@ pub enum Body {
         file: File,
         metadata: Metadata,
     },
+    /// Part of a static file, sent in response to a `Range` request.
+    /// The file is positioned at the start of the range.
+    FileRange {
+        path: PathBuf,
+        file: File,
+        length: u64,
+    },
     /// UTF-8 encoded HTML.
     Html(String),
     /// Raw bytes.
@@ -28,6 +64,13 @@ pub enum Body {
     Json(Vec<u8>),
     /// A file that's already read into memory.
     FileInclude { path: PathBuf, bytes: Vec<u8> },
+    /// Chunks generated while the response is sent.
+    ///
+    /// If the length is not known, the body is sent using chunked transfer encoding.
+    Stream {
+        stream: BodyStream,
+        length: Option<usize>,
+    },
 }
 
 impl Clone for Body {
@@ -35,7 +78,7 @@ impl Clone for Body {
     ///
     /// # Panics
     ///
-    /// Will panic if [`Body::File`] is cloned.
+    /// Will panic if [`Body::File`], [`Body::FileRange`] or [`Body::Stream`] is cloned.
     fn clone(&self) -> Self {
         use Body::*;
         match self {
@@ -47,9 +90,10 @@ impl Clone for Body {
             Text(text) => Text(text.clone()),
             Json(json) => Json(json.clone()),
             Bytes(bytes) => Bytes(bytes.clone()),
-            File { .. } => {
+            File { .. } | FileRange { .. } => {
                 panic!("file body cannot be cloned, it contains an open file descriptor")
             }
+            Stream { .. } => panic!("stream body cannot be cloned, it can only be consumed once"),
         }
     }
 }
@@ -73,13 +117,48 @@ impl Body {
         }
     }
 
+    /// Create a new body from a stream of chunks.
+    ///
+    /// If the length of the body is known in advance, it will be sent
+    /// with the `Content-Length` header, otherwise using chunked transfer encoding.
+    pub fn stream<B: Into<Bytes>>(
+        stream: impl Stream<Item = B> + Send + 'static,
+        length: Option<usize>,
+    ) -> Self {
+        Self::Stream {
+            stream: BodyStream::new(stream),
+            length,
+        }
+    }
+
+    /// The body is sent using chunked transfer encoding.
+    pub fn chunked(&self) -> bool {
+        matches!(self, Body::Stream { length: None, .. })
+    }
+
     /// Send the body to the stream. If the body is a file,
     /// it will be sent efficiently using [`tokio::io::copy`].
+    /// Streamed bodies are flushed after each chunk, so the client
+    /// receives the data as soon as it's generated.
     /// The stream is not flushed, so if call `stream.flush().await`
     /// to make sure the data reaches the client.
-    pub async fn send(
+    pub async fn send(&mut self, stream: impl AsyncWrite + Unpin) -> Result<(), std::io::Error> {
+        self.write(stream, true).await
+    }
+
+    /// Send the body without chunked transfer encoding, for protocols which
+    /// frame the data themselves, like HTTP/2.
+    pub(crate) async fn send_unframed(
+        &mut self,
+        stream: impl AsyncWrite + Unpin,
+    ) -> Result<(), std::io::Error> {
+        self.write(stream, false).await
+    }
+
+    async fn write(
         &mut self,
         mut stream: impl AsyncWrite + Unpin,
+        chunked: bool,
     ) -> Result<(), std::io::Error> {
         use Body::*;
 
@@ -88,11 +167,72 @@ impl Body {
                 copy(file, &mut stream).await?;
                 Ok(())
             }
+            FileRange { file, length, .. } => {
+                copy(&mut file.take(*length), &mut stream).await?;
+                Ok(())
+            }
             Bytes(bytes) => Ok(stream.write_all(bytes).await?),
             Text(text) => Ok(stream.write_all(text.as_bytes()).await?),
             Html(html) => Ok(stream.write_all(html.as_bytes()).await?),
             Json(json) => Ok(stream.write_all(json.as_slice()).await?),
             FileInclude { bytes, .. } => Ok(stream.write_all(bytes).await?),
+            Stream {
+                stream: body,
+                length,
+            } => {
+                let mut sent = 0;
+
+                while let Some(chunk) = body.0.get_mut().next().await {
+                    // An empty chunk would terminate the chunked body early.
+                    if chunk.is_empty() {
+                        continue;
+                    }
+
+                    sent += chunk.len();
+
+                    if length.is_some() || !chunked {
+                        stream.write_all(&chunk).await?;
+                    } else {
+                        stream
+                            .write_all(format!("{:x}\r\n", chunk.len()).as_bytes())
+                            .await?;
+                        stream.write_all(&chunk).await?;
+                        stream.write_all(b"\r\n").await?;
+                    }
+
+                    stream.flush().await?;
+                }
+
+                match length {
+                    None if chunked => Ok(stream.write_all(b"0\r\n\r\n").await?),
+                    None => Ok(()),
+                    // The client would wait for the rest of the body forever,
+                    // so the connection has to be closed.
+                    Some(length) if *length != sent => Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        format!(
+                            "stream body length mismatch: expected {} bytes, sent {}",
+                            length, sent
+                        ),
+                    )),
+                    Some(_) => Ok(()),
+                }
+            }
+        }
+    }
+
+    /// Get the body contents, if it's loaded into memory.
+    /// Returns `None` for static files which are streamed from disk.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        use Body::*;
+
+        match self {
+            File { .. } | FileRange { .. } | Stream { .. } => None,
+            Bytes(bytes) => Some(bytes),
+            Text(text) => Some(text.as_bytes()),
+            Html(html) => Some(html.as_bytes()),
+            Json(json) => Some(json),
+            FileInclude { bytes, .. } => Some(bytes),
         }
     }
 
@@ -102,11 +242,13 @@ impl Body {
 
         match self {
             File { metadata, .. } => metadata.len() as usize,
+            FileRange { length, .. } => *length as usize,
             Bytes(bytes) => bytes.len(),
             Html(html) => html.as_bytes().len(),
             Json(json) => json.len(),
             Text(text) => text.as_bytes().len(),
             FileInclude { bytes, .. } => bytes.len(),
+            Stream { length, .. } => length.unwrap_or(0),
         }
     }
 
@@ -125,7 +267,7 @@ impl Body {
         use Body::*;
 
         match self {
-            File { path, .. } | FileInclude { path, .. } => {
+            File { path, .. } | FileRange { path, .. } | FileInclude { path, .. } => {
                 // Guessing the mime by the extension.
                 let extension = match path.extension() {
                     Some(extension) => extension.to_str().expect("OsStr to_str"),
@@ -221,7 +363,7 @@ impl Body {
             Text(_) => "text/plain",
             Html(_) => "text/html; charset=utf-8",
             Json(_) => "application/json",
-            Bytes(_) => "application/octet-stream",
+            Bytes(_) | Stream { .. } => "application/octet-stream",
         }
     }
 }
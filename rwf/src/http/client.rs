@@ -0,0 +1,318 @@
+//! HTTP client, used to call external services, e.g. a search engine.
+//!
+//! Connections are pooled and reused between requests. HTTPS is supported,
+//! with certificates verified using the Mozilla root certificates.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use rwf::http::client::Client;
+//!
+//! let response = Client::post("http://localhost:7700/indexes/posts/search")
+//!     .bearer("master-key")
+//!     .json(&serde_json::json!({ "q": "rust" }))?
+//!     .send()
+//!     .await?;
+//!
+//! let results: serde_json::Value = response.json()?;
+//! ```
+use std::time::Duration;
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::{connect::HttpConnector, Client as HyperClient};
+use hyper_util::rt::TokioExecutor;
+use once_cell::sync::Lazy;
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+use tokio::time::timeout;
+use tokio_rustls::rustls::crypto::ring;
+
+use super::{Headers, Request};
+
+/// Error returned by the HTTP client.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The request couldn't be sent or the response couldn't be read.
+    #[error("http client error: {0}")]
+    Request(String),
+
+    /// The response didn't arrive in time.
+    #[error("http client timeout")]
+    Timeout(#[from] tokio::time::error::Elapsed),
+
+    /// Error encoding/decoding JSON.
+    #[error("json: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+static CLIENT: Lazy<HyperClient<HttpsConnector<HttpConnector>, Full<Bytes>>> = Lazy::new(|| {
+    let connector = HttpsConnectorBuilder::new()
+        .with_provider_and_webpki_roots(ring::default_provider())
+        .expect("tls configuration")
+        .https_or_http()
+        .enable_http1()
+        .build();
+
+    HyperClient::builder(TokioExecutor::new()).build(connector)
+});
+
+/// HTTP request sent to another server.
+#[derive(Debug, Clone)]
+pub struct Client {
+    method: http::Method,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    timeout: Duration,
+}
+
+impl Client {
+    /// Create a request using the specified method, e.g. `PATCH`.
+    pub fn new(method: &str, url: impl ToString) -> Self {
+        Self {
+            method: http::Method::from_bytes(method.to_uppercase().as_bytes())
+                .unwrap_or(http::Method::GET),
+            url: url.to_string(),
+            headers: vec![],
+            body: vec![],
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Create a `GET` request.
+    pub fn get(url: impl ToString) -> Self {
+        Self::new("GET", url)
+    }
+
+    /// Create a `POST` request.
+    pub fn post(url: impl ToString) -> Self {
+        Self::new("POST", url)
+    }
+
+    /// Create a `PUT` request.
+    pub fn put(url: impl ToString) -> Self {
+        Self::new("PUT", url)
+    }
+
+    /// Create a `HEAD` request.
+    pub fn head(url: impl ToString) -> Self {
+        Self::new("HEAD", url)
+    }
+
+    /// Create a `DELETE` request.
+    pub fn delete(url: impl ToString) -> Self {
+        Self::new("DELETE", url)
+    }
+
+    /// Add a header to the request.
+    pub fn header(mut self, name: impl ToString, value: impl ToString) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Authenticate with a bearer token, e.g. an API key.
+    pub fn bearer(self, token: impl std::fmt::Display) -> Self {
+        self.header("authorization", format!("Bearer {}", token))
+    }
+
+    /// Set the request body.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Set the request body to the value serialized as JSON.
+    pub fn json(self, body: &impl Serialize) -> Result<Self, Error> {
+        let body = serde_json::to_vec(body)?;
+        Ok(self.header("content-type", "application/json").body(body))
+    }
+
+    /// Set the request body to URL-encoded form fields, e.g. to call an OAuth2 provider.
+    pub fn form(self, fields: &[(&str, &str)]) -> Self {
+        let body = fields
+            .iter()
+            .map(|(name, value)| format!("{}={}", super::urlencode(name), super::urlencode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+        self.header("content-type", "application/x-www-form-urlencoded")
+            .body(body)
+    }
+
+    /// Pass the ID and the trace of the request being handled to the other service,
+    /// so its logs can be matched with ours.
+    pub fn propagate(self, request: &Request) -> Self {
+        let client = self.header("x-request-id", request.id());
+
+        match request.trace() {
+            Some(trace) => {
+                let client = client.header("traceparent", trace.traceparent());
+                match trace.state {
+                    Some(ref state) => client.header("tracestate", state),
+                    None => client,
+                }
+            }
+            None => client,
+        }
+    }
+
+    /// How long to wait for the response. Default: 30 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send the request and read the response.
+    pub async fn send(self) -> Result<ClientResponse, Error> {
+        let mut request = http::Request::builder().method(self.method).uri(&self.url);
+
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+
+        let request = request
+            .body(Full::new(Bytes::from(self.body)))
+            .map_err(|err| Error::Request(err.to_string()))?;
+
+        let response = timeout(self.timeout, async {
+            let response = CLIENT
+                .request(request)
+                .await
+                .map_err(|err| Error::Request(err.to_string()))?;
+            let (parts, body) = response.into_parts();
+            let body = body
+                .collect()
+                .await
+                .map_err(|err| Error::Request(err.to_string()))?
+                .to_bytes();
+
+            Ok::<_, Error>((parts, body))
+        })
+        .await??;
+
+        let (parts, body) = response;
+        let mut headers = Headers::new();
+        for (name, value) in &parts.headers {
+            if let Ok(value) = value.to_str() {
+                headers.insert(name.as_str(), value);
+            }
+        }
+
+        Ok(ClientResponse {
+            code: parts.status.as_u16(),
+            headers,
+            body: body.to_vec(),
+        })
+    }
+}
+
+/// Response received from another server.
+#[derive(Debug, Clone)]
+pub struct ClientResponse {
+    code: u16,
+    headers: Headers,
+    body: Vec<u8>,
+}
+
+impl ClientResponse {
+    /// Response status code.
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    /// The status code is 2xx.
+    pub fn ok(&self) -> bool {
+        (200..300).contains(&self.code)
+    }
+
+    /// Response headers.
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Response body.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Response body as text. Invalid UTF-8 characters are replaced.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+
+    /// Deserialize the response body from JSON.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::controller::{Controller, Error as ControllerError};
+    use crate::http::request::test::dummy_ip;
+    use crate::http::{Response, Router, Server};
+    use std::sync::Arc;
+
+    struct Echo;
+
+    #[crate::async_trait]
+    impl Controller for Echo {
+        fn skip_csrf(&self) -> bool {
+            true
+        }
+
+        async fn handle(&self, request: &Request) -> Result<Response, ControllerError> {
+            let body = String::from_utf8_lossy(request.body()).to_string();
+            let header = request.header("x-test").cloned().unwrap_or_default();
+            Ok(Response::new().code(201).json(serde_json::json!({
+                "method": request.method().to_string(),
+                "header": header,
+                "body": body,
+            }))?)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client() {
+        let router = Arc::new(Router::new(vec![Echo.route("/echo")]).unwrap());
+        let addr = Server::serve_test(router).await;
+
+        let response = Client::post(format!("http://{}/echo", addr))
+            .header("x-test", "1")
+            .form(&[("name", "rwf framework")])
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.code(), 201);
+        assert!(response.ok());
+        let json: serde_json::Value = response.json().unwrap();
+        assert_eq!(json["method"], "POST");
+        assert_eq!(json["header"], "1");
+        assert_eq!(json["body"], "name=rwf%20framework");
+        assert!(response.headers().get("x-request-id").is_some());
+
+        // The request ID is passed to other services.
+        let request = Request::read(
+            dummy_ip(),
+            "GET / HTTP/1.1\r\nX-Request-Id: abc-123\r\n\r\n".as_bytes(),
+        )
+        .await
+        .unwrap();
+        let response = Client::get(format!("http://{}/echo", addr))
+            .propagate(&request)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.headers().get("x-request-id").unwrap(), "abc-123");
+
+        let response = Client::get(format!("http://{}/missing", addr))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.code(), 404);
+        assert!(!response.ok());
+    }
+}
@@ -0,0 +1,157 @@
+//! Response compression and request decompression.
+//!
+//! Responses are compressed with `br` (Brotli) or `gzip`, depending on what the client
+//! accepts in the `Accept-Encoding` header. Only responses of configured content types,
+//! and larger than the configured minimum size, are compressed. See [`crate::config::CompressionConfig`].
+//!
+//! Request bodies sent with `Content-Encoding: gzip` are decompressed before they are passed to controllers.
+use std::io::{Read, Write};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+use super::Error;
+
+/// Content encoding supported by the server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    /// Brotli.
+    Brotli,
+    /// Gzip.
+    Gzip,
+}
+
+impl Encoding {
+    /// Name of the encoding used in the `Content-Encoding` header.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// Pick the encoding from the `Accept-Encoding` header value.
+    ///
+    /// The encoding with the highest quality value is used. If the client has no preference,
+    /// Brotli is preferred over gzip. Returns `None` if the client doesn't accept
+    /// any supported encoding.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rwf::http::compression::Encoding;
+    /// assert_eq!(Encoding::negotiate("gzip, deflate, br"), Some(Encoding::Brotli));
+    /// assert_eq!(Encoding::negotiate("br;q=0.5, gzip"), Some(Encoding::Gzip));
+    /// assert_eq!(Encoding::negotiate("identity"), None);
+    /// ```
+    pub fn negotiate(accept_encoding: &str) -> Option<Self> {
+        let mut brotli = None;
+        let mut gzip = None;
+        let mut wildcard = None;
+
+        for value in accept_encoding.split(",") {
+            let mut parts = value.split(";");
+            let name = parts.next().unwrap_or("").trim().to_lowercase();
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .filter_map(|q| q.trim().parse::<f32>().ok())
+                .next()
+                .unwrap_or(1.0);
+
+            match name.as_str() {
+                "br" => brotli = Some(quality),
+                "gzip" | "x-gzip" => gzip = Some(quality),
+                "*" => wildcard = Some(quality),
+                _ => (),
+            }
+        }
+
+        let brotli = brotli.or(wildcard).unwrap_or(0.0);
+        let gzip = gzip.or(wildcard).unwrap_or(0.0);
+
+        if brotli <= 0.0 && gzip <= 0.0 {
+            None
+        } else if brotli >= gzip {
+            Some(Encoding::Brotli)
+        } else {
+            Some(Encoding::Gzip)
+        }
+    }
+
+    /// Compress the bytes.
+    pub fn compress(&self, bytes: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+        match self {
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(vec![], Compression::default());
+                encoder.write_all(bytes)?;
+                encoder.finish()
+            }
+
+            Encoding::Brotli => {
+                let mut output = vec![];
+                {
+                    // Quality 5 is a good tradeoff between speed and compression ratio
+                    // for dynamically generated responses.
+                    let mut encoder = brotli::CompressorWriter::new(&mut output, 4096, 5, 22);
+                    encoder.write_all(bytes)?;
+                    encoder.flush()?;
+                }
+                Ok(output)
+            }
+        }
+    }
+}
+
+/// Decompress a gzip-encoded request body.
+///
+/// Bodies that decompress to more than `limit` bytes are rejected,
+/// so small requests can't expand into very large ones.
+pub fn gunzip(bytes: &[u8], limit: usize) -> Result<Vec<u8>, Error> {
+    let mut body = vec![];
+    GzDecoder::new(bytes)
+        .take(limit as u64 + 1)
+        .read_to_end(&mut body)
+        .map_err(|_| Error::MalformedRequest("invalid gzip body"))?;
+
+    if body.len() > limit {
+        Err(Error::MalformedRequest("decompressed body is too large"))
+    } else {
+        Ok(body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Encoding::negotiate("gzip"), Some(Encoding::Gzip));
+        assert_eq!(Encoding::negotiate("br, gzip"), Some(Encoding::Brotli));
+        assert_eq!(
+            Encoding::negotiate("br;q=0.2, gzip;q=0.8"),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(Encoding::negotiate("*"), Some(Encoding::Brotli));
+        assert_eq!(Encoding::negotiate("*, br;q=0"), Some(Encoding::Gzip));
+        assert_eq!(Encoding::negotiate("gzip;q=0, deflate"), None);
+        assert_eq!(Encoding::negotiate(""), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let body = "hello world ".repeat(100);
+
+        let gzip = Encoding::Gzip.compress(body.as_bytes()).unwrap();
+        assert!(gzip.len() < body.len());
+        assert_eq!(gunzip(&gzip, 4096).unwrap(), body.as_bytes());
+        assert!(gunzip(&gzip, 100).is_err());
+        assert!(gunzip(b"not gzip", 4096).is_err());
+
+        let brotli = Encoding::Brotli.compress(body.as_bytes()).unwrap();
+        let mut decompressed = vec![];
+        brotli::Decompressor::new(brotli.as_slice(), 4096)
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, body.as_bytes());
+    }
+}
@@ -2,18 +2,19 @@
 //!
 //! This module handles decoding the `Cookie` header,
 //! and generating `Set-Cookie` headers.
+use base64::{engine::general_purpose, Engine as _};
 use std::collections::HashMap;
 use time::{Duration, OffsetDateTime};
 
 use super::url::urldecode;
-use super::Error;
+use super::{Error, ToParameter};
 use crate::config::get_config;
 use crate::controller::Session;
-use crate::crypto::{decrypt, encrypt};
+use crate::crypto::{decrypt_with, encrypt_with, sign, verify, Purpose};
 
 /// Cookies storage.
 ///
-/// Supports both plain text and encrypted (private) cookies.
+/// Supports plain text, signed and encrypted (private) cookies.
 #[derive(Debug, Clone, Default)]
 pub struct Cookies {
     cookies: HashMap<String, Cookie>,
@@ -60,8 +61,17 @@ impl Cookies {
     /// If this is set on the [`crate::http::Response`], this cookie will be sent
     /// to the client.
     pub fn add_private(&mut self, cookie: impl ToCookie) -> Result<(), Error> {
+        Ok(self.add_encrypted(cookie, Purpose::Data)?)
+    }
+
+    /// Add a cookie encrypted with the key for this purpose.
+    pub fn add_encrypted(
+        &mut self,
+        cookie: impl ToCookie,
+        purpose: Purpose,
+    ) -> Result<(), crate::crypto::Error> {
         let mut cookie = cookie.to_cookie();
-        cookie.value = encrypt(cookie.value.as_bytes())?;
+        cookie.value = encrypt_with(purpose, cookie.value.as_bytes())?;
         self.cookies.insert(cookie.name.clone(), cookie);
 
         Ok(())
@@ -76,7 +86,7 @@ impl Cookies {
     pub fn get_private(&self, name: &str) -> Result<Option<Cookie>, Error> {
         if let Some(cookie) = self.cookies.get(name) {
             let mut cookie = cookie.clone();
-            cookie.value = String::from_utf8(match decrypt(&cookie.value) {
+            cookie.value = String::from_utf8(match decrypt_with(Purpose::Data, &cookie.value) {
                 Ok(value) => value,
                 Err(_) => return Ok(None),
             })?;
@@ -86,6 +96,54 @@ impl Cookies {
         }
     }
 
+    /// Get a cookie encrypted with the key for this purpose, see [`Cookies::add_encrypted`].
+    ///
+    /// `None` is returned if the cookie isn't set or can't be decrypted.
+    pub fn get_encrypted(&self, name: &str, purpose: Purpose) -> Option<Cookie> {
+        let mut cookie = self.cookies.get(name)?.clone();
+        let value = decrypt_with(purpose, &cookie.value).ok()?;
+        cookie.value = String::from_utf8(value).ok()?;
+
+        Some(cookie)
+    }
+
+    /// Add a signed cookie. The client can read its value, but can't change it
+    /// without [`Cookies::get_signed`] noticing. Use private cookies for secrets.
+    pub fn add_signed(&mut self, cookie: impl ToCookie) {
+        let mut cookie = cookie.to_cookie();
+        let value = general_purpose::URL_SAFE_NO_PAD.encode(&cookie.value);
+        let signature = sign(format!("{}={}", cookie.name, value).as_bytes());
+        cookie.value = format!("{}.{}", value, signature);
+        self.cookies.insert(cookie.name.clone(), cookie);
+    }
+
+    /// Get a signed cookie received from the client, converted to the requested type.
+    ///
+    /// `None` is returned if the cookie isn't set, its value was modified or signed with
+    /// a different secret key, or it can't be converted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rwf::http::Cookies;
+    /// let mut cookies = Cookies::new();
+    /// cookies.add_signed(("visits", "5"));
+    ///
+    /// let cookies = Cookies::parse(&cookies.get("visits").unwrap().to_string());
+    /// assert_eq!(cookies.get_signed::<i64>("visits"), Some(5));
+    /// ```
+    pub fn get_signed<T: ToParameter>(&self, name: &str) -> Option<T> {
+        let cookie = self.cookies.get(name)?;
+        let (value, signature) = cookie.value.rsplit_once('.')?;
+
+        if !verify(format!("{}={}", name, value).as_bytes(), signature) {
+            return None;
+        }
+
+        let value = general_purpose::URL_SAFE_NO_PAD.decode(value).ok()?;
+        T::to_parameter(&String::from_utf8(value).ok()?).ok()
+    }
+
     /// Add a cookie.
     ///
     /// If this is done to the response, the cookie will be sent it to the client,
@@ -106,7 +164,7 @@ impl Cookies {
     ///
     /// If the session is not valid UTF-8, an error is returned.
     pub fn get_session(&self) -> Result<Option<Session>, Error> {
-        let cookie = self.get_private("rwf_session")?;
+        let cookie = self.get_encrypted("rwf_session", Purpose::Session);
 
         if let Some(cookie) = cookie {
             Ok(serde_json::from_str(cookie.value())?)
@@ -119,23 +177,53 @@ impl Cookies {
     /// when the session does.
     pub fn add_session(&mut self, session: &Session) -> Result<(), Error> {
         let value = serde_json::to_string(session)?;
-        self.add_private(
+        Ok(self.add_encrypted(
             CookieBuilder::new()
                 .name("rwf_session")
                 .value(value)
                 .expiration(OffsetDateTime::from_unix_timestamp(session.expiration)?)
                 .build(),
-        )
+            Purpose::Session,
+        )?)
+    }
+
+    /// Get the key of a session kept in a server-side [session store](crate::controller::session_store),
+    /// if one is set.
+    pub fn get_session_key(&self) -> Result<Option<String>, Error> {
+        Ok(self
+            .get_encrypted("rwf_session_key", Purpose::Session)
+            .map(|cookie| cookie.value().to_string()))
+    }
+
+    /// Set the key of a session kept in a server-side session store. The cookie expires
+    /// when the session does.
+    pub fn add_session_key(&mut self, key: &str, expiration: i64) -> Result<(), Error> {
+        Ok(self.add_encrypted(
+            CookieBuilder::new()
+                .name("rwf_session_key")
+                .value(key)
+                .expiration(OffsetDateTime::from_unix_timestamp(expiration)?)
+                .build(),
+            Purpose::Session,
+        )?)
     }
 
     /// Convert cookies to `Set-Cookie` headers which will be sent to the client.
     pub fn to_headers(&self) -> Vec<u8> {
         let mut headers = vec![];
-        for (_, cookie) in &self.cookies {
+        for cookie in self.set_cookie() {
             headers.extend_from_slice(format!("set-cookie: {}\r\n", cookie).as_bytes());
         }
         headers
     }
+
+    /// Values of the `Set-Cookie` headers, one per cookie.
+    pub(crate) fn set_cookie(&self) -> Vec<String> {
+        self.cookies
+            .values()
+            .map(|cookie| cookie.to_string())
+            .collect()
+    }
 }
 
 impl std::fmt::Display for Cookies {
@@ -187,7 +275,44 @@ pub struct Cookie {
     domain: Option<String>,
     http_only: bool,
     secure: bool,
-    same_site: Option<String>,
+    same_site: Option<SameSite>,
+}
+
+/// The cookie `SameSite` attribute, controlling if the browser sends the cookie
+/// with requests started by other sites.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SameSite {
+    /// Only send the cookie with requests from this site.
+    Strict,
+    /// Also send the cookie when the user follows a link from another site.
+    #[default]
+    Lax,
+    /// Send the cookie with all requests, including from other sites' pages.
+    /// The cookie must be `Secure`.
+    None,
+}
+
+impl std::fmt::Display for SameSite {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SameSite::Strict => write!(f, "Strict"),
+            SameSite::Lax => write!(f, "Lax"),
+            SameSite::None => write!(f, "None"),
+        }
+    }
+}
+
+impl std::str::FromStr for SameSite {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "strict" => Ok(SameSite::Strict),
+            "lax" => Ok(SameSite::Lax),
+            "none" => Ok(SameSite::None),
+            _ => Err(()),
+        }
+    }
 }
 
 impl Cookie {
@@ -219,6 +344,16 @@ impl Cookie {
                     "Secure" => {
                         builder = builder.secure();
                     }
+                    "Path" => {
+                        if let Some(value) = value {
+                            builder = builder.path(value);
+                        }
+                    }
+                    "SameSite" => {
+                        if let Some(same_site) = value.and_then(|value| value.parse().ok()) {
+                            builder = builder.same_site(same_site);
+                        }
+                    }
                     "Max-Age" => {
                         if let Some(value) = value {
                             match value.parse::<i64>() {
@@ -272,19 +407,34 @@ impl Cookie {
     }
 
     /// Check if the cookie is secure.
-    fn secure(&self) -> bool {
+    pub fn secure(&self) -> bool {
         self.secure
     }
 
     /// Check if the cookie is HTTP-only.
-    fn http_only(&self) -> bool {
+    pub fn http_only(&self) -> bool {
         self.http_only
     }
 
     /// Get the cookie's `MaxAge` attribute if any is set.
-    fn max_age(&self) -> Option<Duration> {
+    pub fn max_age(&self) -> Option<Duration> {
         self.max_age
     }
+
+    /// Get the cookie's `Path` attribute if any is set.
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// Get the cookie's `Domain` attribute if any is set.
+    pub fn domain(&self) -> Option<&str> {
+        self.domain.as_deref()
+    }
+
+    /// Get the cookie's `SameSite` attribute. Cookies are `Lax` unless set otherwise.
+    pub fn same_site(&self) -> SameSite {
+        self.same_site.unwrap_or_default()
+    }
 }
 
 impl std::fmt::Display for Cookie {
@@ -292,7 +442,7 @@ impl std::fmt::Display for Cookie {
         write!(f, "{}={}", self.name, self.value)?;
 
         if let Some(ref max_age) = self.max_age {
-            write!(f, "; Max-Age: {}", max_age.whole_seconds())?;
+            write!(f, "; Max-Age={}", max_age.whole_seconds())?;
         }
 
         if self.secure {
@@ -313,11 +463,7 @@ impl std::fmt::Display for Cookie {
             write!(f, "; Domain={}", domain)?;
         }
 
-        if let Some(ref same_site) = self.same_site {
-            write!(f, "; SameSite={}", same_site)?;
-        } else {
-            write!(f, "; SameSite=Lax")?;
-        }
+        write!(f, "; SameSite={}", self.same_site())?;
 
         if let Some(ref expiration) = self.expiration {
             write!(
@@ -418,20 +564,33 @@ impl CookieBuilder {
     ///
     /// This setting is desirable if you want
     /// the cookie set on redirects from external sites.
-    pub fn lax(mut self) -> Self {
-        self.cookie.same_site = Some("Lax".to_string());
-        self
+    pub fn lax(self) -> Self {
+        self.same_site(SameSite::Lax)
     }
 
     /// Set cookie `SameSite` attribute to `Strict`.
     ///
     /// This cookie won't be set on redirects from external links, breaking
     /// authentication.
-    pub fn strict(mut self) -> Self {
-        self.cookie.same_site = Some("Strict".to_string());
+    pub fn strict(self) -> Self {
+        self.same_site(SameSite::Strict)
+    }
+
+    /// Set cookie `SameSite` attribute. Browsers reject `SameSite=None` cookies
+    /// which aren't `Secure`, so [`SameSite::None`] makes the cookie secure as well.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        if same_site == SameSite::None {
+            self.cookie.secure = true;
+        }
+        self.cookie.same_site = Some(same_site);
         self
     }
 
+    /// Keep the cookie for 20 years, i.e. until the user deletes it.
+    pub fn permanent(self) -> Self {
+        self.max_age(Duration::days(20 * 365))
+    }
+
     /// Build the cookie.
     ///
     /// This consumes the builder.
@@ -453,6 +612,11 @@ mod test {
         assert!(cookie.secure());
         assert_eq!(cookie.max_age(), Some(Duration::seconds(55)));
 
+        let value = "prefs=dark; Path=/settings; SameSite=strict";
+        let cookie = Cookie::parse(value).expect("cookie parse");
+        assert_eq!(cookie.path(), Some("/settings"));
+        assert_eq!(cookie.same_site(), SameSite::Strict);
+
         let value = "random=hello_world";
         let cookie = Cookie::parse(value).expect("cookie parse");
         assert_eq!(cookie.name(), "random");
@@ -480,4 +644,54 @@ mod test {
             "super_secret_key"
         );
     }
+
+    #[test]
+    fn test_set_cookie() {
+        let cookie = CookieBuilder::new()
+            .name("theme")
+            .value("dark")
+            .path("/app")
+            .domain("example.com")
+            .same_site(SameSite::None)
+            .http_only()
+            .permanent()
+            .build();
+        assert_eq!(
+            cookie.to_string(),
+            "theme=dark; Max-Age=630720000; Secure; HttpOnly; Path=/app; Domain=example.com; SameSite=None"
+        );
+
+        let cookie = CookieBuilder::new().name("theme").value("dark").build();
+        assert_eq!(cookie.to_string(), "theme=dark; Path=/; SameSite=Lax");
+    }
+
+    #[test]
+    fn test_signed_cookies() {
+        let mut cookies = Cookies::new();
+        cookies.add_signed(("user_id", "1234"));
+        cookies.add_signed(("name", "Alice; Bob"));
+        let value = cookies.get("user_id").unwrap().value().to_string();
+
+        let cookies = Cookies::parse(&format!(
+            "user_id={}; name={}; other={}",
+            value,
+            cookies.get("name").unwrap().value(),
+            value
+        ));
+        assert_eq!(cookies.get_signed::<i64>("user_id"), Some(1234));
+        assert_eq!(
+            cookies.get_signed::<String>("name"),
+            Some("Alice; Bob".to_string())
+        );
+        assert_eq!(cookies.get_signed::<bool>("user_id"), None);
+
+        // Signed for another cookie.
+        assert_eq!(cookies.get_signed::<i64>("other"), None);
+
+        // Modified by the client.
+        let (_, signature) = value.rsplit_once('.').unwrap();
+        let cookies = Cookies::parse(&format!("user_id=NTY3OA.{}", signature));
+        assert_eq!(cookies.get_signed::<i64>("user_id"), None);
+        assert!(cookies.get("user_id").is_some());
+    }
 }
@@ -0,0 +1,225 @@
+//! Route coverage, i.e. which routes were exercised by tests.
+//!
+//! The [`Router`](super::Router) counts requests routed to each handler. After running the tests against
+//! a server, its coverage shows which routes were never requested, and which can't be requested at all
+//! because other routes take precedence, e.g. `/users/new` added before `/users/:id`.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let server = Server::new(routes);
+//! tokio::spawn(server.clone().launch());
+//!
+//! // Run the tests...
+//!
+//! server.coverage().assert_reachable().assert_visited();
+//! ```
+use std::fmt::Write;
+
+/// Requests routed to a handler.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteVisits {
+    /// Route path, e.g. `/users/:id`.
+    pub path: String,
+    /// Name of the controller serving the route.
+    pub controller: &'static str,
+    /// Number of requests routed to the controller.
+    pub visits: usize,
+    /// Path of the route taking all requests matching this one, if any.
+    pub shadowed_by: Option<String>,
+}
+
+impl RouteVisits {
+    /// At least one request was routed to the controller.
+    pub fn visited(&self) -> bool {
+        self.visits > 0
+    }
+
+    /// No request can be routed to the controller.
+    pub fn shadowed(&self) -> bool {
+        self.shadowed_by.is_some()
+    }
+}
+
+/// Route coverage report, created with [`Router::coverage`](super::Router::coverage)
+/// or [`Server::coverage`](super::Server::coverage).
+#[derive(Debug, Clone, Default)]
+pub struct RouteCoverage {
+    routes: Vec<RouteVisits>,
+    ignored: Vec<String>,
+}
+
+impl RouteCoverage {
+    pub(crate) fn new(routes: Vec<RouteVisits>) -> Self {
+        Self {
+            routes,
+            ignored: vec![],
+        }
+    }
+
+    /// Don't check the route with this path, e.g. a route only used in production.
+    pub fn ignore(mut self, path: impl ToString) -> Self {
+        self.ignored.push(path.to_string());
+        self
+    }
+
+    /// All routes, in the order they were added to the router, except ignored ones.
+    pub fn routes(&self) -> Vec<&RouteVisits> {
+        self.routes
+            .iter()
+            .filter(|route| !self.ignored.contains(&route.path))
+            .collect()
+    }
+
+    /// Routes which were never requested.
+    pub fn unvisited(&self) -> Vec<&RouteVisits> {
+        self.routes()
+            .into_iter()
+            .filter(|route| !route.visited())
+            .collect()
+    }
+
+    /// Routes which can't be requested because other routes take precedence.
+    pub fn shadowed(&self) -> Vec<&RouteVisits> {
+        self.routes()
+            .into_iter()
+            .filter(|route| route.shadowed())
+            .collect()
+    }
+
+    /// Fraction of routes which were requested, between 0 and 1.
+    pub fn ratio(&self) -> f64 {
+        let routes = self.routes();
+
+        if routes.is_empty() {
+            1.0
+        } else {
+            let visited = routes.iter().filter(|route| route.visited()).count();
+            visited as f64 / routes.len() as f64
+        }
+    }
+
+    /// Panic if some routes were never requested, listing them.
+    #[track_caller]
+    pub fn assert_visited(&self) -> &Self {
+        let unvisited = self.unvisited();
+
+        if !unvisited.is_empty() {
+            let mut message = format!("{} route(s) not visited:", unvisited.len());
+            for route in unvisited {
+                write!(&mut message, "\n  {} => {}", route.path, route.controller).unwrap();
+            }
+            panic!("{}", message);
+        }
+
+        self
+    }
+
+    /// Panic if some routes can't be requested, listing them and the routes taking their requests.
+    #[track_caller]
+    pub fn assert_reachable(&self) -> &Self {
+        let shadowed = self.shadowed();
+
+        if !shadowed.is_empty() {
+            let mut message = format!("{} route(s) unreachable:", shadowed.len());
+            for route in shadowed {
+                write!(
+                    &mut message,
+                    "\n  {} => {} (shadowed by {})",
+                    route.path,
+                    route.controller,
+                    route.shadowed_by.as_deref().unwrap_or_default()
+                )
+                .unwrap();
+            }
+            panic!("{}", message);
+        }
+
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{Path, Router};
+    use crate::controller::{Controller, Error};
+    use crate::http::{Request, Response};
+
+    struct Users;
+    struct NewUser;
+    struct Files;
+
+    #[crate::async_trait]
+    impl Controller for Users {
+        async fn handle(&self, _request: &Request) -> Result<Response, Error> {
+            Ok(Response::new())
+        }
+    }
+
+    #[crate::async_trait]
+    impl Controller for NewUser {
+        async fn handle(&self, _request: &Request) -> Result<Response, Error> {
+            Ok(Response::new())
+        }
+    }
+
+    #[crate::async_trait]
+    impl Controller for Files {
+        async fn handle(&self, _request: &Request) -> Result<Response, Error> {
+            Ok(Response::new())
+        }
+    }
+
+    #[test]
+    fn test_coverage() {
+        let router = Router::new(vec![
+            NewUser.route("/users/new"),
+            Users.route("/users/:id"),
+            Files.wildcard("/files"),
+        ])
+        .unwrap();
+
+        router.find(&Path::parse("/users/5").unwrap());
+        router.find(&Path::parse("/users/6").unwrap());
+        router.find(&Path::parse("/missing").unwrap());
+
+        let coverage = router.coverage();
+        assert_eq!(coverage.routes().len(), 3);
+        assert_eq!(coverage.routes()[1].visits, 2);
+        assert_eq!(
+            coverage
+                .unvisited()
+                .iter()
+                .map(|route| route.path.as_str())
+                .collect::<Vec<_>>(),
+            vec!["/users/new", "/files/*"]
+        );
+        assert!((coverage.ratio() - 1.0 / 3.0).abs() < f64::EPSILON);
+
+        // Added before `/users/:id`, which has the same precedence.
+        let shadowed = coverage.shadowed();
+        assert_eq!(shadowed.len(), 1);
+        assert_eq!(shadowed[0].path, "/users/new");
+        assert_eq!(shadowed[0].shadowed_by.as_deref(), Some("/users/:id"));
+
+        let coverage = coverage.ignore("/users/new").ignore("/files/*");
+        coverage.assert_reachable().assert_visited();
+
+        router.reset_coverage();
+        assert!(router.coverage().unvisited().len() == 3);
+
+        // Added in the right order, both can be reached.
+        let router =
+            Router::new(vec![Users.route("/users/:id"), NewUser.route("/users/new")]).unwrap();
+        assert!(router.coverage().shadowed().is_empty());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "1 route(s) not visited:\n  /files/* => rwf::http::coverage::test::Files"
+    )]
+    fn test_assert_visited() {
+        let router = Router::new(vec![Files.wildcard("/files")]).unwrap();
+        router.coverage().assert_visited();
+    }
+}
@@ -44,6 +44,14 @@ pub enum Error {
     #[error("parameter is missing")]
     MissingParameter,
 
+    /// A URL parameter couldn't be converted to the requested type.
+    #[error("parameter \"{0}\" is invalid")]
+    InvalidParameter(String),
+
+    /// The request body couldn't be parsed into the requested type.
+    #[error("request body is invalid: {0}")]
+    InvalidBody(String),
+
     /// Something took too long.
     #[error("timeout exceeded")]
     Timeout(#[from] tokio::time::error::Elapsed),
@@ -56,6 +64,10 @@ pub enum Error {
     #[error("unauthorized")]
     Unauthorized,
 
+    /// The user isn't allowed to do this.
+    #[error("forbidden")]
+    Forbidden,
+
     /// HTTP request exceeds configured size.
     #[error("content too large")]
     ContentTooLarge(Head),
@@ -64,9 +76,41 @@ pub enum Error {
     #[error("user model id is not an integer")]
     UserIdNotAnInteger,
 
+    /// TLS configuration error, e.g. the certificate doesn't match the private key.
+    #[error("tls error: {0}")]
+    Tls(Box<tokio_rustls::rustls::Error>),
+
+    /// The app can't run in cluster mode.
+    #[error("{0}")]
+    Cluster(#[from] crate::cluster::Error),
+
+    /// The configuration failed to load or is invalid.
+    #[error("{0}")]
+    Config(#[from] crate::config::Error),
+
+    /// The session store returned an error.
+    #[error("{0}")]
+    SessionStore(#[from] crate::controller::session_store::Error),
+
     /// Model used as user has null id column.
     #[error("user model is is null")]
     UserIdIsNull,
+
+    /// The request data is invalid.
+    #[error("{0}")]
+    Validation(super::ValidationErrors),
+
+    /// A template failed to compile.
+    #[error("{0}")]
+    Template(#[from] crate::view::Error),
+
+    /// There is no route with this name.
+    #[error("route \"{0}\" doesn't exist")]
+    UnknownRoute(String),
+
+    /// A parameter of the route wasn't given when generating its URL.
+    #[error("route \"{0}\" requires the \"{1}\" parameter")]
+    MissingRouteParameter(String, String),
 }
 
 impl Error {
@@ -74,14 +118,22 @@ impl Error {
     /// that should be sent to the client.
     pub fn code(&self) -> u16 {
         match self {
-            Self::MissingParameter => 400,
+            Self::MissingParameter | Self::InvalidParameter(_) | Self::InvalidBody(_) => 400,
             Self::Unauthorized => 401,
+            Self::Forbidden => 403,
             Self::ContentTooLarge(_) => 413,
+            Self::Validation(_) => 422,
             _ => 500,
         }
     }
 }
 
+impl From<super::ValidationErrors> for Error {
+    fn from(errors: super::ValidationErrors) -> Error {
+        Error::Validation(errors)
+    }
+}
+
 impl From<crate::controller::Error> for Error {
     fn from(error: crate::controller::Error) -> Error {
         Error::Controller(error)
@@ -93,3 +145,9 @@ impl From<time::error::ComponentRange> for Error {
         Error::Time(error)
     }
 }
+
+impl From<tokio_rustls::rustls::Error> for Error {
+    fn from(error: tokio_rustls::rustls::Error) -> Error {
+        Error::Tls(Box::new(error))
+    }
+}
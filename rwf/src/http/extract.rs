@@ -0,0 +1,327 @@
+//! Typed extractors.
+//!
+//! Extractors parse parts of the request into Rust types, returning
+//! `400 - Bad Request` automatically if the request doesn't match:
+//!
+//! - [`Path`] extracts path parameters, e.g. `/users/:id`
+//! - [`Query`] extracts the URL query into a struct implementing [`FromFormData`]
+//! - [`Form`] extracts the submitted form into a struct implementing [`FromFormData`]
+//! - [`Json`] deserializes the JSON body
+//! - [`Settings`] gets an app [configuration section](crate::config::ConfigSection)
+//!
+//! Several extractors can be combined using a tuple.
+//!
+//! # Example
+//!
+//! ```
+//! use rwf::prelude::*;
+//! use rwf::http::extract::{Json, Path, Query};
+//! use serde::Deserialize;
+//!
+//! #[derive(macros::Form)]
+//! struct Pagination {
+//!     page: Option<i64>,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! struct Comment {
+//!     body: String,
+//! }
+//!
+//! #[derive(Default)]
+//! struct PostComments;
+//!
+//! #[async_trait]
+//! impl Controller for PostComments {
+//!     async fn handle(&self, request: &Request) -> Result<Response, Error> {
+//!         if request.get() {
+//!             let (Path(post_id), Query(pagination)) =
+//!                 request.extract::<(Path<i64>, Query<Pagination>)>()?;
+//!             // Fetch comments for the post.
+//!         } else {
+//!             let Json(comment) = request.extract::<Json<Comment>>()?;
+//!             // Save the comment.
+//!         }
+//!
+//!         Ok(Response::new())
+//!     }
+//! }
+//! ```
+use serde::de::DeserializeOwned;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use super::{Error, FormData, FromFormData, Request, ToParameter};
+use crate::config::{Config, ConfigSection};
+
+/// Extract a value from a request.
+///
+/// Implement this trait to create custom extractors.
+pub trait FromRequest: Sized {
+    /// Perform the extraction.
+    fn from_request(request: &Request) -> Result<Self, Error>;
+}
+
+/// Path parameters.
+///
+/// A single parameter is extracted by its position, e.g. `Path<i64>` extracts the first parameter in the path.
+/// Use a tuple to extract several parameters, e.g. `Path<(i64, String)>` for `/users/:id/files/*path`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Path<T>(pub T);
+
+/// URL query, e.g. `?page=1&page_size=25`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query<T>(pub T);
+
+/// Form submitted with `POST`, encoded as `x-www-form-urlencoded` or `multipart/form-data`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Form<T>(pub T);
+
+/// JSON request body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Json<T>(pub T);
+
+/// App configuration section, loaded from `rwf.toml`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings<T>(pub Arc<T>);
+
+impl<T> Deref for Settings<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+macro_rules! deref {
+    ($($extractor:ident),*) => {
+        $(
+            impl<T> Deref for $extractor<T> {
+                type Target = T;
+
+                fn deref(&self) -> &Self::Target {
+                    &self.0
+                }
+            }
+
+            impl<T> $extractor<T> {
+                /// Get the extracted value.
+                pub fn into_inner(self) -> T {
+                    self.0
+                }
+            }
+        )*
+    };
+}
+
+deref!(Path, Query, Form, Json);
+
+/// Convert path parameters to a Rust type.
+pub trait FromPath: Sized {
+    /// Convert parameters, identified by their position in the path.
+    fn from_path(request: &Request, names: &[&str]) -> Result<Self, Error>;
+}
+
+fn parameter<T: ToParameter>(
+    request: &Request,
+    names: &[&str],
+    position: usize,
+) -> Result<T, Error> {
+    let name = names.get(position).ok_or(Error::MissingParameter)?;
+    request.parameter(name)?.ok_or(Error::MissingParameter)
+}
+
+impl<T: ToParameter> FromPath for T {
+    fn from_path(request: &Request, names: &[&str]) -> Result<Self, Error> {
+        parameter(request, names, 0)
+    }
+}
+
+macro_rules! from_path_tuple {
+    ($($ty:ident => $position:tt),*) => {
+        impl<$($ty: ToParameter),*> FromPath for ($($ty,)*) {
+            fn from_path(request: &Request, names: &[&str]) -> Result<Self, Error> {
+                Ok(($(parameter::<$ty>(request, names, $position)?,)*))
+            }
+        }
+    };
+}
+
+from_path_tuple!(A => 0, B => 1);
+from_path_tuple!(A => 0, B => 1, C => 2);
+from_path_tuple!(A => 0, B => 1, C => 2, D => 3);
+
+impl<T: FromPath> FromRequest for Path<T> {
+    fn from_request(request: &Request) -> Result<Self, Error> {
+        Ok(Path(T::from_path(request, &request.parameter_names())?))
+    }
+}
+
+impl<T: FromFormData> FromRequest for Query<T> {
+    fn from_request(request: &Request) -> Result<Self, Error> {
+        let query = FormData::UrlEncoded(request.query().clone());
+        Ok(Query(T::from_form_data(&query)?))
+    }
+}
+
+impl<T: FromFormData> FromRequest for Form<T> {
+    fn from_request(request: &Request) -> Result<Self, Error> {
+        Ok(Form(request.form()?))
+    }
+}
+
+impl<T: DeserializeOwned> FromRequest for Json<T> {
+    fn from_request(request: &Request) -> Result<Self, Error> {
+        serde_json::from_slice(request.body())
+            .map(Json)
+            .map_err(|err| Error::InvalidBody(err.to_string()))
+    }
+}
+
+impl<T: ConfigSection> FromRequest for Settings<T> {
+    fn from_request(_request: &Request) -> Result<Self, Error> {
+        Ok(Settings(Config::section::<T>()?))
+    }
+}
+
+impl<T: FromRequest> FromRequest for Option<T> {
+    fn from_request(request: &Request) -> Result<Self, Error> {
+        Ok(T::from_request(request).ok())
+    }
+}
+
+macro_rules! from_request_tuple {
+    ($($ty:ident),*) => {
+        impl<$($ty: FromRequest),*> FromRequest for ($($ty,)*) {
+            fn from_request(request: &Request) -> Result<Self, Error> {
+                Ok(($($ty::from_request(request)?,)*))
+            }
+        }
+    };
+}
+
+from_request_tuple!(A, B);
+from_request_tuple!(A, B, C);
+from_request_tuple!(A, B, C, D);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::path::PathWithRegex;
+    use crate::http::request::test::dummy_ip;
+    use crate::http::Path as RoutePath;
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct User {
+        email: String,
+    }
+
+    #[derive(Debug)]
+    struct Pagination {
+        page: i64,
+        page_size: Option<i64>,
+    }
+
+    impl FromFormData for Pagination {
+        fn from_form_data(form_data: &FormData) -> Result<Self, Error> {
+            Ok(Self {
+                page: form_data.get_required("page")?,
+                page_size: form_data.get("page_size"),
+            })
+        }
+    }
+
+    async fn request(route: &str, request: &str) -> Request {
+        let path = PathWithRegex::route(RoutePath::parse(route).unwrap()).unwrap();
+        Request::read(dummy_ip(), request.as_bytes())
+            .await
+            .unwrap()
+            .with_params(path.params())
+    }
+
+    #[tokio::test]
+    async fn test_extract() {
+        let body = r#"{"email": "test@test.com"}"#;
+        let req = request(
+            "/users/:id/files/*path",
+            &format!(
+                "POST /users/5/files/a/b.txt?page=2 HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            ),
+        )
+        .await;
+
+        let (Path(id), Query(pagination), Json(user)) = req
+            .extract::<(Path<i64>, Query<Pagination>, Json<User>)>()
+            .unwrap();
+        assert_eq!(id, 5);
+        assert_eq!(pagination.page, 2);
+        assert_eq!(pagination.page_size, None);
+        assert_eq!(user.email, "test@test.com");
+
+        let Path((id, path)) = req.extract::<Path<(i64, String)>>().unwrap();
+        assert_eq!((id, path.as_str()), (5, "a/b.txt"));
+
+        assert!(req.extract::<Option<Form<Pagination>>>().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_extract_errors() {
+        let req = request(
+            "/users/:id",
+            "POST /users/apple HTTP/1.1\r\nContent-Length: 3\r\n\r\n{a}",
+        )
+        .await;
+
+        let err = req.extract::<Path<i64>>().unwrap_err();
+        assert!(matches!(err, Error::InvalidParameter(ref name) if name == "id"));
+
+        let err = req.extract::<Path<(String, i64)>>().unwrap_err();
+        assert!(matches!(err, Error::MissingParameter));
+
+        let err = req.extract::<Json<User>>().unwrap_err();
+        assert!(matches!(err, Error::InvalidBody(_)));
+        assert_eq!(err.code(), 400);
+
+        let err = req.extract::<Query<Pagination>>().unwrap_err();
+        assert_eq!(err.code(), 400);
+
+        // Missing configuration is the server's fault.
+        let err = req.extract::<Settings<Stripe>>().unwrap_err();
+        assert!(matches!(err, Error::Config(_)));
+        assert_eq!(err.code(), 500);
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Stripe {
+        _secret_key: String,
+    }
+
+    impl ConfigSection for Stripe {
+        const NAME: &'static str = "extract_test_stripe";
+    }
+
+    #[derive(Deserialize, Debug, Default)]
+    #[serde(default)]
+    struct Features {
+        beta: bool,
+    }
+
+    impl ConfigSection for Features {
+        const NAME: &'static str = "extract_test_features";
+    }
+
+    #[tokio::test]
+    async fn test_extract_settings() {
+        let req = request("/", "GET / HTTP/1.1\r\nContent-Length: 0\r\n\r\n").await;
+
+        let Settings(features) = req.extract::<Settings<Features>>().unwrap();
+        assert!(!features.beta);
+
+        // Loaded once.
+        let again = req.extract::<Settings<Features>>().unwrap();
+        assert!(Arc::ptr_eq(&features, &again.0));
+    }
+}
@@ -1,5 +1,65 @@
 //! Form parsing.
-use super::{Error, FormData};
+//!
+//! Structs deriving `macros::Form` are parsed field by field. Errors are collected for all fields
+//! and returned together as [`FormErrors`], so they can be shown next to their field when the form is shown again:
+//!
+//! ```
+//! # use rwf::prelude::*;
+//! # use rwf::http::{FormData, FromFormData, Query};
+//! #[derive(macros::Form)]
+//! struct SignupForm {
+//!     email: String,
+//!     #[form(message = "must be a number")]
+//!     age: i32,
+//!     // Blank or missing fields are `None`.
+//!     referral: Option<String>,
+//!     // Checked checkboxes are `true`, unchecked ones aren't sent.
+//!     newsletter: bool,
+//!     // All selected options of a multi-select.
+//!     interests: Vec<String>,
+//! }
+//!
+//! let form_data = FormData::UrlEncoded(Query::parse(
+//!     "email=alice@example.com&age=33&referral=&newsletter=on&interests=rust&interests=web",
+//! ));
+//! let form = SignupForm::from_form_data(&form_data).unwrap();
+//! assert_eq!(form.age, 33);
+//! assert_eq!(form.referral, None);
+//! assert!(form.newsletter);
+//! assert_eq!(form.interests, vec!["rust", "web"]);
+//!
+//! let form_data = FormData::UrlEncoded(Query::parse("age=old"));
+//! let Err(rwf::http::Error::Validation(errors)) = SignupForm::from_form_data(&form_data) else {
+//!     panic!("form is invalid");
+//! };
+//! assert_eq!(errors.get("email"), &["is required"]);
+//! assert_eq!(errors.get("age"), &["must be a number"]);
+//! ```
+//!
+//! In controllers, use [`Request::form`](super::Request::form):
+//!
+//! ```ignore
+//! match request.form::<SignupForm>() {
+//!     Ok(form) => { /* save the user */ }
+//!     Err(errors) => { /* show the form again with the errors */ }
+//! }
+//! ```
+use std::str::FromStr;
+
+use super::{Error, FormData, ValidationErrors};
+
+/// Errors of a submitted form, by field name.
+pub type FormErrors = ValidationErrors;
+
+/// Error message of required fields which weren't submitted.
+pub const MISSING: &str = "is required";
+/// Error message of fields which can't be converted to their data type.
+pub const INVALID: &str = "is invalid";
+
+/// Values of a checked checkbox.
+const CHECKED: &[&str] = &["true", "t", "on", "1", "yes"];
+/// Values of an unchecked checkbox.
+const UNCHECKED: &[&str] = &["false", "f", "off", "0", "no", ""];
 
 /// HTTP form.
 pub struct Form {
@@ -19,3 +79,131 @@ impl FromFormData for FormData {
         Ok(form_data.clone())
     }
 }
+
+/// Parses form fields one by one, collecting errors. Used by `macros::Form`.
+pub struct Fields<'a> {
+    form_data: &'a FormData,
+    errors: FormErrors,
+}
+
+impl<'a> Fields<'a> {
+    /// Parse fields of this form.
+    pub fn new(form_data: &'a FormData) -> Self {
+        Self {
+            form_data,
+            errors: FormErrors::new(),
+        }
+    }
+
+    /// Required field. Fields which are blank and can't be converted, e.g. an empty number, are missing.
+    pub fn required<T: FromStr>(&mut self, name: &str, message: Option<&str>) -> Option<T> {
+        match self.form_data.get::<String>(name) {
+            Some(value) => match value.parse::<T>() {
+                Ok(value) => Some(value),
+                Err(_) if value.trim().is_empty() => self.error(name, message, MISSING),
+                Err(_) => self.error(name, message, INVALID),
+            },
+            None => self.error(name, message, MISSING),
+        }
+    }
+
+    /// Optional field. Fields which are missing or empty are `None`.
+    pub fn optional<T: FromStr>(&mut self, name: &str, message: Option<&str>) -> Option<T> {
+        match self.form_data.get::<String>(name) {
+            Some(value) if !value.is_empty() => match value.parse::<T>() {
+                Ok(value) => Some(value),
+                Err(_) => self.error(name, message, INVALID),
+            },
+            _ => None,
+        }
+    }
+
+    /// All values of the field, e.g. the selected options of a multi-select. Empty values are skipped.
+    pub fn list<T: FromStr>(&mut self, name: &str, message: Option<&str>) -> Vec<T> {
+        let values = self
+            .form_data
+            .get_all::<String>(name)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|value| !value.is_empty())
+            .map(|value| value.parse::<T>())
+            .collect::<Result<Vec<_>, _>>();
+
+        match values {
+            Ok(values) => values,
+            Err(_) => self.error(name, message, INVALID).unwrap_or_default(),
+        }
+    }
+
+    /// Checkbox. Unchecked checkboxes aren't sent by browsers, so missing checkboxes are `false`.
+    pub fn checkbox(&mut self, name: &str, message: Option<&str>) -> bool {
+        let value = self
+            .form_data
+            .get::<String>(name)
+            .unwrap_or_default()
+            .to_lowercase();
+
+        if CHECKED.contains(&value.as_str()) {
+            true
+        } else if UNCHECKED.contains(&value.as_str()) {
+            false
+        } else {
+            self.error(name, message, INVALID).unwrap_or_default()
+        }
+    }
+
+    /// Return the errors, if any field couldn't be parsed.
+    pub fn finish(self) -> Result<(), Error> {
+        self.errors.into_result().map_err(Error::Validation)
+    }
+
+    fn error<T>(&mut self, name: &str, message: Option<&str>, default: &str) -> Option<T> {
+        self.errors.add(name, message.unwrap_or(default));
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::Query;
+
+    #[test]
+    fn test_fields() {
+        let form_data = FormData::UrlEncoded(Query::parse(
+            "name=Alice&age=&height=tall&nickname=&tags=a&tags=b&roles%5B%5D=1&roles%5B%5D=x&newsletter=on&terms=maybe",
+        ));
+        let mut fields = Fields::new(&form_data);
+
+        assert_eq!(
+            fields.required::<String>("name", None),
+            Some("Alice".into())
+        );
+        assert_eq!(fields.required::<i32>("age", None), None);
+        assert_eq!(
+            fields.required::<f64>("height", Some("must be a number")),
+            None
+        );
+        assert_eq!(fields.required::<String>("email", None), None);
+        assert_eq!(fields.optional::<String>("nickname", None), None);
+        assert_eq!(fields.optional::<i64>("missing", None), None);
+        assert_eq!(fields.list::<String>("tags", None), vec!["a", "b"]);
+        assert!(fields.list::<i64>("roles", None).is_empty());
+        assert!(fields.list::<i64>("missing", None).is_empty());
+        assert!(fields.checkbox("newsletter", None));
+        assert!(!fields.checkbox("admin", None));
+        assert!(!fields.checkbox("terms", None));
+
+        match fields.finish() {
+            Err(Error::Validation(errors)) => {
+                assert_eq!(errors.get("age"), &[MISSING]);
+                assert_eq!(errors.get("height"), &["must be a number"]);
+                assert_eq!(errors.get("email"), &[MISSING]);
+                assert_eq!(errors.get("roles"), &[INVALID]);
+                assert_eq!(errors.get("terms"), &[INVALID]);
+                assert_eq!(errors.fields().len(), 5);
+            }
+            _ => panic!("expected validation errors"),
+        }
+    }
+}
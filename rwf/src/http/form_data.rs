@@ -1,7 +1,10 @@
 //! Handle parsing forms.
 //!
 //! Both `x-www-form-urlencoded` and `multipart/form-data` formats are supported.
-use super::{urldecode, Error, Query, Request};
+use super::{
+    upload::{boundary, parts},
+    urldecode, Error, Query, Request,
+};
 use std::str::FromStr;
 
 use std::collections::btree_map::{BTreeMap, IntoIter};
@@ -26,18 +29,15 @@ impl FormData {
         if content_type.contains("application/x-www-form-urlencoded") {
             Self::from_url_encoded(request)
         } else if content_type.contains("multipart/form-data") {
-            // Extract the multipart boundary from the Content-Type header.
-            if let Some(boundary) = content_type.split(";").last() {
-                let boundary = boundary.split("=").last();
-                if let Some(boundary) = boundary {
-                    let multipart = Multipart::read(request.body(), boundary.trim())?;
+            // Parsed while the request was received.
+            if let Some(form) = request.received_form() {
+                return form.form_data();
+            }
 
-                    Ok(Self::Multipart(multipart))
-                } else {
-                    Err(Error::MalformedRequest("multipart missing boundary"))
-                }
-            } else {
-                Err(Error::MalformedRequest("multipart missing boundary"))
+            // Extract the multipart boundary from the Content-Type header.
+            match boundary(content_type) {
+                Some(boundary) => Ok(Self::Multipart(Multipart::read(request.body(), &boundary)?)),
+                None => Err(Error::MalformedRequest("multipart missing boundary")),
             }
         } else {
             return Err(Error::MalformedRequest(
@@ -91,6 +91,37 @@ impl FormData {
         }
     }
 
+    /// Get all values of a field sent more than once, e.g. by a multi-select, converted to the data type.
+    /// If any value can't be converted, `None` is returned. Values sent with the `name[]` convention are
+    /// included.
+    ///
+    /// #### Example
+    ///
+    /// ```rust,ignore
+    /// let form_data = request.form_data()?;
+    /// let tags = form_data.get_all::<String>("tags").unwrap_or_default();
+    /// ```
+    pub fn get_all<T: FromStr>(&self, name: &str) -> Option<Vec<T>> {
+        let brackets = format!("{}[]", name);
+        let values = match self {
+            FormData::UrlEncoded(query) => {
+                let mut values = query.get_all(name);
+                values.extend(query.get_all(&brackets));
+                values
+            }
+            FormData::Multipart(multipart) => {
+                let mut values = multipart.get_all(name);
+                values.extend(multipart.get_all(&brackets));
+                values
+            }
+        };
+
+        values
+            .into_iter()
+            .map(|value| value.parse::<T>().ok())
+            .collect()
+    }
+
     /// Get file data from a `multipart/form-data` form.
     pub fn file<'a>(&'a self, name: &str) -> Option<File<'a>> {
         match self {
@@ -124,6 +155,18 @@ impl FormData {
         }
     }
 
+    /// Remove a value from the form, e.g. a field the user isn't allowed to change.
+    pub fn remove(&mut self, name: &str) {
+        match self {
+            FormData::UrlEncoded(query) => {
+                query.remove(name);
+            }
+            FormData::Multipart(multipart) => {
+                multipart.entries.remove(name);
+            }
+        }
+    }
+
     /// Return a [`Result`] instead of [`Option`] for the required parameter. When used in combination with
     /// the `?` operator, a controller will return `400 - Bad Request` automatically if the parameter is not set or is set
     /// to the wrong data type.
@@ -146,6 +189,8 @@ impl FormData {
 #[derive(Debug, Clone)]
 pub struct Multipart {
     entries: BTreeMap<String, MultipartEntry>,
+    // All values of fields sent more than once, e.g. by a multi-select.
+    repeated: BTreeMap<String, Vec<String>>,
 }
 
 /// Multipart form submission entry.
@@ -157,6 +202,18 @@ pub struct MultipartEntry {
 }
 
 impl MultipartEntry {
+    pub(crate) fn new(
+        content_disposition: ContentDisposition,
+        content_type: Option<String>,
+        data: Vec<u8>,
+    ) -> Self {
+        Self {
+            data,
+            content_disposition,
+            content_type,
+        }
+    }
+
     /// Convert the multipart entry to string, if it's valid UTF-8 data.
     pub fn to_string(&self) -> Result<String, Error> {
         Ok(String::from_utf8(self.data.clone())?)
@@ -178,24 +235,6 @@ impl MultipartEntry {
     }
 }
 
-// Read a single "HTTP line".
-macro_rules! read_line {
-    ($reader: expr) => {{
-        let mut buf = Vec::new();
-
-        while let Some(c) = $reader.next() {
-            if *c as char == '\r' {
-                let _ = $reader.next();
-                break;
-            } else {
-                buf.push(*c);
-            }
-        }
-
-        buf
-    }};
-}
-
 /// A file uploaded via a `multipart/form-data` form.
 ///
 /// The file is loaded into memory. Typically, you don't want to handle large file uploads via multipart forms.
@@ -233,76 +272,52 @@ impl File<'_> {
 impl Multipart {
     /// Read multi-part body from request's body.
     fn read(body: &[u8], boundary: &str) -> Result<Self, Error> {
-        let mut entries = BTreeMap::new();
-        let mut reader = body.into_iter();
-
-        let start_boundary = format!("--{}", boundary).as_bytes().to_vec();
-        let end_boundary = format!("--{}--", boundary).as_bytes().to_vec();
-
-        let mut buf = Vec::new();
-        let mut content_disposition: Option<ContentDisposition> = None;
-        let mut content_type: Option<String> = None;
-
-        while reader.len() > 0 {
-            let line = read_line!(reader);
-
-            if line.is_empty() {
-                break;
-            }
+        Ok(Self::from_entries(parts(body, boundary)?.into_iter().map(
+            |part| MultipartEntry::new(part.disposition, part.content_type, part.data.to_vec()),
+        )))
+    }
 
-            if line == start_boundary {
-                if let Some(content_disposition) = content_disposition.take() {
-                    entries.insert(
-                        content_disposition.name.clone(),
-                        MultipartEntry {
-                            data: buf.clone(),
-                            content_disposition,
-                            content_type: content_type.take(),
-                        },
-                    );
-                    buf.clear();
-                }
-                let ct = ContentDisposition::parse(&String::from_utf8(read_line!(reader))?)?;
-
-                // Check if we're parsing a file.
-                if ct.filename.is_some() {
-                    // Get content type.
-                    let ct = read_line!(reader);
-                    if ct.to_ascii_lowercase().starts_with(b"content-type") {
-                        content_type = Some(String::from_utf8(ct)?);
-                        // Read and discard "\r\n".
-                        let _ = read_line!(reader);
-                    }
-                } else {
-                    // Read and discard "\r\n".
-                    let _ = read_line!(reader);
-                }
-                content_disposition = Some(ct);
-            } else if line == end_boundary {
-                // We've reached the end of the form data.
-                if let Some(content_disposition) = content_disposition.take() {
-                    entries.insert(
-                        content_disposition.name.clone(),
-                        MultipartEntry {
-                            data: buf.clone(),
-                            content_disposition,
-                            content_type: content_type.take(),
-                        },
-                    );
+    /// Create the form from its entries, in the order they were sent.
+    pub(crate) fn from_entries(form: impl IntoIterator<Item = MultipartEntry>) -> Self {
+        let mut entries = BTreeMap::new();
+        let mut repeated = BTreeMap::<String, Vec<String>>::new();
+
+        for entry in form {
+            let name = entry.content_disposition.name.clone();
+
+            if let Some(previous) = entries.insert(name.clone(), entry) {
+                if let (Ok(previous), Ok(value)) =
+                    (previous.to_string(), entries[&name].to_string())
+                {
+                    repeated
+                        .entry(name)
+                        .or_insert_with(|| vec![previous])
+                        .push(value);
                 }
-                break;
-            } else {
-                buf.extend(line);
             }
         }
 
-        Ok(Multipart { entries })
+        Multipart { entries, repeated }
     }
 
     /// Get a multi-part entry, if it exists.
     pub fn get(&self, name: &str) -> Option<&MultipartEntry> {
         self.entries.get(name)
     }
+
+    /// Get all values of a field sent more than once, in the order they were sent.
+    /// Files aren't included.
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        match self.entries.get(name) {
+            Some(entry) if entry.content_disposition.filename.is_none() => {
+                match self.repeated.get(name) {
+                    Some(values) => values.iter().map(|value| value.as_str()).collect(),
+                    None => std::str::from_utf8(&entry.data).into_iter().collect(),
+                }
+            }
+            _ => vec![],
+        }
+    }
 }
 
 /// HTTP `Content-Disposition` header.
@@ -316,8 +331,8 @@ pub struct ContentDisposition {
 
 impl ContentDisposition {
     // Parse the Content-Disposition header.
-    fn parse(header: &str) -> Result<ContentDisposition, Error> {
-        let mut names = header.split(":").into_iter().map(|s| s.trim());
+    pub(crate) fn parse(header: &str) -> Result<ContentDisposition, Error> {
+        let mut names = header.splitn(2, ":").map(|s| s.trim());
 
         if let Some(header) = names.next() {
             if header.to_lowercase() != "content-disposition" {
@@ -335,7 +350,7 @@ impl ContentDisposition {
             let mut filename: Option<String> = None;
 
             for param in params {
-                let mut parts = param.split("=").into_iter();
+                let mut parts = param.splitn(2, "=");
                 let name = parts.next();
                 let value = parts.next();
 
@@ -0,0 +1,272 @@
+//! Content negotiation, picking the response format the client prefers from the `Accept` header.
+//!
+//! The `respond_to!` macro lets one controller action serve browsers, Turbo and API clients:
+//!
+//! ```ignore
+//! respond_to!(request, {
+//!     html => render!(request, "templates/post.html", "post" => post),
+//!     turbo_stream => turbo_stream!(request, "templates/post.html", "post", "post" => post),
+//!     json => Response::new().json(&post),
+//! })
+//! ```
+//!
+//! Formats are picked by their quality in the `Accept` header; formats with the same quality are picked
+//! in the order the client listed them. If the client accepts any format, e.g. `*/*` or no `Accept` header,
+//! the first format of the list is used. Clients which accept none of them get `406 - Not Acceptable`.
+//!
+//! Routes accepting format extensions, e.g. `route!("/report" => Report).formats(&[Format::Csv, Format::Json])`,
+//! serve `/report.csv` and `/report.json` too. The extension takes precedence over the `Accept` header.
+//!
+//! Like `render!`, the macro returns the response from the controller. Responses include the `Vary: Accept` header,
+//! so caches store each format separately.
+use super::Response;
+use crate::controller::Error;
+use crate::view::TurboStream;
+
+/// Response format.
+///
+/// # Example
+///
+/// ```
+/// use rwf::prelude::*;
+///
+/// #[derive(Default)]
+/// struct Post;
+///
+/// #[async_trait]
+/// impl Controller for Post {
+///     async fn handle(&self, request: &Request) -> Result<Response, Error> {
+///         respond_to!(request, {
+///             html => Response::new().html("<h1>Hello</h1>"),
+///             json => Response::new().json(serde_json::json!({ "title": "Hello" })),
+///         })
+///     }
+/// }
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// for (accept, content_type) in [
+///     ("application/json", "application/json"),
+///     ("text/html, */*;q=0.5", "text/html"),
+///     ("*/*", "text/html"),
+/// ] {
+///     let request = format!("GET /posts/1 HTTP/1.1\r\nAccept: {}\r\n\r\n", accept);
+///     let request = Request::read("127.0.0.1:8000".parse().unwrap(), request.as_bytes())
+///         .await
+///         .unwrap();
+///     let response = Post.handle(&request).await.unwrap();
+///
+///     let header = response.headers().get("content-type").unwrap();
+///     assert!(header.starts_with(content_type));
+///     assert_eq!(response.headers().get("vary").unwrap(), "accept");
+/// }
+///
+/// let request = "GET /posts/1 HTTP/1.1\r\nAccept: image/png\r\n\r\n";
+/// let request = Request::read("127.0.0.1:8000".parse().unwrap(), request.as_bytes())
+///     .await
+///     .unwrap();
+/// assert_eq!(Post.handle(&request).await.unwrap().status().code(), 406);
+/// # });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// HTML page, `text/html`.
+    Html,
+    /// JSON, `application/json`.
+    Json,
+    /// Turbo Stream, `text/vnd.turbo-stream.html`.
+    TurboStream,
+    /// Plain text, `text/plain`.
+    Text,
+    /// XML, `application/xml`.
+    Xml,
+    /// CSV, `text/csv`.
+    Csv,
+}
+
+impl Format {
+    /// Media type of the format, e.g. `text/html`.
+    pub fn mime(&self) -> &'static str {
+        match self {
+            Format::Html => "text/html",
+            Format::Json => "application/json",
+            Format::TurboStream => "text/vnd.turbo-stream.html",
+            Format::Text => "text/plain",
+            Format::Xml => "application/xml",
+            Format::Csv => "text/csv",
+        }
+    }
+
+    /// Extension of paths requesting the format, e.g. `csv` for `/report.csv`.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Html => "html",
+            Format::Json => "json",
+            Format::TurboStream => "turbo_stream",
+            Format::Text => "txt",
+            Format::Xml => "xml",
+            Format::Csv => "csv",
+        }
+    }
+
+    /// Format requested by the path extension, e.g. `Csv` for `csv`.
+    pub fn from_extension(extension: &str) -> Option<Format> {
+        [
+            Format::Html,
+            Format::Json,
+            Format::TurboStream,
+            Format::Text,
+            Format::Xml,
+            Format::Csv,
+        ]
+        .into_iter()
+        .find(|format| format.extension().eq_ignore_ascii_case(extension))
+    }
+
+    /// The media range from the `Accept` header matches the format, e.g. `text/*` matches HTML.
+    pub fn matches(&self, range: &str) -> bool {
+        let range = range.trim().to_ascii_lowercase();
+        let mime = self.mime();
+
+        match range.as_str() {
+            "*/*" | "*" => true,
+            "application/xhtml+xml" => *self == Format::Html,
+            "text/xml" => *self == Format::Xml,
+            range => match range.strip_suffix("/*") {
+                Some(kind) => mime.split('/').next() == Some(kind),
+                None => range == mime,
+            },
+        }
+    }
+
+    /// Pick the format the client prefers from `formats`, using the `Accept` header value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rwf::http::Format;
+    /// let formats = [Format::Html, Format::Json];
+    ///
+    /// assert_eq!(Format::negotiate("application/json", &formats), Some(Format::Json));
+    /// assert_eq!(Format::negotiate("text/html;q=0.5, application/json", &formats), Some(Format::Json));
+    /// assert_eq!(Format::negotiate("*/*", &formats), Some(Format::Html));
+    /// assert_eq!(Format::negotiate("image/png", &formats), None);
+    /// ```
+    pub fn negotiate(accept: &str, formats: &[Format]) -> Option<Format> {
+        let ranges = accept
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let range = parts.next()?.trim();
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map(|q| q.trim().parse::<f32>().unwrap_or(0.0))
+                    .unwrap_or(1.0);
+
+                (!range.is_empty()).then_some((range, quality))
+            })
+            .collect::<Vec<_>>();
+
+        if ranges.is_empty() {
+            return formats.first().copied();
+        }
+
+        // Each format gets the quality of the most specific range matching it, e.g. `text/html;q=0`
+        // excludes HTML even if the client also accepts `*/*`.
+        formats
+            .iter()
+            .enumerate()
+            .filter_map(|(index, format)| {
+                let (position, quality) = ranges
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (range, _))| format.matches(range))
+                    .min_by_key(|(_, (range, _))| std::cmp::Reverse(specificity(range)))
+                    .map(|(position, (_, quality))| (position, *quality))?;
+
+                (quality > 0.0).then_some((*format, quality, position, index))
+            })
+            // Highest quality first, then the order of the `Accept` header, then the order of the formats.
+            .max_by(|a, b| a.1.total_cmp(&b.1).then(b.2.cmp(&a.2)).then(b.3.cmp(&a.3)))
+            .map(|(format, ..)| format)
+    }
+}
+
+/// How specific the media range is: `*/*` matches any format, `text/*` all text formats.
+fn specificity(range: &str) -> u8 {
+    let range = range.trim();
+    if range == "*/*" || range == "*" {
+        0
+    } else if range.ends_with("/*") {
+        1
+    } else {
+        2
+    }
+}
+
+/// Convert the value of a `respond_to!` branch into a response.
+pub trait ToResponse {
+    /// Perform the conversion.
+    fn to_response(self) -> Result<Response, Error>;
+}
+
+impl ToResponse for Response {
+    fn to_response(self) -> Result<Response, Error> {
+        Ok(self)
+    }
+}
+
+impl<E: Into<Error>> ToResponse for Result<Response, E> {
+    fn to_response(self) -> Result<Response, Error> {
+        self.map_err(|err| err.into())
+    }
+}
+
+impl ToResponse for TurboStream {
+    fn to_response(self) -> Result<Response, Error> {
+        Ok(Response::new().turbo_stream(&[self]))
+    }
+}
+
+impl ToResponse for Vec<TurboStream> {
+    fn to_response(self) -> Result<Response, Error> {
+        Ok(Response::new().turbo_stream(&self))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        let formats = [Format::Html, Format::Json, Format::TurboStream];
+
+        // Turbo form submissions.
+        assert_eq!(
+            Format::negotiate(
+                "text/vnd.turbo-stream.html, text/html, application/xhtml+xml",
+                &formats
+            ),
+            Some(Format::TurboStream)
+        );
+        // Browsers.
+        assert_eq!(
+            Format::negotiate(
+                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+                &formats
+            ),
+            Some(Format::Html)
+        );
+        assert_eq!(
+            Format::negotiate("application/*", &formats),
+            Some(Format::Json)
+        );
+        assert_eq!(
+            Format::negotiate("text/html;q=0, */*", &formats),
+            Some(Format::Json)
+        );
+        assert_eq!(Format::negotiate("text/html;q=0", &formats), None);
+        assert_eq!(Format::negotiate("", &formats), Some(Format::Html));
+        assert_eq!(Format::negotiate("text/csv", &[]), None);
+    }
+}
@@ -0,0 +1,268 @@
+//! Client address and scheme of requests forwarded by reverse proxies, e.g. load balancers.
+//!
+//! Proxies add the address of the client they received the request from to the `Forwarded`
+//! or `X-Forwarded-For` header, and the scheme it used to `X-Forwarded-Proto`. Clients can set
+//! these headers too, so they are only read if the request came from a trusted proxy, configured
+//! with `trusted_proxies` in the `[general]` section of `rwf.toml`.
+use std::net::{IpAddr, SocketAddr};
+
+use super::Request;
+use crate::config::get_config;
+
+/// IP address of the client. If the request was forwarded by trusted proxies, it's the last
+/// address in the forwarded chain which isn't a trusted proxy.
+pub(crate) fn client_ip(request: &Request) -> IpAddr {
+    let peer = request.peer().ip().to_canonical();
+
+    if !get_config().general.trusted_proxy(peer) {
+        return peer;
+    }
+
+    let hops = forwarded_for(request);
+
+    match client_hop(&hops) {
+        Some(hop) => hops[hop].map(|ip| ip.to_canonical()).unwrap_or(peer),
+        None => peer,
+    }
+}
+
+/// Index of the hop the client address is taken from: the last one which isn't a trusted proxy,
+/// or the first one if they all are. `None` if the last proxy didn't say where the request came from.
+fn client_hop(hops: &[Option<IpAddr>]) -> Option<usize> {
+    let general = &get_config().general;
+    let mut client = None;
+
+    // Each proxy appends the address it received the request from.
+    for (index, hop) in hops.iter().enumerate().rev() {
+        match hop {
+            Some(ip) => {
+                client = Some(index);
+                if !general.trusted_proxy(ip.to_canonical()) {
+                    break;
+                }
+            }
+            // Proxies can hide addresses, e.g. with `unknown`.
+            None => break,
+        }
+    }
+
+    client
+}
+
+/// The client used HTTPS, to connect to the server or to the trusted proxy which forwarded the request.
+pub(crate) fn secure(request: &Request) -> bool {
+    if request.tls() {
+        return true;
+    }
+
+    if !get_config()
+        .general
+        .trusted_proxy(request.peer().ip().to_canonical())
+    {
+        return false;
+    }
+
+    forwarded_proto(request).is_some_and(|proto| proto.eq_ignore_ascii_case("https"))
+}
+
+/// Addresses in the `Forwarded` header or, if it's not set, in `X-Forwarded-For`, from the client to the last proxy.
+fn forwarded_for(request: &Request) -> Vec<Option<IpAddr>> {
+    match request.header("forwarded") {
+        Some(forwarded) => elements(forwarded)
+            .map(|element| parameter(element, "for").and_then(|node| node_ip(&node)))
+            .collect(),
+
+        None => request
+            .header("x-forwarded-for")
+            .map(|header| header.split(',').map(|node| node_ip(node.trim())).collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// Scheme used by the client, in the `Forwarded` header or, if it's not set, in `X-Forwarded-Proto`.
+///
+/// It's taken from the hop [`client_ip`] selects, since the elements before it can be set by the client.
+/// If that hop doesn't have one, the scheme set by the closest trusted proxy after it is used.
+fn forwarded_proto(request: &Request) -> Option<String> {
+    let hops = forwarded_for(request);
+    let last = hops.len().checked_sub(1);
+    let client = client_hop(&hops).or(last);
+
+    match request.header("forwarded") {
+        Some(forwarded) => elements(forwarded)
+            .skip(client?)
+            .find_map(|element| parameter(element, "proto")),
+
+        None => {
+            let protos = request
+                .header("x-forwarded-proto")?
+                .split(',')
+                .map(|proto| proto.trim().to_string())
+                .collect::<Vec<_>>();
+
+            // Proxies which don't append to the header replace it, so it's the last proxy's.
+            match client {
+                Some(client) if protos.len() == hops.len() => protos.into_iter().nth(client),
+                _ => protos.into_iter().last(),
+            }
+        }
+    }
+}
+
+/// Elements of the `Forwarded` header, one per proxy, e.g. `for=192.0.2.60;proto=https`.
+fn elements(header: &str) -> impl Iterator<Item = &str> {
+    header.split(',').map(|element| element.trim())
+}
+
+/// Value of the parameter in the element, without quotes.
+fn parameter(element: &str, name: &str) -> Option<String> {
+    element.split(';').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        if key.trim().eq_ignore_ascii_case(name) {
+            Some(value.trim().trim_matches('"').to_string())
+        } else {
+            None
+        }
+    })
+}
+
+/// IP address of a node, e.g. `192.0.2.60`, `192.0.2.60:4711` or `[2001:db8::1]:4711`.
+fn node_ip(node: &str) -> Option<IpAddr> {
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| {
+            node.strip_prefix('[')
+                .and_then(|node| node.strip_suffix(']'))
+                .and_then(|ip| ip.parse().ok())
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    async fn request(peer: &str, headers: &[(&str, &str)]) -> Request {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}\r\n", name, value))
+            .collect::<String>();
+        let req = format!("GET / HTTP/1.1\r\n{}\r\n", headers);
+        Request::read(peer.parse().unwrap(), req.as_bytes())
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_node_ip() {
+        assert_eq!(node_ip("192.0.2.60"), Some([192, 0, 2, 60].into()));
+        assert_eq!(node_ip("192.0.2.60:4711"), Some([192, 0, 2, 60].into()));
+        assert_eq!(node_ip("[2001:db8::1]:4711"), "2001:db8::1".parse().ok());
+        assert_eq!(node_ip("[2001:db8::1]"), "2001:db8::1".parse().ok());
+        assert_eq!(node_ip("unknown"), None);
+        assert_eq!(node_ip("_hidden"), None);
+    }
+
+    #[tokio::test]
+    async fn test_client_ip() {
+        // Not a proxy.
+        let req = request("203.0.113.5:1234", &[("x-forwarded-for", "1.2.3.4")]).await;
+        assert_eq!(client_ip(&req), "203.0.113.5".parse::<IpAddr>().unwrap());
+
+        // The client can't pretend to be someone else, the proxy appended its real address.
+        let req = request(
+            "10.0.0.1:1234",
+            &[("x-forwarded-for", "1.2.3.4, 198.51.100.7, 10.0.0.2")],
+        )
+        .await;
+        assert_eq!(client_ip(&req), "198.51.100.7".parse::<IpAddr>().unwrap());
+
+        let req = request(
+            "127.0.0.1:1234",
+            &[(
+                "forwarded",
+                r#"for=198.51.100.7;proto=https, for="[2001:db8::1]:4711""#,
+            )],
+        )
+        .await;
+        assert_eq!(client_ip(&req), "2001:db8::1".parse::<IpAddr>().unwrap());
+
+        // All proxies are trusted.
+        let req = request(
+            "10.0.0.1:1234",
+            &[("x-forwarded-for", "10.0.0.3, 10.0.0.2")],
+        )
+        .await;
+        assert_eq!(client_ip(&req), "10.0.0.3".parse::<IpAddr>().unwrap());
+
+        // IPv4 peer connected over IPv6.
+        let req = request("[::ffff:10.0.0.1]:1234", &[("x-forwarded-for", "1.2.3.4")]).await;
+        assert_eq!(client_ip(&req), "1.2.3.4".parse::<IpAddr>().unwrap());
+
+        let req = request("10.0.0.1:1234", &[]).await;
+        assert_eq!(client_ip(&req), "10.0.0.1".parse::<IpAddr>().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_secure() {
+        let req = request("10.0.0.1:1234", &[("x-forwarded-proto", "https")]).await;
+        assert!(secure(&req));
+
+        let req = request("10.0.0.1:1234", &[("forwarded", "for=1.2.3.4;proto=HTTPS")]).await;
+        assert!(secure(&req));
+
+        let req = request("10.0.0.1:1234", &[("x-forwarded-proto", "http")]).await;
+        assert!(!secure(&req));
+
+        // Not a proxy.
+        let req = request("203.0.113.5:1234", &[("x-forwarded-proto", "https")]).await;
+        assert!(!secure(&req));
+
+        let req = request("203.0.113.5:1234", &[]).await.with_tls(true);
+        assert!(secure(&req));
+
+        // The client set the scheme in a leading element; the proxy says it used HTTP.
+        let req = request(
+            "10.0.0.1:1234",
+            &[(
+                "forwarded",
+                "for=1.2.3.4;proto=https, for=198.51.100.7;proto=http",
+            )],
+        )
+        .await;
+        assert_eq!(client_ip(&req), "198.51.100.7".parse::<IpAddr>().unwrap());
+        assert!(!secure(&req));
+
+        let req = request(
+            "10.0.0.1:1234",
+            &[
+                ("x-forwarded-for", "1.2.3.4, 198.51.100.7"),
+                ("x-forwarded-proto", "https, http"),
+            ],
+        )
+        .await;
+        assert!(!secure(&req));
+
+        // The scheme is set by a trusted proxy after the client's hop.
+        let req = request(
+            "10.0.0.1:1234",
+            &[(
+                "forwarded",
+                "for=1.2.3.4;proto=http, for=198.51.100.7, for=10.0.0.2;proto=https",
+            )],
+        )
+        .await;
+        assert!(secure(&req));
+
+        // The last proxy replaced the header.
+        let req = request(
+            "10.0.0.1:1234",
+            &[
+                ("x-forwarded-for", "1.2.3.4, 198.51.100.7"),
+                ("x-forwarded-proto", "https"),
+            ],
+        )
+        .await;
+        assert!(secure(&req));
+    }
+}
@@ -0,0 +1,30 @@
+//! Client location.
+//!
+//! Set on the request by the [`GeoIp`](crate::controller::middleware::geoip::GeoIp) middleware,
+//! which looks up the client's IP address in a MaxMind database.
+use serde::{Deserialize, Serialize};
+
+/// Client location.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Geo {
+    /// ISO 3166-1 country code, e.g. `US`.
+    pub country: Option<String>,
+    /// Country name in English.
+    pub country_name: Option<String>,
+    /// ISO 3166-2 code of the region (state, province, etc.), e.g. `CA`.
+    pub region: Option<String>,
+    /// Region name in English.
+    pub region_name: Option<String>,
+    /// City name in English.
+    pub city: Option<String>,
+    /// Continent code, e.g. `NA`.
+    pub continent: Option<String>,
+    /// IANA time zone, e.g. `America/Los_Angeles`.
+    pub time_zone: Option<String>,
+    /// Approximate latitude.
+    pub latitude: Option<f64>,
+    /// Approximate longitude.
+    pub longitude: Option<f64>,
+    /// The country is a member of the European Union.
+    pub in_european_union: bool,
+}
@@ -4,7 +4,7 @@
 //! See [`crate::http::router`] documentation for routing implementation details.
 use super::{
     path::{PathType, PathWithRegex},
-    Path,
+    Format, Method, Path,
 };
 use crate::controller::Controller;
 
@@ -19,6 +19,9 @@ pub struct Handler {
     name: Option<String>,
     controller: Box<dyn Controller>,
     rank: i64,
+    methods: Option<Vec<Method>>,
+    host: Option<String>,
+    formats: Vec<Format>,
 }
 
 impl Handler {
@@ -29,6 +32,9 @@ impl Handler {
             controller: Box::new(controller),
             name: None,
             rank: 0,
+            methods: None,
+            host: None,
+            formats: vec![],
         }
     }
 
@@ -66,12 +72,121 @@ impl Handler {
         Self::new(path, controller, PathType::Route)
     }
 
-    /// Set the route name.
+    /// Set the route name. URLs of named routes can be generated with [`url_for`](crate::url_for).
     pub fn name(mut self, name: impl ToString) -> Self {
         self.name = Some(name.to_string());
         self
     }
 
+    /// Get the route name, if it has one.
+    pub fn route_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Only route requests with these methods to the controller. Requests with other methods
+    /// get `405 - Method Not Allowed`, unless another route accepts them. `HEAD` is allowed with `GET`.
+    ///
+    /// Use the `get!`, `post!`, `put!`, `patch!` and `delete!` macros instead:
+    ///
+    /// ```rust,ignore
+    /// get!("/users" => UsersController)
+    /// ```
+    pub fn methods(mut self, methods: &[Method]) -> Self {
+        self.methods = Some(methods.to_vec());
+        self
+    }
+
+    /// Methods accepted by the route. All methods are accepted if it's not set.
+    pub fn allowed_methods(&self) -> Option<&[Method]> {
+        self.methods.as_deref()
+    }
+
+    /// The route accepts requests with this method.
+    pub fn allows(&self, method: &Method) -> bool {
+        match self.methods {
+            Some(ref methods) => {
+                methods.contains(method)
+                    || (method == &Method::Head && methods.contains(&Method::Get))
+            }
+            None => true,
+        }
+    }
+
+    /// Only route requests sent to this host to the controller, e.g. `admin.example.com`.
+    /// `*.example.com` accepts all subdomains of `example.com`.
+    pub fn host(mut self, host: impl ToString) -> Self {
+        self.host = Some(host.to_string().to_lowercase());
+        self
+    }
+
+    /// Only route requests sent to this subdomain to the controller, e.g. `admin` for `admin.example.com`.
+    pub fn subdomain(self, subdomain: &str) -> Self {
+        self.host(format!("{}.*", subdomain))
+    }
+
+    /// Host accepted by the route, if any.
+    pub fn route_host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    /// The route accepts requests sent to this host.
+    pub fn accepts_host(&self, host: Option<&str>) -> bool {
+        let pattern = match self.host {
+            Some(ref pattern) => pattern,
+            None => return true,
+        };
+
+        let host = match host {
+            Some(host) => host.to_lowercase(),
+            None => return false,
+        };
+
+        if let Some(domain) = pattern.strip_prefix("*.") {
+            host.strip_suffix(domain)
+                .is_some_and(|subdomain| subdomain.len() > 1 && subdomain.ends_with('.'))
+        } else if let Some(subdomain) = pattern.strip_suffix(".*") {
+            host.strip_prefix(subdomain)
+                .is_some_and(|domain| domain.len() > 1 && domain.starts_with('.'))
+        } else {
+            &host == pattern
+        }
+    }
+
+    /// Route paths with the extension of these formats to the controller too, e.g. `/report.csv` and `/report.json`
+    /// for `/report`. The extension is in the `format` parameter, and picked by [`Request::format`](super::Request::format).
+    pub fn formats(mut self, formats: &[Format]) -> Self {
+        self.path = PathWithRegex::with_formats(
+            self.path.deref().clone(),
+            self.path.path_type().clone(),
+            formats,
+        )
+        .unwrap();
+        self.formats = formats.to_vec();
+        self
+    }
+
+    /// Formats accepted by the route as path extensions.
+    pub fn route_formats(&self) -> &[Format] {
+        &self.formats
+    }
+
+    /// The handler accepts all requests this handler does, ignoring the path.
+    pub(crate) fn covers(&self, other: &Handler) -> bool {
+        let methods = match (&self.methods, &other.methods) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(_), Some(methods)) => methods.iter().all(|method| self.allows(method)),
+        };
+
+        let host = match (&self.host, &other.host) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(host), Some(other)) => host == other,
+        };
+
+        methods && host
+    }
+
     /// Get the path and its correspoding regex, used in the router.
     pub fn path_with_regex(&self) -> &PathWithRegex {
         &self.path
@@ -93,6 +208,11 @@ impl Handler {
     pub fn controller_name(&self) -> &'static str {
         self.deref().controller_name()
     }
+
+    /// Take the controller out of the handler, e.g. to serve it on other paths.
+    pub(crate) fn into_controller(self) -> Box<dyn Controller> {
+        self.controller
+    }
 }
 
 impl Deref for Handler {
@@ -0,0 +1,150 @@
+//! Serve requests in-process, without a network connection.
+//!
+//! The [`Harness`] passes requests through the same stack as the [`Server`](super::Server), i.e. the
+//! middleware, the router, the controller, session handling and compression, but reads them from memory
+//! and returns the response instead of writing it to a socket. Used to benchmark the framework hot path,
+//! e.g. with [criterion](https://docs.rs/criterion), so performance regressions can be measured.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let harness = Harness::new(vec![route!("/users" => UsersController)])?;
+//! let runtime = tokio::runtime::Runtime::new()?;
+//!
+//! c.bench_function("users", |b| {
+//!     b.to_async(&runtime).iter(|| harness.get("/users"))
+//! });
+//! ```
+use super::{server::Server, Error, Handler, Request, Response, Router};
+use crate::controller::middleware::{MiddlewareHandler, MiddlewareSet};
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use tokio::io::{duplex, DuplexStream};
+
+/// Serves routes without listening on a port.
+pub struct Harness {
+    router: Arc<Router>,
+    middleware: Vec<MiddlewareHandler>,
+    middleware_set: Arc<MiddlewareSet>,
+    peer: SocketAddr,
+}
+
+impl Harness {
+    /// Create a harness serving the routes.
+    pub fn new(routes: Vec<Handler>) -> Result<Self, Error> {
+        Ok(Self {
+            router: Arc::new(Router::new(routes)?),
+            middleware: vec![],
+            middleware_set: Arc::new(MiddlewareSet::without_default(vec![])),
+            peer: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0),
+        })
+    }
+
+    /// Run the middleware on all requests, like [`Server::middleware`].
+    pub fn middleware(mut self, middleware: MiddlewareHandler) -> Self {
+        self.middleware.push(middleware);
+        self.middleware_set = Arc::new(MiddlewareSet::without_default(self.middleware.clone()));
+        self
+    }
+
+    /// Router serving the requests, e.g. to check route coverage.
+    pub fn router(&self) -> &Router {
+        &self.router
+    }
+
+    /// Parse the request, e.g. `GET / HTTP/1.1\r\n\r\n`, and pass it to the controller.
+    pub async fn request(&self, request: impl AsRef<[u8]>) -> Result<Response, Error> {
+        let request = Request::read(self.peer, request.as_ref()).await?;
+        Ok(self.send(request).await)
+    }
+
+    /// Send a `GET` request for the path.
+    pub async fn get(&self, path: &str) -> Result<Response, Error> {
+        self.request(format!(
+            "GET {} HTTP/1.1\r\nContent-Length: 0\r\n\r\n",
+            path
+        ))
+        .await
+    }
+
+    /// Send a `POST` request for the path, with a JSON body.
+    pub async fn post_json(&self, path: &str, body: &str) -> Result<Response, Error> {
+        self.request(format!(
+            "POST {} HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            path,
+            body.len(),
+            body
+        ))
+        .await
+    }
+
+    /// Pass the request through the middleware and to the controller matching its path.
+    pub async fn send(&self, request: Request) -> Response {
+        let (_, response, _) =
+            Server::handle_request(&self.router, &self.middleware_set, request).await;
+        response
+    }
+
+    /// Open an in-memory connection to the server, e.g. to upgrade it to a WebSocket.
+    /// Requests sent over it are served like the server serves TCP connections.
+    pub fn connect(&self) -> DuplexStream {
+        let (client, server) = duplex(64 * 1024);
+        Server::handle_connection(
+            self.router.clone(),
+            self.middleware_set.clone(),
+            server,
+            self.peer,
+        );
+        client
+    }
+}
+
+impl From<Server> for Harness {
+    /// Serve the routes of the server, running its middleware.
+    fn from(server: Server) -> Self {
+        let (router, middleware) = server.into_parts();
+
+        Self {
+            router,
+            middleware_set: Arc::new(MiddlewareSet::without_default(middleware.clone())),
+            middleware,
+            peer: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::controller::{Controller, Error as ControllerError};
+
+    struct Hello;
+
+    #[crate::async_trait]
+    impl Controller for Hello {
+        async fn handle(&self, request: &Request) -> Result<Response, ControllerError> {
+            let name = request.parameter::<String>("name")?.unwrap_or_default();
+            Ok(Response::new().text(format!("hello {}", name)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_harness() {
+        let harness = Harness::new(vec![Hello.route("/hello/:name")]).unwrap();
+
+        let response = harness.get("/hello/world").await.unwrap();
+        assert_eq!(response.status().code(), 200);
+
+        let mut bytes = vec![];
+        response.send(&mut bytes).await.unwrap();
+        assert!(String::from_utf8(bytes)
+            .unwrap()
+            .ends_with("\r\n\r\nhello world"));
+
+        let response = harness.post_json("/missing", "{}").await.unwrap();
+        assert_eq!(response.status().code(), 404);
+
+        assert_eq!(harness.router().coverage().routes()[0].visits, 1);
+    }
+}
@@ -0,0 +1,182 @@
+//! HTTP/2 support.
+//!
+//! HTTP/2 is negotiated with the client using ALPN when the server is launched with TLS,
+//! see [`crate::http::Server::launch_tls`]. Each stream is converted to a [`Request`]
+//! and passed to the controllers like any HTTP/1.1 request, so the client can send
+//! many requests in parallel over a single connection.
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use bytes::Bytes;
+use h2::{server::SendResponse, RecvStream, SendStream};
+use tokio::io::AsyncWrite;
+
+use super::{Error, Head, Request, Response};
+use crate::config::get_config;
+
+/// Headers which only apply to HTTP/1.1 connections and are not allowed in HTTP/2.
+const CONNECTION_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Read the request head and body sent on an HTTP/2 stream.
+pub(crate) async fn read_request(
+    peer: SocketAddr,
+    request: http::Request<RecvStream>,
+) -> Result<Request, Error> {
+    let (parts, mut body) = request.into_parts();
+
+    let path = parts
+        .uri
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+
+    let mut head = format!("{} {} HTTP/2\r\n", parts.method, path);
+
+    if let Some(authority) = parts.uri.authority() {
+        head.push_str(&format!("host: {}\r\n", authority));
+    }
+
+    for name in parts.headers.keys() {
+        if name == "content-length" || name == "host" {
+            continue;
+        }
+
+        let values = parts
+            .headers
+            .get_all(name)
+            .iter()
+            .map(|value| value.to_str())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| Error::MalformedRequest("header value"))?;
+
+        // Clients can split cookies into separate headers to compress them better.
+        let separator = if name == "cookie" { "; " } else { ", " };
+        head.push_str(&format!("{}: {}\r\n", name, values.join(separator)));
+    }
+
+    let max_size = get_config().general.max_request_size;
+    let mut content = vec![];
+
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(std::io::Error::other)?;
+        let _ = body.flow_control().release_capacity(chunk.len());
+        content.extend_from_slice(&chunk);
+
+        if content.len() > max_size {
+            head.push_str("\r\n");
+            return Err(Error::ContentTooLarge(Head::read(head.as_bytes()).await?));
+        }
+    }
+
+    head.push_str(&format!("content-length: {}\r\n\r\n", content.len()));
+
+    let mut bytes = head.into_bytes();
+    bytes.extend(content);
+
+    Request::read(peer, bytes.as_slice()).await
+}
+
+/// Send the response on an HTTP/2 stream.
+pub(crate) async fn send_response(
+    mut respond: SendResponse<Bytes>,
+    response: Response,
+    head_only: bool,
+) -> Result<(), Error> {
+    // Reset the stream instead of responding, the closest HTTP/2 has to a dropped connection.
+    if response.dropped() {
+        respond.send_reset(h2::Reason::INTERNAL_ERROR);
+        return Ok(());
+    }
+
+    let (code, headers, mut body) = response.into_parts();
+
+    let mut builder = http::Response::builder().status(code);
+
+    for (name, value) in &headers {
+        if !CONNECTION_HEADERS.contains(&name.as_str()) {
+            builder = builder.header(name, value);
+        }
+    }
+
+    let head = builder
+        .body(())
+        .map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err))?;
+
+    let stream = respond
+        .send_response(head, head_only)
+        .map_err(std::io::Error::other)?;
+
+    if !head_only {
+        let mut stream = SendBody::new(stream);
+        body.send_unframed(&mut stream).await?;
+        stream.end()?;
+    }
+
+    Ok(())
+}
+
+/// Write the response body as DATA frames, respecting the flow control window of the client.
+struct SendBody {
+    stream: SendStream<Bytes>,
+}
+
+impl SendBody {
+    fn new(stream: SendStream<Bytes>) -> Self {
+        Self { stream }
+    }
+
+    /// Tell the client the body is complete.
+    fn end(&mut self) -> Result<(), std::io::Error> {
+        self.stream
+            .send_data(Bytes::new(), true)
+            .map_err(std::io::Error::other)
+    }
+}
+
+impl AsyncWrite for SendBody {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        self.stream.reserve_capacity(buf.len());
+
+        loop {
+            match ready!(self.stream.poll_capacity(cx)) {
+                Some(Ok(0)) => continue,
+                Some(Ok(capacity)) => {
+                    let len = capacity.min(buf.len());
+                    self.stream
+                        .send_data(Bytes::copy_from_slice(&buf[..len]), false)
+                        .map_err(std::io::Error::other)?;
+                    return Poll::Ready(Ok(len));
+                }
+                Some(Err(err)) => return Poll::Ready(Err(std::io::Error::other(err))),
+                None => return Poll::Ready(Err(ErrorKind::BrokenPipe.into())),
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), std::io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,364 @@
+//! Locale-prefixed routes, e.g. `/en/about` and `/fr/about`.
+//!
+//! [`Locales`] serves each route once per supported locale, with the locale as the first path segment,
+//! and sets the locale on the request, so controllers can read it with [`Request::locale`]. Requests
+//! for the path without a locale, e.g. `/about`, are redirected to the locale the client prefers: the one
+//! saved in its session, or the best match for its `Accept-Language` header.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let routes = Locales::new(&["en", "fr"]).routes(vec![
+//!     route!("/" => Index),
+//!     route!("/about" => About),
+//! ]);
+//!
+//! Server::new(routes).launch().await?;
+//! ```
+use std::sync::Arc;
+
+use super::{Handler, Request, Response, Stream};
+use crate::controller::{AuthHandler, Controller, Error, MiddlewareSet, Session};
+use crate::i18n;
+
+/// Key of the preferred locale in the session payload.
+const SESSION_KEY: &str = "locale";
+
+/// Supported locales. The first one is used if the client doesn't prefer any of them.
+#[derive(Debug, Clone)]
+pub struct Locales {
+    locales: Arc<Vec<String>>,
+    redirect: bool,
+}
+
+impl Locales {
+    /// Support these locales, e.g. `&["en", "fr"]`. The first one is the default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no locales are given.
+    pub fn new(locales: &[&str]) -> Self {
+        assert!(!locales.is_empty(), "at least one locale is required");
+
+        Self {
+            locales: Arc::new(locales.iter().map(|locale| locale.to_string()).collect()),
+            redirect: true,
+        }
+    }
+
+    /// Don't redirect paths without a locale. Use this if they are served by other routes.
+    pub fn no_redirect(mut self) -> Self {
+        self.redirect = false;
+        self
+    }
+
+    /// Supported locales.
+    pub fn locales(&self) -> &[String] {
+        &self.locales
+    }
+
+    /// The default locale.
+    pub fn default_locale(&self) -> &str {
+        &self.locales[0]
+    }
+
+    /// Serve the routes under each locale. The path without a locale redirects
+    /// to the path in the client's preferred locale.
+    pub fn routes(&self, handlers: Vec<Handler>) -> Vec<Handler> {
+        let mut routes = vec![];
+
+        for handler in handlers {
+            let path = handler.path().base().to_string();
+            let path_type = handler.path_with_regex().path_type().clone();
+            let rank = handler.rank();
+            let controller = Arc::new(handler.into_controller());
+
+            for locale in self.locales.iter() {
+                let localized = Localized {
+                    locale: locale.clone(),
+                    controller: controller.clone(),
+                };
+
+                routes.push(
+                    Handler::new(&locale_path(locale, &path), localized, path_type.clone())
+                        .with_rank(rank),
+                );
+            }
+
+            if self.redirect {
+                routes.push(
+                    Handler::new(&path, LocaleRedirect::new(self.clone()), path_type)
+                        .with_rank(rank),
+                );
+            }
+        }
+
+        routes
+    }
+
+    /// Locale the client prefers: the one saved in its session, the best match for its
+    /// `Accept-Language` header, or the default locale.
+    pub fn preferred(&self, request: &Request) -> &str {
+        let saved = request
+            .session()
+            .payload
+            .get(SESSION_KEY)
+            .and_then(|locale| locale.as_str())
+            .and_then(|locale| self.find(locale));
+
+        if let Some(locale) = saved {
+            return locale;
+        }
+
+        request
+            .header("accept-language")
+            .and_then(|header| self.negotiate(header))
+            .unwrap_or(self.default_locale())
+    }
+
+    /// Best supported locale for the `Accept-Language` header,
+    /// e.g. `fr-CH, fr;q=0.9, en;q=0.8`.
+    pub fn negotiate(&self, header: &str) -> Option<&str> {
+        let mut ranges = header
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let tag = parts.next()?.trim();
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map(|q| q.trim().parse::<f32>().unwrap_or(0.0))
+                    .unwrap_or(1.0);
+
+                (!tag.is_empty() && quality > 0.0).then_some((tag, quality))
+            })
+            .collect::<Vec<_>>();
+
+        // Stable, so ranges with the same quality keep their order.
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        ranges.into_iter().find_map(|(tag, _)| {
+            if tag == "*" {
+                return Some(self.default_locale());
+            }
+
+            // Exact match, e.g. `pt-BR`, otherwise the same language, e.g. `fr-CH` for `fr`.
+            self.find(tag).or_else(|| {
+                let language = primary(tag);
+                self.locales
+                    .iter()
+                    .find(|locale| primary(locale).eq_ignore_ascii_case(language))
+                    .map(|locale| locale.as_str())
+            })
+        })
+    }
+
+    fn find(&self, locale: &str) -> Option<&str> {
+        self.locales
+            .iter()
+            .find(|supported| supported.eq_ignore_ascii_case(locale))
+            .map(|locale| locale.as_str())
+    }
+}
+
+/// Save the preferred locale in the session, so paths without a locale redirect to it,
+/// regardless of the `Accept-Language` header. Set the returned session on the response.
+///
+/// # Example
+///
+/// ```
+/// # use rwf::prelude::*;
+/// # use rwf::http::locale;
+/// # let request = Request::default();
+/// let response = Response::new()
+///     .set_session(locale::remember(&request, "fr"))
+///     .redirect(request.path_in_locale("fr"));
+/// ```
+pub fn remember(request: &Request, locale: &str) -> Session {
+    let mut session = request.session().clone();
+
+    if !session.payload.is_object() {
+        session.payload = serde_json::json!({});
+    }
+
+    session.payload[SESSION_KEY] = serde_json::Value::String(locale.to_string());
+    session
+}
+
+/// Path in this locale, e.g. `/about` in `fr` is `/fr/about`.
+pub fn locale_path(locale: &str, path: &str) -> String {
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    };
+
+    if path == "/" {
+        format!("/{}", locale)
+    } else if path.starts_with("/?") {
+        format!("/{}{}", locale, &path[1..])
+    } else {
+        format!("/{}{}", locale, path)
+    }
+}
+
+/// Language of the locale, e.g. `pt` for `pt-BR`.
+fn primary(locale: &str) -> &str {
+    locale.split(['-', '_']).next().unwrap_or(locale)
+}
+
+/// Serves a route in one locale.
+struct Localized {
+    locale: String,
+    controller: Arc<Box<dyn Controller>>,
+}
+
+#[crate::async_trait]
+impl Controller for Localized {
+    fn auth(&self) -> &AuthHandler {
+        self.controller.auth()
+    }
+
+    fn middleware(&self) -> &MiddlewareSet {
+        self.controller.middleware()
+    }
+
+    fn skip_csrf(&self) -> bool {
+        self.controller.skip_csrf()
+    }
+
+    async fn handle_stream(&self, request: &Request, stream: Stream<'_>) -> Result<bool, Error> {
+        self.controller.handle_stream(request, stream).await
+    }
+
+    async fn handle_internal(&self, request: Request) -> Result<Response, Error> {
+        i18n::scope(
+            &self.locale,
+            self.controller
+                .handle_internal(request.with_locale(&self.locale)),
+        )
+        .await
+    }
+
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let request = request.clone().with_locale(&self.locale);
+        self.controller.handle(&request).await
+    }
+
+    fn controller_name(&self) -> &'static str {
+        self.controller.controller_name()
+    }
+}
+
+/// Redirects paths without a locale to the client's preferred locale.
+struct LocaleRedirect {
+    locales: Locales,
+}
+
+impl LocaleRedirect {
+    fn new(locales: Locales) -> Self {
+        Self { locales }
+    }
+}
+
+#[crate::async_trait]
+impl Controller for LocaleRedirect {
+    async fn handle(&self, request: &Request) -> Result<Response, Error> {
+        let locale = self.locales.preferred(request);
+        let path = locale_path(locale, &request.path().to_string());
+
+        Ok(Response::new()
+            .redirect(path)
+            .header("vary", "accept-language, cookie"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::Harness;
+
+    struct Page;
+
+    #[crate::async_trait]
+    impl Controller for Page {
+        async fn handle(&self, request: &Request) -> Result<Response, Error> {
+            Ok(Response::new().text(format!(
+                "{} {} {}",
+                request.locale().unwrap_or("none"),
+                request.parameter::<i64>("id")?.unwrap_or(0),
+                request.path_in_locale("en"),
+            )))
+        }
+    }
+
+    async fn body(response: Response) -> String {
+        let mut bytes = vec![];
+        response.send(&mut bytes).await.unwrap();
+        let response = String::from_utf8(bytes).unwrap();
+        response.split_once("\r\n\r\n").unwrap().1.to_string()
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let locales = Locales::new(&["en", "fr", "pt-BR"]);
+
+        assert_eq!(locales.negotiate("fr-CH, fr;q=0.9, en;q=0.8"), Some("fr"));
+        assert_eq!(locales.negotiate("de, en;q=0.5, fr;q=0.7"), Some("fr"));
+        assert_eq!(locales.negotiate("pt"), Some("pt-BR"));
+        assert_eq!(locales.negotiate("pt-br"), Some("pt-BR"));
+        assert_eq!(locales.negotiate("de, *;q=0.1"), Some("en"));
+        assert_eq!(locales.negotiate("fr;q=0, de"), None);
+        assert_eq!(locales.negotiate(""), None);
+    }
+
+    #[test]
+    fn test_locale_path() {
+        assert_eq!(locale_path("fr", "/"), "/fr");
+        assert_eq!(locale_path("fr", "/about"), "/fr/about");
+        assert_eq!(locale_path("fr", "about"), "/fr/about");
+        assert_eq!(locale_path("fr", "/?page=2"), "/fr?page=2");
+    }
+
+    #[tokio::test]
+    async fn test_locale_routes() {
+        let locales = Locales::new(&["en", "fr"]);
+        let harness =
+            Harness::new(locales.routes(vec![Page.route("/"), Page.route("/users/:id")])).unwrap();
+
+        let response = harness.get("/fr/users/5?tab=1").await.unwrap();
+        assert_eq!(body(response).await, "fr 5 /en/users/5?tab=1");
+
+        let response = harness.get("/en").await.unwrap();
+        assert_eq!(body(response).await, "en 0 /en");
+
+        let response = harness.get("/de/users/5").await.unwrap();
+        assert_eq!(response.status().code(), 404);
+
+        // Bare paths redirect to the preferred locale.
+        let response = harness
+            .request(
+                "GET /users/5?tab=1 HTTP/1.1\r\nAccept-Language: fr-CA, en;q=0.5\r\nContent-Length: 0\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status().code(), 302);
+        assert_eq!(
+            response.headers().get("location").unwrap(),
+            "/fr/users/5?tab=1"
+        );
+
+        let response = harness.get("/").await.unwrap();
+        assert_eq!(response.headers().get("location").unwrap(), "/en");
+    }
+
+    #[tokio::test]
+    async fn test_session_preference() {
+        let locales = Locales::new(&["en", "fr"]);
+        let request = Request::default();
+        assert_eq!(locales.preferred(&request), "en");
+
+        let session = remember(&request, "fr");
+        let request = request.set_session(session);
+        assert_eq!(locales.preferred(&request), "fr");
+    }
+}
@@ -0,0 +1,284 @@
+//! JSON Merge Patch ([RFC 7386](https://www.rfc-editor.org/rfc/rfc7386)), used to partially update
+//! models with `PATCH` requests.
+//!
+//! The patch is a JSON object containing only the fields to change. Fields set to `null` are removed,
+//! which for a model means setting the column to `NULL`, while fields missing from the patch keep their
+//! current values. Nested objects, e.g. stored in a JSON column, are merged the same way.
+//!
+//! ```text
+//! PATCH /users/5
+//!
+//! {"name": "Alice", "avatar_url": null}
+//! ```
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut conn = Pool::connection().await?;
+//! let user = User::find(id).fetch(&mut conn).await?;
+//!
+//! // Only the columns which changed are updated.
+//! let patch = request.merge_patch(&user)?;
+//! let user = patch.update(id).fetch(&mut conn).await?;
+//! ```
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value as Json};
+
+use super::Error;
+use crate::model::{Model, Query, ToValue, Update, Value};
+
+/// Apply the patch to the JSON document, as described in RFC 7386.
+pub fn merge(target: &mut Json, patch: &Json) {
+    match patch {
+        Json::Object(patch) => {
+            if !target.is_object() {
+                *target = Json::Object(Map::new());
+            }
+
+            if let Json::Object(target) = target {
+                for (name, value) in patch {
+                    if value.is_null() {
+                        target.remove(name);
+                    } else {
+                        merge(target.entry(name.clone()).or_insert(Json::Null), value);
+                    }
+                }
+            }
+        }
+
+        patch => *target = patch.clone(),
+    }
+}
+
+/// A merge patch applied to a model.
+#[derive(Debug, Clone)]
+pub struct MergePatch<T> {
+    model: T,
+    changes: Vec<(&'static str, Value)>,
+}
+
+impl<T: Model + Serialize + DeserializeOwned> MergePatch<T> {
+    /// Apply the patch to the model. Returns an error if the patch isn't an object, changes the
+    /// primary key, or the patched model can't be deserialized, e.g. because it removes a field
+    /// which isn't optional.
+    pub fn new(model: &T, patch: &Json) -> Result<Self, Error> {
+        if !patch.is_object() {
+            return Err(Error::InvalidBody(
+                "merge patch must be a JSON object".into(),
+            ));
+        }
+
+        let mut json = serde_json::to_value(model)?;
+        merge(&mut json, patch);
+
+        let patched =
+            serde_json::from_value::<T>(json).map_err(|err| Error::InvalidBody(err.to_string()))?;
+
+        if patched.id() != model.id() {
+            return Err(Error::InvalidBody("primary key can't be changed".into()));
+        }
+
+        let changes = T::column_names()
+            .iter()
+            .zip(model.values().into_iter().zip(patched.values()))
+            .filter(|(_, (before, after))| before != after)
+            .map(|(column, (_, after))| (*column, after))
+            .collect();
+
+        Ok(Self {
+            model: patched,
+            changes,
+        })
+    }
+
+    /// The model with the patch applied.
+    pub fn model(&self) -> &T {
+        &self.model
+    }
+
+    /// The model with the patch applied.
+    pub fn into_model(self) -> T {
+        self.model
+    }
+
+    /// Columns changed by the patch, and their new values.
+    pub fn changes(&self) -> &[(&'static str, Value)] {
+        &self.changes
+    }
+
+    /// The patch changes the column.
+    pub fn changed(&self, column: &str) -> bool {
+        self.changes.iter().any(|(name, _)| *name == column)
+    }
+
+    /// The patch doesn't change anything.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Keep only changes to columns for which the function returns true, e.g. to ignore
+    /// columns the user isn't allowed to change.
+    pub fn retain(mut self, mut f: impl FnMut(&str) -> bool) -> Self {
+        self.changes.retain(|(column, _)| f(column));
+        self
+    }
+
+    /// Query updating the changed columns of the record, and returning it. If nothing changed,
+    /// the record is fetched instead.
+    pub fn update(&self, id: impl ToValue) -> Query<T> {
+        if self.changes.is_empty() {
+            return T::find(id);
+        }
+
+        let (columns, values): (Vec<_>, Vec<_>) = self.changes.iter().cloned().unzip();
+        Query::Update(Update::from_columns(id, &columns, &values))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge() {
+        // Examples from RFC 7386, appendix A.
+        for (target, patch, result) in [
+            (json!({"a": "b"}), json!({"a": "c"}), json!({"a": "c"})),
+            (
+                json!({"a": "b"}),
+                json!({"b": "c"}),
+                json!({"a": "b", "b": "c"}),
+            ),
+            (json!({"a": "b"}), json!({"a": null}), json!({})),
+            (
+                json!({"a": "b", "b": "c"}),
+                json!({"a": null}),
+                json!({"b": "c"}),
+            ),
+            (json!({"a": ["b"]}), json!({"a": "c"}), json!({"a": "c"})),
+            (json!({"a": "c"}), json!({"a": ["b"]}), json!({"a": ["b"]})),
+            (
+                json!({"a": {"b": "c"}}),
+                json!({"a": {"b": "d", "c": null}}),
+                json!({"a": {"b": "d"}}),
+            ),
+            (
+                json!({"a": [{"b": "c"}]}),
+                json!({"a": [1]}),
+                json!({"a": [1]}),
+            ),
+            (json!(["a", "b"]), json!(["c", "d"]), json!(["c", "d"])),
+            (json!({"a": "b"}), json!(["c"]), json!(["c"])),
+            (json!({"a": "foo"}), json!(null), json!(null)),
+            (json!({"a": "foo"}), json!("bar"), json!("bar")),
+            (
+                json!({"e": null}),
+                json!({"a": 1}),
+                json!({"e": null, "a": 1}),
+            ),
+            (
+                json!([1, 2]),
+                json!({"a": "b", "c": null}),
+                json!({"a": "b"}),
+            ),
+            (
+                json!({}),
+                json!({"a": {"bb": {"ccc": null}}}),
+                json!({"a": {"bb": {}}}),
+            ),
+        ] {
+            let mut target = target;
+            merge(&mut target, &patch);
+            assert_eq!(target, result);
+        }
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    struct User {
+        id: Option<i64>,
+        name: String,
+        avatar_url: Option<String>,
+        settings: serde_json::Value,
+    }
+
+    impl crate::model::FromRow for User {
+        fn from_row(_row: tokio_postgres::Row) -> Result<Self, crate::model::Error> {
+            unimplemented!()
+        }
+    }
+
+    impl Model for User {
+        fn id(&self) -> Value {
+            self.id.to_value()
+        }
+
+        fn table_name() -> &'static str {
+            "users"
+        }
+
+        fn foreign_key() -> &'static str {
+            "user_id"
+        }
+
+        fn column_names() -> &'static [&'static str] {
+            &["name", "avatar_url", "settings"]
+        }
+
+        fn values(&self) -> Vec<Value> {
+            vec![
+                self.name.to_value(),
+                self.avatar_url.to_value(),
+                self.settings.to_value(),
+            ]
+        }
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        let user = User {
+            id: Some(5),
+            name: "Bob".into(),
+            avatar_url: Some("https://example.com/bob.png".into()),
+            settings: json!({"theme": "dark", "beta": true}),
+        };
+
+        let patch = MergePatch::new(
+            &user,
+            &json!({"name": "Alice", "avatar_url": null, "settings": {"beta": null}}),
+        )
+        .unwrap();
+        assert_eq!(patch.model().name, "Alice");
+        assert_eq!(patch.model().avatar_url, None);
+        assert_eq!(patch.model().settings, json!({"theme": "dark"}));
+        assert_eq!(
+            patch
+                .changes()
+                .iter()
+                .map(|(column, _)| *column)
+                .collect::<Vec<_>>(),
+            vec!["name", "avatar_url", "settings"]
+        );
+        assert_eq!(patch.changes()[1].1, None::<String>.to_value());
+
+        // Missing fields and unchanged values aren't updated.
+        let patch = MergePatch::new(&user, &json!({"name": "Bob"})).unwrap();
+        assert!(patch.is_empty());
+        assert_eq!(patch.model().avatar_url, user.avatar_url);
+
+        let patch = MergePatch::new(&user, &json!({"name": "Alice", "avatar_url": null}))
+            .unwrap()
+            .retain(|column| column != "name");
+        assert!(!patch.changed("name"));
+        assert!(patch.changed("avatar_url"));
+
+        // Required fields can't be removed, the primary key can't change.
+        for patch in [json!({"name": null}), json!({"id": 6}), json!([1])] {
+            assert!(matches!(
+                MergePatch::new(&user, &patch),
+                Err(Error::InvalidBody(_))
+            ));
+        }
+    }
+}
@@ -11,18 +11,37 @@
 #![allow(dead_code)]
 pub mod authorization;
 pub mod body;
+pub mod client;
+pub mod compression;
 pub mod cookies;
+pub mod coverage;
 pub mod error;
+pub mod extract;
 pub mod form;
+pub mod format;
 pub mod form_data;
+pub mod forwarded;
+pub mod geo;
 pub mod handler;
+pub mod harness;
 pub mod head;
 pub mod headers;
+pub mod http2;
+pub mod locale;
+pub mod merge_patch;
+pub mod openapi;
+pub mod pagination;
 pub mod path;
+pub mod post_process;
+pub mod problem;
 pub mod request;
 pub mod response;
+pub mod rewriter;
 pub mod router;
 pub mod server;
+pub mod tls;
+pub mod trace;
+pub mod upload;
 pub mod url;
 pub mod websocket;
 
@@ -31,18 +50,30 @@ pub mod wsgi;
 
 pub use authorization::Authorization;
 pub use body::Body;
-pub use cookies::{Cookie, CookieBuilder, Cookies};
+pub use cookies::{Cookie, CookieBuilder, Cookies, SameSite};
+pub use coverage::RouteCoverage;
 pub use error::Error;
-pub use form::{Form, FromFormData};
+pub use extract::FromRequest;
+pub use form::{Form, FormErrors, FromFormData};
+pub use format::{Format, ToResponse};
 pub use form_data::FormData;
+pub use geo::Geo;
 pub use handler::Handler;
+pub use harness::Harness;
 pub use head::{Head, Method};
+pub use locale::Locales;
+pub use merge_patch::MergePatch;
 pub use headers::Headers;
+pub use openapi::{OpenApi, Operation, Schema};
+pub use pagination::Pagination;
 pub use path::{Params, Path, Query, ToParameter};
+pub use problem::{Problem, ValidationErrors};
 pub use request::Request;
-pub use response::Response;
-pub use router::Router;
-pub use server::{Server, Stream};
+pub use response::{ErrorReport, Response};
+pub use router::{url_for, RouteMatch, Router};
+pub use server::{ConnectionMetrics, Server, Stream};
+pub use trace::TraceContext;
+pub use upload::{MultipartForm, UploadedFile};
 pub use url::{urldecode, urlencode};
 pub use websocket::{Message, ToMessage};
 
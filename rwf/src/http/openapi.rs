@@ -0,0 +1,801 @@
+//! [OpenAPI 3](https://spec.openapis.org/oas/v3.0.3) specification of the app's API.
+//!
+//! Controllers describe the requests they handle by returning [`Operation`]s from
+//! [`Controller::openapi`]. Request and response bodies are described with JSON Schema, using the
+//! [`Schema`] trait. It can be derived for structs and enums that implement `serde::Serialize` or `Deserialize`,
+//! and respects `serde` attributes like `rename`, `rename_all`, `skip` and `default`.
+//!
+//! Extractors describe themselves too, see [`Operation::extract`]. Path parameters, e.g. `:id`, are
+//! added to the operations automatically.
+//!
+//! [`Server::openapi`](crate::http::Server::openapi) serves the specification, at `/openapi.json` by default,
+//! and optionally [Swagger UI](https://swagger.io/tools/swagger-ui/) to browse it.
+//!
+//! # Example
+//!
+//! ```
+//! use rwf::prelude::*;
+//! use rwf::http::{extract::Json, Server};
+//! use rwf::http::OpenApi;
+//! use serde::{Deserialize, Serialize};
+//!
+//! /// A comment on a post.
+//! #[derive(Serialize, Deserialize, macros::Schema)]
+//! struct Comment {
+//!     body: String,
+//!     #[serde(rename = "author")]
+//!     author_name: Option<String>,
+//! }
+//!
+//! #[derive(Default)]
+//! struct Comments;
+//!
+//! #[async_trait]
+//! impl Controller for Comments {
+//!     fn openapi(&self) -> Vec<Operation> {
+//!         vec![
+//!             Operation::get()
+//!                 .summary("List comments")
+//!                 .response::<Vec<Comment>>(200, "Comments of the post"),
+//!             Operation::post()
+//!                 .summary("Add a comment")
+//!                 .extract::<Json<Comment>>()
+//!                 .response::<Comment>(201, "The new comment"),
+//!         ]
+//!     }
+//!
+//!     async fn handle(&self, request: &Request) -> Result<Response, Error> {
+//!         Ok(Response::new())
+//!     }
+//! }
+//!
+//! let server = Server::new(vec![route!("/posts/:post_id/comments" => Comments)])
+//!     .openapi(OpenApi::new("Blog", "1.0").swagger_ui("/docs"));
+//! ```
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::{json, Map, Value as Json};
+
+use super::extract::{Form, Json as JsonBody, Path, Query, Settings};
+use super::{Handler, Method, Request, Response};
+use crate::controller::{Controller, Error};
+
+/// JSON Schema of a type, used to describe request and response bodies.
+///
+/// Derive it with `#[derive(macros::Schema)]`. Implement it manually for types with custom `serde` implementations.
+///
+/// # Example
+///
+/// ```
+/// use rwf::prelude::*;
+/// use serde_json::json;
+///
+/// #[derive(Serialize, Deserialize, macros::Schema)]
+/// #[serde(rename_all = "camelCase")]
+/// struct User {
+///     full_name: String,
+///     /// Where the user lives.
+///     country: Option<String>,
+///     role: Role,
+///     #[serde(skip)]
+///     password: String,
+/// }
+///
+/// #[derive(Serialize, Deserialize, macros::Schema)]
+/// #[serde(rename_all = "lowercase")]
+/// enum Role {
+///     Admin,
+///     Member,
+/// }
+///
+/// assert_eq!(
+///     User::schema(),
+///     json!({
+///         "type": "object",
+///         "properties": {
+///             "fullName": { "type": "string" },
+///             "country": { "type": "string", "nullable": true, "description": "Where the user lives." },
+///             "role": { "type": "string", "enum": ["admin", "member"] },
+///         },
+///         "required": ["fullName", "role"],
+///     })
+/// );
+/// ```
+pub trait Schema {
+    /// The JSON Schema, e.g. `{"type": "string"}`.
+    fn schema() -> Json;
+}
+
+macro_rules! schema {
+    ($schema:tt => $($ty:ty),*) => {
+        $(
+            impl Schema for $ty {
+                fn schema() -> Json {
+                    json!($schema)
+                }
+            }
+        )*
+    };
+}
+
+schema!({ "type": "boolean" } => bool);
+schema!({ "type": "integer", "format": "int32" } => i8, i16, i32, u8, u16, u32);
+schema!({ "type": "integer", "format": "int64" } => i64, u64, isize, usize);
+schema!({ "type": "number", "format": "float" } => f32);
+schema!({ "type": "number", "format": "double" } => f64);
+schema!({ "type": "string" } => String, str, char);
+schema!({ "type": "string", "format": "uuid" } => uuid::Uuid);
+schema!({ "type": "string", "format": "date-time" } => time::OffsetDateTime, time::PrimitiveDateTime);
+schema!({ "type": "string", "format": "date" } => time::Date);
+schema!({} => Json);
+
+impl<T: Schema + ?Sized> Schema for &T {
+    fn schema() -> Json {
+        T::schema()
+    }
+}
+
+impl<T: Schema + ?Sized> Schema for Box<T> {
+    fn schema() -> Json {
+        T::schema()
+    }
+}
+
+impl<T: Schema + ?Sized> Schema for Arc<T> {
+    fn schema() -> Json {
+        T::schema()
+    }
+}
+
+impl<T: Schema> Schema for Option<T> {
+    fn schema() -> Json {
+        let mut schema = T::schema();
+        if let Some(object) = schema.as_object_mut() {
+            object.insert("nullable".into(), Json::Bool(true));
+        }
+        schema
+    }
+}
+
+macro_rules! array_schema {
+    ($($ty:ident),*) => {
+        $(
+            impl<T: Schema> Schema for $ty<T> {
+                fn schema() -> Json {
+                    json!({ "type": "array", "items": T::schema() })
+                }
+            }
+        )*
+    };
+}
+
+array_schema!(Vec, HashSet, BTreeSet);
+
+impl<T: Schema> Schema for [T] {
+    fn schema() -> Json {
+        Vec::<T>::schema()
+    }
+}
+
+impl<K, T: Schema> Schema for HashMap<K, T> {
+    fn schema() -> Json {
+        json!({ "type": "object", "additionalProperties": T::schema() })
+    }
+}
+
+impl<K, T: Schema> Schema for BTreeMap<K, T> {
+    fn schema() -> Json {
+        HashMap::<K, T>::schema()
+    }
+}
+
+/// Describe the part of the request an extractor reads, e.g. the JSON body.
+pub trait Describe {
+    /// Add the description to the operation.
+    fn describe(operation: Operation) -> Operation;
+}
+
+impl<T: Schema> Describe for JsonBody<T> {
+    fn describe(operation: Operation) -> Operation {
+        operation.json::<T>()
+    }
+}
+
+impl<T: Schema> Describe for Form<T> {
+    fn describe(operation: Operation) -> Operation {
+        operation.form::<T>()
+    }
+}
+
+impl<T: Schema> Describe for Query<T> {
+    fn describe(operation: Operation) -> Operation {
+        operation.query::<T>()
+    }
+}
+
+impl<T> Describe for Settings<T> {
+    fn describe(operation: Operation) -> Operation {
+        operation
+    }
+}
+
+impl<T: Describe> Describe for Option<T> {
+    fn describe(operation: Operation) -> Operation {
+        T::describe(operation)
+    }
+}
+
+macro_rules! describe_path {
+    ($($ty:ident),*) => {
+        impl<$($ty: Schema),*> Describe for Path<($($ty,)*)> {
+            fn describe(mut operation: Operation) -> Operation {
+                operation.path_schemas = vec![$($ty::schema()),*];
+                operation
+            }
+        }
+    };
+}
+
+describe_path!(A);
+describe_path!(A, B);
+describe_path!(A, B, C);
+describe_path!(A, B, C, D);
+
+macro_rules! describe_scalar_path {
+    ($($ty:ty),*) => {
+        $(
+            impl Describe for Path<$ty> {
+                fn describe(mut operation: Operation) -> Operation {
+                    operation.path_schemas = vec![<$ty>::schema()];
+                    operation
+                }
+            }
+        )*
+    };
+}
+
+describe_scalar_path!(
+    i8,
+    i16,
+    i32,
+    i64,
+    u8,
+    u16,
+    u32,
+    u64,
+    isize,
+    usize,
+    f32,
+    f64,
+    bool,
+    String,
+    uuid::Uuid
+);
+
+macro_rules! describe_tuple {
+    ($($ty:ident),*) => {
+        impl<$($ty: Describe),*> Describe for ($($ty,)*) {
+            fn describe(operation: Operation) -> Operation {
+                $(let operation = $ty::describe(operation);)*
+                operation
+            }
+        }
+    };
+}
+
+describe_tuple!(A, B);
+describe_tuple!(A, B, C);
+describe_tuple!(A, B, C, D);
+
+/// A request handled by a controller, e.g. `GET /users`.
+#[derive(Debug, Clone)]
+pub struct Operation {
+    method: Method,
+    path: String,
+    summary: Option<String>,
+    description: Option<String>,
+    tags: Vec<String>,
+    parameters: Vec<Json>,
+    path_schemas: Vec<Json>,
+    request_body: Option<Json>,
+    responses: Vec<(u16, String, Option<Json>)>,
+}
+
+impl Operation {
+    /// Describe a request with this method, to the controller's route.
+    pub fn new(method: Method) -> Self {
+        Self {
+            method,
+            path: String::new(),
+            summary: None,
+            description: None,
+            tags: vec![],
+            parameters: vec![],
+            path_schemas: vec![],
+            request_body: None,
+            responses: vec![],
+        }
+    }
+
+    /// `GET` request.
+    pub fn get() -> Self {
+        Self::new(Method::Get)
+    }
+
+    /// `POST` request.
+    pub fn post() -> Self {
+        Self::new(Method::Post)
+    }
+
+    /// `PUT` request.
+    pub fn put() -> Self {
+        Self::new(Method::Put)
+    }
+
+    /// `PATCH` request.
+    pub fn patch() -> Self {
+        Self::new(Method::Patch)
+    }
+
+    /// `DELETE` request.
+    pub fn delete() -> Self {
+        Self::new(Method::Delete)
+    }
+
+    /// Path below the controller's route, e.g. `/:id` for requests to a record of a REST controller.
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = path.to_string();
+        self
+    }
+
+    /// Short summary of what the request does.
+    pub fn summary(mut self, summary: impl ToString) -> Self {
+        self.summary = Some(summary.to_string());
+        self
+    }
+
+    /// Longer description of the request. Supports Markdown.
+    pub fn description(mut self, description: impl ToString) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// Group the request with others in the documentation, e.g. `"users"`.
+    pub fn tag(mut self, tag: impl ToString) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    /// Add a parameter, e.g. `.parameter::<i64>("page", "query")`. `location` is `"query"`, `"path"`,
+    /// `"header"` or `"cookie"`. Path parameters and parameters which aren't an `Option` are required.
+    pub fn parameter<T: Schema>(mut self, name: &str, location: &str) -> Self {
+        let schema = T::schema();
+        let required = location == "path" || schema.get("nullable").is_none();
+        self.parameters.push(json!({
+            "name": name,
+            "in": location,
+            "required": required,
+            "schema": schema,
+        }));
+        self
+    }
+
+    /// Add a query parameter for each field of the struct.
+    pub fn query<T: Schema>(mut self) -> Self {
+        let schema = T::schema();
+        let required = required(&schema);
+        if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
+            for (name, schema) in properties {
+                self.parameters.push(json!({
+                    "name": name,
+                    "in": "query",
+                    "required": required.contains(&name.as_str()),
+                    "schema": schema,
+                }));
+            }
+        }
+        self
+    }
+
+    /// The request body is JSON.
+    pub fn json<T: Schema>(self) -> Self {
+        self.body::<T>("application/json")
+    }
+
+    /// The request body is a submitted form.
+    pub fn form<T: Schema>(self) -> Self {
+        self.body::<T>("application/x-www-form-urlencoded")
+            .body::<T>("multipart/form-data")
+    }
+
+    /// The request body has this content type, e.g. `"application/xml"`.
+    pub fn body<T: Schema>(self, content_type: &str) -> Self {
+        self.body_schema(content_type, T::schema())
+    }
+
+    /// The request body has this content type and JSON Schema.
+    pub fn body_schema(mut self, content_type: &str, schema: Json) -> Self {
+        let body = self
+            .request_body
+            .get_or_insert_with(|| json!({ "required": true, "content": {} }));
+        body["content"][content_type] = json!({ "schema": schema });
+        self
+    }
+
+    /// Describe the parts of the request read by the extractor, e.g. `.extract::<Json<Comment>>()`.
+    /// Several extractors can be combined using a tuple.
+    pub fn extract<E: Describe>(self) -> Self {
+        E::describe(self)
+    }
+
+    /// The controller responds with this status code and a JSON body.
+    pub fn response<T: Schema>(self, code: u16, description: &str) -> Self {
+        self.response_schema(code, description, T::schema())
+    }
+
+    /// The controller responds with this status code and a JSON body with this schema.
+    pub fn response_schema(mut self, code: u16, description: &str, schema: Json) -> Self {
+        self.responses
+            .push((code, description.to_string(), Some(schema)));
+        self
+    }
+
+    /// The controller responds with this status code and no body, e.g. `204` or `404`.
+    pub fn status(mut self, code: u16, description: &str) -> Self {
+        self.responses.push((code, description.to_string(), None));
+        self
+    }
+
+    /// The request method.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// The operation object. `names` are the names of the path parameters.
+    fn to_json(&self, names: &[String]) -> Json {
+        let mut operation = Map::new();
+
+        if let Some(ref summary) = self.summary {
+            operation.insert("summary".into(), json!(summary));
+        }
+
+        if let Some(ref description) = self.description {
+            operation.insert("description".into(), json!(description));
+        }
+
+        if !self.tags.is_empty() {
+            operation.insert("tags".into(), json!(self.tags));
+        }
+
+        let mut parameters = vec![];
+        for (position, name) in names.iter().enumerate() {
+            let described = self
+                .parameters
+                .iter()
+                .any(|p| p["in"] == "path" && p["name"] == name.as_str());
+            if !described {
+                let schema = self
+                    .path_schemas
+                    .get(position)
+                    .cloned()
+                    .unwrap_or_else(String::schema);
+                parameters.push(json!({
+                    "name": name,
+                    "in": "path",
+                    "required": true,
+                    "schema": schema,
+                }));
+            }
+        }
+        parameters.extend(self.parameters.iter().cloned());
+
+        if !parameters.is_empty() {
+            operation.insert("parameters".into(), json!(parameters));
+        }
+
+        if let Some(ref body) = self.request_body {
+            operation.insert("requestBody".into(), body.clone());
+        }
+
+        let mut responses = Map::new();
+        for (code, description, schema) in &self.responses {
+            let mut response = json!({ "description": description });
+            if let Some(schema) = schema {
+                response["content"] = json!({ "application/json": { "schema": schema } });
+            }
+            responses.insert(code.to_string(), response);
+        }
+
+        if responses.is_empty() {
+            responses.insert("200".into(), json!({ "description": "OK" }));
+        }
+
+        operation.insert("responses".into(), Json::Object(responses));
+
+        Json::Object(operation)
+    }
+}
+
+/// Required properties of an object schema.
+fn required(schema: &Json) -> Vec<&str> {
+    schema
+        .get("required")
+        .and_then(|required| required.as_array())
+        .map(|required| required.iter().filter_map(|name| name.as_str()).collect())
+        .unwrap_or_default()
+}
+
+/// Convert a route to an OpenAPI path, e.g. `/users/:id` to `/users/{id}`, returning the names of its parameters.
+fn openapi_path(route: &str) -> (String, Vec<String>) {
+    let mut names = vec![];
+    let parts = route
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .map(
+            |part| match part.strip_prefix(':').or(part.strip_prefix('*')) {
+                Some(name) => {
+                    names.push(name.to_string());
+                    format!("{{{}}}", name)
+                }
+                None => part.to_string(),
+            },
+        )
+        .collect::<Vec<_>>();
+
+    (format!("/{}", parts.join("/")), names)
+}
+
+/// The OpenAPI document of the app.
+///
+/// # Example
+///
+/// ```
+/// use rwf::http::{openapi::OpenApi, Server};
+///
+/// let server = Server::new(vec![])
+///     .openapi(OpenApi::new("My API", "1.0").swagger_ui("/docs"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct OpenApi {
+    title: String,
+    version: String,
+    description: Option<String>,
+    path: String,
+    swagger_ui: Option<String>,
+}
+
+impl OpenApi {
+    /// Document the API with this title and version. The version is the version of your API, not of OpenAPI.
+    pub fn new(title: &str, version: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            version: version.to_string(),
+            description: None,
+            path: "/openapi.json".into(),
+            swagger_ui: None,
+        }
+    }
+
+    /// Description of the API. Supports Markdown.
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// Serve the specification at this path. Default is `/openapi.json`.
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = path.to_string();
+        self
+    }
+
+    /// Serve Swagger UI at this path, e.g. `/docs`. Swagger UI is loaded from the unpkg.com CDN.
+    pub fn swagger_ui(mut self, path: &str) -> Self {
+        self.swagger_ui = Some(path.to_string());
+        self
+    }
+
+    /// Build the specification from the operations of the controllers. Controllers which don't
+    /// describe any operations are left out.
+    pub fn spec(&self, handlers: &[Handler]) -> Json {
+        let mut paths = Map::new();
+
+        for handler in handlers {
+            for operation in handler.openapi() {
+                let route = format!("{}/{}", handler.path().path(), operation.path);
+                let (path, names) = openapi_path(&route);
+                let method = operation.method().to_string().to_lowercase();
+
+                if let Json::Object(ref mut item) = paths.entry(path).or_insert(json!({})) {
+                    item.insert(method, operation.to_json(&names));
+                }
+            }
+        }
+
+        let mut info = json!({
+            "title": self.title,
+            "version": self.version,
+        });
+
+        if let Some(ref description) = self.description {
+            info["description"] = json!(description);
+        }
+
+        json!({
+            "openapi": "3.0.3",
+            "info": info,
+            "paths": paths,
+        })
+    }
+
+    /// Route handlers serving the specification of the `handlers` and, if enabled, Swagger UI.
+    pub fn handlers(&self, handlers: &[Handler]) -> Vec<Handler> {
+        let spec = OpenApiController {
+            spec: Arc::new(self.spec(handlers)),
+        };
+        let mut handlers = vec![spec.route(&self.path)];
+
+        if let Some(ref path) = self.swagger_ui {
+            let swagger_ui = SwaggerUi {
+                url: self.path.clone(),
+                title: self.title.clone(),
+            };
+            handlers.push(swagger_ui.route(path));
+        }
+
+        handlers
+    }
+}
+
+/// Serves the OpenAPI specification.
+struct OpenApiController {
+    spec: Arc<Json>,
+}
+
+#[async_trait]
+impl Controller for OpenApiController {
+    async fn handle(&self, _request: &Request) -> Result<Response, Error> {
+        Ok(Response::new().json(self.spec.as_ref())?)
+    }
+}
+
+/// Serves Swagger UI for the specification.
+struct SwaggerUi {
+    url: String,
+    title: String,
+}
+
+#[async_trait]
+impl Controller for SwaggerUi {
+    async fn handle(&self, _request: &Request) -> Result<Response, Error> {
+        Ok(Response::new().html(format!(
+            r##"<!doctype html>
+<html lang="en">
+<head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>{}</title>
+    <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+    <div id="swagger-ui"></div>
+    <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+    <script>
+        window.ui = SwaggerUIBundle({{ url: {}, dom_id: "#swagger-ui" }});
+    </script>
+</body>
+</html>"##,
+            crate::safe_html(&self.title),
+            json!(self.url),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Comments;
+
+    #[async_trait]
+    impl Controller for Comments {
+        fn openapi(&self) -> Vec<Operation> {
+            vec![
+                Operation::get()
+                    .extract::<(Path<i64>, Query<Page>)>()
+                    .response::<Vec<String>>(200, "Comments"),
+                Operation::delete().path(":id").status(204, "Deleted"),
+            ]
+        }
+
+        async fn handle(&self, _request: &Request) -> Result<Response, Error> {
+            Ok(Response::new())
+        }
+    }
+
+    struct Page;
+
+    impl Schema for Page {
+        fn schema() -> Json {
+            json!({
+                "type": "object",
+                "properties": { "page": { "type": "integer" }, "sort": { "type": "string" } },
+                "required": ["page"],
+            })
+        }
+    }
+
+    #[test]
+    fn test_openapi_path() {
+        assert_eq!(
+            openapi_path("/posts/:post_id/files/*path/"),
+            (
+                "/posts/{post_id}/files/{path}".to_string(),
+                vec!["post_id".to_string(), "path".to_string()]
+            )
+        );
+        assert_eq!(openapi_path("//"), ("/".to_string(), vec![]));
+    }
+
+    #[test]
+    fn test_schema() {
+        assert_eq!(
+            Option::<Vec<i64>>::schema(),
+            json!({ "type": "array", "items": { "type": "integer", "format": "int64" }, "nullable": true })
+        );
+        assert_eq!(
+            HashMap::<String, bool>::schema(),
+            json!({ "type": "object", "additionalProperties": { "type": "boolean" } })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_spec() {
+        let handlers = vec![
+            Handler::route("/posts/:post_id/comments", Comments),
+            Handler::route("/health", crate::controller::HealthController::new()),
+        ];
+        let openapi = OpenApi::new("Blog", "1.0").swagger_ui("/docs");
+        let spec = openapi.spec(&handlers);
+
+        assert_eq!(spec["openapi"], "3.0.3");
+        assert_eq!(spec["info"]["title"], "Blog");
+        assert_eq!(spec["paths"].as_object().unwrap().len(), 2);
+
+        let list = &spec["paths"]["/posts/{post_id}/comments"]["get"];
+        assert_eq!(
+            list["parameters"],
+            json!([
+                { "name": "post_id", "in": "path", "required": true, "schema": { "type": "integer", "format": "int64" } },
+                { "name": "page", "in": "query", "required": true, "schema": { "type": "integer" } },
+                { "name": "sort", "in": "query", "required": false, "schema": { "type": "string" } },
+            ])
+        );
+        assert_eq!(
+            list["responses"]["200"]["content"]["application/json"]["schema"]["type"],
+            "array"
+        );
+
+        let delete = &spec["paths"]["/posts/{post_id}/comments/{id}"]["delete"];
+        assert_eq!(delete["parameters"].as_array().unwrap().len(), 2);
+        assert_eq!(delete["parameters"][1]["schema"]["type"], "string");
+        assert_eq!(delete["responses"]["204"]["description"], "Deleted");
+
+        let handlers = openapi.handlers(&handlers);
+        assert_eq!(handlers.len(), 2);
+
+        let request = Request::read(
+            crate::http::request::test::dummy_ip(),
+            "GET /openapi.json HTTP/1.1\r\n\r\n".as_bytes(),
+        )
+        .await
+        .unwrap();
+        let response = handlers[0].handle(&request).await.unwrap();
+        let mut bytes = vec![];
+        response.send(&mut bytes).await.unwrap();
+        let body = String::from_utf8(bytes).unwrap();
+        let (_, body) = body.split_once("\r\n\r\n").unwrap();
+        assert_eq!(serde_json::from_str::<Json>(body).unwrap(), spec);
+    }
+}
@@ -0,0 +1,231 @@
+//! Pagination of collections, e.g. the list endpoint of a [`ModelController`](crate::controller::ModelController).
+//!
+//! Clients can page through a collection with query parameters, e.g. `?page=2&page_size=50`,
+//! or with the `Range` header:
+//!
+//! ```text
+//! GET /users
+//! Range: items=0-49
+//! ```
+//!
+//! Ranges are zero-based and inclusive. A response to a range request is sent with `206 - Partial Content`
+//! and the `Content-Range` header, which includes the size of the collection:
+//!
+//! ```text
+//! HTTP/1.1 206 Partial Content
+//! Content-Range: items 0-49/1250
+//! ```
+//!
+//! Ranges starting past the end of the collection return `416 - Range Not Satisfiable`.
+use super::{Error, FromRequest, Request, Response};
+
+/// Number of items returned when the client doesn't specify the page size.
+pub const DEFAULT_PAGE_SIZE: i64 = 25;
+
+/// Page of a collection requested by the client.
+///
+/// # Example
+///
+/// ```
+/// use rwf::prelude::*;
+/// use rwf::http::pagination::Pagination;
+///
+/// #[derive(Default)]
+/// struct Users;
+///
+/// #[async_trait]
+/// impl Controller for Users {
+///     async fn handle(&self, request: &Request) -> Result<Response, Error> {
+///         let pagination = request.extract::<Pagination>()?;
+///         let users = (0..1000)
+///             .skip(pagination.offset() as usize)
+///             .take(pagination.limit() as usize)
+///             .collect::<Vec<_>>();
+///         let count = users.len();
+///
+///         Ok(pagination.response(Response::new().json(users)?, count, Some(1000)))
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pagination {
+    offset: i64,
+    limit: i64,
+    range: bool,
+}
+
+impl Pagination {
+    /// Get the page requested with the `Range` header or the `page` and `page_size`
+    /// query parameters. The `Range` header takes precedence.
+    pub fn new(request: &Request, page_size: i64) -> Self {
+        if let Some(range) = request.header("range").and_then(|range| parse_range(range)) {
+            return range;
+        }
+
+        let page_size = request
+            .query()
+            .get::<i64>("page_size")
+            .filter(|page_size| *page_size > 0)
+            .unwrap_or(page_size);
+        let page = request.query().get::<i64>("page").unwrap_or(1);
+
+        Self {
+            offset: (std::cmp::max(1, page) - 1) * page_size,
+            limit: page_size,
+            range: false,
+        }
+    }
+
+    /// Number of items to skip.
+    pub fn offset(&self) -> i64 {
+        self.offset
+    }
+
+    /// Maximum number of items to return.
+    pub fn limit(&self) -> i64 {
+        self.limit
+    }
+
+    /// The page was requested with the `Range` header.
+    pub fn range(&self) -> bool {
+        self.range
+    }
+
+    /// Set the pagination headers on the response containing `count` items. If the page was requested
+    /// with the `Range` header, the response is sent with `206 - Partial Content`, or `416 - Range Not Satisfiable`
+    /// if the range starts past the end of the collection. The total size of the collection is included if known.
+    pub fn response(&self, response: Response, count: usize, total: Option<i64>) -> Response {
+        let response = response.header("accept-ranges", "items");
+
+        if !self.range {
+            return response;
+        }
+
+        let total = total
+            .map(|total| total.to_string())
+            .unwrap_or_else(|| "*".to_string());
+
+        if count == 0 {
+            return match self.offset {
+                0 => response.header("content-range", format!("items */{}", total)),
+                _ => Response::new()
+                    .header("accept-ranges", "items")
+                    .header("content-range", format!("items */{}", total))
+                    .code(416),
+            };
+        }
+
+        response
+            .header(
+                "content-range",
+                format!(
+                    "items {}-{}/{}",
+                    self.offset,
+                    self.offset + count as i64 - 1,
+                    total
+                ),
+            )
+            .code(206)
+    }
+}
+
+impl FromRequest for Pagination {
+    fn from_request(request: &Request) -> Result<Self, Error> {
+        Ok(Self::new(request, DEFAULT_PAGE_SIZE))
+    }
+}
+
+/// Parse the `Range` header, e.g. `items=0-49`. Open-ended ranges, e.g. `items=50-`, return
+/// the default number of items. Anything else is ignored, like other units or multiple ranges.
+fn parse_range(header: &str) -> Option<Pagination> {
+    let range = header.trim().strip_prefix("items=")?;
+
+    if range.contains(',') {
+        return None;
+    }
+
+    let (start, end) = range.split_once('-')?;
+    let start = start
+        .trim()
+        .parse::<i64>()
+        .ok()
+        .filter(|start| *start >= 0)?;
+    let end = match end.trim() {
+        "" => start + DEFAULT_PAGE_SIZE - 1,
+        end => end.parse::<i64>().ok()?,
+    };
+
+    if start > end {
+        return None;
+    }
+
+    Some(Pagination {
+        offset: start,
+        limit: end - start + 1,
+        range: true,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::test::dummy_ip;
+
+    async fn request(query: &str, range: Option<&str>) -> Request {
+        let range = range
+            .map(|range| format!("Range: {}\r\n", range))
+            .unwrap_or_default();
+        let request = format!("GET /users{} HTTP/1.1\r\n{}\r\n", query, range);
+        Request::read(dummy_ip(), request.as_bytes()).await.unwrap()
+    }
+
+    #[test]
+    fn test_parse_range() {
+        let range = parse_range("items=0-49").unwrap();
+        assert_eq!(
+            (range.offset(), range.limit(), range.range()),
+            (0, 50, true)
+        );
+        let range = parse_range("items=100-").unwrap();
+        assert_eq!((range.offset(), range.limit()), (100, DEFAULT_PAGE_SIZE));
+        assert!(parse_range("bytes=0-49").is_none());
+        assert!(parse_range("items=10-5").is_none());
+        assert!(parse_range("items=-5").is_none());
+        assert!(parse_range("items=0-4,10-14").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pagination() {
+        let pagination = request("?page=3&page_size=10", None)
+            .await
+            .extract::<Pagination>()
+            .unwrap();
+        assert_eq!((pagination.offset(), pagination.limit()), (20, 10));
+        assert!(!pagination.range());
+
+        let response = pagination.response(Response::new(), 10, Some(100));
+        assert_eq!(response.status().code(), 200);
+        assert!(response.headers().get("content-range").is_none());
+
+        // The header takes precedence.
+        let pagination = request("?page=3", Some("items=10-19"))
+            .await
+            .extract::<Pagination>()
+            .unwrap();
+        assert_eq!((pagination.offset(), pagination.limit()), (10, 10));
+
+        let response = pagination.response(Response::new(), 5, Some(15));
+        assert_eq!(response.status().code(), 206);
+        assert_eq!(
+            response.headers().get("content-range").unwrap(),
+            "items 10-14/15"
+        );
+
+        let response = pagination.response(Response::new(), 0, Some(5));
+        assert_eq!(response.status().code(), 416);
+        assert_eq!(
+            response.headers().get("content-range").unwrap(),
+            "items */5"
+        );
+    }
+}
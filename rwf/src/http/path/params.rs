@@ -40,6 +40,13 @@ impl Params {
         None
     }
 
+    /// Names of the parameters, in the order they appear in the path.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = self.params.iter().collect::<Vec<_>>();
+        names.sort_by_key(|(_, index)| **index);
+        names.into_iter().map(|(name, _)| name.as_str()).collect()
+    }
+
     /// Get the regex that parsed the parameters.
     pub fn regex(&self) -> &Regex {
         &self.regex
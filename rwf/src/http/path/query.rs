@@ -3,7 +3,10 @@ use std::collections::btree_map::{BTreeMap, IntoIter};
 use std::ops::{Deref, DerefMut};
 use std::str::FromStr;
 
+use crate::config::get_config;
 use crate::http::Error;
+
+static FILTERED: &str = "[FILTERED]";
 use crate::http::{urldecode, urlencode};
 
 /// GET request query.
@@ -16,6 +19,8 @@ use crate::http::{urldecode, urlencode};
 #[derive(Debug, Clone)]
 pub struct Query {
     query: BTreeMap<String, String>,
+    // All values of parameters set more than once, e.g. by a multi-select.
+    repeated: BTreeMap<String, Vec<String>>,
 }
 
 impl Query {
@@ -23,6 +28,7 @@ impl Query {
     pub fn new() -> Self {
         Self {
             query: BTreeMap::new(),
+            repeated: BTreeMap::new(),
         }
     }
 
@@ -51,7 +57,13 @@ impl Query {
             let key = urldecode(&key_value.next().expect("path query key"));
             let value = urldecode(&key_value.next().unwrap_or(&"")); // ?key=&value=two
 
-            query.insert(key, value);
+            if let Some(previous) = query.insert(key.clone(), value.clone()) {
+                query
+                    .repeated
+                    .entry(key)
+                    .or_insert_with(|| vec![previous])
+                    .push(value);
+            }
         }
 
         query
@@ -81,6 +93,28 @@ impl Query {
         }
     }
 
+    /// Get all values of a parameter set more than once, e.g. `tag=rust&tag=web`,
+    /// in the order they were sent. For other parameters, the one value is returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rwf::http::Query;
+    /// let query = Query::parse("tag=rust&tag=web&page=1");
+    /// assert_eq!(query.get_all("tag"), vec!["rust", "web"]);
+    /// assert_eq!(query.get_all("page"), vec!["1"]);
+    /// assert!(query.get_all("sort").is_empty());
+    /// ```
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        match self.query.get(name) {
+            Some(value) => match self.repeated.get(name) {
+                Some(values) => values.iter().map(|value| value.as_str()).collect(),
+                None => vec![value.as_str()],
+            },
+            None => vec![],
+        }
+    }
+
     /// Get a query parameter value. If it's not set, return an error.
     /// When used with the `?` operator, the controller will automatically
     /// return `400 - Bad Request`.
@@ -109,6 +143,36 @@ impl Query {
         serde_json::to_value(&self.query).unwrap_or(serde_json::Value::default())
     }
 
+    /// Copy of the query with values of sensitive parameters, e.g. passwords and tokens,
+    /// replaced with `[FILTERED]`. Parameters are filtered using the `filter_parameters` setting.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rwf::http::Query;
+    /// let query = Query::parse("page=2&api_token=secret").scrub();
+    /// assert_eq!(query.to_string(), "api_token=%5BFILTERED%5D&page=2");
+    /// ```
+    pub fn scrub(&self) -> Self {
+        let config = &get_config().general;
+        let query = self
+            .query
+            .iter()
+            .map(|(name, value)| {
+                if config.filter_parameter(name) {
+                    (name.clone(), FILTERED.to_string())
+                } else {
+                    (name.clone(), value.clone())
+                }
+            })
+            .collect();
+
+        Self {
+            query,
+            repeated: BTreeMap::new(),
+        }
+    }
+
     /// An owning iterator over the query.
     ///
     /// # Example
@@ -10,17 +10,40 @@ pub trait ToParameter: Sync + Send + Debug {
         Self: Sized;
 }
 
-impl ToParameter for i64 {
-    fn to_parameter(s: &str) -> Result<i64, Error> {
-        match s.parse() {
-            Ok(id) => Ok(id),
-            Err(_) => Err(Error::MalformedRequest("i64")),
-        }
-    }
+macro_rules! parse_parameter {
+    ($($ty:ty),*) => {
+        $(
+            impl ToParameter for $ty {
+                fn to_parameter(s: &str) -> Result<$ty, Error> {
+                    match s.parse() {
+                        Ok(value) => Ok(value),
+                        Err(_) => Err(Error::MalformedRequest(stringify!($ty))),
+                    }
+                }
+            }
+        )*
+    };
 }
 
+parse_parameter!(i64, i32, u64, u32, usize, f64, f32, bool, uuid::Uuid);
+
 impl ToParameter for String {
     fn to_parameter(s: &str) -> Result<String, Error> {
         Ok(s.to_string())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_to_parameter() {
+        assert_eq!(i64::to_parameter("-5").unwrap(), -5);
+        assert_eq!(u32::to_parameter("5").unwrap(), 5);
+        assert!(bool::to_parameter("true").unwrap());
+        assert!(u64::to_parameter("-5").is_err());
+        assert!(i64::to_parameter("apple").is_err());
+        assert!(uuid::Uuid::to_parameter("5").is_err());
+    }
+}
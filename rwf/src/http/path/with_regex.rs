@@ -3,9 +3,11 @@
 //! 1. Route requests to a controller
 //! 2. Extract parameters from the URL
 //!
-//! Parameters are denoted by the column-name notation, e.g. `:param1`.
+//! Parameters are denoted by the column-name notation, e.g. `:param1`. A parameter
+//! starting with a star, e.g. `*path`, captures the rest of the URL, including slashes.
 
 use super::{Error, Params, Path};
+use crate::http::Format;
 use regex::Regex;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -33,6 +35,16 @@ pub enum PathType {
 impl PathWithRegex {
     /// Create the path-specified regex.
     pub(crate) fn new(path: Path, path_type: PathType) -> Result<Self, Error> {
+        Self::with_formats(path, path_type, &[])
+    }
+
+    /// Create the path-specified regex, matching the path with the extension of one of the formats too,
+    /// e.g. `/report.csv`. The extension is captured by the `format` parameter.
+    pub(crate) fn with_formats(
+        path: Path,
+        path_type: PathType,
+        formats: &[Format],
+    ) -> Result<Self, Error> {
         let mut params = HashMap::new();
         // Parameter regex groups start at 1 since the first group
         // is the path base URL.
@@ -45,6 +57,11 @@ impl PathWithRegex {
                 params.insert(part[1..].to_owned(), i);
                 i += 1;
                 "([a-zA-Z0-9_-]+)"
+            } else if let Some(name) = part.strip_prefix("*") {
+                // Wildcard parameter, matches one or more path segments.
+                params.insert(name.to_owned(), i);
+                i += 1;
+                "(.+?)"
             } else {
                 // Match the URL part as-is.
                 part
@@ -69,12 +86,30 @@ impl PathWithRegex {
 
             +
 
+            // Optional format extension, e.g. `.csv`
+            &if formats.is_empty() {
+                String::new()
+            } else {
+                let extensions = formats
+                    .iter()
+                    .map(|format| regex::escape(format.extension()))
+                    .collect::<Vec<_>>();
+                format!(r#"(?:\.({}))?"#, extensions.join("|"))
+            }
+
+            +
+
             // Last slash is optional
             if path.base().ends_with("/") { "$" } else { r#"\/?$"# };
 
         // :id parameter
         if path_type == PathType::Rest {
             params.insert("id".to_string(), i);
+            i += 1;
+        }
+
+        if !formats.is_empty() {
+            params.insert("format".to_string(), i);
         }
 
         let regex = Regex::new(&regex)?;
@@ -148,4 +183,38 @@ mod test {
         let name = params.parameter(url, "name");
         assert_eq!(name, Some("hello-world"));
     }
+
+    #[test]
+    fn test_nested_parameters() {
+        let path = Path::parse("/api/users/:user_id/orders/:id").unwrap();
+        let with_regex = PathWithRegex::route(path).unwrap();
+        let params = with_regex.params();
+
+        let url = "/api/users/15/orders/2";
+        assert!(with_regex.regex().is_match(url));
+        assert_eq!(params.parameter(url, "user_id"), Some("15"));
+        assert_eq!(params.parameter(url, "id"), Some("2"));
+        assert!(!with_regex.regex().is_match("/api/users/15/orders"));
+    }
+
+    #[test]
+    fn test_wildcard_parameter() {
+        let path = Path::parse("/files/:bucket/*path").unwrap();
+        let with_regex = PathWithRegex::route(path).unwrap();
+        assert_eq!(
+            with_regex.regex().as_str(),
+            r#"^\/files\/([a-zA-Z0-9_-]+)\/(.+?)\/?$"#
+        );
+        let params = with_regex.params();
+
+        let url = "/files/images/2024/10/cat.png";
+        assert_eq!(params.parameter(url, "bucket"), Some("images"));
+        assert_eq!(params.parameter(url, "path"), Some("2024/10/cat.png"));
+        assert_eq!(
+            params.parameter("/files/images/2024/", "path"),
+            Some("2024")
+        );
+        assert!(!with_regex.regex().is_match("/files/images"));
+        assert!(!with_regex.regex().is_match("/files/images/"));
+    }
 }
@@ -0,0 +1,397 @@
+//! Response post-processors, which rewrite outgoing HTML.
+//!
+//! Post-processors run on every HTML response after the controller and middleware, and before the response
+//! is compressed. They can change the response, e.g. add headers, and add handlers to an
+//! [`HtmlRewriter`] to change the elements of the page as it's sent to the client. Rwf comes with
+//! a few you can use:
+//!
+//! - [`CspNonce`] adds a random nonce to scripts and styles, and sends it in the `Content-Security-Policy` header
+//! - [`LazyImages`] tells the browser to load images only when they are about to be displayed
+//! - [`CdnAssets`] serves static assets from a CDN
+//!
+//! # Example
+//!
+//! ```
+//! use rwf::config::Environment;
+//! use rwf::http::post_process::{self, CdnAssets, CspNonce, LazyImages};
+//!
+//! post_process::register(CspNonce::new());
+//! post_process::register(LazyImages::new());
+//! post_process::register_in(Environment::Production, CdnAssets::new("https://cdn.example.com"));
+//! ```
+use std::sync::Arc;
+
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+
+use super::{rewriter::HtmlRewriter, Error, Request, Response};
+use crate::config::{get_config, Environment};
+use crate::crypto::random_string;
+
+static POST_PROCESSORS: Lazy<RwLock<PostProcessors>> =
+    Lazy::new(|| RwLock::new(PostProcessors::new()));
+
+/// Changes HTML responses before they are sent to the client.
+pub trait PostProcessor: Send + Sync {
+    /// Change the response, and add handlers to the rewriter to change its HTML. The body
+    /// is rewritten once all post-processors ran.
+    fn process(
+        &self,
+        request: &Request,
+        response: Response,
+        rewriter: &mut HtmlRewriter,
+    ) -> Result<Response, Error>;
+
+    /// Name of the post-processor.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// Post-processors applied to responses, in the order they were added.
+#[derive(Clone, Default)]
+pub struct PostProcessors {
+    processors: Vec<Arc<dyn PostProcessor>>,
+}
+
+impl PostProcessors {
+    /// No post-processors.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a post-processor.
+    pub fn add(&mut self, processor: impl PostProcessor + 'static) -> &mut Self {
+        self.processors.push(Arc::new(processor));
+        self
+    }
+
+    /// Names of the post-processors, in the order they run.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.processors
+            .iter()
+            .map(|processor| processor.name())
+            .collect()
+    }
+
+    /// Run the post-processors on the response, if it's HTML.
+    pub fn process(&self, request: &Request, mut response: Response) -> Result<Response, Error> {
+        let html = response
+            .headers()
+            .get("content-type")
+            .map(|content_type| content_type.starts_with("text/html"))
+            .unwrap_or(false);
+
+        if self.processors.is_empty() || !html {
+            return Ok(response);
+        }
+
+        let mut rewriter = HtmlRewriter::new();
+
+        for processor in &self.processors {
+            response = processor.process(request, response, &mut rewriter)?;
+        }
+
+        Ok(response.rewrite(rewriter))
+    }
+}
+
+/// Run the post-processor on all HTML responses.
+pub fn register(processor: impl PostProcessor + 'static) {
+    POST_PROCESSORS.write().add(processor);
+}
+
+/// Run the post-processor on all HTML responses, if the app is running in this environment,
+/// e.g. serve assets from a CDN only in production.
+pub fn register_in(environment: Environment, processor: impl PostProcessor + 'static) {
+    if get_config().environment == environment {
+        register(processor);
+    }
+}
+
+/// Run the registered post-processors on the response.
+pub fn process(request: &Request, response: Response) -> Result<Response, Error> {
+    let processors = POST_PROCESSORS.read().clone();
+    processors.process(request, response)
+}
+
+/// Add a random nonce to `<script>` and `<style>` elements, and allow only elements with this nonce
+/// to run using the `Content-Security-Policy` header. Scripts injected into the page, e.g. with
+/// an XSS attack, don't have the nonce, so the browser won't run them.
+///
+/// The header isn't changed if the controller already set it.
+#[derive(Debug, Clone)]
+pub struct CspNonce {
+    policy: String,
+}
+
+impl Default for CspNonce {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CspNonce {
+    /// Allow scripts and styles with the nonce, and scripts they load.
+    pub fn new() -> Self {
+        Self::policy(
+            "script-src 'nonce-{nonce}' 'strict-dynamic'; style-src 'self' 'nonce-{nonce}'; object-src 'none'; base-uri 'self'",
+        )
+    }
+
+    /// Use a different policy. `{nonce}` is replaced with the nonce.
+    pub fn policy(policy: impl ToString) -> Self {
+        Self {
+            policy: policy.to_string(),
+        }
+    }
+}
+
+impl PostProcessor for CspNonce {
+    fn process(
+        &self,
+        _request: &Request,
+        response: Response,
+        rewriter: &mut HtmlRewriter,
+    ) -> Result<Response, Error> {
+        if response.headers().get("content-security-policy").is_some() {
+            return Ok(response);
+        }
+
+        let nonce = random_string(22);
+
+        for tag in ["script", "style"] {
+            let nonce = nonce.clone();
+            rewriter.on(tag, move |element| {
+                if !element.has_attribute("nonce") {
+                    element.set_attribute("nonce", &nonce);
+                }
+            });
+        }
+
+        Ok(response.header(
+            "content-security-policy",
+            self.policy.replace("{nonce}", &nonce),
+        ))
+    }
+}
+
+/// Add `loading="lazy"` to images and iframes, so the browser loads them only when they are about
+/// to be displayed. Elements which set `loading` already are left unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct LazyImages {
+    skip: usize,
+}
+
+impl LazyImages {
+    /// Load all images lazily.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the first images on the page right away, since they are likely displayed
+    /// when the page opens, e.g. a logo.
+    pub fn skip(mut self, images: usize) -> Self {
+        self.skip = images;
+        self
+    }
+}
+
+impl PostProcessor for LazyImages {
+    fn process(
+        &self,
+        _request: &Request,
+        response: Response,
+        rewriter: &mut HtmlRewriter,
+    ) -> Result<Response, Error> {
+        let seen = Arc::new(Mutex::new(0));
+
+        for tag in ["img", "iframe"] {
+            let (seen, skip) = (seen.clone(), self.skip);
+
+            rewriter.on(tag, move |element| {
+                let mut seen = seen.lock();
+                *seen += 1;
+
+                if *seen > skip && !element.has_attribute("loading") {
+                    element.set_attribute("loading", "lazy");
+                }
+            });
+        }
+
+        Ok(response)
+    }
+}
+
+/// Attributes which contain asset URLs.
+const ASSET_ATTRIBUTES: &[(&str, &str)] = &[
+    ("img", "src"),
+    ("img", "srcset"),
+    ("script", "src"),
+    ("link", "href"),
+    ("source", "src"),
+    ("source", "srcset"),
+    ("video", "src"),
+    ("video", "poster"),
+    ("audio", "src"),
+];
+
+/// Serve static assets from a CDN, by changing their URLs to point to it,
+/// e.g. `/static/app.js` to `https://cdn.example.com/static/app.js`.
+#[derive(Debug, Clone)]
+pub struct CdnAssets {
+    host: String,
+    prefixes: Vec<String>,
+}
+
+impl CdnAssets {
+    /// Serve assets under `/static/` from the CDN host, e.g. `https://cdn.example.com`.
+    pub fn new(host: impl ToString) -> Self {
+        Self {
+            host: host.to_string().trim_end_matches('/').to_string(),
+            prefixes: vec!["/static/".into()],
+        }
+    }
+
+    /// Serve assets under this path too, e.g. `/assets/`.
+    pub fn prefix(mut self, prefix: impl ToString) -> Self {
+        self.prefixes.push(prefix.to_string());
+        self
+    }
+
+    fn url(&self, url: &str) -> Option<String> {
+        self.prefixes
+            .iter()
+            .any(|prefix| url.starts_with(prefix.as_str()))
+            .then(|| format!("{}{}", self.host, url))
+    }
+
+    /// Rewrite each URL in a `srcset`, e.g. `/static/cat.png 1x, /static/cat@2x.png 2x`.
+    fn srcset(&self, srcset: &str) -> String {
+        srcset
+            .split(',')
+            .map(|candidate| {
+                let candidate = candidate.trim();
+                let (url, descriptor) = candidate.split_once(' ').unwrap_or((candidate, ""));
+                let url = self.url(url).unwrap_or_else(|| url.to_string());
+
+                if descriptor.is_empty() {
+                    url
+                } else {
+                    format!("{} {}", url, descriptor.trim())
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl PostProcessor for CdnAssets {
+    fn process(
+        &self,
+        _request: &Request,
+        response: Response,
+        rewriter: &mut HtmlRewriter,
+    ) -> Result<Response, Error> {
+        for (tag, attribute) in ASSET_ATTRIBUTES {
+            let cdn = self.clone();
+
+            rewriter.on(tag, move |element| {
+                let value = match element.attribute(attribute) {
+                    Some(value) => value,
+                    None => return,
+                };
+
+                let rewritten = if *attribute == "srcset" {
+                    Some(cdn.srcset(&value)).filter(|srcset| *srcset != value)
+                } else {
+                    cdn.url(&value)
+                };
+
+                if let Some(rewritten) = rewritten {
+                    element.set_attribute(attribute, rewritten);
+                }
+            });
+        }
+
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::test::dummy_ip;
+    use crate::tokio_stream;
+
+    async fn request() -> Request {
+        Request::read(
+            dummy_ip(),
+            "GET / HTTP/1.1\r\nContent-Length: 0\r\n\r\n".as_bytes(),
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn body(response: Response) -> String {
+        let mut bytes = vec![];
+        response.send(&mut bytes).await.unwrap();
+        let response = String::from_utf8(bytes).unwrap();
+        response.split_once("\r\n\r\n").unwrap().1.to_string()
+    }
+
+    #[tokio::test]
+    async fn test_post_process() {
+        let mut processors = PostProcessors::new();
+        processors
+            .add(CspNonce::new())
+            .add(LazyImages::new().skip(1))
+            .add(CdnAssets::new("https://cdn.example.com/"));
+
+        let html = r#"<link rel="stylesheet" href="/static/app.css"><img src="/static/logo.png"><script src="/static/app.js"></script><img src="https://example.com/cat.png" srcset="/static/cat.png 1x, /static/cat@2x.png 2x"><script nonce="mine">let a = "<img src='/static/x.png'>";</script>"#;
+        let response = processors
+            .process(&request().await, Response::new().html(html))
+            .unwrap();
+
+        let policy = response
+            .headers()
+            .get("content-security-policy")
+            .unwrap()
+            .clone();
+        let nonce = policy
+            .split("'nonce-")
+            .nth(1)
+            .and_then(|nonce| nonce.split('\'').next())
+            .unwrap()
+            .to_string();
+        assert_eq!(nonce.len(), 22);
+
+        assert_eq!(
+            body(response).await,
+            format!(
+                r#"<link rel="stylesheet" href="https://cdn.example.com/static/app.css"><img src="https://cdn.example.com/static/logo.png"><script src="https://cdn.example.com/static/app.js" nonce="{nonce}"></script><img src="https://example.com/cat.png" srcset="https://cdn.example.com/static/cat.png 1x, https://cdn.example.com/static/cat@2x.png 2x" loading="lazy"><script nonce="mine">let a = "<img src='/static/x.png'>";</script>"#
+            )
+        );
+
+        // Not HTML.
+        let response = processors
+            .process(
+                &request().await,
+                Response::new().text("<img src=/static/a.png>"),
+            )
+            .unwrap();
+        assert!(response.headers().get("content-security-policy").is_none());
+        assert_eq!(body(response).await, "<img src=/static/a.png>");
+
+        // Streamed.
+        let chunks = ["<p><im", "g src=\"/static/a.png\"></p>"];
+        let response = Response::new()
+            .stream(tokio_stream::iter(chunks))
+            .header("content-type", "text/html");
+        let response = processors.process(&request().await, response).unwrap();
+        let body = body(response).await;
+        assert!(body.starts_with("3\r\n<p>\r\n"));
+        assert!(body.contains("<img src=\"https://cdn.example.com/static/a.png\"></p>\r\n"));
+        assert!(body.ends_with("\r\n0\r\n\r\n"));
+    }
+}
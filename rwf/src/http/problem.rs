@@ -0,0 +1,357 @@
+//! Machine-readable error responses for JSON APIs.
+//!
+//! Browsers get HTML error pages, but API clients need errors they can parse. When a request is
+//! an API request, errors returned by controllers and error responses generated by Rwf, e.g. `404 - Not Found`,
+//! are sent as JSON instead. By default, they follow [RFC 9457](https://www.rfc-editor.org/rfc/rfc9457)
+//! (`application/problem+json`):
+//!
+//! ```json
+//! {
+//!   "type": "about:blank",
+//!   "title": "Unprocessable Entity",
+//!   "status": 422,
+//!   "detail": "validation failed",
+//!   "instance": "/api/users",
+//!   "request_id": "b3b0a4f4-...",
+//!   "errors": {
+//!     "email": ["is invalid"]
+//!   }
+//! }
+//! ```
+//!
+//! A request is an API request if it accepts JSON but not HTML, was authenticated with a bearer token,
+//! or its path starts with the `prefix` set in the `[api]` section of `rwf.toml`. The format is configured there too.
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::{json, Value as Json};
+
+use super::{Error, Request, Response};
+use crate::config::{get_config, ErrorFormat};
+use crate::controller::Error as ControllerError;
+
+/// Validation errors, by field name. Return them from a controller with `?` to send
+/// `422 - Unprocessable Entity` with the errors to the client.
+///
+/// # Example
+///
+/// ```
+/// use rwf::http::problem::ValidationErrors;
+///
+/// let mut errors = ValidationErrors::new();
+/// errors.add("email", "is invalid");
+///
+/// assert!(errors.into_result().is_err());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ValidationErrors {
+    fields: BTreeMap<String, Vec<String>>,
+}
+
+impl ValidationErrors {
+    /// No errors.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an error to the field.
+    pub fn add(&mut self, field: impl ToString, message: impl ToString) -> &mut Self {
+        self.fields
+            .entry(field.to_string())
+            .or_default()
+            .push(message.to_string());
+        self
+    }
+
+    /// There are no errors.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Errors of this field.
+    pub fn get(&self, field: &str) -> &[String] {
+        self.fields.get(field).map(|f| f.as_slice()).unwrap_or(&[])
+    }
+
+    /// All errors, by field name.
+    pub fn fields(&self) -> &BTreeMap<String, Vec<String>> {
+        &self.fields
+    }
+
+    /// Return an error if there are any validation errors.
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+impl std::fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let errors = self
+            .fields
+            .iter()
+            .flat_map(|(field, messages)| {
+                messages
+                    .iter()
+                    .map(move |message| format!("{} {}", field, message))
+            })
+            .collect::<Vec<_>>();
+
+        write!(f, "validation failed: {}", errors.join(", "))
+    }
+}
+
+/// Error sent to an API client.
+#[derive(Debug, Clone)]
+pub struct Problem {
+    status: u16,
+    detail: Option<String>,
+    errors: Option<ValidationErrors>,
+}
+
+impl Problem {
+    /// Error with this HTTP status code.
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            detail: None,
+            errors: None,
+        }
+    }
+
+    /// Explain what went wrong.
+    pub fn detail(mut self, detail: impl ToString) -> Self {
+        self.detail = Some(detail.to_string());
+        self
+    }
+
+    /// Add validation errors.
+    pub fn errors(mut self, errors: ValidationErrors) -> Self {
+        self.errors = Some(errors);
+        self
+    }
+
+    /// Error returned by a controller. Details of internal errors are only
+    /// shown in development (debug) builds.
+    pub fn from_error(err: &ControllerError) -> Self {
+        match err {
+            ControllerError::HttpError(err) => match err.as_ref() {
+                Error::Validation(errors) => Self::new(422)
+                    .detail("validation failed")
+                    .errors(errors.clone()),
+                err if err.code() < 500 => Self::new(err.code()).detail(err),
+                err => Self::internal(err),
+            },
+            err => Self::internal(err),
+        }
+    }
+
+    fn internal(err: &impl std::fmt::Display) -> Self {
+        let problem = Self::new(500);
+
+        #[cfg(debug_assertions)]
+        let problem = problem.detail(err);
+
+        #[cfg(not(debug_assertions))]
+        let _ = err;
+
+        problem
+    }
+
+    /// HTTP status code.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Create the response in the configured format.
+    pub fn response(&self, request: &Request) -> Response {
+        let (body, content_type) = self.body(request);
+
+        Response::new()
+            .body(body)
+            .header("content-type", content_type)
+            .code(self.status)
+    }
+
+    fn body(&self, request: &Request) -> (Vec<u8>, &'static str) {
+        let config = &get_config().api;
+
+        let mut problem = json!({
+            "title": title(self.status),
+            "status": self.status,
+        });
+        if let Some(ref detail) = self.detail {
+            problem["detail"] = detail.as_str().into();
+        }
+        if let Some(ref errors) = self.errors {
+            problem["errors"] = json!(errors.fields());
+        }
+        problem["request_id"] = request.id().into();
+
+        let (body, content_type) = match config.error_format {
+            ErrorFormat::Envelope => {
+                let mut envelope = Json::Object(Default::default());
+                envelope[&config.envelope_key] = problem;
+                (envelope, "application/json")
+            }
+            _ => {
+                problem["type"] = "about:blank".into();
+                problem["instance"] = request.path().path().into();
+                (problem, "application/problem+json")
+            }
+        };
+
+        (serde_json::to_vec(&body).expect("json"), content_type)
+    }
+}
+
+/// The request is made by an API client, so errors should be sent as JSON.
+pub fn api_request(request: &Request) -> bool {
+    let config = &get_config().api;
+
+    if config.error_format == ErrorFormat::Html {
+        return false;
+    }
+
+    if let Some(ref prefix) = config.prefix {
+        if request.path().path().starts_with(prefix.as_str()) {
+            return true;
+        }
+    }
+
+    if request.token_claims().is_some() {
+        return true;
+    }
+
+    match request.header("accept") {
+        Some(accept) => {
+            let accept = accept.to_lowercase();
+            accept.contains("json") && !accept.contains("text/html")
+        }
+        None => false,
+    }
+}
+
+/// Replace HTML error pages with JSON errors for API requests. *This is used internally automatically.*
+pub(crate) fn api_error(response: Response, request: &Request) -> Response {
+    let code = response.status().code();
+    let html = response
+        .headers()
+        .get("content-type")
+        .map(|content_type| content_type.starts_with("text/html"))
+        .unwrap_or(false);
+
+    if code < 400 || !html || !api_request(request) {
+        return response;
+    }
+
+    // Keep the other headers, e.g. `WWW-Authenticate` or `Retry-After`.
+    let (body, content_type) = Problem::new(code).body(request);
+    response
+        .body(body)
+        .header("content-type", content_type)
+        .code(code)
+}
+
+/// Reason phrase of the HTTP status code.
+fn title(status: u16) -> &'static str {
+    http::StatusCode::from_u16(status)
+        .ok()
+        .and_then(|status| status.canonical_reason())
+        .unwrap_or("Error")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::controller::Controller;
+    use crate::http::request::test::dummy_ip;
+    use async_trait::async_trait;
+
+    struct Signup;
+
+    #[async_trait]
+    impl Controller for Signup {
+        async fn handle(&self, _request: &Request) -> Result<Response, ControllerError> {
+            let mut errors = ValidationErrors::new();
+            errors.add("email", "is invalid").add("email", "is taken");
+            errors.into_result()?;
+
+            Ok(Response::new())
+        }
+    }
+
+    async fn request(accept: &str) -> Request {
+        let request = format!(
+            "GET /signup HTTP/1.1\r\nAccept: {}\r\nX-Request-Id: abc\r\n\r\n",
+            accept
+        );
+        Request::read(dummy_ip(), request.as_bytes()).await.unwrap()
+    }
+
+    async fn body(response: Response) -> Json {
+        let mut bytes = vec![];
+        response.send(&mut bytes).await.unwrap();
+        let bytes = String::from_utf8(bytes).unwrap();
+        serde_json::from_str(bytes.split("\r\n\r\n").nth(1).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_validation_errors() {
+        let response = Signup
+            .handle_internal(request("application/json").await)
+            .await
+            .unwrap();
+        assert_eq!(response.status().code(), 422);
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "application/problem+json"
+        );
+
+        let body = body(response).await;
+        assert_eq!(body["title"], "Unprocessable Entity");
+        assert_eq!(body["instance"], "/signup");
+        assert_eq!(body["request_id"], "abc");
+        assert_eq!(body["errors"]["email"], json!(["is invalid", "is taken"]));
+
+        // Browsers get an HTML page.
+        let response = Signup
+            .handle_internal(request("text/html,application/json").await)
+            .await
+            .unwrap();
+        assert_eq!(response.status().code(), 422);
+        assert!(response
+            .headers()
+            .get("content-type")
+            .unwrap()
+            .starts_with("text/html"));
+    }
+
+    #[tokio::test]
+    async fn test_api_error() {
+        let request = request("application/json").await;
+        let response = api_error(Response::unauthorized(Some("Bearer")), &request);
+
+        assert_eq!(response.status().code(), 401);
+        assert_eq!(
+            response.headers().get("www-authenticate").unwrap(),
+            "Bearer"
+        );
+        assert_eq!(body(response).await["title"], "Unauthorized");
+
+        // Responses set by the controller are kept.
+        let response = Response::new()
+            .json(json!({"ok": false}))
+            .unwrap()
+            .code(400);
+        assert_eq!(
+            body(api_error(response, &request)).await,
+            json!({"ok": false})
+        );
+    }
+}
@@ -1,20 +1,29 @@
 //! HTTP request.
+use std::io::ErrorKind;
 use std::marker::Unpin;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::ops::Deref;
 use std::sync::Arc;
-use std::{collections::HashMap, fmt::Debug};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+};
 
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::{Deserializer, Value};
 use time::OffsetDateTime;
 use tokio::io::{AsyncRead, AsyncReadExt};
+use tracing::warn;
 
-use super::{Cookies, Error, FormData, FromFormData, Head, Params, Response, ToParameter};
+use super::{
+    compression::gunzip, forwarded, locale::locale_path, trace, upload, upload::ReceivedForm,
+    Cookies, Error, FormData, FormErrors, Format, FromFormData, FromRequest, Geo, Head, MergePatch,
+    MultipartForm, Params, Response, ToParameter, TraceContext,
+};
 use crate::prelude::ToConnectionRequest;
 use crate::{
     config::get_config,
-    controller::{Session, SessionId},
+    controller::{session_store, Session, SessionId},
     model::Model,
     view::ToTemplateValue,
 };
@@ -26,10 +35,14 @@ pub struct Request {
     session: Session,
     inner: Arc<Inner>,
     params: Option<Arc<Params>>,
+    geo: Option<Arc<Geo>>,
+    locale: Option<Arc<String>>,
+    route: Option<Arc<String>>,
     received_at: OffsetDateTime,
     // Don't check for valid CSRF token.
     skip_csrf: bool,
     renew_session: bool,
+    tls: bool,
 }
 
 impl Default for Request {
@@ -39,9 +52,13 @@ impl Default for Request {
             session: Session::default(),
             inner: Arc::new(Inner::default()),
             params: None,
+            geo: None,
+            locale: None,
+            route: None,
             received_at: OffsetDateTime::now_utc(),
             skip_csrf: false,
             renew_session: false,
+            tls: false,
         }
     }
 }
@@ -51,6 +68,14 @@ struct Inner {
     body: Vec<u8>,
     cookies: Cookies,
     peer: SocketAddr,
+    id: String,
+    trace: Option<TraceContext>,
+    // Key of the session in a server-side session store.
+    session_key: Option<String>,
+    // Claims of the bearer token used to authenticate the request.
+    token_claims: Option<serde_json::Value>,
+    // `multipart/form-data` form, parsed while the body was received.
+    form: Option<ReceivedForm>,
 }
 
 impl Default for Inner {
@@ -59,6 +84,11 @@ impl Default for Inner {
             body: Vec::default(),
             cookies: Cookies::default(),
             peer: "127.0.0.1:8000".parse().unwrap(), // Just used for testing.
+            id: String::default(),
+            trace: None,
+            session_key: None,
+            token_claims: None,
+            form: None,
         }
     }
 }
@@ -68,14 +98,23 @@ impl Request {
     ///
     /// #### Implementation note
     ///
-    /// The request is fully received and loaded into memory before it's passed to a controller.
+    /// The request is fully received and loaded into memory before it's passed to a controller,
+    /// except files uploaded with `multipart/form-data` forms, which are written to temporary files
+    /// as they are received. See [`crate::http::upload`].
     /// It's safe to clone since the contents are behind an [`std::sync::Arc`].
     pub async fn read(peer: SocketAddr, mut stream: impl AsyncRead + Unpin) -> Result<Self, Error> {
-        let head = Head::read(&mut stream).await?;
+        let mut head = Head::read(&mut stream).await?;
         let content_length = head.content_length().unwrap_or(0);
 
+        let uploads = &get_config().uploads;
+        let boundary = upload::streamed_boundary(&head);
+        let max_size = match boundary {
+            Some(_) => uploads.max_request_size,
+            None => get_config().general.max_request_size,
+        };
+
         // Handle requests which are too large.
-        if content_length > get_config().general.max_request_size {
+        if content_length > max_size {
             // Throw away whatever we receive.
             let mut throw_away = vec![0u8; 4096];
             let mut content_length = content_length as i64;
@@ -92,15 +131,30 @@ impl Request {
             return Err(Error::ContentTooLarge(head));
         }
 
-        let mut body = vec![0u8; content_length];
-        stream
-            .read_exact(&mut body)
-            .await
-            .map_err(|_| Error::MalformedRequest("incorrect content length"))?;
+        let (body, form) = match boundary {
+            Some(boundary) => {
+                let form =
+                    ReceivedForm::receive(&mut stream, &head, content_length, &boundary, uploads)
+                        .await?;
+                (vec![], Some(form))
+            }
+
+            None => (
+                Self::read_body(&mut head, &mut stream, content_length).await?,
+                None,
+            ),
+        };
 
         let cookies = head.cookies();
+        let id = trace::request_id(&head);
+        let trace = trace::trace_context(&head);
 
-        let (session, renew_session) = match cookies.get_session()? {
+        let (session, session_key) = if session_store::store().cookie() {
+            (cookies.get_session()?, None)
+        } else {
+            Self::load_session(&cookies).await?
+        };
+        let (session, renew_session) = match session {
             Some(session) => (session, false),
             None => (Session::anonymous(), true),
         };
@@ -108,32 +162,152 @@ impl Request {
         Ok(Request {
             head,
             params: None,
+            geo: None,
+            locale: None,
+            route: None,
             session,
             inner: Arc::new(Inner {
+                session_key,
+                token_claims: None,
+                form,
                 body,
                 peer,
                 cookies,
+                id,
+                trace,
             }),
             received_at: OffsetDateTime::now_utc(),
             skip_csrf: false,
             renew_session,
+            tls: false,
         })
     }
 
+    async fn read_body(
+        head: &mut Head,
+        mut stream: impl AsyncRead + Unpin,
+        content_length: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let mut body = vec![0u8; content_length];
+        stream
+            .read_exact(&mut body)
+            .await
+            .map_err(|err| match err.kind() {
+                ErrorKind::UnexpectedEof => Error::MalformedRequest("incorrect content length"),
+                _ => Error::Io(err),
+            })?;
+
+        // Decompress request bodies sent with `Content-Encoding: gzip`.
+        match head.header("content-encoding").map(|e| e.to_lowercase()) {
+            Some(encoding) if encoding == "gzip" || encoding == "x-gzip" => {
+                let body = gunzip(&body, get_config().general.max_request_size)?;
+                head.headers_mut().remove("content-encoding");
+                head.headers_mut()
+                    .insert("content-length", body.len().to_string());
+                Ok(body)
+            }
+            _ => Ok(body),
+        }
+    }
+
     /// Get the request source IP address.
     ///
     /// This is the IP address of the TCP socket, and does
-    /// not have to be the actual client's IP address.
+    /// not have to be the actual client's IP address. See [`Request::client_ip`].
     pub fn peer(&self) -> &SocketAddr {
         &self.inner.peer
     }
 
+    /// IP address of the client. If the request was forwarded by a trusted proxy, e.g. a load balancer,
+    /// it's the address the proxy received the request from, taken from the `Forwarded` or `X-Forwarded-For` header.
+    /// Otherwise, it's the address of the TCP socket.
+    pub fn client_ip(&self) -> IpAddr {
+        forwarded::client_ip(self)
+    }
+
+    /// The client used HTTPS, either to connect to the server, or to the trusted proxy which forwarded
+    /// the request, as set in the `Forwarded` or `X-Forwarded-Proto` header.
+    pub fn secure(&self) -> bool {
+        forwarded::secure(self)
+    }
+
+    /// The request was received over a TLS connection.
+    pub fn tls(&self) -> bool {
+        self.tls
+    }
+
+    /// Set whether the request was received over a TLS connection.
+    pub(crate) fn with_tls(mut self, tls: bool) -> Self {
+        self.tls = tls;
+        self
+    }
+
     /// Set params on the request.
     pub fn with_params(mut self, params: Arc<Params>) -> Self {
         self.params = Some(params);
         self
     }
 
+    /// Set the route which matched the request.
+    pub(crate) fn with_route(mut self, route: &str) -> Self {
+        self.route = Some(Arc::new(route.to_string()));
+        self
+    }
+
+    /// Route which matched the request, e.g. `/users/:id`. `None` if
+    /// the request wasn't routed to a controller.
+    pub fn route(&self) -> Option<&str> {
+        self.route.as_ref().map(|route| route.as_str())
+    }
+
+    /// Set the client location on the request.
+    pub fn with_geo(mut self, geo: Geo) -> Self {
+        self.geo = Some(Arc::new(geo));
+        self
+    }
+
+    /// Client location, looked up from the client's IP address. Requires
+    /// the [`GeoIp`](crate::controller::middleware::geoip::GeoIp) middleware.
+    pub fn geo(&self) -> Option<&Geo> {
+        self.geo.as_deref()
+    }
+
+    /// Set the locale the request is served in.
+    pub fn with_locale(mut self, locale: impl ToString) -> Self {
+        self.locale = Some(Arc::new(locale.to_string()));
+        self
+    }
+
+    /// Locale the request is served in, e.g. `fr`, if it was routed
+    /// through [`Locales`](crate::http::Locales).
+    pub fn locale(&self) -> Option<&str> {
+        self.locale.as_ref().map(|locale| locale.as_str())
+    }
+
+    /// Path in the request locale, e.g. `/about` becomes `/fr/about`. The path
+    /// isn't changed if the request has no locale.
+    pub fn localized(&self, path: &str) -> String {
+        match self.locale() {
+            Some(locale) => locale_path(locale, path),
+            None => path.to_string(),
+        }
+    }
+
+    /// Path of this page in another locale, e.g. for a language switcher. If the request is
+    /// served at `/fr/about`, the `en` path is `/en/about`.
+    pub fn path_in_locale(&self, locale: &str) -> String {
+        let path = self.path().to_string();
+        let path = match self.locale() {
+            Some(current) => match path.strip_prefix(&format!("/{}", current)) {
+                Some(rest) if rest.is_empty() || rest.starts_with(['/', '?']) => rest.to_string(),
+                _ => path,
+            },
+            None => path,
+        };
+
+        locale_path(locale, &path)
+    }
+
     /// Return request head (headers, method, etc.).
     ///
     /// [`crate::http::Head`] is dereferenced from this struct,
@@ -152,23 +326,57 @@ impl Request {
     /// The parameter must be specified
     /// in the path provided to the router at controller registration. The only exception
     /// is the `id` parameter which is automatically configured on REST controllers.
+    ///
+    /// If the parameter can't be converted to the requested type, [`Error::InvalidParameter`]
+    /// is returned, which results in a `400 - Bad Request` response.
     pub fn parameter<T: ToParameter>(&self, name: &str) -> Result<Option<T>, Error> {
         if let Some(ref params) = self.params {
             if let Some(parameter) = params.parameter(self.path().base(), name) {
-                return Ok(Some(T::to_parameter(&parameter)?));
+                return T::to_parameter(parameter)
+                    .map(Some)
+                    .map_err(|_| Error::InvalidParameter(name.to_string()));
             }
         }
 
         Ok(None)
     }
 
+    /// Names of the parameters in the path the request was routed to,
+    /// in the order they appear in the path.
+    pub fn parameter_names(&self) -> Vec<&str> {
+        match self.params {
+            Some(ref params) => params.names(),
+            None => vec![],
+        }
+    }
+
+    /// Extract a typed value from the request, e.g. a path parameter or
+    /// the JSON body. See [`crate::http::extract`] for available extractors.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// use rwf::http::extract::{Json, Path};
+    ///
+    /// let (Path(id), Json(user)) = request.extract::<(Path<i64>, Json<User>)>()?;
+    /// ```
+    pub fn extract<T: FromRequest>(&self) -> Result<T, Error> {
+        T::from_request(self)
+    }
+
     /// Retrieve the reequest body as bytes.
     ///
-    /// It's the job of the caller to handle encoding, if any.
+    /// It's the job of the caller to handle encoding, if any. The body of `multipart/form-data` forms
+    /// is parsed while it's received and is not kept, see [`Request::multipart`].
     pub fn body(&self) -> &[u8] {
         &self.inner.body
     }
 
+    /// The form parsed while the request was received.
+    pub(crate) fn received_form(&self) -> Option<&ReceivedForm> {
+        self.inner.form.as_ref()
+    }
+
     /// Request body parsed JSON value. If the body isn't JSON, an error is returned.
     pub fn json_raw(&self) -> Result<Value, serde_json::Error> {
         self.json()
@@ -193,10 +401,32 @@ impl Request {
     /// Return data submitted via a form, type checked
     /// with a Rust struct.
     ///
-    /// This allows to check inputs of complex forms easily,
-    /// or return a `400 - Bad Request` automatically if not (using the `?` operator).
-    pub fn form<T: FromFormData>(&self) -> Result<T, Error> {
-        T::from_form_data(&self.form_data()?)
+    /// This allows to check inputs of complex forms easily. If any field is missing or invalid,
+    /// the errors of all fields are returned, e.g. to show the form again with the errors, or to return
+    /// `422 - Unprocessable Entity` automatically (using the `?` operator). If the request doesn't contain
+    /// a form, the error is set on the `form` field.
+    pub fn form<T: FromFormData>(&self) -> Result<T, FormErrors> {
+        match self
+            .form_data()
+            .and_then(|form_data| T::from_form_data(&form_data))
+        {
+            Ok(form) => Ok(form),
+            Err(Error::Validation(errors)) => Err(errors),
+            Err(err) => {
+                let mut errors = FormErrors::new();
+                errors.add("form", err);
+                Err(errors)
+            }
+        }
+    }
+
+    /// Get the `multipart/form-data` form, e.g. to handle file uploads. Large files are written
+    /// to temporary files instead of being kept in memory. Limits are set in the `[uploads]`
+    /// section of the configuration. See [`crate::http::upload`].
+    ///
+    /// Uploaded files are only returned the first time this is called, since they are deleted once dropped.
+    pub async fn multipart(&self) -> Result<MultipartForm, Error> {
+        MultipartForm::from_request(self, &get_config().uploads).await
     }
 
     /// Deserialize request body from JSON into a Rust struct. If deserialization fails,
@@ -206,6 +436,19 @@ impl Request {
         T::deserialize(&mut deserializer)
     }
 
+    /// Apply the request body, a [JSON Merge Patch](https://www.rfc-editor.org/rfc/rfc7386),
+    /// to the model. Fields set to `null` in the patch are cleared, fields missing from it are left
+    /// unchanged. See [`crate::http::merge_patch`].
+    pub fn merge_patch<T: Model + Serialize + DeserializeOwned>(
+        &self,
+        model: &T,
+    ) -> Result<MergePatch<T>, Error> {
+        let patch = self
+            .json::<serde_json::Value>()
+            .map_err(|err| Error::InvalidBody(err.to_string()))?;
+        MergePatch::new(model, &patch)
+    }
+
     /// Return cookies set on the request. If no cookies are set,
     /// an empty [`crate::http::Cookies`] is returned.
     pub fn cookies(&self) -> &Cookies {
@@ -220,6 +463,12 @@ impl Request {
         &self.session
     }
 
+    /// Flash messages set by the previous response, e.g. before a redirect,
+    /// by kind, e.g. `notice`. See [`Response::flash`].
+    pub fn flash(&self) -> &BTreeMap<String, String> {
+        &self.session.flash
+    }
+
     /// Was the CSRF protection bypassed on this request?
     ///
     /// Used internally to skip CSRF middleware, but
@@ -228,6 +477,56 @@ impl Request {
         self.skip_csrf
     }
 
+    /// Host the request was sent to, from the `Host` header, without the port.
+    pub fn host(&self) -> Option<&str> {
+        let host = self.header("host")?.trim();
+
+        // IPv6 addresses are in brackets, e.g. `[::1]:8000`.
+        let host = match host.rfind(':') {
+            Some(colon) if !host[colon..].contains(']') => &host[..colon],
+            _ => host,
+        };
+
+        (!host.is_empty()).then_some(host)
+    }
+
+    /// ID of the last Server-Sent Event received by the client, sent
+    /// in the `Last-Event-ID` header when the browser reconnects.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.header("last-event-id").map(|id| id.as_str())
+    }
+
+    /// Pick the response format the client prefers from `formats`, using the path extension, e.g. `.csv`,
+    /// if the route accepts it, or the `Accept` header. Clients without an `Accept` header get the first format.
+    /// See [`crate::http::format`].
+    pub fn format(&self, formats: &[Format]) -> Option<Format> {
+        if let Ok(Some(extension)) = self.parameter::<String>("format") {
+            return Format::from_extension(&extension).filter(|format| formats.contains(format));
+        }
+
+        match self.header("accept") {
+            Some(accept) => Format::negotiate(accept, formats),
+            None => formats.first().copied(),
+        }
+    }
+
+    /// Generate a CSRF token for this session, e.g. to pass it to a JavaScript
+    /// frontend which sends it back in the `X-CSRF-Token` header.
+    pub fn csrf_token(&self) -> Result<String, Error> {
+        Ok(crate::crypto::csrf_token(&self.session_id().to_string())?)
+    }
+
+    /// Request ID, taken from the `X-Request-Id` header if the client sent one,
+    /// or generated by the server. Added to all log lines written while the request is handled.
+    pub fn id(&self) -> &str {
+        &self.inner.id
+    }
+
+    /// Position of the request in a distributed trace, if `trace_context` is enabled.
+    pub fn trace(&self) -> Option<&TraceContext> {
+        self.inner.trace.as_ref()
+    }
+
     /// Return the timestamp of when the request was received by the server.
     pub fn received_at(&self) -> OffsetDateTime {
         self.received_at
@@ -352,25 +651,7 @@ impl Request {
     /// let response = request.login_user(&user).unwrap();
     /// ```
     pub fn login_user(&self, user: &impl Model) -> Result<Response, Error> {
-        use crate::model::Value;
-
-        let get_user_id = |value: Value| -> Result<Response, Error> {
-            match value {
-                Value::Integer(user_id) => Ok(self.login(user_id)),
-                Value::BigInt(user_id) => Ok(self.login(user_id)),
-                Value::Int(user_id) => Ok(self.login(user_id as i64)),
-                Value::SmallInt(user_id) => Ok(self.login(user_id as i64)),
-                _ => Err(Error::UserIdNotAnInteger),
-            }
-        };
-
-        match user.id() {
-            Value::Optional(value) => match *value {
-                Some(user_id) => get_user_id(user_id),
-                None => Err(Error::UserIdIsNull),
-            },
-            value => get_user_id(value),
-        }
+        Ok(self.login(user_id(user)?))
     }
 
     /// Log the user out. This overwrites the session cookie with a guest session.
@@ -386,9 +667,85 @@ impl Request {
         Response::new().set_session(Session::anonymous()).html("")
     }
 
+    /// Log the user out of all their sessions, e.g. after they changed their password,
+    /// and overwrite the session cookie with a guest session.
+    ///
+    /// Requires a server-side [session store](crate::controller::session_store).
+    pub async fn logout_everywhere(&self) -> Result<Response, Error> {
+        if let Some(user_id) = self.session_id().user_id() {
+            session_store::store().delete_user(user_id).await?;
+        }
+
+        Ok(self.logout())
+    }
+
+    /// Authenticate the request with a bearer token instead of the session cookie,
+    /// e.g. by [`crate::controller::TokenAuth`]. The session isn't sent back to the client.
+    pub fn with_token(mut self, user_id: i64, claims: serde_json::Value) -> Self {
+        self.session = Session {
+            session_id: SessionId::Authenticated(user_id),
+            ..Session::anonymous()
+        };
+        self.renew_session = false;
+        Arc::make_mut(&mut self.inner).token_claims = Some(claims);
+        self
+    }
+
+    /// Claims of the bearer token the request was authenticated with, if any.
+    /// For opaque tokens, this only contains the user's ID, in `sub`.
+    pub fn token_claims(&self) -> Option<&serde_json::Value> {
+        self.inner.token_claims.as_ref()
+    }
+
     pub(crate) fn renew_session(&self) -> bool {
         self.renew_session
     }
+
+    /// Key of the session in a server-side session store, if it has one.
+    pub(crate) fn session_key(&self) -> Option<&str> {
+        self.inner.session_key.as_deref()
+    }
+
+    /// Get the session from the server-side store. If the store is unavailable,
+    /// the client gets a guest session instead.
+    async fn load_session(cookies: &Cookies) -> Result<(Option<Session>, Option<String>), Error> {
+        let key = match cookies.get_session_key()? {
+            Some(key) => key,
+            None => return Ok((None, None)),
+        };
+
+        match session_store::store().load(&key).await {
+            Ok(Some(session)) => Ok((Some(session), Some(key))),
+            Ok(None) => Ok((None, None)),
+            Err(err) => {
+                warn!("{}", err);
+                Ok((None, None))
+            }
+        }
+    }
+}
+
+/// Primary key of the user, which must be an integer.
+pub(crate) fn user_id(user: &impl Model) -> Result<i64, Error> {
+    use crate::model::Value;
+
+    let get_user_id = |value: Value| -> Result<i64, Error> {
+        match value {
+            Value::Integer(user_id) => Ok(user_id),
+            Value::BigInt(user_id) => Ok(user_id),
+            Value::Int(user_id) => Ok(user_id as i64),
+            Value::SmallInt(user_id) => Ok(user_id as i64),
+            _ => Err(Error::UserIdNotAnInteger),
+        }
+    };
+
+    match user.id() {
+        Value::Optional(value) => match *value {
+            Some(user_id) => get_user_id(user_id),
+            None => Err(Error::UserIdIsNull),
+        },
+        value => get_user_id(value),
+    }
 }
 
 impl Deref for Request {
@@ -410,6 +767,12 @@ impl ToTemplateValue for Request {
             self.path().query().to_string().to_template_value()?,
         );
         hash.insert("session".to_string(), self.session().to_template_value()?);
+        hash.insert(
+            "locale".to_string(),
+            self.locale()
+                .map(|locale| locale.to_string())
+                .to_template_value()?,
+        );
         Ok(Value::Hash(hash))
     }
 }
@@ -486,6 +849,29 @@ pub mod test {
         };
     }
 
+    #[tokio::test]
+    async fn test_parameters() {
+        use crate::http::{path::PathWithRegex, Path};
+
+        let path =
+            PathWithRegex::route(Path::parse("/users/:user_id/files/*path").unwrap()).unwrap();
+        let req = "GET /users/apple/files/a/b.txt HTTP/1.1\r\nContent-Length: 0\r\n\r\n";
+        let req = Request::read(dummy_ip(), req.as_bytes())
+            .await
+            .unwrap()
+            .with_params(path.params());
+
+        assert_eq!(
+            req.parameter::<String>("path").unwrap(),
+            Some("a/b.txt".into())
+        );
+        assert!(req.parameter::<i64>("id").unwrap().is_none());
+
+        let err = req.parameter::<i64>("user_id").unwrap_err();
+        assert!(matches!(err, Error::InvalidParameter(ref name) if name == "user_id"));
+        assert_eq!(err.code(), 400);
+    }
+
     #[tokio::test]
     async fn test_too_large() {
         // Test too large request.
@@ -504,6 +890,31 @@ pub mod test {
         assert!(err.starts_with("ContentTooLarge"));
     }
 
+    #[tokio::test]
+    async fn test_gzip_body() {
+        use crate::http::compression::Encoding;
+
+        let body = Encoding::Gzip.compress(b"hello=world").unwrap();
+        let mut req = format!(
+            "POST / HTTP/1.1\r\nContent-Encoding: gzip\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        req.extend(body);
+
+        let req = Request::read(dummy_ip(), req.as_slice()).await.unwrap();
+        assert_eq!(req.body(), b"hello=world");
+        assert_eq!(req.content_length(), Some(11));
+        assert!(req.header("content-encoding").is_none());
+        assert_eq!(
+            req.form_data().unwrap().get::<String>("hello"),
+            Some("world".into())
+        );
+
+        let req = "POST / HTTP/1.1\r\nContent-Encoding: gzip\r\nContent-Length: 5\r\n\r\n12345";
+        assert!(Request::read(dummy_ip(), req.as_bytes()).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_login_logout() {
         let req = "GET / HTTP/1.1\r\nContent-Length: 0\r\n\r\n";
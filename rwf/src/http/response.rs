@@ -11,16 +11,29 @@
 //!     .html("<h1>Hello world!</h1>");
 //! ```
 
+use bytes::Bytes;
 use once_cell::sync::Lazy;
+use parking_lot::Mutex;
 use serde::Serialize;
-use std::collections::HashMap;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
 use std::marker::Unpin;
+use std::sync::Arc;
 use time::OffsetDateTime;
 use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio_stream::{Stream, StreamExt};
 
-use super::{head::Version, Body, Cookie, Cookies, Error, Headers, Request};
-use crate::view::{Template, TurboStream};
-use crate::{config::get_config, controller::Session};
+use super::{
+    compression::Encoding, head::Version, rewriter::HtmlRewriter, router::url_for, Body, Cookie,
+    Cookies, Error, Headers, Method, Request,
+};
+use crate::view::{pdf, turbo::frame, Context, Template, TurboFrame, TurboStream};
+use crate::{
+    config::{get_config, CompressionConfig},
+    controller::{session_store, Session},
+    safe_html,
+};
 
 static ERROR_TEMPLATE: Lazy<Template> = Lazy::new(|| {
     let template = include_str!("error.html");
@@ -181,6 +194,16 @@ impl From<u16> for Status {
     }
 }
 
+/// Error which caused a `500 - Internal Server Error` response.
+#[derive(Debug, Clone)]
+pub struct ErrorReport {
+    /// Error message.
+    pub message: String,
+    /// Where the error response was created. Only captured if backtraces are enabled,
+    /// e.g. with `RUST_BACKTRACE=1`.
+    pub backtrace: Option<String>,
+}
+
 /// HTTP response.
 #[derive(Debug)]
 pub struct Response {
@@ -190,6 +213,10 @@ pub struct Response {
     body: Body,
     cookies: Cookies,
     session: Option<Session>,
+    flash: BTreeMap<String, String>,
+    error: Option<Arc<ErrorReport>>,
+    turbo_frame: Option<String>,
+    dropped: bool,
 }
 
 impl Default for Response {
@@ -221,6 +248,10 @@ impl Response {
             version: Version::Http1,
             cookies: Cookies::new(),
             session: None,
+            flash: BTreeMap::new(),
+            error: None,
+            turbo_frame: None,
+            dropped: false,
         }
     }
 
@@ -228,17 +259,50 @@ impl Response {
     ///
     /// This makes sure a valid session cookie is set on all responses.
     pub fn from_request(mut self, request: &Request) -> Result<Self, Error> {
+        // Sessions kept in a server-side store are saved by the server.
+        let cookie = session_store::store().cookie();
+
+        let mut flash = std::mem::take(&mut self.flash);
+
+        // Turbo Stream responses update the page the user is looking at,
+        // so their flash messages are shown right away.
+        if !flash.is_empty() && self.turbo_stream_body() {
+            self = self.flash_now(std::mem::take(&mut flash));
+        }
+
+        // Turbo only needs the frame it requested.
+        if let Some(id) = self.turbo_frame.take() {
+            if request.header("turbo-frame") == Some(&id) {
+                if let Body::Html(ref html) = self.body {
+                    if let Some(frame) = frame::extract(html, &id) {
+                        self.body = Body::Html(frame.to_string());
+                    }
+                }
+            }
+        }
+
         // Session set manually on the request already.
-        if let Some(ref session) = self.session {
-            self.cookies.add_session(&session)?;
-        } else {
+        if let Some(ref mut session) = self.session {
+            session.flash = flash;
+            if cookie {
+                self.cookies.add_session(session)?;
+            }
+        } else if request.token_claims().is_none() {
+            // Requests authenticated with a token don't use the session cookie.
             let session = request.session();
+            let renew = session.should_renew() || request.renew_session();
 
-            if session.should_renew() || request.renew_session() {
-                let session = session
-                    .clone()
-                    .renew(get_config().general.session_duration());
-                self.cookies.add_session(&session)?;
+            // Flash messages are shown once, so the ones sent with the request are removed.
+            if renew || !flash.is_empty() || !session.flash.is_empty() {
+                let mut session = session.clone();
+                if renew {
+                    session = session.renew(get_config().general.session_duration());
+                }
+                session.flash = flash;
+
+                if cookie {
+                    self.cookies.add_session(&session)?;
+                }
 
                 // Set the session on the response, so it can be
                 // passed down in handle_stream.
@@ -249,6 +313,35 @@ impl Response {
         Ok(self)
     }
 
+    /// Save the session set on the response in the server-side session store, and send its key
+    /// to the client. *This is used internally automatically.*
+    ///
+    /// A new key is used when the user logs in or out, so a key obtained before
+    /// can't be used to access the new session.
+    pub(crate) async fn save_session(mut self, request: &Request) -> Result<Self, Error> {
+        let store = session_store::store();
+
+        let session = match self.session {
+            Some(ref session) if !store.cookie() => session,
+            _ => return Ok(self),
+        };
+
+        let key = match request.session_key() {
+            Some(key) if session.session_id == request.session().session_id => key.to_string(),
+            key => {
+                if let Some(key) = key {
+                    store.delete(key).await?;
+                }
+                session_store::key()
+            }
+        };
+
+        store.save(&key, session).await?;
+        self.cookies.add_session_key(&key, session.expiration)?;
+
+        Ok(self)
+    }
+
     /// Set the request body.
     ///
     /// The body will automatically determine the `Content-Type` and `Content-Length` headers.
@@ -256,13 +349,24 @@ impl Response {
     /// when building a response.
     pub fn body(mut self, body: impl Into<Body>) -> Self {
         self.body = body.into();
-        self.headers
-            .insert("content-length".to_string(), self.body.len().to_string());
+        if self.body.chunked() {
+            self.headers.remove("content-length");
+            self.headers.insert("transfer-encoding", "chunked");
+        } else {
+            self.headers.remove("transfer-encoding");
+            self.headers
+                .insert("content-length".to_string(), self.body.len().to_string());
+        }
         self.headers
             .insert("content-type", self.body.mime_type().to_string());
         self
     }
 
+    /// Error which caused this response, if it was created with [`Response::internal_error`].
+    pub fn error(&self) -> Option<&ErrorReport> {
+        self.error.as_deref()
+    }
+
     /// Get response status, e.g. 200 OK.
     pub fn status(&self) -> Status {
         self.code.into()
@@ -332,6 +436,97 @@ impl Response {
         self.body(Body::Text(body.to_string()))
     }
 
+    /// Render a template to PDF and use it as the response body.
+    ///
+    /// The HTML is converted by the renderer configured in [`crate::view::pdf`].
+    /// PDFs produced by an external program are streamed to the client while it's running.
+    /// The PDF is displayed in the browser by default; use [`Response::attachment`]
+    /// to download it instead.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let response = Response::new()
+    ///     .pdf("templates/invoice.html", &context!("total" => 25))
+    ///     .await?
+    ///     .attachment("invoice.pdf");
+    /// ```
+    pub async fn pdf(
+        self,
+        template: impl AsRef<std::path::Path> + Copy,
+        context: impl TryInto<Context, Error = crate::view::Error>,
+    ) -> Result<Self, crate::view::Error> {
+        let html = Template::load(template)?.render(context)?;
+        let body = pdf::render_body(&html).await?;
+
+        Ok(self
+            .body(body)
+            .header("content-type", "application/pdf")
+            .header("content-disposition", "inline"))
+    }
+
+    /// Stream the response body to the client as it's generated, using chunked transfer encoding.
+    ///
+    /// Use this for large responses, e.g. CSV exports, which shouldn't be buffered in memory.
+    /// The `Content-Type` is set to `application/octet-stream`; set it after calling this method
+    /// if needed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rwf::http::Response;
+    /// use rwf::tokio_stream::{self, StreamExt};
+    ///
+    /// let rows = tokio_stream::iter(1..=1000).map(|id| format!("{},user_{}\n", id, id));
+    /// let response = Response::new()
+    ///     .stream(rows)
+    ///     .header("content-type", "text/csv")
+    ///     .attachment("users.csv");
+    /// ```
+    pub fn stream<B: Into<Bytes>>(self, stream: impl Stream<Item = B> + Send + 'static) -> Self {
+        self.body(Body::stream(stream, None))
+    }
+
+    /// Stream the response body to the client, when the length of the body is known in advance.
+    ///
+    /// The body is sent with the `Content-Length` header instead of chunked transfer encoding. If the stream
+    /// produces a different number of bytes, the connection is closed.
+    pub fn stream_with_length<B: Into<Bytes>>(
+        self,
+        stream: impl Stream<Item = B> + Send + 'static,
+        length: usize,
+    ) -> Self {
+        self.body(Body::stream(stream, Some(length)))
+    }
+
+    /// Tell the browser to download the response body and save it
+    /// with the given file name.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rwf::http::Response;
+    ///
+    /// let response = Response::new()
+    ///     .text("1,2,3")
+    ///     .attachment("numbers.csv");
+    /// ```
+    pub fn attachment(self, filename: &str) -> Self {
+        self.header(
+            "content-disposition",
+            content_disposition("attachment", filename),
+        )
+    }
+
+    /// Tell the browser to display the response body, suggesting
+    /// a file name if the user decides to save it.
+    pub fn inline(self, filename: &str) -> Self {
+        self.header(
+            "content-disposition",
+            content_disposition("inline", filename),
+        )
+    }
+
     /// Add a header to the response.
     ///
     /// Header name is lowercased automatically. The value is set as-is.
@@ -350,6 +545,112 @@ impl Response {
         self
     }
 
+    /// Compress the response body, if the client accepts a supported encoding
+    /// in the `Accept-Encoding` header. *This is used internally automatically.*
+    ///
+    /// Responses that are too small, already encoded, streamed from disk, or of a content type
+    /// not allowed by the configuration, are returned unchanged.
+    pub fn compress(mut self, request: &Request, config: &CompressionConfig) -> Self {
+        if !config.enabled
+            || matches!(self.code, 100..=199 | 204 | 304)
+            || self.headers.get("content-encoding").is_some()
+        {
+            return self;
+        }
+
+        let allowed = self
+            .headers
+            .get("content-type")
+            .map(|content_type| config.compress_content_type(content_type))
+            .unwrap_or(false);
+
+        if !allowed {
+            return self;
+        }
+
+        let encoding = match request
+            .header("accept-encoding")
+            .and_then(|accept| Encoding::negotiate(accept))
+        {
+            Some(encoding) => encoding,
+            None => return self,
+        };
+
+        let compressed = match self.body.as_bytes() {
+            Some(bytes) if bytes.len() >= config.min_size => match encoding.compress(bytes) {
+                Ok(compressed) => compressed,
+                Err(_) => return self,
+            },
+            _ => return self,
+        };
+
+        self.body = Body::bytes(compressed);
+        self.headers
+            .insert("content-length", self.body.len().to_string());
+        self.headers.insert("content-encoding", encoding.name());
+
+        let vary = match self.headers.get("vary") {
+            Some(vary) => format!("{}, Accept-Encoding", vary),
+            None => "Accept-Encoding".to_string(),
+        };
+        self.headers.insert("vary", vary);
+
+        self
+    }
+
+    /// Rewrite the HTML body with the rewriter, e.g. to add attributes to elements.
+    ///
+    /// Responses which aren't HTML, or are already compressed, are returned unchanged.
+    /// Streamed bodies are rewritten as they are sent.
+    pub fn rewrite(mut self, mut rewriter: HtmlRewriter) -> Self {
+        let html = self
+            .headers
+            .get("content-type")
+            .map(|content_type| content_type.starts_with("text/html"))
+            .unwrap_or(false);
+
+        if rewriter.is_empty() || !html || self.headers.get("content-encoding").is_some() {
+            return self;
+        }
+
+        let body = std::mem::replace(&mut self.body, Body::bytes(vec![]));
+
+        self.body = match body {
+            Body::Html(html) => Body::Html(rewriter.rewrite(&html)),
+            Body::Stream { stream, .. } => {
+                let rewriter = Arc::new(Mutex::new(rewriter));
+                let end = rewriter.clone();
+
+                // The length changes, so it's sent chunked.
+                self.headers.remove("content-length");
+                self.headers.insert("transfer-encoding", "chunked");
+
+                Body::stream(
+                    stream
+                        .into_inner()
+                        .map(move |chunk| rewriter.lock().write(&chunk))
+                        .chain(tokio_stream::once(()).map(move |_| end.lock().end())),
+                    None,
+                )
+            }
+            body => match body.as_bytes() {
+                Some(bytes) => {
+                    let mut rewritten = rewriter.write(bytes);
+                    rewritten.extend(rewriter.end());
+                    Body::bytes(rewritten)
+                }
+                None => body,
+            },
+        };
+
+        if !self.body.chunked() {
+            self.headers
+                .insert("content-length", self.body.len().to_string());
+        }
+
+        self
+    }
+
     /// Send the response to a stream, serialized as bytes.
     pub async fn send(mut self, mut stream: impl AsyncWrite + Unpin) -> Result<(), std::io::Error> {
         let mut response = format!("{} {}\r\n", self.version, self.code)
@@ -364,6 +665,24 @@ impl Response {
         self.body.send(stream).await
     }
 
+    /// Split the response into the status code, the headers, including cookies, and the body,
+    /// e.g. to send it over HTTP/2.
+    pub(crate) fn into_parts(self) -> (u16, Vec<(String, String)>, Body) {
+        let mut headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect::<Vec<_>>();
+        headers.extend(
+            self.cookies
+                .set_cookie()
+                .into_iter()
+                .map(|cookie| ("set-cookie".to_string(), cookie)),
+        );
+
+        (self.code, headers, self.body)
+    }
+
     /// Mutable reference to response cookies. Used to set cookies on the response.
     ///
     /// # Example
@@ -392,6 +711,25 @@ impl Response {
         Ok(self)
     }
 
+    /// Set a signed cookie on the response. The browser can read it, but can't change it,
+    /// see [`Cookies::add_signed`].
+    ///
+    /// ```
+    /// # use rwf::http::{Response, CookieBuilder};
+    /// let response = Response::new()
+    ///     .signed_cookie(
+    ///         CookieBuilder::new()
+    ///             .name("theme")
+    ///             .value("dark")
+    ///             .permanent()
+    ///             .build()
+    ///     );
+    /// ```
+    pub fn signed_cookie(mut self, cookie: Cookie) -> Self {
+        self.cookies.add_signed(cookie);
+        self
+    }
+
     /// Set a cookie on the response.
     ///
     /// This is more ergonomic that using [`Request::cookies`].
@@ -411,6 +749,58 @@ impl Response {
         self
     }
 
+    /// Show a flash message on the next page the user sees, e.g. after a redirect.
+    /// Messages are kept in the session, and removed once they are shown.
+    ///
+    /// Turbo Stream responses show their messages right away, by updating the
+    /// element with the `flash` ID.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rwf::http::Response;
+    /// let response = Response::new()
+    ///     .redirect("/posts")
+    ///     .flash("notice", "Post saved!");
+    /// ```
+    pub fn flash(mut self, kind: impl ToString, message: impl ToString) -> Self {
+        self.flash.insert(kind.to_string(), message.to_string());
+        self
+    }
+
+    /// The body is a list of Turbo Streams.
+    fn turbo_stream_body(&self) -> bool {
+        matches!(self.body, Body::Html(_))
+            && self
+                .headers
+                .get("content-type")
+                .is_some_and(|content_type| content_type.starts_with("text/vnd.turbo-stream.html"))
+    }
+
+    /// Add a Turbo Stream showing the flash messages to the response.
+    fn flash_now(self, flash: BTreeMap<String, String>) -> Self {
+        let body = match self.body {
+            Body::Html(ref body) => body.clone(),
+            _ => return self,
+        };
+
+        let messages = flash
+            .iter()
+            .map(|(kind, message)| {
+                format!(
+                    r#"<div class="flash flash-{}">{}</div>"#,
+                    safe_html(kind),
+                    safe_html(message)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let stream = TurboStream::new(messages).action("update").target("flash");
+
+        self.html(format!("{}\n{}", body, stream.render()))
+            .header("content-type", "text/vnd.turbo-stream.html")
+    }
+
     /// Set the session on the response.
     ///
     /// The session is renewed automatically if it has expired.
@@ -432,6 +822,18 @@ impl Response {
         self.code == 101 && self.headers.get("upgrade").map(|s| s == "websocket") == Some(true)
     }
 
+    /// Close the connection without sending the response. Used to simulate network failures,
+    /// see [`Chaos`](crate::controller::middleware::Chaos).
+    pub fn drop_connection(mut self) -> Self {
+        self.dropped = true;
+        self
+    }
+
+    /// The connection will be closed without sending the response.
+    pub fn dropped(&self) -> bool {
+        self.dropped
+    }
+
     /// Create a response containing turbo streams. This sets the correct
     /// `Content-Type` headers to be parsed by Turbo.
     pub fn turbo_stream(self, body: &[TurboStream]) -> Self {
@@ -444,6 +846,30 @@ impl Response {
             .header("content-type", "text/vnd.turbo-stream.html")
     }
 
+    /// Create a page containing the Turbo Frame. If the frame is requested by Turbo,
+    /// i.e. with the `Turbo-Frame` header, only the frame is sent. Content which doesn't contain the frame
+    /// is the content of the frame.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rwf::http::Response;
+    ///
+    /// let response = Response::new().turbo_frame("comments", "<p>No comments yet.</p>");
+    /// ```
+    pub fn turbo_frame(mut self, id: impl ToString, content: impl ToString) -> Self {
+        let id = id.to_string();
+        let content = content.to_string();
+
+        let content = match frame::extract(&content, &id) {
+            Some(_) => content,
+            None => TurboFrame::new(&id).content(content).render(),
+        };
+
+        self.turbo_frame = Some(id);
+        self.html(content).header("vary", "turbo-frame")
+    }
+
     /// Create a `404 - Not Found` response.
     pub fn not_found() -> Self {
         Self::error_pretty("404 - Not Found", "").code(404)
@@ -454,6 +880,21 @@ impl Response {
         Self::error_pretty("405 - Method Not Allowed", "").code(405)
     }
 
+    /// Set the `Allow` header to the methods accepted by the resource, e.g. in `405 - Method Not Allowed` responses.
+    pub fn allow(self, methods: &[Method]) -> Self {
+        let methods = methods
+            .iter()
+            .map(|method| method.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.header("allow", methods)
+    }
+
+    /// Create a `406 - Not Acceptable` response, when the client doesn't accept any format the controller can respond with.
+    pub fn not_acceptable() -> Self {
+        Self::error_pretty("406 - Not Acceptable", "").code(406)
+    }
+
     /// Create a `400 - Bad Request` response.
     pub fn bad_request() -> Self {
         Self::error_pretty("400 - Bad Request", "").code(400)
@@ -488,6 +929,15 @@ impl Response {
     /// Requires the error that was returned for debugging purposes.
     /// The error is shown in development (debug) and hidden in production (release).
     pub fn internal_error(err: impl std::error::Error) -> Self {
+        let backtrace = Backtrace::capture();
+        let report = ErrorReport {
+            message: err.to_string(),
+            backtrace: match backtrace.status() {
+                BacktraceStatus::Captured => Some(backtrace.to_string()),
+                _ => None,
+            },
+        };
+
         // TODO:
         #[cfg(debug_assertions)]
         let err = format!("{}", err);
@@ -498,7 +948,9 @@ impl Response {
             ""
         };
 
-        Self::error_pretty("500 - Internal Server Error", &err)
+        let mut response = Self::error_pretty("500 - Internal Server Error", &err);
+        response.error = Some(Arc::new(report));
+        response
     }
 
     /// Use the internal template to render a better looking error page.
@@ -534,6 +986,65 @@ impl Response {
             .header("cache-control", "no-cache")
     }
 
+    /// Create `301 - Moved Permanently` response. Browsers remember permanent redirects,
+    /// and search engines index the new URL instead.
+    pub fn redirect_permanent(mut self, to: impl ToString) -> Self {
+        self = self.redirect(to).code(301);
+        self.headers.remove("cache-control");
+        self
+    }
+
+    /// Create `303 - See Other` response. The browser loads the URL with a `GET` request, whatever
+    /// the method of the request was, so it's the redirect to use after a form was submitted,
+    /// e.g. with `PATCH` or `DELETE` by Turbo.
+    pub fn see_other(self, to: impl ToString) -> Self {
+        self.redirect(to).code(303)
+    }
+
+    /// Redirect to the page the user came from, found in the `Referer` header. If the header
+    /// isn't set, or points to another site, redirect to the fallback URL instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rwf::http::{Request, Response};
+    /// # let request = Request::default();
+    /// let response = Response::new().redirect_back(&request, "/posts");
+    /// ```
+    pub fn redirect_back(self, request: &Request, fallback: impl ToString) -> Self {
+        match referer_path(request) {
+            Some(path) => self.redirect(path),
+            None => self.redirect(fallback),
+        }
+    }
+
+    /// Redirect to a named route, filling in its parameters. See [`url_for`](crate::http::router::url_for).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rwf::prelude::*;
+    /// # #[derive(Default)]
+    /// # struct UserController;
+    /// # #[rwf::async_trait]
+    /// # impl Controller for UserController {
+    /// #     async fn handle(&self, request: &Request) -> Result<Response, Error> {
+    /// #         Ok(Response::new())
+    /// #     }
+    /// # }
+    /// rwf::http::Server::new(vec![route!("/users/:id" => UserController, as: "user")]);
+    ///
+    /// let response = Response::new().redirect_to_route("user", &[("id", &5)]).unwrap();
+    /// assert_eq!(response.headers().get("location").unwrap(), "/users/5");
+    /// ```
+    pub fn redirect_to_route(
+        self,
+        name: &str,
+        params: &[(&str, &dyn Display)],
+    ) -> Result<Self, Error> {
+        Ok(self.redirect(url_for(name, params)?))
+    }
+
     /// Create `101 - Switching Protocols`. Can be used for upgrading the connection
     /// to HTTP/2 or WebSocket. The protocol argument isn't checked, so ideally this is used
     /// internally only.
@@ -580,3 +1091,405 @@ impl From<Vec<TurboStream>> for Response {
         Response::new().turbo_stream(&value)
     }
 }
+
+/// Format the `Content-Disposition` header value. Non-ASCII file names
+/// are encoded as specified in RFC 6266, with an ASCII fallback for older clients.
+fn content_disposition(disposition: &str, filename: &str) -> String {
+    let fallback = filename
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c.is_ascii() && !c.is_ascii_control() => c,
+            _ => '_',
+        })
+        .collect::<String>();
+
+    if fallback == filename {
+        format!("{}; filename=\"{}\"", disposition, fallback)
+    } else {
+        let encoded = filename
+            .bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                    (b as char).to_string()
+                }
+                b => format!("%{:02X}", b),
+            })
+            .collect::<String>();
+
+        format!(
+            "{}; filename=\"{}\"; filename*=UTF-8''{}",
+            disposition, fallback, encoded
+        )
+    }
+}
+
+/// Path and query of the page in the `Referer` header, if it's on the same host as the request.
+fn referer_path(request: &Request) -> Option<String> {
+    let referer = request.header("referer")?;
+    let host = request.header("host")?;
+
+    let url = referer
+        .strip_prefix("https://")
+        .or_else(|| referer.strip_prefix("http://"))?;
+    let (authority, path) = match url.find(['/', '?', '#']) {
+        Some(end) => url.split_at(end),
+        None => (url, "/"),
+    };
+
+    if !authority.eq_ignore_ascii_case(host) {
+        return None;
+    }
+
+    let path = path.split('#').next().unwrap_or_default();
+
+    // Browsers treat `//example.com` and `/\example.com` as another site.
+    if path.starts_with("//") || path.starts_with("/\\") {
+        return None;
+    }
+
+    if path.starts_with('/') {
+        Some(path.to_string())
+    } else {
+        Some(format!("/{}", path))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_content_disposition() {
+        let response = Response::new().text("1,2,3").attachment("numbers.csv");
+        assert_eq!(
+            response.headers().get("content-disposition").unwrap(),
+            "attachment; filename=\"numbers.csv\""
+        );
+
+        assert_eq!(
+            content_disposition("inline", "faktura \"č\".pdf"),
+            "inline; filename=\"faktura ___.pdf\"; filename*=UTF-8''faktura%20%22%C4%8D%22.pdf"
+        );
+    }
+
+    #[test]
+    fn test_internal_error() {
+        let err = std::io::Error::new(std::io::ErrorKind::Other, "disk full");
+        let response = Response::internal_error(err);
+        assert_eq!(response.status().code(), 500);
+        assert_eq!(response.error().unwrap().message, "disk full");
+
+        assert!(Response::not_found().error().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pdf() {
+        pdf::set_renderer(pdf::CommandRenderer::new("cat"));
+
+        let path = std::env::temp_dir().join("rwf_test_pdf.html");
+        std::fs::write(&path, "<h1><%= title %></h1>").unwrap();
+
+        let mut context = Context::new();
+        context.set("title", "Invoice").unwrap();
+
+        let response = Response::new().pdf(&path, &context).await.unwrap();
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "application/pdf"
+        );
+        assert_eq!(
+            response.headers().get("content-disposition").unwrap(),
+            "inline"
+        );
+        assert_eq!(
+            response.headers().get("transfer-encoding").unwrap(),
+            "chunked"
+        );
+
+        let mut sent = vec![];
+        response.send(&mut sent).await.unwrap();
+        assert!(String::from_utf8(sent)
+            .unwrap()
+            .ends_with("\r\n\r\n10\r\n<h1>Invoice</h1>\r\n0\r\n\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_compress() {
+        use crate::http::request::test::dummy_ip;
+
+        let config = CompressionConfig::default();
+        let request = |accept: &str| {
+            let request = format!(
+                "GET / HTTP/1.1\r\nAccept-Encoding: {}\r\nContent-Length: 0\r\n\r\n",
+                accept
+            );
+            Request::read(dummy_ip(), std::io::Cursor::new(request.into_bytes()))
+        };
+        let html = "<p>hello</p>".repeat(200);
+
+        let request = request("gzip, deflate").await.unwrap();
+        let response = Response::new().html(&html).compress(&request, &config);
+        assert_eq!(response.headers().get("content-encoding").unwrap(), "gzip");
+        assert_eq!(response.headers().get("vary").unwrap(), "Accept-Encoding");
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(
+            response.headers().get("content-length").unwrap(),
+            &response.body.len().to_string()
+        );
+        assert!(response.body.len() < html.len());
+
+        // Too small.
+        let response = Response::new()
+            .html("<p>hello</p>")
+            .compress(&request, &config);
+        assert!(response.headers().get("content-encoding").is_none());
+
+        // Content type not allowed.
+        let response = Response::new()
+            .body(html.as_bytes())
+            .compress(&request, &config);
+        assert!(response.headers().get("content-encoding").is_none());
+
+        // Client doesn't accept supported encodings.
+        let request = Request::read(
+            dummy_ip(),
+            "GET / HTTP/1.1\r\nContent-Length: 0\r\n\r\n".as_bytes(),
+        )
+        .await
+        .unwrap();
+        let response = Response::new().html(&html).compress(&request, &config);
+        assert!(response.headers().get("content-encoding").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream() {
+        use tokio_stream::{iter, StreamExt};
+
+        let chunks = iter(["hello", "", " world"]);
+        let response = Response::new().stream(chunks);
+        assert_eq!(
+            response.headers().get("transfer-encoding").unwrap(),
+            "chunked"
+        );
+        assert!(response.headers().get("content-length").is_none());
+
+        let mut sent = vec![];
+        response.send(&mut sent).await.unwrap();
+        let sent = String::from_utf8(sent).unwrap();
+        assert!(sent.ends_with("\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n"));
+
+        let chunks = iter(1..=3).map(|n| n.to_string());
+        let response = Response::new().stream_with_length(chunks, 3);
+        assert_eq!(response.headers().get("content-length").unwrap(), "3");
+        assert!(response.headers().get("transfer-encoding").is_none());
+
+        let mut sent = vec![];
+        response.send(&mut sent).await.unwrap();
+        assert!(String::from_utf8(sent).unwrap().ends_with("\r\n\r\n123"));
+
+        // Stream shorter than the declared length.
+        let response = Response::new().stream_with_length(iter(["12"]), 3);
+        assert!(response.send(&mut vec![]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_flash() {
+        use crate::http::Server;
+        use crate::prelude::*;
+        use crate::testing::TestClient;
+
+        #[derive(Default)]
+        struct Posts;
+
+        #[async_trait]
+        impl Controller for Posts {
+            async fn handle(&self, request: &Request) -> Result<Response, Error> {
+                if request.method() == &Method::Post {
+                    let response = if request
+                        .header("accept")
+                        .is_some_and(|accept| accept.contains("turbo-stream"))
+                    {
+                        Response::new().turbo_stream(&[TurboStream::new("<p>post</p>")
+                            .action("append")
+                            .target("posts")])
+                    } else {
+                        Response::new().redirect("/posts")
+                    };
+                    Ok(response.flash("notice", "Post <saved>!"))
+                } else {
+                    let template = Template::from_str("<%= flash.notice %>")?;
+                    Ok(Response::new().html(template.render(&Context::from_request(request)?)?))
+                }
+            }
+        }
+
+        let client = TestClient::new(Server::new(vec![route!("/posts" => Posts)]));
+
+        client
+            .post("/posts")
+            .send()
+            .await
+            .unwrap()
+            .assert_redirect("/posts");
+
+        client
+            .get("/posts")
+            .send()
+            .await
+            .unwrap()
+            .assert_contains("Post &lt;saved&gt;!");
+
+        // Shown once.
+        let response = client.get("/posts").send().await.unwrap();
+        assert_eq!(response.text(), "");
+
+        // Turbo Streams show the message right away.
+        client
+            .post("/posts")
+            .turbo()
+            .send()
+            .await
+            .unwrap()
+            .assert_turbo_stream("append", "posts", "<p>post</p>")
+            .assert_turbo_stream(
+                "update",
+                "flash",
+                r#"<div class="flash flash-notice">Post &lt;saved&gt;!</div>"#,
+            );
+
+        let response = client.get("/posts").send().await.unwrap();
+        assert_eq!(response.text(), "");
+    }
+
+    #[tokio::test]
+    async fn test_turbo_frame() {
+        use crate::http::Server;
+        use crate::prelude::*;
+        use crate::testing::TestClient;
+
+        #[derive(Default)]
+        struct Post;
+
+        #[async_trait]
+        impl Controller for Post {
+            async fn handle(&self, _request: &Request) -> Result<Response, Error> {
+                Ok(Response::new().turbo_frame(
+                    "comments",
+                    r#"<h1>Post</h1><turbo-frame id="comments"><p>Comment</p></turbo-frame>"#,
+                ))
+            }
+        }
+
+        let client = TestClient::new(Server::new(vec![route!("/post" => Post)]));
+
+        let response = client.get("/post").send().await.unwrap();
+        assert!(response.text().starts_with("<h1>Post</h1>"));
+        assert_eq!(response.header("vary"), Some("turbo-frame"));
+
+        let response = client
+            .get("/post")
+            .turbo_frame("comments")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            response.text(),
+            r#"<turbo-frame id="comments"><p>Comment</p></turbo-frame>"#
+        );
+
+        // Content without the frame is the content of the frame.
+        let response = Response::new().turbo_frame("comments", "<p>Comment</p>");
+        assert_eq!(
+            response.body.as_bytes(),
+            Some(r#"<turbo-frame id="comments"><p>Comment</p></turbo-frame>"#.as_bytes())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_redirect() {
+        use crate::http::request::test::dummy_ip;
+
+        let request = |referer: &str| {
+            let request = format!(
+                "GET / HTTP/1.1\r\nHost: example.com\r\nReferer: {}\r\nContent-Length: 0\r\n\r\n",
+                referer
+            );
+            Request::read(dummy_ip(), std::io::Cursor::new(request.into_bytes()))
+        };
+
+        let response = Response::new().redirect_permanent("/new");
+        assert_eq!(response.status().code(), 301);
+        assert!(response.headers().get("cache-control").is_none());
+
+        let response = Response::new().see_other("/posts");
+        assert_eq!(response.status().code(), 303);
+        assert_eq!(response.headers().get("location").unwrap(), "/posts");
+
+        for (referer, location) in [
+            ("https://example.com/posts?page=2#top", "/posts?page=2"),
+            ("http://EXAMPLE.com", "/"),
+            ("https://example.com?page=2", "/?page=2"),
+            ("https://example.com.evil.com/posts", "/home"),
+            ("https://evil.com/posts", "/home"),
+            ("https://example.com//evil.com", "/home"),
+            ("https://example.com/\\evil.com", "/home"),
+            ("/posts", "/home"),
+            ("javascript:alert(1)", "/home"),
+        ] {
+            let request = request(referer).await.unwrap();
+            let response = Response::new().redirect_back(&request, "/home");
+            assert_eq!(response.status().code(), 302);
+            assert_eq!(
+                response.headers().get("location").unwrap(),
+                location,
+                "{}",
+                referer
+            );
+        }
+
+        let response = Response::new().redirect_back(&Request::default(), "/home");
+        assert_eq!(response.headers().get("location").unwrap(), "/home");
+    }
+
+    #[test]
+    fn test_redirect_to_route() {
+        use crate::controller::Controller;
+
+        struct Posts;
+
+        #[async_trait::async_trait]
+        impl Controller for Posts {
+            async fn handle(
+                &self,
+                _request: &Request,
+            ) -> Result<Response, crate::controller::Error> {
+                Ok(Response::new())
+            }
+        }
+
+        crate::http::Router::new(vec![Posts.route("/posts/:id").name("test_redirect_post")])
+            .unwrap();
+
+        let response = Response::new()
+            .redirect_to_route("test_redirect_post", &[("id", &7), ("page", &2)])
+            .unwrap();
+        assert_eq!(response.status().code(), 302);
+        assert_eq!(
+            response.headers().get("location").unwrap(),
+            "/posts/7?page=2"
+        );
+
+        assert!(matches!(
+            Response::new().redirect_to_route("test_redirect_post", &[]),
+            Err(Error::MissingRouteParameter(_, _))
+        ));
+        assert!(matches!(
+            Response::new().redirect_to_route("test_redirect_missing", &[]),
+            Err(Error::UnknownRoute(_))
+        ));
+    }
+}
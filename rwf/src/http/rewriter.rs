@@ -0,0 +1,529 @@
+//! Streaming HTML rewriter.
+//!
+//! Changes the attributes of HTML elements as the document passes through, without parsing it into a tree.
+//! The document can be written in chunks of any size, e.g. as it's streamed to the client; only an unfinished
+//! tag is buffered until the next chunk arrives. Elements which aren't changed are written exactly as they were.
+//!
+//! The contents of `<script>`, `<style>`, `<textarea>` and `<title>` elements, and comments, are never rewritten.
+//!
+//! # Example
+//!
+//! ```
+//! use rwf::http::rewriter::HtmlRewriter;
+//!
+//! let mut rewriter = HtmlRewriter::new();
+//! rewriter.on("img", |img| {
+//!     if !img.has_attribute("loading") {
+//!         img.set_attribute("loading", "lazy");
+//!     }
+//! });
+//!
+//! let html = rewriter.rewrite(r#"<p><img src="/cat.png"></p>"#);
+//! assert_eq!(html, r#"<p><img src="/cat.png" loading="lazy"></p>"#);
+//! ```
+
+/// Elements whose contents is text, even if it looks like HTML.
+const RAW_TEXT: &[&str] = &["script", "style", "textarea", "title"];
+
+type Handler = Box<dyn FnMut(&mut Element) + Send>;
+
+/// HTML start tag, e.g. `<img src="/cat.png">`, passed to rewriter handlers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Element {
+    name: String,
+    // Values are kept as written in the document, i.e. with HTML entities.
+    attributes: Vec<(String, Option<String>)>,
+    self_closing: bool,
+    modified: bool,
+}
+
+impl Element {
+    /// Parse the tag, without the `<` and `>`.
+    fn parse(tag: &str) -> Option<Self> {
+        let self_closing = tag.ends_with('/');
+        let tag = tag.strip_suffix('/').unwrap_or(tag);
+
+        let name_end = tag
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(tag.len());
+        let name = tag[..name_end].to_ascii_lowercase();
+
+        if name.is_empty() || !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return None;
+        }
+
+        let mut attributes = vec![];
+        let mut rest = tag[name_end..].trim_start();
+
+        while !rest.is_empty() {
+            let name_end = rest
+                .find(|c: char| c.is_ascii_whitespace() || c == '=')
+                .unwrap_or(rest.len());
+            let attribute = rest[..name_end].to_ascii_lowercase();
+            rest = rest[name_end..].trim_start();
+
+            let value = match rest.strip_prefix('=') {
+                Some(value) => {
+                    let value = value.trim_start();
+                    match value.chars().next() {
+                        Some(quote @ ('"' | '\'')) => {
+                            let end = value[1..].find(quote).map(|end| end + 1)?;
+                            rest = &value[end + 1..];
+                            // Written with double quotes if the element is changed.
+                            Some(value[1..end].replace('"', "&quot;"))
+                        }
+                        _ => {
+                            let end = value
+                                .find(|c: char| c.is_ascii_whitespace())
+                                .unwrap_or(value.len());
+                            rest = &value[end..];
+                            Some(value[..end].to_string())
+                        }
+                    }
+                }
+                None => None,
+            };
+
+            if !attribute.is_empty() {
+                attributes.push((attribute, value));
+            }
+
+            rest = rest.trim_start();
+        }
+
+        Some(Self {
+            name,
+            attributes,
+            self_closing,
+            modified: false,
+        })
+    }
+
+    /// Tag name, in lowercase, e.g. `"img"`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the value of an attribute. Attributes without a value, e.g. `async`, have an empty value.
+    pub fn attribute(&self, name: &str) -> Option<String> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute.eq_ignore_ascii_case(name))
+            .map(|(_, value)| decode(value.as_deref().unwrap_or_default()))
+    }
+
+    /// The element has the attribute.
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.attributes
+            .iter()
+            .any(|(attribute, _)| attribute.eq_ignore_ascii_case(name))
+    }
+
+    /// Set the value of an attribute, adding it if it doesn't exist.
+    pub fn set_attribute(&mut self, name: &str, value: impl ToString) {
+        let value = Some(encode(&value.to_string()));
+        let name = name.to_ascii_lowercase();
+
+        match self
+            .attributes
+            .iter_mut()
+            .find(|(attribute, _)| *attribute == name)
+        {
+            Some((_, existing)) => *existing = value,
+            None => self.attributes.push((name, value)),
+        }
+
+        self.modified = true;
+    }
+
+    /// Remove an attribute. Returns `false` if the element didn't have it.
+    pub fn remove_attribute(&mut self, name: &str) -> bool {
+        let len = self.attributes.len();
+        self.attributes
+            .retain(|(attribute, _)| !attribute.eq_ignore_ascii_case(name));
+        let removed = self.attributes.len() != len;
+        self.modified |= removed;
+        removed
+    }
+
+    fn to_html(&self) -> String {
+        let mut html = format!("<{}", self.name);
+
+        for (name, value) in &self.attributes {
+            match value {
+                Some(value) => html.push_str(&format!(" {}=\"{}\"", name, value)),
+                None => html.push_str(&format!(" {}", name)),
+            }
+        }
+
+        if self.self_closing {
+            html.push_str(" /");
+        }
+
+        html.push('>');
+        html
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum State {
+    Text,
+    Tag,
+    Comment,
+    RawText(&'static str),
+}
+
+/// Rewrites elements of an HTML document with the handlers registered for their tag names.
+pub struct HtmlRewriter {
+    handlers: Vec<(String, Handler)>,
+    state: State,
+    pending: Vec<u8>,
+}
+
+impl Default for HtmlRewriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for HtmlRewriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HtmlRewriter")
+            .field(
+                "handlers",
+                &self
+                    .handlers
+                    .iter()
+                    .map(|(tag, _)| tag.as_str())
+                    .collect::<Vec<_>>(),
+            )
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl HtmlRewriter {
+    /// Create a rewriter without handlers. It doesn't change the document until handlers are added.
+    pub fn new() -> Self {
+        Self {
+            handlers: vec![],
+            state: State::Text,
+            pending: vec![],
+        }
+    }
+
+    /// Call the handler for every element with this tag name, e.g. `"img"`, or `"*"` for all elements.
+    /// Handlers are called in the order they were added.
+    pub fn on(
+        &mut self,
+        tag: &str,
+        handler: impl FnMut(&mut Element) + Send + 'static,
+    ) -> &mut Self {
+        self.handlers
+            .push((tag.to_ascii_lowercase(), Box::new(handler)));
+        self
+    }
+
+    /// No handlers were added.
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    /// Rewrite a whole document.
+    pub fn rewrite(&mut self, html: &str) -> String {
+        let mut output = self.write(html.as_bytes());
+        output.extend(self.end());
+        String::from_utf8_lossy(&output).into_owned()
+    }
+
+    /// Rewrite the next chunk of the document. Returns the part of the document that can be sent.
+    pub fn write(&mut self, chunk: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(chunk);
+        self.process(false)
+    }
+
+    /// The document is complete. Returns what was left of it.
+    pub fn end(&mut self) -> Vec<u8> {
+        let output = self.process(true);
+        self.state = State::Text;
+        output
+    }
+
+    fn process(&mut self, end: bool) -> Vec<u8> {
+        let input = std::mem::take(&mut self.pending);
+        let mut output = Vec::with_capacity(input.len());
+        let mut pos = 0;
+
+        while pos < input.len() {
+            let rest = &input[pos..];
+
+            match self.state {
+                State::Text => match rest.iter().position(|b| *b == b'<') {
+                    Some(start) => {
+                        output.extend_from_slice(&rest[..start]);
+                        pos += start;
+                        self.state = State::Tag;
+                    }
+                    None => {
+                        output.extend_from_slice(rest);
+                        pos = input.len();
+                    }
+                },
+
+                State::Tag => {
+                    // Not enough to know what this is yet.
+                    if rest.len() < 4 && b"<!--".starts_with(rest) && !end {
+                        break;
+                    }
+
+                    if rest.starts_with(b"<!--") {
+                        self.state = State::Comment;
+                        continue;
+                    }
+
+                    // `<` followed by something other than a tag, e.g. `a < b`.
+                    if rest.len() > 1 && !is_tag_start(rest[1]) {
+                        output.push(b'<');
+                        pos += 1;
+                        self.state = State::Text;
+                        continue;
+                    }
+
+                    match tag_end(rest) {
+                        Some(tag_end) => {
+                            let tag = &rest[..=tag_end];
+                            pos += tag_end + 1;
+                            self.state = State::Text;
+
+                            match self.rewrite_tag(tag) {
+                                Some(element) => {
+                                    if !element.self_closing {
+                                        if let Some(raw) =
+                                            RAW_TEXT.iter().find(|raw| **raw == element.name)
+                                        {
+                                            self.state = State::RawText(raw);
+                                        }
+                                    }
+
+                                    if element.modified {
+                                        output.extend_from_slice(element.to_html().as_bytes());
+                                    } else {
+                                        output.extend_from_slice(tag);
+                                    }
+                                }
+                                None => output.extend_from_slice(tag),
+                            }
+                        }
+                        None if end => {
+                            output.extend_from_slice(rest);
+                            pos = input.len();
+                        }
+                        None => break,
+                    }
+                }
+
+                State::Comment => match find(&rest[4.min(rest.len())..], b"-->") {
+                    Some(comment_end) => {
+                        let comment_end = 4 + comment_end + 3;
+                        output.extend_from_slice(&rest[..comment_end]);
+                        pos += comment_end;
+                        self.state = State::Text;
+                    }
+                    None if end => {
+                        output.extend_from_slice(rest);
+                        pos = input.len();
+                    }
+                    None => break,
+                },
+
+                State::RawText(name) => {
+                    let closing = format!("</{}", name);
+
+                    match find_ignore_case(rest, closing.as_bytes()) {
+                        Some(start) => {
+                            output.extend_from_slice(&rest[..start]);
+                            pos += start;
+                            self.state = State::Tag;
+                        }
+                        None if end => {
+                            output.extend_from_slice(rest);
+                            pos = input.len();
+                        }
+                        None => {
+                            // The closing tag could start at the end of this chunk.
+                            let keep = (closing.len() - 1).min(rest.len());
+                            output.extend_from_slice(&rest[..rest.len() - keep]);
+                            pos = input.len() - keep;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        self.pending = input[pos..].to_vec();
+        output
+    }
+
+    /// Parse a start tag and pass it to the handlers. Returns `None` for other tags, e.g. `</p>` or `<!doctype html>`.
+    fn rewrite_tag(&mut self, tag: &[u8]) -> Option<Element> {
+        if !tag[1].is_ascii_alphabetic() {
+            return None;
+        }
+
+        let tag = std::str::from_utf8(tag).ok()?;
+        let mut element = Element::parse(&tag[1..tag.len() - 1])?;
+
+        for (name, handler) in self.handlers.iter_mut() {
+            if name == "*" || *name == element.name {
+                handler(&mut element);
+            }
+        }
+
+        Some(element)
+    }
+}
+
+fn is_tag_start(byte: u8) -> bool {
+    byte.is_ascii_alphabetic() || matches!(byte, b'/' | b'!' | b'?')
+}
+
+/// Find the `>` ending the tag, skipping quoted attribute values.
+fn tag_end(tag: &[u8]) -> Option<usize> {
+    let mut quote = None;
+    let mut previous = 0;
+
+    for (i, byte) in tag.iter().enumerate().skip(1) {
+        match quote {
+            Some(q) if *byte == q => quote = None,
+            Some(_) => (),
+            None => match byte {
+                b'>' => return Some(i),
+                b'"' | b'\'' if previous == b'=' => quote = Some(*byte),
+                _ => (),
+            },
+        }
+
+        if !byte.is_ascii_whitespace() {
+            previous = *byte;
+        }
+    }
+
+    None
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn find_ignore_case(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle))
+}
+
+fn encode(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn decode(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rewriter() -> HtmlRewriter {
+        let mut rewriter = HtmlRewriter::new();
+        rewriter.on("script", |script| script.set_attribute("nonce", "abc"));
+        rewriter.on("a", |a| {
+            if let Some(href) = a.attribute("href") {
+                a.set_attribute("href", format!("{}&ref=1", href));
+            }
+        });
+        rewriter
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let html = r#"<!doctype html>
+<html>
+<!-- <script>not a tag</script> -->
+<SCRIPT src="/app.js" async></SCRIPT>
+<script>if (a < b && "</p>") { document.write("<a href='x'>") }</script>
+<a href="/search?q=1&amp;page=2" title='a > "b"'>link</a>
+<p>1 < 2</p>
+</html>"#;
+
+        let expected = r#"<!doctype html>
+<html>
+<!-- <script>not a tag</script> -->
+<script src="/app.js" async nonce="abc"></SCRIPT>
+<script nonce="abc">if (a < b && "</p>") { document.write("<a href='x'>") }</script>
+<a href="/search?q=1&amp;page=2&amp;ref=1" title="a > &quot;b&quot;">link</a>
+<p>1 < 2</p>
+</html>"#;
+
+        assert_eq!(rewriter().rewrite(html), expected);
+
+        // Same result no matter how the document is split.
+        for size in [1, 2, 3, 7, 16] {
+            let mut rewriter = rewriter();
+            let mut output = vec![];
+            for chunk in html.as_bytes().chunks(size) {
+                output.extend(rewriter.write(chunk));
+            }
+            output.extend(rewriter.end());
+            assert_eq!(String::from_utf8(output).unwrap(), expected, "{}", size);
+        }
+    }
+
+    #[test]
+    fn test_element() {
+        let mut element =
+            Element::parse(r#"img src=/cat.png alt="A &quot;cat&quot;" hidden data-x = 'y' /"#)
+                .unwrap();
+        assert_eq!(element.name(), "img");
+        assert_eq!(element.attribute("src").as_deref(), Some("/cat.png"));
+        assert_eq!(element.attribute("alt").as_deref(), Some("A \"cat\""));
+        assert_eq!(element.attribute("hidden").as_deref(), Some(""));
+        assert_eq!(element.attribute("data-x").as_deref(), Some("y"));
+        assert!(element.self_closing);
+
+        assert!(element.remove_attribute("hidden"));
+        assert!(!element.remove_attribute("hidden"));
+        element.set_attribute("loading", "lazy");
+        assert_eq!(
+            element.to_html(),
+            r#"<img src="/cat.png" alt="A &quot;cat&quot;" data-x="y" loading="lazy" />"#
+        );
+
+        assert!(Element::parse("1abc").is_none());
+    }
+
+    #[test]
+    fn test_unchanged() {
+        let html = "<div class=a><img src='x'>text</div><title><b></title>";
+        let mut rewriter = HtmlRewriter::new();
+        rewriter.on("b", |b| b.set_attribute("class", "bold"));
+        assert_eq!(rewriter.rewrite(html), html);
+
+        // Unfinished tag at the end of the document.
+        assert_eq!(
+            rewriter.rewrite("<p>hello</p><a href="),
+            "<p>hello</p><a href="
+        );
+    }
+}
@@ -23,17 +23,61 @@
 //! Currently, Rwf makes no effort to protect against poorly constructed regexes by the user. This will change
 //! in the future.
 //!
-use super::{Error, Handler, Path};
+//! ### Coverage
+//!
+//! The router counts requests routed to each handler, so tests can check that all routes were exercised,
+//! and finds routes which are never matched because other routes take precedence. See [`crate::http::coverage`].
+//!
+//! ### Constraints
+//!
+//! Routes can accept only some methods, e.g. `get!("/users" => UsersController)` or `.methods(&[Method::Get])`,
+//! requests sent to some host, e.g. `.host("admin.example.com")` or `.subdomain("admin")`, and format extensions,
+//! e.g. `.formats(&[Format::Csv])` serves `/report.csv`. Routes not accepting the host are skipped. If routes match
+//! the path but none accepts the method, the server returns `405 - Method Not Allowed` with the `Allow` header.
+//!
+//! ### Named routes
+//!
+//! Routes can be named, e.g. `route!("/users/:id" => UserController, as: "user")`, and their URLs generated
+//! with [`url_for!`](crate::url_for), e.g. `url_for!("user", id = 5)`, so they aren't hardcoded across the app.
+//!
+use super::coverage::{RouteCoverage, RouteVisits};
+use super::{urlencode, Error, Handler, Method, Path, Request};
 use crate::{colors::MaybeColorize, http::path::PathType};
 
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
 use regex::RegexSet;
-use tracing::info;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::{info, warn};
+
+static ROUTES: Lazy<RwLock<HashMap<String, NamedRoute>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// Path of a named route.
+#[derive(Debug, Clone)]
+struct NamedRoute {
+    path: String,
+    rest: bool,
+}
+
+/// Handler matching a request, see [`Router::route`].
+pub enum RouteMatch<'a> {
+    /// The handler serving the request.
+    Found(&'a Handler),
+    /// Routes match the path, but none accepts the request method. These methods are allowed.
+    MethodNotAllowed(Vec<Method>),
+    /// No route matches the path.
+    NotFound,
+}
 
 /// The HTTP request router.
 #[derive(Default)]
 pub struct Router {
     regex: RegexSet,
     handlers: Vec<Handler>,
+    visits: Vec<AtomicUsize>,
 }
 
 impl Router {
@@ -44,23 +88,99 @@ impl Router {
             .map(|h| h.path_with_regex().regex().as_str())
             .collect::<Vec<_>>();
         let regex = RegexSet::new(paths)?;
+        let visits = handlers.iter().map(|_| AtomicUsize::new(0)).collect();
+
+        let mut routes = ROUTES.write();
+        for handler in &handlers {
+            if let Some(name) = handler.route_name() {
+                routes.insert(
+                    name.to_string(),
+                    NamedRoute {
+                        path: handler.path().base().to_string(),
+                        rest: handler.path_with_regex().path_type() == &PathType::Rest,
+                    },
+                );
+            }
+        }
+        drop(routes);
+
+        Ok(Self {
+            regex,
+            handlers,
+            visits,
+        })
+    }
 
-        Ok(Self { regex, handlers })
+    /// Take the handlers out of the router, e.g. to add more and build a new router.
+    pub fn into_handlers(self) -> Vec<Handler> {
+        self.handlers
     }
 
-    /// Find the best handler for the request path.
+    /// Find the best handler for the request path, ignoring the method and host constraints of routes.
     ///
     /// See [`crate::http::router`] documentation for route matching algorithm description.
     pub fn find(&self, path: &Path) -> Option<&Handler> {
-        let matches = self.regex.matches(path.base());
+        let index = self.find_index(path.base(), |_| true)?;
+        self.visits[index].fetch_add(1, Ordering::Relaxed);
+        Some(&self.handlers[index])
+    }
+
+    /// Find the best handler for the request with this path, accepting the request method and host.
+    pub fn route(&self, path: &Path, request: &Request) -> RouteMatch<'_> {
+        let host = request.host();
+        let candidates = self.candidates(path.base(), |handler| handler.accepts_host(host));
+
+        if candidates.is_empty() {
+            return RouteMatch::NotFound;
+        }
+
+        match candidates
+            .iter()
+            .find(|index| self.handlers[**index].allows(request.method()))
+        {
+            Some(index) => {
+                self.visits[*index].fetch_add(1, Ordering::Relaxed);
+                RouteMatch::Found(&self.handlers[*index])
+            }
+
+            None => {
+                // Methods in the order the routes were added.
+                let mut candidates = candidates;
+                candidates.sort();
+
+                let mut allowed = vec![];
+                for index in candidates {
+                    for method in self.handlers[index].allowed_methods().unwrap_or_default() {
+                        if !allowed.contains(method) {
+                            allowed.push(method.clone());
+                        }
+                    }
+                }
+
+                if allowed.contains(&Method::Get) && !allowed.contains(&Method::Head) {
+                    allowed.push(Method::Head);
+                }
+
+                RouteMatch::MethodNotAllowed(allowed)
+            }
+        }
+    }
+
+    /// Find the position of the best handler for the path, without counting the visit.
+    fn find_index(&self, path: &str, accepts: impl Fn(&Handler) -> bool) -> Option<usize> {
+        self.candidates(path, accepts).first().copied()
+    }
+
+    /// Positions of the handlers matching the path, best first.
+    fn candidates(&self, path: &str, accepts: impl Fn(&Handler) -> bool) -> Vec<usize> {
+        let matches = self.regex.matches(path);
         let mut handlers = self
             .handlers
             .iter()
             .enumerate()
-            .filter(|(i, _h)| matches.matched(*i))
-            .map(|(_i, h)| h)
+            .filter(|(i, h)| matches.matched(*i) && accepts(h))
             .collect::<Vec<_>>();
-        handlers.sort_by(|a, b| {
+        handlers.sort_by(|(_, a), (_, b)| {
             let a_len = a.path().base().len();
             let b_len = b.path().base().len();
             let a_rank = a.rank();
@@ -72,7 +192,81 @@ impl Router {
                 a_rank.cmp(&b_rank)
             }
         }); // Get the most specific path (longest match).
-        handlers.last().copied()
+        handlers.into_iter().rev().map(|(i, _h)| i).collect()
+    }
+
+    /// Requests routed to each handler since the router was created, or since the last [`Router::reset_coverage`],
+    /// and handlers no request can reach.
+    pub fn coverage(&self) -> RouteCoverage {
+        let routes = self
+            .handlers
+            .iter()
+            .enumerate()
+            .map(|(i, handler)| RouteVisits {
+                path: route_path(handler),
+                controller: handler.controller_name(),
+                visits: self.visits[i].load(Ordering::Relaxed),
+                shadowed_by: self
+                    .shadowed_by(i)
+                    .map(|shadow| route_path(&self.handlers[shadow])),
+            })
+            .collect();
+
+        RouteCoverage::new(routes)
+    }
+
+    /// Forget requests counted so far.
+    pub fn reset_coverage(&self) {
+        for visits in &self.visits {
+            visits.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Find the handler taking all requests matching the handler at this position, if any.
+    ///
+    /// Sample paths are made from the handler's path by filling in the parameters. If none of them
+    /// are routed to the handler, it can't be reached.
+    fn shadowed_by(&self, index: usize) -> Option<usize> {
+        let handler = &self.handlers[index];
+        let regex = handler.path_with_regex().regex();
+        let base = handler
+            .path()
+            .base()
+            .split("/")
+            .map(|part| {
+                if part.starts_with(":") || part.starts_with("*") {
+                    "sample"
+                } else {
+                    part
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let mut samples = vec![base.clone()];
+        match handler.path_with_regex().path_type() {
+            PathType::Rest | PathType::Wildcard => {
+                samples.push(format!("{}/sample", base.trim_end_matches("/")))
+            }
+            PathType::Route => (),
+        }
+
+        for format in handler.route_formats() {
+            samples.push(format!("{}.{}", base, format.extension()));
+        }
+
+        let mut shadow = None;
+
+        // Only routes accepting all the handler's requests, e.g. with the same method, can shadow it.
+        for sample in samples.iter().filter(|sample| regex.is_match(sample)) {
+            match self.find_index(sample, |candidate| candidate.covers(handler)) {
+                Some(found) if found == index => return None,
+                Some(found) => shadow = shadow.or(Some(found)),
+                None => (),
+            }
+        }
+
+        shadow
     }
 
     /// Pretty print all registered routes.
@@ -82,20 +276,9 @@ impl Router {
         let mut handlers = self.handlers.iter().map(|s| s).collect::<Vec<_>>();
         handlers.sort_by_key(|s| s.path().path());
         for handler in handlers {
-            let indicator = match handler.path_with_regex().path_type() {
-                PathType::Route | PathType::Rest => "",
-                PathType::Wildcard => {
-                    if handler.path().base().ends_with("/") {
-                        "*"
-                    } else {
-                        "/*"
-                    }
-                }
-            };
             info!(
-                ">> {}{}{} => {}",
-                handler.path().path().purple(),
-                indicator.purple(),
+                ">> {}{} => {}",
+                route_path(handler).purple(),
                 match handler.rank() {
                     0 => "".into(),
                     rank => format!(" [{}]", rank),
@@ -104,7 +287,183 @@ impl Router {
                 // regex,
             );
         }
+
+        for route in self.coverage().shadowed() {
+            warn!(
+                "route {} => {} is unreachable, requests are routed to {} instead",
+                route.path,
+                route.controller,
+                route.shadowed_by.as_deref().unwrap_or_default(),
+            );
+        }
+    }
+}
+
+/// URL of the named route. Parameters fill in the route's path, e.g. `id` in `/users/:id`, and parameters
+/// the path doesn't have are added to the query. An `id` given to REST routes, created with `rest!` or `crud!`,
+/// is appended to the path. Used by [`url_for!`](crate::url_for).
+pub fn url_for(name: &str, params: &[(&str, &dyn Display)]) -> Result<String, Error> {
+    let route = ROUTES
+        .read()
+        .get(name)
+        .cloned()
+        .ok_or_else(|| Error::UnknownRoute(name.to_string()))?;
+
+    let mut used = vec![false; params.len()];
+    let mut param = |param: &str| {
+        let position = params
+            .iter()
+            .position(|(name, _)| *name == param)
+            .ok_or_else(|| Error::MissingRouteParameter(name.to_string(), param.to_string()))?;
+        used[position] = true;
+        Ok::<_, Error>(params[position].1.to_string())
+    };
+
+    let mut segments = vec![];
+    for segment in route.path.split("/") {
+        if let Some(name) = segment.strip_prefix(":") {
+            segments.push(urlencode(&param(name)?));
+        } else if let Some(name) = segment.strip_prefix("*") {
+            // Wildcard parameters can have slashes.
+            let value = param(name)?;
+            segments.push(
+                value
+                    .split("/")
+                    .map(urlencode)
+                    .collect::<Vec<_>>()
+                    .join("/"),
+            );
+        } else {
+            segments.push(segment.to_string());
+        }
+    }
+
+    let mut url = segments.join("/");
+
+    if route.rest {
+        if let Ok(id) = param("id") {
+            url = format!("{}/{}", url.trim_end_matches("/"), urlencode(&id));
+        }
+    }
+
+    let query = params
+        .iter()
+        .zip(used)
+        .filter(|(_, used)| !used)
+        .map(|((name, value), _)| format!("{}={}", urlencode(name), urlencode(&value.to_string())))
+        .collect::<Vec<_>>();
+
+    if !query.is_empty() {
+        url.push('?');
+        url.push_str(&query.join("&"));
     }
+
+    Ok(url)
+}
+
+/// Names of the parameters of the named route, in the order they appear in its path.
+/// REST routes have an `id` parameter at the end.
+pub(crate) fn route_parameters(name: &str) -> Result<Vec<String>, Error> {
+    let route = ROUTES
+        .read()
+        .get(name)
+        .cloned()
+        .ok_or_else(|| Error::UnknownRoute(name.to_string()))?;
+
+    let mut parameters = route
+        .path
+        .split("/")
+        .filter_map(|segment| segment.strip_prefix(":").or(segment.strip_prefix("*")))
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+
+    if route.rest {
+        parameters.push("id".to_string());
+    }
+
+    Ok(parameters)
+}
+
+/// Generate the URL of a named route, filling in its parameters. See [`url_for`](crate::http::router::url_for).
+///
+/// # Example
+///
+/// ```
+/// # use rwf::prelude::*;
+/// # #[derive(Default)]
+/// # struct UserController;
+/// # #[rwf::async_trait]
+/// # impl Controller for UserController {
+/// #     async fn handle(&self, request: &Request) -> Result<Response, Error> {
+/// #         Ok(Response::new())
+/// #     }
+/// # }
+/// rwf::http::Server::new(vec![route!("/users/:id" => UserController, as: "user")]);
+///
+/// assert_eq!(url_for!("user", id = 5).unwrap(), "/users/5");
+/// assert_eq!(url_for!("user", id = 5, tab = "posts").unwrap(), "/users/5?tab=posts");
+/// ```
+#[macro_export]
+macro_rules! url_for {
+    ($name:expr $(, $param:ident = $value:expr)* $(,)?) => {
+        $crate::http::router::url_for(
+            $name,
+            &[$((stringify!($param), &$value as &dyn ::std::fmt::Display)),*],
+        )
+    };
+}
+
+/// Path of the handler as displayed in logs, e.g. `/files/*` for wildcard routes, with the route's constraints,
+/// e.g. `GET /report.{csv,json} (admin.example.com)`.
+fn route_path(handler: &Handler) -> String {
+    let indicator = match handler.path_with_regex().path_type() {
+        PathType::Route | PathType::Rest => "",
+        PathType::Wildcard => {
+            if handler.path().base().ends_with("/") {
+                "*"
+            } else {
+                "/*"
+            }
+        }
+    };
+
+    let methods = match handler.allowed_methods() {
+        Some(methods) => format!(
+            "{} ",
+            methods
+                .iter()
+                .map(|method| method.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        None => String::new(),
+    };
+
+    let formats = match handler.route_formats() {
+        [] => String::new(),
+        formats => format!(
+            ".{{{}}}",
+            formats
+                .iter()
+                .map(|format| format.extension())
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+    };
+
+    let host = match handler.route_host() {
+        Some(host) => format!(" ({})", host),
+        None => String::new(),
+    };
+
+    format!(
+        "{}{}{}{}{}",
+        methods,
+        handler.path().path(),
+        indicator,
+        formats,
+        host
+    )
 }
 
 #[cfg(test)]
@@ -113,6 +472,7 @@ mod test {
     use crate::async_trait;
     use crate::controller::{Controller, Error as ControllerError};
     use crate::http::{Request, Response};
+    use crate::view::Value;
 
     struct OrdersControler {}
     struct UsersController {}
@@ -145,4 +505,136 @@ mod test {
         let result = handler.handle(&Request::default()).await.unwrap();
         assert_eq!(result.status().code(), 200);
     }
+
+    async fn request(method: &str, path: &str, host: &str) -> Request {
+        let req = format!("{} {} HTTP/1.1\r\nHost: {}\r\n\r\n", method, path, host);
+        Request::read("127.0.0.1:1234".parse().unwrap(), req.as_bytes())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_route_constraints() {
+        use crate::http::Format;
+
+        let router = Router::new(vec![
+            UsersController {}.route("/users").methods(&[Method::Get]),
+            OrdersControler {}.route("/users").methods(&[Method::Post]),
+            OrdersControler {}.route("/admin").subdomain("admin"),
+            UsersController {}
+                .route("/report")
+                .formats(&[Format::Csv, Format::Json]),
+        ])
+        .unwrap();
+
+        let route = |req: &Request| match router.route(req.path(), req) {
+            RouteMatch::Found(handler) => Ok(handler.controller_name()),
+            RouteMatch::MethodNotAllowed(methods) => Err(methods),
+            RouteMatch::NotFound => Err(vec![]),
+        };
+
+        assert_eq!(
+            route(&request("GET", "/users", "example.com").await),
+            Ok("rwf::http::router::test::UsersController")
+        );
+        assert_eq!(
+            route(&request("HEAD", "/users", "example.com").await),
+            Ok("rwf::http::router::test::UsersController")
+        );
+        assert_eq!(
+            route(&request("POST", "/users", "example.com").await),
+            Ok("rwf::http::router::test::OrdersControler")
+        );
+        assert_eq!(
+            route(&request("DELETE", "/users", "example.com").await),
+            Err(vec![Method::Get, Method::Post, Method::Head])
+        );
+
+        assert!(route(&request("GET", "/admin", "admin.example.com:8000").await).is_ok());
+        assert_eq!(
+            route(&request("GET", "/admin", "example.com").await),
+            Err(vec![])
+        );
+
+        for path in ["/report", "/report.csv", "/report.json"] {
+            assert!(route(&request("GET", path, "example.com").await).is_ok());
+        }
+        assert_eq!(
+            route(&request("GET", "/report.xml", "example.com").await),
+            Err(vec![])
+        );
+
+        let req = request("GET", "/report.csv", "example.com").await;
+        let params = router.find(req.path()).unwrap().path_with_regex().params();
+        let req = req.with_params(params);
+        assert_eq!(req.format(&[Format::Json, Format::Csv]), Some(Format::Csv));
+        assert_eq!(req.format(&[Format::Json]), None);
+
+        // Routes with other methods don't shadow each other.
+        assert!(router.coverage().shadowed().is_empty());
+
+        let response = Response::method_not_allowed().allow(&[Method::Get, Method::Head]);
+        assert_eq!(response.headers().get("allow").unwrap(), "GET, HEAD");
+    }
+
+    #[test]
+    fn test_url_for() {
+        Router::new(vec![
+            UsersController {}.route("/users/:id").name("test_user"),
+            OrdersControler {}
+                .route("/users/:user_id/files/*path")
+                .name("test_user_file"),
+            Handler::rest("/orders", OrdersControler {}).name("test_orders"),
+        ])
+        .expect("to compile");
+
+        assert_eq!(url_for!("test_user", id = 5).unwrap(), "/users/5");
+        assert_eq!(
+            url_for!("test_user", id = "a b", tab = "posts&more").unwrap(),
+            "/users/a%20b?tab=posts%26more"
+        );
+        assert_eq!(
+            url_for!("test_user_file", user_id = 1, path = "docs/a b.pdf").unwrap(),
+            "/users/1/files/docs/a%20b.pdf"
+        );
+        assert_eq!(url_for!("test_orders").unwrap(), "/orders");
+        assert_eq!(url_for!("test_orders", id = 7).unwrap(), "/orders/7");
+
+        assert!(matches!(
+            url_for!("test_user"),
+            Err(Error::MissingRouteParameter(_, param)) if param == "id"
+        ));
+        assert!(matches!(
+            url_for!("test_missing"),
+            Err(Error::UnknownRoute(_))
+        ));
+
+        assert_eq!(
+            route_parameters("test_user_file").unwrap(),
+            vec!["user_id", "path"]
+        );
+        assert_eq!(route_parameters("test_orders").unwrap(), vec!["id"]);
+
+        let template = crate::view::Template::from_str(
+            r#"<%= url_for("test_user", 5) %> <%= url_for("test_orders", order) %>"#,
+        )
+        .unwrap();
+        let mut context = crate::view::Context::new();
+        context
+            .set(
+                "order",
+                Value::Hash(HashMap::from([
+                    ("id".to_string(), Value::Integer(3)),
+                    ("total".to_string(), Value::Integer(10)),
+                ])),
+            )
+            .unwrap();
+        assert_eq!(template.render(&context).unwrap(), "/users/5 /orders/3");
+        assert!(
+            crate::view::Template::from_str(r#"<%= url_for("test_missing") %>"#)
+                .unwrap()
+                .render_default()
+                .is_err()
+        );
+    }
 }
@@ -4,41 +4,231 @@
 //! If no handler is matched, return `404 - Not Found`.
 //!
 //! The server is using Tokio and can support millions of concurrent clients.
-use super::{Error, Handler, Request, Response, Router};
+use super::tls::{Certificate, TlsConfig};
+use super::{
+    http2, post_process, problem, Error, Handler, OpenApi, Request, Response, RouteCoverage,
+    RouteMatch, Router,
+};
 
+use crate::cluster;
 use crate::colors::MaybeColorize;
-use crate::config::get_config;
+use crate::comms;
+use crate::config::{self, get_config};
+use crate::controller::info::Info;
+use crate::controller::middleware::{MiddlewareHandler, MiddlewareSet, Outcome};
+use crate::crypto;
+use crate::events::{self, Event, RequestFinished, RequestStarted};
+use crate::i18n;
+use crate::telemetry;
+use crate::view::cache;
 
+use std::future::Future;
+use std::io::ErrorKind;
 use std::net::SocketAddr;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::task::{Context, Poll};
 use std::time::{Duration, Instant};
 
-use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
-use tokio::net::{TcpListener, TcpStream};
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, BufWriter,
+    DuplexStream, ReadBuf,
+};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
 use tokio::select;
 use tokio::signal::ctrl_c;
-use tokio::task::JoinHandle;
-use tracing::{debug, error, info};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::{sleep, timeout, Sleep};
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+use tracing::{debug, error, info, Instrument};
+
+static METRICS: Metrics = Metrics::new();
+
+/// Environment variable with the listening socket passed by `rwf-cli serve`.
+pub const LISTEN_FD: &str = "RWF_LISTEN_FD";
+
+/// Connection and request counters since the server started.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ConnectionMetrics {
+    /// Connections currently open.
+    pub open_connections: usize,
+    /// Connections accepted.
+    pub connections: usize,
+    /// Requests received.
+    pub requests: usize,
+    /// Requests received over a connection which already served another request.
+    pub reused: usize,
+    /// Connections closed because the client didn't send a request in time.
+    pub idle_timeouts: usize,
+    /// Connections closed because the client stopped sending a request before it was complete.
+    pub read_timeouts: usize,
+    /// Connections closed because they served the maximum number of requests.
+    pub max_requests_reached: usize,
+}
+
+struct Metrics {
+    open_connections: AtomicUsize,
+    connections: AtomicUsize,
+    requests: AtomicUsize,
+    reused: AtomicUsize,
+    idle_timeouts: AtomicUsize,
+    read_timeouts: AtomicUsize,
+    max_requests_reached: AtomicUsize,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            open_connections: AtomicUsize::new(0),
+            connections: AtomicUsize::new(0),
+            requests: AtomicUsize::new(0),
+            reused: AtomicUsize::new(0),
+            idle_timeouts: AtomicUsize::new(0),
+            read_timeouts: AtomicUsize::new(0),
+            max_requests_reached: AtomicUsize::new(0),
+        }
+    }
+
+    /// Count the connection as open until the guard is dropped.
+    fn connection_opened(&'static self) -> OpenConnection {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+        self.open_connections.fetch_add(1, Ordering::Relaxed);
+        OpenConnection(self)
+    }
+
+    fn request(&self, served: usize) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if served > 1 {
+            self.reused.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn snapshot(&self) -> ConnectionMetrics {
+        ConnectionMetrics {
+            open_connections: self.open_connections.load(Ordering::Relaxed),
+            connections: self.connections.load(Ordering::Relaxed),
+            requests: self.requests.load(Ordering::Relaxed),
+            reused: self.reused.load(Ordering::Relaxed),
+            idle_timeouts: self.idle_timeouts.load(Ordering::Relaxed),
+            read_timeouts: self.read_timeouts.load(Ordering::Relaxed),
+            max_requests_reached: self.max_requests_reached.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct OpenConnection(&'static Metrics);
+
+/// Fails reads which wait for data for longer than the timeout.
+struct ReadTimeout<R> {
+    inner: R,
+    timeout: Duration,
+    sleep: Pin<Box<Sleep>>,
+}
+
+impl<R> ReadTimeout<R> {
+    fn new(inner: R, timeout: Duration) -> Self {
+        Self {
+            inner,
+            timeout,
+            sleep: Box::pin(sleep(timeout)),
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ReadTimeout<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+
+        match Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(result) => {
+                let deadline = tokio::time::Instant::now() + this.timeout;
+                this.sleep.as_mut().reset(deadline);
+                Poll::Ready(result)
+            }
+
+            Poll::Pending => match this.sleep.as_mut().poll(cx) {
+                Poll::Ready(()) => Poll::Ready(Err(ErrorKind::TimedOut.into())),
+                Poll::Pending => Poll::Pending,
+            },
+        }
+    }
+}
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        self.0.open_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
 
 /// Type of TCP connection used by the client.
 #[derive(Debug)]
 pub enum Stream<'a> {
     /// Plain text (not encrypted).
     Plain(&'a mut BufReader<BufWriter<TcpStream>>),
+    /// Encrypted with TLS.
+    Tls(&'a mut BufReader<BufWriter<TlsStream<TcpStream>>>),
+    /// In-memory connection, used by the [test client](crate::testing).
+    Memory(&'a mut BufReader<BufWriter<DuplexStream>>),
 }
 
 impl<'a> Stream<'a> {
     /// Get the underlying TCP stream reader & writer.
     pub fn stream(&'a mut self) -> impl AsyncRead + AsyncWrite + 'a {
-        match self {
-            Stream::Plain(stream) => stream,
-        }
+        let stream: &'a mut (dyn Io + 'a) = match self {
+            Stream::Plain(stream) => &mut **stream,
+            Stream::Tls(stream) => &mut **stream,
+            Stream::Memory(stream) => &mut **stream,
+        };
+        stream
+    }
+}
+
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+/// Connection accepted by the server, plain text or encrypted.
+pub(crate) trait Connection:
+    AsyncRead + AsyncWrite + Unpin + Send + Sized + 'static
+{
+    /// The connection is encrypted with TLS.
+    const TLS: bool = false;
+
+    fn stream(stream: &mut BufReader<BufWriter<Self>>) -> Stream<'_>;
+}
+
+impl Connection for TcpStream {
+    fn stream(stream: &mut BufReader<BufWriter<Self>>) -> Stream<'_> {
+        Stream::Plain(stream)
+    }
+}
+
+impl Connection for TlsStream<TcpStream> {
+    const TLS: bool = true;
+
+    fn stream(stream: &mut BufReader<BufWriter<Self>>) -> Stream<'_> {
+        Stream::Tls(stream)
+    }
+}
+
+impl Connection for DuplexStream {
+    fn stream(stream: &mut BufReader<BufWriter<Self>>) -> Stream<'_> {
+        Stream::Memory(stream)
     }
 }
 
 /// HTTP server.
+#[derive(Clone)]
 pub struct Server {
     handlers: Arc<Router>,
+    middleware: Vec<MiddlewareHandler>,
+    tls: TlsConfig,
 }
 
 impl Server {
@@ -49,39 +239,194 @@ impl Server {
     pub fn new(handlers: Vec<Handler>) -> Self {
         Server {
             handlers: Arc::new(Router::new(handlers).unwrap()),
+            middleware: vec![],
+            tls: TlsConfig::default(),
         }
     }
 
+    /// Add middleware that runs on every request.
+    ///
+    /// Server middleware runs before the request is routed, so it can rewrite the path
+    /// or reject requests that don't match any route. It runs before any middleware
+    /// configured on the controller, in the order it was added.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rwf::prelude::*;
+    /// # use rwf::http::Server;
+    /// use rwf::controller::middleware::{Middleware, RateLimiter};
+    ///
+    /// let server = Server::new(vec![])
+    ///     .middleware(RateLimiter::per_second(10).middleware());
+    /// ```
+    pub fn middleware(mut self, middleware: MiddlewareHandler) -> Self {
+        self.middleware.push(middleware);
+        self
+    }
+
+    /// Serve the [OpenAPI specification](super::openapi) of the routes, and optionally Swagger UI.
+    /// The specification is built from the routes added so far.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use rwf::http::{OpenApi, Server};
+    /// let server = Server::new(vec![])
+    ///     .openapi(OpenApi::new("My API", "1.0").swagger_ui("/docs"));
+    /// ```
+    pub fn openapi(mut self, openapi: OpenApi) -> Self {
+        let router = Arc::try_unwrap(self.handlers)
+            .unwrap_or_else(|_| panic!("openapi should be configured before the server is cloned"));
+        let mut handlers = router.into_handlers();
+        handlers.extend(openapi.handlers(&handlers));
+        self.handlers = Arc::new(Router::new(handlers).unwrap());
+        self
+    }
+
+    /// Serve a different certificate to clients connecting to `hostname`, using SNI,
+    /// when the server is launched with [`Server::launch_tls`].
+    ///
+    /// Wildcard certificates are supported, e.g. `*.example.com`. Clients asking for a hostname
+    /// without a matching certificate get the default one.
+    pub fn sni(
+        mut self,
+        hostname: &str,
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> Self {
+        self.tls
+            .hosts
+            .push((hostname.to_string(), Certificate::new(cert_path, key_path)));
+        self
+    }
+
+    /// Advertise application protocols to TLS clients using ALPN, in order of preference.
+    /// Clients which support none of them are refused.
+    ///
+    /// By default, the server offers HTTP/2 (`h2`) and HTTP/1.1 (`http/1.1`). Use `&["http/1.1"]`
+    /// to disable HTTP/2.
+    pub fn alpn(mut self, protocols: &[&str]) -> Self {
+        self.tls.alpn = protocols.iter().map(|p| p.to_string()).collect();
+        self
+    }
+
     /// Launch the server. This blocks until the server is shut down (`SIGINT`/Ctrl-C).
     pub async fn launch(self) -> Result<(), Error> {
         let config = get_config();
         let addr = format!("{}:{}", config.general.host, config.general.port);
+
+        self.listen(addr, None, ctrl_c()).await
+    }
+
+    /// Launch the server and stop accepting connections when the `shutdown` future
+    /// completes, instead of on `SIGINT`/Ctrl-C. Used when the server is supervised by [`crate::app::App`].
+    pub async fn launch_with_shutdown(
+        self,
+        shutdown: impl Future<Output = ()> + Send,
+    ) -> Result<(), Error> {
+        let config = get_config();
+        let addr = format!("{}:{}", config.general.host, config.general.port);
+
+        self.listen(addr, None, shutdown).await
+    }
+
+    /// Launch the server and accept HTTPS connections on `addr`, using the certificate
+    /// chain and private key stored in PEM files. This blocks until the server is shut down (`SIGINT`/Ctrl-C).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use rwf::http::Server;
+    /// # async fn launch() -> Result<(), rwf::http::Error> {
+    /// Server::new(vec![])
+    ///     .sni("admin.example.com", "admin.crt", "admin.key")
+    ///     .alpn(&["http/1.1"])
+    ///     .launch_tls("0.0.0.0:443", "fullchain.pem", "privkey.pem")
+    ///     .await
+    /// # }
+    /// ```
+    pub async fn launch_tls(
+        self,
+        addr: impl ToSocketAddrs,
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> Result<(), Error> {
+        let acceptor = self.tls.acceptor(&Certificate::new(cert_path, key_path))?;
+
+        self.listen(addr, Some(acceptor), ctrl_c()).await
+    }
+
+    async fn listen<T>(
+        self,
+        addr: impl ToSocketAddrs,
+        tls: Option<TlsAcceptor>,
+        shutdown: impl Future<Output = T>,
+    ) -> Result<(), Error> {
         info!(
             "Starting {} {} {}",
             "Rwf".green(),
-            "HTTP".purple(),
+            if tls.is_some() { "HTTPS" } else { "HTTP" }.purple(),
             "server".red()
         );
 
+        // Show what's deployed.
+        Info::collect().await.log();
+
         self.handlers.log_routes();
 
-        let listener = TcpListener::bind(addr).await?;
+        // Don't run with settings the app didn't ask for.
+        config::verify()?;
+
+        // Instances behind a load balancer must share their state.
+        cluster::verify()?;
+
+        // Receive WebSocket messages sent by other instances.
+        let backplane = comms::backplane::backplane();
+        if let Err(err) = backplane.start() {
+            error!("{} backplane error: {}", backplane.name(), err);
+        }
+
+        // Encrypted data must survive restarts.
+        crypto::verify_secret_key()?;
+
+        // Find template syntax errors before users do.
+        cache::verify()?;
+
+        let middleware = Arc::new(MiddlewareSet::without_default(self.middleware));
+
+        let listener = Self::bind(addr).await?;
 
         info!("Listening on {}", listener.local_addr().unwrap());
 
+        tokio::pin!(shutdown);
+
         loop {
             select! {
-                _ = ctrl_c() => {
+                _ = &mut shutdown => {
                     info!("Shutting down...");
+                    // Let clients reconnect to the new version of the app.
+                    comms::shutdown().await;
+                    telemetry::shutdown();
                     return Ok(());
                 }
 
                 result = listener.accept()  => {
                     if let Ok((stream, peer_addr)) = result {
                         let handlers = self.handlers.clone();
+                        let middleware = middleware.clone();
+                        let tls = tls.clone();
 
                         tokio::spawn(async move {
-                            match Self::handle_connection(handlers, stream, peer_addr).await {
+                            let connection = match tls {
+                                Some(acceptor) => match Self::handle_tls_connection(handlers, middleware, acceptor, stream, peer_addr).await {
+                                    Some(connection) => connection,
+                                    None => return,
+                                },
+                                None => Self::handle_connection(handlers, middleware, stream, peer_addr),
+                            };
+
+                            match connection.await {
                                 Ok(_) => (),
                                 Err(_) => {
                                     error!("panic detected, this is a bug; controllers should return an error instead");
@@ -94,111 +439,486 @@ impl Server {
         }
     }
 
-    fn handle_connection(
+    /// Listen on `addr`, or on the socket inherited from `rwf-cli serve`, which keeps it open
+    /// while the app restarts, so no connections are refused in between.
+    async fn bind(addr: impl ToSocketAddrs) -> Result<TcpListener, Error> {
+        #[cfg(unix)]
+        if let Ok(fd) = std::env::var(LISTEN_FD) {
+            use std::os::fd::FromRawFd;
+
+            if let Ok(fd) = fd.parse::<i32>() {
+                // Only one server owns the socket.
+                std::env::remove_var(LISTEN_FD);
+
+                // SAFETY: rwf-cli passes the listening socket it opened, and nothing else uses it in this process.
+                let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+                listener.set_nonblocking(true)?;
+                return Ok(TcpListener::from_std(listener)?);
+            }
+        }
+
+        Ok(TcpListener::bind(addr).await?)
+    }
+
+    /// Serve the connection using the protocol negotiated with ALPN, HTTP/2 or HTTP/1.1.
+    async fn handle_tls_connection(
         handlers: Arc<Router>,
+        middleware: Arc<MiddlewareSet>,
+        acceptor: TlsAcceptor,
+        stream: TcpStream,
+        peer_addr: SocketAddr,
+    ) -> Option<JoinHandle<()>> {
+        let stream = Self::handshake(acceptor, stream, peer_addr).await?;
+
+        if stream.get_ref().1.alpn_protocol() == Some(b"h2") {
+            Some(Self::handle_h2_connection(
+                handlers, middleware, stream, peer_addr,
+            ))
+        } else {
+            Some(Self::handle_connection(
+                handlers, middleware, stream, peer_addr,
+            ))
+        }
+    }
+
+    /// Perform the TLS handshake. Clients which don't complete it in time are disconnected.
+    async fn handshake(
+        acceptor: TlsAcceptor,
         stream: TcpStream,
         peer_addr: SocketAddr,
+    ) -> Option<TlsStream<TcpStream>> {
+        let handshake_timeout = get_config().general.keep_alive_timeout().unsigned_abs();
+
+        match timeout(handshake_timeout, acceptor.accept(stream)).await {
+            Ok(Ok(stream)) => Some(stream),
+            Ok(Err(err)) => {
+                debug!(
+                    "{} client {:?} tls handshake failed: {}",
+                    "http".purple(),
+                    peer_addr,
+                    err
+                );
+                None
+            }
+            Err(_) => {
+                debug!(
+                    "{} client {:?} tls handshake timed out",
+                    "http".purple(),
+                    peer_addr
+                );
+                None
+            }
+        }
+    }
+
+    /// Wait for the next request sent over the connection. The client can stay idle for `idle_timeout`
+    /// before it starts sending the request, and then for at most `read_timeout` between reads until
+    /// the request is received. Returns `None` if the client didn't start sending a request in time.
+    async fn read_request(
+        stream: &mut (impl AsyncBufRead + Unpin),
+        peer_addr: SocketAddr,
+        idle_timeout: Duration,
+        read_timeout: Duration,
+    ) -> Result<Option<Request>, Error> {
+        match timeout(idle_timeout, stream.fill_buf()).await {
+            Ok(result) => result?,
+            Err(_) => return Ok(None),
+        };
+
+        Request::read(peer_addr, ReadTimeout::new(stream, read_timeout))
+            .await
+            .map(Some)
+    }
+
+    /// Serve HTTP/1.1 requests sent over the connection, until it's closed.
+    pub(crate) fn handle_connection<S: Connection>(
+        handlers: Arc<Router>,
+        middleware: Arc<MiddlewareSet>,
+        stream: S,
+        peer_addr: SocketAddr,
     ) -> JoinHandle<()> {
         let mut stream = BufReader::new(BufWriter::new(stream));
 
         tokio::spawn(async move {
             debug!("{} new connection from {:?}", "http".purple(), peer_addr);
 
+            let general = &get_config().general;
+            let idle_timeout = general.keep_alive_timeout().unsigned_abs();
+            let read_timeout = general.read_timeout().unsigned_abs();
+            let max_requests = general.keep_alive_max_requests;
+
+            let _connection = METRICS.connection_opened();
+            let mut served = 0;
+
             loop {
-                let request = match Request::read(peer_addr, &mut stream).await {
-                    Ok(request) => request,
-                    Err(ref err) => {
-                        match err {
-                            Error::ContentTooLarge(head) => {
-                                let response = Response::content_too_large();
-                                let _ = Self::send_response(&mut stream, response).await;
-
-                                info!(
-                                    "{} {} {} 413",
-                                    head.method().to_string().purple(),
-                                    head.path().base().purple(),
-                                    std::any::type_name::<Self>().green(),
-                                );
-                            }
+                let request =
+                    match Self::read_request(&mut stream, peer_addr, idle_timeout, read_timeout)
+                        .await
+                    {
+                        Ok(Some(request)) => request.with_tls(S::TLS),
+                        Ok(None) => {
+                            METRICS.idle_timeouts.fetch_add(1, Ordering::Relaxed);
+                            debug!(
+                                "{} client {:?} idle for {:?}, closing connection",
+                                "http".purple(),
+                                peer_addr,
+                                idle_timeout,
+                            );
+                            break;
+                        }
+                        Err(ref err) => {
+                            match err {
+                                Error::ContentTooLarge(head) => {
+                                    let response =
+                                        Response::content_too_large().header("connection", "close");
+                                    let _ = Self::send_response(&mut stream, response, true).await;
 
-                            _ => (),
+                                    info!(
+                                        "{} {} {} 413",
+                                        head.method().to_string().purple(),
+                                        head.path().base().purple(),
+                                        std::any::type_name::<Self>().green(),
+                                    );
+                                }
+                                Error::Io(err) if err.kind() == ErrorKind::TimedOut => {
+                                    METRICS.read_timeouts.fetch_add(1, Ordering::Relaxed);
+                                }
+                                _ => (),
+                            }
+                            debug!(
+                                "{} client {:?} disconnected: {}",
+                                "http".purple(),
+                                peer_addr,
+                                err
+                            );
+                            break;
                         }
+                    };
+
+                served += 1;
+                METRICS.request(served);
+
+                let start = Instant::now();
+                let span = telemetry::request_span(&request);
+                events::emit(Event::RequestStarted(RequestStarted { request: &request }));
+
+                let (request, response, handler) =
+                    Self::handle_request(&handlers, &middleware, request)
+                        .instrument(span.clone())
+                        .await;
+
+                // Set the session on the request before we pass it down
+                // to the stream handler.
+                let request = match response.session().clone() {
+                    Some(session) => request.set_session(session),
+                    None => request,
+                };
+                let ok = response.status().ok();
+
+                // Calculate duration.
+                // We include the time to find the handler in the duration.
+                let duration = start.elapsed();
+
+                // Log request.
+                let controller_name = match handler {
+                    Some(handler) => handler.controller_name(),
+                    None => std::any::type_name::<Self>(),
+                };
+                span.in_scope(|| Self::log(&request, controller_name, &response, duration));
+                telemetry::record_request(&span, &request, handler, &response, duration);
+                events::emit(Event::RequestFinished(RequestFinished {
+                    request: &request,
+                    response: &response,
+                    controller: controller_name,
+                    duration,
+                }));
+
+                let last = max_requests > 0 && served >= max_requests;
+                if last && request.keep_alive() {
+                    METRICS.max_requests_reached.fetch_add(1, Ordering::Relaxed);
+                }
+                let keep_alive = request.keep_alive() && !last;
+
+                // Pipelined requests are already buffered, so the responses
+                // can be sent together.
+                let flush =
+                    !keep_alive || stream.buffer().is_empty() || response.status().code() == 101;
+
+                if response.dropped() {
+                    debug!("{} dropping connection to {:?}", "http".purple(), peer_addr);
+                    break;
+                }
+
+                let response = Self::connection_headers(response, keep_alive, idle_timeout);
+
+                if let Err(err) = Self::send_response(&mut stream, response, flush).await {
+                    debug!("{} error {:?}", peer_addr, err);
+                    break;
+                }
+
+                if let (true, Some(handler)) = (ok, handler) {
+                    match handler
+                        .handle_stream(&request, S::stream(&mut stream))
+                        .instrument(span)
+                        .await
+                    {
+                        Ok(true) => (),
+                        _ => break,
+                    };
+                }
+
+                if !keep_alive {
+                    break;
+                }
+            }
+
+            // Flush and close the connection, e.g. send TLS close_notify.
+            let _ = stream.shutdown().await;
+        })
+    }
+
+    /// Serve requests sent over HTTP/2. Each stream is handled in its own task,
+    /// so requests sent in parallel don't wait for each other.
+    fn handle_h2_connection(
+        handlers: Arc<Router>,
+        middleware: Arc<MiddlewareSet>,
+        stream: TlsStream<TcpStream>,
+        peer_addr: SocketAddr,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            debug!("{} new h2 connection from {:?}", "http".purple(), peer_addr);
+
+            let general = &get_config().general;
+            let idle_timeout = general.keep_alive_timeout().unsigned_abs();
+            let max_requests = general.keep_alive_max_requests;
+
+            let _connection = METRICS.connection_opened();
+
+            let mut connection = match h2::server::handshake(stream).await {
+                Ok(connection) => connection,
+                Err(err) => {
+                    debug!(
+                        "{} client {:?} h2 handshake failed: {}",
+                        "http".purple(),
+                        peer_addr,
+                        err
+                    );
+                    return;
+                }
+            };
+
+            let mut streams = JoinSet::new();
+            let mut served = 0;
+            let mut closing = false;
+
+            // The connection has to be polled until it's closed, so the responses
+            // of streams still in flight are sent to the client.
+            loop {
+                while let Some(result) = streams.try_join_next() {
+                    if result.is_err() {
+                        error!("panic detected, this is a bug; controllers should return an error instead");
+                    }
+                }
+
+                let (request, respond) = match timeout(idle_timeout, connection.accept()).await {
+                    Ok(Some(Ok(stream))) => stream,
+                    Ok(Some(Err(err))) => {
                         debug!(
                             "{} client {:?} disconnected: {}",
                             "http".purple(),
                             peer_addr,
                             err
                         );
-                        return;
+                        break;
+                    }
+                    Ok(None) => break,
+                    Err(_) => {
+                        if streams.is_empty() && !closing {
+                            METRICS.idle_timeouts.fetch_add(1, Ordering::Relaxed);
+                            debug!(
+                                "{} client {:?} idle for {:?}, closing connection",
+                                "http".purple(),
+                                peer_addr,
+                                idle_timeout,
+                            );
+                            connection.graceful_shutdown();
+                            closing = true;
+                        }
+                        continue;
                     }
                 };
 
-                let start = Instant::now();
+                served += 1;
+                METRICS.request(served);
 
-                match handlers.find(request.path()) {
-                    Some(handler) => {
-                        // Set the matching regex to extract parameters.
-                        let request = request.with_params(handler.path_with_regex().params());
-
-                        // Pass the request to the controller to get a response.
-                        let response = match handler.handle_internal(request.clone()).await {
-                            Ok(response) => response,
-                            Err(err) => {
-                                error!("{}", err);
-                                Response::internal_error(err)
-                            }
-                        };
-
-                        // Set the session on the request before we pass it down
-                        // to the stream handler.
-                        let request = match response.session().clone() {
-                            Some(session) => request.set_session(session),
-                            None => request,
-                        };
-                        let ok = response.status().ok();
-
-                        // Calculate duration.
-                        // We include the time to find the handler in the duration.
-                        let duration = start.elapsed();
+                if max_requests > 0 && served >= max_requests && !closing {
+                    METRICS.max_requests_reached.fetch_add(1, Ordering::Relaxed);
+                    connection.graceful_shutdown();
+                    closing = true;
+                }
 
-                        // Log request.
-                        Self::log(&request, handler.controller_name(), &response, duration);
+                let handlers = handlers.clone();
+                let middleware = middleware.clone();
 
-                        if let Err(err) = Self::send_response(&mut stream, response).await {
-                            debug!("{} error {:?}", peer_addr, err);
-                            break;
-                        }
+                streams.spawn(async move {
+                    let start = Instant::now();
+                    let head_only = request.method() == http::Method::HEAD;
 
-                        if ok {
-                            match handler
-                                .handle_stream(&request, Stream::Plain(&mut stream))
-                                .await
-                            {
-                                Ok(true) => continue,
-                                _ => break,
+                    let request = match http2::read_request(peer_addr, request).await {
+                        // HTTP/2 is only served over TLS.
+                        Ok(request) => request.with_tls(true),
+                        Err(err) => {
+                            debug!(
+                                "{} client {:?} sent invalid request: {}",
+                                "http".purple(),
+                                peer_addr,
+                                err
+                            );
+                            let response = match err {
+                                Error::ContentTooLarge(_) => Response::content_too_large(),
+                                _ => Response::bad_request(),
                             };
+                            let _ = http2::send_response(respond, response, head_only).await;
+                            return;
                         }
+                    };
+
+                    let span = telemetry::request_span(&request);
+                    events::emit(Event::RequestStarted(RequestStarted { request: &request }));
+
+                    let (request, response, handler) =
+                        Self::handle_request(&handlers, &middleware, request)
+                            .instrument(span.clone())
+                            .await;
+
+                    let controller_name = match handler {
+                        Some(handler) => handler.controller_name(),
+                        None => std::any::type_name::<Self>(),
+                    };
+                    let duration = start.elapsed();
+                    span.in_scope(|| Self::log(&request, controller_name, &response, duration));
+                    telemetry::record_request(&span, &request, handler, &response, duration);
+                    events::emit(Event::RequestFinished(RequestFinished {
+                        request: &request,
+                        response: &response,
+                        controller: controller_name,
+                        duration,
+                    }));
+
+                    if let Err(err) = http2::send_response(respond, response, head_only).await {
+                        debug!("{} error {:?}", peer_addr, err);
                     }
+                });
+            }
+        })
+    }
 
-                    None => {
-                        // Log duration of search.
-                        let duration = Instant::now() - start;
+    /// Tell the client if the connection will stay open after the response.
+    fn connection_headers(
+        response: Response,
+        keep_alive: bool,
+        idle_timeout: Duration,
+    ) -> Response {
+        // Upgraded connections, e.g. WebSocket, are handled by the controller.
+        if response.status().code() == 101 {
+            return response;
+        }
+
+        if keep_alive {
+            response
+                .header("connection", "keep-alive")
+                .header("keep-alive", format!("timeout={}", idle_timeout.as_secs()))
+        } else {
+            response.header("connection", "close")
+        }
+    }
 
-                        // Generate default not found response.
-                        let response = Response::not_found();
+    /// Pass the request through the server middleware and to the controller
+    /// matching the path, if any.
+    pub(crate) async fn handle_request<'a>(
+        handlers: &'a Router,
+        middleware: &MiddlewareSet,
+        request: Request,
+    ) -> (Request, Response, Option<&'a Handler>) {
+        let (outcome, executed) = match middleware.handle_request(request.clone()).await {
+            Ok(result) => result,
+            Err(err) => {
+                error!("{}", err);
+                return (request, Response::internal_error(err), None);
+            }
+        };
 
-                        // Log the response.
-                        Self::log(&request, std::any::type_name::<Self>(), &response, duration);
+        let (request, response, handler) = match outcome {
+            Outcome::Stop(request, response) => (request, response, None),
+            Outcome::Forward(request) => match handlers.route(request.path(), &request) {
+                RouteMatch::Found(handler) => {
+                    // Set the matching regex to extract parameters.
+                    let request = request
+                        .with_params(handler.path_with_regex().params())
+                        .with_route(handler.path().path());
 
-                        // Send reply to client.
-                        if let Err(err) = Self::send_response(&mut stream, response).await {
-                            debug!("{} error {:?}", peer_addr, err);
-                            break;
+                    // Pass the request to the controller to get a response,
+                    // translating messages in the request's locale.
+                    let locale = i18n::negotiate(&request);
+                    let response = i18n::scope(locale, handler.handle_internal(request.clone()));
+                    let response = match response.await {
+                        Ok(response) => response,
+                        Err(err) => {
+                            error!("{}", err);
+                            Response::internal_error(err)
                         }
-                    }
+                    };
+
+                    (request, response, Some(handler))
                 }
+
+                RouteMatch::MethodNotAllowed(methods) => (
+                    request,
+                    Response::method_not_allowed().allow(&methods),
+                    None,
+                ),
+
+                // Generate default not found response.
+                RouteMatch::NotFound => (request, Response::not_found(), None),
+            },
+        };
+
+        let response = match middleware
+            .handle_response(&request, response, executed)
+            .await
+        {
+            Ok(response) => response,
+            Err(err) => {
+                error!("{}", err);
+                Response::internal_error(err)
             }
-        })
+        };
+
+        // API clients get errors as JSON.
+        let response = problem::api_error(response, &request);
+
+        let response = match response.save_session(&request).await {
+            Ok(response) => response,
+            Err(err) => {
+                error!("{}", err);
+                Response::internal_error(err)
+            }
+        };
+
+        // Rewrite HTML before it's compressed.
+        let response = match post_process::process(&request, response) {
+            Ok(response) => response,
+            Err(err) => {
+                error!("{}", err);
+                Response::internal_error(err)
+            }
+        };
+
+        let response = response
+            .compress(&request, &get_config().compression)
+            .header("x-request-id", request.id());
+
+        (request, response, handler)
     }
 
     fn log(request: &Request, controller_name: &str, response: &Response, duration: Duration) {
@@ -220,10 +940,365 @@ impl Server {
     async fn send_response(
         mut stream: impl AsyncWrite + Unpin,
         response: Response,
+        flush: bool,
     ) -> Result<(), Error> {
         response.send(&mut stream).await?;
-        stream.flush().await?;
+
+        if flush {
+            stream.flush().await?;
+        }
 
         Ok(())
     }
+
+    /// Serve the routes on a random port, for tests.
+    #[cfg(test)]
+    pub(crate) async fn serve_test(handlers: Arc<Router>) -> SocketAddr {
+        let middleware = Arc::new(MiddlewareSet::without_default(vec![]));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((stream, peer)) = listener.accept().await {
+                Self::handle_connection(handlers.clone(), middleware.clone(), stream, peer);
+            }
+        });
+
+        addr
+    }
+
+    /// Routes and middleware of the server, e.g. to serve requests in-process with the [`Harness`](super::Harness).
+    pub(crate) fn into_parts(self) -> (Arc<Router>, Vec<MiddlewareHandler>) {
+        (self.handlers, self.middleware)
+    }
+
+    /// Connection and request counters, e.g. to check that clients reuse connections.
+    pub fn metrics() -> ConnectionMetrics {
+        METRICS.snapshot()
+    }
+
+    /// Requests routed to each controller, e.g. to check that integration tests exercised all routes.
+    /// Clones of the server share the counters, so clone it before launching.
+    pub fn coverage(&self) -> RouteCoverage {
+        self.handlers.coverage()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::async_trait;
+    use crate::controller::middleware::Middleware;
+    use crate::controller::{Controller, Error as ControllerError};
+    use crate::http::request::test::dummy_ip;
+
+    struct IndexController;
+
+    #[async_trait]
+    impl Controller for IndexController {
+        async fn handle(&self, _request: &Request) -> Result<Response, ControllerError> {
+            Ok(Response::new().text("index"))
+        }
+    }
+
+    struct CookieController;
+
+    #[async_trait]
+    impl Controller for CookieController {
+        fn skip_csrf(&self) -> bool {
+            true
+        }
+
+        async fn handle(&self, request: &Request) -> Result<Response, ControllerError> {
+            let cookie = request.header("cookie").cloned().unwrap_or_default();
+            let body = String::from_utf8_lossy(request.body());
+            Ok(Response::new().text(format!("{} {}", cookie, body)))
+        }
+    }
+
+    struct Blocklist;
+
+    #[async_trait]
+    impl Middleware for Blocklist {
+        async fn handle_request(&self, request: Request) -> Result<Outcome, ControllerError> {
+            if request.path().base() == "/blocked" {
+                Ok(Outcome::Stop(request, Response::forbidden()))
+            } else {
+                Ok(Outcome::Forward(request))
+            }
+        }
+
+        async fn handle_response(
+            &self,
+            _request: &Request,
+            response: Response,
+        ) -> Result<Response, ControllerError> {
+            Ok(response.header("x-server-middleware", "1"))
+        }
+    }
+
+    async fn request(path: &str) -> Request {
+        let request = format!("GET {} HTTP/1.1\r\nContent-Length: 0\r\n\r\n", path);
+        Request::read(dummy_ip(), request.as_bytes()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_server_middleware() {
+        let router = Router::new(vec![IndexController.route("/")]).unwrap();
+        let middleware = MiddlewareSet::without_default(vec![Blocklist.middleware()]);
+
+        for (path, code, routed) in [
+            ("/", 200, true),
+            ("/blocked", 403, false),
+            ("/missing", 404, false),
+        ] {
+            let (_, response, handler) =
+                Server::handle_request(&router, &middleware, request(path).await).await;
+            assert_eq!(response.status().code(), code);
+            assert_eq!(handler.is_some(), routed);
+            // Middleware that stopped the request doesn't process the response.
+            assert_eq!(
+                response.headers().get("x-server-middleware").is_some(),
+                code != 403
+            );
+        }
+    }
+
+    async fn exchange(requests: &str) -> String {
+        use tokio::io::AsyncReadExt;
+
+        let router = Arc::new(Router::new(vec![IndexController.route("/")]).unwrap());
+        let middleware = Arc::new(MiddlewareSet::without_default(vec![]));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let (stream, peer) = listener.accept().await.unwrap();
+        let connection = Server::handle_connection(router, middleware, stream, peer);
+
+        client.write_all(requests.as_bytes()).await.unwrap();
+
+        let mut responses = String::new();
+        client.read_to_string(&mut responses).await.unwrap();
+        connection.await.unwrap();
+
+        responses
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive() {
+        let before = Server::metrics();
+
+        // Pipelined requests are all answered, until the client closes the connection.
+        let responses = exchange(
+            "GET / HTTP/1.1\r\n\r\nGET /missing HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nConnection: close\r\n\r\nGET / HTTP/1.1\r\n\r\n",
+        )
+        .await;
+        assert_eq!(responses.matches("HTTP/1.1 200\r\n").count(), 2);
+        assert_eq!(responses.matches("HTTP/1.1 404\r\n").count(), 1);
+        assert_eq!(responses.matches("connection: keep-alive").count(), 2);
+        assert!(responses.ends_with("index"));
+        assert!(responses.contains("connection: close"));
+
+        let after = Server::metrics();
+        assert!(after.requests >= before.requests + 3);
+        assert!(after.reused >= before.reused + 2);
+
+        // HTTP/1.0 clients have to ask for keep-alive.
+        let responses = exchange("GET / HTTP/1.0\r\n\r\nGET / HTTP/1.0\r\n\r\n").await;
+        assert_eq!(responses.matches("HTTP/1.1 200\r\n").count(), 1);
+        assert!(responses.contains("connection: close"));
+    }
+
+    #[tokio::test]
+    async fn test_read_timeouts() {
+        let idle = Duration::from_millis(50);
+        let read = Duration::from_millis(500);
+        let peer = crate::http::request::test::dummy_ip();
+
+        // The client never starts sending a request.
+        let (_client, server) = tokio::io::duplex(1024);
+        let mut server = BufReader::new(server);
+        let request = Server::read_request(&mut server, peer, idle, read).await;
+        assert!(matches!(request, Ok(None)));
+
+        // The body arrives after the idle timeout, but within the read timeout.
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut server = BufReader::new(server);
+        let sender = tokio::spawn(async move {
+            client
+                .write_all(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\n")
+                .await
+                .unwrap();
+            tokio::time::sleep(idle * 3).await;
+            client.write_all(b"hello").await.unwrap();
+            client
+        });
+        let request = Server::read_request(&mut server, peer, idle, read)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.body(), b"hello");
+        let mut client = sender.await.unwrap();
+
+        // The client stops sending the body.
+        client
+            .write_all(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhel")
+            .await
+            .unwrap();
+        let request = Server::read_request(&mut server, peer, idle, read).await;
+        assert!(matches!(request, Err(Error::Io(ref err)) if err.kind() == ErrorKind::TimedOut));
+    }
+
+    /// Connect to a TLS server using a self-signed certificate, negotiating one of the protocols.
+    async fn connect_tls(
+        alpn: &[&str],
+    ) -> (
+        tokio_rustls::client::TlsStream<TcpStream>,
+        JoinHandle<()>,
+        tempdir::TempDir,
+    ) {
+        use crate::http::tls::test::self_signed;
+        use tokio_rustls::rustls::{
+            crypto::ring, pki_types::ServerName, ClientConfig, RootCertStore,
+        };
+        use tokio_rustls::TlsConnector;
+
+        let dir = tempdir::TempDir::new("rwf_server_tls").unwrap();
+        let certificate = self_signed(dir.path(), "localhost");
+        let acceptor = TlsConfig::default().acceptor(&certificate).unwrap();
+
+        let mut roots = RootCertStore::empty();
+        let pem = std::fs::read(&certificate.cert_path).unwrap();
+        for cert in rustls_pemfile::certs(&mut pem.as_slice()) {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        let mut config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        config.alpn_protocols = alpn.iter().map(|p| p.as_bytes().to_vec()).collect();
+        let connector = TlsConnector::from(Arc::new(config));
+
+        let router = Arc::new(
+            Router::new(vec![
+                IndexController.route("/"),
+                CookieController.route("/cookies"),
+            ])
+            .unwrap(),
+        );
+        let middleware = Arc::new(MiddlewareSet::without_default(vec![]));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let (stream, peer) = listener.accept().await.unwrap();
+            Server::handle_tls_connection(router, middleware, acceptor, stream, peer)
+                .await
+                .unwrap()
+                .await
+                .unwrap();
+        });
+
+        let client = TcpStream::connect(addr).await.unwrap();
+        let client = connector
+            .connect(ServerName::try_from("localhost").unwrap(), client)
+            .await
+            .unwrap();
+
+        (client, server, dir)
+    }
+
+    #[tokio::test]
+    async fn test_tls() {
+        use tokio::io::AsyncReadExt;
+
+        let (mut client, server, _dir) = connect_tls(&["http/1.1"]).await;
+        assert_eq!(client.get_ref().1.alpn_protocol(), Some(&b"http/1.1"[..]));
+
+        client
+            .write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        server.await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200\r\n"));
+        assert!(response.ends_with("index"));
+    }
+
+    #[tokio::test]
+    async fn test_h2() {
+        let (client, server, _dir) = connect_tls(&["h2", "http/1.1"]).await;
+        assert_eq!(client.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+
+        let (sender, connection) = h2::client::handshake(client).await.unwrap();
+        tokio::spawn(connection);
+
+        // Send all requests before reading any response.
+        let mut responses = vec![];
+        for (method, path) in [
+            ("GET", "/"),
+            ("GET", "/missing"),
+            ("HEAD", "/"),
+            ("POST", "/cookies"),
+        ] {
+            let mut sender = sender.clone().ready().await.unwrap();
+            let request = http::Request::builder()
+                .method(method)
+                .uri(format!("https://localhost{}", path))
+                .header("cookie", "a=1")
+                .header("cookie", "b=2")
+                .body(())
+                .unwrap();
+            let (response, mut body) = sender.send_request(request, method != "POST").unwrap();
+            if method == "POST" {
+                body.send_data(bytes::Bytes::from_static(b"name=rwf"), true)
+                    .unwrap();
+            }
+            responses.push(response);
+        }
+
+        let mut results = vec![];
+        for response in responses {
+            let response = response.await.unwrap();
+            let status = response.status().as_u16();
+            assert!(response.headers().get("connection").is_none());
+
+            let mut body = response.into_body();
+            let mut content = vec![];
+            while let Some(chunk) = body.data().await {
+                content.extend_from_slice(&chunk.unwrap());
+            }
+            results.push((status, String::from_utf8(content).unwrap()));
+        }
+
+        assert_eq!(results[0], (200, "index".to_string()));
+        assert_eq!(results[1].0, 404);
+        assert_eq!(results[2], (200, String::new()));
+        // Cookies split into separate headers are joined back together.
+        assert_eq!(results[3], (200, "a=1; b=2 name=rwf".to_string()));
+
+        drop(sender);
+        server.await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_inherited_listener() {
+        use std::os::fd::IntoRawFd;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::env::set_var(LISTEN_FD, listener.into_raw_fd().to_string());
+
+        let listener = Server::bind("127.0.0.1:0").await.unwrap();
+        assert_eq!(listener.local_addr().unwrap(), addr);
+        assert!(std::env::var(LISTEN_FD).is_err());
+    }
 }
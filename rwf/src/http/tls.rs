@@ -0,0 +1,205 @@
+//! TLS termination, using [rustls](https://docs.rs/rustls).
+//!
+//! Certificates and private keys are read from PEM files, e.g. the ones issued by Let's Encrypt.
+//! Additional certificates can be served to clients asking for a specific hostname using SNI,
+//! and the application protocols supported by the server can be advertised using ALPN.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokio_rustls::rustls::{
+    crypto::{ring, CryptoProvider},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    Error as TlsError, ServerConfig,
+};
+use tokio_rustls::TlsAcceptor;
+
+use super::Error;
+
+/// Certificate and private key paths.
+#[derive(Debug, Clone)]
+pub(crate) struct Certificate {
+    pub(crate) cert_path: PathBuf,
+    pub(crate) key_path: PathBuf,
+}
+
+impl Certificate {
+    pub(crate) fn new(cert_path: impl AsRef<Path>, key_path: impl AsRef<Path>) -> Self {
+        Self {
+            cert_path: cert_path.as_ref().to_path_buf(),
+            key_path: key_path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Read the certificate chain and the private key.
+    fn load(&self, provider: &CryptoProvider) -> Result<Arc<CertifiedKey>, Error> {
+        let mut reader = BufReader::new(File::open(&self.cert_path)?);
+        let certs = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+
+        if certs.is_empty() {
+            return Err(TlsError::General(format!(
+                "no certificates found in \"{}\"",
+                self.cert_path.display()
+            ))
+            .into());
+        }
+
+        let mut reader = BufReader::new(File::open(&self.key_path)?);
+        let key = rustls_pemfile::private_key(&mut reader)?.ok_or_else(|| {
+            TlsError::General(format!(
+                "no private key found in \"{}\"",
+                self.key_path.display()
+            ))
+        })?;
+
+        Ok(Arc::new(CertifiedKey::from_der(certs, key, provider)?))
+    }
+}
+
+/// Server TLS settings.
+#[derive(Debug, Clone)]
+pub(crate) struct TlsConfig {
+    /// Certificates served to clients asking for a specific hostname.
+    pub(crate) hosts: Vec<(String, Certificate)>,
+    /// Application protocols, in order of preference.
+    pub(crate) alpn: Vec<String>,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            hosts: vec![],
+            alpn: vec!["h2".into(), "http/1.1".into()],
+        }
+    }
+}
+
+impl TlsConfig {
+    /// Load the certificates and create the TLS acceptor.
+    pub(crate) fn acceptor(&self, default: &Certificate) -> Result<TlsAcceptor, Error> {
+        let provider = Arc::new(ring::default_provider());
+
+        let mut hosts = HashMap::new();
+        for (hostname, certificate) in &self.hosts {
+            hosts.insert(hostname.to_lowercase(), certificate.load(&provider)?);
+        }
+
+        let resolver = Resolver {
+            default: default.load(&provider)?,
+            hosts,
+        };
+
+        let mut config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::new(resolver));
+        config.alpn_protocols = self
+            .alpn
+            .iter()
+            .map(|protocol| protocol.as_bytes().to_vec())
+            .collect();
+
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+/// Pick the certificate using the hostname sent by the client (SNI).
+#[derive(Debug)]
+struct Resolver {
+    default: Arc<CertifiedKey>,
+    hosts: HashMap<String, Arc<CertifiedKey>>,
+}
+
+impl Resolver {
+    fn find(&self, hostname: Option<&str>) -> Arc<CertifiedKey> {
+        hostname
+            .map(|hostname| hostname.to_lowercase())
+            .and_then(|hostname| {
+                self.hosts.get(&hostname).or_else(|| {
+                    // Wildcard certificates match one label, e.g. "*.example.com"
+                    // matches "www.example.com" but not "example.com".
+                    let (_, domain) = hostname.split_once('.')?;
+                    self.hosts.get(&format!("*.{}", domain))
+                })
+            })
+            .unwrap_or(&self.default)
+            .clone()
+    }
+}
+
+impl ResolvesServerCert for Resolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.find(client_hello.server_name()))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    pub(crate) fn self_signed(dir: &Path, hostname: &str) -> Certificate {
+        let generated = rcgen::generate_simple_self_signed(vec![hostname.to_string()]).unwrap();
+        let cert_path = dir.join(format!("{}.crt", hostname));
+        let key_path = dir.join(format!("{}.key", hostname));
+        std::fs::write(&cert_path, generated.cert.pem()).unwrap();
+        std::fs::write(&key_path, generated.key_pair.serialize_pem()).unwrap();
+
+        Certificate::new(cert_path, key_path)
+    }
+
+    #[test]
+    fn test_resolver() {
+        let dir = tempdir::TempDir::new("rwf_tls").unwrap();
+        let provider = ring::default_provider();
+
+        let default = self_signed(dir.path(), "localhost")
+            .load(&provider)
+            .unwrap();
+        let example = self_signed(dir.path(), "example.com")
+            .load(&provider)
+            .unwrap();
+        let wildcard = self_signed(dir.path(), "*.example.com")
+            .load(&provider)
+            .unwrap();
+
+        let resolver = Resolver {
+            default: default.clone(),
+            hosts: HashMap::from([
+                ("example.com".to_string(), example.clone()),
+                ("*.example.com".to_string(), wildcard.clone()),
+            ]),
+        };
+
+        for (hostname, expected) in [
+            (None, &default),
+            (Some("localhost"), &default),
+            (Some("EXAMPLE.com"), &example),
+            (Some("www.example.com"), &wildcard),
+            (Some("a.www.example.com"), &default),
+        ] {
+            assert_eq!(resolver.find(hostname).cert, expected.cert);
+        }
+    }
+
+    #[test]
+    fn test_acceptor() {
+        let dir = tempdir::TempDir::new("rwf_tls").unwrap();
+        let certificate = self_signed(dir.path(), "localhost");
+
+        let config = TlsConfig {
+            hosts: vec![],
+            alpn: vec!["http/1.1".into()],
+        };
+        assert!(config.acceptor(&certificate).is_ok());
+
+        // The private key is not a certificate.
+        let swapped = Certificate::new(&certificate.key_path, &certificate.key_path);
+        assert!(config.acceptor(&swapped).is_err());
+
+        let missing = Certificate::new(dir.path().join("missing.crt"), &certificate.key_path);
+        assert!(matches!(config.acceptor(&missing), Err(Error::Io(_))));
+    }
+}
@@ -0,0 +1,210 @@
+//! Request IDs and [W3C Trace Context](https://www.w3.org/TR/trace-context/) propagation.
+//!
+//! Each request is given an ID, taken from the `X-Request-Id` header if the client (e.g. a load balancer)
+//! sent one, or generated otherwise. The ID is returned in the `X-Request-Id` response header and added to all log lines
+//! written while the request is handled.
+//!
+//! If `trace_context` is enabled, the `traceparent` header is read as well, so requests can be followed
+//! through multiple services in a distributed trace. Use [`crate::http::client::Client::propagate`]
+//! to pass the request ID and the trace to other services.
+use rand::Rng;
+use uuid::Uuid;
+
+use super::Head;
+use crate::config::get_config;
+
+/// Maximum length of a request ID accepted from the client.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Position of the request in a distributed trace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceContext {
+    /// ID of the whole trace, shared by all services, as 32 hex characters.
+    pub trace_id: String,
+    /// ID of the caller's span, if the request was part of a trace already.
+    pub parent_id: Option<String>,
+    /// ID of the span handling this request, as 16 hex characters.
+    pub span_id: String,
+    /// Trace flags, e.g. `01` if the trace is sampled.
+    pub flags: u8,
+    /// Vendor-specific trace data, passed along unchanged.
+    pub state: Option<String>,
+}
+
+impl TraceContext {
+    /// Start a new trace.
+    pub fn new() -> Self {
+        Self {
+            trace_id: random_hex(16),
+            parent_id: None,
+            span_id: random_hex(8),
+            flags: 1,
+            state: None,
+        }
+    }
+
+    /// Continue the trace from a `traceparent` header. Returns `None` if the header is invalid.
+    pub fn parse(traceparent: &str) -> Option<Self> {
+        let parts = traceparent.trim().split('-').collect::<Vec<_>>();
+
+        if parts.len() < 4 {
+            return None;
+        }
+
+        let (version, trace_id, parent_id, flags) = (parts[0], parts[1], parts[2], parts[3]);
+
+        // Version 00 has exactly four fields, future versions may add more.
+        let valid = hex(version, 2)
+            && version != "ff"
+            && (version != "00" || parts.len() == 4)
+            && hex(trace_id, 32)
+            && hex(parent_id, 16)
+            && hex(flags, 2)
+            && !zeros(trace_id)
+            && !zeros(parent_id);
+
+        if !valid {
+            return None;
+        }
+
+        Some(Self {
+            trace_id: trace_id.to_string(),
+            parent_id: Some(parent_id.to_string()),
+            span_id: random_hex(8),
+            flags: u8::from_str_radix(flags, 16).ok()?,
+            state: None,
+        })
+    }
+
+    /// Read the trace context from request headers. A new trace is started if the
+    /// request isn't part of one.
+    pub fn from_head(head: &Head) -> Self {
+        match head.header("traceparent").and_then(|t| Self::parse(t)) {
+            Some(mut context) => {
+                context.state = head.header("tracestate").cloned();
+                context
+            }
+            None => Self::new(),
+        }
+    }
+
+    /// The trace is sampled, i.e. recorded by the caller.
+    pub fn sampled(&self) -> bool {
+        self.flags & 1 == 1
+    }
+
+    /// `traceparent` header to send to other services, making
+    /// this request's span their parent.
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-{:02x}", self.trace_id, self.span_id, self.flags)
+    }
+}
+
+impl Default for TraceContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Lowercase hex string of this length.
+fn hex(value: &str, len: usize) -> bool {
+    value.len() == len
+        && value
+            .chars()
+            .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+}
+
+/// All zeros, which isn't a valid ID.
+fn zeros(value: &str) -> bool {
+    value.chars().all(|c| c == '0')
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut rng = rand::thread_rng();
+    loop {
+        let value = (0..bytes)
+            .map(|_| format!("{:02x}", rng.gen::<u8>()))
+            .collect::<String>();
+
+        if !zeros(&value) {
+            return value;
+        }
+    }
+}
+
+/// Request ID sent by the client, if it's safe to use, or a new one.
+pub fn request_id(head: &Head) -> String {
+    match head.header("x-request-id") {
+        Some(id) if valid_request_id(id) => id.clone(),
+        _ => Uuid::new_v4().to_string(),
+    }
+}
+
+/// The ID isn't too long and can be safely written to logs.
+fn valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.:/+=@".contains(c))
+}
+
+/// Trace context of the request, if propagation is enabled.
+pub fn trace_context(head: &Head) -> Option<TraceContext> {
+    if get_config().general.trace_context {
+        Some(TraceContext::from_head(head))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_traceparent() {
+        let context =
+            TraceContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+        assert_eq!(context.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(context.parent_id.as_deref(), Some("00f067aa0ba902b7"));
+        assert_ne!(context.span_id, "00f067aa0ba902b7");
+        assert!(context.sampled());
+
+        // The span handling the request is the parent of the next one.
+        let traceparent = context.traceparent();
+        assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+        assert!(traceparent.ends_with(&format!("-{}-01", context.span_id)));
+
+        // Future versions can add fields.
+        assert!(TraceContext::parse(
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00-extra"
+        )
+        .is_some());
+
+        for invalid in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        ] {
+            assert!(TraceContext::parse(invalid).is_none(), "{}", invalid);
+        }
+
+        let context = TraceContext::new();
+        assert!(TraceContext::parse(&context.traceparent()).is_some());
+    }
+
+    #[test]
+    fn test_request_id() {
+        assert!(valid_request_id("f0e1d2c3-b4a5-4697-8879-6a5b4c3d2e1f"));
+        assert!(valid_request_id("Root=1-67891233-abcdef012345678912345678"));
+        assert!(!valid_request_id(""));
+        assert!(!valid_request_id("id\r\nx-injected: 1"));
+        assert!(!valid_request_id("id with spaces"));
+        assert!(!valid_request_id(&"a".repeat(129)));
+    }
+}
@@ -0,0 +1,834 @@
+//! File uploads with `multipart/form-data` forms.
+//!
+//! Forms are parsed while the request body is received, so files don't have to fit in memory. Small files are kept
+//! in memory, while larger ones are written to temporary files in the upload directory as they arrive. Temporary files
+//! are deleted when the [`UploadedFile`] is dropped, unless it's moved to a permanent location with [`UploadedFile::persist`].
+//!
+//! Limits are set in the `[uploads]` section of the configuration, see [`crate::config::UploadConfig`]. Requests larger than
+//! `max_request_size` are rejected before the body is read, and files are rejected as soon as they exceed `max_file_size`.
+//! Requests received over HTTP/2 or compressed with gzip are read into memory first and subject to the `max_request_size`
+//! setting in `[general]`.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! let mut form = request.multipart().await?;
+//! let title = form.get_required::<String>("title")?;
+//!
+//! if let Some(avatar) = form.take_file("avatar") {
+//!     let path = format!("uploads/{}", avatar.name);
+//!     avatar.persist(path).await?;
+//! }
+//! ```
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tokio::fs::{remove_file, rename, write, File};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+
+use super::{
+    form_data::{ContentDisposition, Multipart, MultipartEntry},
+    Error, FormData, FromFormData, Head, Query, Request,
+};
+use crate::config::{get_config, UploadConfig};
+use crate::crypto::random_string;
+
+/// Parsed `multipart/form-data` form.
+#[derive(Debug, Default)]
+pub struct MultipartForm {
+    fields: Vec<(String, String)>,
+    files: Vec<UploadedFile>,
+}
+
+impl MultipartForm {
+    /// Get the form sent with the request.
+    ///
+    /// Forms are usually parsed while the request is received, using the limits from the configuration
+    /// at that time. Otherwise, the form is parsed from the request body using the given limits.
+    pub async fn from_request(request: &Request, config: &UploadConfig) -> Result<Self, Error> {
+        if let Some(form) = request.received_form() {
+            return form.take();
+        }
+
+        let boundary = request
+            .header("content-type")
+            .and_then(|content_type| boundary(content_type))
+            .ok_or(Error::InvalidBody("multipart boundary is missing".into()))?;
+
+        match Self::read(request.body(), &boundary, config).await {
+            Err(Error::ContentTooLarge(_)) => Err(Error::ContentTooLarge(request.head().clone())),
+            result => result,
+        }
+    }
+
+    /// Parse the form while it's read. At most `memory_limit` bytes of each file are kept in memory.
+    async fn read(
+        body: impl AsyncRead + Unpin,
+        boundary: &str,
+        config: &UploadConfig,
+    ) -> Result<Self, Error> {
+        let general = &get_config().general;
+        let mut reader = BodyReader::new(body, boundary, general.header_max_size);
+        let mut form = MultipartForm::default();
+        let mut fields_size = 0;
+
+        // Skip the preamble.
+        while reader.chunk().await?.is_some() {}
+
+        while reader.next_part().await? {
+            let (disposition, content_type) = reader.headers().await?;
+
+            match disposition.filename {
+                Some(filename) => {
+                    if !filename.is_empty() && form.files.len() >= config.max_files {
+                        return Err(Error::ContentTooLarge(Head::default()));
+                    }
+
+                    let mut file = UploadedFile {
+                        field: disposition.name,
+                        name: sanitize_filename(&filename),
+                        content_type: content_type.unwrap_or("application/octet-stream".into()),
+                        size: 0,
+                        storage: Storage::Memory(vec![]),
+                    };
+                    let mut temp = None;
+
+                    while let Some(chunk) = reader.chunk().await? {
+                        file.write(&mut temp, &chunk, config).await?;
+                    }
+
+                    if let Some(mut temp) = temp {
+                        temp.flush().await?;
+                    }
+
+                    // Browsers send an empty part when no file is selected.
+                    if filename.is_empty() && file.size == 0 {
+                        continue;
+                    }
+
+                    if form.files.len() >= config.max_files {
+                        return Err(Error::ContentTooLarge(Head::default()));
+                    }
+
+                    form.files.push(file);
+                }
+
+                None => {
+                    let mut value = vec![];
+
+                    while let Some(chunk) = reader.chunk().await? {
+                        fields_size += chunk.len();
+
+                        if fields_size > general.max_request_size {
+                            return Err(Error::ContentTooLarge(Head::default()));
+                        }
+
+                        value.extend_from_slice(&chunk);
+                    }
+
+                    form.fields
+                        .push((disposition.name, String::from_utf8(value)?));
+                }
+            }
+        }
+
+        Ok(form)
+    }
+
+    /// Get a form field, converted to the requested type. Returns `None` if the field
+    /// is missing or can't be converted. If the field is repeated, the first value is returned.
+    pub fn get<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .and_then(|(_, value)| value.parse().ok())
+    }
+
+    /// Same as [`MultipartForm::get`], except it returns [`Error::MissingParameter`] if the field
+    /// is missing or invalid. Used with the `?` operator, the controller will return `400 - Bad Request`.
+    pub fn get_required<T: FromStr>(&self, name: &str) -> Result<T, Error> {
+        self.get(name).ok_or(Error::MissingParameter)
+    }
+
+    /// Get all values of a repeated form field, e.g. checkboxes.
+    pub fn get_all<T: FromStr>(&self, name: &str) -> Vec<T> {
+        self.fields
+            .iter()
+            .filter(|(field, _)| field == name)
+            .filter_map(|(_, value)| value.parse().ok())
+            .collect()
+    }
+
+    /// Convert form fields (except files) into a struct, e.g. one that derives `macros::Form`.
+    pub fn form<T: FromFormData>(&self) -> Result<T, Error> {
+        let mut query = Query::new();
+        for (name, value) in &self.fields {
+            query.entry(name.clone()).or_insert(value.clone());
+        }
+
+        T::from_form_data(&FormData::UrlEncoded(query))
+    }
+
+    /// Get the first file uploaded with the field.
+    pub fn file(&self, name: &str) -> Option<&UploadedFile> {
+        self.files.iter().find(|file| file.field == name)
+    }
+
+    /// All uploaded files, in the order they were sent.
+    pub fn files(&self) -> &[UploadedFile] {
+        &self.files
+    }
+
+    /// Take ownership of the first file uploaded with the field, e.g. to persist it.
+    pub fn take_file(&mut self, name: &str) -> Option<UploadedFile> {
+        self.files
+            .iter()
+            .position(|file| file.field == name)
+            .map(|position| self.files.remove(position))
+    }
+
+    /// Take ownership of all uploaded files, grouped by field name.
+    pub fn into_files(self) -> HashMap<String, Vec<UploadedFile>> {
+        let mut files: HashMap<String, Vec<UploadedFile>> = HashMap::new();
+        for file in self.files {
+            files.entry(file.field.clone()).or_default().push(file);
+        }
+        files
+    }
+}
+
+#[derive(Debug)]
+enum Storage {
+    Memory(Vec<u8>),
+    Disk(PathBuf),
+}
+
+/// A file uploaded with a `multipart/form-data` form.
+///
+/// Files stored on disk are deleted when the struct is dropped, unless they are persisted.
+#[derive(Debug)]
+pub struct UploadedFile {
+    /// Name of the form field.
+    pub field: String,
+    /// File name provided by the browser, without directories. Chosen by the client,
+    /// so don't trust it, e.g. to pick the content type.
+    pub name: String,
+    /// Content type provided by the browser. Default: `application/octet-stream`.
+    pub content_type: String,
+    /// Size of the file in bytes.
+    pub size: usize,
+    storage: Storage,
+}
+
+impl UploadedFile {
+    // Append a chunk of the file, moving it to a temporary file once it's larger than the memory limit.
+    async fn write(
+        &mut self,
+        temp: &mut Option<File>,
+        chunk: &[u8],
+        config: &UploadConfig,
+    ) -> Result<(), Error> {
+        self.size += chunk.len();
+
+        if self.size > config.max_file_size {
+            return Err(Error::ContentTooLarge(Head::default()));
+        }
+
+        if let Some(temp) = temp {
+            temp.write_all(chunk).await?;
+            return Ok(());
+        }
+
+        if self.size <= config.memory_limit {
+            if let Storage::Memory(ref mut data) = self.storage {
+                data.extend_from_slice(chunk);
+            }
+            return Ok(());
+        }
+
+        let directory = config.directory();
+        tokio::fs::create_dir_all(&directory).await?;
+        let path = directory.join(format!("rwf-upload-{}", random_string(16)));
+        let mut file = File::create(&path).await?;
+
+        // The file is deleted if the upload fails from now on.
+        if let Storage::Memory(data) = std::mem::replace(&mut self.storage, Storage::Disk(path)) {
+            file.write_all(&data).await?;
+        }
+        file.write_all(chunk).await?;
+        *temp = Some(file);
+
+        Ok(())
+    }
+
+    /// Path of the temporary file, if the file was too large to be kept in memory.
+    pub fn path(&self) -> Option<&Path> {
+        match self.storage {
+            Storage::Disk(ref path) => Some(path),
+            Storage::Memory(_) => None,
+        }
+    }
+
+    /// The file is kept in memory.
+    pub fn in_memory(&self) -> bool {
+        matches!(self.storage, Storage::Memory(_))
+    }
+
+    /// Get the file contents, reading it from disk if needed.
+    pub async fn bytes(&self) -> Result<Vec<u8>, Error> {
+        match self.storage {
+            Storage::Memory(ref data) => Ok(data.clone()),
+            Storage::Disk(ref path) => Ok(tokio::fs::read(path).await?),
+        }
+    }
+
+    /// Move the file to a permanent location. Returns the path of the file.
+    pub async fn persist(mut self, path: impl AsRef<Path>) -> Result<PathBuf, Error> {
+        let path = path.as_ref().to_path_buf();
+
+        match std::mem::replace(&mut self.storage, Storage::Memory(vec![])) {
+            Storage::Memory(data) => write(&path, data).await?,
+            Storage::Disk(temp) => {
+                // Renaming doesn't work across filesystems.
+                if rename(&temp, &path).await.is_err() {
+                    tokio::fs::copy(&temp, &path).await?;
+                    remove_file(&temp).await?;
+                }
+            }
+        }
+
+        Ok(path)
+    }
+}
+
+impl Drop for UploadedFile {
+    fn drop(&mut self) {
+        if let Storage::Disk(ref path) = self.storage {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// A part of a multipart body.
+pub(crate) struct Part<'a> {
+    pub(crate) disposition: ContentDisposition,
+    pub(crate) content_type: Option<String>,
+    pub(crate) data: &'a [u8],
+}
+
+/// Extract the boundary from the `Content-Type` header.
+pub(crate) fn boundary(content_type: &str) -> Option<String> {
+    content_type
+        .split(";")
+        .filter_map(|param| param.trim().split_once("="))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim().trim_matches('"').to_string())
+        .filter(|boundary| !boundary.is_empty())
+}
+
+/// Split a multipart body into parts. File contents are not copied.
+pub(crate) fn parts<'a>(body: &'a [u8], boundary: &str) -> Result<Vec<Part<'a>>, Error> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let malformed = || Error::InvalidBody("multipart body is malformed".into());
+
+    let mut parts = vec![];
+    let mut position = find(body, &delimiter, 0).ok_or_else(malformed)?;
+
+    loop {
+        position += delimiter.len();
+
+        // The last delimiter is followed by "--".
+        if body[position..].starts_with(b"--") {
+            break;
+        }
+
+        position = line_end(body, position).ok_or_else(malformed)?;
+
+        let mut disposition = None;
+        let mut content_type = None;
+
+        // Part headers, followed by an empty line.
+        loop {
+            let end = line_end(body, position).ok_or_else(malformed)?;
+            let line = trim_line(&body[position..end]);
+            position = end;
+
+            if line.is_empty() {
+                break;
+            }
+
+            let line = std::str::from_utf8(line).map_err(|_| malformed())?;
+            match line.split_once(":") {
+                Some((name, _)) if name.trim().eq_ignore_ascii_case("content-disposition") => {
+                    disposition = Some(ContentDisposition::parse(line).map_err(|_| {
+                        Error::InvalidBody("content-disposition is invalid".into())
+                    })?);
+                }
+
+                Some((name, value)) if name.trim().eq_ignore_ascii_case("content-type") => {
+                    content_type = Some(value.trim().to_string());
+                }
+
+                _ => (),
+            }
+        }
+
+        // Part contents end with a new line followed by the delimiter.
+        let next = find(body, &delimiter, position).ok_or_else(malformed)?;
+        let mut data = &body[position..next];
+        data = data.strip_suffix(b"\n").unwrap_or(data);
+        data = data.strip_suffix(b"\r").unwrap_or(data);
+
+        parts.push(Part {
+            disposition: disposition.ok_or_else(malformed)?,
+            content_type,
+            data,
+        });
+
+        position = next;
+    }
+
+    Ok(parts)
+}
+
+/// Find the position of the delimiter at the start of a line.
+fn find(body: &[u8], delimiter: &[u8], from: usize) -> Option<usize> {
+    let mut from = from;
+
+    while from + delimiter.len() <= body.len() {
+        let position = body[from..]
+            .windows(delimiter.len())
+            .position(|window| window == delimiter)?
+            + from;
+
+        if position == 0 || body[position - 1] == b'\n' {
+            return Some(position);
+        }
+
+        from = position + 1;
+    }
+
+    None
+}
+
+/// Position right after the end of the line starting at `from`.
+fn line_end(body: &[u8], from: usize) -> Option<usize> {
+    body[from..]
+        .iter()
+        .position(|c| *c == b'\n')
+        .map(|position| from + position + 1)
+}
+
+fn trim_line(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Remove directories from a file name. Some browsers send the full path of the file.
+fn sanitize_filename(filename: &str) -> String {
+    filename
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_string()
+}
+
+/// Boundary of a `multipart/form-data` body which can be parsed while it's received.
+/// Compressed bodies are read into memory first.
+pub(crate) fn streamed_boundary(head: &Head) -> Option<String> {
+    if head.header("content-encoding").is_some() {
+        return None;
+    }
+
+    head.header("content-type")
+        .filter(|content_type| content_type.contains("multipart/form-data"))
+        .and_then(|content_type| boundary(content_type))
+}
+
+/// Form parsed while the request was received.
+///
+/// Files are handed over to the first caller of [`Request::multipart`], since temporary files are deleted
+/// once they are dropped. Parsing errors are returned to the controller.
+#[derive(Debug, Clone)]
+pub(crate) struct ReceivedForm(Arc<Mutex<Result<MultipartForm, String>>>);
+
+impl ReceivedForm {
+    /// Receive the form with a body of `length` bytes from the connection.
+    pub(crate) async fn receive(
+        stream: impl AsyncRead + Unpin,
+        head: &Head,
+        length: usize,
+        boundary: &str,
+        config: &UploadConfig,
+    ) -> Result<Self, Error> {
+        let mut body = stream.take(length as u64);
+
+        let form = match MultipartForm::read(&mut body, boundary, config).await {
+            Ok(form) => Ok(form),
+            Err(Error::ContentTooLarge(_)) => return Err(Error::ContentTooLarge(head.clone())),
+            Err(err @ Error::Io(_)) => return Err(err),
+            Err(Error::InvalidBody(err)) => Err(err),
+            Err(err) => Err(err.to_string()),
+        };
+
+        // Read the rest of the body, so the next request can be read from the connection.
+        tokio::io::copy(&mut body, &mut tokio::io::sink()).await?;
+        if body.limit() > 0 {
+            return Err(Error::MalformedRequest("incorrect content length"));
+        }
+
+        Ok(Self(Arc::new(Mutex::new(form))))
+    }
+
+    /// Take the form. Fields are copied, files are only returned once.
+    pub(crate) fn take(&self) -> Result<MultipartForm, Error> {
+        match &mut *self.0.lock() {
+            Ok(form) => Ok(MultipartForm {
+                fields: form.fields.clone(),
+                files: std::mem::take(&mut form.files),
+            }),
+            Err(err) => Err(Error::InvalidBody(err.clone())),
+        }
+    }
+
+    /// Form fields and files kept in memory.
+    pub(crate) fn form_data(&self) -> Result<FormData, Error> {
+        let form = self.0.lock();
+        let form = form
+            .as_ref()
+            .map_err(|err| Error::InvalidBody(err.clone()))?;
+
+        let fields = form.fields.iter().map(|(name, value)| {
+            MultipartEntry::new(
+                ContentDisposition {
+                    name: name.clone(),
+                    filename: None,
+                },
+                None,
+                value.clone().into_bytes(),
+            )
+        });
+
+        let files = form.files.iter().filter_map(|file| match file.storage {
+            Storage::Memory(ref data) => Some(MultipartEntry::new(
+                ContentDisposition {
+                    name: file.field.clone(),
+                    filename: Some(file.name.clone()),
+                },
+                Some(file.content_type.clone()),
+                data.clone(),
+            )),
+            Storage::Disk(_) => None,
+        });
+
+        Ok(FormData::Multipart(Multipart::from_entries(
+            fields.chain(files),
+        )))
+    }
+}
+
+/// Reads parts of a multipart body, without keeping more than a few kilobytes in memory.
+struct BodyReader<R> {
+    body: R,
+    // New line followed by `--` and the boundary.
+    delimiter: Vec<u8>,
+    buffer: Vec<u8>,
+    // The data of the current part was read.
+    end: bool,
+    max_line: usize,
+}
+
+impl<R: AsyncRead + Unpin> BodyReader<R> {
+    fn new(body: R, boundary: &str, max_line: usize) -> Self {
+        Self {
+            body,
+            delimiter: format!("\n--{}", boundary).into_bytes(),
+            // The first delimiter can be at the very start of the body.
+            buffer: b"\r\n".to_vec(),
+            end: false,
+            max_line,
+        }
+    }
+
+    fn malformed() -> Error {
+        Error::InvalidBody("multipart body is malformed".into())
+    }
+
+    async fn fill(&mut self) -> Result<(), Error> {
+        let mut chunk = [0u8; 8 * 1024];
+        let read = self.body.read(&mut chunk).await?;
+
+        if read == 0 {
+            return Err(Self::malformed());
+        }
+
+        self.buffer.extend_from_slice(&chunk[..read]);
+        Ok(())
+    }
+
+    /// Read the next chunk of the current part. Returns `None` once the part ended.
+    async fn chunk(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        if self.end {
+            return Ok(None);
+        }
+
+        loop {
+            if let Some(position) = self
+                .buffer
+                .windows(self.delimiter.len())
+                .position(|window| window == self.delimiter)
+            {
+                let mut data = self
+                    .buffer
+                    .drain(..position + self.delimiter.len())
+                    .collect::<Vec<_>>();
+                data.truncate(position);
+                if data.ends_with(b"\r") {
+                    data.pop();
+                }
+
+                self.end = true;
+                return Ok(Some(data));
+            }
+
+            // Keep what could be the start of the delimiter, and the `\r` before it.
+            let keep = self.delimiter.len() + 1;
+            if self.buffer.len() > keep {
+                let data = self
+                    .buffer
+                    .drain(..self.buffer.len() - keep)
+                    .collect::<Vec<_>>();
+                return Ok(Some(data));
+            }
+
+            self.fill().await?;
+        }
+    }
+
+    /// Move to the next part, after the delimiter. Returns `false` if it was the last delimiter.
+    async fn next_part(&mut self) -> Result<bool, Error> {
+        while self.buffer.len() < 2 {
+            self.fill().await?;
+        }
+
+        // The last delimiter is followed by "--".
+        if self.buffer.starts_with(b"--") {
+            return Ok(false);
+        }
+
+        self.line().await?;
+        self.end = false;
+
+        Ok(true)
+    }
+
+    /// Read the headers of the part, followed by an empty line.
+    async fn headers(&mut self) -> Result<(ContentDisposition, Option<String>), Error> {
+        let mut disposition = None;
+        let mut content_type = None;
+
+        loop {
+            let line = self.line().await?;
+
+            if line.is_empty() {
+                break;
+            }
+
+            let line = String::from_utf8(line).map_err(|_| Self::malformed())?;
+            match line.split_once(":") {
+                Some((name, _)) if name.trim().eq_ignore_ascii_case("content-disposition") => {
+                    disposition = Some(ContentDisposition::parse(&line).map_err(|_| {
+                        Error::InvalidBody("content-disposition is invalid".into())
+                    })?);
+                }
+
+                Some((name, value)) if name.trim().eq_ignore_ascii_case("content-type") => {
+                    content_type = Some(value.trim().to_string());
+                }
+
+                _ => (),
+            }
+        }
+
+        Ok((disposition.ok_or_else(Self::malformed)?, content_type))
+    }
+
+    /// Read a line, without the line ending.
+    async fn line(&mut self) -> Result<Vec<u8>, Error> {
+        loop {
+            if let Some(position) = self.buffer.iter().position(|c| *c == b'\n') {
+                let line = self.buffer.drain(..=position).collect::<Vec<_>>();
+                return Ok(trim_line(&line).to_vec());
+            }
+
+            if self.buffer.len() > self.max_line {
+                return Err(Self::malformed());
+            }
+
+            self.fill().await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::test::dummy_ip;
+
+    fn body() -> String {
+        [
+            "preamble",
+            "--XyZ",
+            "Content-Disposition: form-data; name=\"title\"",
+            "",
+            "Hello",
+            "world",
+            "--XyZ",
+            "Content-Disposition: form-data; name=\"tags\"",
+            "",
+            "rust",
+            "--XyZ",
+            "Content-Disposition: form-data; name=\"tags\"",
+            "",
+            "web",
+            "--XyZ",
+            "Content-Disposition: form-data; name=\"small\"; filename=\"C:\\Users\\a.txt\"",
+            "Content-Type: text/plain",
+            "",
+            "line 1\r\nline 2\r",
+            "--XyZ",
+            "Content-Disposition: form-data; name=\"large\"; filename=\"b.bin\"",
+            "",
+            &"0123456789".repeat(10),
+            "--XyZ",
+            "Content-Disposition: form-data; name=\"empty\"; filename=\"\"",
+            "",
+            "",
+            "--XyZ--",
+            "",
+        ]
+        .join("\r\n")
+    }
+
+    #[test]
+    fn test_parts() {
+        assert_eq!(
+            boundary("multipart/form-data; boundary=\"XyZ\""),
+            Some("XyZ".into())
+        );
+        assert_eq!(boundary("multipart/form-data"), None);
+
+        let body = body();
+        let parts = parts(body.as_bytes(), "XyZ").unwrap();
+        assert_eq!(parts.len(), 6);
+        assert_eq!(parts[0].data, b"Hello\r\nworld");
+        assert_eq!(parts[3].data, b"line 1\r\nline 2\r");
+        assert_eq!(parts[3].content_type, Some("text/plain".into()));
+
+        assert!(super::parts(b"--XyZ\r\nno headers", "XyZ").is_err());
+        assert!(super::parts(b"garbage", "XyZ").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_multipart() {
+        let body = body();
+        let mut config = UploadConfig::default();
+        config.memory_limit = 50;
+        let mut form = MultipartForm::read(body.as_bytes(), "XyZ", &config)
+            .await
+            .unwrap();
+
+        assert_eq!(form.get::<String>("title").unwrap(), "Hello\r\nworld");
+        assert_eq!(form.get_all::<String>("tags"), vec!["rust", "web"]);
+        assert!(form.get::<i64>("title").is_none());
+        assert_eq!(form.files().len(), 2);
+
+        let small = form.file("small").unwrap();
+        assert_eq!(small.name, "a.txt");
+        assert_eq!(small.content_type, "text/plain");
+        assert!(small.in_memory());
+        assert_eq!(small.bytes().await.unwrap(), b"line 1\r\nline 2\r");
+
+        let large = form.take_file("large").unwrap();
+        assert_eq!(large.content_type, "application/octet-stream");
+        assert_eq!(large.size, 100);
+        let temp = large.path().unwrap().to_path_buf();
+        assert!(temp.exists());
+
+        let destination = config
+            .directory()
+            .join(format!("persisted-{}", random_string(8)));
+        let path = large.persist(&destination).await.unwrap();
+        assert!(!temp.exists());
+        assert_eq!(tokio::fs::read(&path).await.unwrap().len(), 100);
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        let mut config = UploadConfig::default();
+        config.max_file_size = 50;
+        assert!(matches!(
+            MultipartForm::read(body.as_bytes(), "XyZ", &config).await,
+            Err(Error::ContentTooLarge(_))
+        ));
+
+        let mut config = UploadConfig::default();
+        config.max_files = 1;
+        assert!(matches!(
+            MultipartForm::read(body.as_bytes(), "XyZ", &config).await,
+            Err(Error::ContentTooLarge(_))
+        ));
+    }
+
+    fn raw_request(body: &str) -> String {
+        format!(
+            "POST /upload HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=XyZ\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    }
+
+    #[tokio::test]
+    async fn test_multipart_received() {
+        // Larger than the read buffer and the memory limit.
+        let data = "0123456789".repeat(10_000);
+        let sent = body().replace(&"0123456789".repeat(10), &data);
+        let request = raw_request(&sent);
+        let request = Request::read(dummy_ip(), request.as_bytes()).await.unwrap();
+        assert!(request.body().is_empty());
+
+        let form = request.form_data().unwrap();
+        assert_eq!(form.get::<String>("title").unwrap(), "Hello\r\nworld");
+
+        let mut form = request.multipart().await.unwrap();
+        assert_eq!(form.get_all::<String>("tags"), vec!["rust", "web"]);
+        let large = form.take_file("large").unwrap();
+        assert_eq!(large.size, data.len());
+        assert!(!large.in_memory());
+        assert_eq!(large.bytes().await.unwrap(), data.as_bytes());
+
+        // Files are handed over once.
+        let form = request.multipart().await.unwrap();
+        assert!(form.files().is_empty());
+        assert_eq!(form.get::<String>("title").unwrap(), "Hello\r\nworld");
+
+        let request = raw_request("--XyZ\r\nno headers");
+        let request = Request::read(dummy_ip(), request.as_bytes()).await.unwrap();
+        assert!(matches!(
+            request.multipart().await,
+            Err(Error::InvalidBody(_))
+        ));
+
+        let large = body().replace(
+            &"0123456789".repeat(10),
+            &"0".repeat(get_config().uploads.max_file_size + 1),
+        );
+        let request = raw_request(&large);
+        assert!(matches!(
+            Request::read(dummy_ip(), request.as_bytes()).await,
+            Err(Error::ContentTooLarge(_))
+        ));
+    }
+}
@@ -3,9 +3,16 @@ use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 use super::Error;
 use crate::view::TurboStream;
+use serde::{Deserialize, Serialize};
 
 use std::marker::Unpin;
 
+/// Close code sent when the server is restarting, e.g. during a deploy. Clients should reconnect.
+pub const CLOSE_SERVICE_RESTART: u16 = 1012;
+
+/// Close code sent when the connection was closed normally.
+pub const CLOSE_NORMAL: u16 = 1000;
+
 /// WebSocket headers.
 #[derive(Debug, Clone)]
 pub struct Headers {
@@ -61,7 +68,7 @@ impl DataFrame {
         self.meta.send(stream).await?;
 
         if let Some(message) = self.message {
-            message.send(stream).await?;
+            message.send_payload(stream).await?;
         }
 
         Ok(())
@@ -115,6 +122,29 @@ impl DataFrame {
         }
     }
 
+    /// This is a close message.
+    pub fn is_close(&self) -> bool {
+        self.header.is_close()
+    }
+
+    /// Create new close message, with the status code and the reason.
+    pub fn new_close(code: u16, reason: &str) -> Self {
+        let mut payload = code.to_be_bytes().to_vec();
+        payload.extend(reason.as_bytes());
+
+        Self {
+            header: Header {
+                fin: true,
+                op_code: OpCode::Close,
+            },
+            meta: Meta {
+                len: payload.len(),
+                mask: None,
+            },
+            message: Some(Message::Binary(payload)),
+        }
+    }
+
     /// Get the message from the frame.
     pub fn message(self) -> Message {
         self.message.unwrap()
@@ -126,6 +156,7 @@ enum OpCode {
     Continuation,
     Text,
     Binary,
+    Close,
     Ping,
     Pong,
 }
@@ -147,6 +178,7 @@ impl Header {
             0 => OpCode::Continuation,
             0x1 => OpCode::Text,
             0x2 => OpCode::Binary,
+            0x8 => OpCode::Close,
             0x9 => OpCode::Ping,
             0xA => OpCode::Pong,
             _ => return Err(Error::MalformedRequest("websocket control code")),
@@ -160,6 +192,7 @@ impl Header {
             OpCode::Continuation => 0,
             OpCode::Text => 0x1,
             OpCode::Binary => 0x2,
+            OpCode::Close => 0x8,
             OpCode::Ping => 0x9,
             OpCode::Pong => 0xA,
         };
@@ -191,6 +224,10 @@ impl Header {
     fn is_ping(&self) -> bool {
         self.op_code == OpCode::Ping
     }
+
+    fn is_close(&self) -> bool {
+        self.op_code == OpCode::Close
+    }
 }
 
 #[derive(Debug)]
@@ -283,7 +320,7 @@ impl Meta {
 }
 
 /// WebSocket message.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Message {
     /// Text message (UTF-8 encoding).
     Text(String),
@@ -349,14 +386,20 @@ impl Message {
 
         header.send(stream).await?;
         meta.send(stream).await?;
+        self.send_payload(stream).await?;
+
+        stream.flush().await?;
 
+        Ok(())
+    }
+
+    /// Send the message content, without the frame header.
+    async fn send_payload(&self, stream: &mut (impl AsyncWrite + Unpin)) -> Result<(), Error> {
         match self {
             Self::Text(text) => stream.write_all(text.as_bytes()).await?,
             Self::Binary(bytes) => stream.write_all(bytes.as_slice()).await?,
         };
 
-        stream.flush().await?;
-
         Ok(())
     }
 }
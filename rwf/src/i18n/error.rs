@@ -0,0 +1,17 @@
+//! Errors returned when loading translations.
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    /// Error reading the translations directory.
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The translations file isn't valid TOML.
+    #[error("locale \"{0}\": {1}")]
+    Toml(String, toml::de::Error),
+
+    /// A translation is neither a string nor a table of plural forms.
+    #[error("locale \"{locale}\": \"{key}\" should be a string, or a table of plural forms")]
+    Invalid { locale: String, key: String },
+}
@@ -0,0 +1,512 @@
+//! Translations, for apps available in several languages.
+//!
+//! Translations are kept in TOML files, one per locale, in the `locales` directory, e.g. `locales/fr.toml`.
+//! Tables group keys, so `title` in the `chat` table is `chat.title`:
+//!
+//! ```toml
+//! [chat]
+//! title = "Discussion"
+//! greeting = "Bonjour, {name} !"
+//!
+//! [chat.unread]
+//! one = "{count} message non lu"
+//! other = "{count} messages non lus"
+//! ```
+//!
+//! Messages are translated with [`t!`](crate::t) in Rust, and with `t` in templates:
+//!
+//! ```ignore
+//! let greeting = t!("chat.greeting", name = user.name);
+//! let unread = t!("chat.unread", count = 5);
+//! ```
+//!
+//! ```erb
+//! <h1><%= t("chat.title") %></h1>
+//! ```
+//!
+//! Messages are translated in the locale of the request being served: the locale in its path,
+//! if it's served with [`Locales`](crate::http::Locales), the locale saved in its session, or the best
+//! match for its `Accept-Language` header. Keys missing from the locale are translated in the default locale,
+//! configured in the `[i18n]` section of `rwf.toml`, and keys missing from both are returned as is.
+use std::collections::HashMap;
+use std::fmt::{Display, Write};
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use tracing::error;
+
+use crate::config::get_config;
+use crate::http::{Locales, Request};
+
+pub mod error;
+pub mod plural;
+
+pub use error::Error;
+
+static TRANSLATIONS: Lazy<RwLock<Option<Arc<Translations>>>> = Lazy::new(|| RwLock::new(None));
+
+tokio::task_local! {
+    static LOCALE: String;
+}
+
+/// Plural forms a message can have, e.g. `one` and `other` in English.
+const PLURAL_FORMS: &[&str] = &["zero", "one", "two", "few", "many", "other"];
+
+/// A translated message.
+#[derive(Debug, Clone)]
+enum Message {
+    Text(String),
+    // Forms by plural category. `other` is always present.
+    Plural(HashMap<String, String>),
+}
+
+/// Translations for all locales.
+#[derive(Debug, Clone, Default)]
+pub struct Translations {
+    locales: HashMap<String, HashMap<String, Message>>,
+}
+
+impl Translations {
+    /// No translations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load translations from the TOML files in the directory, named after their locale,
+    /// e.g. `fr.toml`. If the directory doesn't exist, there are no translations.
+    pub fn load(directory: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut translations = Self::new();
+        let directory = directory.as_ref();
+
+        if !directory.is_dir() {
+            return Ok(translations);
+        }
+
+        for entry in std::fs::read_dir(directory)? {
+            let path = entry?.path();
+
+            if path.extension().and_then(|ext| ext.to_str()) != Some("toml") {
+                continue;
+            }
+
+            if let Some(locale) = path.file_stem().and_then(|stem| stem.to_str()) {
+                translations.add(locale, &std::fs::read_to_string(&path)?)?;
+            }
+        }
+
+        Ok(translations)
+    }
+
+    /// Add translations for the locale, written in TOML. Keys which already
+    /// exist are replaced.
+    pub fn add(&mut self, locale: &str, source: &str) -> Result<&mut Self, Error> {
+        let table = source
+            .parse::<toml::Table>()
+            .map_err(|err| Error::Toml(locale.to_string(), err))?;
+        let messages = self.locales.entry(locale.to_string()).or_default();
+
+        flatten(locale, "", table, messages)?;
+
+        Ok(self)
+    }
+
+    /// Locales with translations, sorted.
+    pub fn locales(&self) -> Vec<&str> {
+        let mut locales = self
+            .locales
+            .keys()
+            .map(|locale| locale.as_str())
+            .collect::<Vec<_>>();
+        locales.sort();
+        locales
+    }
+
+    /// Translate the key in the locale, if it has a translation. Locales without translations
+    /// use their language's, e.g. `fr` for `fr-CA`.
+    ///
+    /// The `count` argument picks the plural form, if the message has them, and
+    /// arguments replace their `{name}` in the message.
+    pub fn translate(
+        &self,
+        locale: &str,
+        key: &str,
+        args: &[(&str, &dyn Display)],
+    ) -> Option<String> {
+        let message = self.messages(locale)?.get(key)?;
+
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Plural(forms) => {
+                let count = args
+                    .iter()
+                    .find(|(name, _)| *name == "count")
+                    .and_then(|(_, count)| count.to_string().parse::<f64>().ok())
+                    .unwrap_or(0.0);
+
+                // An explicit `zero` form is used in all languages.
+                let form = if count == 0.0 && forms.contains_key("zero") {
+                    "zero"
+                } else {
+                    plural::category(locale, count)
+                };
+
+                forms.get(form).or_else(|| forms.get("other"))?
+            }
+        };
+
+        Some(interpolate(text, args))
+    }
+
+    fn messages(&self, locale: &str) -> Option<&HashMap<String, Message>> {
+        let find = |locale: &str| {
+            self.locales
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(locale))
+                .map(|(_, messages)| messages)
+        };
+
+        find(locale).or_else(|| find(locale.split(['-', '_']).next()?))
+    }
+}
+
+/// Add messages in the table to the map, with keys prefixed by the names of the tables they are in.
+fn flatten(
+    locale: &str,
+    prefix: &str,
+    table: toml::Table,
+    messages: &mut HashMap<String, Message>,
+) -> Result<(), Error> {
+    for (name, value) in table {
+        let key = if prefix.is_empty() {
+            name
+        } else {
+            format!("{}.{}", prefix, name)
+        };
+
+        match value {
+            toml::Value::String(text) => {
+                messages.insert(key, Message::Text(text));
+            }
+
+            toml::Value::Table(table)
+                if table.contains_key("other")
+                    && table
+                        .keys()
+                        .all(|form| PLURAL_FORMS.contains(&form.as_str())) =>
+            {
+                let mut forms = HashMap::new();
+
+                for (form, text) in table {
+                    match text {
+                        toml::Value::String(text) => {
+                            forms.insert(form, text);
+                        }
+                        _ => {
+                            return Err(Error::Invalid {
+                                locale: locale.to_string(),
+                                key: format!("{}.{}", key, form),
+                            })
+                        }
+                    }
+                }
+
+                messages.insert(key, Message::Plural(forms));
+            }
+
+            toml::Value::Table(table) => flatten(locale, &key, table, messages)?,
+
+            _ => {
+                return Err(Error::Invalid {
+                    locale: locale.to_string(),
+                    key,
+                })
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Replace `{name}` with the argument's value. Placeholders without an argument are kept.
+fn interpolate(text: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let placeholder = &rest[start + 1..];
+
+        match placeholder.find('}') {
+            Some(end) => {
+                let name = &placeholder[..end];
+
+                match args.iter().find(|(arg, _)| *arg == name) {
+                    Some((_, value)) => {
+                        let _ = write!(result, "{}", value);
+                    }
+                    None => result.push_str(&rest[start..start + end + 2]),
+                }
+
+                rest = &placeholder[end + 1..];
+            }
+
+            None => break,
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Translations used by the app. Unless they were set with [`set_translations`], they are loaded
+/// from the directory configured in `rwf.toml` when they are first needed.
+pub fn translations() -> Arc<Translations> {
+    if let Some(ref translations) = *TRANSLATIONS.read() {
+        return translations.clone();
+    }
+
+    let directory = &get_config().i18n.directory;
+    let translations = Translations::load(directory).unwrap_or_else(|err| {
+        error!(
+            "failed to load translations from \"{}\": {}",
+            directory.display(),
+            err
+        );
+        Translations::new()
+    });
+
+    TRANSLATIONS
+        .write()
+        .get_or_insert_with(|| Arc::new(translations))
+        .clone()
+}
+
+/// Use these translations instead of the ones in the translations directory.
+pub fn set_translations(translations: Translations) {
+    *TRANSLATIONS.write() = Some(Arc::new(translations));
+}
+
+/// Translate the key in the locale. Keys missing from the locale are translated in the default locale,
+/// and keys missing from both are returned as is.
+pub fn translate(locale: &str, key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let translations = translations();
+
+    translations
+        .translate(locale, key, args)
+        .or_else(|| translations.translate(&get_config().i18n.default_locale, key, args))
+        .unwrap_or_else(|| key.to_string())
+}
+
+/// Translate the key in the current locale. Used by [`t!`](crate::t).
+pub fn t(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    translate(&locale(), key, args)
+}
+
+/// Locale of the request being served, or the default locale outside of requests,
+/// e.g. in background jobs. Use [`scope`] to set it.
+pub fn locale() -> String {
+    LOCALE
+        .try_with(|locale| locale.clone())
+        .unwrap_or_else(|_| get_config().i18n.default_locale.clone())
+}
+
+/// Translate messages in the locale while running the future, e.g. in a background job
+/// sending an email to a user.
+///
+/// # Example
+///
+/// ```ignore
+/// let subject = i18n::scope(&user.locale, async { t!("emails.welcome.subject") }).await;
+/// ```
+pub async fn scope<F: Future>(locale: impl ToString, future: F) -> F::Output {
+    LOCALE.scope(locale.to_string(), future).await
+}
+
+/// Locale the request should be served in: the locale in its path, the locale saved in its session,
+/// the best match for its `Accept-Language` header among locales with translations, or the default locale.
+pub fn negotiate(request: &Request) -> String {
+    if let Some(locale) = request.locale() {
+        return locale.to_string();
+    }
+
+    let default_locale = &get_config().i18n.default_locale;
+    let translations = translations();
+    let locales = translations.locales();
+
+    if locales.is_empty() {
+        return default_locale.clone();
+    }
+
+    let mut available = vec![default_locale.as_str()];
+    available.extend(
+        locales
+            .into_iter()
+            .filter(|locale| locale != default_locale),
+    );
+
+    Locales::new(&available).preferred(request).to_string()
+}
+
+/// Translate a message in the locale of the request being served.
+///
+/// Arguments replace their `{name}` in the message, and `count` picks its plural form.
+///
+/// # Example
+///
+/// ```
+/// # use rwf::prelude::*;
+/// # use rwf::i18n::{self, Translations};
+/// let mut translations = Translations::new();
+/// translations.add("en", r#"
+/// greeting = "Hello, {name}!"
+/// unread = { one = "{count} message", other = "{count} messages" }
+/// "#).unwrap();
+/// i18n::set_translations(translations);
+///
+/// let name = "Alice";
+/// assert_eq!(t!("greeting", name = name), "Hello, Alice!");
+/// assert_eq!(t!("unread", count = 2), "2 messages");
+/// assert_eq!(t!("missing.key"), "missing.key");
+/// ```
+#[macro_export]
+macro_rules! t {
+    ($key:expr $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::i18n::t(
+            $key,
+            &[$((stringify!($name), &$value as &dyn ::std::fmt::Display)),*],
+        )
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_translate() {
+        let mut translations = Translations::new();
+        translations
+            .add(
+                "en",
+                r#"
+                title = "Chat"
+
+                [chat]
+                greeting = "Hello, {name}! {unknown}"
+
+                [chat.unread]
+                zero = "No messages"
+                one = "{count} message"
+                other = "{count} messages"
+                "#,
+            )
+            .unwrap()
+            .add(
+                "ru",
+                r#"
+                [chat.unread]
+                one = "{count} сообщение"
+                few = "{count} сообщения"
+                many = "{count} сообщений"
+                other = "{count} сообщения"
+                "#,
+            )
+            .unwrap();
+
+        assert_eq!(translations.locales(), vec!["en", "ru"]);
+        assert_eq!(
+            translations.translate("en", "chat.greeting", &[("name", &"Alice")]),
+            Some("Hello, Alice! {unknown}".into())
+        );
+        assert_eq!(
+            translations.translate("en-US", "title", &[]),
+            Some("Chat".into())
+        );
+        assert_eq!(translations.translate("ru", "title", &[]), None);
+
+        for (locale, count, expected) in [
+            ("en", 0, "No messages"),
+            ("en", 1, "1 message"),
+            ("en", 7, "7 messages"),
+            ("ru", 1, "1 сообщение"),
+            ("ru", 3, "3 сообщения"),
+            ("ru", 11, "11 сообщений"),
+        ] {
+            assert_eq!(
+                translations.translate(locale, "chat.unread", &[("count", &count)]),
+                Some(expected.into())
+            );
+        }
+
+        assert!(matches!(
+            Translations::new().add("en", "count = 5"),
+            Err(Error::Invalid { key, .. }) if key == "count"
+        ));
+        assert!(matches!(
+            Translations::new().add("en", "title = "),
+            Err(Error::Toml(..))
+        ));
+    }
+
+    #[test]
+    fn test_interpolate() {
+        assert_eq!(
+            interpolate("{a} and {b}", &[("a", &1), ("b", &"two")]),
+            "1 and two"
+        );
+        assert_eq!(interpolate("{a", &[("a", &1)]), "{a");
+        assert_eq!(interpolate("{}", &[]), "{}");
+    }
+
+    #[tokio::test]
+    async fn test_template() {
+        use crate::view::template::{Context, Template, Value};
+
+        let mut translations = Translations::new();
+        translations
+            .add(
+                "en",
+                r#"unread = { one = "{count} message from {name}", other = "{count} messages from {name}" }"#,
+            )
+            .unwrap()
+            .add("fr", r#"title = "Discussion <b>""#)
+            .unwrap();
+        set_translations(translations);
+
+        let mut context = Context::new();
+        context
+            .set(
+                "sender",
+                Value::Hash(
+                    [("name".to_string(), Value::String("Alice".into()))]
+                        .into_iter()
+                        .collect(),
+                ),
+            )
+            .unwrap();
+        let template = Template::from_str(
+            r#"<%= t("title") %> <%= t("unread", 2, sender) %> <%= t("missing") %>"#,
+        )
+        .unwrap();
+        let rendered = scope("fr-CA", async { template.render(&context) })
+            .await
+            .unwrap();
+        assert_eq!(
+            rendered,
+            "Discussion &lt;b&gt; 2 messages from Alice missing"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_negotiate() {
+        let request = Request::default();
+        assert_eq!(negotiate(&request.clone().with_locale("fr")), "fr");
+
+        let current = scope("fr", async { locale() }).await;
+        assert_eq!(current, "fr");
+        assert_eq!(locale(), "en");
+    }
+}
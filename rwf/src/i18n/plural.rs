@@ -0,0 +1,110 @@
+//! Plural rules, picking the form of a message for a count, e.g. "1 message" or "2 messages".
+//!
+//! Categories follow the [CLDR plural rules](https://cldr.unicode.org/index/cldr-spec/plural-rules):
+//! `zero`, `one`, `two`, `few`, `many` and `other`. Languages without rules here use the English ones.
+
+/// Plural category of the count in the locale, e.g. `"one"` for `1` in English.
+pub fn category(locale: &str, count: f64) -> &'static str {
+    let language = locale
+        .split(['-', '_'])
+        .next()
+        .unwrap_or(locale)
+        .to_ascii_lowercase();
+    let integer = count.fract() == 0.0;
+    let n = count.abs();
+    // Rules below only use the last two digits.
+    let i = (n.trunc() % 100.0) as u64;
+    let (mod10, mod100) = (i % 10, i % 100);
+
+    match language.as_str() {
+        // No plural forms.
+        "ja" | "zh" | "ko" | "vi" | "th" | "id" | "ms" | "tr" => "other",
+
+        // 0 and 1 are singular.
+        "fr" | "pt" | "hi" | "bn" | "fa" => {
+            if n < 2.0 {
+                "one"
+            } else {
+                "other"
+            }
+        }
+
+        "ru" | "uk" | "be" | "sr" | "hr" | "bs" => match (integer, mod10, mod100) {
+            (false, _, _) => "other",
+            (true, 1, m) if m != 11 => "one",
+            (true, 2..=4, m) if !(12..=14).contains(&m) => "few",
+            _ => "many",
+        },
+
+        "pl" => match (integer, mod10, mod100) {
+            (false, _, _) => "other",
+            _ if n == 1.0 => "one",
+            (true, 2..=4, m) if !(12..=14).contains(&m) => "few",
+            _ => "many",
+        },
+
+        "cs" | "sk" => match (integer, n as u64) {
+            (false, _) => "many",
+            (true, 1) => "one",
+            (true, 2..=4) => "few",
+            _ => "other",
+        },
+
+        "ar" => match (integer, n as u64, mod100) {
+            (true, 0, _) => "zero",
+            (true, 1, _) => "one",
+            (true, 2, _) => "two",
+            (true, _, 3..=10) => "few",
+            (true, _, 11..=99) => "many",
+            _ => "other",
+        },
+
+        // English and most European languages.
+        _ => {
+            if integer && n == 1.0 {
+                "one"
+            } else {
+                "other"
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_category() {
+        for (locale, count, expected) in [
+            ("en", 1.0, "one"),
+            ("en", 0.0, "other"),
+            ("en", 1.5, "other"),
+            ("en-US", 2.0, "other"),
+            ("fr", 0.0, "one"),
+            ("fr", 1.5, "one"),
+            ("fr", 2.0, "other"),
+            ("pt-BR", 1.0, "one"),
+            ("ja", 1.0, "other"),
+            ("ru", 1.0, "one"),
+            ("ru", 21.0, "one"),
+            ("ru", 11.0, "many"),
+            ("ru", 3.0, "few"),
+            ("ru", 13.0, "many"),
+            ("ru", 25.0, "many"),
+            ("ru", 1.5, "other"),
+            ("pl", 1.0, "one"),
+            ("pl", 21.0, "many"),
+            ("pl", 22.0, "few"),
+            ("cs", 3.0, "few"),
+            ("cs", 5.0, "other"),
+            ("ar", 0.0, "zero"),
+            ("ar", 2.0, "two"),
+            ("ar", 105.0, "few"),
+            ("ar", 111.0, "many"),
+            ("ar", 100.0, "other"),
+        ] {
+            assert_eq!(category(locale, count), expected, "{} {}", locale, count);
+        }
+    }
+}
@@ -1,13 +1,18 @@
 //! Scheduled jobs implementation.
 //!
-//! This is also known as a cron.
+//! This is also known as a cron. Only one clock runs at a time: clocks elect a leader
+//! with a Postgres advisory lock, so jobs are scheduled once even if the app is deployed
+//! on many machines. If the leader goes away, its connection closes, releasing the lock,
+//! and another clock takes over.
 //!
-use super::{Cron, Error, Job, JobHandler};
+use super::{Error, Job, JobHandler, Schedule};
 use crate::{
     colors::MaybeColorize,
     model::{ConnectionGuard, Pool},
 };
 
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
 use std::sync::Arc;
 use time::OffsetDateTime;
 
@@ -18,24 +23,29 @@ use tracing::{error, info};
 
 static LOCK: i64 = 4_334_345_490_663;
 
+/// Clock created last in this process.
+static CLOCK: Lazy<RwLock<Option<Clock>>> = Lazy::new(|| RwLock::new(None));
+
+/// Most seconds checked at once when the clock falls behind.
+const MAX_CATCH_UP: i64 = 60;
+
 /// A job that runs on a schedule.
 pub struct ScheduledJob {
     job: JobHandler,
-    args: serde_json::Value,
-    cron: Cron,
+    schedule: Schedule,
 }
 
 impl ScheduledJob {
     /// Execute the job.
     pub async fn schedule(&self) -> Result<(), Error> {
-        self.job.job.execute_async(self.args.clone()).await?;
+        self.job.job.execute_async(self.args().clone()).await?;
 
         Ok(())
     }
 
     /// Check if the job should run at the specified time.
     pub fn should_run(&self, time: &OffsetDateTime) -> bool {
-        self.cron.should_run(time)
+        self.schedule.should_run(time)
     }
 
     /// Get the job handler.
@@ -43,21 +53,33 @@ impl ScheduledJob {
         &self.job.job
     }
 
+    /// Arguments passed to the job.
+    pub fn args(&self) -> &serde_json::Value {
+        &self.schedule.args
+    }
+
+    /// When the job runs.
+    pub fn timing(&self) -> &Schedule {
+        &self.schedule
+    }
+
     /// Create new scheduled job.
     pub fn new(
         schedule: &str,
         job: impl Job + 'static,
         args: impl Serialize,
     ) -> Result<Self, Error> {
-        let cron = Cron::parse(schedule)?;
-        let handler = JobHandler::new(job);
-        let args = serde_json::to_value(args)?;
-
-        Ok(Self {
-            job: handler,
-            args,
-            cron,
-        })
+        let schedule = Schedule::cron(schedule)?.args(serde_json::to_value(args)?);
+
+        Ok(Self::with_schedule(schedule, job))
+    }
+
+    /// Create new job running on the schedule.
+    pub fn with_schedule(schedule: Schedule, job: impl Job + 'static) -> Self {
+        Self {
+            job: JobHandler::new(job),
+            schedule,
+        }
     }
 }
 
@@ -70,9 +92,23 @@ pub struct Clock {
 impl Clock {
     /// Create new clock.
     pub fn new(jobs: Vec<ScheduledJob>) -> Self {
-        Self {
+        let clock = Self {
             jobs: Arc::new(jobs),
-        }
+        };
+
+        *CLOCK.write() = Some(clock.clone());
+
+        clock
+    }
+
+    /// Clock of the worker running in this process, if it runs scheduled jobs.
+    pub fn current() -> Option<Self> {
+        CLOCK.read().clone()
+    }
+
+    /// Jobs run by the clock.
+    pub fn jobs(&self) -> &[ScheduledJob] {
+        &self.jobs
     }
 
     async fn check_lock(conn: &ConnectionGuard) -> Result<bool, Error> {
@@ -100,22 +136,36 @@ impl Clock {
 
         info!("Clock is running");
 
+        let mut last = OffsetDateTime::now_utc().unix_timestamp() - 1;
+
         loop {
             let start = Instant::now();
-            let now = OffsetDateTime::now_utc();
+            let now = OffsetDateTime::now_utc().unix_timestamp();
             let jobs = self.jobs.clone();
 
+            // Check every second since the last tick, so jobs aren't skipped
+            // when the clock falls behind.
+            let ticks = (last + 1).max(now - MAX_CATCH_UP)..=now;
+            last = last.max(now);
+
             tokio::spawn(async move {
-                for job in jobs.iter() {
-                    if job.should_run(&now) {
-                        match job.schedule().await {
-                            Ok(_) => (),
-                            Err(err) => {
-                                error!(
-                                    "job {} failed to schedule: {:?}",
-                                    job.job().job_name().green(),
-                                    err
-                                );
+                for tick in ticks {
+                    let time = match OffsetDateTime::from_unix_timestamp(tick) {
+                        Ok(time) => time,
+                        Err(_) => continue,
+                    };
+
+                    for job in jobs.iter() {
+                        if job.should_run(&time) {
+                            match job.schedule().await {
+                                Ok(_) => (),
+                                Err(err) => {
+                                    error!(
+                                        "job {} failed to schedule: {:?}",
+                                        job.job().job_name().green(),
+                                        err
+                                    );
+                                }
                             }
                         }
                     }
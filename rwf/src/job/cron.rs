@@ -1,7 +1,11 @@
 //! Implements the UNIX cron syntax.
 use super::Error;
+use std::fmt::Display;
 use std::ops::Range;
-use time::OffsetDateTime;
+use time::{Duration, OffsetDateTime};
+
+/// Furthest in the future [`Cron::next_run`] looks, in minutes.
+const MAX_LOOKAHEAD: i64 = 366 * 24 * 60;
 
 #[derive(Clone, Debug)]
 enum CronValue {
@@ -64,6 +68,7 @@ impl CronValue {
 /// UNIX cron syntax.
 #[derive(Clone, Debug)]
 pub struct Cron {
+    expression: String,
     second: CronValue,
     minute: CronValue,
     hour: CronValue,
@@ -87,6 +92,7 @@ impl Cron {
         match parts.len() {
             // Second is specified
             6 => Ok(Self {
+                expression: value.to_string(),
                 second: CronValue::parse(parts[0])?,
                 minute: CronValue::parse(parts[1])?,
                 hour: CronValue::parse(parts[2])?,
@@ -97,6 +103,7 @@ impl Cron {
 
             // Second is omitted.
             5 => Ok(Self {
+                expression: value.to_string(),
                 second: CronValue::Exact(0),
                 minute: CronValue::parse(parts[0])?,
                 hour: CronValue::parse(parts[1])?,
@@ -111,15 +118,30 @@ impl Cron {
 
     /// Should the cron execute at the provided time?
     pub fn should_run(&self, time: &OffsetDateTime) -> bool {
-        let second = time.second();
+        self.second.matches(time.second() as i64) && self.matches_minute(time)
+    }
+
+    /// Next time the cron executes, after the provided time. Looks up to a year ahead.
+    pub fn next_run(&self, after: &OffsetDateTime) -> Option<OffsetDateTime> {
+        let start = after.replace_second(0).ok()?.replace_nanosecond(0).ok()?;
+
+        // Find the minute first, then the second, so checking a year takes a few milliseconds.
+        (0..=MAX_LOOKAHEAD)
+            .map(|minute| start + Duration::minutes(minute))
+            .filter(|minute| self.matches_minute(minute))
+            .flat_map(|minute| (0..60).map(move |second| minute + Duration::seconds(second)))
+            .find(|time| time > after && self.should_run(time))
+    }
+
+    /// The cron executes during this minute.
+    fn matches_minute(&self, time: &OffsetDateTime) -> bool {
         let minute = time.minute();
         let hour = time.hour();
         let day = time.day();
         let month = time.month();
         let weekday = time.weekday().number_from_sunday();
 
-        self.second.matches(second as i64)
-            && self.minute.matches(minute as i64)
+        self.minute.matches(minute as i64)
             && self.hour.matches(hour as i64)
             && self.dom.matches(day as i64)
             && self.month.matches(month as i64)
@@ -127,6 +149,12 @@ impl Cron {
     }
 }
 
+impl Display for Cron {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -168,4 +196,29 @@ mod test {
 
         assert!(cron.should_run(&time));
     }
+
+    #[test]
+    fn test_cron_next_run() {
+        let time = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap(); // 2023-11-14 22:13:20
+
+        let cron = Cron::parse("30 * * * *").unwrap();
+        assert_eq!(cron.to_string(), "30 * * * *");
+        let next = cron.next_run(&time).unwrap();
+        assert_eq!((next.hour(), next.minute(), next.second()), (22, 30, 0));
+        assert_eq!(cron.next_run(&next).unwrap(), next + Duration::hours(1));
+
+        let cron = Cron::parse("*/15 * * * * *").unwrap();
+        assert_eq!(cron.next_run(&time).unwrap(), time + Duration::seconds(10));
+
+        let cron = Cron::parse("0 3 1 * *").unwrap();
+        let next = cron.next_run(&time).unwrap();
+        assert_eq!(
+            (next.month(), next.day(), next.hour()),
+            (time::Month::December, 1, 3)
+        );
+
+        // Never runs.
+        let cron = Cron::parse("0 0 31 2 *").unwrap();
+        assert!(cron.next_run(&time).is_none());
+    }
 }
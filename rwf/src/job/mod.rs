@@ -6,10 +6,12 @@ pub mod clock;
 pub mod cron;
 pub mod error;
 pub mod model;
+pub mod schedule;
 pub mod worker;
 
 pub use clock::Clock;
 pub use cron::Cron;
 pub use error::Error;
 pub use model::{queue_async, queue_delay, Job, JobHandler, JobModel};
+pub use schedule::Schedule;
 pub use worker::Worker;
@@ -27,7 +27,7 @@ pub struct JobModel {
 }
 
 impl JobModel {
-    fn new(name: &str, args: serde_json::Value) -> Self {
+    fn new(name: &str, args: serde_json::Value, retries: i64) -> Self {
         Self {
             id: None,
             name: name.to_string(),
@@ -36,14 +36,14 @@ impl JobModel {
             start_after: OffsetDateTime::now_utc(),
             started_at: None,
             attempts: 0,
-            retries: 25,
+            retries,
             completed_at: None,
             error: None,
         }
     }
 
-    fn new_with_delay(name: &str, args: serde_json::Value, delay: Duration) -> Self {
-        let mut job = Self::new(name, args);
+    fn new_with_delay(name: &str, args: serde_json::Value, retries: i64, delay: Duration) -> Self {
+        let mut job = Self::new(name, args, retries);
         job.start_after = OffsetDateTime::now_utc() + delay;
         job
     }
@@ -87,7 +87,58 @@ impl JobModel {
 
     /// Get all jobs that are currently queued.
     pub fn queued() -> Scope<Self> {
-        Self::filter("completed_at", Value::Null).filter("started_at", Value::Null)
+        Self::filter("completed_at", Value::Null)
+            .filter("started_at", Value::Null)
+            .filter_lt("attempts", JobModel::column("retries"))
+    }
+
+    /// Get all jobs that failed on every attempt and won't be retried again.
+    pub fn dead() -> Scope<Self> {
+        Self::filter("completed_at", Value::Null)
+            .filter("started_at", Value::Null)
+            .filter_gte("attempts", JobModel::column("retries"))
+    }
+
+    /// Put dead jobs back in the queue, e.g. after fixing the bug that made them fail.
+    /// They are retried as many times as before.
+    pub fn retry_dead() -> Scope<Self> {
+        Self::dead().update_all(&[("attempts", 0)])
+    }
+
+    /// Run the job again now, e.g. after it failed on every attempt. It's retried as many times
+    /// as before. Jobs which are running or have finished aren't changed.
+    pub fn retry(id: i64) -> Scope<Self> {
+        Self::filter("id", id)
+            .filter("completed_at", Value::Null)
+            .filter("started_at", Value::Null)
+            .update_all(&[
+                ("attempts", Value::Int(0)),
+                ("start_after", OffsetDateTime::now_utc().to_value()),
+            ])
+    }
+
+    /// Remove the job from the queue without running it. Jobs which are running
+    /// or have finished aren't removed.
+    pub fn discard(id: i64) -> Scope<Self> {
+        Self::find_by_sql(
+            "DELETE FROM rwf_jobs WHERE id = $1 AND completed_at IS NULL AND started_at IS NULL RETURNING *",
+            &[id.to_value()],
+        )
+    }
+
+    /// Get all jobs that finished successfully.
+    pub fn succeeded() -> Scope<Self> {
+        Self::all().not("completed_at", Value::Null)
+    }
+
+    /// Get all queued jobs which failed before and will be retried.
+    pub fn retrying() -> Scope<Self> {
+        Self::queued().not("error", Value::Null)
+    }
+
+    /// The job failed on every attempt.
+    pub fn is_dead(&self) -> bool {
+        self.completed_at.is_none() && self.attempts as i64 >= self.retries
     }
 
     /// Get all jobs that had a problem.
@@ -179,7 +230,7 @@ pub trait Job: Sync + Send {
     /// running the job.
     async fn execute_async(&self, args: serde_json::Value) -> Result<(), Error> {
         let mut conn = get_connection().await?;
-        JobModel::new(self.job_name(), args)
+        JobModel::new(self.job_name(), args, self.retries())
             .save()
             .execute(&mut conn)
             .await?;
@@ -191,7 +242,7 @@ pub trait Job: Sync + Send {
 
     async fn execute_delay(&self, args: serde_json::Value, delay: Duration) -> Result<(), Error> {
         let mut conn = get_connection().await?;
-        JobModel::new_with_delay(self.job_name(), args, delay)
+        JobModel::new_with_delay(self.job_name(), args, self.retries(), delay)
             .save()
             .execute(&mut conn)
             .await?;
@@ -205,6 +256,44 @@ pub trait Job: Sync + Send {
         Ok(())
     }
 
+    /// Schedule this job to run in the background with the arguments.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// WelcomeEmail::enqueue(WelcomeEmailArgs { user_id: 1 }).await?;
+    /// ```
+    async fn enqueue<T: Serialize + Send>(args: T) -> Result<(), Error>
+    where
+        Self: Default + Sized,
+    {
+        Self::default()
+            .execute_async(serde_json::to_value(args)?)
+            .await
+    }
+
+    /// Schedule this job to run in the background with the arguments, after the delay.
+    async fn enqueue_in<T: Serialize + Send>(args: T, delay: Duration) -> Result<(), Error>
+    where
+        Self: Default + Sized,
+    {
+        Self::default()
+            .execute_delay(serde_json::to_value(args)?, delay)
+            .await
+    }
+
+    /// How many times the job is attempted before it's considered dead. Failed attempts
+    /// are retried with exponential back-off.
+    fn retries(&self) -> i64 {
+        25
+    }
+
+    /// Account billed for running the job with these arguments, if [usage is metered](crate::analytics::usage).
+    /// Jobs that finish successfully are counted under the `jobs` metric.
+    fn account(&self, _args: &serde_json::Value) -> Option<String> {
+        None
+    }
+
     fn schedule(self, args: serde_json::Value, schedule: &str) -> Result<ScheduledJob, Error>
     where
         Self: Sized + 'static,
@@ -260,3 +349,56 @@ pub async fn queue_delay<T: Job + Serialize>(job: &T, delay: Duration) -> Result
 pub async fn queue_async<T: Job + Serialize>(job: &T) -> Result<(), Error> {
     queue(job).await
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{test::bootstrap, Pool};
+
+    #[tokio::test]
+    async fn test_dead_jobs() -> Result<(), Error> {
+        let pool = Pool::from_env();
+        let mut conn = pool.transaction().await?;
+
+        bootstrap(conn.client()).await?;
+
+        let name = "rwf::job::model::test::DeadJob";
+        let mut job = JobModel::new(name, serde_json::json!({}), 2)
+            .save()
+            .fetch(&mut conn)
+            .await?;
+        assert!(!job.is_dead());
+
+        let queued = || JobModel::queued().filter("name", name);
+        let dead = || JobModel::dead().filter("name", name);
+
+        for _ in 0..2 {
+            assert_eq!(queued().count(&mut conn).await?, 1);
+            job.attempts += 1;
+            job = job.save().fetch(&mut conn).await?;
+        }
+
+        assert!(job.is_dead());
+        assert_eq!(queued().count(&mut conn).await?, 0);
+        assert_eq!(dead().count(&mut conn).await?, 1);
+
+        JobModel::retry_dead().execute(&mut conn).await?;
+        assert_eq!(dead().count(&mut conn).await?, 0);
+        assert_eq!(queued().count(&mut conn).await?, 1);
+
+        let id = job.id.unwrap();
+        job.attempts = 2;
+        job.start_after = OffsetDateTime::now_utc() + Duration::hours(1);
+        job.save().execute(&mut conn).await?;
+        JobModel::retry(id).execute(&mut conn).await?;
+        assert_eq!(queued().count(&mut conn).await?, 1);
+        let job = JobModel::find(id).fetch(&mut conn).await?;
+        assert_eq!(job.attempts, 0);
+        assert!(job.start_after <= OffsetDateTime::now_utc());
+
+        assert_eq!(JobModel::discard(id).fetch_all(&mut conn).await?.len(), 1);
+        assert_eq!(queued().count(&mut conn).await?, 0);
+
+        Ok(())
+    }
+}
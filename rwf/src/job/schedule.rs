@@ -0,0 +1,180 @@
+//! Schedules for jobs run by the clock.
+//!
+//! Jobs can run at a fixed interval, or on a cron schedule:
+//!
+//! ```
+//! # use rwf::prelude::*;
+//! # use rwf::job::{Error as JobError, Schedule};
+//! #[derive(Default)]
+//! struct Cleanup;
+//!
+//! #[async_trait]
+//! impl Job for Cleanup {
+//!     async fn execute(&self, _args: serde_json::Value) -> Result<(), JobError> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let hourly = Schedule::every(Duration::hours(1)).run::<Cleanup>();
+//! let nightly = Schedule::cron("0 3 * * *").unwrap().run::<Cleanup>();
+//! ```
+use std::fmt::Display;
+
+use serde_json::Value;
+use time::{Duration, OffsetDateTime};
+
+use super::{clock::ScheduledJob, Cron, Error, Job};
+
+/// When a scheduled job runs, and with which arguments.
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    timing: Timing,
+    pub(crate) args: Value,
+}
+
+#[derive(Clone, Debug)]
+enum Timing {
+    /// Interval in seconds.
+    Every(i64),
+    Cron(Cron),
+}
+
+impl Schedule {
+    /// Run the job every `interval`, rounded down to the second. Intervals are counted
+    /// from the Unix epoch, so a job running every hour runs at the start of each hour,
+    /// no matter when the clock was started.
+    pub fn every(interval: Duration) -> Self {
+        Self {
+            timing: Timing::Every(interval.whole_seconds().max(1)),
+            args: Value::Null,
+        }
+    }
+
+    /// Run the job on a cron schedule, e.g. `"0 0 * * 0"` for every Sunday at midnight.
+    pub fn cron(schedule: &str) -> Result<Self, Error> {
+        Ok(Self {
+            timing: Timing::Cron(Cron::parse(schedule)?),
+            args: Value::Null,
+        })
+    }
+
+    /// Arguments passed to the job. Jobs receive `null` by default.
+    pub fn args(mut self, args: Value) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Run the job, created with [`Default`], on this schedule.
+    pub fn run<T: Job + Default + 'static>(self) -> ScheduledJob {
+        self.run_job(T::default())
+    }
+
+    /// Run the job on this schedule.
+    pub fn run_job(self, job: impl Job + 'static) -> ScheduledJob {
+        ScheduledJob::with_schedule(self, job)
+    }
+
+    /// The job should run at this time.
+    pub fn should_run(&self, time: &OffsetDateTime) -> bool {
+        match &self.timing {
+            Timing::Every(seconds) => time.unix_timestamp() % seconds == 0,
+            Timing::Cron(cron) => cron.should_run(time),
+        }
+    }
+
+    /// Next time the job runs, after this time.
+    pub fn next_run(&self, after: &OffsetDateTime) -> Option<OffsetDateTime> {
+        match &self.timing {
+            Timing::Every(seconds) => {
+                let next = (after.unix_timestamp().div_euclid(*seconds) + 1) * seconds;
+                OffsetDateTime::from_unix_timestamp(next).ok()
+            }
+            Timing::Cron(cron) => cron.next_run(after),
+        }
+    }
+}
+
+impl Display for Schedule {
+    /// The cron expression, or the interval, e.g. `every 1h 30m`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.timing {
+            Timing::Cron(cron) => write!(f, "{}", cron),
+            Timing::Every(seconds) => {
+                let mut parts = vec![];
+                let mut rest = *seconds;
+
+                for (name, unit) in [("d", 86_400), ("h", 3600), ("m", 60), ("s", 1)] {
+                    if rest >= unit {
+                        parts.push(format!("{}{}", rest / unit, name));
+                        rest %= unit;
+                    }
+                }
+
+                write!(f, "every {}", parts.join(" "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_trait::async_trait;
+
+    #[derive(Default)]
+    struct Cleanup;
+
+    #[async_trait]
+    impl Job for Cleanup {
+        async fn execute(&self, _args: Value) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_schedule() {
+        let hour = OffsetDateTime::from_unix_timestamp(1_700_000_000 / 3600 * 3600).unwrap();
+
+        let schedule = Schedule::every(Duration::hours(1));
+        assert!(schedule.should_run(&hour));
+        assert!(!schedule.should_run(&(hour + Duration::seconds(1))));
+        assert!(!schedule.should_run(&(hour + Duration::minutes(30))));
+        assert!(schedule.should_run(&(hour + Duration::hours(1))));
+
+        // Shorter intervals run every second.
+        let schedule = Schedule::every(Duration::milliseconds(10));
+        assert!(schedule.should_run(&(hour + Duration::seconds(1))));
+
+        let schedule = Schedule::cron("30 * * * *").unwrap();
+        assert!(!schedule.should_run(&hour));
+        assert!(schedule.should_run(&(hour + Duration::minutes(30))));
+        assert!(Schedule::cron("every hour").is_err());
+
+        let job = Schedule::every(Duration::minutes(5))
+            .args(serde_json::json!({"days": 30}))
+            .run::<Cleanup>();
+        assert_eq!(job.job().job_name(), Cleanup.job_name());
+        assert_eq!(job.args()["days"], 30);
+    }
+
+    #[test]
+    fn test_next_run() {
+        let hour = OffsetDateTime::from_unix_timestamp(1_700_000_000 / 3600 * 3600).unwrap();
+
+        let schedule = Schedule::every(Duration::hours(1));
+        assert_eq!(schedule.to_string(), "every 1h");
+        assert_eq!(schedule.next_run(&hour), Some(hour + Duration::hours(1)));
+        assert_eq!(
+            schedule.next_run(&(hour + Duration::minutes(59))),
+            Some(hour + Duration::hours(1))
+        );
+
+        let schedule = Schedule::every(Duration::minutes(90) + Duration::seconds(5));
+        assert_eq!(schedule.to_string(), "every 1h 30m 5s");
+        assert_eq!(Schedule::every(Duration::days(2)).to_string(), "every 2d");
+
+        let schedule = Schedule::cron("30 * * * *").unwrap();
+        assert_eq!(schedule.to_string(), "30 * * * *");
+        assert_eq!(schedule.next_run(&hour), Some(hour + Duration::minutes(30)));
+    }
+}
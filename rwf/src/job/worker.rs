@@ -10,14 +10,21 @@ use crate::colors::MaybeColorize;
 use time::OffsetDateTime;
 
 use tokio::time::{sleep, Duration};
-use tracing::{error, info, warn};
+use tracing::{error, info, warn, Instrument};
 
+use crate::analytics::usage;
+use crate::app::Shutdown;
+use crate::events::{self, Event, JobFailed};
 use crate::model::{get_connection, get_pool, Model};
+use crate::telemetry;
 
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Instant;
 
+/// Longest time to wait before retrying a failed job, in seconds.
+const MAX_BACKOFF: u64 = 24 * 3600;
+
 /// Background job worker.
 #[derive(Clone)]
 pub struct Worker {
@@ -75,9 +82,15 @@ impl Worker {
     ///
     /// This implements the worker logic of fetching and running jobs.
     pub async fn run(&self) {
+        self.run_until(Shutdown::never()).await
+    }
+
+    /// Run the background worker until the application shuts down. The job being
+    /// executed is finished before returning.
+    pub async fn run_until(&self, shutdown: Shutdown) {
         info!("Background jobs worker started");
 
-        loop {
+        while !shutdown.is_shutdown() {
             let start = Instant::now();
             let worker = self.clone();
             let run_result = tokio::spawn(async move {
@@ -109,16 +122,26 @@ impl Worker {
 
                         // Run the job in a separate task. If the job panics,
                         // we won't crash this task.
-                        let result = tokio::spawn(async move {
-                            let registered_job = &worker.jobs[&name];
+                        let span = telemetry::job_span(&name, job.id);
+
+                        let result = tokio::spawn(
+                            async move {
+                                let registered_job = &worker.jobs[&name];
 
-                            registered_job.job.execute(args).await?;
+                                registered_job.job.execute(args.clone()).await?;
 
-                            Ok::<(), Error>(())
-                        })
+                                if let Some(account) = registered_job.job.account(&args) {
+                                    usage::record(account, "jobs", 1);
+                                }
+
+                                Ok::<(), Error>(())
+                            }
+                            .instrument(span),
+                        )
                         .await;
 
                         let elapsed = now.elapsed();
+                        telemetry::record_job(&job.name, matches!(result, Ok(Ok(()))), elapsed);
 
                         let mut conn = get_connection().await?;
 
@@ -149,14 +172,33 @@ impl Worker {
                                 );
 
                                 // Retry with exponential back-off.
-                                let delay =
-                                    Duration::from_secs(2_i64.pow(job.attempts as u32) as u64);
+                                let delay = Duration::from_secs(
+                                    2_u64.saturating_pow(job.attempts as u32).min(MAX_BACKOFF),
+                                );
 
-                                job.error = Some(err);
                                 job.attempts += 1;
-                                job.start_after = job.created_at + delay;
+                                job.start_after = OffsetDateTime::now_utc() + delay;
                                 job.started_at = None;
 
+                                events::emit(Event::JobFailed(JobFailed {
+                                    name: &job.name,
+                                    id: job.id,
+                                    args: &job.args,
+                                    error: &err,
+                                    attempts: job.attempts,
+                                    dead: job.is_dead(),
+                                }));
+
+                                job.error = Some(err);
+
+                                if job.is_dead() {
+                                    error!(
+                                        "job {} failed {} times and won't be retried",
+                                        job.name.green(),
+                                        job.attempts
+                                    );
+                                }
+
                                 job.save().execute(&mut conn).await?;
                             }
                         }
@@ -185,6 +227,11 @@ impl Worker {
         }
     }
 
+    /// Scheduled jobs run by this worker, if any.
+    pub(crate) fn scheduler(&self) -> Option<&Clock> {
+        self.clock.as_ref()
+    }
+
     /// Spawn an additional instance of this worker. Spawning more workers
     /// creates more concurrency in the system but uses more system resources.
     pub fn spawn(&self) -> &Self {
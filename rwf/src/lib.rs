@@ -82,19 +82,36 @@
 //!
 // #![warn(missing_docs)]
 pub mod analytics;
+pub mod app;
+pub mod assets;
+pub mod auth;
+pub mod broker;
+pub mod cluster;
 pub mod colors;
 pub mod comms;
 pub mod config;
 pub mod controller;
 pub mod crypto;
+pub mod dashboard;
 pub mod error;
+pub mod events;
+pub mod gdpr;
 pub mod hmr;
 pub mod http;
+pub mod i18n;
 pub mod job;
 pub mod lock;
 pub mod logging;
+pub mod mail;
 pub mod model;
+pub mod payments;
 pub mod prelude;
+pub mod replay;
+pub mod search;
+pub mod storage;
+pub mod tasks;
+pub mod telemetry;
+pub mod testing;
 pub mod view;
 
 /// Wrapper around async traits to make them easy to use.
@@ -103,6 +120,8 @@ pub use async_trait::async_trait;
 pub use rwf_macros as macros;
 /// Serde is used for (de)serialization.
 pub use serde;
+/// JSON (de)serialization, used by the `Schema` derive.
+pub use serde_json;
 /// Tokio is an asynchronous runtime for Rust.
 pub use tokio;
 /// Asynchronous PostgreSQL driver.
@@ -161,7 +180,12 @@ pub fn pascal_case(string: &str) -> String {
 /// Remove unsafe characters from a string printed
 /// inside an HTML template.
 pub fn safe_html(string: &str) -> String {
-    string.replace("<", "&lt;").replace(">", "&gt;")
+    string
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
 }
 
 /// Extract the first socket address from a string.
@@ -176,3 +200,5 @@ pub fn peer_addr(addr: &str) -> Option<SocketAddr> {
 
     None
 }
+/// Asynchronous streams, used for streaming response bodies.
+pub use tokio_stream;
@@ -2,6 +2,7 @@
 //!
 //! Configures application-wide logging to go to stderr at the `INFO` level.
 //! If you prefer to use your own logging subscriber, don't initialize the `Logger`.
+//! With the `telemetry` feature enabled, spans are exported to an OTLP collector as well; see [`crate::telemetry`].
 //!
 //! ### Example
 //!
@@ -12,7 +13,9 @@
 //! ```
 use crate::config::get_config;
 use once_cell::sync::OnceCell;
-use tracing_subscriber::{filter::LevelFilter, fmt, util::SubscriberInitExt, EnvFilter};
+use tracing_subscriber::{
+    filter::LevelFilter, fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter,
+};
 
 static INITIALIZED: OnceCell<()> = OnceCell::new();
 
@@ -33,15 +36,28 @@ impl Logger {
 }
 
 fn setup_logging() {
-    fmt()
-        .with_env_filter(
+    let registry = tracing_subscriber::registry()
+        .with(
             EnvFilter::builder()
                 .with_default_directive(LevelFilter::INFO.into())
                 .from_env_lossy(),
         )
-        .with_ansi(get_config().general.tty)
-        .with_file(false)
-        .with_target(false)
-        .finish()
-        .init();
+        .with(
+            fmt::layer()
+                .with_ansi(get_config().general.tty)
+                .with_file(false)
+                .with_target(false),
+        );
+
+    // Export spans to the OTLP collector.
+    #[cfg(feature = "telemetry")]
+    let registry = registry.with(match crate::telemetry::layer() {
+        Ok(layer) => Some(layer),
+        Err(err) => {
+            eprintln!("telemetry disabled: {}", err);
+            None
+        }
+    });
+
+    registry.init();
 }
@@ -0,0 +1,43 @@
+//! Errors returned by mailers.
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The mailer is missing from the configuration.
+    #[error("mail is not configured: {0} is missing")]
+    NotConfigured(&'static str),
+
+    /// The email can't be delivered as is, e.g. it has no recipients.
+    #[error("email is invalid: {0}")]
+    Invalid(&'static str),
+
+    /// The SMTP server rejected a command.
+    #[error("smtp server replied {0}: {1}")]
+    Smtp(u16, String),
+
+    /// The email delivery service rejected the email.
+    #[error("email provider replied {0}: {1}")]
+    Provider(u16, String),
+
+    /// Error connecting to the SMTP server.
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The SMTP server didn't reply in time.
+    #[error("smtp server timeout")]
+    Timeout(#[from] tokio::time::error::Elapsed),
+
+    /// Error rendering the email template.
+    #[error("template: {0}")]
+    Template(Box<crate::view::template::Error>),
+
+    /// Error calling the email delivery service.
+    #[error("{0}")]
+    Client(#[from] crate::http::client::Error),
+}
+
+impl From<crate::view::template::Error> for Error {
+    fn from(err: crate::view::template::Error) -> Self {
+        Self::Template(Box::new(err))
+    }
+}
@@ -0,0 +1,309 @@
+//! Email messages.
+use base64::{engine::general_purpose, Engine as _};
+use time::{format_description::well_known::Rfc2822, OffsetDateTime};
+use uuid::Uuid;
+
+use super::Error;
+use crate::view::template::{Context, Error as TemplateError, Template};
+
+/// An email.
+///
+/// ```
+/// use rwf::mail::Email;
+///
+/// let email = Email::new()
+///     .from("Acme <hello@acme.com>")
+///     .to("alice@example.com")
+///     .subject("Welcome!")
+///     .text("Thanks for signing up.");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Email {
+    /// Sender, e.g. `"Acme <hello@acme.com>"`.
+    pub from: Option<String>,
+    /// Recipients.
+    pub to: Vec<String>,
+    /// Recipients receiving a copy.
+    pub cc: Vec<String>,
+    /// Recipients receiving a copy, hidden from other recipients.
+    pub bcc: Vec<String>,
+    /// Where replies should be sent.
+    pub reply_to: Option<String>,
+    /// Subject line.
+    pub subject: String,
+    /// Plain text body.
+    pub text: Option<String>,
+    /// HTML body.
+    pub html: Option<String>,
+    /// Additional headers.
+    pub headers: Vec<(String, String)>,
+}
+
+impl Email {
+    /// Create an empty email.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the sender.
+    pub fn from(mut self, from: impl ToString) -> Self {
+        self.from = Some(from.to_string());
+        self
+    }
+
+    /// Add a recipient.
+    pub fn to(mut self, to: impl ToString) -> Self {
+        self.to.push(to.to_string());
+        self
+    }
+
+    /// Add a recipient receiving a copy.
+    pub fn cc(mut self, cc: impl ToString) -> Self {
+        self.cc.push(cc.to_string());
+        self
+    }
+
+    /// Add a hidden recipient.
+    pub fn bcc(mut self, bcc: impl ToString) -> Self {
+        self.bcc.push(bcc.to_string());
+        self
+    }
+
+    /// Set the address replies are sent to.
+    pub fn reply_to(mut self, reply_to: impl ToString) -> Self {
+        self.reply_to = Some(reply_to.to_string());
+        self
+    }
+
+    /// Set the subject line.
+    pub fn subject(mut self, subject: impl ToString) -> Self {
+        self.subject = subject.to_string();
+        self
+    }
+
+    /// Set the plain text body.
+    pub fn text(mut self, text: impl ToString) -> Self {
+        self.text = Some(text.to_string());
+        self
+    }
+
+    /// Set the HTML body.
+    pub fn html(mut self, html: impl ToString) -> Self {
+        self.html = Some(html.to_string());
+        self
+    }
+
+    /// Add a header, e.g. `List-Unsubscribe`.
+    pub fn header(mut self, name: impl ToString, value: impl ToString) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Render the body from templates. The HTML body is rendered from `<path>.html` and the
+    /// plain text body from `<path>.txt`. At least one of them has to exist.
+    ///
+    /// ```ignore
+    /// let email = Email::new()
+    ///     .to(&user.email)
+    ///     .subject("Welcome!")
+    ///     .template("templates/mail/welcome", [("name", user.name.as_str())])?;
+    /// ```
+    pub fn template(
+        mut self,
+        path: &str,
+        context: impl TryInto<Context, Error = TemplateError>,
+    ) -> Result<Self, Error> {
+        let context = context.try_into()?;
+
+        let html = Self::render(&format!("{}.html", path), &context)?;
+        let text = Self::render(&format!("{}.txt", path), &context)?;
+
+        if html.is_none() && text.is_none() {
+            return Err(
+                TemplateError::TemplateDoesNotExist(format!("{}.html", path).into()).into(),
+            );
+        }
+
+        if html.is_some() {
+            self.html = html;
+        }
+
+        if text.is_some() {
+            self.text = text;
+        }
+
+        Ok(self)
+    }
+
+    fn render(path: &str, context: &Context) -> Result<Option<String>, Error> {
+        match Template::load(path) {
+            Ok(template) => Ok(Some(template.render(context)?)),
+            Err(TemplateError::TemplateDoesNotExist(_)) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// All recipients, including hidden ones.
+    pub fn recipients(&self) -> impl Iterator<Item = &String> {
+        self.to.iter().chain(self.cc.iter()).chain(self.bcc.iter())
+    }
+
+    /// Check the email can be delivered.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.from.is_none() {
+            return Err(Error::Invalid("sender is missing"));
+        }
+
+        if self.recipients().next().is_none() {
+            return Err(Error::Invalid("recipients are missing"));
+        }
+
+        if self.text.is_none() && self.html.is_none() {
+            return Err(Error::Invalid("body is missing"));
+        }
+
+        Ok(())
+    }
+
+    /// Format the email as a MIME message (RFC 5322), as sent to SMTP servers.
+    /// Hidden recipients aren't included.
+    pub fn mime(&self) -> Result<String, Error> {
+        self.validate()?;
+
+        let from = self.from.as_deref().unwrap_or_default();
+        let domain = address(from)
+            .rsplit_once('@')
+            .map(|(_, domain)| domain)
+            .unwrap_or("localhost");
+
+        let mut headers = vec![
+            ("From".to_string(), from.to_string()),
+            (
+                "Date".to_string(),
+                OffsetDateTime::now_utc()
+                    .format(&Rfc2822)
+                    .unwrap_or_default(),
+            ),
+            (
+                "Message-ID".to_string(),
+                format!("<{}@{}>", Uuid::new_v4(), domain),
+            ),
+        ];
+
+        if !self.to.is_empty() {
+            headers.push(("To".into(), self.to.join(", ")));
+        }
+
+        if !self.cc.is_empty() {
+            headers.push(("Cc".into(), self.cc.join(", ")));
+        }
+
+        if let Some(ref reply_to) = self.reply_to {
+            headers.push(("Reply-To".into(), reply_to.clone()));
+        }
+
+        headers.push(("Subject".into(), encode_header(&self.subject)));
+        headers.push(("MIME-Version".into(), "1.0".into()));
+        headers.extend(self.headers.iter().cloned());
+
+        let mut message = String::new();
+
+        for (name, value) in &headers {
+            message.push_str(&format!("{}: {}\r\n", clean(name), clean(value)));
+        }
+
+        match (&self.text, &self.html) {
+            (Some(text), Some(html)) => {
+                let boundary = format!("rwf-{}", Uuid::new_v4().simple());
+
+                message.push_str(&format!(
+                    "Content-Type: multipart/alternative; boundary=\"{}\"\r\n\r\n",
+                    boundary
+                ));
+
+                for (content_type, body) in [("text/plain", text), ("text/html", html)] {
+                    message.push_str(&format!("--{}\r\n", boundary));
+                    message.push_str(&part(content_type, body));
+                }
+
+                message.push_str(&format!("--{}--\r\n", boundary));
+            }
+
+            (Some(text), None) => message.push_str(&part("text/plain", text)),
+            (None, Some(html)) => message.push_str(&part("text/html", html)),
+            (None, None) => (),
+        }
+
+        Ok(message)
+    }
+}
+
+/// Email address, without the name, e.g. `hello@acme.com` for `"Acme <hello@acme.com>"`.
+pub fn address(mailbox: &str) -> &str {
+    match (mailbox.rfind('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &mailbox[start + 1..end],
+        _ => mailbox.trim(),
+    }
+}
+
+/// Body part with its headers, encoded in base64.
+fn part(content_type: &str, body: &str) -> String {
+    let encoded = general_purpose::STANDARD.encode(body);
+    let mut part = format!(
+        "Content-Type: {}; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n",
+        content_type
+    );
+
+    // Lines can't be longer than 76 characters.
+    for line in encoded.as_bytes().chunks(76) {
+        part.push_str(&String::from_utf8_lossy(line));
+        part.push_str("\r\n");
+    }
+
+    part
+}
+
+/// Encode non-ASCII header values (RFC 2047).
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        value.to_string()
+    } else {
+        format!("=?utf-8?B?{}?=", general_purpose::STANDARD.encode(value))
+    }
+}
+
+/// Remove line breaks, so values can't add headers.
+fn clean(value: &str) -> String {
+    value.replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mime() {
+        let email = Email::new().to("alice@example.com").text("Hello");
+        assert!(matches!(email.validate(), Err(Error::Invalid(_))));
+
+        let email = email
+            .from("Acme <hello@acme.com>")
+            .bcc("bob@example.com")
+            .subject("Héllo\r\nBcc: eve@example.com")
+            .html("<p>Hello</p>");
+        assert_eq!(email.recipients().count(), 2);
+
+        let mime = email.mime().unwrap();
+        assert!(mime.contains("From: Acme <hello@acme.com>\r\n"));
+        assert!(mime.contains("To: alice@example.com\r\n"));
+        assert!(mime.contains("@acme.com>\r\n"));
+        assert!(mime.contains("Subject: =?utf-8?B?"));
+        assert!(mime.contains("Content-Type: multipart/alternative"));
+        assert!(mime.contains(&general_purpose::STANDARD.encode("<p>Hello</p>")));
+        assert!(!mime.contains("bob@example.com"));
+        assert!(!mime.contains("\r\nBcc:"));
+
+        assert_eq!(address("Acme <hello@acme.com>"), "hello@acme.com");
+        assert_eq!(address(" hello@acme.com "), "hello@acme.com");
+    }
+}
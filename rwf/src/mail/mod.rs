@@ -0,0 +1,138 @@
+//! Sending emails.
+//!
+//! Emails are delivered by a [`Mailer`], configured in the `[mail]` section of `rwf.toml`:
+//!
+//! ```toml
+//! [mail]
+//! host = "smtp.example.com"
+//! username = "apikey"
+//! password = "secret"
+//! from = "Acme <hello@acme.com>"
+//! ```
+//!
+//! Bodies are rendered with templates, with an HTML part from `<path>.html`
+//! and a plain text part from `<path>.txt`:
+//!
+//! ```ignore
+//! use rwf::mail::Email;
+//!
+//! Email::new()
+//!     .to(&user.email)
+//!     .subject("Welcome!")
+//!     .template("templates/mail/welcome", [("name", user.name.as_str())])?
+//!     .deliver()
+//!     .await?;
+//! ```
+//!
+//! Email delivery services with an HTTP API can be used by implementing [`ApiProvider`].
+//! In tests, emails are captured by a [`TestMailer`] instead of being delivered.
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+
+use crate::config::{get_config, Environment};
+
+pub mod error;
+pub mod message;
+pub mod provider;
+pub mod smtp;
+pub mod testing;
+
+pub use error::Error;
+pub use message::{address, Email};
+pub use provider::ApiProvider;
+pub use smtp::Smtp;
+pub use testing::TestMailer;
+
+static MAILER: Lazy<RwLock<Option<Arc<dyn Mailer>>>> = Lazy::new(|| RwLock::new(None));
+
+/// Delivers emails.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    /// Deliver the email.
+    async fn deliver(&self, email: &Email) -> Result<(), Error>;
+}
+
+/// Deliver emails with this mailer, instead of the one in the configuration.
+pub fn set_mailer(mailer: impl Mailer + 'static) {
+    *MAILER.write() = Some(Arc::new(mailer));
+}
+
+/// Capture emails with a new [`TestMailer`], and return it so tests can check
+/// what was delivered.
+pub fn test() -> TestMailer {
+    let mailer = TestMailer::new();
+    set_mailer(mailer.clone());
+    mailer
+}
+
+/// The mailer delivering emails. Unless one was set with [`set_mailer`], it's an SMTP client
+/// configured in `rwf.toml`, or a [`TestMailer`] in the test environment.
+pub fn mailer() -> Result<Arc<dyn Mailer>, Error> {
+    if let Some(ref mailer) = *MAILER.read() {
+        return Ok(mailer.clone());
+    }
+
+    let config = get_config();
+    let mailer: Arc<dyn Mailer> = match Smtp::from_config(&config.mail) {
+        Some(smtp) => Arc::new(smtp),
+        None if config.environment == Environment::Test => Arc::new(TestMailer::new()),
+        None => return Err(Error::NotConfigured("mail.host")),
+    };
+
+    Ok(MAILER.write().get_or_insert(mailer).clone())
+}
+
+/// Deliver the email with the configured mailer. Emails without a sender
+/// are sent from the `from` address in the configuration.
+pub async fn deliver(mut email: Email) -> Result<(), Error> {
+    if email.from.is_none() {
+        email.from = get_config().mail.from.clone();
+    }
+
+    mailer()?.deliver(&email).await
+}
+
+impl Email {
+    /// Deliver the email with the configured mailer, see [`deliver`].
+    pub async fn deliver(self) -> Result<(), Error> {
+        deliver(self).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::view::template::Context;
+    use std::fs::{create_dir_all, write};
+
+    #[tokio::test]
+    async fn test_template_email() {
+        let dir = tempdir::TempDir::new("rwf_mail").unwrap();
+        let path = dir.path().join("templates/welcome");
+        create_dir_all(path.parent().unwrap()).unwrap();
+        write(path.with_extension("html"), "<p>Hi <%= name %></p>").unwrap();
+        write(path.with_extension("txt"), "Hi <%= name %>").unwrap();
+
+        let mut context = Context::new();
+        context.set("name", "Alice").unwrap();
+
+        let email = Email::new()
+            .from("hello@acme.com")
+            .to("alice@example.com")
+            .template(path.to_str().unwrap(), &context)
+            .unwrap();
+        assert_eq!(email.html.as_deref(), Some("<p>Hi Alice</p>"));
+        assert_eq!(email.text.as_deref(), Some("Hi Alice"));
+
+        let missing = Email::new().template("templates/does_not_exist", &context);
+        assert!(missing.is_err());
+
+        let mailer = TestMailer::new();
+        mailer.deliver(&email).await.unwrap();
+        assert_eq!(mailer.deliveries(), vec![email]);
+        assert!(mailer.deliver(&Email::new()).await.is_err());
+    }
+}
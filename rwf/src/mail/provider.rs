@@ -0,0 +1,51 @@
+//! Email delivery services with an HTTP API.
+use async_trait::async_trait;
+
+use super::{Email, Error, Mailer};
+use crate::http::client::Client;
+
+/// Email delivery service with an HTTP API, e.g. Postmark or SendGrid.
+/// Providers build the API request, and the email is delivered if the service
+/// replies with a 2xx status code.
+///
+/// ```
+/// use rwf::http::client::Client;
+/// use rwf::mail::{ApiProvider, Email, Error};
+///
+/// struct Postmark {
+///     token: String,
+/// }
+///
+/// impl ApiProvider for Postmark {
+///     fn request(&self, email: &Email) -> Result<Client, Error> {
+///         Ok(Client::post("https://api.postmarkapp.com/email")
+///             .header("x-postmark-server-token", &self.token)
+///             .json(&serde_json::json!({
+///                 "From": email.from,
+///                 "To": email.to.join(","),
+///                 "Subject": email.subject,
+///                 "TextBody": email.text,
+///                 "HtmlBody": email.html,
+///             }))?)
+///     }
+/// }
+/// ```
+pub trait ApiProvider: Send + Sync {
+    /// Build the API request delivering the email.
+    fn request(&self, email: &Email) -> Result<Client, Error>;
+}
+
+#[async_trait]
+impl<T: ApiProvider> Mailer for T {
+    async fn deliver(&self, email: &Email) -> Result<(), Error> {
+        email.validate()?;
+
+        let response = self.request(email)?.send().await?;
+
+        if response.ok() {
+            Ok(())
+        } else {
+            Err(Error::Provider(response.code(), response.text()))
+        }
+    }
+}
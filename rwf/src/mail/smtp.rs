@@ -0,0 +1,329 @@
+//! SMTP client.
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine as _};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_rustls::rustls::{crypto::ring, pki_types::ServerName, ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+use super::{address, Email, Error, Mailer};
+use crate::config::{MailConfig, SmtpTls};
+
+/// Mailer delivering emails to an SMTP server.
+///
+/// ```
+/// use rwf::mail::Smtp;
+/// use rwf::config::SmtpTls;
+///
+/// let smtp = Smtp::new("smtp.example.com")
+///     .port(465)
+///     .tls(SmtpTls::Implicit)
+///     .credentials("apikey", "secret");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Smtp {
+    host: String,
+    port: u16,
+    tls: SmtpTls,
+    credentials: Option<(String, String)>,
+    timeout: Duration,
+}
+
+impl Smtp {
+    /// Deliver emails to the server, on port 587 with `STARTTLS`.
+    pub fn new(host: impl ToString) -> Self {
+        Self {
+            host: host.to_string(),
+            port: 587,
+            tls: SmtpTls::StartTls,
+            credentials: None,
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Create the mailer from the `[mail]` section of the configuration, if the server is set.
+    pub fn from_config(config: &MailConfig) -> Option<Self> {
+        let host = config.host.as_ref()?;
+        let smtp = Self::new(host).port(config.port).tls(config.tls).timeout(
+            config
+                .timeout()
+                .try_into()
+                .unwrap_or(Duration::from_secs(30)),
+        );
+
+        Some(match (&config.username, config.password()) {
+            (Some(username), Some(password)) => smtp.credentials(username, password),
+            _ => smtp,
+        })
+    }
+
+    /// Server port.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// How to encrypt the connection.
+    pub fn tls(mut self, tls: SmtpTls) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Log in with the username and password.
+    pub fn credentials(mut self, username: impl ToString, password: impl ToString) -> Self {
+        self.credentials = Some((username.to_string(), password.to_string()));
+        self
+    }
+
+    /// How long to wait for the server to deliver an email.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn send(&self, email: &Email) -> Result<(), Error> {
+        let stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+
+        match self.tls {
+            SmtpTls::None => {
+                let mut conn = Connection::new(stream);
+                conn.hello().await?;
+                self.transaction(conn, email).await
+            }
+
+            SmtpTls::Implicit => {
+                let mut conn = Connection::new(self.encrypt(stream).await?);
+                conn.hello().await?;
+                self.transaction(conn, email).await
+            }
+
+            SmtpTls::StartTls => {
+                let mut conn = Connection::new(stream);
+                conn.hello().await?;
+                conn.command("STARTTLS", 220).await?;
+
+                let stream = self.encrypt(conn.stream.into_inner()).await?;
+                let mut conn = Connection::new(stream);
+                conn.command("EHLO localhost", 250).await?;
+                self.transaction(conn, email).await
+            }
+        }
+    }
+
+    async fn encrypt<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        stream: S,
+    ) -> Result<tokio_rustls::client::TlsStream<S>, Error> {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(std::io::Error::other)?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let name = ServerName::try_from(self.host.clone()).map_err(std::io::Error::other)?;
+
+        Ok(TlsConnector::from(Arc::new(config))
+            .connect(name, stream)
+            .await?)
+    }
+
+    async fn transaction<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        mut conn: Connection<S>,
+        email: &Email,
+    ) -> Result<(), Error> {
+        if let Some((ref username, ref password)) = self.credentials {
+            let token = general_purpose::STANDARD.encode(format!("\0{}\0{}", username, password));
+            conn.command(&format!("AUTH PLAIN {}", token), 235).await?;
+        }
+
+        let from = address(email.from.as_deref().unwrap_or_default());
+        conn.command(&format!("MAIL FROM:<{}>", from), 250).await?;
+
+        for recipient in email.recipients() {
+            conn.command(&format!("RCPT TO:<{}>", address(recipient)), 250)
+                .await?;
+        }
+
+        conn.command("DATA", 354).await?;
+
+        // Lines starting with a dot are escaped with another one,
+        // since a dot on its own line ends the message.
+        let mut data = String::new();
+        for line in email.mime()?.split("\r\n") {
+            if line.starts_with('.') {
+                data.push('.');
+            }
+            data.push_str(line);
+            data.push_str("\r\n");
+        }
+        data.push_str(".\r\n");
+
+        conn.write(&data).await?;
+        conn.reply(250).await?;
+
+        let _ = conn.command("QUIT", 221).await;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Mailer for Smtp {
+    async fn deliver(&self, email: &Email) -> Result<(), Error> {
+        email.validate()?;
+        timeout(self.timeout, self.send(email)).await?
+    }
+}
+
+/// Connection to an SMTP server.
+struct Connection<S> {
+    stream: BufReader<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    fn new(stream: S) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    /// Wait for the greeting and introduce ourselves.
+    async fn hello(&mut self) -> Result<(), Error> {
+        self.reply(220).await?;
+        self.command("EHLO localhost", 250).await?;
+        Ok(())
+    }
+
+    async fn command(&mut self, command: &str, expected: u16) -> Result<String, Error> {
+        self.write(&format!("{}\r\n", command)).await?;
+        self.reply(expected).await
+    }
+
+    async fn write(&mut self, data: &str) -> Result<(), Error> {
+        self.stream.get_mut().write_all(data.as_bytes()).await?;
+        self.stream.get_mut().flush().await?;
+        Ok(())
+    }
+
+    /// Read a reply, which can span many lines, e.g. `250-first`, `250 last`.
+    async fn reply(&mut self, expected: u16) -> Result<String, Error> {
+        let mut reply = String::new();
+
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await? == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+
+            let line = line.trim_end();
+            let code = line
+                .get(..3)
+                .and_then(|code| code.parse::<u16>().ok())
+                .ok_or_else(|| Error::Smtp(0, line.to_string()))?;
+            let last = line.as_bytes().get(3) != Some(&b'-');
+
+            reply.push_str(line.get(4..).unwrap_or_default());
+
+            if last {
+                if code == expected {
+                    return Ok(reply);
+                } else {
+                    return Err(Error::Smtp(code, reply));
+                }
+            }
+
+            reply.push('\n');
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_smtp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut commands = vec![];
+
+            stream.get_mut().write_all(b"220 ready\r\n").await.unwrap();
+
+            loop {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                let line = line.trim_end().to_string();
+
+                let reply: &[u8] = match line.as_str() {
+                    "EHLO localhost" => b"250-mail.test\r\n250 AUTH PLAIN\r\n",
+                    "DATA" => b"354 go ahead\r\n",
+                    "QUIT" => b"221 bye\r\n",
+                    line if line.starts_with("AUTH") => b"235 ok\r\n",
+                    _ => b"250 ok\r\n",
+                };
+
+                if line == "DATA" {
+                    stream.get_mut().write_all(reply).await.unwrap();
+                    let mut data = String::new();
+                    while !data.ends_with("\r\n.\r\n") {
+                        stream.read_line(&mut data).await.unwrap();
+                    }
+                    commands.push(data);
+                    stream.get_mut().write_all(b"250 queued\r\n").await.unwrap();
+                    continue;
+                }
+
+                let done = line == "QUIT";
+                commands.push(line);
+                stream.get_mut().write_all(reply).await.unwrap();
+
+                if done {
+                    return commands;
+                }
+            }
+        });
+
+        let smtp = Smtp::new("127.0.0.1")
+            .port(port)
+            .tls(SmtpTls::None)
+            .credentials("user", "pass");
+        let email = Email::new()
+            .from("Acme <hello@acme.com>")
+            .to("Alice <alice@example.com>")
+            .subject("Hello")
+            .text("Hello");
+        smtp.deliver(&email).await.unwrap();
+
+        let commands = server.await.unwrap();
+        assert_eq!(commands[1], "AUTH PLAIN AHVzZXIAcGFzcw==");
+        assert_eq!(commands[2], "MAIL FROM:<hello@acme.com>");
+        assert_eq!(commands[3], "RCPT TO:<alice@example.com>");
+        assert!(commands[4].contains("Subject: Hello\r\n"));
+        assert_eq!(commands[5], "QUIT");
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.write_all(b"554 go away\r\n").await.unwrap();
+        });
+
+        let smtp = Smtp::new("127.0.0.1").port(port).tls(SmtpTls::None);
+        assert!(matches!(
+            smtp.deliver(&email).await,
+            Err(Error::Smtp(554, _))
+        ));
+    }
+}
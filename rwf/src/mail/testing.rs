@@ -0,0 +1,63 @@
+//! Mailer capturing emails instead of delivering them, for tests.
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+
+use super::{Email, Error, Mailer};
+
+/// Mailer keeping delivered emails in memory, so tests can check them.
+///
+/// ```
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// use rwf::mail::{self, Email};
+///
+/// let mailer = mail::test();
+///
+/// Email::new()
+///     .from("hello@acme.com")
+///     .to("alice@example.com")
+///     .subject("Welcome!")
+///     .text("Thanks for signing up.")
+///     .deliver()
+///     .await
+///     .unwrap();
+///
+/// assert_eq!(mailer.last().unwrap().subject, "Welcome!");
+/// # })
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TestMailer {
+    deliveries: Arc<Mutex<Vec<Email>>>,
+}
+
+impl TestMailer {
+    /// Create a mailer with no deliveries.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Emails delivered so far.
+    pub fn deliveries(&self) -> Vec<Email> {
+        self.deliveries.lock().clone()
+    }
+
+    /// The last email delivered.
+    pub fn last(&self) -> Option<Email> {
+        self.deliveries.lock().last().cloned()
+    }
+
+    /// Forget delivered emails.
+    pub fn clear(&self) {
+        self.deliveries.lock().clear();
+    }
+}
+
+#[async_trait]
+impl Mailer for TestMailer {
+    async fn deliver(&self, email: &Email) -> Result<(), Error> {
+        email.validate()?;
+        self.deliveries.lock().push(email.clone());
+        Ok(())
+    }
+}
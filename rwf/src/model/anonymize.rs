@@ -0,0 +1,407 @@
+//! Replace personal data (PII) with fake values.
+//!
+//! Columns containing personal information, e.g. emails, names or phone numbers, are
+//! marked on the model with the `pii` attribute. The [`Anonymizer`] then replaces them
+//! with fake values on a copy of the production database, so it can be shared
+//! with developers or used for testing.
+//!
+//! Fake values are consistent: the same original value is always replaced with the same fake value,
+//! as long as the seed doesn't change. Records can still be joined on anonymized columns,
+//! and unique constraints keep holding (with the exception of names and addresses, which are picked from a short list).
+//!
+//! # Example
+//!
+//! ```ignore
+//! #[derive(Clone, macros::Model, macros::Anonymize)]
+//! struct User {
+//!     id: Option<i64>,
+//!     #[pii(email)]
+//!     email: String,
+//!     #[pii(name)]
+//!     full_name: String,
+//!     #[pii(null)]
+//!     phone: Option<String>,
+//! }
+//!
+//! let pool = Pool::new("postgres://localhost/myapp_copy", PoolConfig::default());
+//! let mut conn = pool.transaction().await?;
+//!
+//! Anonymizer::new("seed")
+//!     .model::<User>()
+//!     .run(&mut conn)
+//!     .await?;
+//!
+//! conn.commit().await?;
+//! ```
+use async_trait::async_trait;
+use tracing::info;
+
+use super::{ConnectionGuard, Error, Model};
+use crate::colors::MaybeColorize;
+use crate::config::get_config;
+
+static FIRST_NAMES: &[&str] = &[
+    "Alice", "Bob", "Carol", "David", "Emma", "Frank", "Grace", "Henry", "Isabel", "Jack", "Karen",
+    "Liam", "Maria", "Noah", "Olivia", "Peter", "Quinn", "Rosa", "Sam", "Tara",
+];
+
+static LAST_NAMES: &[&str] = &[
+    "Smith", "Johnson", "Williams", "Brown", "Jones", "Garcia", "Miller", "Davis", "Lopez",
+    "Wilson", "Anderson", "Thomas", "Taylor", "Moore", "Jackson", "Martin", "Lee", "Clark",
+    "Lewis", "Walker",
+];
+
+static STREETS: &[&str] = &[
+    "Main St",
+    "Oak Ave",
+    "Pine St",
+    "Maple Ave",
+    "Cedar Rd",
+    "Elm St",
+    "Park Ave",
+    "Lake Dr",
+    "Hill Rd",
+    "River Rd",
+];
+
+/// Kind of personal data stored in a column. Determines the fake value which replaces it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pii {
+    /// Email address, replaced with `user_<hash>@example.com`.
+    Email,
+    /// Full name, e.g. "Alice Smith".
+    Name,
+    /// First name, e.g. "Alice".
+    FirstName,
+    /// Last name, e.g. "Smith".
+    LastName,
+    /// Phone number, replaced with a `555` number.
+    Phone,
+    /// Street address, e.g. "42 Oak Ave".
+    Address,
+    /// IP address stored as `INET`. The host part is removed, leaving the `/24` (IPv4)
+    /// or `/48` (IPv6) network.
+    IpAddress,
+    /// Any text, replaced with its keyed hash.
+    Hash,
+    /// Removed entirely.
+    Null,
+}
+
+impl Pii {
+    /// SQL expression computing the fake value for the column. The seed is passed in as `$1`.
+    pub fn to_sql(&self, column: &str) -> String {
+        let column = format!(r#""{}""#, column.replace('"', r#""""#));
+
+        let value = match self {
+            Pii::Email => format!(
+                "'user_' || left({}, 12) || '@example.com'",
+                digest(&column, "email")
+            ),
+            Pii::Name => format!(
+                "{} || ' ' || {}",
+                pick(FIRST_NAMES, &column, "first_name"),
+                pick(LAST_NAMES, &column, "last_name")
+            ),
+            Pii::FirstName => pick(FIRST_NAMES, &column, "first_name"),
+            Pii::LastName => pick(LAST_NAMES, &column, "last_name"),
+            Pii::Phone => format!(
+                "'555-' || lpad(({} % 10000000)::TEXT, 7, '0')",
+                number(&column, "phone")
+            ),
+            Pii::Address => format!(
+                "({} % 9999 + 1)::TEXT || ' ' || {}",
+                number(&column, "address"),
+                pick(STREETS, &column, "street")
+            ),
+            Pii::IpAddress => format!(
+                "network(set_masklen({column}, CASE WHEN family({column}) = 4 THEN 24 ELSE 48 END))::INET"
+            ),
+            Pii::Hash => digest(&column, "hash"),
+            Pii::Null => return "NULL".into(),
+        };
+
+        // NULLs stay NULL.
+        format!("CASE WHEN {column} IS NULL THEN NULL ELSE {value} END")
+    }
+}
+
+/// Keyed hash of the column value. The kind is included so the same value
+/// used in different contexts doesn't produce related fake values.
+fn digest(column: &str, kind: &str) -> String {
+    format!("md5($1 || ':{}:' || {}::TEXT)", kind, column)
+}
+
+/// A non-negative number derived from the column value.
+fn number(column: &str, kind: &str) -> String {
+    format!(
+        "('x' || left({}, 8))::BIT(32)::BIGINT",
+        digest(column, kind)
+    )
+}
+
+/// Pick a value from the list based on the column value.
+fn pick(values: &[&str], column: &str, kind: &str) -> String {
+    let array = values
+        .iter()
+        .map(|value| format!("'{}'", value))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        "(ARRAY[{}])[1 + ({} % {})]",
+        array,
+        number(column, kind),
+        values.len()
+    )
+}
+
+/// Model with columns containing personal data.
+///
+/// Implement it with the `Anonymize` derive macro and the `pii` attribute on the fields.
+///
+/// ```
+/// # use rwf::prelude::*;
+/// # use rwf::model::anonymize::Pii;
+/// #[derive(Clone, macros::Model, macros::Anonymize)]
+/// struct User {
+///     id: Option<i64>,
+///     #[pii(email)]
+///     email: String,
+///     #[pii(ip_address)]
+///     last_ip: Option<String>,
+///     admin: bool,
+/// }
+///
+/// assert_eq!(
+///     User::pii(),
+///     &[("email", Pii::Email), ("last_ip", Pii::IpAddress)]
+/// );
+/// ```
+#[async_trait]
+pub trait Anonymize: Model {
+    /// Columns containing personal data.
+    fn pii() -> &'static [(&'static str, Pii)];
+
+    /// Query replacing all personal data in the table with fake values.
+    fn anonymize_sql() -> String {
+        let columns = Self::pii()
+            .iter()
+            .map(|(column, pii)| {
+                format!(
+                    r#""{}" = {}"#,
+                    column.replace('"', r#""""#),
+                    pii.to_sql(column)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        format!(r#"UPDATE "{}" SET {}"#, Self::table_name(), columns)
+    }
+
+    /// Replace personal data in all records with fake values. Returns the number of updated records.
+    ///
+    /// This changes data permanently; use [`Anonymizer`] which refuses to run against the application database.
+    async fn anonymize(conn: &mut ConnectionGuard, seed: &str) -> Result<u64, Error> {
+        if Self::pii().is_empty() {
+            return Ok(0);
+        }
+
+        let sql = Self::anonymize_sql();
+        Ok(conn.client().execute(&sql, &[&seed]).await?)
+    }
+}
+
+type Step = (&'static str, String);
+
+/// Anonymize personal data in a copy of the application database.
+pub struct Anonymizer {
+    seed: String,
+    steps: Vec<Step>,
+}
+
+impl Anonymizer {
+    /// Create an anonymizer. The seed determines the fake values; keep it secret,
+    /// since knowing it allows to check if a fake value was generated from a guessed original.
+    pub fn new(seed: impl ToString) -> Self {
+        Self {
+            seed: seed.to_string(),
+            steps: vec![],
+        }
+    }
+
+    /// Anonymize the model.
+    pub fn model<T: Anonymize>(mut self) -> Self {
+        if !T::pii().is_empty() {
+            self.steps.push((T::table_name(), T::anonymize_sql()));
+        }
+        self
+    }
+
+    /// Anonymize all models. Refuses to run against the database configured for the application,
+    /// which is most likely production.
+    ///
+    /// Use a transaction to make sure all models are anonymized, or none.
+    pub async fn run(&self, conn: &mut ConnectionGuard) -> Result<u64, Error> {
+        let database: String = conn
+            .client()
+            .query_one("SELECT current_database()::TEXT", &[])
+            .await?
+            .try_get(0)?;
+
+        if Some(database.as_str()) == configured_database().as_deref() {
+            return Err(Error::Unknown(format!(
+                "refusing to anonymize \"{}\", the application database; run it against a copy",
+                database
+            )));
+        }
+
+        let mut total = 0;
+
+        for (table, sql) in &self.steps {
+            let rows = conn.client().execute(sql, &[&self.seed]).await?;
+            info!("{} anonymized {} records", table.green(), rows);
+            total += rows;
+        }
+
+        Ok(total)
+    }
+}
+
+/// Name of the database the application is configured to use.
+fn configured_database() -> Option<String> {
+    let url = get_config().database.database_url();
+    let name = url.rsplit('/').next()?.split('?').next()?;
+
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{FromRow, Pool, ToValue, Value};
+
+    #[derive(Clone, Debug)]
+    struct Customer {
+        id: Option<i64>,
+        email: String,
+        name: String,
+        phone: Option<String>,
+    }
+
+    impl FromRow for Customer {
+        fn from_row(row: tokio_postgres::Row) -> Result<Self, Error> {
+            Ok(Self {
+                id: row.try_get("id")?,
+                email: row.try_get("email")?,
+                name: row.try_get("name")?,
+                phone: row.try_get("phone")?,
+            })
+        }
+    }
+
+    impl Model for Customer {
+        fn id(&self) -> Value {
+            self.id.to_value()
+        }
+
+        fn table_name() -> &'static str {
+            "rwf_test_anonymize_customers"
+        }
+
+        fn foreign_key() -> &'static str {
+            "customer_id"
+        }
+
+        fn column_names() -> &'static [&'static str] {
+            &["email", "name", "phone"]
+        }
+
+        fn values(&self) -> Vec<Value> {
+            vec![
+                self.email.to_value(),
+                self.name.to_value(),
+                self.phone.to_value(),
+            ]
+        }
+    }
+
+    impl Anonymize for Customer {
+        fn pii() -> &'static [(&'static str, Pii)] {
+            &[
+                ("email", Pii::Email),
+                ("name", Pii::Name),
+                ("phone", Pii::Phone),
+            ]
+        }
+    }
+
+    #[test]
+    fn test_anonymize_sql() {
+        assert_eq!(Pii::Null.to_sql("phone"), "NULL");
+        assert_eq!(
+            Pii::Hash.to_sql("ssn"),
+            r#"CASE WHEN "ssn" IS NULL THEN NULL ELSE md5($1 || ':hash:' || "ssn"::TEXT) END"#
+        );
+        assert!(Customer::anonymize_sql()
+            .starts_with(r#"UPDATE "rwf_test_anonymize_customers" SET "email" = CASE"#));
+    }
+
+    #[tokio::test]
+    async fn test_anonymize() -> Result<(), Error> {
+        let mut conn = Pool::begin().await?;
+
+        conn.client()
+            .execute(
+                "CREATE TABLE rwf_test_anonymize_customers (
+                    id BIGSERIAL PRIMARY KEY,
+                    email VARCHAR NOT NULL,
+                    name VARCHAR NOT NULL,
+                    phone VARCHAR
+                )",
+                &[],
+            )
+            .await?;
+
+        for (email, name, phone) in [
+            ("jane@acme.com", "Jane Doe", Some("+1 415 000 1234")),
+            ("john@acme.com", "John Doe", None),
+            ("jane@acme.com", "Jane D.", None),
+        ] {
+            Customer::create(&[
+                ("email", email.to_value()),
+                ("name", name.to_value()),
+                ("phone", phone.to_value()),
+            ])
+            .execute(&mut conn)
+            .await?;
+        }
+
+        assert_eq!(Customer::anonymize(&mut conn, "seed").await?, 3);
+
+        let customers = Customer::all().order("id").fetch_all(&mut conn).await?;
+
+        assert!(customers[0].email.starts_with("user_"));
+        assert!(customers[0].email.ends_with("@example.com"));
+        assert_eq!(customers[0].email, customers[2].email);
+        assert_ne!(customers[0].email, customers[1].email);
+        assert_eq!(customers[0].name.split(' ').count(), 2);
+        assert!(customers[0].phone.as_ref().unwrap().starts_with("555-"));
+        assert_eq!(customers[0].phone.as_ref().unwrap().len(), 11);
+        assert!(customers[1].phone.is_none());
+
+        // Running against the application database is not allowed.
+        assert!(Anonymizer::new("seed")
+            .model::<Customer>()
+            .run(&mut conn)
+            .await
+            .is_err());
+
+        conn.rollback().await
+    }
+}
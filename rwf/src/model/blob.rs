@@ -0,0 +1,379 @@
+//! Streaming binary data to and from the database.
+//!
+//! Small binary values, e.g. thumbnails or tokens, can be stored in `BYTEA` columns and
+//! read like any other column, as `Vec<u8>`. Larger values shouldn't be loaded in memory
+//! all at once: [`Bytea`] reads and writes a `BYTEA` column in chunks, and [`LargeObject`]
+//! streams PostgreSQL [large objects](https://www.postgresql.org/docs/current/largeobjects.html),
+//! which can be up to 4 TB in size.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! let mut file = tokio::fs::File::open("video.mp4").await?;
+//! let mut transaction = Pool::begin().await?;
+//!
+//! let video = LargeObject::create(&transaction, &mut file).await?;
+//! transaction.commit().await?;
+//! ```
+use std::marker::PhantomData;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::{pool::Transaction, ConnectionGuard, Error, Escape, Model, ToValue, Value};
+
+/// Default size of chunks read and written at a time: 256 KB.
+const CHUNK_SIZE: usize = 256 * 1024;
+
+// Large object access modes, from `libpq/libpq-fs.h`.
+const INV_WRITE: i32 = 0x0002_0000;
+const INV_READ: i32 = 0x0004_0000;
+
+/// Reads and writes a `BYTEA` column of a model in chunks.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let mut thumbnail = vec![];
+/// Bytea::<Image>::new(image_id, "thumbnail")
+///     .read(&conn, &mut thumbnail)
+///     .await?;
+/// ```
+pub struct Bytea<T: Model> {
+    id: Value,
+    column: String,
+    chunk_size: usize,
+    _model: PhantomData<T>,
+}
+
+impl<T: Model> Bytea<T> {
+    /// Access the column of the record with this primary key.
+    pub fn new(id: impl ToValue, column: &str) -> Self {
+        Self {
+            id: id.to_value(),
+            column: column.to_string(),
+            chunk_size: CHUNK_SIZE,
+            _model: PhantomData,
+        }
+    }
+
+    /// Read and write chunks of this size. Default: 256 KB.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Copy the column into the writer. Returns the number of bytes read.
+    /// `NULL` is treated as an empty value.
+    pub async fn read(
+        &self,
+        conn: &ConnectionGuard,
+        writer: &mut (impl AsyncWrite + Unpin),
+    ) -> Result<u64, Error> {
+        let query = format!(
+            r#"SELECT substring("{}" FROM $2 FOR $3) FROM "{}" WHERE "{}" = $1"#,
+            self.column.escape(),
+            T::table_name().escape(),
+            T::primary_key().escape(),
+        );
+        let chunk_size = self.chunk_size as i32;
+        let mut offset = 0_i64;
+
+        loop {
+            // Postgres strings start at position 1.
+            let start = offset as i32 + 1;
+            let row = conn
+                .client()
+                .query_opt(&query, &[&self.id, &start, &chunk_size])
+                .await?
+                .ok_or(Error::RecordNotFound)?;
+            let chunk: Option<Vec<u8>> = row.try_get(0)?;
+            let chunk = chunk.unwrap_or_default();
+
+            writer.write_all(&chunk).await?;
+            offset += chunk.len() as i64;
+
+            if chunk.len() < self.chunk_size {
+                break;
+            }
+        }
+
+        writer.flush().await?;
+
+        Ok(offset as u64)
+    }
+
+    /// Replace the column with the contents of the reader. Returns the number of bytes written.
+    ///
+    /// Each chunk is appended separately, so run this in a transaction
+    /// to make sure other queries don't see a partially written value.
+    pub async fn write(
+        &self,
+        conn: &ConnectionGuard,
+        reader: &mut (impl AsyncRead + Unpin),
+    ) -> Result<u64, Error> {
+        let table = T::table_name().escape();
+        let primary_key = T::primary_key().escape();
+        let column = self.column.escape();
+
+        let clear = format!(
+            r#"UPDATE "{}" SET "{}" = ''::bytea WHERE "{}" = $1"#,
+            table, column, primary_key
+        );
+        let append = format!(
+            r#"UPDATE "{}" SET "{}" = "{}" || $2 WHERE "{}" = $1"#,
+            table, column, column, primary_key
+        );
+
+        if conn.client().execute(&clear, &[&self.id]).await? == 0 {
+            return Err(Error::RecordNotFound);
+        }
+
+        let mut written = 0_u64;
+        let mut chunk = vec![0_u8; self.chunk_size];
+
+        loop {
+            let read = fill(reader, &mut chunk).await?;
+            if read == 0 {
+                break;
+            }
+
+            conn.client()
+                .execute(&append, &[&self.id, &&chunk[..read]])
+                .await?;
+            written += read as u64;
+        }
+
+        Ok(written)
+    }
+}
+
+/// PostgreSQL large object, identified by its OID.
+///
+/// Large objects can only be accessed inside a transaction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LargeObject {
+    oid: u32,
+}
+
+impl LargeObject {
+    /// Large object with this OID, e.g. stored in an `OID` column.
+    pub fn new(oid: u32) -> Self {
+        Self { oid }
+    }
+
+    /// The object's OID, used to find it again.
+    pub fn oid(&self) -> u32 {
+        self.oid
+    }
+
+    /// Create a large object with the contents of the reader.
+    pub async fn create(
+        transaction: &Transaction,
+        reader: &mut (impl AsyncRead + Unpin),
+    ) -> Result<Self, Error> {
+        let row = transaction
+            .client()
+            .query_one("SELECT lo_create(0)", &[])
+            .await?;
+        let object = Self::new(row.try_get(0)?);
+
+        object.write(transaction, reader).await?;
+
+        Ok(object)
+    }
+
+    /// Replace the contents of the object with the contents of the reader.
+    /// Returns the number of bytes written.
+    pub async fn write(
+        &self,
+        transaction: &Transaction,
+        reader: &mut (impl AsyncRead + Unpin),
+    ) -> Result<u64, Error> {
+        let client = transaction.client();
+        let fd = self.open(transaction, INV_WRITE).await?;
+
+        client.execute("SELECT lo_truncate($1, 0)", &[&fd]).await?;
+
+        let mut written = 0_u64;
+        let mut chunk = vec![0_u8; CHUNK_SIZE];
+
+        loop {
+            let read = fill(reader, &mut chunk).await?;
+            if read == 0 {
+                break;
+            }
+
+            client
+                .execute("SELECT lowrite($1, $2)", &[&fd, &&chunk[..read]])
+                .await?;
+            written += read as u64;
+        }
+
+        client.execute("SELECT lo_close($1)", &[&fd]).await?;
+
+        Ok(written)
+    }
+
+    /// Copy the contents of the object into the writer. Returns the number of bytes read.
+    pub async fn read(
+        &self,
+        transaction: &Transaction,
+        writer: &mut (impl AsyncWrite + Unpin),
+    ) -> Result<u64, Error> {
+        let client = transaction.client();
+        let fd = self.open(transaction, INV_READ).await?;
+        let chunk_size = CHUNK_SIZE as i32;
+        let mut read = 0_u64;
+
+        loop {
+            let row = client
+                .query_one("SELECT loread($1, $2)", &[&fd, &chunk_size])
+                .await?;
+            let chunk: Vec<u8> = row.try_get(0)?;
+
+            if chunk.is_empty() {
+                break;
+            }
+
+            writer.write_all(&chunk).await?;
+            read += chunk.len() as u64;
+        }
+
+        writer.flush().await?;
+        client.execute("SELECT lo_close($1)", &[&fd]).await?;
+
+        Ok(read)
+    }
+
+    /// Delete the object.
+    pub async fn delete(self, transaction: &Transaction) -> Result<(), Error> {
+        transaction
+            .client()
+            .execute("SELECT lo_unlink($1)", &[&self.oid])
+            .await?;
+
+        Ok(())
+    }
+
+    async fn open(&self, transaction: &Transaction, mode: i32) -> Result<i32, Error> {
+        let row = transaction
+            .client()
+            .query_one("SELECT lo_open($1, $2)", &[&self.oid, &mode])
+            .await?;
+
+        Ok(row.try_get(0)?)
+    }
+}
+
+/// Read from the reader until the buffer is full or the reader is done.
+async fn fill(reader: &mut (impl AsyncRead + Unpin), buffer: &mut [u8]) -> Result<usize, Error> {
+    let mut filled = 0;
+
+    while filled < buffer.len() {
+        let read = reader.read(&mut buffer[filled..]).await?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{FromRow, Pool};
+
+    #[derive(Clone, Debug)]
+    struct Attachment {
+        id: Option<i64>,
+        data: Option<Vec<u8>>,
+    }
+
+    impl FromRow for Attachment {
+        fn from_row(row: tokio_postgres::Row) -> Result<Self, Error> {
+            Ok(Self {
+                id: row.try_get("id")?,
+                data: row.try_get("data")?,
+            })
+        }
+    }
+
+    impl Model for Attachment {
+        fn table_name() -> &'static str {
+            "attachments"
+        }
+
+        fn foreign_key() -> &'static str {
+            "attachment_id"
+        }
+
+        fn column_names() -> &'static [&'static str] {
+            &["data"]
+        }
+
+        fn values(&self) -> Vec<Value> {
+            vec![self.data.to_value()]
+        }
+
+        fn id(&self) -> Value {
+            self.id.to_value()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bytea() -> Result<(), Error> {
+        let mut transaction = Pool::begin().await?;
+        transaction
+            .client()
+            .batch_execute(
+                "CREATE TEMPORARY TABLE attachments (id BIGSERIAL PRIMARY KEY, data BYTEA)",
+            )
+            .await?;
+
+        let attachment = Attachment::create(&[("data", vec![1_u8, 2, 3].to_value())])
+            .fetch(&mut transaction)
+            .await?;
+        assert_eq!(attachment.data, Some(vec![1, 2, 3]));
+
+        let data = (0..=255).collect::<Vec<u8>>();
+        let bytea = Bytea::<Attachment>::new(attachment.id, "data").chunk_size(100);
+        assert_eq!(bytea.write(&transaction, &mut &data[..]).await?, 256);
+
+        let mut read = vec![];
+        assert_eq!(bytea.read(&transaction, &mut read).await?, 256);
+        assert_eq!(read, data);
+
+        let attachment = Attachment::find(attachment.id)
+            .fetch(&mut transaction)
+            .await?;
+        assert_eq!(attachment.data, Some(data));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_large_object() -> Result<(), Error> {
+        let transaction = Pool::begin().await?;
+        let data = vec![7_u8; CHUNK_SIZE + 10];
+
+        let object = LargeObject::create(&transaction, &mut &data[..]).await?;
+
+        let mut read = vec![];
+        assert_eq!(
+            object.read(&transaction, &mut read).await?,
+            data.len() as u64
+        );
+        assert_eq!(read, data);
+
+        object.write(&transaction, &mut &b"hello"[..]).await?;
+        let mut read = vec![];
+        object.read(&transaction, &mut read).await?;
+        assert_eq!(read, b"hello");
+
+        object.delete(&transaction).await?;
+
+        Ok(())
+    }
+}
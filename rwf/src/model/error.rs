@@ -31,6 +31,9 @@ pub enum Error {
     #[error("record not found")]
     RecordNotFound,
 
+    #[error("transition from \"{0}\" to \"{1}\" is not allowed")]
+    InvalidTransition(&'static str, &'static str),
+
     #[error("unknown token in template: {0}")]
     UnknownToken(String),
 
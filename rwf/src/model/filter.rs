@@ -27,6 +27,8 @@ enum Comparison {
     GreaterEqualThan((Column, Value)),
     /// x <= 1
     LesserEqualThan((Column, Value)),
+    /// (x::text ILIKE '%a%' OR y::text ILIKE '%a%')
+    Contains((Vec<Column>, Value)),
 }
 
 impl Comparison {
@@ -42,6 +44,7 @@ impl Comparison {
             LesserThan((_, v)) => v.placeholder(),
             GreaterEqualThan((_, v)) => v.placeholder(),
             LesserEqualThan((_, v)) => v.placeholder(),
+            Contains((_, v)) => v.placeholder(),
             _ => false,
         }
     }
@@ -77,6 +80,14 @@ impl ToSql for Comparison {
             LesserEqualThan((column, value)) => {
                 format!("{} <= {}", column.to_sql(), value.to_sql())
             }
+            Contains((columns, value)) => format!(
+                "({})",
+                columns
+                    .iter()
+                    .map(|column| format!("{}::text ILIKE {}", column.to_sql(), value.to_sql()))
+                    .collect::<Vec<_>>()
+                    .join(" OR ")
+            ),
         }
     }
 }
@@ -239,6 +250,12 @@ impl Filter {
             .push(Comparison::LesserEqualThan((column, value.to_value())));
     }
 
+    /// Add a predicate matching if any of the columns contains the pattern, e.g.
+    /// `("email"::text ILIKE $1 OR "name"::text ILIKE $1)`, using the AND operator.
+    pub fn contains(&mut self, columns: Vec<Column>, pattern: Value) {
+        self.clauses.push(Comparison::Contains((columns, pattern)));
+    }
+
     /// Append all predicates of the filter into the current filter.
     pub fn concat(&self, filter: Filter) -> Self {
         // Concatenating filters with different operations, e.g. AND and OR
@@ -0,0 +1,362 @@
+//! Import and export model data as JSON fixtures.
+//!
+//! Records are written one JSON object per line (JSON lines), with all the table columns,
+//! so they can be loaded into another database, e.g. to copy production data into a development
+//! environment, or to attach the records involved in a bug report to the issue.
+//!
+//! When records are loaded into a database which already has data, their primary keys can
+//! be reassigned, and the foreign keys of other models pointing to them remapped to the new ids.
+//! See [`LoadOptions`].
+//!
+//! # Example
+//!
+//! ```ignore
+//! // Export.
+//! let mut file = tokio::fs::File::create("users.jsonl").await?;
+//! User::dump(&mut conn, &mut file).await?;
+//!
+//! // Import, reassigning ids.
+//! let reader = tokio::io::BufReader::new(tokio::fs::File::open("users.jsonl").await?);
+//! let users = User::load_with(&mut conn, reader, &LoadOptions::new().new_ids()).await?;
+//!
+//! let reader = tokio::io::BufReader::new(tokio::fs::File::open("projects.jsonl").await?);
+//! let options = LoadOptions::new().new_ids().remap("user_id", users);
+//! Project::load_with(&mut conn, reader, &options).await?;
+//! ```
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use serde_json::{Map, Value as Json};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::{ConnectionGuard, Error, Model, Query, ToSql};
+
+/// Primary keys of loaded records: the id in the fixture mapped to the id in the database.
+pub type IdMap = HashMap<i64, i64>;
+
+/// How fixtures are loaded.
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    keep_ids: bool,
+    skip_existing: bool,
+    remap: HashMap<String, IdMap>,
+    remap_self: Vec<String>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            keep_ids: true,
+            skip_existing: false,
+            remap: HashMap::new(),
+            remap_self: vec![],
+        }
+    }
+}
+
+impl LoadOptions {
+    /// Load records with their original primary keys. Fails if a record with
+    /// the same primary key already exists, unless [`LoadOptions::skip_existing`] is set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let the database assign new primary keys to the records. The mapping
+    /// between the old and new ids is returned by [`Fixtures::load_with`].
+    pub fn new_ids(mut self) -> Self {
+        self.keep_ids = false;
+        self
+    }
+
+    /// Don't load records which conflict with existing ones, e.g. with the same primary key.
+    pub fn skip_existing(mut self) -> Self {
+        self.skip_existing = true;
+        self
+    }
+
+    /// Replace the values of a foreign key column using ids returned by loading another model.
+    /// Values missing from the map are left unchanged.
+    pub fn remap(mut self, column: &str, ids: IdMap) -> Self {
+        self.remap.insert(column.to_string(), ids);
+        self
+    }
+
+    /// Replace the values of a foreign key column pointing to the same table, e.g. `parent_id`,
+    /// with the ids of records loaded earlier in the same fixture. Parents must be before their children.
+    pub fn remap_self(mut self, column: &str) -> Self {
+        self.remap_self.push(column.to_string());
+        self
+    }
+}
+
+/// Dump and load model records as JSON lines. Implemented for all models.
+#[async_trait]
+pub trait Fixtures: Model {
+    /// Write all records of the model, ordered by primary key. Returns the number of records written.
+    async fn dump<W: AsyncWrite + Unpin + Send>(
+        conn: &mut ConnectionGuard,
+        writer: W,
+    ) -> Result<usize, Error> {
+        let query = Self::all().order((
+            super::Column::new(Self::table_name(), Self::primary_key()),
+            "ASC",
+        ));
+        Self::dump_query(query, conn, writer).await
+    }
+
+    /// Write the records returned by the query, e.g. records matching a filter.
+    async fn dump_query<W: AsyncWrite + Unpin + Send>(
+        query: Query<Self>,
+        conn: &mut ConnectionGuard,
+        mut writer: W,
+    ) -> Result<usize, Error> {
+        let select = match query {
+            Query::Select(ref select) => select,
+            _ => {
+                return Err(Error::QueryError(
+                    "only SELECT queries can be dumped".into(),
+                    query.to_sql(),
+                ))
+            }
+        };
+
+        let sql = format!(
+            r#"SELECT row_to_json("fixture")::TEXT FROM ({}) AS "fixture""#,
+            query.to_sql()
+        );
+        let placeholders = select.placeholders();
+        let rows = conn.query_cached(&sql, &placeholders.values()).await?;
+
+        for row in &rows {
+            let line: String = row.try_get(0)?;
+            writer.write_all(line.as_bytes()).await?;
+            writer.write_all(b"\n").await?;
+        }
+
+        writer.flush().await?;
+
+        Ok(rows.len())
+    }
+
+    /// Load records written by [`Fixtures::dump`], keeping their primary keys.
+    async fn load<R: AsyncBufRead + Unpin + Send>(
+        conn: &mut ConnectionGuard,
+        reader: R,
+    ) -> Result<IdMap, Error> {
+        Self::load_with(conn, reader, &LoadOptions::default()).await
+    }
+
+    /// Load records written by [`Fixtures::dump`]. Returns the primary keys of the records
+    /// in the fixture mapped to the primary keys in the database, which can be used to remap
+    /// foreign keys of other models.
+    ///
+    /// Use a transaction to make sure all records are loaded, or none.
+    async fn load_with<R: AsyncBufRead + Unpin + Send>(
+        conn: &mut ConnectionGuard,
+        reader: R,
+        options: &LoadOptions,
+    ) -> Result<IdMap, Error> {
+        let table = quote(Self::table_name());
+        let primary_key = Self::primary_key();
+        let mut ids = IdMap::new();
+        let mut lines = reader.lines();
+
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let mut record: Map<String, Json> = serde_json::from_str(&line)
+                .map_err(|err| Error::ValueError("fixture", err.to_string()))?;
+
+            let old_id = record.get(primary_key).and_then(|id| id.as_i64());
+
+            if !options.keep_ids {
+                record.remove(primary_key);
+            }
+
+            for (column, map) in &options.remap {
+                remap(&mut record, column, map);
+            }
+
+            for column in &options.remap_self {
+                remap(&mut record, column, &ids);
+            }
+
+            if record.is_empty() {
+                continue;
+            }
+
+            let columns = record
+                .keys()
+                .map(|c| quote(c))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let sql = format!(
+                r#"INSERT INTO {table} ({columns}) SELECT {columns} FROM json_populate_record(NULL::{table}, $1::TEXT::JSON){conflict} RETURNING {primary_key}::BIGINT"#,
+                conflict = if options.skip_existing {
+                    " ON CONFLICT DO NOTHING"
+                } else {
+                    ""
+                },
+                primary_key = quote(primary_key),
+            );
+
+            let json = Json::Object(record).to_string();
+            let rows = conn.query_cached(&sql, &[&json]).await?;
+
+            if let (Some(old_id), Some(row)) = (old_id, rows.first()) {
+                ids.insert(old_id, row.try_get(0)?);
+            }
+        }
+
+        // Records inserted with explicit ids don't advance the sequence.
+        if options.keep_ids && !ids.is_empty() {
+            let sql = format!(
+                r#"SELECT setval(pg_get_serial_sequence($1, $2), MAX({primary_key})) FROM {table}"#,
+                primary_key = quote(primary_key),
+            );
+            conn.query_cached(&sql, &[&table, &primary_key]).await?;
+        }
+
+        Ok(ids)
+    }
+}
+
+impl<T: Model> Fixtures for T {}
+
+fn remap(record: &mut Map<String, Json>, column: &str, ids: &IdMap) {
+    if let Some(value) = record.get_mut(column) {
+        if let Some(id) = value.as_i64().and_then(|id| ids.get(&id)) {
+            *value = Json::from(*id);
+        }
+    }
+}
+
+/// Quote an identifier, e.g. a column name read from a fixture.
+fn quote(identifier: &str) -> String {
+    format!(r#""{}""#, identifier.replace('"', r#""""#))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{FromRow, Pool, ToValue, Value};
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Category {
+        id: Option<i64>,
+        name: String,
+        parent_id: Option<i64>,
+    }
+
+    impl FromRow for Category {
+        fn from_row(row: tokio_postgres::Row) -> Result<Self, Error> {
+            Ok(Self {
+                id: row.try_get("id")?,
+                name: row.try_get("name")?,
+                parent_id: row.try_get("parent_id")?,
+            })
+        }
+    }
+
+    impl Model for Category {
+        fn id(&self) -> Value {
+            self.id.to_value()
+        }
+
+        fn table_name() -> &'static str {
+            "rwf_test_fixture_categories"
+        }
+
+        fn foreign_key() -> &'static str {
+            "category_id"
+        }
+
+        fn column_names() -> &'static [&'static str] {
+            &["name", "parent_id"]
+        }
+
+        fn values(&self) -> Vec<Value> {
+            vec![self.name.to_value(), self.parent_id.to_value()]
+        }
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("name"), r#""name""#);
+        assert_eq!(quote(r#"na"me"#), r#""na""me""#);
+    }
+
+    #[tokio::test]
+    async fn test_dump_load() -> Result<(), Error> {
+        let mut conn = Pool::begin().await?;
+
+        conn.client()
+            .execute(
+                "CREATE TABLE rwf_test_fixture_categories (
+                    id BIGSERIAL PRIMARY KEY,
+                    name VARCHAR NOT NULL,
+                    parent_id BIGINT REFERENCES rwf_test_fixture_categories(id),
+                    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+                )",
+                &[],
+            )
+            .await?;
+
+        let books = Category::create(&[("name", "books")])
+            .fetch(&mut conn)
+            .await?;
+        Category::create(&[
+            ("name", "fiction".to_value()),
+            ("parent_id", books.id.to_value()),
+        ])
+        .execute(&mut conn)
+        .await?;
+
+        let mut fixture = vec![];
+        assert_eq!(Category::dump(&mut conn, &mut fixture).await?, 2);
+
+        let lines = String::from_utf8(fixture.clone()).unwrap();
+        let first: Json = serde_json::from_str(lines.lines().next().unwrap()).unwrap();
+        assert_eq!(first["name"], "books");
+        assert!(first["created_at"].is_string());
+
+        let mut filtered = vec![];
+        let query = Category::filter("name", "fiction");
+        assert_eq!(
+            Category::dump_query(query, &mut conn, &mut filtered).await?,
+            1
+        );
+
+        // Same ids already exist.
+        assert_eq!(
+            Category::load_with(
+                &mut conn,
+                fixture.as_slice(),
+                &LoadOptions::new().skip_existing()
+            )
+            .await?,
+            IdMap::new()
+        );
+
+        let ids = Category::load_with(
+            &mut conn,
+            fixture.as_slice(),
+            &LoadOptions::new().new_ids().remap_self("parent_id"),
+        )
+        .await?;
+        assert_eq!(ids.len(), 2);
+
+        let new_books = ids[&books.id.unwrap()];
+        assert_ne!(new_books, books.id.unwrap());
+
+        let copies = Category::filter("parent_id", new_books)
+            .fetch_all(&mut conn)
+            .await?;
+        assert_eq!(copies.len(), 1);
+        assert_eq!(copies[0].name, "fiction");
+
+        conn.rollback().await
+    }
+}
@@ -104,6 +104,22 @@ pub struct Join {
 }
 
 impl Join {
+    /// Create a join on the given columns, e.g. for associations
+    /// which don't follow the foreign key naming convention.
+    pub fn new(
+        kind: JoinKind,
+        table_name: impl ToString,
+        table_column: Column,
+        foreign_column: Column,
+    ) -> Self {
+        Self {
+            kind,
+            table_name: table_name.to_string(),
+            table_column,
+            foreign_column,
+        }
+    }
+
     fn replace_kind(mut self, kind: JoinKind) -> Self {
         self.kind = kind;
         self
@@ -51,3 +51,11 @@ CREATE INDEX IF NOT EXISTS rwf_requests_path_created_at ON rwf_requests USING bt
 CREATE INDEX IF NOT EXISTS rwf_requests_errors ON rwf_requests USING btree(created_at, code, client_id) WHERE code >= 400;
 
 CREATE INDEX IF NOT EXISTS rwf_requests_too_slow ON rwf_requests USING btree(created_at, duration, client_id) WHERE duration >= 1000.0; -- the unit is milliseconds
+
+ALTER TABLE rwf_requests ADD COLUMN IF NOT EXISTS route VARCHAR;
+
+ALTER TABLE rwf_requests ADD COLUMN IF NOT EXISTS error TEXT;
+
+ALTER TABLE rwf_requests ADD COLUMN IF NOT EXISTS backtrace TEXT;
+
+CREATE INDEX IF NOT EXISTS rwf_requests_route_created_at ON rwf_requests USING btree(created_at, route);
@@ -0,0 +1,53 @@
+//! Tables used by optional features, e.g. tags or payments.
+//!
+//! Only the tables used by the framework itself are created when migrations run. Applications
+//! using a feature add the migration creating its tables with `rwf-cli migrate feature <name>`.
+//! The migrations don't fail if the tables already exist.
+
+/// Optional feature which stores its data in its own tables.
+#[derive(Debug, Clone, Copy)]
+pub struct Feature {
+    /// Name of the feature, e.g. `"tags"`.
+    pub name: &'static str,
+    /// What the tables are used for.
+    pub description: &'static str,
+    /// Migration creating the tables.
+    pub up: &'static str,
+    /// Migration dropping the tables.
+    pub down: &'static str,
+}
+
+macro_rules! feature {
+    ($name:literal, $description:literal) => {
+        Feature {
+            name: $name,
+            description: $description,
+            up: include_str!(concat!("features/", $name, ".up.sql")),
+            down: include_str!(concat!("features/", $name, ".down.sql")),
+        }
+    };
+}
+
+/// All optional features with tables.
+pub const FEATURES: &[Feature] = &[
+    feature!("outbox", "Transactional outbox for the message broker"),
+    feature!("tags", "Tags attached to records of Taggable models"),
+    feature!(
+        "state_transitions",
+        "Transitions recorded by state machines"
+    ),
+    feature!("data_requests", "GDPR data export and erasure requests"),
+    feature!("dashboard", "Layout of the admin dashboard widgets"),
+    feature!("payments", "Stripe customers and subscriptions"),
+    feature!("rate_limits", "Counters of the Postgres rate limiter store"),
+    feature!("nonces", "Nonces of the Postgres replay protection store"),
+    feature!("sessions", "Sessions of the Postgres session store"),
+    feature!("admin", "Admin panel audit log and SQL console history"),
+    feature!("usage", "Usage metering events and hourly rollups"),
+    feature!("slugs", "Previous slugs of Sluggable models"),
+];
+
+/// Get the feature by name.
+pub fn feature(name: &str) -> Option<&'static Feature> {
+    FEATURES.iter().find(|feature| feature.name == name)
+}
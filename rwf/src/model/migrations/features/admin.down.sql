@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS rwf_admin_console_queries;
+DROP TABLE IF EXISTS rwf_admin_audit_log;
@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS rwf_admin_audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT,
+    actor VARCHAR NOT NULL,
+    action VARCHAR NOT NULL,
+    method VARCHAR NOT NULL,
+    path VARCHAR NOT NULL,
+    ip VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS rwf_admin_audit_log_created_at_idx ON rwf_admin_audit_log USING btree(created_at);
+
+CREATE TABLE IF NOT EXISTS rwf_admin_console_queries (
+    id BIGSERIAL PRIMARY KEY,
+    actor VARCHAR NOT NULL,
+    query TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS rwf_admin_console_queries_actor_idx ON rwf_admin_console_queries USING btree(actor, id);
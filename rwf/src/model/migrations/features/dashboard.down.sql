@@ -0,0 +1 @@
+DROP TABLE IF EXISTS rwf_dashboard_widgets;
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS rwf_dashboard_widgets (
+    id BIGSERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL UNIQUE,
+    position INTEGER NOT NULL DEFAULT 0,
+    width INTEGER NOT NULL DEFAULT 4,
+    hidden BOOLEAN NOT NULL DEFAULT false
+);
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS rwf_data_requests (
+    id BIGSERIAL PRIMARY KEY,
+    action VARCHAR NOT NULL,
+    subject VARCHAR NOT NULL,
+    subject_id BIGINT NOT NULL,
+    status VARCHAR NOT NULL,
+    summary JSONB NOT NULL DEFAULT '{}'::jsonb,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS rwf_data_requests_subject_idx ON rwf_data_requests USING btree(subject, subject_id);
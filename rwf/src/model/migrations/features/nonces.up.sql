@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS rwf_nonces (
+    nonce VARCHAR PRIMARY KEY,
+    expires_at BIGINT NOT NULL
+);
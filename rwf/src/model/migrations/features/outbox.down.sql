@@ -0,0 +1 @@
+DROP TABLE IF EXISTS rwf_outbox;
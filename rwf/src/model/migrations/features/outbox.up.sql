@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS rwf_outbox (
+    id BIGSERIAL PRIMARY KEY,
+    topic VARCHAR NOT NULL,
+    key VARCHAR,
+    payload JSONB NOT NULL DEFAULT '{}'::jsonb,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    published_at TIMESTAMPTZ,
+    attempts INT NOT NULL DEFAULT 0,
+    error VARCHAR
+);
+
+-- Events waiting to be published
+CREATE INDEX IF NOT EXISTS rwf_outbox_pending_idx ON rwf_outbox USING btree(id) WHERE published_at IS NULL;
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS rwf_subscriptions;
+DROP TABLE IF EXISTS rwf_customers;
@@ -0,0 +1,24 @@
+CREATE TABLE IF NOT EXISTS rwf_customers (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT,
+    stripe_id VARCHAR NOT NULL UNIQUE,
+    email VARCHAR,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS rwf_customers_user_id_idx ON rwf_customers USING btree(user_id);
+
+CREATE TABLE IF NOT EXISTS rwf_subscriptions (
+    id BIGSERIAL PRIMARY KEY,
+    customer_id BIGINT NOT NULL REFERENCES rwf_customers(id) ON DELETE CASCADE,
+    stripe_id VARCHAR NOT NULL UNIQUE,
+    status VARCHAR NOT NULL,
+    price_id VARCHAR,
+    quantity BIGINT NOT NULL DEFAULT 1,
+    current_period_end TIMESTAMPTZ,
+    cancel_at_period_end BOOLEAN NOT NULL DEFAULT false,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS rwf_subscriptions_customer_id_idx ON rwf_subscriptions USING btree(customer_id);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS rwf_rate_limits (
+    key VARCHAR PRIMARY KEY,
+    value DOUBLE PRECISION NOT NULL,
+    previous DOUBLE PRECISION NOT NULL,
+    started_at DOUBLE PRECISION NOT NULL
+);
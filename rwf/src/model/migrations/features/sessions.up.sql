@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS rwf_sessions (
+    key VARCHAR PRIMARY KEY,
+    user_id BIGINT,
+    session JSONB NOT NULL,
+    expires_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS rwf_sessions_user_id_idx ON rwf_sessions USING btree(user_id);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS rwf_slugs (
+    id BIGSERIAL PRIMARY KEY,
+    sluggable_type VARCHAR NOT NULL,
+    sluggable_id BIGINT NOT NULL,
+    slug VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (sluggable_type, slug)
+);
+
+CREATE INDEX IF NOT EXISTS rwf_slugs_sluggable_idx ON rwf_slugs USING btree(sluggable_type, sluggable_id);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS rwf_state_transitions (
+    id BIGSERIAL PRIMARY KEY,
+    model VARCHAR NOT NULL,
+    record_id BIGINT NOT NULL,
+    from_state VARCHAR NOT NULL,
+    to_state VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS rwf_state_transitions_record_idx ON rwf_state_transitions USING btree(model, record_id);
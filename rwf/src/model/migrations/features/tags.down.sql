@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS rwf_taggings;
+DROP TABLE IF EXISTS rwf_tags;
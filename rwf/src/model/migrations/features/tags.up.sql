@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS rwf_tags (
+    id BIGSERIAL PRIMARY KEY,
+    name VARCHAR UNIQUE NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS rwf_taggings (
+    id BIGSERIAL PRIMARY KEY,
+    tag_id BIGINT NOT NULL REFERENCES rwf_tags(id) ON DELETE CASCADE,
+    taggable_type VARCHAR NOT NULL,
+    taggable_id BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (taggable_type, taggable_id, tag_id)
+);
+
+CREATE INDEX IF NOT EXISTS rwf_taggings_tag_id_idx ON rwf_taggings USING btree(tag_id, taggable_type);
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS rwf_usage_rollups;
+DROP TABLE IF EXISTS rwf_usage_events;
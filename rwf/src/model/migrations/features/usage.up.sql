@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS rwf_usage_events (
+    id BIGSERIAL PRIMARY KEY,
+    account VARCHAR NOT NULL,
+    metric VARCHAR NOT NULL,
+    quantity BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS rwf_usage_events_account_idx ON rwf_usage_events USING btree(account, metric, created_at);
+
+CREATE TABLE IF NOT EXISTS rwf_usage_rollups (
+    id BIGSERIAL PRIMARY KEY,
+    account VARCHAR NOT NULL,
+    metric VARCHAR NOT NULL,
+    period_start TIMESTAMPTZ NOT NULL,
+    quantity BIGINT NOT NULL,
+    UNIQUE (account, metric, period_start)
+);
@@ -1,4 +1,5 @@
 //! Implements database migrations, a deterministic mechanism to change the database schema.
+pub mod features;
 pub mod model;
 use crate::config::get_config;
 use crate::model::{get_connection, get_pool, start_transaction, Model};
@@ -299,6 +300,67 @@ impl Migrations {
         &self.migrations
     }
 
+    /// Migrations in the `"migrations"` folder which haven't been applied
+    /// to the database yet, e.g. because the app was deployed without running them.
+    pub async fn pending() -> Result<Vec<String>, Error> {
+        let path = current_dir()?.join("migrations");
+
+        if !path.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut files = vec![];
+        let mut dir_entries = read_dir(path).await?;
+        while let Some(dir_entry) = dir_entries.next_entry().await? {
+            let file_name = dir_entry.file_name().to_string_lossy().to_string();
+
+            if file_name.starts_with(".") || !RE.is_match(&file_name) {
+                continue;
+            }
+
+            let file = MigrationFile::parse(&file_name)?;
+            if file.direction == Direction::Up {
+                files.push(file);
+            }
+        }
+
+        if files.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let applied = Self::load()
+            .await?
+            .migrations
+            .into_iter()
+            .filter(|migration| migration.applied_at.is_some())
+            .map(|migration| (migration.version, migration.name))
+            .collect::<Vec<_>>();
+
+        files.sort_by_key(|file| file.version);
+
+        Ok(files
+            .into_iter()
+            .filter(|file| !applied.contains(&(file.version as i64, file.name.clone())))
+            .map(|file| format!("{}_{}", file.version, file.name))
+            .collect())
+    }
+
+    /// The latest migration applied to the database, e.g. `"12_add_users"`, or `None`
+    /// if no migrations were applied or the app doesn't have a `"migrations"` folder.
+    pub async fn version() -> Result<Option<String>, Error> {
+        if !current_dir()?.join("migrations").is_dir() {
+            return Ok(None);
+        }
+
+        Ok(Self::load()
+            .await?
+            .migrations
+            .into_iter()
+            .filter(|migration| migration.applied_at.is_some())
+            .max_by_key(|migration| migration.version)
+            .map(|migration| format!("{}_{}", migration.version, migration.name)))
+    }
+
     /// Execute all migrations in the up direction.
     pub async fn migrate() -> Result<Migrations, Error> {
         Migrations::sync().await?.apply(Direction::Up, None).await
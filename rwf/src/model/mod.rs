@@ -3,12 +3,16 @@
 //! See [documentation](https://levkk.github.io/rwf/models/) for detailed examples on how to use the ORM.
 use crate::colors::MaybeColorize;
 use crate::config::get_config;
+use crate::events::{self, Event, QueryExecuted};
 use crate::model::column::ToAggregation;
+use crate::telemetry;
 
 use pool::ToConnectionRequest;
 use std::time::{Duration, Instant};
-use tracing::{error, info};
+use tracing::{error, info, Instrument};
 
+pub mod anonymize;
+pub mod blob;
 pub mod callbacks;
 pub mod column;
 pub mod error;
@@ -16,6 +20,7 @@ pub mod escape;
 pub mod exists;
 pub mod explain;
 pub mod filter;
+pub mod fixtures;
 pub mod insert;
 pub mod join;
 pub mod limit;
@@ -26,17 +31,26 @@ pub mod picked;
 pub mod placeholders;
 pub mod pool;
 pub mod prelude;
+pub mod publishable;
+pub mod recorded;
 pub mod row;
 pub mod select;
+pub mod sluggable;
+pub mod state_machine;
+pub mod taggable;
+pub mod tree;
 pub mod update;
 pub mod value;
 
+pub use anonymize::{Anonymize, Anonymizer};
+pub use blob::{Bytea, LargeObject};
 pub use column::{Column, Columns, ToColumn};
 pub use error::Error;
 pub use escape::Escape;
 pub use exists::Exists;
 pub use explain::Explain;
 pub use filter::{Filter, WhereClause};
+pub use fixtures::Fixtures;
 pub use insert::Insert;
 pub use join::{Association, AssociationType, Join, Joined, Joins};
 pub use limit::Limit;
@@ -46,8 +60,14 @@ pub use order_by::{OrderBy, OrderColumn, ToOrderBy};
 pub use picked::Picked;
 pub use placeholders::Placeholders;
 pub use pool::{get_connection, get_pool, start_transaction, Connection, ConnectionGuard, Pool};
+pub use publishable::Publishable;
+pub use recorded::Recorded;
 pub use row::Row;
 pub use select::Select;
+pub use sluggable::Sluggable;
+pub use state_machine::{StateMachine, Stateful};
+pub use taggable::{Tag, Taggable};
+pub use tree::Tree;
 pub use update::Update;
 pub use value::{ToValue, Value};
 
@@ -427,6 +447,16 @@ impl<T: Model> Query<T> {
         }
     }
 
+    /// Find records where any of the columns contains the text, ignoring case,
+    /// e.g. to search records in the admin panel.
+    pub fn filter_contains(self, columns: &[impl ToColumn], text: &str) -> Self {
+        use Query::*;
+        match self {
+            Select(select) => Select(select.filter_contains(columns, text)),
+            _ => self,
+        }
+    }
+
     pub fn or(self, f: fn(Self) -> Self) -> Self {
         use Query::*;
         match self {
@@ -683,6 +713,33 @@ impl<T: Model> Query<T> {
     async fn execute_internal(
         &self,
         client: impl ToConnectionRequest<'_>,
+    ) -> Result<Vec<tokio_postgres::Row>, Error> {
+        let (model, action) = (Self::type_name(), self.action());
+        let span = telemetry::query_span(&model, action, || self.to_sql());
+        let start = Instant::now();
+
+        let result = self.run(client).instrument(span).await;
+        let duration = start.elapsed();
+
+        telemetry::record_query(&model, action, duration);
+
+        if events::listening() {
+            events::emit(Event::QueryExecuted(QueryExecuted {
+                model: &model,
+                action,
+                query: &self.to_sql(),
+                duration,
+                rows: result.as_ref().map(|rows| rows.len()).unwrap_or(0),
+                error: result.as_ref().err(),
+            }));
+        }
+
+        result
+    }
+
+    async fn run(
+        &self,
+        client: impl ToConnectionRequest<'_>,
     ) -> Result<Vec<tokio_postgres::Row>, Error> {
         let request = client.to_connection_request()?;
         let mut conn = request.get().await?;
@@ -1126,6 +1183,23 @@ pub trait Model: FromRow {
         "id"
     }
 
+    /// Who can read and write the fields of this model, e.g. in API responses.
+    /// Fields without rules can be read and written by everyone. See [`crate::auth::fields`].
+    ///
+    /// Set with the `readable` and `writable` attributes when the model is derived:
+    ///
+    /// ```ignore
+    /// #[derive(Clone, macros::Model)]
+    /// struct Employee {
+    ///     id: Option<i64>,
+    ///     #[readable(roles("admin"))]
+    ///     salary: i64,
+    /// }
+    /// ```
+    fn field_rules() -> &'static [crate::auth::fields::FieldRule] {
+        &[]
+    }
+
     /// Select one record from the table. The row returned is determined by the database.
     ///
     /// # Example
@@ -1637,11 +1711,25 @@ pub trait Model: FromRow {
 }
 
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
     use super::join::AssociationType;
     use super::*;
     use tokio_postgres::row::Row;
 
+    /// Create the tables used by the framework and its optional features, for tests of features
+    /// which store their data in the database.
+    pub(crate) async fn bootstrap(client: &tokio_postgres::Client) -> Result<(), Error> {
+        client
+            .batch_execute(include_str!("migrations/bootstrap.sql"))
+            .await?;
+
+        for feature in migrations::features::FEATURES {
+            client.batch_execute(feature.up).await?;
+        }
+
+        Ok(())
+    }
+
     #[derive(Debug, Clone, Default)]
     struct User {
         id: i64,
@@ -1939,6 +2027,26 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_filter_contains() {
+        let query = User::filter("id", 5).filter_contains(&["email", "name"], "50%_off");
+
+        assert_eq!(
+            query.to_sql(),
+            r#"SELECT * FROM "users" WHERE "users"."id" = $1 AND ("users"."email"::text ILIKE $2 OR "users"."name"::text ILIKE $2)"#
+        );
+
+        match query {
+            Query::Select(select) => {
+                assert_eq!(
+                    select.placeholders().get(2),
+                    Some(&Value::String("%50\\%\\_off%".into()))
+                );
+            }
+            _ => panic!("not a select"),
+        }
+    }
+
     #[test]
     fn test_find_by() {
         let query = User::find_by("email", "test@test.com");
@@ -120,9 +120,10 @@ impl Connection {
         }
     }
 
-    /// Is the connection broken?
+    /// Is the connection broken? Connections closed by the server, or whose
+    /// runtime was shut down, are broken too.
     pub fn bad(&self) -> bool {
-        self.inner.bad.load(Ordering::Relaxed)
+        self.inner.bad.load(Ordering::Relaxed) || self.client.is_closed()
     }
 
     /// Forcibly close the connection once it's returned to the pool.
@@ -140,6 +141,11 @@ impl Connection {
         self.last_used
     }
 
+    /// Handle to close the connection from another task.
+    pub(crate) fn terminator(&self) -> Terminator {
+        Terminator(self.inner.clone())
+    }
+
     /// Get the database driver reference to manually execute
     /// queries against the database, bypassing the connection manager.
     pub fn client(&self) -> &Client {
@@ -151,6 +157,19 @@ impl Connection {
     }
 }
 
+/// Closes a connection used by another task, e.g. to abort its transaction.
+#[derive(Debug, Clone)]
+pub(crate) struct Terminator(Arc<ConnectionInner>);
+
+impl Terminator {
+    /// Close the connection. Postgres rolls back its open transaction, and
+    /// queries executed on the connection afterwards return an error.
+    pub(crate) fn terminate(&self) {
+        self.0.bad.store(true, Ordering::Relaxed);
+        self.0.shutdown.notify_one();
+    }
+}
+
 impl Drop for Connection {
     fn drop(&mut self) {
         self.shutdown();
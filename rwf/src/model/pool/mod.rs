@@ -11,6 +11,10 @@
 //!
 //! This implementation uses FIFO to increase connection re-use.
 //!
+//! Transactions held open for too long, e.g. because the code forgot to commit them while doing slow work,
+//! are logged with the request or job that started them, and can be aborted automatically, see
+//! [`PoolConfig::transaction_warning`] and [`PoolConfig::transaction_timeout`].
+//!
 //! ## Get a connection
 //!
 //! ```ignore
@@ -31,7 +35,7 @@ use tokio::time::{sleep, timeout, Duration};
 
 use parking_lot::Mutex;
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::future::Future;
 use std::ops::{Deref, DerefMut};
 use std::sync::{
@@ -43,6 +47,7 @@ use std::time::Instant;
 use once_cell::sync::OnceCell;
 
 use crate::config::get_config;
+use crate::controller::middleware::chaos;
 
 pub mod connection;
 pub mod transaction;
@@ -52,6 +57,8 @@ use super::Error;
 pub use connection::Connection;
 pub use transaction::Transaction;
 
+use transaction::OpenTransaction;
+
 static POOL: OnceCell<Pool> = OnceCell::new();
 
 /// Get the connection pool.
@@ -227,6 +234,10 @@ struct PoolInner {
     /// Number of connections the pool has idle
     /// and checked out by users.
     expected: usize,
+
+    /// Transactions currently open, by ID.
+    transactions: HashMap<usize, OpenTransaction>,
+    next_transaction: usize,
 }
 
 /// Connection pool configuration options.
@@ -241,6 +252,12 @@ pub struct PoolConfig {
 
     /// Maximum time a connection remains open and available while not in use.
     pub idle_timeout: Duration,
+
+    /// Log a warning when a transaction is open for longer than this.
+    pub transaction_warning: Option<Duration>,
+
+    /// Abort transactions open for longer than this, by closing their connection.
+    pub transaction_timeout: Option<Duration>,
 }
 
 impl Default for PoolConfig {
@@ -249,6 +266,8 @@ impl Default for PoolConfig {
             pool_size: 10,
             checkout_timeout: Duration::from_secs(5),
             idle_timeout: Duration::from_secs(3600),
+            transaction_warning: Some(Duration::from_secs(30)),
+            transaction_timeout: None,
         }
     }
 }
@@ -294,6 +313,8 @@ impl Pool {
             inner: Arc::new(Mutex::new(PoolInner {
                 connections: VecDeque::new(),
                 expected: 0,
+                transactions: HashMap::new(),
+                next_transaction: 0,
             })),
             checkin_notify: Arc::new(Notify::new()),
             database_url: database_url.to_string(),
@@ -337,13 +358,20 @@ impl Pool {
                 pool_size: config.pool_size,
                 idle_timeout: config.idle_timeout().unsigned_abs(),
                 checkout_timeout: config.checkout_timeout().unsigned_abs(),
+                transaction_warning: config.transaction_warning().map(|d| d.unsigned_abs()),
+                transaction_timeout: config.transaction_timeout().map(|d| d.unsigned_abs()),
             },
         )
     }
 
     /// Get a connection from the pool or wait until one is available.
     pub async fn get(&self) -> Result<ConnectionGuard, Error> {
-        match timeout(self.config.checkout_timeout, self.get_internal()).await {
+        let checkout = async {
+            chaos::checkout().await?;
+            self.get_internal().await
+        };
+
+        match timeout(self.config.checkout_timeout, checkout).await {
             Ok(result) => result,
             Err(_) => {
                 // self.inner.lock().expected -= 1;
@@ -480,6 +508,24 @@ impl Pool {
         });
         let removed = before - inner.connections.len();
         inner.expected -= removed;
+
+        inner
+            .transactions
+            .retain(|_, transaction| transaction.check(now, &self.config));
+    }
+
+    /// Start watching a transaction which was just opened.
+    fn track_transaction(&self, transaction: OpenTransaction) -> usize {
+        let mut inner = self.inner.lock();
+        let id = inner.next_transaction;
+        inner.next_transaction += 1;
+        inner.transactions.insert(id, transaction);
+        id
+    }
+
+    /// The transaction was committed or rolled back.
+    fn untrack_transaction(&self, id: usize) {
+        self.inner.lock().transactions.remove(&id);
     }
 
     async fn checkin_rollback(&self, mut connection: Connection) {
@@ -581,4 +627,29 @@ mod test {
         let _conn = pool.get().await.unwrap();
         assert_eq!(pool.inner.lock().expected, 2);
     }
+
+    #[tokio::test]
+    async fn test_transaction_timeout() -> Result<(), Error> {
+        let pool = Pool::new(
+            &get_config().database.database_url(),
+            PoolConfig {
+                transaction_timeout: Some(Duration::from_millis(100)),
+                ..Default::default()
+            },
+        );
+
+        let committed = pool.transaction().await?;
+        assert_eq!(pool.inner.lock().transactions.len(), 1);
+        committed.commit().await?;
+        assert!(pool.inner.lock().transactions.is_empty());
+
+        let forgotten = pool.transaction().await?;
+        sleep(Duration::from_millis(1500)).await;
+
+        assert!(pool.inner.lock().transactions.is_empty());
+        assert!(forgotten.bad());
+        assert!(forgotten.commit().await.is_err());
+
+        Ok(())
+    }
 }
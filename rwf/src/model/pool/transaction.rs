@@ -1,14 +1,27 @@
 //! Manages a transaction lifecycle.
-use super::{ConnectionGuard, Error};
+//!
+//! Transactions can be nested with [`Transaction::nested`], which uses savepoints, so code
+//! handed a transaction can run a group of statements atomically without committing the caller's work.
+//! Savepoints only exist inside a transaction: code that isn't handed one starts it with
+//! [`Pool::begin`](super::Pool::begin) instead.
+//!
+//! Open transactions are watched by the pool. Transactions held for longer than the configured
+//! `transaction_warning` are logged with the request or job that started them, and, if
+//! `transaction_timeout` is set, aborted by closing their connection.
+use super::{connection::Terminator, ConnectionGuard, Error, PoolConfig};
 use crate::config::get_config;
 
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::ops::AsyncFnOnce;
 use std::time::Instant;
-use tracing::info;
+use tracing::{error, info, warn, Span};
 
 /// Explicit PostgreSQL transaction.
 pub struct Transaction {
     connection: ConnectionGuard,
     rollback: bool,
+    savepoints: usize,
+    id: usize,
 }
 
 impl Transaction {
@@ -23,12 +36,98 @@ impl Transaction {
             info!("BEGIN ({:.3} ms)", start.elapsed().as_secs_f64() * 1000.0);
         }
 
+        let id = connection
+            .pool
+            .track_transaction(OpenTransaction::new(&connection));
+
         Ok(Self {
             connection,
             rollback: true,
+            savepoints: 0,
+            id,
         })
     }
 
+    /// Run the closure in a nested transaction, using a savepoint.
+    ///
+    /// If the closure returns an error, all statements it executed are rolled back and
+    /// the error is returned; the outer transaction can continue. Otherwise, its changes
+    /// become part of the outer transaction, and are committed with it. Nested transactions
+    /// can be nested too.
+    ///
+    /// There is no savepoint without a transaction, so top-level code starts one with
+    /// [`Pool::begin`](super::Pool::begin) and calls `nested` on it, or just commits it.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let mut transaction = Pool::begin().await?;
+    ///
+    /// let result = transaction
+    ///     .nested(async |transaction| {
+    ///         User::create(&[("email", "jane@example.com")])
+    ///             .fetch(transaction)
+    ///             .await
+    ///     })
+    ///     .await;
+    ///
+    /// // The user wasn't created if it already exists, but
+    /// // the transaction can still be committed.
+    /// transaction.commit().await?;
+    /// ```
+    pub async fn nested<R, E>(
+        &mut self,
+        f: impl AsyncFnOnce(&mut Transaction) -> Result<R, E>,
+    ) -> Result<R, E>
+    where
+        E: From<Error>,
+    {
+        self.savepoints += 1;
+        let savepoint = format!("rwf_savepoint_{}", self.savepoints);
+
+        let result = match self.savepoint("SAVEPOINT", &savepoint).await {
+            Ok(()) => f(self).await,
+            Err(err) => Err(err.into()),
+        };
+
+        // Rolling back to a savepoint keeps it, so it's released in both cases.
+        let end = match result {
+            Ok(_) => self.savepoint("RELEASE SAVEPOINT", &savepoint).await,
+            Err(_) => match self.savepoint("ROLLBACK TO SAVEPOINT", &savepoint).await {
+                Ok(()) => self.savepoint("RELEASE SAVEPOINT", &savepoint).await,
+                Err(err) => Err(err),
+            },
+        };
+
+        self.savepoints -= 1;
+
+        match (result, end) {
+            (Ok(_), Err(err)) => Err(err.into()),
+            (result, _) => result,
+        }
+    }
+
+    /// Number of nested transactions currently open.
+    pub fn depth(&self) -> usize {
+        self.savepoints
+    }
+
+    async fn savepoint(&self, command: &str, savepoint: &str) -> Result<(), Error> {
+        let start = Instant::now();
+        let query = format!("{} {}", command, savepoint);
+        self.connection.client().batch_execute(&query).await?;
+
+        if get_config().general.log_queries {
+            info!(
+                "{} ({:.3} ms)",
+                query,
+                start.elapsed().as_secs_f64() * 1000.0
+            );
+        }
+
+        Ok(())
+    }
+
     /// Commit the transaction to the database.
     /// The connection is automatically returned into the pool.
     pub async fn commit(mut self) -> Result<(), Error> {
@@ -67,12 +166,84 @@ impl Drop for Transaction {
     /// Rollback the transaction and return the connection
     /// to the pool.
     fn drop(&mut self) {
+        self.connection.pool.untrack_transaction(self.id);
+
         if self.rollback {
             self.connection.rollback();
         }
     }
 }
 
+/// Transaction watched by the pool.
+#[derive(Debug)]
+pub(super) struct OpenTransaction {
+    started_at: Instant,
+    // Span of the request or job which started the transaction.
+    span: Span,
+    // Captured only if enabled with `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE`.
+    backtrace: Backtrace,
+    terminator: Terminator,
+    warned: bool,
+}
+
+impl OpenTransaction {
+    fn new(connection: &ConnectionGuard) -> Self {
+        Self {
+            started_at: Instant::now(),
+            span: Span::current(),
+            backtrace: Backtrace::capture(),
+            terminator: connection.terminator(),
+            warned: false,
+        }
+    }
+
+    /// Warn about the transaction if it's been open for too long, or abort it.
+    /// Returns `false` if the transaction was aborted and shouldn't be watched anymore.
+    pub(super) fn check(&mut self, now: Instant, config: &PoolConfig) -> bool {
+        let open = now.duration_since(self.started_at);
+
+        if config
+            .transaction_timeout
+            .is_some_and(|timeout| open > timeout)
+        {
+            self.span.in_scope(|| {
+                error!(
+                    "transaction open for {:.3}s, aborting it{}",
+                    open.as_secs_f64(),
+                    self.started()
+                )
+            });
+            self.terminator.terminate();
+            return false;
+        }
+
+        if !self.warned
+            && config
+                .transaction_warning
+                .is_some_and(|warning| open > warning)
+        {
+            self.warned = true;
+            self.span.in_scope(|| {
+                warn!(
+                    "transaction open for {:.3}s, did you forget to commit it?{}",
+                    open.as_secs_f64(),
+                    self.started()
+                )
+            });
+        }
+
+        true
+    }
+
+    /// Where the transaction was started, if known.
+    fn started(&self) -> String {
+        match self.backtrace.status() {
+            BacktraceStatus::Captured => format!("\nstarted at:\n{}", self.backtrace),
+            _ => String::new(),
+        }
+    }
+}
+
 impl std::ops::Deref for Transaction {
     type Target = ConnectionGuard;
 
@@ -86,3 +257,62 @@ impl std::ops::DerefMut for Transaction {
         &mut self.connection
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::Pool;
+
+    async fn count(transaction: &Transaction) -> Result<i64, Error> {
+        let row = transaction
+            .client()
+            .query_one("SELECT COUNT(*) FROM nested_test", &[])
+            .await?;
+        Ok(row.get(0))
+    }
+
+    #[tokio::test]
+    async fn test_nested() -> Result<(), Error> {
+        let mut transaction = Pool::begin().await?;
+        transaction
+            .client()
+            .batch_execute("CREATE TEMPORARY TABLE nested_test (id BIGINT PRIMARY KEY)")
+            .await?;
+
+        let inserted = transaction
+            .nested(async |transaction| {
+                transaction
+                    .client()
+                    .execute("INSERT INTO nested_test VALUES (1)", &[])
+                    .await?;
+                assert_eq!(transaction.depth(), 1);
+
+                // Duplicate key, rolled back without aborting the outer transactions.
+                let failed = transaction
+                    .nested(async |transaction| {
+                        transaction
+                            .client()
+                            .execute("INSERT INTO nested_test VALUES (2)", &[])
+                            .await?;
+                        transaction
+                            .client()
+                            .execute("INSERT INTO nested_test VALUES (1)", &[])
+                            .await?;
+                        Ok::<_, Error>(())
+                    })
+                    .await;
+                assert!(failed.is_err());
+
+                count(transaction).await
+            })
+            .await?;
+
+        assert_eq!(inserted, 1);
+        assert_eq!(transaction.depth(), 0);
+        assert_eq!(count(&transaction).await?, 1);
+
+        transaction.rollback().await?;
+
+        Ok(())
+    }
+}
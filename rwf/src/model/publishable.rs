@@ -0,0 +1,243 @@
+//! Draft and publish workflow.
+//!
+//! Models with a nullable `published_at` column can be drafts (not published), scheduled
+//! (published in the future) or published. Implement [`Publishable`], or derive it with `macros::Publishable`,
+//! and use [`Publishable::published`] instead of [`Model::all`] in public-facing controllers.
+//!
+//! Drafts can be shared before they are published using preview tokens.
+//!
+//! # Example
+//!
+//! ```ignore
+//! #[derive(Clone, macros::Model, macros::Publishable)]
+//! struct Post {
+//!     id: Option<i64>,
+//!     title: String,
+//!     published_at: Option<OffsetDateTime>,
+//! }
+//!
+//! let posts = Post::published().fetch_all(&mut conn).await?;
+//! let post = post.publish().fetch(&mut conn).await?;
+//! ```
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+use super::{Error, Model, Scope, Value};
+use crate::crypto::{decrypt, encrypt};
+
+#[derive(Serialize, Deserialize)]
+struct PreviewToken {
+    #[serde(rename = "t")]
+    table_name: String,
+    #[serde(rename = "i")]
+    id: i64,
+    #[serde(rename = "e")]
+    expiration: i64,
+}
+
+/// Model that can be a draft or published.
+pub trait Publishable: Model {
+    /// When the record was (or will be) published. `None` if it's a draft.
+    fn published_at(&self) -> Option<OffsetDateTime>;
+
+    /// Set when the record is published.
+    fn set_published_at(&mut self, published_at: Option<OffsetDateTime>);
+
+    /// Name of the column storing the publication time.
+    fn published_at_column() -> &'static str {
+        "published_at"
+    }
+
+    /// The record is published and visible to everyone.
+    fn is_published(&self) -> bool {
+        match self.published_at() {
+            Some(published_at) => published_at <= OffsetDateTime::now_utc(),
+            None => false,
+        }
+    }
+
+    /// The record is a draft and hasn't been scheduled for publishing.
+    fn is_draft(&self) -> bool {
+        self.published_at().is_none()
+    }
+
+    /// Records that are published.
+    fn published() -> Scope<Self> {
+        Self::all().filter_lte(Self::published_at_column(), OffsetDateTime::now_utc())
+    }
+
+    /// Records that are drafts.
+    fn drafts() -> Scope<Self> {
+        Self::filter(Self::published_at_column(), Value::Null)
+    }
+
+    /// Records that will be published in the future.
+    fn scheduled() -> Scope<Self> {
+        Self::all().filter_gt(Self::published_at_column(), OffsetDateTime::now_utc())
+    }
+
+    /// Publish the record now.
+    fn publish(self) -> Scope<Self> {
+        self.publish_at(OffsetDateTime::now_utc())
+    }
+
+    /// Publish the record at a specific time, e.g. in the future.
+    fn publish_at(mut self, published_at: OffsetDateTime) -> Scope<Self> {
+        self.set_published_at(Some(published_at));
+        self.save()
+    }
+
+    /// Turn the record back into a draft.
+    fn unpublish(mut self) -> Scope<Self> {
+        self.set_published_at(None);
+        self.save()
+    }
+
+    /// Create a token that allows to view the record before it's published.
+    ///
+    /// The token is encrypted with the application secret key and expires after the given time.
+    /// Only models with an integer primary key are supported.
+    fn preview_token(&self, expires_in: Duration) -> Result<String, Error> {
+        let id = match self.id() {
+            Value::Integer(id) | Value::BigInt(id) => id,
+            Value::Optional(id) => match *id {
+                Some(Value::Integer(id)) | Some(Value::BigInt(id)) => id,
+                _ => return Err(Error::RecordNotFound),
+            },
+            _ => return Err(Error::RecordNotFound),
+        };
+
+        let token = PreviewToken {
+            table_name: Self::table_name().to_string(),
+            id,
+            expiration: (OffsetDateTime::now_utc() + expires_in).unix_timestamp(),
+        };
+
+        encrypt(&serde_json::to_vec(&token).map_err(|err| Error::Unknown(err.to_string()))?)
+            .map_err(|err| Error::Unknown(err.to_string()))
+    }
+
+    /// Find the record using a preview token, whether it's published or not.
+    ///
+    /// Returns [`Error::RecordNotFound`] if the token is invalid, expired or was created
+    /// for a different model.
+    fn preview(token: &str) -> Result<Scope<Self>, Error> {
+        let token = decrypt(token)
+            .ok()
+            .and_then(|token| serde_json::from_slice::<PreviewToken>(&token).ok())
+            .ok_or(Error::RecordNotFound)?;
+
+        if token.table_name != Self::table_name()
+            || token.expiration < OffsetDateTime::now_utc().unix_timestamp()
+        {
+            return Err(Error::RecordNotFound);
+        }
+
+        Ok(Self::find(token.id))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{FromRow, ToSql, ToValue};
+
+    #[derive(Clone, Debug)]
+    struct Post {
+        id: Option<i64>,
+        title: String,
+        published_at: Option<OffsetDateTime>,
+    }
+
+    impl FromRow for Post {
+        fn from_row(row: tokio_postgres::Row) -> Result<Self, Error> {
+            Ok(Self {
+                id: row.try_get("id")?,
+                title: row.try_get("title")?,
+                published_at: row.try_get("published_at")?,
+            })
+        }
+    }
+
+    impl Model for Post {
+        fn id(&self) -> Value {
+            self.id.to_value()
+        }
+
+        fn table_name() -> &'static str {
+            "posts"
+        }
+
+        fn foreign_key() -> &'static str {
+            "post_id"
+        }
+
+        fn column_names() -> &'static [&'static str] {
+            &["title", "published_at"]
+        }
+
+        fn values(&self) -> Vec<Value> {
+            vec![self.title.to_value(), self.published_at.to_value()]
+        }
+    }
+
+    impl Publishable for Post {
+        fn published_at(&self) -> Option<OffsetDateTime> {
+            self.published_at
+        }
+
+        fn set_published_at(&mut self, published_at: Option<OffsetDateTime>) {
+            self.published_at = published_at;
+        }
+    }
+
+    fn post() -> Post {
+        Post {
+            id: Some(5),
+            title: "Hello".into(),
+            published_at: None,
+        }
+    }
+
+    #[test]
+    fn test_scopes() {
+        assert_eq!(
+            Post::published().to_sql(),
+            r#"SELECT * FROM "posts" WHERE "posts"."published_at" <= $1"#
+        );
+        assert_eq!(
+            Post::drafts().to_sql(),
+            r#"SELECT * FROM "posts" WHERE "posts"."published_at" IS NULL"#
+        );
+
+        let mut post = post();
+        assert!(post.is_draft());
+        assert!(!post.is_published());
+
+        post.set_published_at(Some(OffsetDateTime::now_utc() + Duration::days(1)));
+        assert!(!post.is_draft());
+        assert!(!post.is_published());
+
+        assert!(post.publish().to_sql().starts_with(r#"UPDATE "posts" SET"#));
+    }
+
+    #[test]
+    fn test_preview_token() {
+        let token = post().preview_token(Duration::hours(1)).unwrap();
+        assert_eq!(
+            Post::preview(&token).unwrap().to_sql(),
+            Post::find(5_i64).to_sql()
+        );
+
+        let expired = post().preview_token(Duration::hours(-1)).unwrap();
+        assert!(matches!(
+            Post::preview(&expired),
+            Err(Error::RecordNotFound)
+        ));
+        assert!(Post::preview("garbage").is_err());
+
+        let mut draft = post();
+        draft.id = None;
+        assert!(draft.preview_token(Duration::hours(1)).is_err());
+    }
+}
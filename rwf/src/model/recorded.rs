@@ -0,0 +1,166 @@
+//! Query results recorded in memory, used instead of the database.
+//!
+//! The query is built by the ORM as usual, and the recorded records matching its SQL and
+//! placeholder values are returned instead of sending it to Postgres. This makes it possible
+//! to benchmark controllers, including their use of the ORM, without a database server.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let users = Recorded::new().record(
+//!     User::all().limit(25),
+//!     vec![User { id: Some(1), email: "alice@example.com".into() }],
+//! );
+//!
+//! let page = users.fetch_all(User::all().limit(25))?;
+//! ```
+use std::collections::HashMap;
+
+use super::{Error, Model, Query, ToSql};
+
+/// Records returned by queries, keyed by the query.
+#[derive(Debug, Clone)]
+pub struct Recorded<T: Model> {
+    results: HashMap<String, Vec<T>>,
+}
+
+impl<T: Model> Default for Recorded<T> {
+    fn default() -> Self {
+        Self {
+            results: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Model> Recorded<T> {
+    /// No recorded queries.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the records when the same query, with the same placeholder values, is fetched.
+    pub fn record(mut self, query: Query<T>, records: Vec<T>) -> Self {
+        self.results.insert(key(&query), records);
+        self
+    }
+
+    /// Get all records recorded for the query.
+    pub fn fetch_all(&self, query: Query<T>) -> Result<Vec<T>, Error> {
+        let key = key(&query);
+
+        match self.results.get(&key) {
+            Some(records) => Ok(records.clone()),
+            None => Err(Error::QueryError(
+                "query not recorded".into(),
+                query.to_sql(),
+            )),
+        }
+    }
+
+    /// Get the first record recorded for the query.
+    pub fn fetch(&self, query: Query<T>) -> Result<T, Error> {
+        match self.fetch_all(query)?.into_iter().next() {
+            Some(record) => Ok(record),
+            None => Err(Error::RecordNotFound),
+        }
+    }
+
+    /// Get the first record recorded for the query, if any.
+    pub fn fetch_optional(&self, query: Query<T>) -> Result<Option<T>, Error> {
+        match self.fetch(query) {
+            Ok(record) => Ok(Some(record)),
+            Err(Error::RecordNotFound) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+fn key<T: Model>(query: &Query<T>) -> String {
+    let placeholders = match query {
+        Query::Select(select) => format!("{:?}", select.placeholders),
+        Query::Update(update) => format!("{:?}", update.placeholders),
+        Query::Insert(insert) => format!("{:?}", insert.placeholders),
+        Query::InsertIfNotExists { select, insert, .. } => {
+            format!("{:?} {:?}", select.placeholders, insert.placeholders)
+        }
+        Query::Raw { placeholders, .. } => format!("{:?}", placeholders),
+        Query::Picked(picked) => format!("{:?}", picked.select.placeholders),
+    };
+
+    format!("{} {}", query.to_sql(), placeholders)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{Column, Error as ModelError, FromRow, ToValue, Value};
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct User {
+        id: Option<i64>,
+        email: String,
+    }
+
+    impl FromRow for User {
+        fn from_row(row: tokio_postgres::Row) -> Result<Self, ModelError> {
+            Ok(Self {
+                id: row.try_get("id")?,
+                email: row.try_get("email")?,
+            })
+        }
+    }
+
+    impl Model for User {
+        fn table_name() -> &'static str {
+            "users"
+        }
+
+        fn foreign_key() -> &'static str {
+            "user_id"
+        }
+
+        fn column_names() -> &'static [&'static str] {
+            &["email"]
+        }
+
+        fn values(&self) -> Vec<Value> {
+            vec![self.email.to_value()]
+        }
+
+        fn id(&self) -> Value {
+            self.id.to_value()
+        }
+    }
+
+    #[test]
+    fn test_recorded() {
+        let alice = User {
+            id: Some(1),
+            email: "alice@example.com".into(),
+        };
+
+        let users = Recorded::new()
+            .record(
+                User::filter("email", "alice@example.com"),
+                vec![alice.clone()],
+            )
+            .record(User::filter("email", "bob@example.com"), vec![]);
+
+        assert_eq!(
+            users
+                .fetch(User::filter("email", "alice@example.com"))
+                .unwrap(),
+            alice
+        );
+        assert_eq!(
+            users
+                .fetch_optional(User::filter("email", "bob@example.com"))
+                .unwrap(),
+            None
+        );
+        assert!(matches!(
+            users.fetch_all(User::filter(Column::name("email"), "carol@example.com")),
+            Err(Error::QueryError(..))
+        ));
+    }
+}
@@ -178,6 +178,40 @@ impl<T: FromRow> Select<T> {
         self
     }
 
+    /// Rows where any of the columns contains the text, ignoring case.
+    pub fn filter_contains(mut self, columns: &[impl ToColumn], text: &str) -> Self {
+        let columns = columns
+            .iter()
+            .map(|column| {
+                let column = column.to_column();
+                if !column.qualified() {
+                    column.qualify(&self.table_name)
+                } else {
+                    column
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if columns.is_empty() {
+            return self;
+        }
+
+        // `%` and `_` in the text are matched literally.
+        let pattern = format!(
+            "%{}%",
+            text.replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        let placeholder = self.placeholders.add(&pattern.to_value());
+
+        let mut filter = Filter::default();
+        filter.contains(columns, placeholder);
+        self.where_clause.concat(filter);
+
+        self
+    }
+
     pub fn join(mut self, join: Join) -> Self {
         self.joins = self.joins.add(join);
         self.columns = self.columns.table_name(&self.table_name);
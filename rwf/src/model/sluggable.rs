@@ -0,0 +1,502 @@
+//! URL slugs, e.g. `/posts/hello-world` instead of `/posts/42`.
+//!
+//! The model implements [`Sluggable`] and is saved with [`Sluggable::save_with_slug`], which generates
+//! a slug from another field, e.g. the title, adding a number if the slug is taken, e.g. `hello-world-2`.
+//! When the slug changes, the old one is kept in the `rwf_slugs` table, created by the migration added with
+//! `rwf-cli migrate feature slugs`, so old links keep working.
+//!
+//! # Example
+//!
+//! ```ignore
+//! #[derive(Clone, macros::Model)]
+//! struct Post {
+//!     id: Option<i64>,
+//!     title: String,
+//!     slug: String,
+//! }
+//!
+//! impl Sluggable for Post {
+//!     fn slug(&self) -> &str {
+//!         &self.slug
+//!     }
+//!
+//!     fn set_slug(&mut self, slug: String) {
+//!         self.slug = slug;
+//!     }
+//!
+//!     fn slug_source(&self) -> String {
+//!         self.title.clone()
+//!     }
+//! }
+//!
+//! let post = post.save_with_slug(&mut conn).await?;
+//! let post = Post::find_by_slug_or_id("hello-world", &mut conn).await?;
+//! ```
+use async_trait::async_trait;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use super::{ConnectionGuard, Error, FromRow, Model, Query, Scope, ToValue, Value};
+
+/// Longest slug generated, in characters.
+const MAX_LENGTH: usize = 80;
+
+/// An old slug, recorded in the `rwf_slugs` table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Slug {
+    id: Option<i64>,
+    /// Identifies the model, the table name by default.
+    pub sluggable_type: String,
+    /// Primary key of the record.
+    pub sluggable_id: i64,
+    /// The old slug.
+    pub slug: String,
+    /// When the slug stopped being used.
+    pub created_at: OffsetDateTime,
+}
+
+impl FromRow for Slug {
+    fn from_row(row: tokio_postgres::Row) -> Result<Self, Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            sluggable_type: row.try_get("sluggable_type")?,
+            sluggable_id: row.try_get("sluggable_id")?,
+            slug: row.try_get("slug")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl Model for Slug {
+    fn table_name() -> &'static str {
+        "rwf_slugs"
+    }
+
+    fn foreign_key() -> &'static str {
+        "slug_id"
+    }
+
+    fn id(&self) -> Value {
+        self.id.to_value()
+    }
+
+    fn column_names() -> &'static [&'static str] {
+        &["sluggable_type", "sluggable_id", "slug", "created_at"]
+    }
+
+    fn values(&self) -> Vec<Value> {
+        vec![
+            self.sluggable_type.to_value(),
+            self.sluggable_id.to_value(),
+            self.slug.to_value(),
+            self.created_at.to_value(),
+        ]
+    }
+}
+
+/// Record found by [`Sluggable::find_by_slug_or_id`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Found<T> {
+    /// Found by its slug or primary key.
+    Current(T),
+    /// Found by an old slug, with its current slug. Links should be redirected to the current one.
+    Moved(T, String),
+}
+
+impl<T> Found<T> {
+    /// The record.
+    pub fn record(self) -> T {
+        match self {
+            Self::Current(record) => record,
+            Self::Moved(record, _) => record,
+        }
+    }
+}
+
+/// Model identified in URLs by a slug. Only models with an integer primary key are supported.
+///
+/// The slug column should have a unique index.
+#[async_trait]
+pub trait Sluggable: Model + Send + Sync {
+    /// The record's slug. Empty if it doesn't have one yet.
+    fn slug(&self) -> &str;
+
+    /// Set the record's slug.
+    fn set_slug(&mut self, slug: String);
+
+    /// Text the slug is generated from, e.g. the title.
+    fn slug_source(&self) -> String;
+
+    /// Name of the column storing the slug.
+    fn slug_column() -> &'static str {
+        "slug"
+    }
+
+    /// Identifies the model in the `rwf_slugs` table. Defaults to the table name.
+    fn sluggable_type() -> &'static str {
+        Self::table_name()
+    }
+
+    /// Generate a new slug when the record is saved. By default, the slug is generated if
+    /// the record doesn't have one, or if the source changed, e.g. the post was renamed.
+    /// Return `self.slug().is_empty()` to never change slugs once they are set.
+    fn should_generate_slug(&self) -> bool {
+        !is_slug_of(self.slug(), &slugify(&self.slug_source()))
+    }
+
+    /// Find the record with the slug.
+    fn find_by_slug(slug: &str) -> Query<Self> {
+        Self::find_by(Self::slug_column(), slug)
+    }
+
+    /// Old slugs of the record, oldest first.
+    fn slug_history(&self) -> Scope<Slug> {
+        Slug::filter("sluggable_type", Self::sluggable_type())
+            .filter("sluggable_id", self.id())
+            .order("created_at")
+    }
+
+    /// Generate a slug from the source, unique among records of this model and their old slugs.
+    async fn generate_slug(&self, conn: &mut ConnectionGuard) -> Result<String, Error> {
+        let base = match slugify(&self.slug_source()) {
+            base if base.is_empty() => Uuid::new_v4().simple().to_string()[..8].to_string(),
+            base => base,
+        };
+
+        let taken = conn
+            .client()
+            .query(
+                &format!(
+                    r#"SELECT "{slug}" FROM "{table}"
+                    WHERE ("{slug}" = $1 OR "{slug}" LIKE $2) AND "{id}" IS DISTINCT FROM $3
+                    UNION ALL
+                    SELECT "slug" FROM "rwf_slugs"
+                    WHERE "sluggable_type" = $4 AND ("slug" = $1 OR "slug" LIKE $2)
+                    AND "sluggable_id" IS DISTINCT FROM $3"#,
+                    slug = Self::slug_column(),
+                    table = Self::table_name(),
+                    id = Self::primary_key(),
+                ),
+                &[
+                    &base,
+                    &format!("{}-%", base),
+                    &integer_id(&self.id()),
+                    &Self::sluggable_type(),
+                ],
+            )
+            .await?
+            .into_iter()
+            .filter_map(|row| row.try_get::<_, String>(0).ok())
+            .collect::<Vec<_>>();
+
+        Ok(unique(&base, &taken))
+    }
+
+    /// Save the record, generating its slug if needed, see [`Sluggable::should_generate_slug`].
+    /// If the slug changed, the old one is kept, so it can still be used to find the record.
+    /// Returns the saved record.
+    async fn save_with_slug(mut self, conn: &mut ConnectionGuard) -> Result<Self, Error> {
+        let previous = match integer_id(&self.id()) {
+            Some(id) => Self::find(id)
+                .fetch_optional(&mut *conn)
+                .await?
+                .map(|record| record.slug().to_string()),
+            None => None,
+        };
+
+        if self.should_generate_slug() {
+            let slug = self.generate_slug(conn).await?;
+            self.set_slug(slug);
+        }
+
+        let record = self.save().fetch(&mut *conn).await?;
+        let id = integer_id(&record.id());
+
+        if let Some(previous) = previous.filter(|slug| !slug.is_empty() && slug != record.slug()) {
+            conn.client()
+                .execute(
+                    r#"INSERT INTO "rwf_slugs" ("sluggable_type", "sluggable_id", "slug") VALUES ($1, $2, $3)
+                    ON CONFLICT ("sluggable_type", "slug")
+                    DO UPDATE SET "sluggable_id" = EXCLUDED."sluggable_id", "created_at" = NOW()"#,
+                    &[&Self::sluggable_type(), &id, &previous],
+                )
+                .await?;
+        }
+
+        // The record may be getting one of its old slugs back.
+        conn.client()
+            .execute(
+                r#"DELETE FROM "rwf_slugs" WHERE "sluggable_type" = $1 AND "slug" = $2"#,
+                &[&Self::sluggable_type(), &record.slug()],
+            )
+            .await?;
+
+        Ok(record)
+    }
+
+    /// Find the record identified in a URL by its slug, an old slug, or its primary key.
+    async fn find_by_slug_or_id(
+        slug_or_id: &str,
+        conn: &mut ConnectionGuard,
+    ) -> Result<Option<Found<Self>>, Error> {
+        if let Some(record) = Self::find_by_slug(slug_or_id)
+            .fetch_optional(&mut *conn)
+            .await?
+        {
+            return Ok(Some(Found::Current(record)));
+        }
+
+        if let Ok(id) = slug_or_id.parse::<i64>() {
+            if let Some(record) = Self::find(id).fetch_optional(&mut *conn).await? {
+                return Ok(Some(Found::Current(record)));
+            }
+        }
+
+        let old = Slug::filter("sluggable_type", Self::sluggable_type())
+            .filter("slug", slug_or_id)
+            .take_one()
+            .fetch_optional(&mut *conn)
+            .await?;
+
+        match old {
+            Some(old) => Ok(Self::find(old.sluggable_id)
+                .fetch_optional(conn)
+                .await?
+                .map(|record| {
+                    let slug = record.slug().to_string();
+                    Found::Moved(record, slug)
+                })),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Turn text into a slug: lowercase ASCII letters and digits separated by dashes.
+///
+/// # Example
+///
+/// ```
+/// # use rwf::model::sluggable::slugify;
+/// assert_eq!(slugify("Héllo, World!"), "hello-world");
+/// ```
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+
+    for c in text.chars().flat_map(|c| c.to_lowercase()) {
+        let ascii = match c {
+            'a'..='z' | '0'..='9' => c.to_string(),
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => "a".into(),
+            'æ' => "ae".into(),
+            'ç' => "c".into(),
+            'è' | 'é' | 'ê' | 'ë' => "e".into(),
+            'ì' | 'í' | 'î' | 'ï' => "i".into(),
+            'ñ' => "n".into(),
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' => "o".into(),
+            'œ' => "oe".into(),
+            'ù' | 'ú' | 'û' | 'ü' => "u".into(),
+            'ý' | 'ÿ' => "y".into(),
+            'ß' => "ss".into(),
+            _ => "-".into(),
+        };
+
+        for c in ascii.chars() {
+            if c != '-' || !(slug.is_empty() || slug.ends_with('-')) {
+                slug.push(c);
+            }
+        }
+    }
+
+    let mut slug = slug.trim_end_matches('-').to_string();
+
+    // Cut long slugs between words.
+    if slug.len() > MAX_LENGTH {
+        slug.truncate(MAX_LENGTH);
+        if let Some((start, _)) = slug.rsplit_once('-') {
+            slug = start.to_string();
+        }
+    }
+
+    slug
+}
+
+/// The slug was generated from the base, e.g. `hello-world-2` from `hello-world`.
+fn is_slug_of(slug: &str, base: &str) -> bool {
+    if slug.is_empty() {
+        return false;
+    }
+
+    match slug.strip_prefix(base) {
+        Some("") => true,
+        Some(suffix) => suffix
+            .strip_prefix('-')
+            .map(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+            .unwrap_or(false),
+        None => false,
+    }
+}
+
+/// First slug not taken: the base, or the base followed by a number.
+fn unique(base: &str, taken: &[String]) -> String {
+    if !taken.iter().any(|slug| slug == base) {
+        return base.to_string();
+    }
+
+    (2..)
+        .map(|n| format!("{}-{}", base, n))
+        .find(|slug| !taken.contains(slug))
+        .unwrap()
+}
+
+/// Integer primary key, if the record has one.
+pub(crate) fn integer_id(id: &Value) -> Option<i64> {
+    match id {
+        Value::Integer(id) => Some(*id),
+        Value::Optional(id) => id.as_ref().as_ref().and_then(integer_id),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{test::bootstrap, Pool};
+
+    #[derive(Clone, Debug)]
+    struct Post {
+        id: Option<i64>,
+        title: String,
+        slug: String,
+    }
+
+    impl FromRow for Post {
+        fn from_row(row: tokio_postgres::Row) -> Result<Self, Error> {
+            Ok(Self {
+                id: row.try_get("id")?,
+                title: row.try_get("title")?,
+                slug: row.try_get("slug")?,
+            })
+        }
+    }
+
+    impl Model for Post {
+        fn id(&self) -> Value {
+            self.id.to_value()
+        }
+
+        fn table_name() -> &'static str {
+            "rwf_test_slug_posts"
+        }
+
+        fn foreign_key() -> &'static str {
+            "post_id"
+        }
+
+        fn column_names() -> &'static [&'static str] {
+            &["title", "slug"]
+        }
+
+        fn values(&self) -> Vec<Value> {
+            vec![self.title.to_value(), self.slug.to_value()]
+        }
+    }
+
+    impl Sluggable for Post {
+        fn slug(&self) -> &str {
+            &self.slug
+        }
+
+        fn set_slug(&mut self, slug: String) {
+            self.slug = slug;
+        }
+
+        fn slug_source(&self) -> String {
+            self.title.clone()
+        }
+    }
+
+    fn post(title: &str) -> Post {
+        Post {
+            id: None,
+            title: title.into(),
+            slug: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("  Hello,   World! "), "hello-world");
+        assert_eq!(slugify("Crème Brûlée"), "creme-brulee");
+        assert_eq!(slugify("--"), "");
+        assert!(slugify(&"word ".repeat(50)).len() <= MAX_LENGTH);
+        assert!(!slugify(&"word ".repeat(50)).ends_with('-'));
+
+        assert!(is_slug_of("hello-world", "hello-world"));
+        assert!(is_slug_of("hello-world-2", "hello-world"));
+        assert!(!is_slug_of("hello-world-again", "hello-world"));
+        assert!(!is_slug_of("", ""));
+
+        let taken = vec!["hello".to_string(), "hello-2".to_string()];
+        assert_eq!(unique("hello", &taken), "hello-3");
+        assert_eq!(unique("bye", &taken), "bye");
+    }
+
+    #[tokio::test]
+    async fn test_sluggable() -> Result<(), Error> {
+        let pool = Pool::from_env();
+        let mut conn = pool.transaction().await?;
+
+        bootstrap(conn.client()).await?;
+
+        conn.client()
+            .execute(
+                "CREATE TABLE rwf_test_slug_posts (id BIGSERIAL PRIMARY KEY, title VARCHAR NOT NULL, slug VARCHAR NOT NULL UNIQUE)",
+                &[],
+            )
+            .await?;
+
+        let first = post("Hello, World!").save_with_slug(&mut conn).await?;
+        assert_eq!(first.slug, "hello-world");
+
+        let second = post("Hello world").save_with_slug(&mut conn).await?;
+        assert_eq!(second.slug, "hello-world-2");
+
+        // Saving without changes keeps the slug.
+        let second = second.save_with_slug(&mut conn).await?;
+        assert_eq!(second.slug, "hello-world-2");
+
+        let mut renamed = first.clone();
+        renamed.title = "Goodbye".into();
+        let renamed = renamed.save_with_slug(&mut conn).await?;
+        assert_eq!(renamed.slug, "goodbye");
+        let history = renamed.slug_history().fetch_all(&mut conn).await?;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].slug, "hello-world");
+
+        // The old slug is reserved for the renamed post.
+        let third = post("Hello, World").save_with_slug(&mut conn).await?;
+        assert_eq!(third.slug, "hello-world-3");
+
+        match Post::find_by_slug_or_id("hello-world", &mut conn).await? {
+            Some(Found::Moved(post, slug)) => {
+                assert_eq!(post.id, renamed.id);
+                assert_eq!(slug, "goodbye");
+            }
+            found => panic!("expected the post to be found by its old slug: {:?}", found),
+        }
+
+        match Post::find_by_slug_or_id("goodbye", &mut conn).await? {
+            Some(Found::Current(post)) => assert_eq!(post.id, renamed.id),
+            found => panic!("expected the post to be found by its slug: {:?}", found),
+        }
+
+        let id = second.id.unwrap().to_string();
+        let found = Post::find_by_slug_or_id(&id, &mut conn).await?.unwrap();
+        assert_eq!(found.record().id, second.id);
+
+        assert!(Post::find_by_slug_or_id("missing", &mut conn)
+            .await?
+            .is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,463 @@
+//! State machines persisted to a model column.
+//!
+//! The states are declared as an enum deriving `macros::StateMachine`, with the allowed
+//! transitions listed on each variant. States are stored in the model column as strings,
+//! e.g. `"pending"`, so the column should be a `VARCHAR`.
+//!
+//! The model implements [`Stateful`] and changes state with [`Stateful::transition_to`], which checks that
+//! the transition is allowed, runs the guard and callbacks, and records the transition in the
+//! `rwf_state_transitions` table, created by the migration added with
+//! `rwf-cli migrate feature state_transitions`.
+//!
+//! # Example
+//!
+//! ```ignore
+//! #[derive(Clone, Copy, Debug, PartialEq, macros::StateMachine)]
+//! enum OrderState {
+//!     #[transitions(Paid, Cancelled)]
+//!     Pending,
+//!     #[transitions(Shipped, Cancelled)]
+//!     Paid,
+//!     Shipped,
+//!     Cancelled,
+//! }
+//!
+//! #[derive(Clone, macros::Model)]
+//! struct Order {
+//!     id: Option<i64>,
+//!     state: OrderState,
+//! }
+//!
+//! impl Stateful for Order {
+//!     type State = OrderState;
+//!
+//!     fn state(&self) -> OrderState {
+//!         self.state
+//!     }
+//!
+//!     fn set_state(&mut self, state: OrderState) {
+//!         self.state = state;
+//!     }
+//! }
+//!
+//! let order = order.transition_to(OrderState::Paid, &mut conn).await?;
+//! ```
+use async_trait::async_trait;
+use time::OffsetDateTime;
+
+use super::{ConnectionGuard, Error, FromRow, Model, Scope, ToValue, Value};
+
+/// States of a state machine and the transitions allowed between them.
+///
+/// Usually implemented with `macros::StateMachine`.
+///
+/// # Example
+///
+/// ```
+/// # use rwf::prelude::*;
+/// #[derive(Clone, Copy, Debug, PartialEq, macros::StateMachine)]
+/// enum OrderState {
+///     #[transitions(Paid, Cancelled)]
+///     Pending,
+///     Paid,
+///     Cancelled,
+/// }
+///
+/// #[derive(Clone, macros::Model)]
+/// struct Order {
+///     id: Option<i64>,
+///     state: OrderState,
+/// }
+///
+/// assert!(OrderState::Pending.can_transition_to(OrderState::Paid));
+/// assert!(OrderState::Paid.is_final());
+/// assert_eq!(OrderState::from_name("cancelled"), Some(OrderState::Cancelled));
+/// ```
+pub trait StateMachine: Copy + PartialEq + std::fmt::Debug + Send + Sync + 'static {
+    /// Name of the state, as stored in the database.
+    fn name(&self) -> &'static str;
+
+    /// Get the state from its name.
+    fn from_name(name: &str) -> Option<Self>;
+
+    /// All states, in the order they are declared.
+    fn states() -> &'static [Self];
+
+    /// States the machine can move to from this state.
+    fn transitions(&self) -> &'static [Self];
+
+    /// The machine can move from this state to the other state.
+    fn can_transition_to(&self, to: Self) -> bool {
+        self.transitions().contains(&to)
+    }
+
+    /// No transitions are allowed from this state.
+    fn is_final(&self) -> bool {
+        self.transitions().is_empty()
+    }
+}
+
+/// A state transition recorded in the `rwf_state_transitions` table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateTransition {
+    id: Option<i64>,
+    /// Table name of the model.
+    pub model: String,
+    /// Primary key of the record.
+    pub record_id: i64,
+    /// State before the transition.
+    pub from_state: String,
+    /// State after the transition.
+    pub to_state: String,
+    /// When the transition happened.
+    pub created_at: OffsetDateTime,
+}
+
+impl FromRow for StateTransition {
+    fn from_row(row: tokio_postgres::Row) -> Result<Self, Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            model: row.try_get("model")?,
+            record_id: row.try_get("record_id")?,
+            from_state: row.try_get("from_state")?,
+            to_state: row.try_get("to_state")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl Model for StateTransition {
+    fn table_name() -> &'static str {
+        "rwf_state_transitions"
+    }
+
+    fn foreign_key() -> &'static str {
+        "state_transition_id"
+    }
+
+    fn id(&self) -> Value {
+        self.id.to_value()
+    }
+
+    fn column_names() -> &'static [&'static str] {
+        &["model", "record_id", "from_state", "to_state", "created_at"]
+    }
+
+    fn values(&self) -> Vec<Value> {
+        vec![
+            self.model.to_value(),
+            self.record_id.to_value(),
+            self.from_state.to_value(),
+            self.to_state.to_value(),
+            self.created_at.to_value(),
+        ]
+    }
+}
+
+/// Model with a state column controlled by a [`StateMachine`].
+#[async_trait]
+pub trait Stateful: Model + Send + Sync {
+    /// The state machine.
+    type State: StateMachine;
+
+    /// Current state of the record.
+    fn state(&self) -> Self::State;
+
+    /// Set the state of the record. Use [`Stateful::transition_to`] to change
+    /// the state of a saved record.
+    fn set_state(&mut self, state: Self::State);
+
+    /// Name of the column storing the state.
+    fn state_column() -> &'static str {
+        "state"
+    }
+
+    /// Additional check performed before the transition, e.g. an order can't be shipped
+    /// before it has an address. Transitions not declared by the state machine
+    /// are never allowed, whatever the guard returns.
+    fn guard(&self, _to: Self::State) -> bool {
+        true
+    }
+
+    /// Called before the state is changed. Returning an error cancels the transition.
+    async fn before_transition(
+        &self,
+        _to: Self::State,
+        _conn: &mut ConnectionGuard,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called after the state is changed, with the record in its new state.
+    async fn after_transition(
+        &self,
+        _from: Self::State,
+        _conn: &mut ConnectionGuard,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// The record can move to the state.
+    fn can_transition_to(&self, to: Self::State) -> bool {
+        self.state().can_transition_to(to) && self.guard(to)
+    }
+
+    /// Records in the state.
+    fn in_state(state: Self::State) -> Scope<Self> {
+        Self::filter(Self::state_column(), state.name())
+    }
+
+    /// Move the record to another state and record the transition. Returns the updated record.
+    ///
+    /// Returns [`Error::InvalidTransition`] if the transition isn't allowed,
+    /// or if the record was moved to another state by someone else in the meantime.
+    /// Use a transaction to make sure the callbacks and the transition are committed together.
+    async fn transition_to(
+        self,
+        to: Self::State,
+        conn: &mut ConnectionGuard,
+    ) -> Result<Self, Error> {
+        let from = self.state();
+
+        if !self.can_transition_to(to) {
+            return Err(Error::InvalidTransition(from.name(), to.name()));
+        }
+
+        self.before_transition(to, conn).await?;
+
+        // The record is only updated if it's still in the state it was fetched in.
+        let record = Self::find_by_sql(
+            format!(
+                r#"WITH updated AS (
+                    UPDATE "{table}" SET "{state}" = $1 WHERE "{id}" = $2 AND "{state}" = $3 RETURNING *
+                ), history AS (
+                    INSERT INTO "rwf_state_transitions" ("model", "record_id", "from_state", "to_state")
+                    SELECT $4, "{id}", $3, $1 FROM updated
+                )
+                SELECT * FROM updated"#,
+                table = Self::table_name(),
+                state = Self::state_column(),
+                id = Self::primary_key(),
+            ),
+            &[
+                to.name().to_value(),
+                self.id(),
+                from.name().to_value(),
+                Self::table_name().to_value(),
+            ],
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(Error::InvalidTransition(from.name(), to.name()))?;
+
+        record.after_transition(from, conn).await?;
+
+        Ok(record)
+    }
+
+    /// Transitions of the record, oldest first.
+    fn state_history(&self) -> Scope<StateTransition> {
+        StateTransition::filter("model", Self::table_name())
+            .filter("record_id", self.id())
+            .order("id")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{test::bootstrap, Pool, ToSql};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum OrderState {
+        Pending,
+        Paid,
+        Shipped,
+        Cancelled,
+    }
+
+    // Equivalent to #[derive(StateMachine)].
+    impl StateMachine for OrderState {
+        fn name(&self) -> &'static str {
+            match self {
+                Self::Pending => "pending",
+                Self::Paid => "paid",
+                Self::Shipped => "shipped",
+                Self::Cancelled => "cancelled",
+            }
+        }
+
+        fn from_name(name: &str) -> Option<Self> {
+            Self::states()
+                .iter()
+                .find(|state| state.name() == name)
+                .copied()
+        }
+
+        fn states() -> &'static [Self] {
+            &[Self::Pending, Self::Paid, Self::Shipped, Self::Cancelled]
+        }
+
+        fn transitions(&self) -> &'static [Self] {
+            match self {
+                Self::Pending => &[Self::Paid, Self::Cancelled],
+                Self::Paid => &[Self::Shipped, Self::Cancelled],
+                _ => &[],
+            }
+        }
+    }
+
+    static SHIPPED: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Clone, Debug)]
+    struct Order {
+        id: Option<i64>,
+        state: OrderState,
+        address: Option<String>,
+    }
+
+    impl FromRow for Order {
+        fn from_row(row: tokio_postgres::Row) -> Result<Self, Error> {
+            let state: String = row.try_get("state")?;
+            Ok(Self {
+                id: row.try_get("id")?,
+                state: OrderState::from_name(&state)
+                    .ok_or(Error::ValueError("state", state.clone()))?,
+                address: row.try_get("address")?,
+            })
+        }
+    }
+
+    impl Model for Order {
+        fn id(&self) -> Value {
+            self.id.to_value()
+        }
+
+        fn table_name() -> &'static str {
+            "rwf_test_orders"
+        }
+
+        fn foreign_key() -> &'static str {
+            "order_id"
+        }
+
+        fn column_names() -> &'static [&'static str] {
+            &["state", "address"]
+        }
+
+        fn values(&self) -> Vec<Value> {
+            vec![self.state.name().to_value(), self.address.to_value()]
+        }
+    }
+
+    #[async_trait]
+    impl Stateful for Order {
+        type State = OrderState;
+
+        fn state(&self) -> OrderState {
+            self.state
+        }
+
+        fn set_state(&mut self, state: OrderState) {
+            self.state = state;
+        }
+
+        fn guard(&self, to: OrderState) -> bool {
+            to != OrderState::Shipped || self.address.is_some()
+        }
+
+        async fn after_transition(
+            &self,
+            _from: OrderState,
+            _conn: &mut ConnectionGuard,
+        ) -> Result<(), Error> {
+            if self.state == OrderState::Shipped {
+                SHIPPED.fetch_add(1, Ordering::SeqCst);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_state_machine() {
+        assert!(OrderState::Pending.can_transition_to(OrderState::Paid));
+        assert!(!OrderState::Pending.can_transition_to(OrderState::Shipped));
+        assert!(OrderState::Shipped.is_final());
+
+        let order = Order {
+            id: Some(1),
+            state: OrderState::Paid,
+            address: None,
+        };
+        assert!(!order.can_transition_to(OrderState::Shipped));
+        assert!(order.can_transition_to(OrderState::Cancelled));
+
+        assert_eq!(
+            Order::in_state(OrderState::Paid).to_sql(),
+            r#"SELECT * FROM "rwf_test_orders" WHERE "rwf_test_orders"."state" = $1"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transition_to() -> Result<(), Error> {
+        let mut conn = Pool::begin().await?;
+
+        bootstrap(conn.client()).await?;
+
+        conn.client()
+            .execute(
+                "CREATE TABLE rwf_test_orders (id BIGSERIAL PRIMARY KEY, state VARCHAR NOT NULL, address VARCHAR)",
+                &[],
+            )
+            .await?;
+
+        let order = Order {
+            id: None,
+            state: OrderState::Pending,
+            address: None,
+        }
+        .save()
+        .fetch(&mut conn)
+        .await?;
+
+        let stale = order.clone();
+        let order = order.transition_to(OrderState::Paid, &mut conn).await?;
+        assert_eq!(order.state, OrderState::Paid);
+
+        // Someone else changed the state.
+        assert!(matches!(
+            stale.transition_to(OrderState::Cancelled, &mut conn).await,
+            Err(Error::InvalidTransition("pending", "cancelled"))
+        ));
+
+        // Guard.
+        assert!(order
+            .clone()
+            .transition_to(OrderState::Shipped, &mut conn)
+            .await
+            .is_err());
+
+        let mut order = order;
+        order.address = Some("1 Main St".into());
+        let order = order.save().fetch(&mut conn).await?;
+        let order = order.transition_to(OrderState::Shipped, &mut conn).await?;
+        assert_eq!(SHIPPED.load(Ordering::SeqCst), 1);
+
+        let history = order.state_history().fetch_all(&mut conn).await?;
+        assert_eq!(
+            history
+                .iter()
+                .map(|t| (t.from_state.as_str(), t.to_state.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("pending", "paid"), ("paid", "shipped")]
+        );
+
+        let shipped = Order::in_state(OrderState::Shipped)
+            .fetch_all(&mut conn)
+            .await?;
+        assert_eq!(shipped.len(), 1);
+
+        conn.rollback().await
+    }
+}
@@ -0,0 +1,503 @@
+//! Tagging.
+//!
+//! Any model with an integer primary key can be tagged by implementing [`Taggable`]. Tags are stored
+//! in the `rwf_tags` table and attached to records through the `rwf_taggings` table, both created
+//! by the migration added with `rwf-cli migrate feature tags`.
+//!
+//! # Example
+//!
+//! ```ignore
+//! #[derive(Clone, macros::Model)]
+//! struct Post {
+//!     id: Option<i64>,
+//!     title: String,
+//! }
+//!
+//! impl Taggable for Post {}
+//!
+//! post.set_tag_list("rust, web").execute(&mut conn).await?;
+//! let tags = post.tags().fetch_all(&mut conn).await?;
+//! let posts = Post::tagged_with("rust").fetch_all(&mut conn).await?;
+//! ```
+use time::OffsetDateTime;
+
+use super::{
+    join::{Join, JoinKind},
+    Association, AssociationType, Column, Error, FromRow, Model, Query, Scope, ToValue, Value,
+};
+
+/// A tag.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tag {
+    id: Option<i64>,
+    /// Tag name, e.g. `"rust"`.
+    pub name: String,
+    /// When the tag was first used.
+    pub created_at: OffsetDateTime,
+}
+
+impl Tag {
+    /// Create a new tag. The name is normalized, see [`normalize`].
+    pub fn new(name: &str) -> Self {
+        Self {
+            id: None,
+            name: normalize(name),
+            created_at: OffsetDateTime::now_utc(),
+        }
+    }
+}
+
+impl FromRow for Tag {
+    fn from_row(row: tokio_postgres::Row) -> Result<Self, Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl Model for Tag {
+    fn table_name() -> &'static str {
+        "rwf_tags"
+    }
+
+    fn foreign_key() -> &'static str {
+        "tag_id"
+    }
+
+    fn id(&self) -> Value {
+        self.id.to_value()
+    }
+
+    fn column_names() -> &'static [&'static str] {
+        &["name", "created_at"]
+    }
+
+    fn values(&self) -> Vec<Value> {
+        vec![self.name.to_value(), self.created_at.to_value()]
+    }
+}
+
+/// A tag attached to a record.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tagging {
+    id: Option<i64>,
+    /// Tag id.
+    pub tag_id: i64,
+    /// Type of the tagged record, the table name of its model by default.
+    pub taggable_type: String,
+    /// Primary key of the tagged record.
+    pub taggable_id: i64,
+    /// When the tag was attached.
+    pub created_at: OffsetDateTime,
+}
+
+impl FromRow for Tagging {
+    fn from_row(row: tokio_postgres::Row) -> Result<Self, Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            tag_id: row.try_get("tag_id")?,
+            taggable_type: row.try_get("taggable_type")?,
+            taggable_id: row.try_get("taggable_id")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl Model for Tagging {
+    fn table_name() -> &'static str {
+        "rwf_taggings"
+    }
+
+    fn foreign_key() -> &'static str {
+        "tagging_id"
+    }
+
+    fn id(&self) -> Value {
+        self.id.to_value()
+    }
+
+    fn column_names() -> &'static [&'static str] {
+        &["tag_id", "taggable_type", "taggable_id", "created_at"]
+    }
+
+    fn values(&self) -> Vec<Value> {
+        vec![
+            self.tag_id.to_value(),
+            self.taggable_type.to_value(),
+            self.taggable_id.to_value(),
+            self.created_at.to_value(),
+        ]
+    }
+}
+
+// INNER JOIN "rwf_taggings" ON "rwf_taggings"."taggable_id" = "posts"."id"
+impl<T: Taggable> Association<T> for Tagging {
+    fn association_type() -> AssociationType {
+        AssociationType::HasMany
+    }
+
+    fn construct_join() -> Join {
+        Join::new(
+            JoinKind::Inner,
+            Self::table_name(),
+            Column::new(Self::table_name(), "taggable_id"),
+            Column::new(T::table_name(), T::primary_key()),
+        )
+    }
+}
+
+// INNER JOIN "rwf_tags" ON "rwf_tags"."id" = "rwf_taggings"."tag_id"
+impl<T: Taggable> Association<T> for Tag {
+    fn association_type() -> AssociationType {
+        AssociationType::HasMany
+    }
+
+    fn construct_join() -> Join {
+        Join::new(
+            JoinKind::Inner,
+            Self::table_name(),
+            Column::new(Self::table_name(), Self::primary_key()),
+            Column::new(Tagging::table_name(), Self::foreign_key()),
+        )
+    }
+}
+
+/// Number of records tagged with a tag. Returned by [`Taggable::tag_cloud`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct TagCount {
+    /// Tag name.
+    pub name: String,
+    /// How many records are tagged with it.
+    pub count: i64,
+}
+
+impl FromRow for TagCount {
+    fn from_row(row: tokio_postgres::Row) -> Result<Self, Error> {
+        Ok(Self {
+            name: row.try_get("name")?,
+            count: row.try_get("count")?,
+        })
+    }
+}
+
+// Read-only: only used to fetch the results of `Taggable::tag_cloud`.
+impl Model for TagCount {
+    fn table_name() -> &'static str {
+        "rwf_tags"
+    }
+
+    fn foreign_key() -> &'static str {
+        "tag_id"
+    }
+
+    fn id(&self) -> Value {
+        Value::Null
+    }
+
+    fn column_names() -> &'static [&'static str] {
+        &["name", "count"]
+    }
+
+    fn values(&self) -> Vec<Value> {
+        vec![self.name.to_value(), self.count.to_value()]
+    }
+}
+
+/// Model that can be tagged.
+///
+/// All methods return queries which need to be executed, like any other model query.
+pub trait Taggable: Model {
+    /// Identifies the model in the `rwf_taggings` table. Defaults to the table name.
+    fn taggable_type() -> &'static str {
+        Self::table_name()
+    }
+
+    /// Records tagged with the given tag.
+    fn tagged_with(tag: &str) -> Scope<Self> {
+        Self::all()
+            .join::<Tagging>()
+            .join::<Tag>()
+            .filter(
+                Column::new(Tagging::table_name(), "taggable_type"),
+                Self::taggable_type(),
+            )
+            .filter(Column::new(Tag::table_name(), "name"), normalize(tag))
+    }
+
+    /// Tags attached to the record, ordered by name.
+    fn tags(&self) -> Query<Tag> {
+        Tag::find_by_sql(
+            r#"SELECT "rwf_tags".* FROM "rwf_tags"
+            INNER JOIN "rwf_taggings" ON "rwf_taggings"."tag_id" = "rwf_tags"."id"
+            WHERE "rwf_taggings"."taggable_type" = $1 AND "rwf_taggings"."taggable_id" = $2
+            ORDER BY "rwf_tags"."name""#,
+            &[Self::taggable_type().to_value(), self.id()],
+        )
+    }
+
+    /// Attach the tags to the record. Tags that don't exist yet are created.
+    fn add_tags(&self, tags: &[impl ToString]) -> Query<Tagging> {
+        Tagging::find_by_sql(
+            r#"WITH tags AS (
+                INSERT INTO "rwf_tags" ("name") SELECT unnest($3::VARCHAR[])
+                ON CONFLICT ("name") DO UPDATE SET "name" = EXCLUDED."name"
+                RETURNING "id"
+            )
+            INSERT INTO "rwf_taggings" ("tag_id", "taggable_type", "taggable_id")
+            SELECT "id", $1, $2 FROM tags
+            ON CONFLICT DO NOTHING
+            RETURNING *"#,
+            &[
+                Self::taggable_type().to_value(),
+                self.id(),
+                normalize_all(tags).as_slice().to_value(),
+            ],
+        )
+    }
+
+    /// Remove the tags from the record.
+    fn remove_tags(&self, tags: &[impl ToString]) -> Query<Tagging> {
+        Tagging::find_by_sql(
+            r#"DELETE FROM "rwf_taggings"
+            WHERE "taggable_type" = $1 AND "taggable_id" = $2
+            AND "tag_id" IN (SELECT "id" FROM "rwf_tags" WHERE "name" = ANY($3::VARCHAR[]))
+            RETURNING *"#,
+            &[
+                Self::taggable_type().to_value(),
+                self.id(),
+                normalize_all(tags).as_slice().to_value(),
+            ],
+        )
+    }
+
+    /// Replace all tags on the record with the given tags.
+    fn set_tags(&self, tags: &[impl ToString]) -> Query<Tagging> {
+        Tagging::find_by_sql(
+            r#"WITH tags AS (
+                INSERT INTO "rwf_tags" ("name") SELECT unnest($3::VARCHAR[])
+                ON CONFLICT ("name") DO UPDATE SET "name" = EXCLUDED."name"
+                RETURNING "id"
+            ), removed AS (
+                DELETE FROM "rwf_taggings"
+                WHERE "taggable_type" = $1 AND "taggable_id" = $2
+                AND "tag_id" NOT IN (SELECT "id" FROM tags)
+            )
+            INSERT INTO "rwf_taggings" ("tag_id", "taggable_type", "taggable_id")
+            SELECT "id", $1, $2 FROM tags
+            ON CONFLICT DO NOTHING
+            RETURNING *"#,
+            &[
+                Self::taggable_type().to_value(),
+                self.id(),
+                normalize_all(tags).as_slice().to_value(),
+            ],
+        )
+    }
+
+    /// Replace all tags on the record with tags from a comma-separated list,
+    /// e.g. `"rust, web frameworks"`, usually submitted with a form.
+    fn set_tag_list(&self, tag_list: &str) -> Query<Tagging> {
+        self.set_tags(&parse_tag_list(tag_list))
+    }
+
+    /// Tags used by records of this model, with the number of records using each tag, ordered by name.
+    fn tag_cloud() -> Query<TagCount> {
+        Query::Raw {
+            query: r#"SELECT "rwf_tags"."name", COUNT(*) AS "count" FROM "rwf_tags"
+            INNER JOIN "rwf_taggings" ON "rwf_taggings"."tag_id" = "rwf_tags"."id"
+            WHERE "rwf_taggings"."taggable_type" = $1
+            GROUP BY "rwf_tags"."name"
+            ORDER BY "rwf_tags"."name""#
+                .to_string(),
+            placeholders: vec![Self::taggable_type().to_value()].into(),
+        }
+    }
+}
+
+/// Normalize a tag name: trim whitespace, collapse inner whitespace and lowercase it.
+///
+/// # Example
+///
+/// ```
+/// # use rwf::model::taggable::normalize;
+/// assert_eq!(normalize("  Web   Frameworks "), "web frameworks");
+/// ```
+pub fn normalize(tag: &str) -> String {
+    tag.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Parse a comma-separated list of tags. Tags are normalized, and empty and duplicate tags are removed.
+pub fn parse_tag_list(tag_list: &str) -> Vec<String> {
+    normalize_all(&tag_list.split(",").collect::<Vec<_>>())
+}
+
+/// Join tags into a comma-separated list, e.g. to pre-fill a form field.
+pub fn to_tag_list(tags: &[Tag]) -> String {
+    tags.iter()
+        .map(|tag| tag.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Weight of a tag in a tag cloud, from `1` (least used) to `sizes` (most used),
+/// scaled linearly between the least and most used tags.
+pub fn weight(count: i64, min: i64, max: i64, sizes: i64) -> i64 {
+    let sizes = std::cmp::max(1, sizes);
+
+    if max <= min {
+        sizes
+    } else {
+        1 + (count - min) * (sizes - 1) / (max - min)
+    }
+}
+
+fn normalize_all(tags: &[impl ToString]) -> Vec<String> {
+    let mut result: Vec<String> = vec![];
+
+    for tag in tags {
+        let tag = normalize(&tag.to_string());
+        if !tag.is_empty() && !result.contains(&tag) {
+            result.push(tag);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{test::bootstrap, Pool, ToSql};
+
+    #[derive(Clone, Debug)]
+    struct Article {
+        id: Option<i64>,
+        title: String,
+    }
+
+    impl FromRow for Article {
+        fn from_row(row: tokio_postgres::Row) -> Result<Self, Error> {
+            Ok(Self {
+                id: row.try_get("id")?,
+                title: row.try_get("title")?,
+            })
+        }
+    }
+
+    impl Model for Article {
+        fn id(&self) -> Value {
+            self.id.to_value()
+        }
+
+        fn table_name() -> &'static str {
+            "rwf_test_articles"
+        }
+
+        fn foreign_key() -> &'static str {
+            "article_id"
+        }
+
+        fn column_names() -> &'static [&'static str] {
+            &["title"]
+        }
+
+        fn values(&self) -> Vec<Value> {
+            vec![self.title.to_value()]
+        }
+    }
+
+    impl Taggable for Article {}
+
+    #[test]
+    fn test_tag_list() {
+        assert_eq!(
+            parse_tag_list(" Rust,web  Frameworks,, rust ,"),
+            vec!["rust".to_string(), "web frameworks".to_string()]
+        );
+        assert_eq!(
+            to_tag_list(&[Tag::new("Rust"), Tag::new("web")]),
+            "rust, web"
+        );
+        assert_eq!(weight(1, 1, 10, 5), 1);
+        assert_eq!(weight(10, 1, 10, 5), 5);
+        assert_eq!(weight(5, 1, 10, 5), 2);
+        assert_eq!(weight(3, 3, 3, 5), 5);
+    }
+
+    #[test]
+    fn test_tagged_with() {
+        assert_eq!(
+            Article::tagged_with("Rust").to_sql(),
+            r#"SELECT "rwf_test_articles".* FROM "rwf_test_articles" INNER JOIN "rwf_taggings" ON "rwf_taggings"."taggable_id" = "rwf_test_articles"."id" INNER JOIN "rwf_tags" ON "rwf_tags"."id" = "rwf_taggings"."tag_id" WHERE "rwf_taggings"."taggable_type" = $1 AND "rwf_tags"."name" = $2"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_taggable() -> Result<(), Error> {
+        let mut conn = Pool::begin().await?;
+
+        bootstrap(conn.client()).await?;
+
+        conn.client()
+            .execute(
+                "CREATE TABLE rwf_test_articles (id BIGSERIAL PRIMARY KEY, title VARCHAR NOT NULL)",
+                &[],
+            )
+            .await?;
+
+        let article = Article {
+            id: None,
+            title: "Hello".into(),
+        }
+        .save()
+        .fetch(&mut conn)
+        .await?;
+        let other = Article {
+            id: None,
+            title: "World".into(),
+        }
+        .save()
+        .fetch(&mut conn)
+        .await?;
+
+        article.set_tag_list("Rust, web").execute(&mut conn).await?;
+        other.add_tags(&["rust"]).execute(&mut conn).await?;
+
+        let tags = article.tags().fetch_all(&mut conn).await?;
+        assert_eq!(to_tag_list(&tags), "rust, web");
+
+        article.set_tags(&["web", "sql"]).execute(&mut conn).await?;
+        let tags = article.tags().fetch_all(&mut conn).await?;
+        assert_eq!(to_tag_list(&tags), "sql, web");
+
+        article.remove_tags(&["SQL"]).execute(&mut conn).await?;
+        let tags = article.tags().fetch_all(&mut conn).await?;
+        assert_eq!(to_tag_list(&tags), "web");
+
+        let tagged = Article::tagged_with("rust").fetch_all(&mut conn).await?;
+        assert_eq!(tagged.len(), 1);
+        assert_eq!(tagged[0].title, "World");
+
+        let cloud = Article::tag_cloud().fetch_all(&mut conn).await?;
+        assert_eq!(
+            cloud,
+            vec![
+                TagCount {
+                    name: "rust".into(),
+                    count: 1
+                },
+                TagCount {
+                    name: "web".into(),
+                    count: 1
+                },
+            ]
+        );
+
+        conn.rollback().await
+    }
+}
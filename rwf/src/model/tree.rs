@@ -0,0 +1,445 @@
+//! Hierarchical data, e.g. categories or threaded comments.
+//!
+//! Records are organized in a tree using a materialized path: each record stores the id of its parent
+//! in the `parent_id` column, and the ids of all its ancestors, root first, in the `path` column, e.g. `/1/4/`.
+//! Root records have no parent and their path is `/`. Storing the path allows to fetch all ancestors
+//! or descendants of a record with a single query.
+//!
+//! Implement [`Tree`], or derive it with `macros::Tree`. The path is maintained by [`Tree::set_parent`],
+//! [`Tree::move_to`] and the delete methods; it shouldn't be modified directly.
+//!
+//! # Example
+//!
+//! ```ignore
+//! #[derive(Clone, macros::Model, macros::Tree)]
+//! struct Category {
+//!     id: Option<i64>,
+//!     name: String,
+//!     parent_id: Option<i64>,
+//!     path: String,
+//! }
+//!
+//! let mut books = Category::new("Books");
+//! books.set_parent(Some(&shop));
+//! let books = books.save().fetch(&mut conn).await?;
+//!
+//! let children = shop.children().fetch_all(&mut conn).await?;
+//! let breadcrumbs = books.ancestors().fetch_all(&mut conn).await?;
+//! ```
+//!
+//! For large tables, index the path column with `CREATE INDEX ON categories (path text_pattern_ops)`,
+//! so fetching descendants doesn't scan the whole table.
+use super::{Column, Error, Model, Query, Scope, ToValue, Value};
+
+/// Path of root records.
+pub const ROOT: &str = "/";
+
+/// Model organized in a tree.
+///
+/// Query methods return queries which need to be executed, like any other model query.
+/// Only models with an integer primary key are supported.
+pub trait Tree: Model {
+    /// Id of the parent record. `None` for roots.
+    fn parent_id(&self) -> Option<i64>;
+
+    /// Set the id of the parent record.
+    fn set_parent_id(&mut self, parent_id: Option<i64>);
+
+    /// Ids of the ancestors of the record, e.g. `/1/4/`.
+    fn path(&self) -> &str;
+
+    /// Set the ids of the ancestors of the record.
+    fn set_path(&mut self, path: String);
+
+    /// Name of the column storing the parent id.
+    fn parent_id_column() -> &'static str {
+        "parent_id"
+    }
+
+    /// Name of the column storing the path.
+    fn path_column() -> &'static str {
+        "path"
+    }
+
+    /// The record has no parent.
+    fn is_root(&self) -> bool {
+        self.parent_id().is_none()
+    }
+
+    /// Ids of the ancestors of the record, root first.
+    fn ancestor_ids(&self) -> Vec<i64> {
+        parse_path(self.path())
+    }
+
+    /// How deep in the tree the record is. Roots have a depth of `0`.
+    fn depth(&self) -> usize {
+        self.ancestor_ids().len()
+    }
+
+    /// Path of the children of this record, e.g. `/1/4/9/` if the record id is `9`.
+    /// Returns `None` if the record hasn't been saved yet.
+    fn child_path(&self) -> Option<String> {
+        integer_id(self.id()).map(|id| format!("{}{}/", self.path(), id))
+    }
+
+    /// Place the record under another record, or make it a root if the parent is `None`.
+    ///
+    /// Only changes the record, which then needs to be saved. To move a saved record
+    /// together with its descendants, use [`Tree::move_to`].
+    fn set_parent(&mut self, parent: Option<&Self>) {
+        match parent.and_then(|parent| integer_id(parent.id()).zip(parent.child_path())) {
+            Some((parent_id, path)) => {
+                self.set_parent_id(Some(parent_id));
+                self.set_path(path);
+            }
+
+            None => {
+                self.set_parent_id(None);
+                self.set_path(ROOT.to_string());
+            }
+        }
+    }
+
+    /// Records without a parent.
+    fn roots() -> Scope<Self> {
+        Self::filter(Self::parent_id_column(), Value::Null)
+    }
+
+    /// Direct children of the record.
+    fn children(&self) -> Scope<Self> {
+        Self::filter(Self::parent_id_column(), self.id())
+    }
+
+    /// Records with the same parent, excluding this record.
+    fn siblings(&self) -> Scope<Self> {
+        Self::filter(Self::parent_id_column(), self.parent_id().to_value())
+            .not(Self::primary_key(), self.id())
+    }
+
+    /// Parent of the record. Returns no rows for roots.
+    fn parent(&self) -> Scope<Self> {
+        Self::filter(Self::primary_key(), self.parent_id().to_value())
+    }
+
+    /// All ancestors of the record, root first.
+    fn ancestors(&self) -> Scope<Self> {
+        Self::filter(Self::primary_key(), self.ancestor_ids().as_slice())
+            .order((Column::new(Self::table_name(), Self::path_column()), "ASC"))
+    }
+
+    /// All descendants of the record, ordered by depth.
+    fn descendants(&self) -> Query<Self> {
+        Self::find_by_sql(
+            format!(
+                r#"SELECT * FROM "{}" WHERE "{}" LIKE $1 ORDER BY length("{}"), "{}""#,
+                Self::table_name(),
+                Self::path_column(),
+                Self::path_column(),
+                Self::primary_key(),
+            ),
+            &[descendants_pattern(self).to_value()],
+        )
+    }
+
+    /// Move the record, together with all its descendants, under another record,
+    /// or make it a root if the parent is `None`. Returns all updated records.
+    ///
+    /// Returns an error if the new parent is the record itself or one of its descendants,
+    /// or if the records haven't been saved yet.
+    fn move_to(&self, parent: Option<&Self>) -> Result<Query<Self>, Error> {
+        let id = integer_id(self.id()).ok_or(Error::RecordNotFound)?;
+
+        let (parent_id, new_path) = match parent {
+            Some(parent) => {
+                let parent_id = integer_id(parent.id()).ok_or(Error::RecordNotFound)?;
+                if parent_id == id || parent.ancestor_ids().contains(&id) {
+                    return Err(Error::ValueError(
+                        "parent",
+                        "a record can't be moved under itself or its descendants".into(),
+                    ));
+                }
+                (Some(parent_id), parent.child_path().unwrap_or_default())
+            }
+
+            None => (None, ROOT.to_string()),
+        };
+
+        // Replace the old path prefix with the new one in the record and all its descendants.
+        Ok(Self::find_by_sql(
+            format!(
+                r#"UPDATE "{table}" SET
+                "{path}" = $2::VARCHAR || substr("{path}", length($1::VARCHAR) + 1),
+                "{parent_id}" = CASE WHEN "{id}" = $3 THEN $4 ELSE "{parent_id}" END
+                WHERE "{id}" = $3 OR "{path}" LIKE $5
+                RETURNING *"#,
+                table = Self::table_name(),
+                path = Self::path_column(),
+                parent_id = Self::parent_id_column(),
+                id = Self::primary_key(),
+            ),
+            &[
+                self.path().to_value(),
+                new_path.to_value(),
+                id.to_value(),
+                parent_id.to_value(),
+                format!("{}{}/%", self.path(), id).to_value(),
+            ],
+        ))
+    }
+
+    /// Delete the record and all its descendants. Returns the deleted records.
+    fn delete_subtree(&self) -> Query<Self> {
+        Self::find_by_sql(
+            format!(
+                r#"DELETE FROM "{}" WHERE "{}" = $1 OR "{}" LIKE $2 RETURNING *"#,
+                Self::table_name(),
+                Self::primary_key(),
+                Self::path_column(),
+            ),
+            &[self.id(), descendants_pattern(self).to_value()],
+        )
+    }
+
+    /// Delete the record and move its children to its parent, so its descendants
+    /// stay in the tree. Returns the deleted record.
+    fn delete_and_reparent(&self) -> Query<Self> {
+        let child_path = self.child_path().unwrap_or_default();
+
+        Self::find_by_sql(
+            format!(
+                r#"WITH moved AS (
+                    UPDATE "{table}" SET
+                    "{path}" = $2::VARCHAR || substr("{path}", length($3::VARCHAR) + 1),
+                    "{parent_id}" = CASE WHEN "{parent_id}" = $1 THEN $4 ELSE "{parent_id}" END
+                    WHERE "{path}" LIKE $5
+                )
+                DELETE FROM "{table}" WHERE "{id}" = $1 RETURNING *"#,
+                table = Self::table_name(),
+                path = Self::path_column(),
+                parent_id = Self::parent_id_column(),
+                id = Self::primary_key(),
+            ),
+            &[
+                self.id(),
+                self.path().to_value(),
+                child_path.to_value(),
+                self.parent_id().to_value(),
+                descendants_pattern(self).to_value(),
+            ],
+        )
+    }
+}
+
+/// Parse ancestor ids from a path, e.g. `/1/4/` becomes `[1, 4]`.
+///
+/// # Example
+///
+/// ```
+/// # use rwf::model::tree::parse_path;
+/// assert_eq!(parse_path("/1/4/"), vec![1, 4]);
+/// assert!(parse_path("/").is_empty());
+/// ```
+pub fn parse_path(path: &str) -> Vec<i64> {
+    path.split("/")
+        .filter_map(|id| id.trim().parse().ok())
+        .collect()
+}
+
+/// LIKE pattern matching the paths of all descendants of the record.
+/// Matches nothing if the record hasn't been saved yet.
+fn descendants_pattern(record: &impl Tree) -> String {
+    record
+        .child_path()
+        .map(|path| format!("{}%", path))
+        .unwrap_or_default()
+}
+
+fn integer_id(value: Value) -> Option<i64> {
+    match value {
+        Value::Integer(id) | Value::BigInt(id) => Some(id),
+        Value::Optional(id) => (*id).and_then(integer_id),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{FromRow, Pool, ToSql};
+
+    #[derive(Clone, Debug)]
+    struct Category {
+        id: Option<i64>,
+        name: String,
+        parent_id: Option<i64>,
+        path: String,
+    }
+
+    impl Category {
+        fn new(name: &str, parent: Option<&Category>) -> Self {
+            let mut category = Category {
+                id: None,
+                name: name.into(),
+                parent_id: None,
+                path: ROOT.into(),
+            };
+            category.set_parent(parent);
+            category
+        }
+    }
+
+    impl FromRow for Category {
+        fn from_row(row: tokio_postgres::Row) -> Result<Self, Error> {
+            Ok(Self {
+                id: row.try_get("id")?,
+                name: row.try_get("name")?,
+                parent_id: row.try_get("parent_id")?,
+                path: row.try_get("path")?,
+            })
+        }
+    }
+
+    impl Model for Category {
+        fn id(&self) -> Value {
+            self.id.to_value()
+        }
+
+        fn table_name() -> &'static str {
+            "rwf_test_categories"
+        }
+
+        fn foreign_key() -> &'static str {
+            "category_id"
+        }
+
+        fn column_names() -> &'static [&'static str] {
+            &["name", "parent_id", "path"]
+        }
+
+        fn values(&self) -> Vec<Value> {
+            vec![
+                self.name.to_value(),
+                self.parent_id.to_value(),
+                self.path.to_value(),
+            ]
+        }
+    }
+
+    impl Tree for Category {
+        fn parent_id(&self) -> Option<i64> {
+            self.parent_id
+        }
+
+        fn set_parent_id(&mut self, parent_id: Option<i64>) {
+            self.parent_id = parent_id;
+        }
+
+        fn path(&self) -> &str {
+            &self.path
+        }
+
+        fn set_path(&mut self, path: String) {
+            self.path = path;
+        }
+    }
+
+    fn names(categories: &[Category]) -> Vec<&str> {
+        categories.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_tree() {
+        let mut root = Category::new("root", None);
+        root.id = Some(1);
+        let mut child = Category::new("child", Some(&root));
+        child.id = Some(4);
+
+        assert!(root.is_root());
+        assert_eq!(child.path, "/1/");
+        assert_eq!(child.parent_id, Some(1));
+        assert_eq!(child.child_path(), Some("/1/4/".into()));
+        assert_eq!(child.depth(), 1);
+
+        assert_eq!(
+            child.children().to_sql(),
+            r#"SELECT * FROM "rwf_test_categories" WHERE "rwf_test_categories"."parent_id" = $1"#
+        );
+        assert_eq!(
+            child.ancestors().to_sql(),
+            r#"SELECT * FROM "rwf_test_categories" WHERE "rwf_test_categories"."id" = ANY($1) ORDER BY "rwf_test_categories"."path" ASC"#
+        );
+        assert_eq!(
+            Category::roots().to_sql(),
+            r#"SELECT * FROM "rwf_test_categories" WHERE "rwf_test_categories"."parent_id" IS NULL"#
+        );
+
+        assert!(root.move_to(Some(&child)).is_err());
+        assert!(child.move_to(Some(&child)).is_err());
+        assert!(child.move_to(None).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_tree_maintenance() -> Result<(), Error> {
+        let mut conn = Pool::begin().await?;
+
+        conn.client()
+            .execute(
+                "CREATE TABLE rwf_test_categories (
+                    id BIGSERIAL PRIMARY KEY,
+                    name VARCHAR NOT NULL,
+                    parent_id BIGINT,
+                    path VARCHAR NOT NULL DEFAULT '/'
+                )",
+                &[],
+            )
+            .await?;
+
+        let books = Category::new("books", None).save().fetch(&mut conn).await?;
+        let music = Category::new("music", None).save().fetch(&mut conn).await?;
+        let fiction = Category::new("fiction", Some(&books))
+            .save()
+            .fetch(&mut conn)
+            .await?;
+        let fantasy = Category::new("fantasy", Some(&fiction))
+            .save()
+            .fetch(&mut conn)
+            .await?;
+        Category::new("history", Some(&books))
+            .save()
+            .execute(&mut conn)
+            .await?;
+
+        let descendants = books.descendants().fetch_all(&mut conn).await?;
+        assert_eq!(names(&descendants), vec!["fiction", "history", "fantasy"]);
+
+        let ancestors = fantasy.ancestors().fetch_all(&mut conn).await?;
+        assert_eq!(names(&ancestors), vec!["books", "fiction"]);
+
+        let siblings = fiction.siblings().fetch_all(&mut conn).await?;
+        assert_eq!(names(&siblings), vec!["history"]);
+
+        let moved = fiction.move_to(Some(&music))?.fetch_all(&mut conn).await?;
+        assert_eq!(moved.len(), 2);
+
+        let fantasy = Category::find(fantasy.id).fetch(&mut conn).await?;
+        assert_eq!(
+            fantasy.ancestor_ids(),
+            vec![music.id.unwrap(), fiction.id.unwrap()]
+        );
+        let children = music.children().fetch_all(&mut conn).await?;
+        assert_eq!(names(&children), vec!["fiction"]);
+
+        let fiction = Category::find(fiction.id).fetch(&mut conn).await?;
+        fiction.delete_and_reparent().execute(&mut conn).await?;
+        let fantasy = Category::find(fantasy.id).fetch(&mut conn).await?;
+        assert_eq!(fantasy.parent_id, music.id);
+        assert_eq!(fantasy.path, format!("/{}/", music.id.unwrap()));
+
+        let deleted = books.delete_subtree().fetch_all(&mut conn).await?;
+        assert_eq!(deleted.len(), 2);
+        let roots = Category::roots().fetch_all(&mut conn).await?;
+        assert_eq!(names(&roots), vec!["music"]);
+
+        conn.rollback().await
+    }
+}
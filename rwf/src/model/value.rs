@@ -39,6 +39,8 @@ pub enum Value {
     IpAddr(IpAddr),
     /// `UUID`
     Uuid(Uuid),
+    /// `BYTEA`, binary data, e.g. `'\xdeadbeef'`.
+    Bytes(Vec<u8>),
     /// List (Postgres array) of values, e.g. `{1, 2, 3}`.
     List(Vec<Value>),
     /// Tuple (also known as "record") of values, e.g. `(1, 2, 3)`.
@@ -230,6 +232,24 @@ impl ToValue for Option<Uuid> {
     }
 }
 
+impl ToValue for Vec<u8> {
+    fn to_value(&self) -> Value {
+        Value::Bytes(self.clone())
+    }
+}
+
+impl ToValue for &[u8] {
+    fn to_value(&self) -> Value {
+        Value::Bytes(self.to_vec())
+    }
+}
+
+impl ToValue for Option<Vec<u8>> {
+    fn to_value(&self) -> Value {
+        Value::Optional(Box::new(self.as_ref().map(|v| v.to_value())))
+    }
+}
+
 impl ToValue for Value {
     fn to_value(&self) -> Value {
         self.clone()
@@ -242,6 +262,12 @@ impl ToValue for &[&str] {
     }
 }
 
+impl ToValue for &[String] {
+    fn to_value(&self) -> Value {
+        Value::List(self.iter().map(|v| v.to_value()).collect::<Vec<_>>())
+    }
+}
+
 impl ToValue for &[i64] {
     fn to_value(&self) -> Value {
         Value::List(self.iter().map(|v| v.to_value()).collect::<Vec<_>>())
@@ -373,6 +399,7 @@ impl tokio_postgres::types::ToSql for Value {
             Value::Timestamp(timestamp) => timestamp.to_sql(ty, out),
             Value::IpAddr(ip) => ip.to_sql(ty, out),
             Value::Uuid(uuid) => uuid.to_sql(ty, out),
+            Value::Bytes(bytes) => bytes.to_sql(ty, out),
             Value::List(values) => values.to_sql(ty, out),
             Value::Json(json) => json.to_sql(ty, out),
             Value::Optional(value) => {
@@ -413,8 +440,9 @@ impl<'a> tokio_postgres::types::FromSql<'a> for Value {
             &Type::TIMESTAMPTZ => Ok(Value::TimestampT(OffsetDateTime::from_sql(ty, raw)?)),
             &Type::TIMESTAMP => Ok(Value::Timestamp(PrimitiveDateTime::from_sql(ty, raw)?)),
             &Type::UUID => Ok(Value::Uuid(Uuid::from_sql(ty, raw)?)),
+            &Type::BYTEA => Ok(Value::Bytes(Vec::<u8>::from_sql(ty, raw)?)),
 
-            ty => todo!("unimplemented conversion from {:?} to rust", ty),
+            ty => Err(format!("conversion from \"{}\" to rust isn't supported", ty).into()),
         }
     }
 
@@ -443,6 +471,13 @@ impl ToSql for Value {
             Real(float) => float.to_string(),
             IpAddr(ip) => ip.to_string(),
             Uuid(uuid) => uuid.to_string(),
+            Bytes(bytes) => format!(
+                "'\\x{}'::bytea",
+                bytes
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect::<std::string::String>()
+            ),
             Placeholder(number) => format!("${}", number),
             Range((a, b)) => format!("BETWEEN {} AND {}", a.to_sql(), b.to_sql()),
             List(values) => format!(
@@ -516,6 +551,10 @@ impl From<Value> for serde_json::Value {
             Value::Json(json) => json,
             Value::IpAddr(ip) => serde_json::Value::String(ip.to_string()),
             Value::Uuid(uuid) => serde_json::Value::String(uuid.to_string()),
+            Value::Bytes(bytes) => {
+                use base64::{engine::general_purpose, Engine as _};
+                serde_json::Value::String(general_purpose::STANDARD.encode(bytes))
+            }
             Value::Optional(value) => match *value {
                 Some(value) => value.into(),
                 None => serde_json::Value::Null,
@@ -556,6 +595,13 @@ mod test {
         assert_eq!(value.to_sql(), "BETWEEN 1 AND 25");
     }
 
+    #[test]
+    fn test_bytes() {
+        let value = vec![0xde_u8, 0xad, 0x01].to_value();
+        assert_eq!(value.to_sql(), r"'\xdead01'::bytea");
+        assert_eq!(serde_json::Value::from(value), "3q0B");
+    }
+
     #[test]
     fn test_function_args() {
         let value = Value::Function(("lower".into(), vec!["my string".to_value()]));
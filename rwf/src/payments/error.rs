@@ -0,0 +1,39 @@
+//! Errors returned by the payments integration.
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    /// A required setting is missing, e.g. the API key.
+    #[error("payments are not configured: {0} is not set")]
+    NotConfigured(&'static str),
+
+    /// The payment provider returned an error.
+    #[error("stripe error ({0}): {1}")]
+    Provider(u16, String),
+
+    /// The webhook signature is missing or doesn't match the payload.
+    #[error("webhook signature is invalid")]
+    InvalidSignature,
+
+    /// The webhook was signed too long ago and could be replayed.
+    #[error("webhook timestamp is outside the tolerance")]
+    ExpiredSignature,
+
+    /// Couldn't reach the payment provider.
+    #[error("{0}")]
+    Http(#[from] crate::http::client::Error),
+
+    /// Error encoding/decoding JSON.
+    #[error("json: {0}")]
+    Json(#[from] serde_json::Error),
+
+    /// The ORM returned an error.
+    #[error("payments database error: {0}")]
+    DatabaseError(#[from] crate::model::Error),
+}
+
+impl From<tokio_postgres::Error> for Error {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DatabaseError(err.into())
+    }
+}
@@ -0,0 +1,35 @@
+//! Payments with Stripe: checkout sessions, webhooks, customers and subscriptions.
+//!
+//! Send customers to a checkout page hosted by Stripe from any controller:
+//!
+//! ```ignore
+//! use rwf::payments::Checkout;
+//!
+//! Checkout::subscription("price_a1b2c3")
+//!     .client_reference_id(user_id)
+//!     .success_url("https://example.com/billing")
+//!     .cancel_url("https://example.com/pricing")
+//!     .redirect()
+//!     .await?
+//! ```
+//!
+//! Stripe then notifies the application about payments and subscription changes with
+//! webhooks, received by the [`StripeWebhook`] controller. It keeps the [`Customer`] and
+//! [`Subscription`] models up to date, so checking if a user has paid is a database query:
+//!
+//! ```ignore
+//! let subscriptions = Subscription::active_for_user(user_id)
+//!     .fetch_all(&mut conn)
+//!     .await?;
+//! ```
+//!
+//! The API keys are configured in the `[payments]` section of `rwf.toml`.
+pub mod error;
+pub mod models;
+pub mod stripe;
+pub mod webhook;
+
+pub use error::Error;
+pub use models::{Customer, Subscription};
+pub use stripe::{Checkout, CheckoutSession};
+pub use webhook::{Event, EventKind, StripeWebhook, WebhookHandler};
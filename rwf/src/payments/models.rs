@@ -0,0 +1,231 @@
+//! Customers and subscriptions, kept in sync with the payment provider by the webhook.
+use time::OffsetDateTime;
+
+use crate::model::{Error, FromRow, Model, Query, ToValue, Value};
+
+/// Customer of the payment provider.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Customer {
+    id: Option<i64>,
+    /// ID of the user in your application, passed to
+    /// [`Checkout::client_reference_id`](super::Checkout::client_reference_id).
+    pub user_id: Option<i64>,
+    /// Customer ID in Stripe.
+    pub stripe_id: String,
+    /// Customer email.
+    pub email: Option<String>,
+    /// When the customer was created.
+    pub created_at: OffsetDateTime,
+}
+
+impl FromRow for Customer {
+    fn from_row(row: tokio_postgres::Row) -> Result<Self, Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            stripe_id: row.try_get("stripe_id")?,
+            email: row.try_get("email")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl Model for Customer {
+    fn table_name() -> &'static str {
+        "rwf_customers"
+    }
+
+    fn foreign_key() -> &'static str {
+        "customer_id"
+    }
+
+    fn id(&self) -> Value {
+        self.id.to_value()
+    }
+
+    fn column_names() -> &'static [&'static str] {
+        &["user_id", "stripe_id", "email", "created_at"]
+    }
+
+    fn values(&self) -> Vec<Value> {
+        vec![
+            self.user_id.to_value(),
+            self.stripe_id.to_value(),
+            self.email.to_value(),
+            self.created_at.to_value(),
+        ]
+    }
+}
+
+impl Customer {
+    /// Customer of the user.
+    pub fn for_user(user_id: i64) -> Query<Self> {
+        Self::filter("user_id", user_id)
+    }
+
+    /// Subscriptions of the customer, including canceled ones.
+    pub fn subscriptions(&self) -> Query<Subscription> {
+        Subscription::filter("customer_id", self.id())
+    }
+
+    /// Create the customer or update the fields that are set.
+    pub(crate) fn upsert(
+        stripe_id: &str,
+        email: Option<&str>,
+        user_id: Option<i64>,
+    ) -> Query<Self> {
+        Self::find_by_sql(
+            "INSERT INTO rwf_customers (stripe_id, email, user_id)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (stripe_id) DO UPDATE
+            SET email = COALESCE(EXCLUDED.email, rwf_customers.email),
+                user_id = COALESCE(EXCLUDED.user_id, rwf_customers.user_id)
+            RETURNING *",
+            &[
+                stripe_id.to_value(),
+                email.map(String::from).to_value(),
+                user_id.to_value(),
+            ],
+        )
+    }
+}
+
+/// Subscription to a recurring price.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Subscription {
+    id: Option<i64>,
+    /// The subscribed customer.
+    pub customer_id: i64,
+    /// Subscription ID in Stripe.
+    pub stripe_id: String,
+    /// `trialing`, `active`, `past_due`, `canceled`, etc.
+    pub status: String,
+    /// ID of the subscribed price.
+    pub price_id: Option<String>,
+    /// Number of units, e.g. seats.
+    pub quantity: i64,
+    /// End of the billing period.
+    pub current_period_end: Option<OffsetDateTime>,
+    /// The subscription will be canceled at the end of the billing period.
+    pub cancel_at_period_end: bool,
+    /// When the subscription was created.
+    pub created_at: OffsetDateTime,
+    /// When the event that last changed the subscription was sent.
+    pub updated_at: OffsetDateTime,
+}
+
+impl FromRow for Subscription {
+    fn from_row(row: tokio_postgres::Row) -> Result<Self, Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            customer_id: row.try_get("customer_id")?,
+            stripe_id: row.try_get("stripe_id")?,
+            status: row.try_get("status")?,
+            price_id: row.try_get("price_id")?,
+            quantity: row.try_get("quantity")?,
+            current_period_end: row.try_get("current_period_end")?,
+            cancel_at_period_end: row.try_get("cancel_at_period_end")?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+}
+
+impl Model for Subscription {
+    fn table_name() -> &'static str {
+        "rwf_subscriptions"
+    }
+
+    fn foreign_key() -> &'static str {
+        "subscription_id"
+    }
+
+    fn id(&self) -> Value {
+        self.id.to_value()
+    }
+
+    fn column_names() -> &'static [&'static str] {
+        &[
+            "customer_id",
+            "stripe_id",
+            "status",
+            "price_id",
+            "quantity",
+            "current_period_end",
+            "cancel_at_period_end",
+            "created_at",
+            "updated_at",
+        ]
+    }
+
+    fn values(&self) -> Vec<Value> {
+        vec![
+            self.customer_id.to_value(),
+            self.stripe_id.to_value(),
+            self.status.to_value(),
+            self.price_id.to_value(),
+            self.quantity.to_value(),
+            self.current_period_end.to_value(),
+            self.cancel_at_period_end.to_value(),
+            self.created_at.to_value(),
+            self.updated_at.to_value(),
+        ]
+    }
+}
+
+impl Subscription {
+    /// The customer has access to the subscribed product, i.e. the subscription
+    /// is active or in its free trial.
+    pub fn is_active(&self) -> bool {
+        matches!(self.status.as_str(), "active" | "trialing")
+    }
+
+    /// Active subscriptions of the user.
+    pub fn active_for_user(user_id: i64) -> Query<Self> {
+        Self::find_by_sql(
+            "SELECT rwf_subscriptions.* FROM rwf_subscriptions
+            INNER JOIN rwf_customers ON rwf_customers.id = rwf_subscriptions.customer_id
+            WHERE rwf_customers.user_id = $1 AND rwf_subscriptions.status IN ('active', 'trialing')
+            ORDER BY rwf_subscriptions.id",
+            &[user_id.to_value()],
+        )
+    }
+
+    /// Save the subscription received from Stripe. Older events, which Stripe
+    /// can deliver out of order, don't overwrite newer ones.
+    pub(crate) fn upsert(
+        customer: &Customer,
+        subscription: &super::stripe::Subscription,
+        updated_at: OffsetDateTime,
+    ) -> Query<Self> {
+        let item = subscription.item();
+        let current_period_end = subscription
+            .period_end()
+            .and_then(|timestamp| OffsetDateTime::from_unix_timestamp(timestamp).ok());
+
+        Self::find_by_sql(
+            "INSERT INTO rwf_subscriptions
+                (customer_id, stripe_id, status, price_id, quantity, current_period_end, cancel_at_period_end, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (stripe_id) DO UPDATE
+            SET status = EXCLUDED.status,
+                price_id = EXCLUDED.price_id,
+                quantity = EXCLUDED.quantity,
+                current_period_end = EXCLUDED.current_period_end,
+                cancel_at_period_end = EXCLUDED.cancel_at_period_end,
+                updated_at = EXCLUDED.updated_at
+            WHERE rwf_subscriptions.updated_at <= EXCLUDED.updated_at
+            RETURNING *",
+            &[
+                customer.id.to_value(),
+                subscription.id.to_value(),
+                subscription.status.to_value(),
+                item.map(|item| item.price.id.clone()).to_value(),
+                item.and_then(|item| item.quantity).unwrap_or(1).to_value(),
+                current_period_end.to_value(),
+                subscription.cancel_at_period_end.to_value(),
+                updated_at.to_value(),
+            ],
+        )
+    }
+}
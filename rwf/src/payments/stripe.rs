@@ -0,0 +1,378 @@
+//! Stripe API objects and checkout sessions.
+//!
+//! Only the fields used by Rwf are deserialized. The full object is always
+//! available in the webhook [`Event`](super::Event) as JSON.
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value as Json;
+
+use super::Error;
+use crate::config::get_config;
+use crate::http::{client::Client, Response};
+
+/// Checkout session, created with [`Checkout`] or received in the `checkout.session.completed` event.
+#[derive(Deserialize, Debug, Clone)]
+pub struct CheckoutSession {
+    /// Session ID, e.g. `cs_test_a1b2c3`.
+    pub id: String,
+    /// URL of the checkout page. Only set while the session is open.
+    pub url: Option<String>,
+    /// `payment`, `subscription` or `setup`.
+    pub mode: String,
+    /// ID of the customer who paid.
+    pub customer: Option<String>,
+    /// Email entered by the customer.
+    pub customer_email: Option<String>,
+    /// Customer details collected during checkout.
+    pub customer_details: Option<CustomerDetails>,
+    /// ID passed to [`Checkout::client_reference_id`].
+    pub client_reference_id: Option<String>,
+    /// ID of the subscription created by the session.
+    pub subscription: Option<String>,
+    /// `paid`, `unpaid` or `no_payment_required`.
+    pub payment_status: Option<String>,
+    /// Total amount, in the smallest currency unit, e.g. cents.
+    pub amount_total: Option<i64>,
+    /// Three-letter currency code, e.g. `usd`.
+    pub currency: Option<String>,
+}
+
+impl CheckoutSession {
+    /// Email of the customer, if they entered one.
+    pub fn email(&self) -> Option<&str> {
+        self.customer_details
+            .as_ref()
+            .and_then(|details| details.email.as_deref())
+            .or(self.customer_email.as_deref())
+    }
+}
+
+/// Customer details collected during checkout.
+#[derive(Deserialize, Debug, Clone)]
+pub struct CustomerDetails {
+    /// Customer email.
+    pub email: Option<String>,
+    /// Customer name.
+    pub name: Option<String>,
+}
+
+/// Stripe customer.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Customer {
+    /// Customer ID, e.g. `cus_a1b2c3`.
+    pub id: String,
+    /// Customer email.
+    pub email: Option<String>,
+    /// Customer name.
+    pub name: Option<String>,
+}
+
+/// Stripe subscription.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Subscription {
+    /// Subscription ID, e.g. `sub_a1b2c3`.
+    pub id: String,
+    /// ID of the subscribed customer.
+    pub customer: String,
+    /// `trialing`, `active`, `past_due`, `canceled`, etc.
+    pub status: String,
+    /// The subscription will be canceled at the end of the billing period.
+    #[serde(default)]
+    pub cancel_at_period_end: bool,
+    /// End of the billing period, as a Unix timestamp. Newer API versions
+    /// set it on the subscription items instead.
+    pub current_period_end: Option<i64>,
+    /// Subscribed prices.
+    pub items: List<SubscriptionItem>,
+}
+
+impl Subscription {
+    /// The first subscribed item. Most subscriptions have only one.
+    pub fn item(&self) -> Option<&SubscriptionItem> {
+        self.items.data.first()
+    }
+
+    /// End of the billing period, as a Unix timestamp.
+    pub fn period_end(&self) -> Option<i64> {
+        self.current_period_end
+            .or_else(|| self.item().and_then(|item| item.current_period_end))
+    }
+}
+
+/// Price subscribed to.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SubscriptionItem {
+    /// Item ID.
+    pub id: String,
+    /// Subscribed price.
+    pub price: Price,
+    /// Number of units, e.g. seats.
+    pub quantity: Option<i64>,
+    /// End of the billing period, as a Unix timestamp.
+    pub current_period_end: Option<i64>,
+}
+
+/// Stripe price.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Price {
+    /// Price ID, e.g. `price_a1b2c3`.
+    pub id: String,
+    /// ID of the product.
+    pub product: Option<String>,
+}
+
+/// Stripe invoice.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Invoice {
+    /// Invoice ID, e.g. `in_a1b2c3`.
+    pub id: String,
+    /// ID of the billed customer.
+    pub customer: Option<String>,
+    /// Amount due, in the smallest currency unit.
+    pub amount_due: i64,
+    /// Amount paid, in the smallest currency unit.
+    pub amount_paid: i64,
+    /// Three-letter currency code.
+    pub currency: String,
+    /// `draft`, `open`, `paid`, `void` or `uncollectible`.
+    pub status: Option<String>,
+    /// Page where the customer can view and pay the invoice.
+    pub hosted_invoice_url: Option<String>,
+}
+
+/// List of objects.
+#[derive(Deserialize, Debug, Clone)]
+pub struct List<T> {
+    /// Objects in the list.
+    pub data: Vec<T>,
+}
+
+/// Create a Stripe Checkout session, the page hosted by Stripe where
+/// customers enter their payment details.
+///
+/// # Example
+///
+/// ```ignore
+/// let session = Checkout::subscription("price_a1b2c3")
+///     .client_reference_id(user.id)
+///     .success_url("https://example.com/billing?session={CHECKOUT_SESSION_ID}")
+///     .cancel_url("https://example.com/pricing")
+///     .create()
+///     .await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Checkout {
+    mode: &'static str,
+    items: Vec<(String, i64)>,
+    success_url: Option<String>,
+    cancel_url: Option<String>,
+    customer: Option<String>,
+    customer_email: Option<String>,
+    client_reference_id: Option<String>,
+    trial_days: Option<i64>,
+    metadata: Vec<(String, String)>,
+}
+
+impl Checkout {
+    fn new(mode: &'static str, price: impl ToString) -> Self {
+        Self {
+            mode,
+            items: vec![(price.to_string(), 1)],
+            success_url: None,
+            cancel_url: None,
+            customer: None,
+            customer_email: None,
+            client_reference_id: None,
+            trial_days: None,
+            metadata: vec![],
+        }
+    }
+
+    /// Subscribe to a recurring price.
+    pub fn subscription(price: impl ToString) -> Self {
+        Self::new("subscription", price)
+    }
+
+    /// Pay once for a price.
+    pub fn payment(price: impl ToString) -> Self {
+        Self::new("payment", price)
+    }
+
+    /// Add another price to the session.
+    pub fn item(mut self, price: impl ToString, quantity: i64) -> Self {
+        self.items.push((price.to_string(), quantity));
+        self
+    }
+
+    /// Number of units of the last added price, e.g. seats. Default: 1.
+    pub fn quantity(mut self, quantity: i64) -> Self {
+        if let Some(item) = self.items.last_mut() {
+            item.1 = quantity;
+        }
+        self
+    }
+
+    /// Where the customer is sent after paying. Stripe replaces `{CHECKOUT_SESSION_ID}`
+    /// in the URL with the session ID.
+    pub fn success_url(mut self, url: impl ToString) -> Self {
+        self.success_url = Some(url.to_string());
+        self
+    }
+
+    /// Where the customer is sent if they go back without paying.
+    pub fn cancel_url(mut self, url: impl ToString) -> Self {
+        self.cancel_url = Some(url.to_string());
+        self
+    }
+
+    /// Existing customer paying, e.g. when they subscribe again.
+    pub fn customer(mut self, customer: impl ToString) -> Self {
+        self.customer = Some(customer.to_string());
+        self
+    }
+
+    /// Prefill the email of a new customer.
+    pub fn customer_email(mut self, email: impl ToString) -> Self {
+        self.customer_email = Some(email.to_string());
+        self
+    }
+
+    /// Reference to the paying user in your application, usually their ID. It's
+    /// saved on the [`Customer`](super::Customer) when checkout is completed.
+    pub fn client_reference_id(mut self, id: impl ToString) -> Self {
+        self.client_reference_id = Some(id.to_string());
+        self
+    }
+
+    /// Free trial before the first payment, in days.
+    pub fn trial_days(mut self, days: i64) -> Self {
+        self.trial_days = Some(days);
+        self
+    }
+
+    /// Add metadata to the session.
+    pub fn metadata(mut self, key: impl ToString, value: impl ToString) -> Self {
+        self.metadata.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    fn params(&self) -> Vec<(String, String)> {
+        let mut params = vec![("mode".to_string(), self.mode.to_string())];
+
+        for (i, (price, quantity)) in self.items.iter().enumerate() {
+            params.push((format!("line_items[{}][price]", i), price.clone()));
+            params.push((format!("line_items[{}][quantity]", i), quantity.to_string()));
+        }
+
+        let optional = [
+            ("success_url", &self.success_url),
+            ("cancel_url", &self.cancel_url),
+            ("customer", &self.customer),
+            ("customer_email", &self.customer_email),
+            ("client_reference_id", &self.client_reference_id),
+        ];
+
+        for (name, value) in optional {
+            if let Some(value) = value {
+                params.push((name.to_string(), value.clone()));
+            }
+        }
+
+        if let Some(days) = self.trial_days {
+            params.push((
+                "subscription_data[trial_period_days]".into(),
+                days.to_string(),
+            ));
+        }
+
+        for (key, value) in &self.metadata {
+            params.push((format!("metadata[{}]", key), value.clone()));
+        }
+
+        params
+    }
+
+    /// Create the checkout session.
+    pub async fn create(self) -> Result<CheckoutSession, Error> {
+        post("/v1/checkout/sessions", &self.params()).await
+    }
+
+    /// Create the checkout session and redirect the customer to it.
+    pub async fn redirect(self) -> Result<Response, Error> {
+        let session = self.create().await?;
+        let url = session
+            .url
+            .ok_or(Error::Provider(200, "checkout session has no url".into()))?;
+
+        Ok(Response::new().redirect(url).code(303))
+    }
+}
+
+/// Call the Stripe API.
+async fn post<T: DeserializeOwned>(path: &str, params: &[(String, String)]) -> Result<T, Error> {
+    let config = &get_config().payments;
+    let key = config
+        .stripe_secret_key()
+        .ok_or(Error::NotConfigured("stripe_secret_key"))?;
+
+    let params = params
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect::<Vec<_>>();
+
+    let response = Client::post(format!("{}{}", config.stripe_api_url, path))
+        .bearer(key)
+        .form(&params)
+        .send()
+        .await?;
+
+    if !response.ok() {
+        let message = response
+            .json::<Json>()
+            .ok()
+            .and_then(|json| json["error"]["message"].as_str().map(String::from))
+            .unwrap_or(response.text());
+        return Err(Error::Provider(response.code(), message));
+    }
+
+    Ok(response.json()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_checkout_params() {
+        let params = Checkout::subscription("price_1")
+            .quantity(3)
+            .item("price_2", 1)
+            .client_reference_id(42)
+            .success_url("https://example.com/ok?session={CHECKOUT_SESSION_ID}")
+            .trial_days(14)
+            .metadata("plan", "pro")
+            .params();
+
+        let expected = [
+            ("mode", "subscription"),
+            ("line_items[0][price]", "price_1"),
+            ("line_items[0][quantity]", "3"),
+            ("line_items[1][price]", "price_2"),
+            ("line_items[1][quantity]", "1"),
+            (
+                "success_url",
+                "https://example.com/ok?session={CHECKOUT_SESSION_ID}",
+            ),
+            ("client_reference_id", "42"),
+            ("subscription_data[trial_period_days]", "14"),
+            ("metadata[plan]", "pro"),
+        ];
+
+        assert_eq!(
+            params,
+            expected
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,585 @@
+//! Stripe webhook: verifies and handles events sent by Stripe.
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::{json, Value as Json};
+use sha2::Sha256;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tracing::{debug, warn};
+
+use super::{stripe, Customer, Error, Subscription};
+use crate::config::get_config;
+use crate::controller::{Controller, Error as ControllerError};
+use crate::http::{Request, Response};
+use crate::model::{ConnectionGuard, Pool};
+use crate::replay::{Error as ReplayError, ReplayGuard};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Event sent by Stripe.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Event {
+    /// Event ID, e.g. `evt_a1b2c3`.
+    pub id: String,
+    /// Event type, e.g. `customer.subscription.updated`.
+    #[serde(rename = "type")]
+    pub event_type: String,
+    /// When the event was created, as a Unix timestamp.
+    pub created: i64,
+    /// The event was sent in live mode, not test mode.
+    #[serde(default)]
+    pub livemode: bool,
+    /// The object that changed.
+    pub data: EventData,
+}
+
+/// The object attached to an event.
+#[derive(Deserialize, Debug, Clone)]
+pub struct EventData {
+    /// The object, e.g. a subscription.
+    pub object: Json,
+}
+
+/// Events with typed objects. Other events can be handled using the JSON object.
+#[derive(Debug, Clone)]
+pub enum EventKind {
+    /// `checkout.session.completed`
+    CheckoutCompleted(stripe::CheckoutSession),
+    /// `customer.created`
+    CustomerCreated(stripe::Customer),
+    /// `customer.updated`
+    CustomerUpdated(stripe::Customer),
+    /// `customer.deleted`
+    CustomerDeleted(stripe::Customer),
+    /// `customer.subscription.created`
+    SubscriptionCreated(stripe::Subscription),
+    /// `customer.subscription.updated`
+    SubscriptionUpdated(stripe::Subscription),
+    /// `customer.subscription.deleted`
+    SubscriptionDeleted(stripe::Subscription),
+    /// `invoice.paid`
+    InvoicePaid(stripe::Invoice),
+    /// `invoice.payment_failed`
+    InvoicePaymentFailed(stripe::Invoice),
+    /// Any other event.
+    Other,
+}
+
+impl Event {
+    /// Verify the `Stripe-Signature` header and parse the event.
+    pub fn verify(payload: &[u8], signature: &str, secret: &str) -> Result<Self, Error> {
+        let (timestamp, signatures) = parse(signature);
+        let timestamp = timestamp.ok_or(Error::InvalidSignature)?;
+        let mac = sign(payload, secret, timestamp);
+
+        // Compare in constant time, so the signature can't be guessed byte by byte.
+        if !signatures
+            .iter()
+            .any(|signature| mac.clone().verify_slice(signature).is_ok())
+        {
+            return Err(Error::InvalidSignature);
+        }
+
+        let age = OffsetDateTime::now_utc().unix_timestamp() - timestamp;
+        if age.abs() > get_config().payments.webhook_tolerance {
+            return Err(Error::ExpiredSignature);
+        }
+
+        Ok(serde_json::from_slice(payload)?)
+    }
+
+    /// Create the `Stripe-Signature` header for a payload, e.g. to test webhook handlers.
+    pub fn signature(payload: &[u8], secret: &str, timestamp: i64) -> String {
+        let signature = sign(payload, secret, timestamp).finalize().into_bytes();
+        let signature = signature
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+
+        format!("t={},v1={}", timestamp, signature)
+    }
+
+    /// The event with its object deserialized.
+    pub fn kind(&self) -> Result<EventKind, Error> {
+        let object = self.data.object.clone();
+
+        Ok(match self.event_type.as_str() {
+            "checkout.session.completed" => {
+                EventKind::CheckoutCompleted(serde_json::from_value(object)?)
+            }
+            "customer.created" => EventKind::CustomerCreated(serde_json::from_value(object)?),
+            "customer.updated" => EventKind::CustomerUpdated(serde_json::from_value(object)?),
+            "customer.deleted" => EventKind::CustomerDeleted(serde_json::from_value(object)?),
+            "customer.subscription.created" => {
+                EventKind::SubscriptionCreated(serde_json::from_value(object)?)
+            }
+            "customer.subscription.updated" => {
+                EventKind::SubscriptionUpdated(serde_json::from_value(object)?)
+            }
+            "customer.subscription.deleted" => {
+                EventKind::SubscriptionDeleted(serde_json::from_value(object)?)
+            }
+            "invoice.paid" => EventKind::InvoicePaid(serde_json::from_value(object)?),
+            "invoice.payment_failed" => {
+                EventKind::InvoicePaymentFailed(serde_json::from_value(object)?)
+            }
+            _ => EventKind::Other,
+        })
+    }
+
+    /// Update customers and subscriptions changed by the event.
+    pub async fn sync(&self, conn: &mut ConnectionGuard) -> Result<(), Error> {
+        let updated_at =
+            OffsetDateTime::from_unix_timestamp(self.created).unwrap_or(OffsetDateTime::now_utc());
+
+        match self.kind()? {
+            EventKind::CheckoutCompleted(session) => {
+                if let Some(ref customer) = session.customer {
+                    let user_id = session
+                        .client_reference_id
+                        .as_ref()
+                        .and_then(|id| id.parse::<i64>().ok());
+                    Customer::upsert(customer, session.email(), user_id)
+                        .execute(conn)
+                        .await?;
+                }
+            }
+
+            EventKind::CustomerCreated(customer) | EventKind::CustomerUpdated(customer) => {
+                Customer::upsert(&customer.id, customer.email.as_deref(), None)
+                    .execute(conn)
+                    .await?;
+            }
+
+            EventKind::CustomerDeleted(customer) => {
+                conn.client()
+                    .execute(
+                        "DELETE FROM rwf_customers WHERE stripe_id = $1",
+                        &[&customer.id],
+                    )
+                    .await?;
+            }
+
+            EventKind::SubscriptionCreated(subscription)
+            | EventKind::SubscriptionUpdated(subscription)
+            | EventKind::SubscriptionDeleted(subscription) => {
+                let customer = Customer::upsert(&subscription.customer, None, None)
+                    .fetch(&mut *conn)
+                    .await?;
+                Subscription::upsert(&customer, &subscription, updated_at)
+                    .execute(conn)
+                    .await?;
+            }
+
+            EventKind::InvoicePaid(_) | EventKind::InvoicePaymentFailed(_) | EventKind::Other => {}
+        }
+
+        Ok(())
+    }
+}
+
+fn sign(payload: &[u8], secret: &str, timestamp: i64) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(payload);
+    mac
+}
+
+/// Timestamp and signatures in the `Stripe-Signature` header.
+fn parse(signature: &str) -> (Option<i64>, Vec<Vec<u8>>) {
+    let mut timestamp = None;
+    let mut signatures = vec![];
+
+    for part in signature.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => signatures.extend(unhex(value)),
+            _ => (),
+        }
+    }
+
+    (timestamp, signatures)
+}
+
+fn unhex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Handle events received by the [`StripeWebhook`]. Customers and subscriptions are
+/// updated before the handler is called.
+///
+/// # Example
+///
+/// ```ignore
+/// struct Billing;
+///
+/// #[async_trait]
+/// impl WebhookHandler for Billing {
+///     async fn event(&self, event: &Event) -> Result<(), Error> {
+///         if let EventKind::InvoicePaymentFailed(invoice) = event.kind()? {
+///             // Let the customer know.
+///         }
+///         Ok(())
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait WebhookHandler: Sync + Send {
+    /// Handle the event. If an error is returned, Stripe will send the event again later.
+    async fn event(&self, event: &Event) -> Result<(), Error>;
+}
+
+/// Controller receiving Stripe webhooks.
+///
+/// Add it to the routes and register its URL in the Stripe dashboard:
+///
+/// ```ignore
+/// route!("/webhooks/stripe" => StripeWebhook),
+/// // or, to handle events yourself:
+/// StripeWebhook::new(Billing).route("/webhooks/stripe"),
+/// ```
+///
+/// Each signature is accepted only once, so events intercepted in transit can't be sent again.
+/// See [`crate::replay`] to share seen signatures between servers.
+pub struct StripeWebhook {
+    handler: Option<Box<dyn WebhookHandler>>,
+    replay: ReplayGuard,
+}
+
+impl Default for StripeWebhook {
+    fn default() -> Self {
+        let tolerance = get_config().payments.webhook_tolerance.max(0) as u64;
+
+        Self {
+            handler: None,
+            replay: ReplayGuard::new("stripe webhook", Duration::from_secs(tolerance)),
+        }
+    }
+}
+
+impl StripeWebhook {
+    /// Create a webhook which calls the handler for each event.
+    pub fn new(handler: impl WebhookHandler + 'static) -> Self {
+        Self {
+            handler: Some(Box::new(handler)),
+            ..Default::default()
+        }
+    }
+}
+
+#[async_trait]
+impl Controller for StripeWebhook {
+    // Stripe can't send a CSRF token; events are verified with their signature instead.
+    fn skip_csrf(&self) -> bool {
+        true
+    }
+
+    async fn handle(&self, request: &Request) -> Result<Response, ControllerError> {
+        let secret = get_config()
+            .payments
+            .stripe_webhook_secret()
+            .ok_or(Error::NotConfigured("stripe_webhook_secret"))?;
+        let signature = request
+            .header("stripe-signature")
+            .cloned()
+            .unwrap_or_default();
+
+        let event = match Event::verify(request.body(), &signature, &secret) {
+            Ok(event) => event,
+            Err(err) => {
+                warn!("stripe webhook rejected: {}", err);
+                return Ok(Response::bad_request());
+            }
+        };
+
+        // Stripe signs events again when it retries them, so a signature
+        // seen before is a replay.
+        let timestamp = parse(&signature).0.unwrap_or_default();
+        match self.replay.check(&signature, timestamp).await {
+            Ok(()) => (),
+            Err(err @ (ReplayError::Replayed | ReplayError::Expired)) => {
+                warn!("stripe webhook rejected: {} ({})", err, event.id);
+                return Ok(Response::bad_request());
+            }
+            Err(err) => return Err(ControllerError::new(err)),
+        }
+
+        debug!("stripe webhook: {} ({})", event.event_type, event.id);
+
+        let mut conn = Pool::begin().await?;
+        event.sync(&mut conn).await?;
+        conn.commit().await?;
+
+        if let Some(ref handler) = self.handler {
+            handler.event(&event).await?;
+        }
+
+        Ok(Response::new().json(json!({ "received": true }))?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::{client::Client, Router, Server};
+    use crate::model::{test::bootstrap, Model};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn event(event_type: &str, created: i64, object: Json) -> Event {
+        serde_json::from_value(json!({
+            "id": "evt_1",
+            "type": event_type,
+            "created": created,
+            "livemode": false,
+            "data": { "object": object },
+        }))
+        .unwrap()
+    }
+
+    fn subscription(status: &str, price: &str) -> Json {
+        json!({
+            "id": "sub_1",
+            "object": "subscription",
+            "customer": "cus_1",
+            "status": status,
+            "cancel_at_period_end": false,
+            "items": {
+                "data": [{
+                    "id": "si_1",
+                    "price": { "id": price },
+                    "quantity": 2,
+                    "current_period_end": 1_767_225_600,
+                }],
+            },
+        })
+    }
+
+    #[test]
+    fn test_verify() {
+        let payload = serde_json::to_vec(&json!({
+            "id": "evt_1",
+            "type": "invoice.paid",
+            "created": 1,
+            "data": {
+                "object": {
+                    "id": "in_1",
+                    "customer": "cus_1",
+                    "amount_due": 1000,
+                    "amount_paid": 1000,
+                    "currency": "usd",
+                    "status": "paid",
+                },
+            },
+        }))
+        .unwrap();
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        let signature = Event::signature(&payload, "whsec_test", now);
+        let event = Event::verify(&payload, &signature, "whsec_test").unwrap();
+        assert_eq!(event.event_type, "invoice.paid");
+        match event.kind().unwrap() {
+            EventKind::InvoicePaid(invoice) => assert_eq!(invoice.amount_paid, 1000),
+            kind => panic!("unexpected event: {:?}", kind),
+        }
+
+        // Secret rotation: Stripe sends one signature per active secret.
+        let rotated = format!(
+            "{},v1={}",
+            Event::signature(&payload, "whsec_old", now),
+            Event::signature(&payload, "whsec_test", now)
+                .split("v1=")
+                .last()
+                .unwrap()
+        );
+        assert!(Event::verify(&payload, &rotated, "whsec_test").is_ok());
+
+        assert!(matches!(
+            Event::verify(&payload, &signature, "whsec_other"),
+            Err(Error::InvalidSignature)
+        ));
+        assert!(matches!(
+            Event::verify(b"{}", &signature, "whsec_test"),
+            Err(Error::InvalidSignature)
+        ));
+        assert!(matches!(
+            Event::verify(&payload, "v1=abc", "whsec_test"),
+            Err(Error::InvalidSignature)
+        ));
+
+        let old = Event::signature(&payload, "whsec_test", now - 3600);
+        assert!(matches!(
+            Event::verify(&payload, &old, "whsec_test"),
+            Err(Error::ExpiredSignature)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_sync() -> Result<(), Error> {
+        let pool = Pool::from_env();
+        let mut conn = pool.transaction().await?;
+
+        bootstrap(conn.client()).await?;
+
+        event(
+            "checkout.session.completed",
+            100,
+            json!({
+                "id": "cs_1",
+                "mode": "subscription",
+                "customer": "cus_1",
+                "customer_details": { "email": "user@example.com" },
+                "client_reference_id": "77",
+                "subscription": "sub_1",
+            }),
+        )
+        .sync(&mut conn)
+        .await?;
+
+        event(
+            "customer.subscription.created",
+            101,
+            subscription("active", "price_1"),
+        )
+        .sync(&mut conn)
+        .await?;
+
+        let customer = Customer::for_user(77).fetch(&mut conn).await?;
+        assert_eq!(customer.stripe_id, "cus_1");
+        assert_eq!(customer.email.as_deref(), Some("user@example.com"));
+
+        let subscriptions = Subscription::active_for_user(77)
+            .fetch_all(&mut conn)
+            .await?;
+        assert_eq!(subscriptions.len(), 1);
+        assert!(subscriptions[0].is_active());
+        assert_eq!(subscriptions[0].price_id.as_deref(), Some("price_1"));
+        assert_eq!(subscriptions[0].quantity, 2);
+        assert_eq!(
+            subscriptions[0]
+                .current_period_end
+                .unwrap()
+                .unix_timestamp(),
+            1_767_225_600
+        );
+
+        // Events received out of order don't overwrite newer changes.
+        event(
+            "customer.subscription.deleted",
+            103,
+            subscription("canceled", "price_1"),
+        )
+        .sync(&mut conn)
+        .await?;
+        event(
+            "customer.subscription.updated",
+            102,
+            subscription("active", "price_2"),
+        )
+        .sync(&mut conn)
+        .await?;
+
+        let subscriptions = customer.subscriptions().fetch_all(&mut conn).await?;
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(subscriptions[0].status, "canceled");
+        assert_eq!(subscriptions[0].price_id.as_deref(), Some("price_1"));
+        assert!(Subscription::active_for_user(77)
+            .fetch_all(&mut conn)
+            .await?
+            .is_empty());
+
+        // Updates without a user don't unlink the customer.
+        event(
+            "customer.updated",
+            104,
+            json!({ "id": "cus_1", "email": "new@example.com" }),
+        )
+        .sync(&mut conn)
+        .await?;
+        let customer = Customer::for_user(77).fetch(&mut conn).await?;
+        assert_eq!(customer.email.as_deref(), Some("new@example.com"));
+
+        event("customer.deleted", 105, json!({ "id": "cus_1" }))
+            .sync(&mut conn)
+            .await?;
+        assert_eq!(Customer::all().count(&mut conn).await?, 0);
+        assert_eq!(Subscription::all().count(&mut conn).await?, 0);
+
+        conn.rollback().await?;
+        Ok(())
+    }
+
+    struct Counter(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl WebhookHandler for Counter {
+        async fn event(&self, event: &Event) -> Result<(), Error> {
+            assert_eq!(event.event_type, "invoice.upcoming");
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_webhook() {
+        std::env::set_var("RWF_STRIPE_WEBHOOK_SECRET", "whsec_webhook_test");
+
+        let events = Arc::new(AtomicUsize::new(0));
+        let router = Arc::new(
+            Router::new(vec![
+                StripeWebhook::new(Counter(events.clone())).route("/webhooks/stripe")
+            ])
+            .unwrap(),
+        );
+        let addr = Server::serve_test(router).await;
+        let url = format!("http://{}/webhooks/stripe", addr);
+
+        let payload = serde_json::to_vec(&json!({
+            "id": "evt_1",
+            "type": "invoice.upcoming",
+            "created": 1,
+            "data": { "object": {} },
+        }))
+        .unwrap();
+
+        let response = Client::post(&url)
+            .header("stripe-signature", "t=1,v1=00")
+            .body(payload.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.code(), 400);
+        assert_eq!(events.load(Ordering::Relaxed), 0);
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let signature = Event::signature(&payload, "whsec_webhook_test", now);
+        let response = Client::post(&url)
+            .header("stripe-signature", signature.clone())
+            .body(payload.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.code(), 200);
+        assert_eq!(events.load(Ordering::Relaxed), 1);
+
+        // The same request sent again is rejected.
+        let response = Client::post(&url)
+            .header("stripe-signature", signature)
+            .body(payload)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.code(), 400);
+        assert_eq!(events.load(Ordering::Relaxed), 1);
+    }
+}
@@ -6,17 +6,27 @@
 //! ```
 //! use rwf::prelude::*;
 //! ```
+pub use crate::auth::SecurePassword;
 pub use crate::comms::Comms;
 pub use crate::config::Config;
 pub use crate::controller::{auth::SessionAuth, AuthHandler};
 pub use crate::controller::{
-    Authentication, Controller, Error, ModelController, PageController, RestController, SessionId,
+    Authentication, Controller, Error, ModelController, PageController, Resource, RestController,
+    SessionId,
+};
+pub use crate::http::{
+    Cookie, CookieBuilder, Message, Method, Operation, Request, Response, Schema, ToMessage,
 };
-pub use crate::http::{Cookie, CookieBuilder, Message, Method, Request, Response, ToMessage};
 pub use crate::job::{queue_async, queue_delay, Job};
 pub use crate::logging::Logger;
-pub use crate::model::{pool::ToConnectionRequest, Migrations, Model, Pool, Scope, ToSql, ToValue};
-pub use crate::view::{Template, ToTemplateValue, TurboStream};
+pub use crate::model::{
+    pool::ToConnectionRequest, Anonymize, Fixtures, Migrations, Model, Pool, Publishable, Scope,
+    Sluggable, StateMachine, Stateful, Taggable, ToSql, ToValue, Tree,
+};
+pub use crate::search::Searchable;
+pub use crate::t;
+pub use crate::url_for;
+pub use crate::view::{form_for, Template, ToTemplateValue, TurboFrame, TurboStream};
 
 /// A macro to easily implement async traits methods.
 pub use async_trait::async_trait;
@@ -25,7 +35,8 @@ pub use time::{Duration, OffsetDateTime};
 pub use tokio;
 
 pub use macros::{
-    context, controller, crud, engine, render, render_include, rest, route, turbo_stream,
+    authorize, context, controller, crud, delete, engine, get, patch, post, put, render,
+    render_include, respond_to, rest, route, turbo_frame, turbo_stream,
 };
 pub use rwf_macros as macros;
 pub use serde::{Deserialize, Serialize};
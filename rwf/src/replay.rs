@@ -0,0 +1,353 @@
+//! Replay protection for signed requests.
+//!
+//! A signature proves who sent a request, but not that it's only been sent once: anyone who
+//! intercepts a signed webhook, form or link can send it again. Signed requests include a timestamp
+//! and a nonce, a value used only once, e.g. a random string or the signature itself.
+//! [`ReplayGuard`] rejects requests with a timestamp outside its window and remembers nonces
+//! until their timestamp leaves the window, rejecting duplicates.
+//!
+//! Nonces are kept in memory by default, so each server remembers only the requests it received.
+//! To share them between servers, use [`PostgresStore`] (see [`set_store`]), or implement [`NonceStore`]
+//! for another backend, e.g. Redis.
+//!
+//! # Example
+//!
+//! ```rust
+//! # #[tokio::main]
+//! # async fn main() {
+//! use rwf::replay::{nonce, ReplayGuard, Error};
+//! use std::time::Duration;
+//! use time::OffsetDateTime;
+//!
+//! let guard = ReplayGuard::new("password reset", Duration::from_secs(300));
+//! let nonce = nonce();
+//! let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+//!
+//! assert!(guard.check(&nonce, timestamp).await.is_ok());
+//! assert!(matches!(guard.check(&nonce, timestamp).await, Err(Error::Replayed)));
+//! # }
+//! ```
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use thiserror::Error;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::cluster;
+use crate::model::{Error as ModelError, Pool};
+
+static STORE: Lazy<RwLock<Arc<dyn NonceStore>>> =
+    Lazy::new(|| RwLock::new(Arc::new(MemoryStore::new())));
+
+/// Error returned when a request is rejected.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The nonce has been used already.
+    #[error("request has been sent already")]
+    Replayed,
+
+    /// The timestamp is outside the window.
+    #[error("request timestamp is outside the window")]
+    Expired,
+
+    /// The store is unavailable.
+    #[error("nonce store error: {0}")]
+    Store(String),
+}
+
+impl From<ModelError> for Error {
+    fn from(err: ModelError) -> Self {
+        Self::Store(err.to_string())
+    }
+}
+
+impl From<tokio_postgres::Error> for Error {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::Store(err.to_string())
+    }
+}
+
+/// Storage for nonces.
+#[async_trait]
+pub trait NonceStore: Send + Sync {
+    /// Remember the nonce until `expires_at`, a Unix timestamp. Returns `false`
+    /// if the nonce is already stored and hasn't expired.
+    async fn insert(&self, nonce: &str, expires_at: i64) -> Result<bool, Error>;
+
+    /// Name of the backend, e.g. `redis`.
+    fn name(&self) -> &'static str {
+        "custom"
+    }
+
+    /// Nonces are shared between all servers. Required in [cluster mode](crate::cluster).
+    fn shared(&self) -> bool {
+        false
+    }
+}
+
+/// Nonces kept in memory. Each server remembers only the requests it received.
+///
+/// The store is bounded: when it's full and no nonces have expired, the one
+/// closest to expiring is forgotten first.
+pub struct MemoryStore {
+    capacity: usize,
+    nonces: Mutex<HashMap<String, i64>>,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryStore {
+    /// Create a store remembering up to 100,000 nonces.
+    pub fn new() -> Self {
+        Self::with_capacity(100_000)
+    }
+
+    /// Create a store remembering up to `capacity` nonces.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            nonces: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl NonceStore for MemoryStore {
+    async fn insert(&self, nonce: &str, expires_at: i64) -> Result<bool, Error> {
+        // Get current time before locking mutex.
+        let now = now();
+        let mut nonces = self.nonces.lock();
+
+        if let Some(existing) = nonces.get(nonce) {
+            if *existing > now {
+                return Ok(false);
+            }
+        }
+
+        if nonces.len() >= self.capacity && !nonces.contains_key(nonce) {
+            nonces.retain(|_, expires_at| *expires_at > now);
+
+            if nonces.len() >= self.capacity {
+                let oldest = nonces
+                    .iter()
+                    .min_by_key(|(_, expires_at)| **expires_at)
+                    .map(|(nonce, _)| nonce.clone());
+                if let Some(oldest) = oldest {
+                    nonces.remove(&oldest);
+                }
+            }
+        }
+
+        nonces.insert(nonce.to_string(), expires_at);
+
+        Ok(true)
+    }
+
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+}
+
+/// Nonces kept in the `rwf_nonces` table, shared between all servers
+/// using the same database. Expired nonces are deleted once a minute.
+#[derive(Default)]
+pub struct PostgresStore {
+    pruned_at: Mutex<i64>,
+}
+
+impl PostgresStore {
+    /// Create a store using the default connection pool.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl NonceStore for PostgresStore {
+    async fn insert(&self, nonce: &str, expires_at: i64) -> Result<bool, Error> {
+        let now = now();
+        let conn = Pool::connection().await?;
+
+        let prune = {
+            let mut pruned_at = self.pruned_at.lock();
+            if now - *pruned_at >= 60 {
+                *pruned_at = now;
+                true
+            } else {
+                false
+            }
+        };
+
+        if prune {
+            conn.client()
+                .execute("DELETE FROM rwf_nonces WHERE expires_at <= $1", &[&now])
+                .await?;
+        }
+
+        // Expired nonces that haven't been deleted yet can be used again.
+        let inserted = conn
+            .client()
+            .execute(
+                "INSERT INTO rwf_nonces (nonce, expires_at) VALUES ($1, $2)
+                ON CONFLICT (nonce) DO UPDATE SET expires_at = EXCLUDED.expires_at
+                WHERE rwf_nonces.expires_at <= $3",
+                &[&nonce, &expires_at, &now],
+            )
+            .await?;
+
+        Ok(inserted == 1)
+    }
+
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn shared(&self) -> bool {
+        true
+    }
+}
+
+/// Rejects requests sent more than once or outside the window.
+#[derive(Clone)]
+pub struct ReplayGuard {
+    scope: String,
+    window: Duration,
+    store: Arc<dyn NonceStore>,
+}
+
+impl ReplayGuard {
+    /// Create a guard accepting requests with a timestamp up to `window` away from now.
+    /// Nonces are namespaced by `scope`, so guards sharing a store don't reject each other's requests.
+    ///
+    /// The guard uses the store set with [`set_store`] when it's created.
+    pub fn new(scope: impl ToString, window: Duration) -> Self {
+        let store = store();
+        cluster::register("replay protection", store.name(), store.shared());
+
+        Self {
+            scope: scope.to_string(),
+            window,
+            store,
+        }
+    }
+
+    /// Accept the request with this nonce and timestamp, in seconds since the Unix epoch, if
+    /// the timestamp is within the window and the nonce hasn't been seen before.
+    pub async fn check(&self, nonce: &str, timestamp: i64) -> Result<(), Error> {
+        let window = self.window.as_secs() as i64;
+
+        if (now() - timestamp).abs() > window {
+            return Err(Error::Expired);
+        }
+
+        // Requests with a timestamp in the future are valid for longer,
+        // so their nonces are kept until it leaves the window.
+        let key = format!("{}:{}", self.scope, nonce);
+        if self.store.insert(&key, timestamp + window).await? {
+            Ok(())
+        } else {
+            Err(Error::Replayed)
+        }
+    }
+
+    /// How far from now timestamps are accepted.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+}
+
+/// Generate a random nonce, e.g. to include in a signed form or link.
+pub fn nonce() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+/// The store used by new guards.
+pub fn store() -> Arc<dyn NonceStore> {
+    STORE.read().clone()
+}
+
+/// Use this store for guards created from now on, e.g. [`PostgresStore`] to share
+/// nonces between servers. Set it before creating routes.
+pub fn set_store(store: impl NonceStore + 'static) {
+    *STORE.write() = Arc::new(store);
+}
+
+/// Current time, in seconds since the Unix epoch.
+fn now() -> i64 {
+    OffsetDateTime::now_utc().unix_timestamp()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::test::bootstrap;
+
+    #[tokio::test]
+    async fn test_replay_guard() {
+        let guard = ReplayGuard::new("test", Duration::from_secs(60));
+        let now = now();
+
+        assert!(guard.check("a", now).await.is_ok());
+        assert!(matches!(guard.check("a", now).await, Err(Error::Replayed)));
+        assert!(matches!(
+            guard.check("b", now - 120).await,
+            Err(Error::Expired)
+        ));
+        assert!(matches!(
+            guard.check("b", now + 120).await,
+            Err(Error::Expired)
+        ));
+
+        // Nonces are namespaced.
+        let other = ReplayGuard::new("other", Duration::from_secs(60));
+        assert!(other.check("a", now).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_memory_store() -> Result<(), Error> {
+        let store = MemoryStore::with_capacity(2);
+        let now = now();
+
+        assert!(store.insert("a", now + 60).await?);
+        assert!(!store.insert("a", now + 60).await?);
+
+        // Expired nonces can be used again.
+        assert!(store.insert("b", now - 1).await?);
+        assert!(store.insert("b", now + 30).await?);
+
+        // The nonce closest to expiring is forgotten first.
+        assert!(store.insert("c", now + 90).await?);
+        assert_eq!(store.nonces.lock().len(), 2);
+        assert!(!store.insert("a", now + 60).await?);
+        assert!(store.insert("b", now + 60).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_postgres_store() -> Result<(), Error> {
+        let conn = Pool::connection().await?;
+        bootstrap(conn.client()).await?;
+
+        let store = PostgresStore::new();
+        let nonce = format!("test:{}", super::nonce());
+
+        assert!(store.insert(&nonce, now() + 60).await?);
+        assert!(!store.insert(&nonce, now() + 60).await?);
+
+        conn.client()
+            .execute("DELETE FROM rwf_nonces WHERE nonce = $1", &[&nonce])
+            .await?;
+
+        Ok(())
+    }
+}
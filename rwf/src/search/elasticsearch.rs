@@ -0,0 +1,240 @@
+//! [Elasticsearch](https://www.elastic.co/elasticsearch) and [OpenSearch](https://opensearch.org) search engines.
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use serde_json::{json, Value as Json};
+
+use super::{Engine, Error, Match, SearchQuery};
+use crate::config::SearchConfig;
+use crate::http::client::{Client, ClientResponse};
+
+/// Elasticsearch client.
+#[derive(Debug, Clone)]
+pub struct Elasticsearch {
+    url: String,
+    api_key: Option<String>,
+}
+
+impl Elasticsearch {
+    /// Create a client using the `[search]` configuration.
+    pub fn new(config: &SearchConfig) -> Self {
+        Self {
+            url: config.url().trim_end_matches('/').to_string(),
+            api_key: config.api_key(),
+        }
+    }
+
+    fn request(&self, method: &str, path: &str) -> Client {
+        let request = Client::new(method, format!("{}{}", self.url, path));
+        match self.api_key {
+            Some(ref key) => request.header("authorization", format!("ApiKey {}", key)),
+            None => request,
+        }
+    }
+
+    /// Send bulk actions, written as JSON lines.
+    async fn bulk(&self, lines: Vec<Json>) -> Result<(), Error> {
+        let mut body = vec![];
+        for line in lines {
+            serde_json::to_writer(&mut body, &line)?;
+            body.push(b'\n');
+        }
+
+        let response = check(
+            self.request("POST", "/_bulk?refresh=wait_for")
+                .header("content-type", "application/x-ndjson")
+                .body(body)
+                .send()
+                .await?,
+        )?;
+
+        // The request succeeds even if some of the actions failed.
+        if response["errors"].as_bool().unwrap_or(false) {
+            let failed = response["items"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|item| item.as_object()?.values().next())
+                .filter(|result| result["error"].is_object() && result["status"] != 404)
+                .collect::<Vec<_>>();
+
+            if !failed.is_empty() {
+                return Err(Error::Engine(400, json!(failed).to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn index_lines(index: &str, primary_key: &str, documents: &[Json]) -> Vec<Json> {
+        documents
+            .iter()
+            .flat_map(|document| {
+                let id = document[primary_key].to_string().replace('"', "");
+                [
+                    json!({"index": {"_index": index, "_id": id}}),
+                    document.clone(),
+                ]
+            })
+            .collect()
+    }
+
+    fn delete_lines(index: &str, ids: &[i64]) -> Vec<Json> {
+        ids.iter()
+            .map(|id| json!({"delete": {"_index": index, "_id": id.to_string()}}))
+            .collect()
+    }
+
+    /// Body of the search request.
+    fn search_body(query: &SearchQuery) -> Json {
+        let fields = query
+            .fields
+            .iter()
+            .map(|field| (field.clone(), json!({})))
+            .collect::<serde_json::Map<_, _>>();
+
+        json!({
+            "query": {
+                "multi_match": {
+                    "query": query.query,
+                    "fields": query.fields,
+                },
+            },
+            "from": query.offset,
+            "size": query.limit,
+            "_source": false,
+            "highlight": {
+                "pre_tags": [query.pre_tag],
+                "post_tags": [query.post_tag],
+                "fields": fields,
+            },
+        })
+    }
+
+    /// Read the matches from the search response.
+    fn matches(response: &Json) -> Result<Vec<Match>, Error> {
+        let hits = response["hits"]["hits"]
+            .as_array()
+            .ok_or_else(|| Error::UnexpectedResponse(response.to_string()))?;
+
+        let mut matches = vec![];
+
+        for hit in hits {
+            let id = match hit["_id"].as_str().and_then(|id| id.parse().ok()) {
+                Some(id) => id,
+                None => continue,
+            };
+
+            let mut highlights = HashMap::new();
+            if let Some(fields) = hit["highlight"].as_object() {
+                for (field, fragments) in fields {
+                    let fragments = fragments
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|fragment| fragment.as_str())
+                        .collect::<Vec<_>>();
+                    highlights.insert(field.clone(), fragments.join(" … "));
+                }
+            }
+
+            matches.push(Match { id, highlights });
+        }
+
+        Ok(matches)
+    }
+}
+
+#[async_trait]
+impl Engine for Elasticsearch {
+    async fn index(&self, index: &str, primary_key: &str, documents: &[Json]) -> Result<(), Error> {
+        self.bulk(Self::index_lines(index, primary_key, documents))
+            .await
+    }
+
+    async fn delete(&self, index: &str, ids: &[i64]) -> Result<(), Error> {
+        self.bulk(Self::delete_lines(index, ids)).await
+    }
+
+    async fn search(&self, index: &str, query: &SearchQuery) -> Result<Vec<Match>, Error> {
+        let path = format!("/{}/_search", index);
+        let response = self
+            .request("POST", &path)
+            .json(&Self::search_body(query))?
+            .send()
+            .await?;
+
+        if response.code() == 404 {
+            return Ok(vec![]);
+        }
+
+        Self::matches(&check(response)?)
+    }
+
+    fn name(&self) -> &'static str {
+        "elasticsearch"
+    }
+}
+
+/// Return the response body if the request succeeded.
+fn check(response: ClientResponse) -> Result<Json, Error> {
+    if response.ok() {
+        Ok(response.json()?)
+    } else {
+        Err(Error::Engine(response.code(), response.text()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bulk() {
+        let lines = Elasticsearch::index_lines("posts", "id", &[json!({"id": 5, "title": "Rust"})]);
+        assert_eq!(
+            lines,
+            vec![
+                json!({"index": {"_index": "posts", "_id": "5"}}),
+                json!({"id": 5, "title": "Rust"}),
+            ]
+        );
+
+        let lines = Elasticsearch::delete_lines("posts", &[5]);
+        assert_eq!(
+            lines,
+            vec![json!({"delete": {"_index": "posts", "_id": "5"}})]
+        );
+    }
+
+    #[test]
+    fn test_search() {
+        let query = SearchQuery::new("rust", &["title", "body"], "id");
+        let body = Elasticsearch::search_body(&query);
+        assert_eq!(
+            body["query"]["multi_match"]["fields"],
+            json!(["title", "body"])
+        );
+        assert_eq!(
+            body["highlight"]["fields"],
+            json!({"title": {}, "body": {}})
+        );
+
+        let response = json!({
+            "hits": {
+                "hits": [
+                    {"_id": "3", "highlight": {"body": ["a <mark>rust</mark>", "<mark>rust</mark> b"]}},
+                    {"_id": "1"},
+                ],
+            },
+        });
+        let matches = Elasticsearch::matches(&response).unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].id, 3);
+        assert_eq!(
+            matches[0].highlights["body"],
+            "a <mark>rust</mark> … <mark>rust</mark> b"
+        );
+        assert!(matches[1].highlights.is_empty());
+    }
+}
@@ -0,0 +1,33 @@
+//! Errors returned by the search engine integration.
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    /// No search engine is configured.
+    #[error("search engine is not configured")]
+    NotConfigured,
+
+    /// The search engine returned an error.
+    #[error("search engine error ({0}): {1}")]
+    Engine(u16, String),
+
+    /// The search engine response couldn't be understood.
+    #[error("unexpected search engine response: {0}")]
+    UnexpectedResponse(String),
+
+    /// Couldn't reach the search engine.
+    #[error("{0}")]
+    Http(#[from] crate::http::client::Error),
+
+    /// Error encoding/decoding JSON.
+    #[error("json: {0}")]
+    Json(#[from] serde_json::Error),
+
+    /// The ORM returned an error.
+    #[error("search database error: {0}")]
+    DatabaseError(#[from] crate::model::Error),
+
+    /// Couldn't queue the indexer job.
+    #[error("{0}")]
+    Job(#[from] crate::job::Error),
+}
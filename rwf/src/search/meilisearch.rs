@@ -0,0 +1,161 @@
+//! [Meilisearch](https://www.meilisearch.com) search engine.
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use serde_json::{json, Value as Json};
+
+use super::{Engine, Error, Match, SearchQuery};
+use crate::config::SearchConfig;
+use crate::http::client::{Client, ClientResponse};
+
+/// Meilisearch client.
+#[derive(Debug, Clone)]
+pub struct Meilisearch {
+    url: String,
+    api_key: Option<String>,
+}
+
+impl Meilisearch {
+    /// Create a client using the `[search]` configuration.
+    pub fn new(config: &SearchConfig) -> Self {
+        Self {
+            url: config.url().trim_end_matches('/').to_string(),
+            api_key: config.api_key(),
+        }
+    }
+
+    fn request(&self, method: &str, path: &str) -> Client {
+        let request = Client::new(method, format!("{}{}", self.url, path));
+        match self.api_key {
+            Some(ref key) => request.bearer(key),
+            None => request,
+        }
+    }
+
+    /// Body of the search request.
+    fn search_body(query: &SearchQuery) -> Json {
+        json!({
+            "q": query.query,
+            "limit": query.limit,
+            "offset": query.offset,
+            "attributesToHighlight": query.fields,
+            "highlightPreTag": query.pre_tag,
+            "highlightPostTag": query.post_tag,
+        })
+    }
+
+    /// Read the matches from the search response.
+    fn matches(response: &Json, query: &SearchQuery) -> Result<Vec<Match>, Error> {
+        let hits = response["hits"]
+            .as_array()
+            .ok_or_else(|| Error::UnexpectedResponse(response.to_string()))?;
+
+        let mut matches = vec![];
+
+        for hit in hits {
+            let id = match id(&hit[&query.primary_key]) {
+                Some(id) => id,
+                None => continue,
+            };
+
+            let mut highlights = HashMap::new();
+            for field in &query.fields {
+                if let Some(text) = hit["_formatted"][field].as_str() {
+                    if text.contains(&query.pre_tag) {
+                        highlights.insert(field.clone(), text.to_string());
+                    }
+                }
+            }
+
+            matches.push(Match { id, highlights });
+        }
+
+        Ok(matches)
+    }
+}
+
+#[async_trait]
+impl Engine for Meilisearch {
+    async fn index(&self, index: &str, primary_key: &str, documents: &[Json]) -> Result<(), Error> {
+        let path = format!("/indexes/{}/documents?primaryKey={}", index, primary_key);
+        check(self.request("POST", &path).json(&documents)?.send().await?)?;
+        Ok(())
+    }
+
+    async fn delete(&self, index: &str, ids: &[i64]) -> Result<(), Error> {
+        let path = format!("/indexes/{}/documents/delete-batch", index);
+        let response = self.request("POST", &path).json(&ids)?.send().await?;
+
+        // Nothing was indexed yet.
+        if response.code() == 404 {
+            return Ok(());
+        }
+
+        check(response)?;
+        Ok(())
+    }
+
+    async fn search(&self, index: &str, query: &SearchQuery) -> Result<Vec<Match>, Error> {
+        let path = format!("/indexes/{}/search", index);
+        let response = self
+            .request("POST", &path)
+            .json(&Self::search_body(query))?
+            .send()
+            .await?;
+
+        if response.code() == 404 {
+            return Ok(vec![]);
+        }
+
+        Self::matches(&check(response)?, query)
+    }
+
+    fn name(&self) -> &'static str {
+        "meilisearch"
+    }
+}
+
+/// Return the response body if the request succeeded.
+fn check(response: ClientResponse) -> Result<Json, Error> {
+    if response.ok() {
+        Ok(response.json()?)
+    } else {
+        Err(Error::Engine(response.code(), response.text()))
+    }
+}
+
+/// Document ids are returned as they were indexed, numbers or strings.
+fn id(value: &Json) -> Option<i64> {
+    value
+        .as_i64()
+        .or_else(|| value.as_str().and_then(|id| id.parse().ok()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_search() {
+        let query = SearchQuery::new("rust", &["title", "body"], "id");
+        let body = Meilisearch::search_body(&query);
+        assert_eq!(body["q"], "rust");
+        assert_eq!(body["attributesToHighlight"], json!(["title", "body"]));
+        assert_eq!(body["highlightPreTag"], "<mark>");
+
+        let response = json!({
+            "hits": [
+                {"id": 2, "title": "Rust", "_formatted": {"id": "2", "title": "<mark>Rust</mark>", "body": "Nothing"}},
+                {"id": "1", "title": "Rust book", "_formatted": {"title": "<mark>Rust</mark> book"}},
+            ],
+        });
+        let matches = Meilisearch::matches(&response, &query).unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].id, 2);
+        assert_eq!(matches[0].highlights["title"], "<mark>Rust</mark>");
+        assert!(!matches[0].highlights.contains_key("body"));
+        assert_eq!(matches[1].id, 1);
+
+        assert!(Meilisearch::matches(&json!({"message": "error"}), &query).is_err());
+    }
+}
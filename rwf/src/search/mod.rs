@@ -0,0 +1,642 @@
+//! Full-text search using an external search engine, e.g. Meilisearch or Elasticsearch.
+//!
+//! Models opt in by deriving [`Searchable`] and listing the fields sent to the search engine:
+//!
+//! ```ignore
+//! #[derive(Clone, macros::Model, macros::Searchable)]
+//! #[searchable(title, body)]
+//! struct Post {
+//!     id: Option<i64>,
+//!     title: String,
+//!     body: String,
+//! }
+//! ```
+//!
+//! Changes are synced to the search engine in the background by the [`Indexer`] job, and searches
+//! return the matching records loaded from the database, with the matching words highlighted:
+//!
+//! ```ignore
+//! // After saving or deleting a record.
+//! post.index_later().await?;
+//!
+//! let hits = Search::<Post>::query("rust").limit(10).fetch(&mut conn).await?;
+//! for hit in hits {
+//!     println!("{}: {:?}", hit.record.title, hit.highlight("body"));
+//! }
+//! ```
+//!
+//! The search engine is configured in the `[search]` section of `rwf.toml`.
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::{json, Value as Json};
+use tracing::info;
+
+use crate::colors::MaybeColorize;
+use crate::config::{get_config, SearchBackend};
+use crate::job::{Error as JobError, Job};
+use crate::model::{Column, ConnectionGuard, Model, Pool, Value};
+
+pub mod elasticsearch;
+pub mod error;
+pub mod meilisearch;
+
+pub use elasticsearch::Elasticsearch;
+pub use error::Error;
+pub use meilisearch::Meilisearch;
+
+/// Model indexed by the search engine. Use the derive macro to implement it:
+///
+/// ```
+/// # use rwf::prelude::*;
+/// #[derive(Clone, macros::Model, macros::Searchable)]
+/// #[searchable(title, body)]
+/// struct Post {
+///     id: Option<i64>,
+///     title: String,
+///     body: String,
+/// }
+///
+/// assert_eq!(Post::searchable_fields(), &["title", "body"]);
+/// assert_eq!(Post::search_index(), "posts");
+/// ```
+#[async_trait]
+pub trait Searchable: Model + Sync + 'static {
+    /// Fields sent to the search engine.
+    fn searchable_fields() -> &'static [&'static str];
+
+    /// Document sent to the search engine: the primary key and the searchable fields.
+    fn search_document(&self) -> Document;
+
+    /// Name of the search engine index. Default: the table name, with the configured prefix.
+    fn search_index() -> String {
+        format!("{}{}", get_config().search.index_prefix, Self::table_name())
+    }
+
+    /// Search for records matching the query.
+    fn search(query: &str) -> Search<Self> {
+        Search::query(query)
+    }
+
+    /// Queue the record to be synced with the search engine. Call this after the record
+    /// is created, updated or deleted: records which no longer exist are removed from the index.
+    async fn index_later(&self) -> Result<(), Error> {
+        match id(&self.id()) {
+            Some(id) => Indexer::<Self>::new().index_later(&[id]).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Send all records to the search engine, e.g. after adding a searchable field.
+    /// Returns the number of records indexed.
+    async fn reindex(conn: &mut ConnectionGuard) -> Result<usize, Error> {
+        let engine = engine()?;
+        let batch_size = get_config().search.batch_size;
+        let primary_key = Column::new(Self::table_name(), Self::primary_key());
+        let mut last = None;
+        let mut indexed = 0;
+
+        loop {
+            let query = Self::all()
+                .order((primary_key.clone(), "ASC"))
+                .limit(batch_size);
+            let query = match last {
+                Some(last) => query.filter_gt(primary_key.clone(), last),
+                None => query,
+            };
+
+            let records = query.fetch_all(&mut *conn).await?;
+            let documents = records
+                .iter()
+                .map(|record| record.search_document().into())
+                .collect::<Vec<_>>();
+
+            if !documents.is_empty() {
+                engine
+                    .index(&Self::search_index(), Self::primary_key(), &documents)
+                    .await?;
+            }
+
+            indexed += records.len();
+            last = records.last().and_then(|record| id(&record.id()));
+
+            if (records.len() as i64) < batch_size || last.is_none() {
+                break;
+            }
+        }
+
+        Ok(indexed)
+    }
+}
+
+/// Document sent to the search engine.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Document {
+    fields: serde_json::Map<String, Json>,
+}
+
+impl Document {
+    /// Create a document for the record, containing its primary key.
+    pub fn new<T: Model>(record: &T) -> Self {
+        Self::default().field(T::primary_key(), &id(&record.id()))
+    }
+
+    /// Add a field to the document. The value is serialized to JSON.
+    pub fn field(mut self, name: &str, value: &impl Serialize) -> Self {
+        self.fields.insert(
+            name.to_string(),
+            serde_json::to_value(value).unwrap_or(Json::Null),
+        );
+        self
+    }
+}
+
+impl From<Document> for Json {
+    fn from(document: Document) -> Json {
+        Json::Object(document.fields)
+    }
+}
+
+/// Search query sent to the search engine.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchQuery {
+    /// Words to search for.
+    pub query: String,
+    /// Fields to search and highlight.
+    pub fields: Vec<String>,
+    /// Name of the primary key in the documents.
+    pub primary_key: String,
+    /// Maximum number of matches returned.
+    pub limit: usize,
+    /// Number of matches to skip, for pagination.
+    pub offset: usize,
+    /// Tag inserted before matching words.
+    pub pre_tag: String,
+    /// Tag inserted after matching words.
+    pub post_tag: String,
+}
+
+impl SearchQuery {
+    /// Create a query for the fields, using the configured highlight tags.
+    pub fn new(query: &str, fields: &[&str], primary_key: &str) -> Self {
+        let config = &get_config().search;
+
+        Self {
+            query: query.to_string(),
+            fields: fields.iter().map(|field| field.to_string()).collect(),
+            primary_key: primary_key.to_string(),
+            limit: 20,
+            offset: 0,
+            pre_tag: config.highlight_pre_tag.clone(),
+            post_tag: config.highlight_post_tag.clone(),
+        }
+    }
+}
+
+/// Document matching a search, as returned by the search engine.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    /// Primary key of the record.
+    pub id: i64,
+    /// Fields with the matching words highlighted.
+    pub highlights: HashMap<String, String>,
+}
+
+/// Search engine backend.
+///
+/// Implement this trait to add support for a search engine that isn't
+/// supported out of the box.
+#[async_trait]
+pub trait Engine: Send + Sync {
+    /// Add or replace the documents in the index. The index is created if it doesn't exist.
+    async fn index(&self, index: &str, primary_key: &str, documents: &[Json]) -> Result<(), Error>;
+
+    /// Remove documents from the index.
+    async fn delete(&self, index: &str, ids: &[i64]) -> Result<(), Error>;
+
+    /// Find documents matching the query, best matches first.
+    async fn search(&self, index: &str, query: &SearchQuery) -> Result<Vec<Match>, Error>;
+
+    /// Name of the search engine, used in logging.
+    fn name(&self) -> &'static str;
+
+    /// Convert the engine into a handle that can be shared between tasks.
+    fn handler(self) -> Arc<Box<dyn Engine>>
+    where
+        Self: Sized + 'static,
+    {
+        Arc::new(Box::new(self))
+    }
+}
+
+/// Get the search engine configured in `[search]`.
+pub fn engine() -> Result<Arc<Box<dyn Engine>>, Error> {
+    let config = &get_config().search;
+
+    match config.backend {
+        SearchBackend::None => Err(Error::NotConfigured),
+        SearchBackend::Meilisearch => Ok(Meilisearch::new(config).handler()),
+        SearchBackend::Elasticsearch => Ok(Elasticsearch::new(config).handler()),
+    }
+}
+
+/// Record matching a search.
+#[derive(Debug, Clone)]
+pub struct Hit<T> {
+    /// The record, loaded from the database.
+    pub record: T,
+    /// Fields with the matching words highlighted. The text is HTML-escaped,
+    /// so it's safe to render in a template.
+    pub highlights: HashMap<String, String>,
+}
+
+impl<T> Hit<T> {
+    /// Field with the matching words highlighted, if the field matched.
+    pub fn highlight(&self, field: &str) -> Option<&str> {
+        self.highlights.get(field).map(|text| text.as_str())
+    }
+}
+
+/// Search for records of a model.
+#[derive(Debug, Clone)]
+pub struct Search<T> {
+    query: SearchQuery,
+    _model: PhantomData<fn() -> T>,
+}
+
+impl<T: Searchable> Search<T> {
+    /// Search for records matching the words. Returns up to 20 records by default.
+    pub fn query(query: &str) -> Self {
+        Self {
+            query: SearchQuery::new(query, T::searchable_fields(), T::primary_key()),
+            _model: PhantomData,
+        }
+    }
+
+    /// Maximum number of records returned.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.query.limit = limit;
+        self
+    }
+
+    /// Skip some records, e.g. to show the second page of results.
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.query.offset = offset;
+        self
+    }
+
+    /// Search only some of the searchable fields.
+    pub fn fields(mut self, fields: &[&str]) -> Self {
+        self.query.fields = fields.iter().map(|field| field.to_string()).collect();
+        self
+    }
+
+    /// Run the search and load the matching records from the database, best matches first.
+    /// Records deleted since they were indexed are skipped.
+    pub async fn fetch(self, conn: &mut ConnectionGuard) -> Result<Vec<Hit<T>>, Error> {
+        self.fetch_with(engine()?.as_ref().as_ref(), conn).await
+    }
+
+    /// Run the search with the provided search engine.
+    pub async fn fetch_with(
+        self,
+        engine: &dyn Engine,
+        conn: &mut ConnectionGuard,
+    ) -> Result<Vec<Hit<T>>, Error> {
+        let matches = engine.search(&T::search_index(), &self.query).await?;
+
+        if matches.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let ids = matches.iter().map(|m| m.id).collect::<Vec<_>>();
+        let mut records = T::filter(
+            Column::new(T::table_name(), T::primary_key()),
+            ids.as_slice(),
+        )
+        .fetch_all(conn)
+        .await?
+        .into_iter()
+        .filter_map(|record| Some((id(&record.id())?, record)))
+        .collect::<HashMap<_, _>>();
+
+        Ok(matches
+            .into_iter()
+            .filter_map(|m| {
+                Some(Hit {
+                    record: records.remove(&m.id)?,
+                    highlights: m
+                        .highlights
+                        .into_iter()
+                        .map(|(field, text)| {
+                            let text = escape(&text, &self.query.pre_tag, &self.query.post_tag);
+                            (field, text)
+                        })
+                        .collect(),
+                })
+            })
+            .collect())
+    }
+}
+
+/// Syncs records with the search engine in the background. Register it with the worker
+/// for each searchable model:
+///
+/// ```ignore
+/// Worker::new(vec![Indexer::<Post>::new().job()]).start().await?;
+/// ```
+pub struct Indexer<T> {
+    _model: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for Indexer<T> {
+    fn default() -> Self {
+        Self {
+            _model: PhantomData,
+        }
+    }
+}
+
+impl<T: Searchable> Indexer<T> {
+    /// Create the indexer job.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue records to be synced with the search engine.
+    pub async fn index_later(&self, ids: &[i64]) -> Result<(), Error> {
+        Ok(self.execute_async(json!({ "ids": ids })).await?)
+    }
+
+    /// Sync the records with the search engine now: records which exist are indexed,
+    /// the others removed from the index.
+    pub async fn sync(
+        &self,
+        engine: &dyn Engine,
+        ids: &[i64],
+        conn: &mut ConnectionGuard,
+    ) -> Result<(), Error> {
+        let records = T::filter(Column::new(T::table_name(), T::primary_key()), ids)
+            .fetch_all(conn)
+            .await?;
+
+        let index = T::search_index();
+        let documents = records
+            .iter()
+            .map(|record| record.search_document().into())
+            .collect::<Vec<_>>();
+        let found = records
+            .iter()
+            .filter_map(|record| id(&record.id()))
+            .collect::<Vec<_>>();
+        let deleted = ids
+            .iter()
+            .filter(|id| !found.contains(id))
+            .copied()
+            .collect::<Vec<_>>();
+
+        if !documents.is_empty() {
+            engine.index(&index, T::primary_key(), &documents).await?;
+        }
+
+        if !deleted.is_empty() {
+            engine.delete(&index, &deleted).await?;
+        }
+
+        info!(
+            "{} indexed {} and removed {} records from \"{}\"",
+            engine.name().purple(),
+            documents.len(),
+            deleted.len(),
+            index,
+        );
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: Searchable> Job for Indexer<T> {
+    async fn execute(&self, args: Json) -> Result<(), JobError> {
+        let ids = args["ids"]
+            .as_array()
+            .ok_or(JobError::Unknown("ids are required".into()))?
+            .iter()
+            .filter_map(|id| id.as_i64())
+            .collect::<Vec<_>>();
+
+        let engine = engine().map_err(|err| JobError::Unknown(err.to_string()))?;
+        let mut conn = Pool::connection().await?;
+
+        self.sync(engine.as_ref().as_ref(), &ids, &mut conn)
+            .await
+            .map_err(|err| match err {
+                // Search engine is temporarily unavailable.
+                Error::Http(_) => JobError::Retry,
+                err => JobError::Unknown(err.to_string()),
+            })
+    }
+}
+
+/// Get the primary key of a record as an integer.
+fn id(value: &Value) -> Option<i64> {
+    match value {
+        Value::Integer(id) | Value::BigInt(id) => Some(*id),
+        Value::Int(id) => Some(*id as i64),
+        Value::SmallInt(id) => Some(*id as i64),
+        Value::Optional(value) => value.as_ref().as_ref().and_then(id),
+        _ => None,
+    }
+}
+
+/// Escape the highlighted text, keeping the highlight tags.
+fn escape(text: &str, pre_tag: &str, post_tag: &str) -> String {
+    text.split(pre_tag)
+        .map(|part| {
+            part.split(post_tag)
+                .map(crate::safe_html)
+                .collect::<Vec<_>>()
+                .join(post_tag)
+        })
+        .collect::<Vec<_>>()
+        .join(pre_tag)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{Error as ModelError, FromRow, ToValue};
+    use parking_lot::Mutex;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Article {
+        id: Option<i64>,
+        title: String,
+    }
+
+    impl FromRow for Article {
+        fn from_row(row: tokio_postgres::Row) -> Result<Self, ModelError> {
+            Ok(Self {
+                id: row.try_get("id")?,
+                title: row.try_get("title")?,
+            })
+        }
+    }
+
+    impl Model for Article {
+        fn id(&self) -> Value {
+            self.id.to_value()
+        }
+
+        fn table_name() -> &'static str {
+            "rwf_test_search_articles"
+        }
+
+        fn foreign_key() -> &'static str {
+            "article_id"
+        }
+
+        fn column_names() -> &'static [&'static str] {
+            &["title"]
+        }
+
+        fn values(&self) -> Vec<Value> {
+            vec![self.title.to_value()]
+        }
+    }
+
+    impl Searchable for Article {
+        fn searchable_fields() -> &'static [&'static str] {
+            &["title"]
+        }
+
+        fn search_document(&self) -> Document {
+            Document::new(self).field("title", &self.title)
+        }
+    }
+
+    /// Search engine keeping documents in memory.
+    #[derive(Default)]
+    struct Memory {
+        documents: Mutex<HashMap<i64, Json>>,
+    }
+
+    #[async_trait]
+    impl Engine for Memory {
+        async fn index(
+            &self,
+            _index: &str,
+            primary_key: &str,
+            documents: &[Json],
+        ) -> Result<(), Error> {
+            let mut indexed = self.documents.lock();
+            for document in documents {
+                indexed.insert(document[primary_key].as_i64().unwrap(), document.clone());
+            }
+            Ok(())
+        }
+
+        async fn delete(&self, _index: &str, ids: &[i64]) -> Result<(), Error> {
+            let mut indexed = self.documents.lock();
+            for id in ids {
+                indexed.remove(id);
+            }
+            Ok(())
+        }
+
+        async fn search(&self, _index: &str, query: &SearchQuery) -> Result<Vec<Match>, Error> {
+            let mut matches = self
+                .documents
+                .lock()
+                .iter()
+                .filter_map(|(id, document)| {
+                    let title = document["title"].as_str()?;
+                    title.contains(&query.query).then(|| Match {
+                        id: *id,
+                        highlights: HashMap::from([(
+                            "title".to_string(),
+                            title.replace(
+                                &query.query,
+                                &format!("{}{}{}", query.pre_tag, query.query, query.post_tag),
+                            ),
+                        )]),
+                    })
+                })
+                .collect::<Vec<_>>();
+            matches.sort_by_key(|m| -m.id);
+            Ok(matches)
+        }
+
+        fn name(&self) -> &'static str {
+            "memory"
+        }
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("<b>a</b> <mark>rust</mark>", "<mark>", "</mark>"),
+            "&lt;b&gt;a&lt;/b&gt; <mark>rust</mark>"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search() -> Result<(), Error> {
+        let pool = Pool::from_env();
+        let mut conn = pool.transaction().await?;
+
+        conn.client()
+            .execute(
+                "CREATE TABLE rwf_test_search_articles (id BIGSERIAL PRIMARY KEY, title VARCHAR NOT NULL)",
+                &[],
+            )
+            .await
+            .map_err(ModelError::from)?;
+
+        let mut ids = vec![];
+        for title in ["Rust <3", "Python", "Rust web apps"] {
+            let article = Article {
+                id: None,
+                title: title.into(),
+            }
+            .save()
+            .fetch(&mut conn)
+            .await?;
+            ids.push(article.id.unwrap());
+        }
+
+        let engine = Memory::default();
+        let indexer = Indexer::<Article>::new();
+        indexer.sync(&engine, &ids, &mut conn).await?;
+        assert_eq!(engine.documents.lock().len(), 3);
+
+        let hits = Search::<Article>::query("Rust")
+            .fetch_with(&engine, &mut conn)
+            .await?;
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].record.title, "Rust web apps");
+        assert_eq!(hits[1].highlight("title"), Some("<mark>Rust</mark> &lt;3"));
+
+        // Deleted records are removed from the index.
+        conn.client()
+            .execute(
+                "DELETE FROM rwf_test_search_articles WHERE id = $1",
+                &[&ids[0]],
+            )
+            .await
+            .map_err(ModelError::from)?;
+        indexer.sync(&engine, &ids, &mut conn).await?;
+        assert_eq!(engine.documents.lock().len(), 2);
+
+        let hits = Article::search("Rust")
+            .fetch_with(&engine, &mut conn)
+            .await?;
+        assert_eq!(hits.len(), 1);
+
+        conn.rollback().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+//! Errors returned by the object storage.
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    /// Bucket or credentials are missing from the configuration.
+    #[error("storage is not configured: {0} is missing")]
+    NotConfigured(&'static str),
+
+    /// The upload token is malformed or was issued by someone else.
+    #[error("upload token is invalid")]
+    InvalidToken,
+
+    /// The upload token expired.
+    #[error("upload token expired")]
+    Expired,
+
+    /// The storage service returned an unexpected response.
+    #[error("storage service returned {0}")]
+    Status(u16),
+
+    /// Error sending a request to the storage service.
+    #[error("{0}")]
+    Http(#[from] crate::http::client::Error),
+
+    /// Error returned by cryptographic functions.
+    #[error("{0}")]
+    Crypto(#[from] crate::crypto::Error),
+
+    /// Error encoding/decoding JSON.
+    #[error("json: {0}")]
+    Json(#[from] serde_json::Error),
+}
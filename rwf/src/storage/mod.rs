@@ -0,0 +1,21 @@
+//! S3-compatible object storage.
+//!
+//! Rwf doesn't proxy file uploads through the app server. Instead, it signs requests
+//! that allow clients to upload and download objects directly from the bucket configured
+//! in the `[storage]` section of `rwf.toml`:
+//!
+//! ```toml
+//! [storage]
+//! bucket = "my-app-uploads"
+//! region = "us-east-1"
+//! ```
+//!
+//! Credentials are read from the configuration or from the standard `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`
+//! environment variables.
+pub mod error;
+pub mod s3;
+pub mod upload;
+
+pub use error::Error;
+pub use s3::{PresignedPost, S3};
+pub use upload::{Upload, UploadController};
@@ -0,0 +1,200 @@
+//! Direct-to-storage uploads.
+//!
+//! Large files can be uploaded by the browser directly to the bucket, bypassing the app server.
+//! The [`UploadController`] hands out presigned URLs together with an encrypted upload token,
+//! and accepts the token back once the upload is finished, so the object can be attached to a model.
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+use super::{Error, S3};
+use crate::config::get_config;
+use crate::controller::{Controller, Error as ControllerError, SessionId};
+use crate::crypto::{decrypt, encrypt};
+use crate::http::{Request, Response};
+
+/// Object the client was allowed to upload.
+///
+/// Its token is encrypted with the application secret key, so the client can't change
+/// the object key, and is only valid for the session it was issued to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Upload {
+    /// Object key in the bucket.
+    #[serde(rename = "k")]
+    pub key: String,
+    /// Token expiration (UNIX timestamp in UTC).
+    #[serde(rename = "e")]
+    pub expiration: i64,
+    #[serde(rename = "s")]
+    session_id: SessionId,
+    /// Size of the uploaded object in bytes, known once the upload is verified.
+    #[serde(skip)]
+    pub size: u64,
+}
+
+impl Upload {
+    /// Allow the session to upload an object with the given key.
+    pub fn new(key: impl ToString, session_id: &SessionId, expires_in: Duration) -> Self {
+        Self {
+            key: key.to_string(),
+            expiration: (OffsetDateTime::now_utc() + expires_in).unix_timestamp(),
+            session_id: session_id.clone(),
+            size: 0,
+        }
+    }
+
+    /// Encrypted token the client sends back once the upload is complete.
+    pub fn token(&self) -> Result<String, Error> {
+        Ok(encrypt(&serde_json::to_vec(self)?)?)
+    }
+
+    /// Decrypt and validate the upload token.
+    pub fn from_token(token: &str, session_id: &SessionId) -> Result<Self, Error> {
+        let upload: Self =
+            serde_json::from_slice(&decrypt(token).map_err(|_| Error::InvalidToken)?)?;
+
+        if &upload.session_id != session_id {
+            Err(Error::InvalidToken)
+        } else if upload.expiration < OffsetDateTime::now_utc().unix_timestamp() {
+            Err(Error::Expired)
+        } else {
+            Ok(upload)
+        }
+    }
+
+    /// Object URL in the configured bucket.
+    pub fn url(&self) -> Result<String, Error> {
+        Ok(S3::from_config()?.url(&self.key))
+    }
+}
+
+/// Controller that lets clients upload files directly to the bucket.
+///
+/// - `GET` returns a presigned `PUT` URL, a presigned `POST` form and the upload token, as JSON
+/// - `POST` accepts the `token` (JSON or form field) after the upload finished, checks that the object exists
+///   and isn't larger than [`UploadController::max_size`], and calls [`UploadController::attach`]
+///
+/// #### Example
+///
+/// ```
+/// use rwf::prelude::*;
+/// use rwf::storage::{Upload, UploadController};
+///
+/// #[derive(Default, macros::UploadController)]
+/// struct AvatarUpload;
+///
+/// #[async_trait]
+/// impl UploadController for AvatarUpload {
+///     async fn attach(&self, request: &Request, upload: Upload) -> Result<Response, Error> {
+///         // Save `upload.key` on the user model.
+///         Ok(Response::new().json(serde_json::json!({"url": upload.url()?}))?)
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait UploadController: Controller {
+    /// Object key for a new upload. Defaults to a random key in the `uploads/` directory.
+    fn key(&self, request: &Request) -> String {
+        let _ = request;
+        format!("uploads/{}", uuid::Uuid::new_v4())
+    }
+
+    /// How long the presigned URLs and the upload token are valid for.
+    fn expires_in(&self) -> Duration {
+        get_config().storage.upload_expires()
+    }
+
+    /// Maximum size of the upload. Enforced by the presigned `POST` form
+    /// and checked before the object is attached.
+    fn max_size(&self) -> u64 {
+        get_config().storage.max_upload_size
+    }
+
+    /// Attach the uploaded object to a model.
+    async fn attach(&self, request: &Request, upload: Upload) -> Result<Response, ControllerError>;
+
+    /// Mint presigned URLs for a new upload.
+    async fn presign(&self, request: &Request) -> Result<Response, ControllerError> {
+        let s3 = S3::from_config()?;
+        let key = self.key(request);
+        let expires_in = self.expires_in();
+        let upload = Upload::new(&key, &request.session_id(), expires_in);
+
+        Ok(Response::new().json(serde_json::json!({
+            "key": key,
+            "token": upload.token()?,
+            "put": s3.presigned_put(&key, expires_in),
+            "post": s3.presigned_post(&key, expires_in, self.max_size()),
+        }))?)
+    }
+
+    /// Handle the presign and upload callback requests.
+    async fn handle(&self, request: &Request) -> Result<Response, ControllerError> {
+        if request.get() {
+            UploadController::presign(self, request).await
+        } else if request.post() {
+            let token = match request.json_raw() {
+                Ok(json) => json["token"].as_str().map(|token| token.to_string()),
+                Err(_) => request.form_data()?.get::<String>("token"),
+            };
+
+            let token = match token {
+                Some(token) => token,
+                None => return Ok(Response::bad_request()),
+            };
+
+            let mut upload = match Upload::from_token(&token, &request.session_id()) {
+                Ok(upload) => upload,
+                Err(_) => return Ok(Response::forbidden()),
+            };
+
+            // Presigned PUT URLs don't limit the size of the object,
+            // so it's checked before the object is attached.
+            let s3 = S3::from_config()?;
+
+            match s3.size(&upload.key).await? {
+                None => Ok(Response::bad_request()),
+                Some(size) if size > self.max_size() => {
+                    s3.delete(&upload.key).await?;
+                    Ok(Response::content_too_large())
+                }
+                Some(size) => {
+                    upload.size = size;
+                    self.attach(request, upload).await
+                }
+            }
+        } else {
+            Ok(Response::method_not_allowed())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_upload_token() {
+        let session_id = SessionId::Authenticated(5);
+        let upload = Upload::new("uploads/a.png", &session_id, Duration::minutes(5));
+        let token = upload.token().unwrap();
+
+        assert_eq!(Upload::from_token(&token, &session_id).unwrap(), upload);
+        assert!(matches!(
+            Upload::from_token(&token, &SessionId::Authenticated(6)),
+            Err(Error::InvalidToken)
+        ));
+        assert!(matches!(
+            Upload::from_token("apples", &session_id),
+            Err(Error::InvalidToken)
+        ));
+
+        let expired = Upload::new("uploads/a.png", &session_id, Duration::minutes(-5))
+            .token()
+            .unwrap();
+        assert!(matches!(
+            Upload::from_token(&expired, &session_id),
+            Err(Error::Expired)
+        ));
+    }
+}
@@ -0,0 +1,460 @@
+//! Maintenance tasks.
+//!
+//! Tasks keep the database healthy: they vacuum tables which change a lot, like the job queue,
+//! and delete rows which aren't needed anymore, like expired sessions or old audit log entries.
+//! Rows are deleted in small batches, each in its own transaction, so tasks don't hold locks
+//! for long or produce large bursts of WAL, and can run while the database is being backed up.
+//!
+//! Built-in tasks:
+//!
+//! | Name | What it does |
+//! |------|--------------|
+//! | `vacuum` | Vacuums and analyzes the job queue, session, nonce, rate limit and outbox tables, if they exist. |
+//! | `prune_jobs` | Deletes jobs completed more than 30 days ago. |
+//! | `prune_sessions` | Deletes expired sessions and nonces. |
+//! | `prune_audit_log` | Deletes admin audit log entries older than 90 days. |
+//!
+//! Applications can add their own tasks, or replace built-in ones, e.g. to change how long
+//! data is kept, with [`register`]. Tasks are run with `rwf-cli tasks:run <name>`, or on a schedule
+//! using the [`Maintenance`] job.
+//!
+//! # Example
+//!
+//! ```
+//! use rwf::tasks::{self, PruneAuditLog, PruneDeleted};
+//! # use rwf::prelude::*;
+//! # #[derive(Clone, macros::Model)]
+//! # struct User { id: Option<i64>, deleted_at: Option<OffsetDateTime> }
+//!
+//! // Keep the audit log for a year.
+//! tasks::register(PruneAuditLog::default().older_than(Duration::days(365)));
+//!
+//! // Delete users soft-deleted more than 30 days ago.
+//! tasks::register(PruneDeleted::<User>::new("deleted_at").older_than(Duration::days(30)));
+//!
+//! assert!(tasks::get("prune_deleted_users").is_some());
+//! ```
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde_json::json;
+use time::{Duration, OffsetDateTime};
+use tokio_postgres::types::ToSql;
+use tracing::info;
+
+use crate::colors::MaybeColorize;
+use crate::job::{clock::ScheduledJob, Error, Job};
+use crate::model::{ConnectionGuard, Model, Pool};
+
+/// Most rows deleted by one statement.
+const BATCH_SIZE: i64 = 10_000;
+
+static TASKS: Lazy<RwLock<Vec<Arc<dyn Task>>>> = Lazy::new(|| {
+    RwLock::new(vec![
+        Arc::new(Vacuum::default()),
+        Arc::new(PruneJobs::default()),
+        Arc::new(PruneSessions),
+        Arc::new(PruneAuditLog::default()),
+    ])
+});
+
+/// A maintenance task.
+#[async_trait]
+pub trait Task: Send + Sync {
+    /// Name used to run the task, e.g. `vacuum`. Must be unique.
+    fn name(&self) -> &str;
+
+    /// What the task does.
+    fn description(&self) -> String {
+        String::new()
+    }
+
+    /// Run the task.
+    async fn run(&self) -> Result<(), Error>;
+}
+
+/// Add the task, replacing the task with the same name, if any.
+pub fn register(task: impl Task + 'static) {
+    let mut tasks = TASKS.write();
+    tasks.retain(|registered| registered.name() != task.name());
+    tasks.push(Arc::new(task));
+}
+
+/// Get the task with the name.
+pub fn get(name: &str) -> Option<Arc<dyn Task>> {
+    TASKS
+        .read()
+        .iter()
+        .find(|task| task.name() == name)
+        .cloned()
+}
+
+/// All registered tasks, including built-in ones.
+pub fn tasks() -> Vec<Arc<dyn Task>> {
+    TASKS.read().clone()
+}
+
+/// Run the task with the name.
+pub async fn run(name: &str) -> Result<(), Error> {
+    let task = get(name).ok_or(Error::Unknown(format!("task \"{}\" not found", name)))?;
+
+    info!("running task {}", name.green());
+    task.run().await
+}
+
+/// Schedule the task, e.g. `schedule("0 3 * * *", "vacuum")` vacuums tables every night at 3 am.
+/// The [`Maintenance`] job must be registered with the worker.
+pub fn schedule(cron: &str, task: &str) -> Result<ScheduledJob, Error> {
+    Maintenance.schedule(json!({ "task": task }), cron)
+}
+
+/// Job running maintenance tasks in the background, on a schedule created with [`schedule`].
+///
+/// The name of the task is passed in the `task` argument.
+#[derive(Default)]
+pub struct Maintenance;
+
+#[async_trait]
+impl Job for Maintenance {
+    async fn execute(&self, args: serde_json::Value) -> Result<(), Error> {
+        match args["task"].as_str() {
+            Some(name) => run(name).await,
+            None => Err(Error::Unknown("task name is missing".into())),
+        }
+    }
+
+    fn retries(&self) -> i64 {
+        3
+    }
+}
+
+/// Delete rows matching the condition in batches, committing each batch.
+/// Returns the number of deleted rows.
+async fn delete_in_batches(
+    conn: &mut ConnectionGuard,
+    table: &str,
+    condition: &str,
+    params: &[&(dyn ToSql + Sync)],
+) -> Result<u64, Error> {
+    let query = format!(
+        r#"DELETE FROM "{table}" WHERE ctid IN (SELECT ctid FROM "{table}" WHERE {condition} LIMIT {BATCH_SIZE})"#,
+    );
+    let mut deleted = 0;
+
+    loop {
+        let rows = conn.client().execute(&query, params).await?;
+        deleted += rows;
+
+        if rows < BATCH_SIZE as u64 {
+            return Ok(deleted);
+        }
+    }
+}
+
+/// The table exists. Tables of optional features, e.g. sessions, are only
+/// created by their migration.
+async fn exists(conn: &ConnectionGuard, table: &str) -> Result<bool, Error> {
+    let row = conn
+        .client()
+        .query_one("SELECT to_regclass($1) IS NOT NULL", &[&table])
+        .await?;
+
+    Ok(row.get(0))
+}
+
+/// Vacuum and analyze tables, so Postgres can reuse space taken by deleted rows
+/// and plans queries with fresh statistics.
+#[derive(Debug, Clone)]
+pub struct Vacuum {
+    tables: Vec<String>,
+}
+
+impl Default for Vacuum {
+    fn default() -> Self {
+        Self::tables(&[
+            "rwf_jobs",
+            "rwf_sessions",
+            "rwf_nonces",
+            "rwf_rate_limits",
+            "rwf_outbox",
+        ])
+    }
+}
+
+impl Vacuum {
+    /// Vacuum these tables instead.
+    pub fn tables(tables: &[&str]) -> Self {
+        Self {
+            tables: tables.iter().map(|table| table.to_string()).collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl Task for Vacuum {
+    fn name(&self) -> &str {
+        "vacuum"
+    }
+
+    fn description(&self) -> String {
+        format!("Vacuum and analyze {}", self.tables.join(", "))
+    }
+
+    async fn run(&self) -> Result<(), Error> {
+        let conn = Pool::connection().await?;
+
+        for table in &self.tables {
+            if !exists(&conn, table).await? {
+                continue;
+            }
+
+            // Not VACUUM FULL, which locks the table and rewrites it.
+            conn.client()
+                .batch_execute(&format!(r#"VACUUM (ANALYZE) "{}""#, table))
+                .await?;
+            info!("vacuumed {}", table);
+        }
+
+        Ok(())
+    }
+}
+
+/// Delete jobs which completed a while ago. Failed jobs which are
+/// still retried, or dead, are kept.
+#[derive(Debug, Clone)]
+pub struct PruneJobs {
+    older_than: Duration,
+}
+
+impl Default for PruneJobs {
+    fn default() -> Self {
+        Self {
+            older_than: Duration::days(30),
+        }
+    }
+}
+
+impl PruneJobs {
+    /// Delete jobs completed longer ago than this.
+    pub fn older_than(mut self, older_than: Duration) -> Self {
+        self.older_than = older_than;
+        self
+    }
+}
+
+#[async_trait]
+impl Task for PruneJobs {
+    fn name(&self) -> &str {
+        "prune_jobs"
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Delete jobs completed more than {} days ago",
+            self.older_than.whole_days()
+        )
+    }
+
+    async fn run(&self) -> Result<(), Error> {
+        let mut conn = Pool::connection().await?;
+        let cutoff = OffsetDateTime::now_utc() - self.older_than;
+        let deleted =
+            delete_in_batches(&mut conn, "rwf_jobs", "completed_at < $1", &[&cutoff]).await?;
+        info!("deleted {} completed jobs", deleted);
+
+        Ok(())
+    }
+}
+
+/// Delete expired sessions and nonces.
+#[derive(Debug, Clone, Default)]
+pub struct PruneSessions;
+
+#[async_trait]
+impl Task for PruneSessions {
+    fn name(&self) -> &str {
+        "prune_sessions"
+    }
+
+    fn description(&self) -> String {
+        "Delete expired sessions and nonces".into()
+    }
+
+    async fn run(&self) -> Result<(), Error> {
+        let mut conn = Pool::connection().await?;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        for table in ["rwf_sessions", "rwf_nonces"] {
+            if !exists(&conn, table).await? {
+                continue;
+            }
+
+            let deleted = delete_in_batches(&mut conn, table, "expires_at <= $1", &[&now]).await?;
+            info!("deleted {} expired rows from {}", deleted, table);
+        }
+
+        Ok(())
+    }
+}
+
+/// Delete old entries from the admin audit log.
+#[derive(Debug, Clone)]
+pub struct PruneAuditLog {
+    older_than: Duration,
+}
+
+impl Default for PruneAuditLog {
+    fn default() -> Self {
+        Self {
+            older_than: Duration::days(90),
+        }
+    }
+}
+
+impl PruneAuditLog {
+    /// Delete entries older than this.
+    pub fn older_than(mut self, older_than: Duration) -> Self {
+        self.older_than = older_than;
+        self
+    }
+}
+
+#[async_trait]
+impl Task for PruneAuditLog {
+    fn name(&self) -> &str {
+        "prune_audit_log"
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Delete audit log entries older than {} days",
+            self.older_than.whole_days()
+        )
+    }
+
+    async fn run(&self) -> Result<(), Error> {
+        let mut conn = Pool::connection().await?;
+
+        if !exists(&conn, "rwf_admin_audit_log").await? {
+            return Ok(());
+        }
+
+        let cutoff = OffsetDateTime::now_utc() - self.older_than;
+        let deleted = delete_in_batches(
+            &mut conn,
+            "rwf_admin_audit_log",
+            "created_at < $1",
+            &[&cutoff],
+        )
+        .await?;
+        info!("deleted {} audit log entries", deleted);
+
+        Ok(())
+    }
+}
+
+/// Delete records of a model which were soft-deleted a while ago, i.e. their
+/// deletion timestamp column is older than the retention period.
+///
+/// The task is named `prune_deleted_<table>`.
+pub struct PruneDeleted<T: Model> {
+    name: String,
+    column: String,
+    older_than: Duration,
+    _model: PhantomData<fn() -> T>,
+}
+
+impl<T: Model> PruneDeleted<T> {
+    /// Prune records soft-deleted using the column, e.g. `deleted_at`. Records are kept
+    /// for 30 days by default.
+    pub fn new(column: &str) -> Self {
+        Self {
+            name: format!("prune_deleted_{}", T::table_name()),
+            column: column.to_string(),
+            older_than: Duration::days(30),
+            _model: PhantomData,
+        }
+    }
+
+    /// Delete records soft-deleted longer ago than this.
+    pub fn older_than(mut self, older_than: Duration) -> Self {
+        self.older_than = older_than;
+        self
+    }
+}
+
+#[async_trait]
+impl<T: Model> Task for PruneDeleted<T> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Delete {} soft-deleted more than {} days ago",
+            T::table_name(),
+            self.older_than.whole_days()
+        )
+    }
+
+    async fn run(&self) -> Result<(), Error> {
+        let mut conn = Pool::connection().await?;
+        let cutoff = OffsetDateTime::now_utc() - self.older_than;
+        let condition = format!(r#""{}" < $1"#, self.column);
+        let deleted = delete_in_batches(&mut conn, T::table_name(), &condition, &[&cutoff]).await?;
+        info!("deleted {} soft-deleted {}", deleted, T::table_name());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Counter;
+
+    #[async_trait]
+    impl Task for Counter {
+        fn name(&self) -> &str {
+            "test_counter"
+        }
+
+        async fn run(&self) -> Result<(), Error> {
+            RUNS.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tasks() {
+        assert!(get("vacuum").is_some());
+        assert!(get("prune_sessions").is_some());
+
+        register(Counter);
+        register(Counter);
+        assert_eq!(
+            tasks()
+                .iter()
+                .filter(|task| task.name() == "test_counter")
+                .count(),
+            1
+        );
+
+        run("test_counter").await.unwrap();
+        Maintenance
+            .execute(json!({ "task": "test_counter" }))
+            .await
+            .unwrap();
+        assert_eq!(RUNS.load(Ordering::Relaxed), 2);
+
+        assert!(run("missing").await.is_err());
+        assert!(Maintenance.execute(json!({})).await.is_err());
+    }
+}
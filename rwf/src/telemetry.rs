@@ -0,0 +1,317 @@
+//! OpenTelemetry instrumentation.
+//!
+//! With the `telemetry` feature enabled, the HTTP server, ORM queries, template rendering and background jobs
+//! create [`tracing`] spans and record metrics, which are exported to an OTLP collector, e.g. the OpenTelemetry Collector,
+//! Grafana Alloy or Jaeger. Exporting starts when the [`crate::logging::Logger`] is initialized.
+//!
+//! ### Example
+//!
+//! ```toml
+//! [telemetry]
+//! endpoint = "http://localhost:4318"
+//! service_name = "my-app"
+//! ```
+//!
+//! Without the feature, these functions do nothing.
+#![cfg_attr(not(feature = "telemetry"), allow(unused_variables))]
+use std::time::Duration;
+
+use tracing::Span;
+
+use crate::http::{Handler, Request, Response};
+
+#[cfg(feature = "telemetry")]
+pub use otel::layer;
+
+/// Span of an HTTP request. All log lines written while the request is handled include its ID.
+pub(crate) fn request_span(request: &Request) -> Span {
+    #[cfg(feature = "telemetry")]
+    return otel::request_span(request);
+
+    #[cfg(not(feature = "telemetry"))]
+    match request.trace() {
+        Some(trace) => {
+            tracing::info_span!("request", id = %request.id(), trace_id = %trace.trace_id)
+        }
+        None => tracing::info_span!("request", id = %request.id()),
+    }
+}
+
+/// Record the response to the request.
+pub(crate) fn record_request(
+    span: &Span,
+    request: &Request,
+    handler: Option<&Handler>,
+    response: &Response,
+    duration: Duration,
+) {
+    #[cfg(feature = "telemetry")]
+    otel::record_request(span, request, handler, response, duration);
+}
+
+/// Span of an ORM query.
+pub(crate) fn query_span(model: &str, action: &str, query: impl FnOnce() -> String) -> Span {
+    #[cfg(feature = "telemetry")]
+    return tracing::info_span!(
+        "query",
+        otel.name = %format!("{} {}", action, model),
+        otel.kind = "client",
+        db.system.name = "postgresql",
+        db.query.text = %query(),
+    );
+
+    #[cfg(not(feature = "telemetry"))]
+    Span::none()
+}
+
+/// Record how long a query took.
+pub(crate) fn record_query(model: &str, action: &str, duration: Duration) {
+    #[cfg(feature = "telemetry")]
+    otel::record(
+        &otel::METRICS.query_duration,
+        duration,
+        &[("db.operation.name", action), ("db.collection.name", model)],
+    );
+}
+
+/// Span of a template rendering.
+pub(crate) fn template_span(path: &str) -> Span {
+    #[cfg(feature = "telemetry")]
+    return tracing::info_span!("render", otel.name = %format!("render {}", path));
+
+    #[cfg(not(feature = "telemetry"))]
+    Span::none()
+}
+
+/// Record how long a template took to render.
+pub(crate) fn record_template(path: &str, duration: Duration) {
+    #[cfg(feature = "telemetry")]
+    otel::record(
+        &otel::METRICS.template_duration,
+        duration,
+        &[("template", path)],
+    );
+}
+
+/// Span of a background job.
+pub(crate) fn job_span(name: &str, id: Option<i64>) -> Span {
+    #[cfg(feature = "telemetry")]
+    return tracing::info_span!(
+        "job",
+        otel.name = %format!("job {}", name),
+        otel.kind = "consumer",
+        job.id = id,
+    );
+
+    #[cfg(not(feature = "telemetry"))]
+    tracing::info_span!("job", name = %name, id = id)
+}
+
+/// Record how long a job took.
+pub(crate) fn record_job(name: &str, ok: bool, duration: Duration) {
+    #[cfg(feature = "telemetry")]
+    otel::record(
+        &otel::METRICS.job_duration,
+        duration,
+        &[("job", name), ("status", if ok { "ok" } else { "error" })],
+    );
+}
+
+/// Export spans and metrics that haven't been sent yet and stop exporting.
+/// Called when the server shuts down.
+pub fn shutdown() {
+    #[cfg(feature = "telemetry")]
+    otel::shutdown();
+}
+
+#[cfg(feature = "telemetry")]
+mod otel {
+    use std::time::Duration;
+
+    use once_cell::sync::{Lazy, OnceCell};
+    use opentelemetry::metrics::Histogram;
+    use opentelemetry::trace::{
+        SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState, TracerProvider,
+    };
+    use opentelemetry::{global, Context, KeyValue};
+    use opentelemetry_otlp::{MetricExporter, SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+    use opentelemetry_sdk::trace::{Sampler, SdkTracer, SdkTracerProvider};
+    use opentelemetry_sdk::Resource;
+    use tracing::{field::Empty, Span, Subscriber};
+    use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+    use tracing_subscriber::registry::LookupSpan;
+
+    use crate::config::get_config;
+    use crate::http::{Handler, Request, Response};
+
+    static PROVIDERS: OnceCell<(SdkTracerProvider, SdkMeterProvider)> = OnceCell::new();
+
+    pub(super) struct Metrics {
+        pub(super) request_duration: Histogram<f64>,
+        pub(super) query_duration: Histogram<f64>,
+        pub(super) template_duration: Histogram<f64>,
+        pub(super) job_duration: Histogram<f64>,
+    }
+
+    pub(super) static METRICS: Lazy<Metrics> = Lazy::new(|| {
+        let meter = global::meter("rwf");
+        let histogram = |name: &'static str, description: &'static str| {
+            meter
+                .f64_histogram(name)
+                .with_unit("s")
+                .with_description(description)
+                .build()
+        };
+
+        Metrics {
+            request_duration: histogram(
+                "http.server.request.duration",
+                "Duration of HTTP requests.",
+            ),
+            query_duration: histogram(
+                "db.client.operation.duration",
+                "Duration of database queries.",
+            ),
+            template_duration: histogram(
+                "rwf.template.render.duration",
+                "Duration of template rendering.",
+            ),
+            job_duration: histogram("rwf.job.duration", "Duration of background jobs."),
+        }
+    });
+
+    /// Layer exporting spans to the collector. Used by the [`crate::logging::Logger`]
+    /// when the `telemetry` feature is enabled; add it to your own subscriber otherwise.
+    pub fn layer<S>() -> Result<OpenTelemetryLayer<S, SdkTracer>, String>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        let config = &get_config().telemetry;
+        let endpoint = config.endpoint.trim_end_matches('/');
+        let resource = Resource::builder()
+            .with_service_name(config.service_name.clone())
+            .build();
+
+        let spans = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/traces", endpoint))
+            .build()
+            .map_err(|err| err.to_string())?;
+        let metrics = MetricExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/metrics", endpoint))
+            .build()
+            .map_err(|err| err.to_string())?;
+
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_batch_exporter(spans)
+            .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                config.sample_ratio,
+            ))))
+            .with_resource(resource.clone())
+            .build();
+        let meter_provider = SdkMeterProvider::builder()
+            .with_reader(
+                PeriodicReader::builder(metrics)
+                    .with_interval(config.metrics_interval().unsigned_abs())
+                    .build(),
+            )
+            .with_resource(resource)
+            .build();
+
+        let tracer = tracer_provider.tracer("rwf");
+        global::set_tracer_provider(tracer_provider.clone());
+        global::set_meter_provider(meter_provider.clone());
+        let _ = PROVIDERS.set((tracer_provider, meter_provider));
+
+        Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+    }
+
+    pub(super) fn request_span(request: &Request) -> Span {
+        let span = tracing::info_span!(
+            "request",
+            id = %request.id(),
+            otel.kind = "server",
+            otel.name = Empty,
+        );
+
+        // Attributes aren't span fields, so they're exported but not added to log lines.
+        span.set_attribute("http.request.method", request.method().to_string());
+        span.set_attribute("url.path", request.path().path().to_string());
+
+        // Continue the caller's trace.
+        if let Some(trace) = request.trace() {
+            if let (Some(parent_id), Ok(trace_id)) =
+                (&trace.parent_id, TraceId::from_hex(&trace.trace_id))
+            {
+                if let Ok(parent_id) = SpanId::from_hex(parent_id) {
+                    let parent = SpanContext::new(
+                        trace_id,
+                        parent_id,
+                        TraceFlags::new(trace.flags),
+                        true,
+                        TraceState::default(),
+                    );
+                    let _ = span.set_parent(Context::new().with_remote_span_context(parent));
+                }
+            }
+        }
+
+        span
+    }
+
+    pub(super) fn record_request(
+        span: &Span,
+        request: &Request,
+        handler: Option<&Handler>,
+        response: &Response,
+        duration: Duration,
+    ) {
+        let method = request.method().to_string();
+        let code = response.status().code();
+        let route = handler.map(|handler| handler.path().path().to_string());
+
+        match route {
+            Some(ref route) => {
+                span.record("otel.name", format!("{} {}", method, route));
+                span.set_attribute("http.route", route.clone());
+            }
+            None => {
+                span.record("otel.name", method.as_str());
+            }
+        }
+        span.set_attribute("http.response.status_code", code as i64);
+
+        let mut attributes = vec![
+            KeyValue::new("http.request.method", method),
+            KeyValue::new("http.response.status_code", code as i64),
+        ];
+        if let Some(route) = route {
+            attributes.push(KeyValue::new("http.route", route));
+        }
+
+        METRICS
+            .request_duration
+            .record(duration.as_secs_f64(), &attributes);
+    }
+
+    pub(super) fn record(
+        histogram: &Histogram<f64>,
+        duration: Duration,
+        attributes: &[(&'static str, &str)],
+    ) {
+        let attributes = attributes
+            .iter()
+            .map(|(name, value)| KeyValue::new(*name, value.to_string()))
+            .collect::<Vec<_>>();
+        histogram.record(duration.as_secs_f64(), &attributes);
+    }
+
+    pub(super) fn shutdown() {
+        if let Some((tracer_provider, meter_provider)) = PROVIDERS.get() {
+            let _ = tracer_provider.shutdown();
+            let _ = meter_provider.shutdown();
+        }
+    }
+}
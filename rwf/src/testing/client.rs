@@ -0,0 +1,349 @@
+//! In-process HTTP client.
+use super::{TestResponse, TestWebsocket};
+use crate::controller::Session;
+use crate::http::{urlencode, Error, Harness, Request, Response, Server};
+use crate::model::Model;
+
+use base64::{engine::general_purpose, Engine as _};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Mutex;
+
+/// Sends requests to the server's controllers without a network connection.
+///
+/// Cookies set by responses, like the session, are sent with the following requests.
+pub struct TestClient {
+    harness: Harness,
+    cookies: Mutex<BTreeMap<String, String>>,
+}
+
+impl TestClient {
+    /// Create a client for the server, running its middleware.
+    pub fn new(server: Server) -> Self {
+        Self {
+            harness: Harness::from(server),
+            cookies: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Create a `GET` request.
+    pub fn get(&self, path: &str) -> TestRequest<'_> {
+        self.request("GET", path)
+    }
+
+    /// Create a `POST` request.
+    pub fn post(&self, path: &str) -> TestRequest<'_> {
+        self.request("POST", path)
+    }
+
+    /// Create a `PUT` request.
+    pub fn put(&self, path: &str) -> TestRequest<'_> {
+        self.request("PUT", path)
+    }
+
+    /// Create a `PATCH` request.
+    pub fn patch(&self, path: &str) -> TestRequest<'_> {
+        self.request("PATCH", path)
+    }
+
+    /// Create a `DELETE` request.
+    pub fn delete(&self, path: &str) -> TestRequest<'_> {
+        self.request("DELETE", path)
+    }
+
+    /// Create a request with any method.
+    pub fn request(&self, method: &str, path: &str) -> TestRequest<'_> {
+        TestRequest {
+            client: self,
+            method: method.to_uppercase(),
+            path: path.to_string(),
+            headers: vec![],
+            body: vec![],
+            session: None,
+            csrf: true,
+        }
+    }
+
+    /// Value of a cookie set by a previous response, e.g. `rwf_session`.
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies.lock().unwrap().get(name).cloned()
+    }
+
+    /// Forget all cookies, e.g. to start a new browser session.
+    pub fn clear_cookies(&self) {
+        self.cookies.lock().unwrap().clear();
+    }
+
+    /// Harness serving the requests, e.g. to check route coverage.
+    pub fn harness(&self) -> &Harness {
+        &self.harness
+    }
+
+    /// Keep the session cookie, like the server sets it when the session changes.
+    async fn save_session(&self, session: Session) -> Result<(), Error> {
+        let request = Request::default();
+        let response = Response::new()
+            .set_session(session)
+            .from_request(&request)?
+            .save_session(&request)
+            .await?;
+        let response = TestResponse::read(response).await?;
+        self.save_cookies(&response);
+
+        Ok(())
+    }
+
+    fn save_cookies(&self, response: &TestResponse) {
+        let mut cookies = self.cookies.lock().unwrap();
+
+        for cookie in response.headers_all("set-cookie") {
+            let mut attributes = cookie.split(';');
+            let (name, value) = match attributes.next().and_then(|pair| pair.split_once('=')) {
+                Some((name, value)) => (name.trim(), value.trim()),
+                None => continue,
+            };
+            let expired = attributes.any(|attribute| {
+                let attribute = attribute.trim().to_lowercase();
+                attribute == "max-age=0" || attribute.starts_with("max-age=-")
+            });
+
+            if expired || value.is_empty() {
+                cookies.remove(name);
+            } else {
+                cookies.insert(name.to_string(), value.to_string());
+            }
+        }
+    }
+}
+
+/// Request built by the [`TestClient`].
+pub struct TestRequest<'a> {
+    client: &'a TestClient,
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    session: Option<Result<Session, Error>>,
+    csrf: bool,
+}
+
+impl TestRequest<'_> {
+    /// Set a request header.
+    pub fn header(mut self, name: impl ToString, value: impl ToString) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Send the value as a JSON body.
+    pub fn json(self, body: &impl Serialize) -> Self {
+        let body = serde_json::to_vec(body).expect("body to serialize to JSON");
+        self.header("content-type", "application/json").body(body)
+    }
+
+    /// Send the fields as a URL-encoded form.
+    pub fn form(self, fields: &[(&str, &str)]) -> Self {
+        let body = fields
+            .iter()
+            .map(|(name, value)| format!("{}={}", urlencode(name), urlencode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+        self.header("content-type", "application/x-www-form-urlencoded")
+            .body(body)
+    }
+
+    /// Set the request body.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Send the request as the user, logged in with an authenticated session.
+    pub fn with_session(mut self, user: &impl Model) -> Self {
+        self.session =
+            Some(crate::http::request::user_id(user).and_then(|user_id| {
+                Ok(Session::new_authenticated(serde_json::json!({}), user_id)?)
+            }));
+        self
+    }
+
+    /// Send the request with the session, e.g. a guest session with a custom payload.
+    pub fn session(mut self, session: Session) -> Self {
+        self.session = Some(Ok(session));
+        self
+    }
+
+    /// Accept a Turbo Stream response, like Turbo does when submitting forms.
+    pub fn turbo(self) -> Self {
+        self.header("accept", "text/vnd.turbo-stream.html, text/html")
+    }
+
+    /// Request the Turbo Frame, like Turbo does when following links inside a frame.
+    pub fn turbo_frame(self, id: impl ToString) -> Self {
+        self.header("turbo-frame", id)
+    }
+
+    /// Don't send the CSRF token, e.g. to test that requests without one are rejected.
+    ///
+    /// By default, requests include a valid token in the `X-CSRF-Token` header.
+    pub fn without_csrf(mut self) -> Self {
+        self.csrf = false;
+        self
+    }
+
+    /// Send the request and wait for the response.
+    pub async fn send(self) -> Result<TestResponse, Error> {
+        let peer = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+        let mut request = Request::read(peer, &self.to_bytes()[..]).await?;
+
+        if let Some(session) = self.session {
+            request = request.set_session(session?);
+        }
+
+        if self.csrf {
+            let token = request.csrf_token()?;
+            request
+                .head_mut()
+                .headers_mut()
+                .insert("x-csrf-token", token);
+        }
+
+        let response = self.client.harness.send(request).await;
+        let response = TestResponse::read(response).await?;
+        self.client.save_cookies(&response);
+
+        Ok(response)
+    }
+
+    /// Upgrade the connection to a WebSocket, e.g. to test a [`WebsocketController`](crate::controller::WebsocketController).
+    ///
+    /// The session is sent in the session cookie, like a browser does, and kept for the following requests.
+    pub async fn websocket(mut self) -> Result<TestWebsocket, super::Error> {
+        if let Some(session) = self.session.take() {
+            self.client.save_session(session?).await?;
+        }
+
+        let key: [u8; 16] = rand::random();
+        let request = self
+            .header("connection", "upgrade")
+            .header("upgrade", "websocket")
+            .header("sec-websocket-key", general_purpose::STANDARD.encode(key))
+            .header("sec-websocket-version", "13");
+
+        TestWebsocket::connect(request.client.harness.connect(), &request.to_bytes()).await
+    }
+
+    /// The request, as sent by a HTTP/1.1 client, with the cookies set by previous responses.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut head = format!("{} {} HTTP/1.1\r\n", self.method, self.path);
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+
+        let cookies = self
+            .client
+            .cookies
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>();
+        if !cookies.is_empty() {
+            head.push_str(&format!("cookie: {}\r\n", cookies.join("; ")));
+        }
+        head.push_str(&format!("content-length: {}\r\n\r\n", self.body.len()));
+
+        let mut bytes = head.into_bytes();
+        bytes.extend(&self.body);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Session, TestClient};
+    use crate::http::Server;
+    use crate::prelude::*;
+
+    #[derive(Default)]
+    struct Counter;
+
+    #[async_trait]
+    impl Controller for Counter {
+        async fn handle(&self, request: &Request) -> Result<Response, Error> {
+            let count = request
+                .session()
+                .payload
+                .get("count")
+                .and_then(|count| count.as_i64())
+                .unwrap_or(0)
+                + 1;
+            let mut session = request.session().clone();
+            session.payload = serde_json::json!({ "count": count });
+
+            Ok(Response::new()
+                .set_session(session)
+                .json(serde_json::json!({ "count": count, "user_id": request.user_id().ok() }))?)
+        }
+    }
+
+    #[derive(Default)]
+    struct Echo;
+
+    #[async_trait]
+    impl Controller for Echo {
+        async fn handle(&self, request: &Request) -> Result<Response, Error> {
+            let body: serde_json::Value = request.json_raw()?;
+            Ok(Response::new().json(body)?.code(201))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client() {
+        let client = TestClient::new(Server::new(vec![
+            route!("/count" => Counter),
+            route!("/echo" => Echo),
+        ]));
+
+        client
+            .get("/count")
+            .send()
+            .await
+            .unwrap()
+            .assert_status(200)
+            .assert_json(serde_json::json!({ "count": 1, "user_id": null }));
+
+        // The session cookie is sent back.
+        assert!(client.cookie("rwf_session").is_some());
+        client
+            .get("/count")
+            .send()
+            .await
+            .unwrap()
+            .assert_json(serde_json::json!({ "count": 2, "user_id": null }));
+
+        client
+            .get("/count")
+            .session(Session::new_authenticated(serde_json::json!({}), 5).unwrap())
+            .send()
+            .await
+            .unwrap()
+            .assert_json(serde_json::json!({ "count": 1, "user_id": 5 }));
+
+        client
+            .post("/echo")
+            .json(&serde_json::json!({ "hello": "world" }))
+            .send()
+            .await
+            .unwrap()
+            .assert_status(201)
+            .assert_header("content-type", "application/json");
+
+        client
+            .get("/missing")
+            .send()
+            .await
+            .unwrap()
+            .assert_status(404);
+    }
+}
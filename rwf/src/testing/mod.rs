@@ -0,0 +1,63 @@
+//! Test controllers in-process, without binding a socket.
+//!
+//! The [`TestClient`] sends requests through the server's middleware, router and controllers,
+//! like a browser would, keeping cookies between requests. Responses have helpers to check
+//! the status, headers, JSON bodies and Turbo Stream fragments:
+//!
+//! ```ignore
+//! use rwf::testing::TestClient;
+//!
+//! #[tokio::test]
+//! async fn test_chat() {
+//!     let client = TestClient::new(Server::new(vec![route!("/chat" => Chat)]));
+//!
+//!     client
+//!         .get("/chat")
+//!         .with_session(&user)
+//!         .send()
+//!         .await
+//!         .unwrap()
+//!         .assert_status(200)
+//!         .assert_contains("Welcome back");
+//! }
+//! ```
+//!
+//! WebSocket controllers are tested by upgrading a request with [`TestRequest::websocket`],
+//! and exchanging messages with the [`TestWebsocket`]:
+//!
+//! ```ignore
+//! let mut websocket = client.get("/chat").with_session(&user).websocket().await?;
+//!
+//! websocket.send("hello").await?;
+//! websocket.assert_turbo_stream("append", "messages", "hello").await;
+//! ```
+pub mod client;
+pub mod response;
+pub mod websocket;
+
+pub use client::{TestClient, TestRequest};
+pub use response::{TestResponse, TurboStreamFragment};
+pub use websocket::TestWebsocket;
+
+use std::time::Duration;
+use thiserror::Error;
+
+/// Error returned by the WebSocket test client.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// Error sending the request or reading the response.
+    #[error("{0}")]
+    Http(#[from] crate::http::Error),
+
+    /// The server didn't upgrade the connection to a WebSocket.
+    #[error("websocket upgrade failed with status {0}")]
+    Upgrade(u16),
+
+    /// No message arrived in time.
+    #[error("no message received in {0:?}")]
+    Timeout(Duration),
+
+    /// The server closed the connection.
+    #[error("websocket connection closed")]
+    Closed,
+}
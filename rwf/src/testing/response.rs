@@ -0,0 +1,234 @@
+//! Response received by the [`TestClient`](super::TestClient), with assertions.
+use crate::http::{Error, Response};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+static TURBO_STREAM: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?s)<turbo-stream\s([^>]*)>(.*?)</turbo-stream>"#).unwrap());
+static ATTRIBUTE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"([\w-]+)="([^"]*)""#).unwrap());
+
+/// Response with its body read into memory.
+#[derive(Debug, Clone)]
+pub struct TestResponse {
+    code: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+/// `<turbo-stream>` element found in a response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TurboStreamFragment {
+    /// Action, e.g. `replace` or `append`.
+    pub action: String,
+    /// ID of the element the action targets.
+    pub target: String,
+    /// HTML inside the `<template>`.
+    pub html: String,
+}
+
+impl TestResponse {
+    pub(crate) async fn read(response: Response) -> Result<Self, Error> {
+        let (code, headers, mut body) = response.into_parts();
+        let mut bytes = vec![];
+        body.send_unframed(&mut bytes).await?;
+
+        Ok(Self {
+            code,
+            headers,
+            body: bytes,
+        })
+    }
+
+    /// Status code, e.g. `200`.
+    pub fn status(&self) -> u16 {
+        self.code
+    }
+
+    /// Value of the header, if set. Case insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Values of all headers with this name, e.g. `set-cookie`.
+    pub fn headers_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.headers
+            .iter()
+            .filter(move |(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Response body.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Response body as text.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+
+    /// Deserialize the JSON body.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+
+    /// `<turbo-stream>` elements in the body.
+    pub fn turbo_streams(&self) -> Vec<TurboStreamFragment> {
+        turbo_streams(&self.text())
+    }
+
+    /// Check the status code.
+    #[track_caller]
+    pub fn assert_status(self, code: u16) -> Self {
+        assert_eq!(
+            self.code,
+            code,
+            "expected status {}, got {}: {}",
+            code,
+            self.code,
+            self.text()
+        );
+        self
+    }
+
+    /// Check the value of a header.
+    #[track_caller]
+    pub fn assert_header(self, name: &str, value: &str) -> Self {
+        assert_eq!(
+            self.header(name),
+            Some(value),
+            "unexpected value of header \"{}\"",
+            name
+        );
+        self
+    }
+
+    /// Check the response redirects to the URL.
+    #[track_caller]
+    pub fn assert_redirect(self, to: &str) -> Self {
+        assert!(
+            (300..400).contains(&self.code),
+            "expected a redirect, got status {}",
+            self.code
+        );
+        self.assert_header("location", to)
+    }
+
+    /// Check the body is equal to the JSON value.
+    #[track_caller]
+    pub fn assert_json(self, expected: Value) -> Self {
+        let json = self
+            .json::<Value>()
+            .unwrap_or_else(|err| panic!("body is not JSON ({}): {}", err, self.text()));
+        assert_eq!(json, expected);
+        self
+    }
+
+    /// Check the body contains the text.
+    #[track_caller]
+    pub fn assert_contains(self, text: &str) -> Self {
+        let body = self.text();
+        assert!(
+            body.contains(text),
+            "body doesn't contain \"{}\": {}",
+            text,
+            body
+        );
+        self
+    }
+
+    /// Check the body has a Turbo Stream with this action and target, whose HTML contains the text.
+    #[track_caller]
+    pub fn assert_turbo_stream(self, action: &str, target: &str, contains: &str) -> Self {
+        let streams = self.turbo_streams();
+        assert!(
+            streams.iter().any(|stream| stream.action == action
+                && stream.target == target
+                && stream.html.contains(contains)),
+            "no <turbo-stream action=\"{}\" target=\"{}\"> containing \"{}\" in {:?}",
+            action,
+            target,
+            contains,
+            streams
+        );
+        self
+    }
+}
+
+/// Find the `<turbo-stream>` elements in the HTML.
+pub(crate) fn turbo_streams(text: &str) -> Vec<TurboStreamFragment> {
+    TURBO_STREAM
+        .captures_iter(text)
+        .map(|captures| {
+            let attribute = |name: &str| {
+                ATTRIBUTE
+                    .captures_iter(&captures[1])
+                    .find(|attribute| &attribute[1] == name)
+                    .map(|attribute| attribute[2].to_string())
+                    .unwrap_or_default()
+            };
+            let html = captures[2].trim();
+            let html = html
+                .strip_prefix("<template>")
+                .and_then(|html| html.strip_suffix("</template>"))
+                .unwrap_or(html);
+
+            TurboStreamFragment {
+                action: attribute("action"),
+                target: attribute("target"),
+                html: html.trim().to_string(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::view::TurboStream;
+
+    #[tokio::test]
+    async fn test_turbo_streams() {
+        let response = Response::new().turbo_stream(&[
+            TurboStream::new("<li>Hello</li>")
+                .action("append")
+                .target("messages"),
+            TurboStream::new("").action("remove").target("message-1"),
+        ]);
+        let response = TestResponse::read(response).await.unwrap();
+
+        assert_eq!(
+            response.turbo_streams(),
+            vec![
+                TurboStreamFragment {
+                    action: "append".into(),
+                    target: "messages".into(),
+                    html: "<li>Hello</li>".into(),
+                },
+                TurboStreamFragment {
+                    action: "remove".into(),
+                    target: "message-1".into(),
+                    html: "".into(),
+                },
+            ]
+        );
+
+        response
+            .assert_header("content-type", "text/vnd.turbo-stream.html")
+            .assert_turbo_stream("append", "messages", "Hello");
+    }
+
+    #[tokio::test]
+    async fn test_redirect() {
+        let response = TestResponse::read(Response::new().redirect("/login"))
+            .await
+            .unwrap();
+        response.assert_redirect("/login");
+    }
+}
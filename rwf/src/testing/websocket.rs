@@ -0,0 +1,286 @@
+//! In-process WebSocket client.
+use super::{response::turbo_streams, Error};
+use crate::http::websocket::{DataFrame, Message, CLOSE_NORMAL};
+use crate::http::ToMessage;
+
+use serde::de::DeserializeOwned;
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream};
+use tokio::time::timeout;
+
+/// WebSocket connection to a controller, opened with [`TestRequest::websocket`](super::TestRequest::websocket).
+///
+/// Pings sent by the server are answered automatically.
+pub struct TestWebsocket {
+    stream: BufReader<DuplexStream>,
+    received: VecDeque<Message>,
+    timeout: Duration,
+}
+
+/// Frame received from the server.
+enum Frame {
+    Message(Message),
+    Pong,
+}
+
+impl TestWebsocket {
+    /// Send the upgrade request and wait for the server to accept messages.
+    pub(crate) async fn connect(stream: DuplexStream, request: &[u8]) -> Result<Self, Error> {
+        let mut stream = BufReader::new(stream);
+        stream
+            .write_all(request)
+            .await
+            .map_err(crate::http::Error::from)?;
+        stream.flush().await.map_err(crate::http::Error::from)?;
+
+        let mut head = vec![];
+        loop {
+            let mut line = String::new();
+            let read = stream
+                .read_line(&mut line)
+                .await
+                .map_err(crate::http::Error::from)?;
+            if read == 0 || line == "\r\n" {
+                break;
+            }
+            head.push(line);
+        }
+
+        let code = head
+            .first()
+            .and_then(|status| status.split_whitespace().nth(1))
+            .and_then(|code| code.parse().ok())
+            .unwrap_or(0);
+        if code != 101 {
+            return Err(Error::Upgrade(code));
+        }
+
+        let mut websocket = Self {
+            stream,
+            received: VecDeque::new(),
+            timeout: Duration::from_secs(5),
+        };
+
+        // The server subscribes the connection to its messages before it answers pings,
+        // so messages sent after this aren't lost.
+        DataFrame::new_ping().flush(&mut websocket.stream).await?;
+        loop {
+            match websocket.frame(websocket.timeout).await? {
+                Some(Frame::Pong) => break,
+                Some(Frame::Message(message)) => websocket.received.push_back(message),
+                None => return Err(Error::Timeout(websocket.timeout)),
+            }
+        }
+
+        Ok(websocket)
+    }
+
+    /// How long to wait for messages before giving up. Defaults to 5 seconds.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Send a message to the controller.
+    pub async fn send(&mut self, message: impl ToMessage) -> Result<(), Error> {
+        message.to_message().send(&mut self.stream).await?;
+        Ok(())
+    }
+
+    /// Wait for the next message, e.g. a reply from the controller or a message sent with [`Comms`](crate::comms::Comms).
+    pub async fn receive(&mut self) -> Result<Message, Error> {
+        match self.receive_within(self.timeout).await? {
+            Some(message) => Ok(message),
+            None => Err(Error::Timeout(self.timeout)),
+        }
+    }
+
+    /// Wait for the next message, and return it as text.
+    pub async fn receive_text(&mut self) -> Result<String, Error> {
+        match self.receive().await? {
+            Message::Text(text) => Ok(text),
+            Message::Binary(bytes) => Ok(String::from_utf8_lossy(&bytes).to_string()),
+        }
+    }
+
+    /// Wait for the next message, and deserialize it from JSON.
+    pub async fn receive_json<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
+        let text = self.receive_text().await?;
+        Ok(serde_json::from_str(&text).map_err(crate::http::Error::from)?)
+    }
+
+    /// Wait for the next message for at most the duration. Returns `None` if no message arrived.
+    pub async fn receive_within(&mut self, wait: Duration) -> Result<Option<Message>, Error> {
+        if let Some(message) = self.received.pop_front() {
+            return Ok(Some(message));
+        }
+
+        loop {
+            match self.frame(wait).await? {
+                Some(Frame::Message(message)) => return Ok(Some(message)),
+                Some(Frame::Pong) => continue,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Check the next message is the text.
+    pub async fn assert_text(&mut self, expected: &str) -> &mut Self {
+        let text = self
+            .receive_text()
+            .await
+            .unwrap_or_else(|err| panic!("expected message \"{}\": {}", expected, err));
+        assert_eq!(text, expected);
+        self
+    }
+
+    /// Check the next message is a Turbo Stream with this action and target, whose HTML contains the text.
+    pub async fn assert_turbo_stream(
+        &mut self,
+        action: &str,
+        target: &str,
+        contains: &str,
+    ) -> &mut Self {
+        let text = self.receive_text().await.unwrap_or_else(|err| {
+            panic!(
+                "expected <turbo-stream action=\"{}\" target=\"{}\">: {}",
+                action, target, err
+            )
+        });
+        let streams = turbo_streams(&text);
+        assert!(
+            streams.iter().any(|stream| stream.action == action
+                && stream.target == target
+                && stream.html.contains(contains)),
+            "no <turbo-stream action=\"{}\" target=\"{}\"> containing \"{}\" in {:?}",
+            action,
+            target,
+            contains,
+            text
+        );
+        self
+    }
+
+    /// Check no message arrives for the duration, e.g. that a broadcast skipped this client.
+    pub async fn assert_no_message(&mut self, wait: Duration) -> &mut Self {
+        match self.receive_within(wait).await {
+            Ok(None) => (),
+            Ok(Some(message)) => panic!("unexpected message: {:?}", message),
+            Err(err) => panic!("{}", err),
+        }
+        self
+    }
+
+    /// Close the connection, and wait for the server to close it too.
+    pub async fn close(mut self) -> Result<(), Error> {
+        DataFrame::new_close(CLOSE_NORMAL, "")
+            .flush(&mut self.stream)
+            .await?;
+
+        loop {
+            match self.frame(self.timeout).await {
+                Err(Error::Closed) => return Ok(()),
+                Err(err) => return Err(err),
+                Ok(None) => return Err(Error::Timeout(self.timeout)),
+                Ok(Some(_)) => continue,
+            }
+        }
+    }
+
+    /// Read the next frame, answering pings. Returns `None` if nothing arrived in time.
+    async fn frame(&mut self, wait: Duration) -> Result<Option<Frame>, Error> {
+        loop {
+            let frame = match timeout(wait, DataFrame::read(&mut self.stream)).await {
+                Ok(frame) => frame?,
+                Err(_) => return Ok(None),
+            };
+
+            if frame.is_ping() {
+                DataFrame::new_pong(frame).flush(&mut self.stream).await?;
+            } else if frame.is_pong() {
+                return Ok(Some(Frame::Pong));
+            } else if frame.is_close() {
+                return Err(Error::Closed);
+            } else {
+                return Ok(Some(Frame::Message(frame.message())));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::TestClient;
+    use crate::comms::Comms;
+    use crate::controller::{auth::SessionId, Session, WebsocketController};
+    use crate::http::{websocket, Server, Stream};
+    use crate::prelude::*;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Chat;
+
+    #[async_trait]
+    impl Controller for Chat {
+        async fn handle(&self, request: &Request) -> Result<Response, Error> {
+            WebsocketController::handle(self, request).await
+        }
+
+        async fn handle_stream(
+            &self,
+            request: &Request,
+            stream: Stream<'_>,
+        ) -> Result<bool, Error> {
+            WebsocketController::handle_stream(self, request, stream).await
+        }
+    }
+
+    #[async_trait]
+    impl WebsocketController for Chat {
+        async fn client_request(
+            &self,
+            _session_id: &SessionId,
+            message: websocket::Message,
+        ) -> Result<Option<websocket::Message>, Error> {
+            Ok(Some(message))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_websocket() {
+        let user_id = rand::random::<i32>() as i64;
+        let client = TestClient::new(Server::new(vec![route!("/chat" => Chat)]));
+
+        let mut websocket = client
+            .get("/chat")
+            .session(Session::new_authenticated(serde_json::json!({}), user_id).unwrap())
+            .websocket()
+            .await
+            .unwrap();
+
+        websocket.send("hello").await.unwrap();
+        websocket.assert_text("hello").await;
+
+        Comms::websocket(user_id)
+            .send(
+                TurboStream::new("<li>hi</li>")
+                    .action("append")
+                    .target("messages"),
+            )
+            .unwrap();
+        websocket
+            .assert_turbo_stream("append", "messages", "hi")
+            .await
+            .assert_no_message(Duration::from_millis(50))
+            .await;
+
+        websocket.close().await.unwrap();
+
+        // Not a WebSocket controller.
+        let client = TestClient::new(Server::new(vec![route!("/" => Chat)]));
+        assert!(matches!(
+            client.get("/missing").websocket().await,
+            Err(super::Error::Upgrade(404))
+        ));
+    }
+}
@@ -2,24 +2,49 @@
 //!
 //! Using the cache ensures that templates are only compiled once, increasing their
 //! execution speed considerably.
-//! The template cache is enabled by default in production (`release`), and disabled
-//! in development (`debug`).
+//! In production (`release`), cached templates are used as-is. In development (`debug`),
+//! templates are compiled again when they change on disk, and the [`crate::hmr`] watcher removes
+//! changed templates from the cache.
 //!
 //! [`Template::load`] uses the template cache automatically.
 use super::{template::Error, Template};
 use crate::config::get_config;
 use std::collections::HashMap;
-use std::path::{Path, PathBuf};
+use std::fs::{metadata, read_dir};
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
+use std::time::SystemTime;
 
 use once_cell::sync::Lazy;
 use parking_lot::{Mutex, MutexGuard};
+use tracing::info;
 
 static TEMPLATES: Lazy<Mutex<Templates>> = Lazy::new(|| Mutex::new(Templates::new()));
 
 /// Templates cache.
 pub struct Templates {
-    templates: HashMap<PathBuf, Arc<Template>>,
+    templates: HashMap<PathBuf, Cached>,
+}
+
+/// Compiled template.
+struct Cached {
+    template: Arc<Template>,
+    // When the template file was last modified, if it's stored on disk.
+    modified: Option<SystemTime>,
+}
+
+impl Cached {
+    fn new(template: Template, modified: Option<SystemTime>) -> Self {
+        Self {
+            template: Arc::new(template),
+            modified,
+        }
+    }
+}
+
+/// When the file was last modified.
+fn modified(path: &Path) -> Option<SystemTime> {
+    metadata(path).and_then(|metadata| metadata.modified()).ok()
 }
 
 impl Templates {
@@ -33,6 +58,9 @@ impl Templates {
     /// Retrieve a template from the cache. If the template doesn't exist, it will be fetched
     /// from disk and compiled.
     ///
+    /// Unless `cache_templates` is enabled, the template is compiled again if the file
+    /// changed since it was cached.
+    ///
     /// # Implementation note
     ///
     /// While this has to be done while holding the global template lock, this operation will be
@@ -44,20 +72,19 @@ impl Templates {
     /// To avoid cold start delays, preload your templates at runtime with [`Templates::preload`].
     pub fn get(&mut self, path: impl AsRef<Path> + Copy) -> Result<Arc<Template>, Error> {
         let cache_templates = get_config().general.cache_templates;
+        let modified = modified(path.as_ref());
 
-        if let Some(t) = self.templates.get(path.as_ref()) {
-            return Ok(t.clone());
+        if let Some(cached) = self.templates.get(path.as_ref()) {
+            if cache_templates || cached.modified == modified {
+                return Ok(cached.template.clone());
+            }
         }
 
-        let template = Arc::new(Template::new(path)?);
+        let cached = Cached::new(Template::new(path)?, modified);
+        let template = cached.template.clone();
+        self.templates.insert(path.as_ref().to_owned(), cached);
 
-        if cache_templates {
-            self.templates
-                .insert(path.as_ref().to_owned(), template.clone());
-            Ok(template)
-        } else {
-            Ok(template)
-        }
+        Ok(template)
     }
 
     /// Compile the template from source and store it in the cache. Requires a globally unique
@@ -69,31 +96,72 @@ impl Templates {
     ) -> Result<Arc<Template>, Error> {
         let cache_templates = get_config().general.cache_templates;
 
-        if let Some(t) = self.templates.get(path.as_ref()) {
-            return Ok(t.clone());
+        if let Some(cached) = self.templates.get(path.as_ref()) {
+            return Ok(cached.template.clone());
         }
 
-        let template = Arc::new(Template::from_str(src)?);
+        let cached = Cached::new(Template::from_str(src)?, None);
+        let template = cached.template.clone();
 
         if cache_templates {
-            self.templates
-                .insert(path.as_ref().to_owned(), template.clone());
-            Ok(template)
-        } else {
-            Ok(template)
+            self.templates.insert(path.as_ref().to_owned(), cached);
         }
+
+        Ok(template)
     }
 
     /// Load and compile a template and store it in the cache. This will ensure
     /// the template is loaded from cache at runtime.
     pub fn preload(&mut self, path: impl AsRef<Path> + Copy) -> Result<(), Error> {
-        let template = Arc::new(Template::new(path)?);
+        let modified = modified(path.as_ref());
+        let template = Template::new(path).map_err(|err| err.pretty_from_path(path))?;
         self.templates
-            .insert(path.as_ref().to_owned(), template.clone());
+            .insert(path.as_ref().to_owned(), Cached::new(template, modified));
 
         Ok(())
     }
 
+    /// Compile all templates in the directory and its subdirectories, and store them in the cache.
+    /// Returns the number of templates compiled. If any template has a syntax error,
+    /// all errors are returned together.
+    pub fn precompile(&mut self, dir: impl AsRef<Path>) -> Result<usize, Error> {
+        let mut paths = vec![];
+        templates_in(dir.as_ref(), &mut paths)?;
+
+        let errors = paths
+            .iter()
+            .filter_map(|path| self.preload(path).err())
+            .map(|err| err.to_string())
+            .collect::<Vec<_>>();
+
+        if errors.is_empty() {
+            Ok(paths.len())
+        } else {
+            Err(Error::Pretty(errors.join("\n\n")))
+        }
+    }
+
+    /// Remove the template from the cache, so it's compiled again the next time it's used.
+    /// Templates are cached with the path they were loaded with, which is often relative
+    /// to the working directory, so the path can be relative or absolute.
+    pub fn invalidate(&mut self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+
+        self.templates.retain(|cached, _| {
+            // `./templates/index.html` and `templates/index.html` are the same template.
+            let cached = cached
+                .components()
+                .filter(|component| component != &Component::CurDir)
+                .collect::<PathBuf>();
+            !path.ends_with(&cached)
+        });
+    }
+
+    /// Remove all templates from the cache.
+    pub fn clear(&mut self) {
+        self.templates.clear();
+    }
+
     /// Load and compile a template from source. A template referenced with this path at runtime
     /// will be fetched from cache.
     ///
@@ -108,9 +176,9 @@ impl Templates {
     ///         .unwrap();
     /// ```
     pub fn preload_str(&mut self, path: impl AsRef<Path> + Copy, src: &str) -> Result<(), Error> {
-        let template = Arc::new(Template::from_str(src)?);
+        let template = Template::from_str(src)?;
         self.templates
-            .insert(path.as_ref().to_owned(), template.clone());
+            .insert(path.as_ref().to_owned(), Cached::new(template, None));
 
         Ok(())
     }
@@ -120,3 +188,108 @@ impl Templates {
         TEMPLATES.lock()
     }
 }
+
+/// Find all files in the directory and its subdirectories. Hidden files, e.g. `.DS_Store`, are skipped.
+fn templates_in(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let entries = read_dir(dir).map_err(|_| Error::TemplateDoesNotExist(dir.to_owned()))?;
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+
+        if path.is_dir() {
+            templates_in(&path, paths)?;
+        } else {
+            paths.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Compile all templates in the template directory, if `precompile_templates` is enabled.
+/// The server refuses to start if any template has a syntax error.
+pub fn verify() -> Result<(), Error> {
+    let config = get_config();
+
+    if !config.general.precompile_templates {
+        return Ok(());
+    }
+
+    let dir = &config.general.template_dir;
+    let compiled = Templates::cache().precompile(dir)?;
+    info!(
+        "Precompiled {} templates in \"{}\"",
+        compiled,
+        dir.display()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::{write, File};
+    use std::time::Duration;
+
+    #[test]
+    fn test_recompile_modified() -> Result<(), Error> {
+        let dir = tempdir::TempDir::new("rwf_cache").unwrap();
+        let path = dir.path().join("index.html");
+        let mut templates = Templates::new();
+
+        write(&path, "<%= 1 %>").unwrap();
+        assert_eq!(templates.get(&path)?.render_default()?, "1");
+
+        // Same modification time, still cached.
+        let modified = metadata(&path).unwrap().modified().unwrap();
+        write(&path, "<%= 2 %>").unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert_eq!(templates.get(&path)?.render_default()?, "1");
+
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified + Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(templates.get(&path)?.render_default()?, "2");
+
+        templates.preload_str("templates/inline.html", "inline")?;
+        templates.invalidate(dir.path().join("index.html"));
+        templates.invalidate("/app/templates/inline.html");
+        assert!(templates.templates.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_precompile() -> Result<(), Error> {
+        let dir = tempdir::TempDir::new("rwf_precompile").unwrap();
+        std::fs::create_dir(dir.path().join("partials")).unwrap();
+        write(dir.path().join("index.html"), "<%= title %>").unwrap();
+        write(dir.path().join("partials/nav.html"), "<nav></nav>").unwrap();
+        write(dir.path().join(".DS_Store"), "<% if %>").unwrap();
+
+        let mut templates = Templates::new();
+        assert_eq!(templates.precompile(dir.path())?, 2);
+        assert!(templates
+            .templates
+            .contains_key(&dir.path().join("partials/nav.html")));
+
+        write(dir.path().join("partials/footer.html"), "<% if true %>").unwrap();
+        let err = templates.precompile(dir.path()).unwrap_err();
+        assert!(err.to_string().contains("footer.html"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,562 @@
+//! Form builder, generating HTML forms bound to models.
+//!
+//! Fields are filled in with the model's values, or with the values the user submitted when the
+//! form is shown again after a failed `POST`, so nothing they typed is lost. Validation errors are shown
+//! next to their field, and the CSRF token is added automatically.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let form = form_for(&user, "/users")
+//!     .request(request)
+//!     .errors(&errors)
+//!     .text("name")
+//!     .email("email")
+//!     .select("role", &[("member", "Member"), ("admin", "Admin")])
+//!     .checkbox("newsletter")
+//!     .submit("Save");
+//!
+//! render!(request, "templates/users/edit.html", "form" => form)
+//! ```
+//!
+//! ```erb
+//! <%= form %>
+//! ```
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use super::template::{Error, ToTemplateValue, Value};
+use crate::controller::middleware::csrf::CSRF_INPUT;
+use crate::http::{Method, Request, ValidationErrors};
+use crate::model::Model;
+use crate::safe_html;
+
+/// Values of a checkbox which mean it's checked.
+const CHECKED: &[&str] = &["true", "t", "on", "1", "yes"];
+
+/// Kind of form field.
+#[derive(Debug, Clone)]
+enum Kind {
+    /// `<input>` of this type, e.g. `text` or `email`.
+    Input(String),
+    TextArea,
+    Checkbox,
+    /// Options, with their value and label.
+    Select(Vec<(String, String)>),
+}
+
+#[derive(Debug, Clone)]
+struct Field {
+    name: String,
+    kind: Kind,
+}
+
+/// Builds an HTML form.
+#[derive(Debug, Clone)]
+pub struct FormBuilder {
+    action: String,
+    method: String,
+    prefix: Option<String>,
+    values: HashMap<String, String>,
+    labels: HashMap<String, String>,
+    errors: ValidationErrors,
+    csrf_token: Option<String>,
+    fields: Vec<Field>,
+    submit: Option<String>,
+}
+
+/// Form for the model, submitted to the action. Fields are filled in with the model's values.
+pub fn form_for<T: Model>(model: &T, action: impl ToString) -> FormBuilder {
+    let mut form = FormBuilder::new(action);
+    let prefix = T::foreign_key();
+    form.prefix = Some(prefix.strip_suffix("_id").unwrap_or(prefix).to_string());
+
+    for (column, value) in T::column_names().iter().zip(model.values()) {
+        if let Ok(value) = value.to_template_value() {
+            form = form.value(column, value);
+        }
+    }
+
+    form
+}
+
+impl FormBuilder {
+    /// Empty form, submitted with `POST` to the action.
+    pub fn new(action: impl ToString) -> Self {
+        Self {
+            action: action.to_string(),
+            method: "post".into(),
+            prefix: None,
+            values: HashMap::new(),
+            labels: HashMap::new(),
+            errors: ValidationErrors::new(),
+            csrf_token: None,
+            fields: vec![],
+            submit: None,
+        }
+    }
+
+    /// Submit the form with this method, e.g. `get` for search forms.
+    pub fn method(mut self, method: impl ToString) -> Self {
+        self.method = method.to_string().to_lowercase();
+        self
+    }
+
+    /// Add the CSRF token of the request's session and, if the form was submitted with this request,
+    /// fill in the fields with the submitted values.
+    pub fn request(mut self, request: &Request) -> Self {
+        self.csrf_token = request.csrf_token().ok();
+
+        let submitted = matches!(
+            request.head().method(),
+            Method::Post | Method::Put | Method::Patch
+        );
+
+        if submitted {
+            if let Ok(form_data) = request.form_data() {
+                for (name, value) in form_data.into_iter() {
+                    if name != CSRF_INPUT {
+                        self.values.insert(name, value);
+                    }
+                }
+            }
+        }
+
+        self
+    }
+
+    /// Show the validation errors next to their field. Errors for fields which
+    /// aren't in the form are shown above it.
+    pub fn errors(mut self, errors: &ValidationErrors) -> Self {
+        self.errors = errors.clone();
+        self
+    }
+
+    /// Set the value of the field.
+    pub fn value(mut self, name: impl ToString, value: impl ToTemplateValue) -> Self {
+        let value = match value.to_template_value() {
+            Ok(Value::Null) | Err(_) => String::new(),
+            Ok(value) => value.to_string(),
+        };
+        self.values.insert(name.to_string(), value);
+        self
+    }
+
+    /// Set the label of the field. By default, the label is the field name, e.g. `First name` for `first_name`.
+    pub fn label(mut self, name: impl ToString, label: impl ToString) -> Self {
+        self.labels.insert(name.to_string(), label.to_string());
+        self
+    }
+
+    /// Add an `<input>` of this type, e.g. `url` or `color`.
+    pub fn input(mut self, name: impl ToString, kind: impl ToString) -> Self {
+        self.fields.push(Field {
+            name: name.to_string(),
+            kind: Kind::Input(kind.to_string()),
+        });
+        self
+    }
+
+    /// Add a text input.
+    pub fn text(self, name: impl ToString) -> Self {
+        self.input(name, "text")
+    }
+
+    /// Add an email input.
+    pub fn email(self, name: impl ToString) -> Self {
+        self.input(name, "email")
+    }
+
+    /// Add a password input. Passwords are never filled in.
+    pub fn password(self, name: impl ToString) -> Self {
+        self.input(name, "password")
+    }
+
+    /// Add a number input.
+    pub fn number(self, name: impl ToString) -> Self {
+        self.input(name, "number")
+    }
+
+    /// Add a date input.
+    pub fn date(self, name: impl ToString) -> Self {
+        self.input(name, "date")
+    }
+
+    /// Add a hidden input.
+    pub fn hidden(self, name: impl ToString) -> Self {
+        self.input(name, "hidden")
+    }
+
+    /// Add a `<textarea>`.
+    pub fn textarea(mut self, name: impl ToString) -> Self {
+        self.fields.push(Field {
+            name: name.to_string(),
+            kind: Kind::TextArea,
+        });
+        self
+    }
+
+    /// Add a checkbox. Unchecked checkboxes are submitted as `false`.
+    pub fn checkbox(mut self, name: impl ToString) -> Self {
+        self.fields.push(Field {
+            name: name.to_string(),
+            kind: Kind::Checkbox,
+        });
+        self
+    }
+
+    /// Add a `<select>` with the options, as pairs of value and label.
+    pub fn select(mut self, name: impl ToString, options: &[(&str, &str)]) -> Self {
+        self.fields.push(Field {
+            name: name.to_string(),
+            kind: Kind::Select(
+                options
+                    .iter()
+                    .map(|(value, label)| (value.to_string(), label.to_string()))
+                    .collect(),
+            ),
+        });
+        self
+    }
+
+    /// Add the submit button.
+    pub fn submit(mut self, label: impl ToString) -> Self {
+        self.submit = Some(label.to_string());
+        self
+    }
+
+    /// Render one field, with its label and errors.
+    pub fn field(&self, name: &str) -> Option<String> {
+        self.fields
+            .iter()
+            .find(|field| field.name == name)
+            .map(|field| self.render_field(field))
+    }
+
+    /// Render the form.
+    pub fn render(&self) -> String {
+        let mut html = format!(
+            r#"<form action="{}" method="{}">"#,
+            safe_html(&self.action),
+            safe_html(&self.method)
+        );
+
+        if let Some(ref token) = self.csrf_token {
+            if self.method != "get" {
+                let _ = write!(
+                    html,
+                    r#"<input type="hidden" name="{}" value="{}">"#,
+                    CSRF_INPUT,
+                    safe_html(token)
+                );
+            }
+        }
+
+        let names = self
+            .fields
+            .iter()
+            .map(|field| field.name.as_str())
+            .collect::<HashSet<_>>();
+        let other_errors = self
+            .errors
+            .fields()
+            .iter()
+            .filter(|(field, _)| !names.contains(field.as_str()))
+            .flat_map(|(_, messages)| messages)
+            .collect::<Vec<_>>();
+
+        if !other_errors.is_empty() {
+            html.push_str(r#"<ul class="form-errors">"#);
+            for message in other_errors {
+                let _ = write!(html, "<li>{}</li>", safe_html(message));
+            }
+            html.push_str("</ul>");
+        }
+
+        for field in &self.fields {
+            html.push_str(&self.render_field(field));
+        }
+
+        if let Some(ref submit) = self.submit {
+            let _ = write!(
+                html,
+                r#"<button type="submit">{}</button>"#,
+                safe_html(submit)
+            );
+        }
+
+        html.push_str("</form>");
+        html
+    }
+
+    fn render_field(&self, field: &Field) -> String {
+        let name = safe_html(&field.name);
+        let id = match self.prefix {
+            Some(ref prefix) => format!("{}_{}", safe_html(prefix), name),
+            None => name.clone(),
+        };
+        let value = self
+            .values
+            .get(&field.name)
+            .map(|value| safe_html(value))
+            .unwrap_or_default();
+        let errors = self.errors.get(&field.name);
+        let invalid = if errors.is_empty() {
+            String::new()
+        } else {
+            format!(r#" aria-invalid="true" aria-describedby="{}_error""#, id)
+        };
+
+        let input = match field.kind {
+            Kind::Input(ref kind) if kind == "hidden" => {
+                return format!(
+                    r#"<input type="hidden" id="{}" name="{}" value="{}">"#,
+                    id, name, value
+                );
+            }
+
+            Kind::Input(ref kind) if kind == "password" => format!(
+                r#"<input type="password" id="{}" name="{}"{}>"#,
+                id, name, invalid
+            ),
+
+            Kind::Input(ref kind) => format!(
+                r#"<input type="{}" id="{}" name="{}" value="{}"{}>"#,
+                safe_html(kind),
+                id,
+                name,
+                value,
+                invalid
+            ),
+
+            Kind::TextArea => format!(
+                r#"<textarea id="{}" name="{}"{}>{}</textarea>"#,
+                id, name, invalid, value
+            ),
+
+            Kind::Checkbox => format!(
+                r#"<input type="hidden" name="{}" value="false"><input type="checkbox" id="{}" name="{}" value="true"{}{}>"#,
+                name,
+                id,
+                name,
+                if CHECKED.contains(&value.to_lowercase().as_str()) {
+                    " checked"
+                } else {
+                    ""
+                },
+                invalid
+            ),
+
+            Kind::Select(ref options) => {
+                let mut select = format!(r#"<select id="{}" name="{}"{}>"#, id, name, invalid);
+                for (option, label) in options {
+                    let option = safe_html(option);
+                    let _ = write!(
+                        select,
+                        r#"<option value="{}"{}>{}</option>"#,
+                        option,
+                        if option == value { " selected" } else { "" },
+                        safe_html(label)
+                    );
+                }
+                select.push_str("</select>");
+                select
+            }
+        };
+
+        let label = self
+            .labels
+            .get(&field.name)
+            .cloned()
+            .unwrap_or_else(|| humanize(&field.name));
+
+        let mut html = format!(
+            r#"<div class="field{}"><label for="{}">{}</label>{}"#,
+            if errors.is_empty() {
+                ""
+            } else {
+                " field-error"
+            },
+            id,
+            safe_html(&label),
+            input
+        );
+
+        if !errors.is_empty() {
+            let _ = write!(
+                html,
+                r#"<p class="field-error-message" id="{}_error">{}</p>"#,
+                id,
+                safe_html(&errors.join(", "))
+            );
+        }
+
+        html.push_str("</div>");
+        html
+    }
+}
+
+impl std::fmt::Display for FormBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.render())
+    }
+}
+
+impl ToTemplateValue for FormBuilder {
+    fn to_template_value(&self) -> Result<Value, Error> {
+        Ok(Value::SafeString(self.render()))
+    }
+}
+
+/// Label for the field name, e.g. `First name` for `first_name`.
+fn humanize(name: &str) -> String {
+    let name = name.strip_suffix("_id").unwrap_or(name).replace('_', " ");
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::test::dummy_ip;
+    use crate::model::{FromRow, ToValue, Value as ModelValue};
+
+    #[derive(Clone, Default)]
+    struct User {
+        id: Option<i64>,
+        name: String,
+        email: String,
+        role: String,
+        admin: bool,
+    }
+
+    impl FromRow for User {
+        fn from_row(_row: tokio_postgres::Row) -> Result<Self, crate::model::Error> {
+            unimplemented!()
+        }
+    }
+
+    impl Model for User {
+        fn id(&self) -> ModelValue {
+            self.id.to_value()
+        }
+
+        fn table_name() -> &'static str {
+            "users"
+        }
+
+        fn foreign_key() -> &'static str {
+            "user_id"
+        }
+
+        fn column_names() -> &'static [&'static str] {
+            &["name", "email", "role", "admin"]
+        }
+
+        fn values(&self) -> Vec<ModelValue> {
+            vec![
+                self.name.to_value(),
+                self.email.to_value(),
+                self.role.to_value(),
+                self.admin.to_value(),
+            ]
+        }
+    }
+
+    fn user() -> User {
+        User {
+            id: Some(1),
+            name: "Alice <3".into(),
+            email: "alice@example.com".into(),
+            role: "admin".into(),
+            admin: true,
+        }
+    }
+
+    #[test]
+    fn test_form_for() {
+        let form = form_for(&user(), "/users/1")
+            .text("name")
+            .password("password")
+            .select("role", &[("member", "Member"), ("admin", "Admin")])
+            .checkbox("admin")
+            .label("admin", "Administrator")
+            .submit("Save");
+
+        assert_eq!(
+            form.field("name").unwrap(),
+            r#"<div class="field"><label for="user_name">Name</label><input type="text" id="user_name" name="name" value="Alice &lt;3"></div>"#
+        );
+        assert_eq!(
+            form.field("password").unwrap(),
+            r#"<div class="field"><label for="user_password">Password</label><input type="password" id="user_password" name="password"></div>"#
+        );
+        assert!(form.field("role").unwrap().contains(
+            r#"<option value="member">Member</option><option value="admin" selected>Admin</option>"#
+        ));
+        assert_eq!(
+            form.field("admin").unwrap(),
+            r#"<div class="field"><label for="user_admin">Administrator</label><input type="hidden" name="admin" value="false"><input type="checkbox" id="user_admin" name="admin" value="true" checked></div>"#
+        );
+
+        let html = form.render();
+        assert!(html.starts_with(r#"<form action="/users/1" method="post"><div class="field">"#));
+        assert!(html.ends_with(r#"<button type="submit">Save</button></form>"#));
+        assert!(!html.contains(CSRF_INPUT));
+    }
+
+    #[tokio::test]
+    async fn test_failed_post() {
+        let body = format!("{}=token&name=&email=bob%40example.com", CSRF_INPUT);
+        let request = format!(
+            "POST /users HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let request = Request::read(dummy_ip(), request.as_bytes()).await.unwrap();
+
+        let mut errors = ValidationErrors::new();
+        errors
+            .add("name", "can't be blank")
+            .add("base", "too many users");
+
+        let form = form_for(&User::default(), "/users")
+            .request(&request)
+            .errors(&errors)
+            .text("name")
+            .email("email");
+
+        // Submitted values are shown again, with the errors.
+        assert_eq!(
+            form.field("name").unwrap(),
+            r#"<div class="field field-error"><label for="user_name">Name</label><input type="text" id="user_name" name="name" value="" aria-invalid="true" aria-describedby="user_name_error"><p class="field-error-message" id="user_name_error">can&#39;t be blank</p></div>"#
+        );
+        assert!(form
+            .field("email")
+            .unwrap()
+            .contains(r#"value="bob@example.com""#));
+
+        let html = form.render();
+        let token = html
+            .split(&format!(r#"name="{}" value=""#, CSRF_INPUT))
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap();
+        assert!(crate::crypto::csrf_token_validate(
+            token,
+            &request.session_id().to_string()
+        ));
+        assert!(html.contains(r#"<ul class="form-errors"><li>too many users</li></ul>"#));
+    }
+
+    #[test]
+    fn test_humanize() {
+        assert_eq!(humanize("first_name"), "First name");
+        assert_eq!(humanize("team_id"), "Team");
+        assert_eq!(humanize(""), "");
+    }
+}
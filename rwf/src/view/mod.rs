@@ -22,14 +22,20 @@
 //!
 //! See [documentation](https://levkk.github.io/rwf/views/) on how to use templates.
 pub mod cache;
+pub mod form;
+pub mod pdf;
 pub mod prelude;
+pub mod qr;
 pub mod template;
 pub mod turbo;
 
 pub use cache::Templates;
+pub use form::{form_for, FormBuilder};
+pub use pdf::PdfRenderer;
+pub use qr::QrCode;
 pub use template::Context;
 pub use template::Error;
 pub use template::Template;
-pub use turbo::TurboStream;
+pub use turbo::{TurboFrame, TurboStream};
 
 pub use template::{ToTemplateValue, Value};
@@ -0,0 +1,296 @@
+//! PDF rendering.
+//!
+//! Templates are rendered to HTML as usual and the HTML is converted to PDF by a [`PdfRenderer`].
+//! By default, the conversion is done by an external program, e.g. `wkhtmltopdf` or headless Chromium,
+//! configured in the `[pdf]` section of `rwf.toml`. A pure-Rust renderer can be used instead
+//! by implementing the trait and installing it with [`set_renderer`].
+//!
+//! The output of external programs is streamed to the client as it's produced,
+//! so large documents aren't buffered in memory.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let response = Response::new()
+//!     .pdf("templates/invoice.html", &context!("invoice" => invoice))
+//!     .await?
+//!     .attachment("invoice.pdf");
+//! ```
+use async_trait::async_trait;
+use bytes::Bytes;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::process::Stdio;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tracing::error;
+
+use super::Error;
+use crate::config::get_config;
+use crate::http::Body;
+
+/// Size of the chunks read from the program's output.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+static RENDERER: Lazy<Mutex<Arc<Box<dyn PdfRenderer>>>> =
+    Lazy::new(|| Mutex::new(CommandRenderer::from_config().handler()));
+
+/// Converts HTML to PDF.
+#[async_trait]
+pub trait PdfRenderer: Send + Sync {
+    /// Render the HTML document to PDF.
+    async fn render(&self, html: &str) -> Result<Vec<u8>, Error>;
+
+    /// Render the HTML document to PDF, returning the response body.
+    ///
+    /// By default, the whole PDF is rendered in memory. Renderers which produce
+    /// the document incrementally can stream it instead.
+    async fn render_body(&self, html: &str) -> Result<Body, Error> {
+        Ok(Body::bytes(self.render(html).await?))
+    }
+
+    /// Convert the renderer into a handle that can be shared between tasks.
+    fn handler(self) -> Arc<Box<dyn PdfRenderer>>
+    where
+        Self: Sized + 'static,
+    {
+        Arc::new(Box::new(self))
+    }
+}
+
+/// Get the renderer used by [`Response::pdf`](crate::http::Response::pdf).
+pub fn renderer() -> Arc<Box<dyn PdfRenderer>> {
+    RENDERER.lock().clone()
+}
+
+/// Replace the renderer used by the application.
+pub fn set_renderer(renderer: impl PdfRenderer + 'static) {
+    *RENDERER.lock() = renderer.handler();
+}
+
+/// Render HTML to PDF using the configured renderer.
+pub async fn render(html: &str) -> Result<Vec<u8>, Error> {
+    renderer().render(html).await
+}
+
+/// Render HTML to PDF using the configured renderer, returning the response body.
+pub async fn render_body(html: &str) -> Result<Body, Error> {
+    renderer().render_body(html).await
+}
+
+/// Renders PDFs with an external program.
+///
+/// The HTML is written to the program's standard input and the PDF
+/// is read from its standard output. Response bodies are streamed
+/// while the program is running; if it fails after producing some output,
+/// the error is logged and the client receives an incomplete PDF.
+#[derive(Debug, Clone)]
+pub struct CommandRenderer {
+    program: String,
+    args: Vec<String>,
+    timeout: std::time::Duration,
+}
+
+impl CommandRenderer {
+    /// Create a renderer running the given program.
+    pub fn new(program: impl ToString) -> Self {
+        Self {
+            program: program.to_string(),
+            args: vec![],
+            timeout: std::time::Duration::from_secs(30),
+        }
+    }
+
+    /// Create a renderer using the command configured in `[pdf]`.
+    pub fn from_config() -> Self {
+        let config = &get_config().pdf;
+        let mut command = config.command.iter();
+        let program = command.next().map(|s| s.as_str()).unwrap_or("wkhtmltopdf");
+
+        Self::new(program)
+            .args(command)
+            .timeout(config.timeout().unsigned_abs())
+    }
+
+    /// Add arguments passed to the program.
+    pub fn args(mut self, args: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.to_string()));
+        self
+    }
+
+    /// How long to wait for the program to finish.
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl CommandRenderer {
+    /// Run the program, sending the PDF to the channel as it's read.
+    async fn run(&self, html: &str, chunks: mpsc::Sender<Bytes>) -> Result<(), Error> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| Error::Pdf(format!("{}: {}", self.program, err)))?;
+
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let html = html.to_string();
+
+        // Write the input and read stderr concurrently with reading the output,
+        // otherwise the program can block on a full pipe.
+        let writer = tokio::spawn(async move {
+            stdin.write_all(html.as_bytes()).await?;
+            stdin.shutdown().await
+        });
+        let errors = tokio::spawn(async move {
+            let mut errors = vec![];
+            stderr.read_to_end(&mut errors).await.map(|_| errors)
+        });
+
+        let output = async {
+            loop {
+                let mut chunk = vec![0; CHUNK_SIZE];
+                let read = stdout.read(&mut chunk).await?;
+
+                if read == 0 {
+                    break;
+                }
+
+                chunk.truncate(read);
+
+                // The client is gone, no need to finish the document.
+                if chunks.send(chunk.into()).await.is_err() {
+                    return Ok(None);
+                }
+            }
+
+            child.wait().await.map(Some)
+        };
+
+        let status = match timeout(self.timeout, output)
+            .await
+            .map_err(|_| Error::Pdf(format!("{} timed out", self.program)))?
+            .map_err(|err| Error::Pdf(err.to_string()))?
+        {
+            Some(status) => status,
+            None => return Ok(()),
+        };
+
+        if let Ok(Err(err)) = writer.await {
+            return Err(Error::Pdf(err.to_string()));
+        }
+
+        if !status.success() {
+            let errors = errors.await.ok().and_then(|errors| errors.ok());
+            return Err(Error::Pdf(format!(
+                "{} exited with {}: {}",
+                self.program,
+                status,
+                String::from_utf8_lossy(&errors.unwrap_or_default()).trim()
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PdfRenderer for CommandRenderer {
+    async fn render(&self, html: &str) -> Result<Vec<u8>, Error> {
+        let (tx, mut rx) = mpsc::channel::<Bytes>(4);
+
+        let collect = async {
+            let mut pdf = vec![];
+            while let Some(chunk) = rx.recv().await {
+                pdf.extend_from_slice(&chunk);
+            }
+            pdf
+        };
+
+        let (result, pdf) = tokio::join!(self.run(html, tx), collect);
+        result.map(|_| pdf)
+    }
+
+    async fn render_body(&self, html: &str) -> Result<Body, Error> {
+        let (tx, mut rx) = mpsc::channel::<Bytes>(4);
+        let renderer = self.clone();
+        let html = html.to_string();
+
+        let task = tokio::spawn(async move { renderer.run(&html, tx).await });
+
+        // Wait for the first chunk, so errors starting the program
+        // can still be returned instead of the PDF.
+        match rx.recv().await {
+            Some(first) => {
+                tokio::spawn(async move {
+                    if let Ok(Err(err)) = task.await {
+                        error!("pdf: {}", err);
+                    }
+                });
+
+                let stream = tokio_stream::once(first).chain(ReceiverStream::new(rx));
+                Ok(Body::stream(stream, None))
+            }
+
+            None => {
+                task.await.map_err(|err| Error::Pdf(err.to_string()))??;
+                Ok(Body::bytes(vec![]))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_command_renderer() {
+        let pdf = CommandRenderer::new("cat")
+            .render("%PDF-1.4")
+            .await
+            .unwrap();
+        assert_eq!(pdf, b"%PDF-1.4");
+
+        let err = CommandRenderer::new("false").render("").await.unwrap_err();
+        assert!(matches!(err, Error::Pdf(_)));
+
+        let err = CommandRenderer::new("sleep")
+            .args(["5"])
+            .timeout(std::time::Duration::from_millis(100))
+            .render("")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("timed out"));
+    }
+
+    #[tokio::test]
+    async fn test_command_renderer_body() {
+        let mut body = CommandRenderer::new("cat")
+            .render_body("%PDF-1.4")
+            .await
+            .unwrap();
+        assert!(body.chunked());
+
+        let mut sent = vec![];
+        body.send(&mut sent).await.unwrap();
+        assert_eq!(sent, b"8\r\n%PDF-1.4\r\n0\r\n\r\n");
+
+        let err = CommandRenderer::new("false")
+            .render_body("")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Pdf(_)));
+    }
+}
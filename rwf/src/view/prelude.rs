@@ -1,4 +1,5 @@
 //! Types useful when working with templates.
+pub use super::form::{form_for, FormBuilder};
 pub use super::template::{ToTemplateValue, Value};
 pub use super::Template;
 pub use super::Templates;
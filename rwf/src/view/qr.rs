@@ -0,0 +1,125 @@
+//! QR code generation.
+//!
+//! QR codes can be rendered as SVG or PNG, and embedded in pages as data URIs. They are also available
+//! in templates on strings:
+//!
+//! ```erb
+//! <!-- Inline SVG -->
+//! <%= totp_uri.qr %>
+//!
+//! <!-- Image -->
+//! <img src="<%= ticket_url.qr_png %>">
+//! ```
+//!
+//! # Example
+//!
+//! ```
+//! use rwf::view::QrCode;
+//!
+//! let qr = QrCode::new("https://rustwebframework.org").unwrap();
+//! let svg = qr.svg();
+//! let png = qr.png().unwrap();
+//! ```
+use base64::{engine::general_purpose, Engine as _};
+use image::{ImageFormat, Luma};
+use qrcode::render::svg;
+use std::io::Cursor;
+
+use super::Error;
+
+/// QR code.
+#[derive(Clone)]
+pub struct QrCode {
+    code: qrcode::QrCode,
+    size: u32,
+}
+
+impl QrCode {
+    /// Encode data into a QR code.
+    ///
+    /// An error is returned if the data is too long to fit in a QR code.
+    pub fn new(data: impl AsRef<[u8]>) -> Result<Self, Error> {
+        let code = qrcode::QrCode::new(data).map_err(|err| Error::Runtime(err.to_string()))?;
+
+        Ok(Self { code, size: 200 })
+    }
+
+    /// Minimum width and height of the rendered image, in pixels. Default: 200.
+    pub fn size(mut self, size: u32) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Render the QR code as an SVG document.
+    pub fn svg(&self) -> String {
+        self.code
+            .render::<svg::Color>()
+            .min_dimensions(self.size, self.size)
+            .build()
+    }
+
+    /// Render the QR code as a PNG image.
+    pub fn png(&self) -> Result<Vec<u8>, Error> {
+        let image = self
+            .code
+            .render::<Luma<u8>>()
+            .min_dimensions(self.size, self.size)
+            .build();
+
+        let mut png = Cursor::new(vec![]);
+        image
+            .write_to(&mut png, ImageFormat::Png)
+            .map_err(|err| Error::Runtime(err.to_string()))?;
+
+        Ok(png.into_inner())
+    }
+
+    /// SVG data URI, which can be used in the `src` attribute of an `<img>` tag.
+    pub fn svg_data_uri(&self) -> String {
+        data_uri("image/svg+xml", self.svg().as_bytes())
+    }
+
+    /// PNG data URI, which can be used in the `src` attribute of an `<img>` tag.
+    pub fn png_data_uri(&self) -> Result<String, Error> {
+        Ok(data_uri("image/png", &self.png()?))
+    }
+}
+
+fn data_uri(mime_type: &str, data: &[u8]) -> String {
+    format!(
+        "data:{};base64,{}",
+        mime_type,
+        general_purpose::STANDARD.encode(data)
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::view::{Context, Template};
+
+    #[test]
+    fn test_qr_code() {
+        let qr = QrCode::new("otpauth://totp/rwf:test?secret=JBSWY3DPEHPK3PXP").unwrap();
+
+        assert!(qr.svg().contains("<svg"));
+        assert!(qr.png().unwrap().starts_with(b"\x89PNG"));
+        assert!(qr.svg_data_uri().starts_with("data:image/svg+xml;base64,"));
+        assert!(qr
+            .png_data_uri()
+            .unwrap()
+            .starts_with("data:image/png;base64,"));
+
+        assert!(QrCode::new(vec![b'a'; 8000]).is_err());
+    }
+
+    #[test]
+    fn test_qr_code_template() {
+        let template =
+            Template::from_str(r#"<%= "hello".qr %><img src="<%= "hello".qr_png %>">"#).unwrap();
+        let rendered = template.render(&Context::new()).unwrap();
+
+        assert!(rendered.starts_with("<?xml"));
+        assert!(rendered.contains(r#"<img src="data:image/png;base64,"#));
+    }
+}
@@ -10,10 +10,14 @@
 //! ```
 //!
 use crate::http::Request;
-use crate::view::template::{Error, ToTemplateValue, Value};
+use crate::view::template::{
+    language::{Macro, Statement},
+    Error, ToTemplateValue, Value,
+};
 use parking_lot::RwLock;
 use std::collections::HashMap;
 use std::ops::{Index, IndexMut};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use once_cell::sync::Lazy;
@@ -21,10 +25,21 @@ use once_cell::sync::Lazy;
 static DEFAULTS: Lazy<Arc<RwLock<Context>>> =
     Lazy::new(|| Arc::new(RwLock::new(Context::default())));
 
+// Macros calling each other deeper than this are most likely calling themselves forever.
+const MAX_MACRO_DEPTH: usize = 16;
+
 /// Template context.
 #[derive(Debug, Default, Clone)]
 pub struct Context {
     values: HashMap<String, Value>,
+    // Partials being rendered, outermost first.
+    partials: Vec<PathBuf>,
+    // Blocks defined by templates extending the one being rendered.
+    blocks: HashMap<String, Arc<Vec<Statement>>>,
+    // Macros defined or imported by the templates being rendered.
+    macros: HashMap<String, Arc<Macro>>,
+    // Macros being rendered.
+    macro_depth: usize,
 }
 
 impl Context {
@@ -38,9 +53,20 @@ impl Context {
         Self::new().with_request(request)
     }
 
-    /// Add request to context.
+    /// Add request to context, and its flash messages as `flash`. The usual `notice`
+    /// and `alert` messages are always set, so templates can print them without checking.
     pub fn with_request(mut self, request: &Request) -> Result<Self, Error> {
         self.set("request", request.to_template_value()?)?;
+
+        let mut flash = HashMap::from([
+            ("notice".to_string(), Value::String(String::new())),
+            ("alert".to_string(), Value::String(String::new())),
+        ]);
+        for (kind, message) in request.flash() {
+            flash.insert(kind.clone(), Value::String(message.clone()));
+        }
+        self.set("flash", Value::Hash(flash))?;
+
         Ok(self)
     }
 
@@ -56,6 +82,57 @@ impl Context {
         Ok(self)
     }
 
+    /// Record that a partial is being rendered with this context. Returns an error if the partial
+    /// is already being rendered, since it would include itself forever.
+    pub(crate) fn enter_partial(&mut self, path: &Path) -> Result<(), Error> {
+        if self.partials.iter().any(|partial| partial == path) {
+            let mut cycle = self
+                .partials
+                .iter()
+                .skip_while(|partial| *partial != path)
+                .map(|partial| partial.display().to_string())
+                .collect::<Vec<_>>();
+            cycle.push(path.display().to_string());
+
+            return Err(Error::IncludeCycle(cycle.join(" -> ")));
+        }
+
+        self.partials.push(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Override a block in the layout being rendered. Blocks are set by the most derived
+    /// template first, so blocks which are already set are kept.
+    pub(crate) fn set_block(&mut self, name: &str, body: Arc<Vec<Statement>>) {
+        self.blocks.entry(name.to_string()).or_insert(body);
+    }
+
+    /// Get the block override, if any.
+    pub(crate) fn block(&self, name: &str) -> Option<Arc<Vec<Statement>>> {
+        self.blocks.get(name).cloned()
+    }
+
+    /// Make the macro callable from the template being rendered. Replaces the macro with the same name.
+    pub(crate) fn set_macro(&mut self, component: Arc<Macro>) {
+        self.macros.insert(component.name().to_string(), component);
+    }
+
+    /// Get the macro, if it's defined.
+    pub(crate) fn get_macro(&self, name: &str) -> Option<Arc<Macro>> {
+        self.macros.get(name).cloned()
+    }
+
+    /// Record that a macro is being rendered with this context. Returns an error if macros
+    /// are nested too deeply, e.g. because a macro calls itself unconditionally.
+    pub(crate) fn enter_macro(&mut self) -> Result<(), Error> {
+        if self.macro_depth >= MAX_MACRO_DEPTH {
+            return Err(Error::Runtime("macros are nested too deeply".into()));
+        }
+
+        self.macro_depth += 1;
+        Ok(())
+    }
+
     /// Set global variable defaults.
     pub fn defaults(context: Self) {
         (*DEFAULTS.write()) = context;
@@ -105,7 +182,10 @@ macro_rules! impl_type {
                     result.insert(key.to_string(), value.to_template_value()?);
                 }
 
-                Ok(Context { values: result })
+                Ok(Context {
+                    values: result,
+                    ..Default::default()
+                })
             }
         }
     };
@@ -29,6 +29,9 @@ pub enum Error {
     #[error("template \"{0}\" does not exist")]
     TemplateDoesNotExist(PathBuf),
 
+    #[error("template includes itself: {0}")]
+    IncludeCycle(String),
+
     #[error("serialization error")]
     SerializationError,
 
@@ -40,6 +43,17 @@ pub enum Error {
 
     #[error("{0}")]
     Runtime(String),
+
+    #[error("{name}(): {message}")]
+    Function {
+        name: String,
+        message: String,
+        // Where the function was called in the template.
+        token: Option<TokenWithContext>,
+    },
+
+    #[error("pdf renderer error: {0}")]
+    Pdf(String),
 }
 
 impl Error {
@@ -50,6 +64,10 @@ impl Error {
             Error::Syntax(ref token) => token,
             Error::ExpressionSyntax(ref token) => token,
             Error::WrongToken(ref token, _) => token,
+            Error::Function {
+                token: Some(ref token),
+                ..
+            } => token,
             _ => {
                 if let Some(path) = path {
                     let prefix = "---> ";
@@ -70,6 +88,11 @@ impl Error {
             Error::Syntax(ref _token) => "syntax error".to_string(),
             Error::ExpressionSyntax(ref _token) => "expression syntax error".to_string(),
             Error::WrongToken(ref _token, _) => "unexpected token".to_string(),
+            Error::Function {
+                ref name,
+                ref message,
+                ..
+            } => format!("{}(): {}", name, message),
             _ => "".to_string(),
         };
 
@@ -0,0 +1,441 @@
+//! Context-aware escaping of values printed with `<%= %>`.
+//!
+//! The lexer follows the HTML around each print tag, so values are escaped for where they end up:
+//!
+//! - in text, HTML characters are escaped, e.g. `<` becomes `&lt;`
+//! - in a tag, e.g. an attribute value, quotes and whitespace are escaped as well
+//! - in a `<script>` element, values are written as JSON, which can't close the element
+//! - in a string, comment or regular expression inside a `<script>` element, all characters
+//!   except ASCII letters and digits are escaped as JavaScript `\uXXXX` sequences
+//!
+//! Safe strings, e.g. `<%= html.safe %>`, and values printed with `<%- %>` are never escaped.
+use super::Value;
+
+/// How to escape a printed value.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Escape {
+    /// Text between HTML tags.
+    #[default]
+    Html,
+    /// Inside an HTML tag, e.g. an attribute value.
+    Attribute,
+    /// Inside a `<script>` element.
+    Script,
+    /// Inside a string, a comment or a regular expression in a `<script>` element.
+    ScriptString,
+}
+
+impl Escape {
+    /// Escape the value for this context.
+    pub fn escape(&self, value: Value) -> Result<String, super::Error> {
+        if let Value::SafeString(value) = value {
+            return Ok(value);
+        }
+
+        Ok(match self {
+            Escape::Html => crate::safe_html(&value.to_string()),
+            Escape::Attribute => attribute(&value.to_string()),
+            Escape::Script => {
+                let json: serde_json::Value = value.try_into()?;
+                script(&json.to_string())
+            }
+            Escape::ScriptString => script_string(&value.to_string()),
+        })
+    }
+}
+
+/// Escape characters that end attribute values, quoted or not.
+fn attribute(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            '`' => result.push_str("&#96;"),
+            '=' => result.push_str("&#61;"),
+            c if c.is_whitespace() => result.push_str(&format!("&#{};", c as u32)),
+            c => result.push(c),
+        }
+    }
+
+    result
+}
+
+/// Escape JSON so it can't close the `<script>` element or start an HTML comment.
+fn script(json: &str) -> String {
+    json.replace('<', "\\u003c")
+        .replace('>', "\\u003e")
+        .replace('&', "\\u0026")
+        .replace('\u{2028}', "\\u2028")
+        .replace('\u{2029}', "\\u2029")
+}
+
+/// Escape all characters except ASCII letters and digits, so the value can't end a JavaScript string,
+/// including template literals, a comment or a regular expression.
+fn script_string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        if c.is_ascii_alphanumeric() {
+            result.push(c);
+        } else {
+            for unit in c.encode_utf16(&mut [0; 2]) {
+                result.push_str(&format!("\\u{:04x}", unit));
+            }
+        }
+    }
+
+    result
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum State {
+    #[default]
+    Text,
+    // After `<`, reading the tag name.
+    TagName,
+    // Inside a tag, after its name.
+    Tag,
+    // After `=` in a tag.
+    BeforeValue,
+    // Inside an attribute value, with its quote, if any.
+    Value(Option<char>),
+    // Inside a `<script>` element.
+    Script,
+    // Inside a string in a `<script>` element, with its quote, and if the previous character was a backslash.
+    ScriptString(char, bool),
+    // After `/` in a `<script>` element, which starts a comment, a regular expression or is a division.
+    ScriptSlash,
+    // Inside a `// ...` comment.
+    ScriptComment,
+    // Inside a `/* ... */` comment, and if the previous character was `*`.
+    ScriptBlockComment(bool),
+    // Inside a regular expression, if inside a `[...]` class, and if the previous character was a backslash.
+    ScriptRegex(bool, bool),
+}
+
+impl State {
+    fn script(&self) -> bool {
+        matches!(
+            self,
+            State::Script
+                | State::ScriptString(..)
+                | State::ScriptSlash
+                | State::ScriptComment
+                | State::ScriptBlockComment(_)
+                | State::ScriptRegex(..)
+        )
+    }
+}
+
+/// Keywords after which `/` starts a regular expression.
+const REGEX_KEYWORDS: &[&str] = &[
+    "return",
+    "typeof",
+    "instanceof",
+    "in",
+    "of",
+    "new",
+    "delete",
+    "void",
+    "throw",
+    "case",
+    "do",
+    "else",
+    "yield",
+    "await",
+];
+
+/// Follows the HTML in template text to find out where values are printed.
+#[derive(Debug, Default)]
+pub(crate) struct HtmlState {
+    state: State,
+    // Name of the tag being read, lowercase.
+    tag: String,
+    // End of the script, to find `</script`.
+    tail: String,
+    // Last character of the script outside of strings and comments, ignoring whitespace.
+    last: Option<char>,
+    // Identifier or keyword ending at the last character.
+    word: String,
+    // Whitespace follows the last character.
+    space: bool,
+}
+
+impl HtmlState {
+    /// Read template text.
+    pub(crate) fn read(&mut self, text: &str) {
+        for c in text.chars() {
+            self.state = match self.state {
+                State::Text => match c {
+                    '<' => {
+                        self.tag.clear();
+                        State::TagName
+                    }
+                    _ => State::Text,
+                },
+                State::TagName => match c {
+                    '>' => self.end_tag(),
+                    c if c.is_alphanumeric() || c == '/' || c == '!' || c == '-' => {
+                        self.tag.push(c.to_ascii_lowercase());
+                        State::TagName
+                    }
+                    // Not a tag, e.g. `a < b`.
+                    _ if self.tag.is_empty() => State::Text,
+                    _ => State::Tag,
+                },
+                State::Tag => match c {
+                    '>' => self.end_tag(),
+                    '=' => State::BeforeValue,
+                    _ => State::Tag,
+                },
+                State::BeforeValue => match c {
+                    '"' | '\'' => State::Value(Some(c)),
+                    '>' => self.end_tag(),
+                    c if c.is_whitespace() => State::BeforeValue,
+                    _ => State::Value(None),
+                },
+                State::Value(Some(quote)) if c == quote => State::Tag,
+                State::Value(None) if c == '>' => self.end_tag(),
+                State::Value(None) if c.is_whitespace() => State::Tag,
+                State::Value(quote) => State::Value(quote),
+                // The element ends at `</script`, even inside a string or a comment.
+                state if state.script() => {
+                    self.tail.push(c.to_ascii_lowercase());
+                    if self.tail.len() > 8 {
+                        self.tail.remove(0);
+                    }
+
+                    if self.tail.ends_with("</script") {
+                        self.tag = "/script".into();
+                        State::Tag
+                    } else {
+                        self.script(state, c)
+                    }
+                }
+                state => state,
+            };
+        }
+    }
+
+    fn script(&mut self, state: State, c: char) -> State {
+        match state {
+            State::ScriptString(quote, true) => State::ScriptString(quote, false),
+            State::ScriptString(quote, false) => match c {
+                '\\' => State::ScriptString(quote, true),
+                c if c == quote => {
+                    self.token(quote);
+                    State::Script
+                }
+                // Only template literals span lines.
+                '\n' | '\r' if quote != '`' => State::Script,
+                _ => State::ScriptString(quote, false),
+            },
+            State::ScriptSlash => match c {
+                '/' => State::ScriptComment,
+                '*' => State::ScriptBlockComment(false),
+                _ if self.regex_allowed() => self.regex(false, false, c),
+                // Division.
+                _ => {
+                    self.token('/');
+                    self.code(c)
+                }
+            },
+            State::ScriptComment => match c {
+                '\n' | '\r' => State::Script,
+                _ => State::ScriptComment,
+            },
+            State::ScriptBlockComment(star) => match c {
+                '/' if star => State::Script,
+                _ => State::ScriptBlockComment(c == '*'),
+            },
+            State::ScriptRegex(class, escaped) => self.regex(class, escaped, c),
+            _ => self.code(c),
+        }
+    }
+
+    // Read a character of the script outside of strings, comments and regular expressions.
+    fn code(&mut self, c: char) -> State {
+        match c {
+            '"' | '\'' | '`' => State::ScriptString(c, false),
+            '/' => State::ScriptSlash,
+            c if c.is_whitespace() => {
+                self.space = true;
+                State::Script
+            }
+            c => {
+                self.token(c);
+                State::Script
+            }
+        }
+    }
+
+    fn regex(&mut self, class: bool, escaped: bool, c: char) -> State {
+        match c {
+            _ if escaped => State::ScriptRegex(class, false),
+            '\\' => State::ScriptRegex(class, true),
+            '[' => State::ScriptRegex(true, false),
+            ']' => State::ScriptRegex(false, false),
+            '/' if !class => {
+                self.token('/');
+                State::Script
+            }
+            // Not a regular expression after all.
+            '\n' | '\r' => State::Script,
+            _ => State::ScriptRegex(class, false),
+        }
+    }
+
+    fn token(&mut self, c: char) {
+        let identifier = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+
+        if !identifier(c) || self.space || !self.last.is_some_and(identifier) {
+            self.word.clear();
+        }
+
+        if identifier(c) {
+            self.word.push(c);
+        }
+
+        self.last = Some(c);
+        self.space = false;
+    }
+
+    // `/` after an operator or a keyword starts a regular expression, otherwise it's a division.
+    fn regex_allowed(&self) -> bool {
+        match self.last {
+            None => true,
+            Some(c) if "(,=:[!&|?{};+-*%~^<>".contains(c) => true,
+            Some(_) => REGEX_KEYWORDS.contains(&self.word.as_str()),
+        }
+    }
+
+    fn end_tag(&mut self) -> State {
+        if self.tag == "script" {
+            self.tail.clear();
+            self.last = None;
+            self.word.clear();
+            self.space = false;
+            State::Script
+        } else {
+            State::Text
+        }
+    }
+
+    /// How to escape a value printed here.
+    pub(crate) fn escape(&self) -> Escape {
+        match self.state {
+            State::Text => Escape::Html,
+            State::TagName | State::Tag | State::BeforeValue | State::Value(_) => Escape::Attribute,
+            State::Script => Escape::Script,
+            State::ScriptString(..)
+            | State::ScriptSlash
+            | State::ScriptComment
+            | State::ScriptBlockComment(_)
+            | State::ScriptRegex(..) => Escape::ScriptString,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn escape(text: &str) -> Escape {
+        let mut state = HtmlState::default();
+        state.read(text);
+        state.escape()
+    }
+
+    #[test]
+    fn test_context() {
+        assert_eq!(escape("<p>"), Escape::Html);
+        assert_eq!(escape("a < b and "), Escape::Html);
+        assert_eq!(escape(r#"<a href=""#), Escape::Attribute);
+        assert_eq!(escape(r#"<a class="btn "#), Escape::Attribute);
+        assert_eq!(escape(r#"<a class="btn">"#), Escape::Html);
+        assert_eq!(escape("<input value="), Escape::Attribute);
+        assert_eq!(escape("<script>const data = "), Escape::Script);
+        assert_eq!(
+            escape(r#"<script type="application/json">"#),
+            Escape::Script
+        );
+        assert_eq!(escape(r#"<script>let a = "it\"s "#), Escape::ScriptString);
+        assert_eq!(escape("<script>let a = `${b}`; "), Escape::Script);
+        assert_eq!(escape("<script>let a = '</p>'</script><p>"), Escape::Html);
+        assert_eq!(escape("<script>let a = '</script><p>"), Escape::Html);
+
+        // Comments.
+        assert_eq!(escape("<script>// don't\nlet a = "), Escape::Script);
+        assert_eq!(escape("<script>/* it's */ let a = "), Escape::Script);
+        assert_eq!(escape("<script>// "), Escape::ScriptString);
+        assert_eq!(escape("<script>/* a * b "), Escape::ScriptString);
+
+        // Regular expressions and division.
+        assert_eq!(escape("<script>let re = /'/; let a = "), Escape::Script);
+        assert_eq!(escape("<script>let re = /[/']/g; let a = "), Escape::Script);
+        assert_eq!(escape(r"<script>let re = /\/'/; let a = "), Escape::Script);
+        assert_eq!(
+            escape("<script>function f(x) { return /'/.test(x) } let a = "),
+            Escape::Script
+        );
+        assert_eq!(escape("<script>let re = /"), Escape::ScriptString);
+        assert_eq!(
+            escape("<script>let a = b / 2; let c = '"),
+            Escape::ScriptString
+        );
+        assert_eq!(escape("<script>let a = (b) / 2; let c = "), Escape::Script);
+
+        // Strings don't span lines, except template literals.
+        assert_eq!(escape("<script>let a = 'it\nlet b = "), Escape::Script);
+        assert_eq!(escape("<script>let a = `it\n"), Escape::ScriptString);
+    }
+
+    #[test]
+    fn test_escape() {
+        let value = Value::String(r#"</script><img src=x onerror="alert(1)">"#.into());
+
+        assert_eq!(
+            Escape::Html.escape(value.clone()).unwrap(),
+            "&lt;/script&gt;&lt;img src=x onerror=&quot;alert(1)&quot;&gt;"
+        );
+        assert_eq!(
+            Escape::Attribute
+                .escape(Value::String("x\" onclick='y'".into()))
+                .unwrap(),
+            "x&quot;&#32;onclick&#61;&#39;y&#39;"
+        );
+        assert_eq!(
+            Escape::Script.escape(value.clone()).unwrap(),
+            r#""\u003c/script\u003e\u003cimg src=x onerror=\"alert(1)\"\u003e""#
+        );
+        assert_eq!(
+            Escape::Script
+                .escape(Value::List(vec![Value::Integer(1), Value::Null]))
+                .unwrap(),
+            "[1,null]"
+        );
+        assert_eq!(
+            Escape::ScriptString
+                .escape(Value::String("it's ${x}\n".into()))
+                .unwrap(),
+            r"it\u0027s\u0020\u0024\u007bx\u007d\u000a"
+        );
+        assert_eq!(
+            Escape::ScriptString
+                .escape(Value::String("é😀".into()))
+                .unwrap(),
+            r"\u00e9\ud83d\ude00"
+        );
+        assert_eq!(
+            Escape::Script
+                .escape(Value::SafeString("<b>".into()))
+                .unwrap(),
+            "<b>"
+        );
+    }
+}
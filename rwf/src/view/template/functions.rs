@@ -0,0 +1,271 @@
+//! Helper functions written in Rust and called from templates.
+//!
+//! Functions are registered once, usually when the app starts, and can be called from any template:
+//!
+//! ```
+//! # use rwf::view::template::*;
+//! Template::register_function("cdn_url", |args| {
+//!     let name: String = args.get(0)?;
+//!     Ok(format!("https://cdn.example.com/{}", name))
+//! });
+//!
+//! let template = Template::from_str(r#"<%= cdn_url("logo.png") %>"#).unwrap();
+//! assert_eq!(template.render_default().unwrap(), "https://cdn.example.com/logo.png");
+//! ```
+//!
+//! Arguments are converted to Rust types with [`Arguments::get`]. If the template passes
+//! the wrong type, or not enough arguments, rendering fails with an error pointing at the call.
+//! Built-in functions, like `render` or `csrf_token`, can't be replaced.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+
+use super::{Error, ToTemplateValue, Value};
+
+type Function = dyn Fn(&Arguments) -> Result<Value, Error> + Send + Sync;
+
+static FUNCTIONS: Lazy<RwLock<HashMap<String, Arc<Function>>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// Register the function, replacing the function with the same name, if any.
+pub fn register<F, R>(name: &str, function: F)
+where
+    F: Fn(&Arguments) -> Result<R, Error> + Send + Sync + 'static,
+    R: ToTemplateValue,
+{
+    FUNCTIONS.write().insert(
+        name.to_string(),
+        Arc::new(move |args| function(args)?.to_template_value()),
+    );
+}
+
+/// Call the function with the name, if it's registered.
+pub(crate) fn call(name: &str, args: &[Value]) -> Option<Result<Value, Error>> {
+    let function = FUNCTIONS.read().get(name).cloned()?;
+    let args = Arguments { name, args };
+
+    Some(function(&args).map_err(|err| match err {
+        Error::Function { .. } => err,
+        err => args.error(err.to_string()),
+    }))
+}
+
+/// Arguments passed to a function by the template.
+#[derive(Debug)]
+pub struct Arguments<'a> {
+    name: &'a str,
+    args: &'a [Value],
+}
+
+impl Arguments<'_> {
+    /// Get the argument, converted to a Rust type. Indexes start at 0.
+    ///
+    /// Optional arguments can be fetched as [`Option`].
+    pub fn get<T: FromTemplateValue>(&self, index: usize) -> Result<T, Error> {
+        let value = self.args.get(index).cloned().unwrap_or(Value::Null);
+        let type_name = value.type_name();
+
+        match T::from_template_value(value) {
+            Some(value) => Ok(value),
+            None if index >= self.args.len() => Err(self.error(format!(
+                "expected at least {} argument(s), got {}",
+                index + 1,
+                self.args.len()
+            ))),
+            None => Err(self.error(format!(
+                "argument {} should be {}, got {} instead",
+                index + 1,
+                T::type_name(),
+                type_name
+            ))),
+        }
+    }
+
+    /// All arguments.
+    pub fn values(&self) -> &[Value] {
+        self.args
+    }
+
+    /// Number of arguments.
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    /// No arguments were passed.
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+
+    /// Function error with the message, e.g. because an argument is invalid.
+    pub fn error(&self, message: impl ToString) -> Error {
+        Error::Function {
+            name: self.name.to_string(),
+            message: message.to_string(),
+            token: None,
+        }
+    }
+}
+
+/// Convert a template value to a Rust type.
+pub trait FromTemplateValue: Sized {
+    /// Convert the value, if it has the right type.
+    fn from_template_value(value: Value) -> Option<Self>;
+
+    /// Name of the expected type, used in errors.
+    fn type_name() -> &'static str;
+}
+
+impl FromTemplateValue for Value {
+    fn from_template_value(value: Value) -> Option<Self> {
+        Some(value)
+    }
+
+    fn type_name() -> &'static str {
+        "any value"
+    }
+}
+
+impl FromTemplateValue for String {
+    fn from_template_value(value: Value) -> Option<Self> {
+        match value {
+            Value::String(s) | Value::SafeString(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn type_name() -> &'static str {
+        "a string"
+    }
+}
+
+impl FromTemplateValue for i64 {
+    fn from_template_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Integer(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    fn type_name() -> &'static str {
+        "an integer"
+    }
+}
+
+impl FromTemplateValue for f64 {
+    fn from_template_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Float(f) => Some(f),
+            Value::Integer(n) => Some(n as f64),
+            _ => None,
+        }
+    }
+
+    fn type_name() -> &'static str {
+        "a number"
+    }
+}
+
+impl FromTemplateValue for bool {
+    fn from_template_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Boolean(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    fn type_name() -> &'static str {
+        "a boolean"
+    }
+}
+
+impl<T: FromTemplateValue> FromTemplateValue for Option<T> {
+    fn from_template_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Null => Some(None),
+            value => T::from_template_value(value).map(Some),
+        }
+    }
+
+    fn type_name() -> &'static str {
+        T::type_name()
+    }
+}
+
+impl<T: FromTemplateValue> FromTemplateValue for Vec<T> {
+    fn from_template_value(value: Value) -> Option<Self> {
+        match value {
+            Value::List(list) => list.into_iter().map(T::from_template_value).collect(),
+            _ => None,
+        }
+    }
+
+    fn type_name() -> &'static str {
+        "a list"
+    }
+}
+
+impl<T: FromTemplateValue> FromTemplateValue for HashMap<String, T> {
+    fn from_template_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Hash(hash) => hash
+                .into_iter()
+                .map(|(key, value)| T::from_template_value(value).map(|value| (key, value)))
+                .collect(),
+            _ => None,
+        }
+    }
+
+    fn type_name() -> &'static str {
+        "a hash"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Template;
+    use super::*;
+
+    #[test]
+    fn test_functions() {
+        register("test_url_for", |args| {
+            let name: String = args.get(0)?;
+            let id: Option<i64> = args.get(1)?;
+
+            Ok(match id {
+                Some(id) => format!("/{}/{}", name, id),
+                None => format!("/{}", name),
+            })
+        });
+
+        let template =
+            Template::from_str(r#"<%= test_url_for("chat") %> <%= test_url_for("users", 5) %>"#)
+                .unwrap();
+        assert_eq!(template.render_default().unwrap(), "/chat /users/5");
+
+        let template = Template::from_str("<%= test_url_for(5) %>").unwrap();
+        let err = template.render_default().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "test_url_for(): argument 1 should be a string, got integer instead"
+        );
+
+        let template = Template::from_str("<%= test_url_for() %>").unwrap();
+        assert_eq!(
+            template.render_default().unwrap_err().to_string(),
+            "test_url_for(): expected at least 1 argument(s), got 0"
+        );
+
+        let source = r#"<a href="<%= test_url_for(true) %>">"#;
+        let err = Template::from_str(source)
+            .unwrap()
+            .render_default()
+            .unwrap_err()
+            .pretty(source, None::<&str>);
+        assert_eq!(
+            err.to_string(),
+            "  | \n1 | <a href=\"<%= test_url_for(true) %>\">\n  |                          ^ test_url_for(): argument 1 should be a string, got boolean instead"
+        );
+    }
+}
@@ -46,6 +46,8 @@ pub enum Expression {
         term: Box<Expression>,
         name: Box<Expression>,
         args: Vec<Expression>,
+        // Name of a global function, to show where it was called in errors.
+        token: Option<TokenWithContext>,
     },
 
     Interpreter,
@@ -84,12 +86,34 @@ impl Expression {
                 Err(err) => return Err(err),
             },
 
+            // Short-circuit, so `user && user.admin` works when `user` isn't defined.
+            Expression::Binary {
+                left,
+                op: Op::And,
+                right,
+            } => Ok(Value::Boolean(
+                left.condition(context)? && right.condition(context)?,
+            )),
+
+            Expression::Binary {
+                left,
+                op: Op::Or,
+                right,
+            } => Ok(Value::Boolean(
+                left.condition(context)? || right.condition(context)?,
+            )),
+
             Expression::Binary { left, op, right } => {
                 let left = left.evaluate(context)?;
                 let right = right.evaluate(context)?;
                 op.evaluate_binary(&left, &right)
             }
 
+            Expression::Unary {
+                op: Op::Not,
+                operand,
+            } => Ok(Value::Boolean(!operand.condition(context)?)),
+
             Expression::Unary { op, operand } => {
                 let operand = operand.evaluate(context)?;
                 op.evaluate_unary(&operand)
@@ -103,7 +127,12 @@ impl Expression {
                 Ok(Value::List(list))
             }
 
-            Expression::Function { term, name, args } => {
+            Expression::Function {
+                term,
+                name,
+                args,
+                token,
+            } => {
                 let value = term.evaluate(context)?;
                 let name = match name.evaluate(context)? {
                     Value::String(name) => name,
@@ -136,13 +165,37 @@ impl Expression {
                     })
                     .collect::<Result<Vec<Value>, Error>>()?;
 
-                Ok(value.call(&name, &args, context)?)
+                value.call(&name, &args, context).map_err(|err| match err {
+                    Error::Function {
+                        name,
+                        message,
+                        token: None,
+                    } => Error::Function {
+                        name,
+                        message,
+                        token: token.clone(),
+                    },
+                    err => err,
+                })
             }
 
             Expression::Interpreter => Ok(Value::Interpreter),
         }
     }
 
+    /// Evaluate the expression as a condition, e.g. in an `if` statement.
+    /// Variables which aren't defined are false.
+    pub fn condition(&self, context: &Context) -> Result<bool, Error> {
+        match self.evaluate(context) {
+            Ok(value) => Ok(value.truthy()),
+            Err(Error::UndefinedVariable(name)) => match self {
+                Expression::Term { .. } => Ok(false),
+                _ => Err(Error::UndefinedVariable(name)),
+            },
+            Err(err) => Err(err),
+        }
+    }
+
     fn term(iter: &mut Peekable<impl Iterator<Item = TokenWithContext>>) -> Result<Self, Error> {
         let next = iter.next().ok_or(Error::Eof("term next"))?;
         let term = match next.token() {
@@ -211,9 +264,12 @@ impl Expression {
                     Token::Variable(name) => {
                         if let Some(next) = iter.peek() {
                             match next.token() {
-                                Token::RoundBracketStart => {
-                                    Self::function(&name, Expression::Interpreter, iter)?
-                                }
+                                Token::RoundBracketStart => Self::function(
+                                    &name,
+                                    Expression::Interpreter,
+                                    Some(next.clone()),
+                                    iter,
+                                )?,
 
                                 _ => Self::variable(name),
                             }
@@ -254,6 +310,7 @@ impl Expression {
     fn function(
         name: &str,
         expr: Self,
+        token: Option<TokenWithContext>,
         iter: &mut Peekable<impl Iterator<Item = TokenWithContext>>,
     ) -> Result<Self, Error> {
         let arg = iter.peek().map(|t| t.token());
@@ -303,6 +360,7 @@ impl Expression {
             term: Box::new(expr),
             name: Box::new(Expression::constant(Value::String(name.to_string()))),
             args,
+            token,
         })
     }
 
@@ -318,11 +376,12 @@ impl Expression {
                     let _ = iter.next().ok_or(Error::Eof("accessor dot"))?;
                     let name = iter.next().ok_or(Error::Eof("accessor name"))?;
                     match name.token() {
-                        Token::Variable(name) => Self::function(&name, expr, iter)?,
+                        Token::Variable(name) => Self::function(&name, expr, None, iter)?,
                         Token::Value(Value::Integer(n)) => Expression::Function {
                             term: Box::new(expr),
                             name: Box::new(Expression::constant(Value::String(n.to_string()))),
                             args: vec![],
+                            token: None,
                         },
                         _ => return Err(Error::ExpressionSyntax(name.clone())),
                     }
@@ -340,6 +399,7 @@ impl Expression {
                         term: Box::new(expr),
                         name: Box::new(name),
                         args: vec![],
+                        token: None,
                     }
                 }
 
@@ -354,79 +414,37 @@ impl Expression {
     pub fn parse(
         iter: &mut Peekable<impl Iterator<Item = TokenWithContext>>,
     ) -> Result<Self, Error> {
-        // Get the left term, if one exists.
-        // TODO: support unary operations.
-        let left = Self::term(iter)?;
-
-        // Check if we have another operator.
-        let next = match iter.peek() {
-            Some(next) => next,
-            None => return Ok(left),
-        };
+        Self::binary(iter, u8::MAX)
+    }
 
-        match Op::from_token(next.token()) {
-            Some(op) => {
-                // We have another operator. Consume the token.
-                let _ = iter.next().ok_or(Error::Eof("parse token"))?;
-
-                // Get the right term. This is a binary op.
-                let right = Self::term(iter)?;
-
-                // Check if there's another operator.
-                let next = iter.peek();
-
-                match next.map(|t| t.token()) {
-                    // Expression is over.
-                    Some(Token::BlockEnd) | None => Ok(Expression::Binary {
-                        left: Box::new(left),
-                        op,
-                        right: Box::new(right),
-                    }),
-
-                    // We have an operator.
-                    Some(token) => match Op::from_token(token) {
-                        Some(second_op) => {
-                            // Consume the token.
-                            let _ = iter.next().ok_or(Error::Eof("parse second op"))?;
-
-                            // Get the right term.
-                            let right2 = Expression::parse(iter)?;
-
-                            // Check operator precendence.
-                            if second_op < op {
-                                let expr = Expression::Binary {
-                                    left: Box::new(right),
-                                    right: Box::new(right2),
-                                    op: second_op,
-                                };
-
-                                Ok(Expression::Binary {
-                                    left: Box::new(left),
-                                    right: Box::new(expr),
-                                    op,
-                                })
-                            } else {
-                                let left = Expression::Binary {
-                                    left: Box::new(left),
-                                    right: Box::new(right),
-                                    op,
-                                };
-
-                                Ok(Expression::Binary {
-                                    left: Box::new(left),
-                                    right: Box::new(right2),
-                                    op: second_op,
-                                })
-                            }
-                        }
+    /// Parse an expression containing only operators with the precedence or
+    /// higher, i.e. a lower or equal precedence number. Operators with the same precedence
+    /// are evaluated left to right, e.g. `1 - 2 - 3` is `(1 - 2) - 3`.
+    fn binary(
+        iter: &mut Peekable<impl Iterator<Item = TokenWithContext>>,
+        precedence: u8,
+    ) -> Result<Self, Error> {
+        let mut left = Self::term(iter)?;
 
-                        // Not an op, so syntax error.
-                        None => Err(Error::ExpressionSyntax(next.unwrap().clone())),
-                    },
-                }
-            }
+        loop {
+            // The expression is over if it's not followed by an operator,
+            // e.g. the block ends or the next include argument starts.
+            let op = match iter.peek().and_then(|next| Op::from_token(next.token())) {
+                Some(op) if op.binary() && op.precendence() <= precedence => op,
+                _ => return Ok(left),
+            };
+
+            // Consume the operator.
+            let _ = iter.next().ok_or(Error::Eof("parse op"))?;
 
-            None => return Ok(left),
+            // The right term takes all operators which bind tighter than this one.
+            let right = Self::binary(iter, op.precendence() - 1)?;
+
+            left = Expression::Binary {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            };
         }
     }
 }
@@ -606,6 +624,40 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_logical_operators() -> Result<(), Error> {
+        // && binds tighter than ||, comparisons tighter than both.
+        assert_eq!(
+            "<% true || false && false %>".evaluate_default()?,
+            Value::Boolean(true)
+        );
+        assert_eq!(
+            "<% (true || false) && false %>".evaluate_default()?,
+            Value::Boolean(false)
+        );
+        assert_eq!(
+            "<% 1 < 2 && 3 >= 4 || 2 + 3 * 2 == 8 %>".evaluate_default()?,
+            Value::Boolean(true)
+        );
+        assert_eq!(
+            "<% !false && !(1 > 2) %>".evaluate_default()?,
+            Value::Boolean(true)
+        );
+        assert_eq!("<% 10 - 2 - 3 %>".evaluate_default()?, Value::Integer(5));
+
+        // The right side isn't evaluated if the left side decides the result.
+        assert_eq!(
+            "<% missing && missing.name %>".evaluate_default()?,
+            Value::Boolean(false)
+        );
+        assert_eq!(
+            "<% !missing || missing.name %>".evaluate_default()?,
+            Value::Boolean(true)
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_parenthesis() -> Result<(), Error> {
         let t1 = "<% ((1 + 2) + (-1 - -1)) * 5 + (25 - 5) %>";
@@ -0,0 +1,182 @@
+//! Template macros, reusable fragments of templates called like functions.
+//!
+//! ```erb
+//! <% macro button(label, kind = "primary") %>
+//!   <button class="btn btn-<%= kind %>"><%= label %></button>
+//! <% end %>
+//!
+//! <%= button("Save") %>
+//! <%= button("Cancel", "secondary") %>
+//! ```
+//!
+//! A macro can also be called with a body, which it renders with `<%= slot %>`:
+//!
+//! ```erb
+//! <% macro card(title) %>
+//!   <div class="card"><h2><%= title %></h2><%= slot %></div>
+//! <% end %>
+//!
+//! <% call card("Profile") %>
+//!   <p><%= user.name %></p>
+//! <% end %>
+//! ```
+use super::super::{Context, Error, Value};
+use super::{Expression, Statement};
+
+/// A macro defined in a template.
+#[derive(Debug, Clone)]
+pub struct Macro {
+    name: String,
+    params: Vec<(String, Option<Expression>)>,
+    body: Vec<Statement>,
+}
+
+impl Macro {
+    /// Create a macro. Parameters can have a default value, used when the argument isn't passed.
+    pub fn new(
+        name: impl ToString,
+        params: Vec<(String, Option<Expression>)>,
+        body: Vec<Statement>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            params,
+            body,
+        }
+    }
+
+    /// The macro's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Render the macro with the arguments. The macro can use all variables of the template
+    /// calling it; its parameters, and the `slot`, are only set inside the macro.
+    pub fn call(
+        &self,
+        args: &[Value],
+        slot: Option<Value>,
+        context: &Context,
+    ) -> Result<String, Error> {
+        if args.len() > self.params.len() {
+            return Err(self.error(format!(
+                "expected at most {} argument(s), got {}",
+                self.params.len(),
+                args.len()
+            )));
+        }
+
+        let mut context = context.clone();
+        context
+            .enter_macro()
+            .map_err(|_| self.error("macro calls itself too many times"))?;
+
+        for (index, (param, default)) in self.params.iter().enumerate() {
+            let value = match (args.get(index), default) {
+                (Some(value), _) => value.clone(),
+                // Defaults can use the arguments before them.
+                (None, Some(default)) => default.evaluate(&context)?,
+                (None, None) => return Err(self.error(format!("missing argument \"{}\"", param))),
+            };
+
+            context.set(param, value)?;
+        }
+
+        context.set("slot", slot.unwrap_or(Value::Null))?;
+
+        let mut result = String::new();
+        for statement in &self.body {
+            result.push_str(&statement.evaluate(&context)?);
+        }
+
+        Ok(result)
+    }
+
+    fn error(&self, message: impl ToString) -> Error {
+        Error::Function {
+            name: self.name.clone(),
+            message: message.to_string(),
+            token: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::Template;
+    use super::*;
+
+    #[test]
+    fn test_macros() -> Result<(), Error> {
+        let mut context = Context::default();
+        context.set("name", "<b>Alice</b>")?;
+
+        // Macros can be called before they are defined, and their output isn't escaped again.
+        let template = Template::from_str(
+            r#"<%= button("Save") %> <%= button(name, "danger") %><% macro button(label, kind = "primary") %><button class="<%= kind %>"><%= label %></button><% end %>"#,
+        )?;
+        assert_eq!(
+            template.render(&context)?,
+            r#"<button class="primary">Save</button> <button class="danger">&lt;b&gt;Alice&lt;/b&gt;</button>"#
+        );
+
+        // The body is rendered in the slot, with the variables of the caller.
+        let template = Template::from_str(
+            r#"<% macro card(title) %><div><h2><%= title %></h2><% if slot %><%- slot %><% end %></div><% end %><% call card("Profile") %><p><%= name %></p><% end %><%= card("Empty") %>"#,
+        )?;
+        assert_eq!(
+            template.render(&context)?,
+            "<div><h2>Profile</h2><p>&lt;b&gt;Alice&lt;/b&gt;</p></div><div><h2>Empty</h2></div>"
+        );
+
+        // Parameters are only set inside the macro.
+        let template =
+            Template::from_str("<% macro divider %><hr><% end %><%= divider() %><% call divider %>ignored<% end %><%= kind %>")?;
+        assert!(
+            matches!(template.render(&context), Err(Error::UndefinedVariable(name)) if name == "kind")
+        );
+
+        for (source, error) in [
+            (
+                r#"<% macro badge(label) %><%= label %><% end %><%= badge("a", "b") %>"#,
+                "badge(): expected at most 1 argument(s), got 2",
+            ),
+            (
+                "<% macro badge(label) %><%= label %><% end %><%= badge() %>",
+                "badge(): missing argument \"label\"",
+            ),
+            ("<% call badge %><% end %>", "badge(): macro is not defined"),
+            (
+                "<% macro forever %><%= forever() %><% end %><%= forever() %>",
+                "forever(): macro calls itself too many times",
+            ),
+        ] {
+            let err = Template::from_str(source)?.render(&context).unwrap_err();
+            assert_eq!(err.to_string(), error);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import() -> Result<(), Error> {
+        let dir = tempdir::TempDir::new("rwf_macros").unwrap();
+        let components = dir.path().join("components.html");
+        std::fs::write(
+            &components,
+            r#"<% macro badge(label) %><span class="badge"><%= label %></span><% end %>ignored"#,
+        )
+        .unwrap();
+
+        let template = Template::from_str(&format!(
+            r#"<% import "{}" %><%= badge("new") %>"#,
+            components.display()
+        ))?;
+        assert_eq!(
+            template.render_default()?,
+            r#"<span class="badge">new</span>"#
+        );
+
+        Ok(())
+    }
+}
@@ -2,12 +2,14 @@
 //!
 //! Includes the parser and runtime.
 pub mod expression;
+pub mod macros;
 pub mod op;
 pub mod program;
 pub mod statement;
 pub mod term;
 
 pub use expression::Expression;
+pub use macros::Macro;
 pub use op::Op;
 pub use program::Program;
 pub use statement::Statement;
@@ -89,8 +89,8 @@ impl Op {
             Op::Or => 12,
             Op::Add | Op::Sub => 4,
             Op::Mult | Op::Div | Op::Mod => 3,
+            Op::GreaterThan | Op::GreaterEqualThan | Op::LessThan | Op::LessEqualThan => 6,
             Op::Equals | Op::NotEquals => 7,
-            _ => todo!(),
         }
     }
 }
@@ -1,8 +1,13 @@
 //! Executable template.
 //!
 //! A program is a list of statements.
-use super::super::{Context, Error, TokenWithContext, Tokenize};
-use super::Statement;
+use super::super::{Context, Error, Template, TokenWithContext, Tokenize};
+use super::{statement::partial, Macro, Statement};
+use crate::config::get_config;
+
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 /// Executable program.
 #[derive(Debug, Clone)]
@@ -13,14 +18,76 @@ pub struct Program {
 impl Program {
     /// Evaluate the program given the context. The context contains variable definitions.
     pub fn evaluate(&self, context: &Context) -> Result<String, Error> {
+        let context = self.with_macros(context)?;
+
+        if let Some(layout) = self.layout() {
+            return self.evaluate_layout(layout, &context);
+        }
+
         let mut result = String::new();
         for statement in &self.statements {
-            result.push_str(&statement.evaluate(context)?);
+            result.push_str(&statement.evaluate(&context)?);
         }
 
         Ok(result)
     }
 
+    /// Macros defined in this template.
+    pub(crate) fn macros(&self) -> impl Iterator<Item = &Arc<Macro>> {
+        self.statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Macro(component) => Some(component),
+                _ => None,
+            })
+    }
+
+    /// Make macros defined and imported by this template callable. Macros can be called
+    /// anywhere in the template, including before they are defined. Imported templates
+    /// only provide the macros they define, not the ones they import.
+    fn with_macros<'a>(&self, context: &'a Context) -> Result<Cow<'a, Context>, Error> {
+        let mut context = Cow::Borrowed(context);
+
+        for statement in &self.statements {
+            match statement {
+                Statement::Import(path) => {
+                    let template = Template::load(&get_config().general.template_dir.join(path))?;
+                    for component in template.macros() {
+                        context.to_mut().set_macro(component.clone());
+                    }
+                }
+                Statement::Macro(component) => context.to_mut().set_macro(component.clone()),
+                _ => (),
+            }
+        }
+
+        Ok(context)
+    }
+
+    /// The layout this template extends, if any.
+    fn layout(&self) -> Option<&PathBuf> {
+        self.statements
+            .iter()
+            .find_map(|statement| match statement {
+                Statement::Extends(path) => Some(path),
+                _ => None,
+            })
+    }
+
+    /// Render the layout, with its blocks replaced by the blocks defined in this template.
+    /// Everything else in this template is ignored.
+    fn evaluate_layout(&self, layout: &Path, context: &Context) -> Result<String, Error> {
+        let mut context = context.clone();
+
+        for statement in &self.statements {
+            if let Statement::Block { name, body } = statement {
+                context.set_block(name, body.clone());
+            }
+        }
+
+        partial(&get_config().general.template_dir.join(layout), context)
+    }
+
     /// Parse the program from a list of tokens.
     pub fn parse(tokens: Vec<TokenWithContext>) -> Result<Self, Error> {
         let mut iter = tokens.into_iter().peekable();
@@ -74,6 +141,54 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_extends() -> Result<(), Error> {
+        let dir = tempdir::TempDir::new("rwf_extends").unwrap();
+        let app = dir.path().join("app.html");
+        let admin = dir.path().join("admin.html");
+        std::fs::write(
+            &app,
+            "<title><% block title %>App<% end %></title><main><% block content %><% end %></main><footer><% block footer %>(c)<% end %></footer>",
+        )
+        .unwrap();
+        std::fs::write(
+            &admin,
+            format!(
+                r#"<% extends "{}" %><% block title %>Admin<% end %><% block content %><nav></nav><% block page %><% end %><% end %>"#,
+                app.display()
+            ),
+        )
+        .unwrap();
+
+        let mut context = Context::default();
+        context.set("name", "Alice")?;
+
+        let page = Program::from_str(&format!(
+            r#"<% extends "{}" %>ignored<% block content %><p>Hi <%= name %></p><% end %>"#,
+            app.display()
+        ))?;
+        assert_eq!(
+            page.evaluate(&context)?,
+            "<title>App</title><main><p>Hi Alice</p></main><footer>(c)</footer>"
+        );
+
+        // Blocks of the most derived template win, others are inherited.
+        let page = Program::from_str(&format!(
+            r#"<% extends "{}" %><% block page %><%= name %><% end %><% block footer %><% end %>"#,
+            admin.display()
+        ))?;
+        assert_eq!(
+            page.evaluate(&context)?,
+            "<title>Admin</title><main><nav></nav>Alice</main><footer></footer>"
+        );
+
+        // Without a layout, blocks render their own content.
+        let page = Program::from_str("<% block title %>Default<% end %>")?;
+        assert_eq!(page.evaluate(&context)?, "Default");
+
+        Ok(())
+    }
+
     #[test]
     fn test_secure_links() -> Result<(), Error> {
         let program = r#"
@@ -95,4 +210,77 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_escape_context() -> Result<(), Error> {
+        let program = Program::from_str(
+            r#"<p title="<%= name %>"><%= name %><%= bold.safe %></p><script>const user = <%= user %>; const name = "<%= name %>";</script>"#,
+        )?;
+
+        let mut context = Context::new();
+        context.set("name", "\"Alice\" <b>")?;
+        context.set("bold", "<b>bold</b>")?;
+        context.set(
+            "user",
+            Value::Hash(HashMap::from([(
+                String::from("name"),
+                Value::String("</script>".into()),
+            )])),
+        )?;
+
+        assert_eq!(
+            program.evaluate(&context)?,
+            r#"<p title="&quot;Alice&quot;&#32;&lt;b&gt;">&quot;Alice&quot; &lt;b&gt;<b>bold</b></p><script>const user = {"name":"\u003c/script\u003e"}; const name = "\u0022Alice\u0022\u0020\u003cb\u003e";</script>"#
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape_script_string() -> Result<(), Error> {
+        // Strings are JSON inside <script>, so they're quoted unless printed inside a string.
+        let program = Program::from_str(
+            r#"<script>const name = '<%= name %>'; const title = <%= name %>;</script>"#,
+        )?;
+
+        let mut context = Context::new();
+        context.set("name", "O'Brien</script>")?;
+
+        assert_eq!(
+            program.evaluate(&context)?,
+            r#"<script>const name = 'O\u0027Brien\u003c\u002fscript\u003e'; const title = "O'Brien\u003c/script\u003e";</script>"#
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape_after_comment_and_regex() -> Result<(), Error> {
+        // Quotes in comments and regular expressions don't start strings.
+        let program = Program::from_str(
+            "<script>// don't\nconst a = <%= v %>; /* it's */ const b = <%= v %>;\nconst re = /'/; const c = <%= v %>; const d = x / 2 + <%= v %>;</script>",
+        )?;
+
+        let mut context = Context::new();
+        context.set("v", "1;alert(document.cookie)//")?;
+
+        assert_eq!(
+            program.evaluate(&context)?,
+            "<script>// don't\nconst a = \"1;alert(document.cookie)//\"; /* it's */ const b = \"1;alert(document.cookie)//\";\nconst re = /'/; const c = \"1;alert(document.cookie)//\"; const d = x / 2 + \"1;alert(document.cookie)//\";</script>"
+        );
+
+        // Values inside comments and regular expressions can't end them.
+        let program = Program::from_str(
+            "<script>// <%= v %>\n/* <%= v %> */ const re = /<%= v %>/;</script>",
+        )?;
+        context.set("v", "*/\n/")?;
+
+        assert_eq!(
+            program.evaluate(&context)?,
+            r"<script>// \u002a\u002f\u000a\u002f
+/* \u002a\u002f\u000a\u002f */ const re = /\u002a\u002f\u000a\u002f/;</script>"
+        );
+
+        Ok(())
+    }
 }
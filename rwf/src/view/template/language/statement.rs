@@ -1,12 +1,15 @@
 //! Language statement, code which executes arbitrary instructions, like for loops or print to screen.
 use super::{
     super::Template,
-    super::{Context, Error, Token, TokenWithContext, Tokenize, Value},
-    Expression, Term,
+    super::{Context, Error, Escape, Token, TokenWithContext, Tokenize, Value},
+    Expression, Macro, Term,
 };
+use std::collections::HashMap;
 use std::iter::{Iterator, Peekable};
 
-use std::path::PathBuf;
+use crate::config::get_config;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 macro_rules! expect {
     ($got:expr, $expected:expr) => {
@@ -30,20 +33,27 @@ macro_rules! block_end {
 #[derive(Debug, Clone)]
 pub enum Statement {
     // e.g. `<%= variable %>`
-    Print(Expression),
+    Print(Expression, Escape),
     PrintRaw(Expression),
     // e.g. `<html><body></body></html>`
     PrintText(String),
     // e.g. `<% if variable == 5 %>right<% else %>wrong<% end %>`
+    //
+    // `<% elsif %>` branches are nested `if` statements in the `else` body.
     If {
         expression: Expression,
         if_body: Vec<Statement>,
         else_body: Vec<Statement>,
-        else_if: bool,
+    },
+
+    // `<% elsif variable == 6 %>`
+    ElseIf {
+        expression: Expression,
+        token: TokenWithContext,
     },
 
     // `<% else %>`
-    Else,
+    Else(TokenWithContext),
     // `<% end %>
     End,
 
@@ -55,6 +65,35 @@ pub enum Statement {
     },
 
     Render(PathBuf),
+
+    // `<% include "partials/nav.html" title = "Home" %>`
+    Include {
+        path: PathBuf,
+        locals: Vec<(String, Expression)>,
+    },
+
+    // `<% extends "layouts/app.html" %>`
+    Extends(PathBuf),
+
+    // `<% block content %><p>Default content</p><% end %>`
+    Block {
+        name: String,
+        body: Arc<Vec<Statement>>,
+    },
+
+    // `<% macro button(label, kind = "primary") %><button><%= label %></button><% end %>`
+    Macro(Arc<Macro>),
+
+    // `<% call card("Title") %><p>Card body</p><% end %>`
+    Call {
+        name: String,
+        args: Vec<Expression>,
+        body: Vec<Statement>,
+        token: TokenWithContext,
+    },
+
+    // `<% import "components.html" %>`
+    Import(PathBuf),
 }
 
 impl Statement {
@@ -67,29 +106,80 @@ impl Statement {
     /// Evaluate a statement given the context.
     pub fn evaluate(&self, context: &Context) -> Result<String, Error> {
         match self {
-            Statement::Render(path) => {
-                let template = Template::load(&path)?;
-                template.render(context)
+            Statement::Render(path) => partial(path, context.clone()),
+            Statement::Include { path, locals } => {
+                let path = get_config().general.template_dir.join(path);
+                let mut partial_context = context.clone();
+
+                for (name, expression) in locals {
+                    partial_context.set(name, expression.evaluate(context)?)?;
+                }
+
+                partial(&path, partial_context)
+            }
+            // The layout is rendered, and macros are defined, by the program,
+            // see [`super::Program::evaluate`].
+            Statement::Extends(_) | Statement::Macro(_) | Statement::Import(_) => Ok(String::new()),
+            Statement::Call {
+                name,
+                args,
+                body,
+                token,
+            } => {
+                let with_token = |err| match err {
+                    Error::Function {
+                        name,
+                        message,
+                        token: None,
+                    } => Error::Function {
+                        name,
+                        message,
+                        token: Some(token.clone()),
+                    },
+                    err => err,
+                };
+
+                let component = context.get_macro(name).ok_or_else(|| Error::Function {
+                    name: name.clone(),
+                    message: "macro is not defined".into(),
+                    token: Some(token.clone()),
+                })?;
+
+                let args = args
+                    .iter()
+                    .map(|arg| arg.evaluate(context))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                // The body is rendered where the macro is called.
+                let mut slot = String::new();
+                for statement in body {
+                    slot.push_str(&statement.evaluate(context)?);
+                }
+
+                component
+                    .call(&args, Some(Value::SafeString(slot)), context)
+                    .map_err(with_token)
+            }
+            Statement::Block { name, body } => {
+                // Blocks are overridden by templates extending this one.
+                let body = context.block(name).unwrap_or_else(|| body.clone());
+                let mut result = String::new();
+
+                for statement in body.iter() {
+                    result.push_str(&statement.evaluate(context)?);
+                }
+
+                Ok(result)
             }
             Statement::PrintText(text) => Ok(text.clone()),
             Statement::If {
                 expression,
                 if_body,
                 else_body,
-                ..
             } => {
                 let mut result = String::new();
-                let truthy = match expression.evaluate(&context) {
-                    Ok(result) => result.truthy(),
-                    Err(Error::UndefinedVariable(name)) => match expression {
-                        Expression::Term { .. } => false,
-                        _ => return Err(Error::UndefinedVariable(name)),
-                    },
 
-                    Err(err) => return Err(err),
-                };
-
-                if truthy {
+                if expression.condition(context)? {
                     for statement in if_body {
                         result.push_str(&statement.evaluate(&context)?);
                     }
@@ -102,13 +192,7 @@ impl Statement {
                 Ok(result)
             }
             Statement::PrintRaw(expression) => Ok(expression.evaluate(context)?.to_string()),
-            Statement::Print(expression) => {
-                let value = expression.evaluate(context)?;
-                Ok(match value {
-                    Value::SafeString(s) => s,
-                    value => crate::safe_html(&value.to_string()),
-                })
-            }
+            Statement::Print(expression, escape) => escape.escape(expression.evaluate(context)?),
             Statement::For {
                 variable,
                 list,
@@ -134,7 +218,9 @@ impl Statement {
                     value => return Err(Error::Runtime(format!("not an iterable: {}", value))),
                 };
 
-                for value in values {
+                let length = values.len();
+
+                for (index, value) in values.into_iter().enumerate() {
                     match variable {
                         // Convert the variable to a value from the list.
                         Term::Variable(name) => {
@@ -144,6 +230,10 @@ impl Statement {
                         _ => todo!(),            // Function call is interesting
                     };
 
+                    // Nested loops get their own, outer loops get theirs back when the inner loop ends
+                    // since each loop evaluates in a copy of the context.
+                    for_context.set("loop", loop_metadata(index, length))?;
+
                     for statement in body {
                         result.push_str(&statement.evaluate(&for_context)?);
                     }
@@ -151,6 +241,10 @@ impl Statement {
 
                 Ok(result)
             }
+            // Not part of an `if` statement.
+            Statement::Else(token) | Statement::ElseIf { token, .. } => {
+                Err(Error::Syntax(token.clone()))
+            }
             statement => todo!("evaluating {:?}", statement),
         }
     }
@@ -168,10 +262,10 @@ impl Statement {
                 }
                 Token::Text(string) => return Ok(Statement::PrintText(string)),
                 Token::BlockStart | Token::BlockEnd => (),
-                Token::BlockStartPrint => {
+                Token::BlockStartPrint(escape) => {
                     let expression = Expression::parse(iter)?;
                     block_end!(iter);
-                    return Ok(Statement::Print(expression));
+                    return Ok(Statement::Print(expression, escape));
                 }
                 Token::BlockStartPrintRaw => {
                     let expression = Expression::parse(iter)?;
@@ -191,71 +285,164 @@ impl Statement {
                 }
                 Token::Else => {
                     block_end!(iter);
-                    return Ok(Statement::Else);
+                    return Ok(Statement::Else(next));
                 }
-                Token::If | Token::ElseIf => {
-                    let else_if = next.token() == Token::ElseIf;
-                    let (mut if_body, mut else_body) = (vec![], vec![]);
-                    let expression = Expression::parse(iter)?;
+                Token::Extends => {
+                    let path = iter.next().ok_or(Error::Eof("extends"))?;
+                    block_end!(iter);
+
+                    match path.token() {
+                        Token::Value(Value::String(path)) => {
+                            return Ok(Statement::Extends(PathBuf::from(path)))
+                        }
+                        _ => return Err(Error::Syntax(path)),
+                    }
+                }
+                Token::Block => {
+                    let name = iter.next().ok_or(Error::Eof("block"))?;
+                    let name = match name.token() {
+                        Token::Variable(name) => name,
+                        _ => return Err(Error::Syntax(name)),
+                    };
+                    block_end!(iter);
+
+                    let mut body = vec![];
 
                     loop {
-                        let statement = Statement::parse(iter)?;
-                        match statement {
-                            Statement::End => {
-                                return Ok(Statement::If {
-                                    expression,
-                                    if_body,
-                                    else_body,
-                                    else_if,
-                                })
-                            }
+                        match Statement::parse(iter)? {
+                            Statement::End => break,
+                            statement => body.push(statement),
+                        }
+                    }
 
-                            Statement::If { else_if: true, .. } => {
-                                else_body.push(statement);
-                                break;
-                                // if
-                                // elsif
-                                // elsif
-                                // else
-                                // end
-                                //
-                                // translates into this:
-                                //
-                                // if
-                                // else
-                                //   if
-                                //   else
-                                //     if
-                                //     else
-                                //     end
-                                //   end
-                                // end
+                    return Ok(Statement::Block {
+                        name,
+                        body: Arc::new(body),
+                    });
+                }
+                Token::Macro => {
+                    let name = iter.next().ok_or(Error::Eof("macro"))?;
+                    let name = match name.token() {
+                        Token::Variable(name) => name,
+                        _ => return Err(Error::Syntax(name)),
+                    };
+                    let mut params = vec![];
+
+                    // Parameters are optional, e.g. `<% macro divider %>`.
+                    if iter.peek().map(|next| next.token()) == Some(Token::RoundBracketStart) {
+                        let _ = iter.next();
+
+                        loop {
+                            let next = iter.next().ok_or(Error::Eof("macro"))?;
+                            match next.token() {
+                                Token::RoundBracketEnd => break,
+                                Token::Comma => (),
+                                Token::Variable(param) => {
+                                    let default = if iter.peek().map(|next| next.token())
+                                        == Some(Token::Assign)
+                                    {
+                                        let _ = iter.next();
+                                        Some(Expression::parse(iter)?)
+                                    } else {
+                                        None
+                                    };
+                                    params.push((param, default));
+                                }
+                                _ => return Err(Error::Syntax(next)),
                             }
+                        }
+                    }
+                    block_end!(iter);
 
-                            Statement::Else => loop {
-                                let statement = Statement::parse(iter)?;
-
-                                match statement {
-                                    Statement::End => {
-                                        return Ok(Statement::If {
-                                            expression,
-                                            if_body,
-                                            else_body,
-                                            else_if,
-                                        })
-                                    }
-                                    statement => else_body.push(statement),
-                                }
-                            },
-                            statement => if_body.push(statement),
+                    let mut body = vec![];
+
+                    loop {
+                        match Statement::parse(iter)? {
+                            Statement::End => break,
+                            statement => body.push(statement),
+                        }
+                    }
+
+                    return Ok(Statement::Macro(Arc::new(Macro::new(name, params, body))));
+                }
+                Token::Call => {
+                    let (name, args) = match Expression::parse(iter)? {
+                        Expression::Term {
+                            term: Term::Variable(name),
+                        } => (name, vec![]),
+                        Expression::Function {
+                            term, name, args, ..
+                        } if matches!(*term, Expression::Interpreter) => match *name {
+                            Expression::Term {
+                                term: Term::Constant(Value::String(name)),
+                            } => (name, args),
+                            _ => return Err(Error::Syntax(next)),
+                        },
+                        _ => return Err(Error::Syntax(next)),
+                    };
+                    block_end!(iter);
+
+                    let mut body = vec![];
+
+                    loop {
+                        match Statement::parse(iter)? {
+                            Statement::End => break,
+                            statement => body.push(statement),
                         }
                     }
 
-                    return Ok(Statement::If {
+                    return Ok(Statement::Call {
+                        name,
+                        args,
+                        body,
+                        token: next,
+                    });
+                }
+                Token::Import => {
+                    let path = iter.next().ok_or(Error::Eof("import"))?;
+                    block_end!(iter);
+
+                    match path.token() {
+                        Token::Value(Value::String(path)) => {
+                            return Ok(Statement::Import(PathBuf::from(path)))
+                        }
+                        _ => return Err(Error::Syntax(path)),
+                    }
+                }
+                Token::Include => {
+                    let path = iter.next().ok_or(Error::Eof("include"))?;
+                    let path = match path.token() {
+                        Token::Value(Value::String(path)) => PathBuf::from(path),
+                        _ => return Err(Error::Syntax(path)),
+                    };
+                    let mut locals = vec![];
+
+                    loop {
+                        let next = iter.next().ok_or(Error::Eof("include"))?;
+                        match next.token() {
+                            Token::BlockEnd => break,
+                            Token::Comma => (),
+                            Token::Variable(name) => {
+                                let assign = iter.next().ok_or(Error::Eof("include"))?;
+                                expect!(assign, Token::Assign);
+                                locals.push((name, Expression::parse(iter)?));
+                            }
+                            _ => return Err(Error::Syntax(next)),
+                        }
+                    }
+
+                    return Ok(Statement::Include { path, locals });
+                }
+                Token::If => {
+                    let expression = Expression::parse(iter)?;
+                    return Self::parse_if(expression, iter);
+                }
+
+                Token::ElseIf => {
+                    let expression = Expression::parse(iter)?;
+                    return Ok(Statement::ElseIf {
                         expression,
-                        if_body,
-                        else_body,
-                        else_if,
+                        token: next,
                     });
                 }
 
@@ -295,6 +482,76 @@ impl Statement {
             }
         }
     }
+
+    /// Parse the branches of an `if` statement, up to and including its `end`.
+    ///
+    /// ```erb
+    /// <% if a %>1<% elsif b %>2<% else %>3<% end %>
+    /// ```
+    ///
+    /// is parsed as
+    ///
+    /// ```erb
+    /// <% if a %>1<% else %><% if b %>2<% else %>3<% end %><% end %>
+    /// ```
+    fn parse_if(
+        expression: Expression,
+        iter: &mut Peekable<impl Iterator<Item = TokenWithContext>>,
+    ) -> Result<Statement, Error> {
+        let mut if_body = vec![];
+        let mut else_body = vec![];
+
+        loop {
+            match Statement::parse(iter)? {
+                Statement::End => break,
+
+                Statement::ElseIf { expression, .. } => {
+                    // The `elsif` chain shares this statement's `end`.
+                    else_body.push(Self::parse_if(expression, iter)?);
+                    break;
+                }
+
+                Statement::Else(_) => {
+                    loop {
+                        match Statement::parse(iter)? {
+                            Statement::End => break,
+                            // Nothing can follow the `else` branch.
+                            Statement::Else(token) | Statement::ElseIf { token, .. } => {
+                                return Err(Error::Syntax(token))
+                            }
+                            statement => else_body.push(statement),
+                        }
+                    }
+                    break;
+                }
+
+                statement => if_body.push(statement),
+            }
+        }
+
+        Ok(Statement::If {
+            expression,
+            if_body,
+            else_body,
+        })
+    }
+}
+
+/// Render the partial, unless it's already being rendered, i.e. it includes itself.
+pub(crate) fn partial(path: &Path, mut context: Context) -> Result<String, Error> {
+    context.enter_partial(path)?;
+    Template::load(path)?.render(&context)
+}
+
+/// The `loop` variable available inside for loops, e.g. `<%= loop.index %>`.
+fn loop_metadata(index: usize, length: usize) -> Value {
+    Value::Hash(HashMap::from([
+        ("index".to_string(), Value::Integer(index as i64 + 1)),
+        ("index0".to_string(), Value::Integer(index as i64)),
+        ("first".to_string(), Value::Boolean(index == 0)),
+        ("last".to_string(), Value::Boolean(index + 1 == length)),
+        ("length".to_string(), Value::Integer(length as i64)),
+    ]))
 }
 
 #[cfg(test)]
@@ -338,6 +595,50 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_statements_elsif_chain() -> Result<(), Error> {
+        let template = r#"<% if n == 1 %>one<% elsif n == 2 %>two<% elsif n == 3 && big %>three<% if big %>!<% end %><% elsif n > 3 || !big %>many<% else %>none<% end %>."#;
+        let ast = Template::from_str(template)?;
+
+        for (n, big, expected) in [
+            (1, false, "one."),
+            (2, false, "two."),
+            (3, true, "three!."),
+            (3, false, "many."),
+            (4, true, "many."),
+            (0, true, "none."),
+        ] {
+            let mut context = Context::default();
+            context.set("n", n)?;
+            context.set("big", big)?;
+            assert_eq!(ast.render(&context)?, expected);
+        }
+
+        // Undefined variables are false, and aren't evaluated if they don't need to be.
+        let result = Template::from_str(
+            "<% if user && user.admin %>admin<% elsif !user || user.guest %>guest<% end %>",
+        )?
+        .render_default()?;
+        assert_eq!(result, "guest");
+
+        // Nothing comes after else.
+        for template in [
+            "<% if a %>1<% else %>2<% elsif b %>3<% end %>",
+            "<% if a %>1<% else %>2<% else %>3<% end %>",
+        ] {
+            assert!(matches!(
+                Template::from_str(template),
+                Err(Error::Syntax(_))
+            ));
+        }
+
+        // Not in an if statement.
+        let result = Template::from_str("<% elsif a %>1<% end %>")?.render_default();
+        assert!(matches!(result, Err(Error::Syntax(_))));
+
+        Ok(())
+    }
+
     #[test]
     fn test_print_expression() -> Result<(), Error> {
         let t1 = "<%= variable %>";
@@ -380,6 +681,82 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_for_loop_metadata() -> Result<(), Error> {
+        let mut context = Context::default();
+        context.set("users", vec!["alice", "bob", "carol"])?;
+
+        let result = Statement::from_str(
+            r#"<% for user in users %><% if loop.first %>[<% end %><%= loop.index %>/<%= loop.length %> <%= user %><% if !loop.last %>, <% end %><% end %>"#,
+        )?
+        .evaluate(&context)?;
+        assert_eq!(result, "[1/3 alice, 2/3 bob, 3/3 carol");
+
+        // Nested loops have their own metadata and don't change the outer loop's.
+        let result = Statement::from_str(
+            r#"<% for row in [[1, 2], [3]] %><% for cell in row %><%= loop.index0 %>:<%= cell %> <% end %>(<%= loop.index %>) <% end %>"#,
+        )?
+        .evaluate(&Context::default())?;
+        assert_eq!(result, "0:1 1:2 (1) 0:3 (2) ");
+
+        // The loop variable isn't available outside the loop.
+        let result = Template::from_str(
+            r#"<% for user in users %><% end %><% if loop %>yes<% else %>no<% end %>"#,
+        )?
+        .render(&context)?;
+        assert_eq!(result, "no");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_include() -> Result<(), Error> {
+        let dir = tempdir::TempDir::new("rwf_include").unwrap();
+        let card = dir.path().join("card.html");
+        let cycle = dir.path().join("cycle.html");
+        std::fs::write(&card, "<h2><%= title %></h2><p><%= user.name %></p>").unwrap();
+        std::fs::write(&cycle, format!(r#"<% include "{}" %>"#, cycle.display())).unwrap();
+
+        let mut context = Context::default();
+        context.set(
+            "user",
+            Value::Hash(HashMap::from([(
+                "name".into(),
+                Value::String("Alice".into()),
+            )])),
+        )?;
+
+        let result = Template::from_str(&format!(
+            r#"<% include "{}" title = "Hi " + user.name, user=user %><%= title %>"#,
+            card.display()
+        ))?
+        .render(&context);
+        // Locals are only set in the partial.
+        assert!(matches!(result, Err(Error::UndefinedVariable(name)) if name == "title"));
+
+        let result = Template::from_str(&format!(
+            r#"<% for i in [1, 2] %><% include "{}" title = i %><% end %>"#,
+            card.display()
+        ))?
+        .render(&context)?;
+        assert_eq!(result, "<h2>1</h2><p>Alice</p><h2>2</h2><p>Alice</p>");
+
+        // A partial including itself is an error, not a stack overflow.
+        let result = Template::from_str(&format!(r#"<% include "{}" %>"#, cycle.display()))?
+            .render(&context);
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("template includes itself"));
+        assert!(err.contains(&format!("{} -> {}", cycle.display(), cycle.display())));
+
+        // Paths are relative to the template directory.
+        let result = Template::from_str(r#"<% include "does_not_exist.html" %>"#)?.render(&context);
+        assert!(
+            matches!(result, Err(Error::TemplateDoesNotExist(path)) if path == std::path::Path::new("templates/does_not_exist.html"))
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_newline() {
         // Make sure lexer doesn't interpret new lines as something.
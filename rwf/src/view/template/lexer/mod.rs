@@ -5,7 +5,7 @@ pub mod value;
 pub use token::Token;
 pub use value::{ToTemplateValue, Value};
 
-use super::Error;
+use super::{escape::HtmlState, Error};
 
 /// Token with source code location context.
 #[derive(Debug, Clone, PartialEq)]
@@ -77,6 +77,8 @@ pub struct Lexer<'a> {
     line: usize,
     // Which column we're on. The parser processes input one character at a time.
     column: usize,
+    // Where in the HTML we are, to escape printed values.
+    html: HtmlState,
 }
 
 impl<'a> Lexer<'a> {
@@ -90,6 +92,7 @@ impl<'a> Lexer<'a> {
             number: false,
             line: 1,
             column: 1,
+            html: HtmlState::default(),
         }
     }
 
@@ -112,6 +115,18 @@ impl<'a> Lexer<'a> {
                     self.column -= 1;
                 } // Handle column count on Windows.
 
+                // Comparison in code, e.g. `<% if a < b %>` or `<% if a <= b %>`.
+                '<' if self.code_block && iter.clone().next() != Some('%') => {
+                    self.drain_buffer();
+
+                    if iter.clone().next() == Some('=') {
+                        let _ = iter.next();
+                        self.tokens.push(self.add_token(Token::LessEqualThan));
+                    } else {
+                        self.tokens.push(self.add_token(Token::LessThan));
+                    }
+                }
+
                 // Possibly a code block start tag.
                 '<' => {
                     let n = iter.next();
@@ -124,7 +139,9 @@ impl<'a> Lexer<'a> {
                                 // `<%=` (print expression)
                                 Some('=') => {
                                     self.drain_buffer();
-                                    self.tokens.push(self.add_token(Token::BlockStartPrint));
+                                    self.tokens.push(
+                                        self.add_token(Token::BlockStartPrint(self.html.escape())),
+                                    );
                                     self.code_block = true;
                                 }
 
@@ -211,25 +228,14 @@ impl<'a> Lexer<'a> {
 
                 '!' => {
                     if self.code_block {
-                        let next = iter.next();
-                        match next {
-                            // `<% != %>`
-                            Some('=') => {
-                                self.drain_buffer();
-                                self.tokens.push(self.add_token(Token::NotEquals));
-                            }
-
-                            Some(c) => {
-                                self.tokens.push(self.add_token(Token::Not));
-
-                                if c == ' ' {
-                                    self.tokens.push(self.add_token(Token::Space));
-                                } else {
-                                    self.buffer.push(c);
-                                }
-                            }
+                        self.drain_buffer();
 
-                            None => return Err(Error::Eof("lexer !")),
+                        // `<% != %>`
+                        if iter.clone().next() == Some('=') {
+                            let _ = iter.next();
+                            self.tokens.push(self.add_token(Token::NotEquals));
+                        } else {
+                            self.tokens.push(self.add_token(Token::Not));
                         }
                     } else {
                         // Just a !, e.g `<h1>oh, hello there!</h1>`
@@ -354,6 +360,23 @@ impl<'a> Lexer<'a> {
                     }
                 }
 
+                '=' => {
+                    if !self.code_block {
+                        self.buffer.push('=');
+                    } else if matches!(self.buffer.as_str(), "=" | ">") {
+                        // Second character of `==` or `>=`.
+                        self.buffer.push('=');
+                    } else if iter.clone().next() == Some('=') {
+                        // First character of `==`.
+                        self.drain_buffer();
+                        self.buffer.push('=');
+                    } else {
+                        // Assignment, e.g. `<% include "nav.html" title = "Home" %>`
+                        self.drain_buffer();
+                        self.tokens.push(self.add_token(Token::Assign));
+                    }
+                }
+
                 '0' | '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9' => {
                     if self.code_block {
                         self.number = true;
@@ -435,6 +458,12 @@ impl<'a> Lexer<'a> {
                     "for" => self.tokens.push(self.add_token(Token::For)),
                     "in" => self.tokens.push(self.add_token(Token::In)),
                     "do" => self.tokens.push(self.add_token(Token::Do)),
+                    "include" => self.tokens.push(self.add_token(Token::Include)),
+                    "extends" => self.tokens.push(self.add_token(Token::Extends)),
+                    "block" => self.tokens.push(self.add_token(Token::Block)),
+                    "macro" => self.tokens.push(self.add_token(Token::Macro)),
+                    "call" => self.tokens.push(self.add_token(Token::Call)),
+                    "import" => self.tokens.push(self.add_token(Token::Import)),
                     "&&" => self.tokens.push(self.add_token(Token::And)),
                     "||" => self.tokens.push(self.add_token(Token::Or)),
                     "==" => self.tokens.push(self.add_token(Token::Equals)),
@@ -463,6 +492,7 @@ impl<'a> Lexer<'a> {
                     }
                 }
             } else {
+                self.html.read(&s);
                 self.tokens.push(self.add_token(Token::Text(s)));
             }
         }
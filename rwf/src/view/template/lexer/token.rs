@@ -1,4 +1,5 @@
 //! Known language tokens.
+use super::super::Escape;
 use super::Value;
 
 /// A template language token, e.g. `if` or `for`.
@@ -20,7 +21,8 @@ pub enum Token {
     Else,
     End,
     BlockStart,
-    BlockStartPrint,
+    // `<%=`, escaped for where it's printed.
+    BlockStartPrint(Escape),
     BlockStartPrintRaw,
     BlockStartRender,
     BlockEnd,
@@ -50,6 +52,20 @@ pub enum Token {
     Comma,
     RoundBracketStart,
     RoundBracketEnd,
+    // `<% include "partials/nav.html" %>`
+    Include,
+    // `<% include "partials/nav.html" title = "Home" %>`
+    Assign,
+    // `<% extends "layouts/app.html" %>`
+    Extends,
+    // `<% block content %>`
+    Block,
+    // `<% macro button(label) %>`
+    Macro,
+    // `<% call card("Title") %>`
+    Call,
+    // `<% import "components.html" %>`
+    Import,
 }
 
 impl Token {
@@ -60,7 +76,7 @@ impl Token {
             Token::End => 3,
             Token::BlockEnd => 2,
             Token::BlockStart => 2,
-            Token::BlockStartPrint => 3,
+            Token::BlockStartPrint(_) => 3,
             Token::BlockStartRender => 3,
             _ => 0,
         }
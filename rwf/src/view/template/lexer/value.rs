@@ -187,6 +187,12 @@ impl Value {
     ) -> Result<Self, Error> {
         match method_name {
             "nil" | "null" | "blank" => return Ok(Value::Boolean(self == &Value::Null)),
+            "safe" | "html_safe" => {
+                return Ok(match self {
+                    Value::SafeString(_) => self.clone(),
+                    value => Value::SafeString(value.to_string()),
+                })
+            }
             "integer" => {
                 return Ok(Value::Boolean(match self {
                     Value::Integer(_) => true,
@@ -267,6 +273,8 @@ impl Value {
                 "len" => Value::Integer(value.len() as i64),
                 "is_empty" | "blank" | "empty" => Value::Boolean(value.is_empty()),
                 "br" => Value::SafeString(crate::safe_html(value).replace("\n", "<br>")),
+                "qr" => Value::SafeString(crate::view::QrCode::new(value)?.svg()),
+                "qr_png" => Value::String(crate::view::QrCode::new(value)?.png_data_uri()?),
                 "replace" | "sub" => match &args {
                     &[v, r] => Value::String(value.replace(&v.to_string(), &r.to_string())),
                     _ => {
@@ -324,6 +332,43 @@ impl Value {
 
                     "len" => Value::Integer(list.len() as i64),
 
+                    "tag_cloud" => {
+                        let sizes = match &args {
+                            &[Value::Integer(sizes)] => *sizes,
+                            _ => 5,
+                        };
+                        let count = |value: &Value| match value {
+                            Value::Hash(hash) => match hash.get("count") {
+                                Some(Value::Integer(count)) => *count,
+                                _ => 0,
+                            },
+                            _ => 0,
+                        };
+                        let min = list.iter().map(count).min().unwrap_or(0);
+                        let max = list.iter().map(count).max().unwrap_or(0);
+
+                        Value::List(
+                            list.iter()
+                                .map(|value| match value {
+                                    Value::Hash(hash) => {
+                                        let mut hash = hash.clone();
+                                        hash.insert(
+                                            "weight".into(),
+                                            Value::Integer(crate::model::taggable::weight(
+                                                count(value),
+                                                min,
+                                                max,
+                                                sizes,
+                                            )),
+                                        );
+                                        Value::Hash(hash)
+                                    }
+                                    value => value.clone(),
+                                })
+                                .collect(),
+                        )
+                    }
+
                     _ => return Err(Error::UnknownMethod(method_name.into(), "list")),
                 },
             },
@@ -346,64 +391,177 @@ impl Value {
                 },
             },
 
-            Value::Interpreter => match method_name {
-                "encrypt_number" => match &args {
-                    &[Value::Integer(n)] => match crate::crypto::encrypt_number(*n) {
-                        Ok(n) => Value::String(n),
-                        Err(_) => Value::Null,
+            Value::Interpreter => {
+                match method_name {
+                    "encrypt_number" => match &args {
+                        &[Value::Integer(n)] => match crate::crypto::encrypt_number(*n) {
+                            Ok(n) => Value::String(n),
+                            Err(_) => Value::Null,
+                        },
+                        _ => Value::Null,
                     },
-                    _ => Value::Null,
-                },
 
-                "decrypt_number" => match &args {
-                    &[Value::String(n)] => match crate::crypto::decrypt_number(&n) {
-                        Ok(n) => Value::Integer(n),
-                        Err(_) => Value::Null,
+                    "decrypt_number" => match &args {
+                        &[Value::String(n)] => match crate::crypto::decrypt_number(n) {
+                            Ok(n) => Value::Integer(n),
+                            Err(_) => Value::Null,
+                        },
+                        _ => Value::Null,
                     },
-                    _ => Value::Null,
-                },
 
-                "rwf_head" => Value::SafeString(HEAD.render(context)?),
-                "rwf_turbo_stream" => match &args {
-                    &[Value::String(endpoint)] => Value::SafeString(
-                        TURBO_STREAM
-                            .render([("endpoint", endpoint.clone())])
-                            .unwrap(),
-                    ),
+                    "rwf_head" => Value::SafeString(HEAD.render(context)?),
+                    "rwf_turbo_stream" => match &args {
+                        &[Value::String(endpoint)] => Value::SafeString(
+                            TURBO_STREAM
+                                .render([("endpoint", endpoint.clone())])
+                                .unwrap(),
+                        ),
+
+                        _ => {
+                            return Err(Error::Runtime(
+                                "rwf_turbo_stream() requires the WebSocket endpoint".into(),
+                            ))
+                        }
+                    },
 
-                    _ => {
-                        return Err(Error::Runtime(
-                            "rwf_turbo_stream() requires the WebSocket endpoint".into(),
-                        ))
+                    // Turbo Frame loading its content from the URL once it's visible, e.g. `turbo_frame("comments", "/comments")`.
+                    "turbo_frame" => match args {
+                        [Value::String(id)] => {
+                            Value::SafeString(crate::view::TurboFrame::new(id).render())
+                        }
+                        [Value::String(id), Value::String(src)] => Value::SafeString(
+                            crate::view::TurboFrame::new(id).src(src).lazy().render(),
+                        ),
+                        _ => {
+                            return Err(Error::Runtime(
+                                "turbo_frame() requires the frame ID, and optionally the URL"
+                                    .into(),
+                            ))
+                        }
+                    },
+
+                    "csrf_token_raw" => {
+                        Value::SafeString(crypto::csrf_token(&context.session_id()?).unwrap())
                     }
-                },
+                    "csrf_token" => Value::SafeString(format!(
+                        r#"<input type="hidden" name="{}" value="{}">"#,
+                        CSRF_INPUT,
+                        crypto::csrf_token(&context.session_id()?).unwrap(),
+                    )),
+
+                    "asset_path" => match &args {
+                        &[Value::String(path)] => Value::String(crate::assets::asset_path(path)),
+                        _ => {
+                            return Err(Error::Runtime(
+                                "asset_path() requires the path of the asset".into(),
+                            ))
+                        }
+                    },
 
-                "csrf_token_raw" => {
-                    Value::SafeString(crypto::csrf_token(&context.session_id()?).unwrap())
-                }
-                "csrf_token" => Value::SafeString(format!(
-                    r#"<input type="hidden" name="{}" value="{}">"#,
-                    CSRF_INPUT,
-                    crypto::csrf_token(&context.session_id()?).unwrap(),
-                )),
-
-                "render" => match &args {
-                    &[Value::String(n)] => {
-                        let template = Template::load(n)?;
-                        Value::SafeString(template.render(context)?)
+                    // URL of the named route, with parameters in the order of the path, e.g. `url_for("user", 5)`,
+                    // or taken from a hash, e.g. `url_for("user", user)`.
+                    "url_for" => {
+                        let (name, values) = match args.split_first() {
+                            Some((Value::String(name), values)) => (name, values),
+                            _ => {
+                                return Err(Error::Runtime(
+                                    "url_for() requires the name of the route".into(),
+                                ))
+                            }
+                        };
+
+                        let parameters = crate::http::router::route_parameters(name)
+                            .map_err(|err| Error::Runtime(err.to_string()))?;
+
+                        let params: Vec<(&str, &dyn std::fmt::Display)> = match values {
+                            [Value::Hash(hash)] => parameters
+                                .iter()
+                                .filter_map(|name| {
+                                    hash.get(name).map(|value| {
+                                        (name.as_str(), value as &dyn std::fmt::Display)
+                                    })
+                                })
+                                .collect(),
+                            values => parameters
+                                .iter()
+                                .zip(values)
+                                .map(|(name, value)| {
+                                    (name.as_str(), value as &dyn std::fmt::Display)
+                                })
+                                .collect(),
+                        };
+
+                        Value::String(
+                            crate::http::url_for(name, &params)
+                                .map_err(|err| Error::Runtime(err.to_string()))?,
+                        )
                     }
 
-                    _ => Value::Null,
-                },
+                    "render" => match &args {
+                        &[Value::String(n)] => {
+                            let template = Template::load(n)?;
+                            Value::SafeString(template.render(context)?)
+                        }
 
-                "default" => match &args {
-                    &[Value::Null, default_value] => default_value.clone(),
-                    &[value, _] => value.clone(),
-                    _ => Value::Null,
-                },
+                        _ => Value::Null,
+                    },
 
-                _ => return Err(Error::UnknownMethod(method_name.into(), "global")),
-            },
+                    "default" => match args {
+                        [Value::Null, default_value] => default_value.clone(),
+                        [value, _] => value.clone(),
+                        _ => Value::Null,
+                    },
+
+                    "can" => match &args {
+                        &[Value::Hash(permissions), Value::String(action)] => Value::Boolean(
+                            permissions
+                                .get(action)
+                                .map(|allowed| allowed.truthy())
+                                .unwrap_or(false),
+                        ),
+                        _ => Value::Boolean(false),
+                    },
+
+                    // Translate the message in the current locale, e.g. `t("chat.unread", count)`.
+                    "t" => {
+                        let (key, count, hash) = match args {
+                            [Value::String(key)] => (key, None, None),
+                            [Value::String(key), Value::Hash(hash)] => (key, None, Some(hash)),
+                            [Value::String(key), count] => (key, Some(count), None),
+                            [Value::String(key), count, Value::Hash(hash)] => {
+                                (key, Some(count), Some(hash))
+                            }
+                            _ => {
+                                return Err(Error::Runtime(
+                                    "t() requires the message key, and optionally the count and arguments"
+                                        .into(),
+                                ))
+                            }
+                        };
+
+                        let mut params: Vec<(&str, &dyn std::fmt::Display)> = vec![];
+                        if let Some(count) = count {
+                            params.push(("count", count));
+                        }
+                        if let Some(hash) = hash {
+                            params.extend(hash.iter().map(|(name, value)| {
+                                (name.as_str(), value as &dyn std::fmt::Display)
+                            }));
+                        }
+
+                        Value::String(crate::i18n::t(key, &params))
+                    }
+
+                    // Macros defined in templates take precedence over functions registered in Rust.
+                    name => match context.get_macro(name) {
+                        Some(component) => Value::SafeString(component.call(args, None, context)?),
+                        None => match super::super::functions::call(name, args) {
+                            Some(result) => result?,
+                            None => return Err(Error::UnknownMethod(method_name.into(), "global")),
+                        },
+                    },
+                }
+            }
 
             v => return Err(Error::UnknownMethod(method_name.into(), v.type_name())),
         })
@@ -568,7 +726,9 @@ impl TryInto<serde_json::Value> for Value {
         use serde_json::value::Number;
         match self {
             Value::Integer(i) => Ok(serde_json::Value::Number(i.into())),
-            Value::Float(f) => Ok(serde_json::Value::Number(Number::from_f64(f).unwrap())),
+            Value::Float(f) => Ok(Number::from_f64(f)
+                .map(serde_json::Value::Number)
+                .unwrap_or(serde_json::Value::Null)),
             Value::String(s) => Ok(serde_json::Value::String(s)),
             Value::Boolean(b) => Ok(serde_json::Value::Bool(b)),
             Value::List(l) => {
@@ -592,6 +752,32 @@ impl TryInto<serde_json::Value> for Value {
     }
 }
 
+impl ToTemplateValue for serde_json::Value {
+    fn to_template_value(&self) -> Result<Value, Error> {
+        Ok(match self {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(b) => Value::Boolean(*b),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Value::Integer(i),
+                None => Value::Float(n.as_f64().unwrap_or_default()),
+            },
+            serde_json::Value::String(s) => Value::String(s.clone()),
+            serde_json::Value::Array(list) => Value::List(
+                list.iter()
+                    .map(|v| v.to_template_value())
+                    .collect::<Result<_, _>>()?,
+            ),
+            serde_json::Value::Object(hash) => {
+                let mut result = HashMap::new();
+                for (key, value) in hash {
+                    result.insert(key.clone(), value.to_template_value()?);
+                }
+                Value::Hash(result)
+            }
+        })
+    }
+}
+
 impl ToTemplateValue for crate::model::Value {
     fn to_template_value(&self) -> Result<Value, Error> {
         use std::ops::Deref;
@@ -620,6 +806,10 @@ impl ToTemplateValue for crate::model::Value {
             }
             ModelValue::IpAddr(addr) => Ok(Value::String(addr.to_string())),
             ModelValue::Uuid(uuid) => Ok(Value::String(uuid.to_string())),
+            ModelValue::Bytes(bytes) => {
+                use base64::{engine::general_purpose, Engine as _};
+                Ok(Value::String(general_purpose::STANDARD.encode(bytes)))
+            }
             ModelValue::List(list) => {
                 let mut new_list = vec![];
                 for item in list.iter() {
@@ -692,6 +882,18 @@ impl ToTemplateValue for HashMap<String, ModelValue> {
     }
 }
 
+/// Errors by field name, e.g. `<% for message in errors.email %>`.
+impl ToTemplateValue for crate::http::ValidationErrors {
+    fn to_template_value(&self) -> Result<Value, Error> {
+        let mut result = HashMap::new();
+        for (field, messages) in self.fields() {
+            result.insert(field.clone(), messages.to_template_value()?);
+        }
+
+        Ok(Value::Hash(result))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -748,4 +950,30 @@ mod test {
             .unwrap();
         assert_eq!(v, Value::String("Hello World, How Are You?".into()))
     }
+
+    #[test]
+    fn test_tag_cloud() {
+        let tag = |name: &str, count: i64| {
+            Value::Hash(HashMap::from([
+                ("name".to_string(), Value::String(name.into())),
+                ("count".to_string(), Value::Integer(count)),
+            ]))
+        };
+        let v = Value::List(vec![tag("rust", 10), tag("sql", 1), tag("web", 4)])
+            .call("tag_cloud", &[Value::Integer(4)], &Context::default())
+            .unwrap();
+
+        let weights = v
+            .to_vec()
+            .into_iter()
+            .map(|tag| match tag {
+                Value::Hash(hash) => hash["weight"].clone(),
+                _ => Value::Null,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            weights,
+            vec![Value::Integer(4), Value::Integer(1), Value::Integer(2)]
+        );
+    }
 }
@@ -8,14 +8,19 @@
 //! see [documentation](https://levkk.github.io/rwf/).
 pub mod context;
 pub mod error;
+pub mod escape;
+pub mod functions;
 pub mod language;
 pub mod lexer;
 
 pub use context::Context;
 pub use error::Error;
+pub use escape::Escape;
+pub use functions::{Arguments, FromTemplateValue};
 pub use lexer::{Lexer, ToTemplateValue, Token, TokenWithContext, Tokenize, Value};
 
 use crate::http::Response;
+use crate::telemetry;
 use crate::view::Templates;
 
 use language::Program;
@@ -23,6 +28,7 @@ use language::Program;
 use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Instant;
 
 /// Rwf template.
 ///
@@ -70,8 +76,21 @@ impl Template {
     /// is a string.
     pub fn render(&self, context: impl TryInto<Context, Error = Error>) -> Result<String, Error> {
         let context: Context = context.try_into()?;
+        let path = self
+            .path
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or("inline".into());
+        let start = Instant::now();
+
+        let result = {
+            let _span = telemetry::template_span(&path).entered();
+            self.program.evaluate(&context)
+        };
+
+        telemetry::record_template(&path, start.elapsed());
 
-        match self.program.evaluate(&context) {
+        match result {
             Ok(result) => Ok(result),
             Err(err) => {
                 if let Some(path) = &self.path {
@@ -103,6 +122,21 @@ impl Template {
         Self::cached(path)
     }
 
+    /// Macros defined in this template.
+    pub(crate) fn macros(&self) -> impl Iterator<Item = &Arc<language::Macro>> {
+        self.program.macros()
+    }
+
+    /// Make the Rust function callable from all templates, e.g. `<%= url_for("chat") %>`.
+    /// See [`functions`] for details.
+    pub fn register_function<F, R>(name: &str, function: F)
+    where
+        F: Fn(&Arguments) -> Result<R, Error> + Send + Sync + 'static,
+        R: ToTemplateValue,
+    {
+        functions::register(name, function);
+    }
+
     /// Set global default values for variables. If the variable isn't defined
     /// in a template context, and a default exists, the default value will be used instead.
     pub fn defaults(context: Context) {
@@ -0,0 +1,191 @@
+//! Turbo Frame implementation on the backend.
+//!
+//! Turbo Frames are parts of the page which are updated on their own. Links and forms inside
+//! a frame fetch the next page with the `Turbo-Frame` header, and Turbo replaces the frame with the
+//! frame of the same ID found in the response. Since only the frame is used, the server can send just the frame,
+//! see [`Response::turbo_frame`](crate::http::Response::turbo_frame).
+
+/// Turbo Frame.
+///
+/// Renders a `<turbo-frame>` element, with its content or the URL to load it from.
+#[derive(Debug, Clone)]
+pub struct TurboFrame {
+    id: String,
+    src: Option<String>,
+    lazy: bool,
+    content: String,
+}
+
+impl std::fmt::Display for TurboFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.render())
+    }
+}
+
+impl TurboFrame {
+    /// Create new empty Turbo Frame with the ID.
+    pub fn new(id: impl ToString) -> Self {
+        Self {
+            id: id.to_string(),
+            src: None,
+            lazy: false,
+            content: "".into(),
+        }
+    }
+
+    /// Load the content of the frame from the URL.
+    pub fn src(mut self, src: impl ToString) -> Self {
+        self.src = Some(src.to_string());
+        self
+    }
+
+    /// Load the content of the frame only once it's visible on the page.
+    pub fn lazy(mut self) -> Self {
+        self.lazy = true;
+        self
+    }
+
+    /// Content of the frame. The content is HTML and isn't escaped.
+    pub fn content(mut self, content: impl ToString) -> Self {
+        self.content = content.to_string();
+        self
+    }
+
+    /// Render the `<turbo-frame>` element.
+    pub fn render(&self) -> String {
+        let mut attributes = format!(r#"id="{}""#, escape(&self.id));
+
+        if let Some(ref src) = self.src {
+            attributes.push_str(&format!(r#" src="{}""#, escape(src)));
+        }
+
+        if self.lazy {
+            attributes.push_str(r#" loading="lazy""#);
+        }
+
+        format!("<turbo-frame {}>{}</turbo-frame>", attributes, self.content)
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Find the `<turbo-frame>` element with the ID in the page, including
+/// its start and end tags. Frames can be nested.
+pub fn extract<'a>(html: &'a str, id: &str) -> Option<&'a str> {
+    const OPEN: &str = "<turbo-frame";
+    const CLOSE: &str = "</turbo-frame>";
+
+    let attributes = [format!(r#"id="{}""#, escape(id)), format!("id='{}'", id)];
+    let mut offset = 0;
+
+    while let Some(position) = html[offset..].find(OPEN) {
+        let start = offset + position;
+        let tag_end = start + html[start..].find('>')? + 1;
+        let tag = &html[start..tag_end];
+        offset = tag_end;
+
+        let found = tag
+            .split_ascii_whitespace()
+            .map(|attribute| attribute.trim_end_matches(['>', '/']))
+            .any(|attribute| attributes.iter().any(|id| id == attribute));
+
+        if !found {
+            continue;
+        }
+
+        let mut depth = 1;
+        let mut cursor = tag_end;
+
+        while depth > 0 {
+            let next_open = html[cursor..].find(OPEN);
+            let next_close = html[cursor..].find(CLOSE)?;
+
+            match next_open {
+                Some(open) if open < next_close => {
+                    depth += 1;
+                    cursor += open + OPEN.len();
+                }
+
+                _ => {
+                    depth -= 1;
+                    cursor += next_close + CLOSE.len();
+                }
+            }
+        }
+
+        return Some(&html[start..cursor]);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let frame = TurboFrame::new("comments")
+            .src("/posts/1/comments?page=2&per=5")
+            .lazy();
+        assert_eq!(
+            frame.render(),
+            r#"<turbo-frame id="comments" src="/posts/1/comments?page=2&amp;per=5" loading="lazy"></turbo-frame>"#
+        );
+
+        let frame = TurboFrame::new("post").content("<p>Hello</p>");
+        assert_eq!(
+            frame.to_string(),
+            r#"<turbo-frame id="post"><p>Hello</p></turbo-frame>"#
+        );
+    }
+
+    #[test]
+    fn test_extract() {
+        let html = r#"<html><body>
+            <turbo-frame id="nav">Nav</turbo-frame>
+            <turbo-frame id="posts" target="_top">
+                <turbo-frame id="post_1">Post</turbo-frame>
+                <p>More</p>
+            </turbo-frame>
+        </body></html>"#;
+
+        assert_eq!(
+            extract(html, "nav"),
+            Some(r#"<turbo-frame id="nav">Nav</turbo-frame>"#)
+        );
+        assert_eq!(
+            extract(html, "posts"),
+            Some(
+                r#"<turbo-frame id="posts" target="_top">
+                <turbo-frame id="post_1">Post</turbo-frame>
+                <p>More</p>
+            </turbo-frame>"#
+            )
+        );
+        assert_eq!(
+            extract(html, "post_1"),
+            Some(r#"<turbo-frame id="post_1">Post</turbo-frame>"#)
+        );
+        assert_eq!(extract(html, "post"), None);
+        assert_eq!(extract("<turbo-frame id='a'>A", "a"), None);
+    }
+
+    #[test]
+    fn test_template_helper() {
+        use crate::view::{Context, Template};
+
+        let template =
+            Template::from_str(r#"<%= turbo_frame("comments", "/comments") %>"#).unwrap();
+        assert_eq!(
+            template.render(&Context::new()).unwrap(),
+            r#"<turbo-frame id="comments" src="/comments" loading="lazy"></turbo-frame>"#
+        );
+    }
+}
@@ -2,8 +2,12 @@
 //!
 //! Turbo Streams are template partials that can dynamically replace
 //! DOM elements, similarly to a single page application written with React or Vue.
+//! [Turbo Frames](frame) update a part of the page without a WebSocket connection.
 use once_cell::sync::Lazy;
 
+pub mod frame;
+pub use frame::TurboFrame;
+
 use super::{Context, Template};
 use crate::view::template::lexer::value::ToTemplateValue;
 